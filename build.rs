@@ -0,0 +1,21 @@
+// Records the git commit being built, when there is one, so images can name the version
+// of the renderer that made them
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    for path in [".git/HEAD", ".git/refs", ".git/packed-refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=RUSTY_RAYS_COMMIT={}", commit.trim());
+    }
+}
@@ -0,0 +1,16 @@
+// The Cornell box, written to the path given or cornell_box.png:
+//     cargo run --release --example cornell_box [OUTPUT]
+use std::env;
+use std::io;
+use std::path::PathBuf;
+
+use rusty_rays::examples_scenes::cornell_box;
+use rusty_rays::render::{render, RenderSettings};
+
+fn main() -> io::Result<()> {
+    let output = env::args_os()
+        .nth(1)
+        .map_or_else(|| PathBuf::from("cornell_box.png"), PathBuf::from);
+    let (scene, camera) = cornell_box();
+    render(&scene, &camera, &RenderSettings::default()).write_image(&output)
+}
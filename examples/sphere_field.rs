@@ -0,0 +1,16 @@
+// A field of randomly scattered spheres, written to the path given or sphere_field.png:
+//     cargo run --release --example sphere_field [OUTPUT]
+use std::env;
+use std::io;
+use std::path::PathBuf;
+
+use rusty_rays::examples_scenes::sphere_field;
+use rusty_rays::render::{render, RenderSettings};
+
+fn main() -> io::Result<()> {
+    let output = env::args_os()
+        .nth(1)
+        .map_or_else(|| PathBuf::from("sphere_field.png"), PathBuf::from);
+    let (scene, camera) = sphere_field(0);
+    render(&scene, &camera, &RenderSettings::default()).write_image(&output)
+}
@@ -0,0 +1,16 @@
+// The scene rendered when no scene file is given, written to the path given or spheres.png:
+//     cargo run --release --example spheres [OUTPUT]
+use std::env;
+use std::io;
+use std::path::PathBuf;
+
+use rusty_rays::examples_scenes::spheres_on_checkerboard;
+use rusty_rays::render::{render, RenderSettings};
+
+fn main() -> io::Result<()> {
+    let output = env::args_os()
+        .nth(1)
+        .map_or_else(|| PathBuf::from("spheres.png"), PathBuf::from);
+    let (scene, camera) = spheres_on_checkerboard();
+    render(&scene, &camera, &RenderSettings::default()).write_image(&output)
+}
@@ -0,0 +1,69 @@
+/* C interface to rusty-rays, built with `cargo build --release --features capi` and
+ * linked against target/release/librusty_rays.so. Kept in step with src/capi.rs, which
+ * tests/capi.rs checks it against with `cargo test --features capi`. */
+#ifndef RUSTY_RAYS_H
+#define RUSTY_RAYS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Returned by the add functions when the object could not be created */
+#define RR_INVALID_ID UINT32_MAX
+
+#define RR_WHITTED 0
+#define RR_PATH 1
+
+typedef struct rr_scene rr_scene;
+
+typedef struct rr_material {
+    float diffuse[3];
+    float albedo[4];
+    float specular_exponent;
+    float refractive_index;
+} rr_material;
+
+typedef struct rr_camera {
+    float position[3];
+    float target[3];
+    float up[3];
+    /* Vertical field of view in degrees */
+    float fov;
+} rr_camera;
+
+/* Why the last failing call on this thread failed; valid until the next failure */
+const char *rr_last_error(void);
+
+/* Fills out with a built-in material such as "ivory" or "glass"; 0 on success */
+int rr_material_named(const char *name, rr_material *out);
+
+/* An empty scene seen from the origin down the negative Z axis */
+rr_scene *rr_scene_new(void);
+void rr_scene_free(rr_scene *scene);
+
+/* A null material means the first built-in one. Both return the new object's ID. */
+uint32_t rr_scene_add_sphere(rr_scene *scene, const float center[3], float radius,
+                             const rr_material *material);
+/* positions holds vertex_count xyz triples, indices triangle_count index triples */
+uint32_t rr_scene_add_mesh(rr_scene *scene, const float *positions, size_t vertex_count,
+                           const uint32_t *indices, size_t triangle_count,
+                           const rr_material *material);
+
+void rr_scene_add_light(rr_scene *scene, const float position[3], float intensity);
+void rr_scene_set_background(rr_scene *scene, const float color[3]);
+void rr_scene_set_camera(rr_scene *scene, const rr_camera *camera);
+
+/* Renders width * height pixels of 8-bit straight-alpha RGBA, row-major from the top
+ * left, into rgba, which must hold at least width * height * 4 bytes. The integrator
+ * is RR_WHITTED or RR_PATH. Returns 0 on success. */
+int rr_scene_render(const rr_scene *scene, uint32_t width, uint32_t height, uint32_t samples,
+                    int integrator, uint8_t *rgba, size_t rgba_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
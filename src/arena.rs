@@ -0,0 +1,93 @@
+use std::ops::Range;
+
+// A bump allocator for values that live and die together, such as the nodes of a tree.
+// Allocating appends to one buffer and everything is freed at once, so building a
+// structure costs a handful of buffer growths instead of an allocation per value.
+// Handles are indices, which keeps the structure free of pointers into itself.
+pub struct Arena<T> {
+    values: Vec<T>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArenaId(u32);
+
+// A run of values allocated together with Arena::alloc_extend
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArenaSlice {
+    start: u32,
+    len: u32,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Arena<T> {
+        Arena::new()
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Arena<T> {
+        Arena { values: Vec::new() }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Arena<T> {
+        Arena {
+            values: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn alloc(&mut self, value: T) -> ArenaId {
+        let id = ArenaId(self.next_index());
+        self.values.push(value);
+        id
+    }
+
+    pub fn alloc_extend<I: IntoIterator<Item = T>>(&mut self, values: I) -> ArenaSlice {
+        let start = self.next_index();
+        self.values.extend(values);
+        ArenaSlice {
+            start,
+            len: self.next_index() - start,
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, id: ArenaId) -> &T {
+        &self.values[id.0 as usize]
+    }
+
+    #[inline]
+    pub fn slice(&self, slice: ArenaSlice) -> &[T] {
+        &self.values[slice.range()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    // Frees every value but keeps the buffer for the next build
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    fn next_index(&self) -> u32 {
+        u32::try_from(self.values.len()).expect("arena holds more than u32::MAX values")
+    }
+}
+
+impl ArenaSlice {
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn range(&self) -> Range<usize> {
+        self.start as usize..(self.start + self.len) as usize
+    }
+}
@@ -0,0 +1,129 @@
+// Glow around highlights, as light scattering in a real lens and eye spreads whatever is
+// far brighter than its surroundings. Runs on the linear image before it is clamped for
+// output, so highlights well above white glow the most.
+use crate::framebuffer::Framebuffer;
+use crate::vec3::{Float, Vec3f};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bloom {
+    // Only light above this luminance spreads, and only by how far it exceeds it
+    pub threshold: Float,
+    // Standard deviation of the glow in pixels
+    pub radius: Float,
+    // Scales the glow added back onto the image
+    pub intensity: Float,
+}
+
+impl Default for Bloom {
+    fn default() -> Bloom {
+        Bloom {
+            threshold: 1.0,
+            radius: 8.0,
+            intensity: 0.5,
+        }
+    }
+}
+
+impl Bloom {
+    pub fn apply(&self, image: &mut Framebuffer) {
+        let (width, height) = (image.width, image.height);
+        if width == 0 || height == 0 || self.intensity <= 0.0 || self.radius <= 0.0 {
+            return;
+        }
+        let bright: Vec<Vec3f> = image.pixels.iter().map(|c| self.bright_pass(c)).collect();
+        // The Gaussian is separable, so blurring rows then columns takes
+        // two passes of n taps per pixel instead of n² taps
+        let kernel = gaussian_kernel(self.radius);
+        let rows = blur(&bright, width, height, &kernel, true);
+        let glow = blur(&rows, width, height, &kernel, false);
+        for (pixel, glow) in image.pixels.iter_mut().zip(&glow) {
+            *pixel += *glow * self.intensity;
+        }
+    }
+
+    fn bright_pass(&self, color: &Vec3f) -> Vec3f {
+        let luminance = 0.2126 * color.0 + 0.7152 * color.1 + 0.0722 * color.2;
+        if luminance <= self.threshold {
+            Vec3f(0.0, 0.0, 0.0)
+        } else {
+            *color * ((luminance - self.threshold) / luminance)
+        }
+    }
+}
+
+// Weights out to three standard deviations, summing to 1
+fn gaussian_kernel(sigma: Float) -> Vec<Float> {
+    let reach = (3.0 * sigma).ceil() as i64;
+    let weights: Vec<Float> = (-reach..=reach)
+        .map(|i| (-((i * i) as Float) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: Float = weights.iter().sum();
+    weights.iter().map(|w| w / total).collect()
+}
+
+// Convolves every row, or every column, with kernel; the image edge repeats outwards
+fn blur(
+    pixels: &[Vec3f],
+    width: usize,
+    height: usize,
+    kernel: &[Float],
+    horizontal: bool,
+) -> Vec<Vec3f> {
+    let reach = (kernel.len() / 2) as i64;
+    let mut out = vec![Vec3f(0.0, 0.0, 0.0); pixels.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = Vec3f(0.0, 0.0, 0.0);
+            for (k, weight) in kernel.iter().enumerate() {
+                let offset = k as i64 - reach;
+                let (sx, sy) = if horizontal {
+                    ((x as i64 + offset).clamp(0, width as i64 - 1) as usize, y)
+                } else {
+                    (x, (y as i64 + offset).clamp(0, height as i64 - 1) as usize)
+                };
+                sum += pixels[sy * width + sx] * *weight;
+            }
+            out[y * width + x] = sum;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spreads_only_the_light_above_the_threshold() {
+        let kernel = gaussian_kernel(2.0);
+        assert_eq!(kernel.len(), 13);
+        assert!((kernel.iter().sum::<Float>() - 1.0).abs() < 1e-5);
+        assert_eq!(kernel[0], kernel[12]);
+        assert!(kernel.windows(2).take(6).all(|w| w[0] < w[1]));
+
+        let bloom = Bloom {
+            threshold: 1.0,
+            radius: 2.0,
+            intensity: 1.0,
+        };
+        let mut dim = Framebuffer::new(8, 8);
+        dim.pixels.fill(Vec3f(0.9, 0.9, 0.9));
+        let before = dim.pixels.clone();
+        bloom.apply(&mut dim);
+        assert_eq!(dim.pixels, before);
+
+        // A white highlight four times over the threshold, well away from the edges
+        let mut image = Framebuffer::new(32, 32);
+        image.pixels[16 * 32 + 16] = Vec3f(4.0, 4.0, 4.0);
+        bloom.apply(&mut image);
+        let at = |x: usize, y: usize| image.pixels[y * 32 + x];
+        let glow: Float = image.pixels.iter().map(|c| c.0).sum::<Float>() - 4.0;
+        assert!((glow - 3.0).abs() < 1e-3, "{}", glow);
+        assert!((at(16, 16).0 - 4.0 - 3.0 * kernel[6] * kernel[6]).abs() < 1e-4);
+        assert_eq!(at(13, 16), at(19, 16));
+        assert_eq!(at(16, 13), at(19, 16));
+        assert!(at(17, 16).0 > at(18, 16).0 && at(18, 16).0 > at(22, 16).0);
+        assert_eq!(at(23, 16), Vec3f(0.0, 0.0, 0.0));
+        assert!(image.pixels.iter().all(|c| c.0 == c.1 && c.1 == c.2));
+    }
+}
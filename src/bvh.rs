@@ -0,0 +1,271 @@
+use crate::arena::{Arena, ArenaId, ArenaSlice};
+use crate::log::{self, Level};
+use crate::stats;
+use crate::vec3::{Float, Vec3f};
+
+const LEAF_SIZE: usize = 4;
+// Median splits halve the items at every level, so no tree over a usize count of items
+// is deeper than this, and traversal never has more nodes than that pending
+const MAX_DEPTH: usize = usize::BITS as usize + 1;
+// 1 + 2 * gamma(3) for Float
+const ROUNDING_SLACK: Float =
+    1.0 + 2.0 * (3.0 * Float::EPSILON * 0.5) / (1.0 - 3.0 * Float::EPSILON * 0.5);
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vec3f,
+    pub max: Vec3f,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3f, max: Vec3f) -> Aabb {
+        Aabb { min, max }
+    }
+
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vec3f(Float::MAX, Float::MAX, Float::MAX),
+            max: Vec3f(Float::MIN, Float::MIN, Float::MIN),
+        }
+    }
+
+    pub fn around(center: Vec3f, half_extent: Vec3f) -> Aabb {
+        Aabb::new(center - half_extent, center + half_extent)
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(&other.min), self.max.max(&other.max))
+    }
+
+    pub fn centroid(&self) -> Vec3f {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn contains(&self, point: &Vec3f) -> bool {
+        (0..3).all(|axis| self.min[axis] <= point[axis] && point[axis] <= self.max[axis])
+    }
+
+    pub fn largest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        if extent.0 > extent.1 && extent.0 > extent.2 {
+            0
+        } else if extent.1 > extent.2 {
+            1
+        } else {
+            2
+        }
+    }
+
+    // Slab test; returns the entry distance when the box is hit closer than t_max.
+    pub fn ray_intersect(&self, orig: &Vec3f, inv_dir: &Vec3f, t_max: Float) -> Option<Float> {
+        self.clip(orig, inv_dir, t_max).map(|(t0, _)| t0)
+    }
+
+    // Parametric range of the ray inside the box, clamped to [0, t_max]
+    pub fn clip(&self, orig: &Vec3f, inv_dir: &Vec3f, t_max: Float) -> Option<(Float, Float)> {
+        let mut t0: Float = 0.0;
+        let mut t1 = t_max;
+        for axis in 0..3 {
+            let near = (self.min[axis] - orig[axis]) * inv_dir[axis];
+            let far = (self.max[axis] - orig[axis]) * inv_dir[axis];
+            let (near, far) = if near > far { (far, near) } else { (near, far) };
+            // Widen the exit by the worst-case rounding error so rays grazing a face
+            // shared by neighbouring boxes are never culled from both (Ize 2013)
+            let far = far * ROUNDING_SLACK;
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+            if t0 > t1 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+}
+
+enum BvhNode {
+    Leaf {
+        bounds: Aabb,
+        items: ArenaSlice,
+    },
+    Interior {
+        bounds: Aabb,
+        left: ArenaId,
+        right: ArenaId,
+    },
+}
+
+impl BvhNode {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { bounds, .. } | BvhNode::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+// Nodes and leaf item lists live in two arenas rather than behind a box per node
+pub struct Bvh {
+    nodes: Arena<BvhNode>,
+    items: Arena<usize>,
+    root: Option<ArenaId>,
+}
+
+impl Bvh {
+    pub fn build(bounds: &[Aabb]) -> Bvh {
+        let mut order: Vec<usize> = (0..bounds.len()).collect();
+        let mut bvh = Bvh {
+            nodes: Arena::with_capacity(2 * bounds.len().div_ceil(LEAF_SIZE)),
+            items: Arena::with_capacity(bounds.len()),
+            root: None,
+        };
+        let start = log::timer(Level::Trace);
+        if !order.is_empty() {
+            bvh.root = Some(bvh.build_node(bounds, &mut order));
+        }
+        if let Some(start) = start {
+            crate::trace!(
+                "built a BVH of {} nodes over {} items in {:.3}s",
+                bvh.nodes.len(),
+                bounds.len(),
+                start.elapsed().as_secs_f64()
+            );
+        }
+        bvh
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        match self.root {
+            Some(root) => *self.nodes.get(root).bounds(),
+            None => Aabb::empty(),
+        }
+    }
+
+    // Walks the tree front to back and returns the nearest item accepted by `intersect`,
+    // which is given the distance to the nearest hit so far and must only return closer ones.
+    pub fn traverse<F>(&self, orig: &Vec3f, dir: &Vec3f, mut intersect: F) -> Option<(usize, Float)>
+    where
+        F: FnMut(usize, Float) -> Option<Float>,
+    {
+        let root = self.root?;
+        let inv_dir = Vec3f(1.0 / dir.0, 1.0 / dir.1, 1.0 / dir.2);
+        let mut nearest: Option<(usize, Float)> = None;
+        // A fixed stack keeps the walk from allocating, which it does once per ray
+        let mut stack = [root; MAX_DEPTH];
+        let mut pending = 1;
+        let mut node_tests = 0;
+
+        while pending > 0 {
+            pending -= 1;
+            let node = self.nodes.get(stack[pending]);
+            let t_max = nearest.map_or(Float::MAX, |(_, t)| t);
+            node_tests += 1;
+            if node.bounds().ray_intersect(orig, &inv_dir, t_max).is_none() {
+                continue;
+            }
+            match *node {
+                BvhNode::Leaf { items, .. } => {
+                    for &item in self.items.slice(items) {
+                        let t_max = nearest.map_or(Float::MAX, |(_, t)| t);
+                        if let Some(t) = intersect(item, t_max) {
+                            if t < t_max {
+                                nearest = Some((item, t));
+                            }
+                        }
+                    }
+                }
+                BvhNode::Interior { left, right, .. } => {
+                    node_tests += 2;
+                    let t_left = self
+                        .nodes
+                        .get(left)
+                        .bounds()
+                        .ray_intersect(orig, &inv_dir, t_max);
+                    let t_right = self
+                        .nodes
+                        .get(right)
+                        .bounds()
+                        .ray_intersect(orig, &inv_dir, t_max);
+                    let mut push = |id| {
+                        stack[pending] = id;
+                        pending += 1;
+                    };
+                    match (t_left, t_right) {
+                        (Some(l), Some(r)) if l <= r => {
+                            push(right);
+                            push(left);
+                        }
+                        (Some(_), Some(_)) => {
+                            push(left);
+                            push(right);
+                        }
+                        (Some(_), None) => push(left),
+                        (None, Some(_)) => push(right),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+
+        stats::record(|s| s.bvh_node_tests += node_tests);
+        nearest
+    }
+
+    // Calls visit with every item whose box holds point, always in the same order
+    pub fn containing<F: FnMut(usize)>(&self, point: &Vec3f, mut visit: F) {
+        let Some(root) = self.root else {
+            return;
+        };
+        let mut stack = [root; MAX_DEPTH];
+        let mut pending = 1;
+        while pending > 0 {
+            pending -= 1;
+            let node = self.nodes.get(stack[pending]);
+            if !node.bounds().contains(point) {
+                continue;
+            }
+            match *node {
+                BvhNode::Leaf { items, .. } => {
+                    self.items.slice(items).iter().for_each(|&i| visit(i))
+                }
+                BvhNode::Interior { left, right, .. } => {
+                    stack[pending] = right;
+                    stack[pending + 1] = left;
+                    pending += 2;
+                }
+            }
+        }
+    }
+
+    fn build_node(&mut self, bounds: &[Aabb], items: &mut [usize]) -> ArenaId {
+        let node_bounds = items
+            .iter()
+            .fold(Aabb::empty(), |acc, &i| acc.union(&bounds[i]));
+
+        if items.len() <= LEAF_SIZE {
+            let items = self.items.alloc_extend(items.iter().copied());
+            return self.nodes.alloc(BvhNode::Leaf {
+                bounds: node_bounds,
+                items,
+            });
+        }
+
+        // Median split along the axis with the widest spread of centroids
+        let centroid_bounds = items.iter().fold(Aabb::empty(), |acc, &i| {
+            let c = bounds[i].centroid();
+            acc.union(&Aabb::new(c, c))
+        });
+        let axis = centroid_bounds.largest_axis();
+        let mid = items.len() / 2;
+        items.select_nth_unstable_by(mid, |&a, &b| {
+            bounds[a].centroid()[axis].total_cmp(&bounds[b].centroid()[axis])
+        });
+
+        let (left_items, right_items) = items.split_at_mut(mid);
+        let left = self.build_node(bounds, left_items);
+        let right = self.build_node(bounds, right_items);
+        self.nodes.alloc(BvhNode::Interior {
+            bounds: node_bounds,
+            left,
+            right,
+        })
+    }
+}
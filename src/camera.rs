@@ -0,0 +1,729 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::bvh::Aabb;
+use crate::differential::{AuxiliaryRays, RayDifferential};
+use crate::onb::{concentric_disk, uniform_triangle};
+use crate::rng::Rng;
+use crate::scene::Scene;
+use crate::texture::ImageTexture;
+use crate::transform::Transform;
+use crate::vec3::{consts::PI, Float, Vec3f};
+
+// Tries at landing a lens sample on a bright part of an aperture mask before settling for
+// the center
+const MASK_TRIES: usize = 32;
+
+// Which image axis the field of view spans; the other follows from the aspect ratio
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FovAxis {
+    Vertical,
+    Horizontal,
+}
+
+// Flaws of real lenses, all off by default. Distortion and fringing are measured at the
+// frame corners, where they are strongest.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Lens {
+    // How much of the cos⁴ falloff of light towards the edges of the frame shows, from 0
+    // for none to 1 for all of it
+    pub vignetting: Float,
+    // Brown's radial coefficient: negative values bow straight lines outwards like a
+    // wide-angle lens (barrel), positive ones inwards like a telephoto (pincushion)
+    pub distortion: Float,
+    // How much larger the red image is than the green, and the blue smaller, as a
+    // fraction; 0.005 already shows colored fringes. Each sample traces three rays.
+    pub chromatic_aberration: Float,
+}
+
+// A lens opening wider than a pinhole, which blurs everything off the focus plane. The
+// blur of a point of light takes the shape of the opening, which is where bokeh comes from.
+#[derive(Clone, Debug)]
+pub struct Aperture {
+    // In scene units
+    pub radius: Float,
+    // Along the view axis, to the plane in sharp focus
+    pub focus_distance: Float,
+    pub shape: ApertureShape,
+}
+
+#[derive(Clone, Debug)]
+pub enum ApertureShape {
+    Round,
+    // A regular polygon, as the straight blades of an iris leave, turned by rotation
+    // radians; even the corners of the opening reach radius
+    Blades { count: u32, rotation: Float },
+    // An image over the square around the opening whose brighter parts let more through,
+    // for stars, hearts and the like
+    Mask(ApertureMask),
+}
+
+#[derive(Clone)]
+pub struct ApertureMask {
+    image: Arc<ImageTexture>,
+    brightest: Float,
+}
+
+impl ApertureMask {
+    pub fn new(image: Arc<ImageTexture>) -> ApertureMask {
+        let (_, hi) = image.range();
+        ApertureMask {
+            image,
+            brightest: hi.0.max(hi.1).max(hi.2),
+        }
+    }
+
+    // How much of the light through (x, y), in [-1, 1]^2, gets past, from 0 to 1
+    fn transmission(&self, x: Float, y: Float) -> Float {
+        let texel = self
+            .image
+            .sample_level((x + 1.0) / 2.0, (y + 1.0) / 2.0, 0.0);
+        texel.0.max(texel.1).max(texel.2) / self.brightest
+    }
+}
+
+impl fmt::Debug for ApertureMask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ApertureMask({}x{})",
+            self.image.width(),
+            self.image.height()
+        )
+    }
+}
+
+impl Aperture {
+    pub fn new(radius: Float, focus_distance: Float) -> Aperture {
+        Aperture {
+            radius,
+            focus_distance,
+            shape: ApertureShape::Round,
+        }
+    }
+
+    // A point on the opening, with the unit disk's scale: rays leave the lens from it
+    // times radius
+    pub fn sample(&self, rng: &mut Rng) -> (Float, Float) {
+        match &self.shape {
+            ApertureShape::Round => concentric_disk(rng.next_float(), rng.next_float()),
+            ApertureShape::Blades { count, rotation } => {
+                // Pick one of the equal triangles between the center and two neighbouring
+                // corners, then a point uniformly inside it
+                let count = (*count).max(3) as Float;
+                let u = rng.next_float() * count;
+                let sector = u.floor().min(count - 1.0);
+                let (b1, b2) = uniform_triangle(u - sector, rng.next_float());
+                let corner = |i: Float| {
+                    let angle = rotation + 2.0 * PI * i / count;
+                    (angle.cos(), angle.sin())
+                };
+                let (a, b) = (corner(sector), corner(sector + 1.0));
+                let b3 = 1.0 - b1 - b2;
+                (a.0 * b2 + b.0 * b3, a.1 * b2 + b.1 * b3)
+            }
+            ApertureShape::Mask(mask) => {
+                for _ in 0..MASK_TRIES {
+                    let (x, y) = (2.0 * rng.next_float() - 1.0, 2.0 * rng.next_float() - 1.0);
+                    if rng.next_float() < mask.transmission(x, y) {
+                        return (x, y);
+                    }
+                }
+                (0.0, 0.0)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Camera {
+    pub position: Vec3f,
+    pub target: Vec3f,
+    pub up: Vec3f,
+    // Field of view in radians along fov_axis
+    pub fov: Float,
+    pub fov_axis: FovAxis,
+    // Distances along the view axis; primary rays start at the near plane and see
+    // nothing beyond the far one
+    pub near: Float,
+    pub far: Float,
+    // Scales the light reaching the image before it is clamped for output; 1 shows the
+    // scene as its lights describe it
+    pub exposure: Float,
+    pub lens: Lens,
+    // A pinhole when None, with everything in focus
+    pub aperture: Option<Aperture>,
+}
+
+// The settings of the "sunny 16" rule, ISO 100 at 1/100 s and f/16, which expose a sunlit
+// scene well; lights of intensity around 1 stand in for the sun, so these leave renders
+// unchanged
+pub const SUNNY_16: (Float, Float, Float) = (100.0, 0.01, 16.0);
+
+impl Camera {
+    // Looks down the negative Z axis, like the original hard-coded camera
+    pub fn new(position: Vec3f, fov: Float) -> Camera {
+        Camera {
+            position,
+            target: position + Vec3f(0.0, 0.0, -1.0),
+            up: Vec3f(0.0, 1.0, 0.0),
+            fov,
+            fov_axis: FovAxis::Vertical,
+            near: 0.0,
+            far: Float::INFINITY,
+            exposure: 1.0,
+            lens: Lens::default(),
+            aperture: None,
+        }
+    }
+
+    pub fn with_horizontal_fov(mut self, fov: Float) -> Camera {
+        self.fov = fov;
+        self.fov_axis = FovAxis::Horizontal;
+        self
+    }
+
+    pub fn vertical_fov(&self, width: usize, height: usize) -> Float {
+        match self.fov_axis {
+            FovAxis::Vertical => self.fov,
+            FovAxis::Horizontal => {
+                let aspect = width as Float / height as Float;
+                2.0 * ((self.fov / 2.0).tan() / aspect).atan()
+            }
+        }
+    }
+
+    pub fn horizontal_fov(&self, width: usize, height: usize) -> Float {
+        match self.fov_axis {
+            FovAxis::Horizontal => self.fov,
+            FovAxis::Vertical => {
+                let aspect = width as Float / height as Float;
+                2.0 * ((self.fov / 2.0).tan() * aspect).atan()
+            }
+        }
+    }
+
+    pub fn looking_at(mut self, target: Vec3f) -> Camera {
+        self.target = target;
+        self
+    }
+
+    pub fn with_clipping(mut self, near: Float, far: Float) -> Camera {
+        self.near = near;
+        self.far = far;
+        self
+    }
+
+    // Brightens the image by 2^ev, each stop doubling the light as on a camera's
+    // exposure compensation dial
+    pub fn with_exposure_compensation(mut self, ev: Float) -> Camera {
+        self.exposure *= ev.exp2();
+        self
+    }
+
+    // Exposes as a camera with this ISO, shutter time in seconds and f-number would,
+    // relative to the sunny 16 settings: the light gathered grows with the sensitivity
+    // and the time the shutter is open and falls with the square of the f-number
+    pub fn with_photographic_exposure(
+        mut self,
+        iso: Float,
+        shutter: Float,
+        f_number: Float,
+    ) -> Camera {
+        let (base_iso, base_shutter, base_f_number) = SUNNY_16;
+        let relative = (iso / base_iso) * (shutter / base_shutter)
+            / ((f_number / base_f_number) * (f_number / base_f_number));
+        self.exposure *= relative;
+        self
+    }
+
+    // Moves the camera along its line of sight to look at the middle of scene from just
+    // far enough that a ball around all of it fits the field of view, with padding to
+    // spare as a fraction of the ball's radius. The ball is fitted along the fov's own
+    // axis, so give hfov for a frame taller than it is wide. Clipping planes and focus
+    // follow the new distance; an empty scene leaves the camera as it was.
+    pub fn frame(mut self, scene: &Scene, padding: Float) -> Camera {
+        let bounds = scene.bounds();
+        let size = bounds.max - bounds.min;
+        if !(size.0 >= 0.0 && size.1 >= 0.0 && size.2 >= 0.0) {
+            return self;
+        }
+        let center = bounds.centroid();
+        let radius = (size.length() / 2.0).max(Float::EPSILON);
+        let distance = radius * (1.0 + padding.max(0.0)) / (self.fov / 2.0).sin();
+        self.position = center - self.forward() * distance;
+        self.target = center;
+        self.near = self.near.min(distance - radius);
+        // Keep all of the ball short of the far plane and of where rays give up
+        let reach = distance + radius;
+        if self.far.is_finite() {
+            self.far = self.far.max(reach);
+        } else if reach > scene.max_distance() {
+            self.far = reach;
+        }
+        if let Some(aperture) = &mut self.aperture {
+            aperture.focus_distance = distance;
+        }
+        self
+    }
+
+    // Swings the camera and what it looks at by angle radians about the up axis through
+    // center, counter-clockwise seen from above
+    pub fn orbit(mut self, center: Vec3f, angle: Float) -> Camera {
+        let turn = Transform::around(center, self.up_axis(), angle);
+        self.position = turn.point(&self.position);
+        self.target = turn.point(&self.target);
+        self
+    }
+
+    // The up vector made unit length, +y when it has none
+    pub fn up_axis(&self) -> Vec3f {
+        self.up.normalized().unwrap_or(Vec3f(0.0, 1.0, 0.0))
+    }
+
+    fn forward(&self) -> Vec3f {
+        (self.target - self.position)
+            .normalized()
+            .unwrap_or(Vec3f(0.0, 0.0, -1.0))
+    }
+
+    // How far a primary ray leaving the near plane along dir may travel before it passes
+    // the far plane; None when there is no far plane
+    pub fn clip_distance(&self, dir: &Vec3f) -> Option<Float> {
+        if !self.far.is_finite() {
+            return None;
+        }
+        let cos = dir.dot(&self.forward()).max(Float::EPSILON);
+        Some(((self.far - self.near) / cos).max(0.0))
+    }
+
+    pub fn with_lens(mut self, lens: Lens) -> Camera {
+        self.lens = lens;
+        self
+    }
+
+    pub fn with_aperture(mut self, aperture: Aperture) -> Camera {
+        self.aperture = Some(aperture);
+        self
+    }
+
+    // Where on the aperture the next primary ray leaves from, for passing to
+    // channel_ray_differential; the center for a pinhole, without drawing from rng
+    pub fn sample_aperture(&self, rng: &mut Rng) -> (Float, Float) {
+        match &self.aperture {
+            Some(aperture) => aperture.sample(rng),
+            None => (0.0, 0.0),
+        }
+    }
+
+    // The share of light reaching the image along dir after vignetting
+    pub fn falloff(&self, dir: &Vec3f) -> Float {
+        if self.lens.vignetting == 0.0 {
+            return 1.0;
+        }
+        let cos = dir.dot(&self.forward()).clamp(0.0, 1.0);
+        let natural = cos * cos * cos * cos;
+        1.0 - self.lens.vignetting * (1.0 - natural)
+    }
+
+    // Primary ray through the continuous pixel position (x, y), y growing downwards,
+    // starting on the near plane
+    pub fn ray(&self, x: Float, y: Float, width: usize, height: usize) -> (Vec3f, Vec3f) {
+        self.magnified_ray(x, y, width, height, 1.0, (0.0, 0.0))
+    }
+
+    // As ray, for an image this much larger about the center of the frame, which is how
+    // one color channel sees a lens with chromatic aberration, and leaving from lens_point
+    // on the aperture
+    fn magnified_ray(
+        &self,
+        x: Float,
+        y: Float,
+        width: usize,
+        height: usize,
+        magnification: Float,
+        lens_point: (Float, Float),
+    ) -> (Vec3f, Vec3f) {
+        let forward = self.forward();
+        let right = forward
+            .cross(&self.up)
+            .normalized()
+            .unwrap_or(Vec3f(1.0, 0.0, 0.0));
+        let up = right.cross(&forward);
+
+        let dir_x = x - width as Float / 2.0;
+        let dir_y = -y + height as Float / 2.0;
+        // The inverse of Brown's model to first order, from where a point lands on the
+        // image back to where an ideal lens would have put it, r running to 1 at corners
+        let corner = (width * width + height * height) as Float / 4.0;
+        let r2 = (dir_x * dir_x + dir_y * dir_y) / corner;
+        let scale = (1.0 - self.lens.distortion * r2) / magnification;
+        let (dir_x, dir_y) = (dir_x * scale, dir_y * scale);
+        let dir_z = height as Float / (2.0 * (self.vertical_fov(width, height) / 2.0).tan());
+
+        let mut dir = (right * dir_x + up * dir_y + forward * dir_z)
+            .normalized()
+            .unwrap_or(forward);
+        let mut orig = self.position;
+        // Every ray through the lens that the pinhole ray would take meets it again on the
+        // focus plane
+        if let Some(aperture) = self.aperture.as_ref().filter(|_| lens_point != (0.0, 0.0)) {
+            let focus =
+                orig + dir * (aperture.focus_distance / dir.dot(&forward).max(Float::EPSILON));
+            orig += (right * lens_point.0 + up * lens_point.1) * aperture.radius;
+            dir = (focus - orig).normalized().unwrap_or(dir);
+        }
+        if self.near > 0.0 {
+            let t = self.near / dir.dot(&forward).max(Float::EPSILON);
+            (orig + dir * t, dir)
+        } else {
+            (orig, dir)
+        }
+    }
+
+    // Primary ray with differentials towards the next pixel in x and y
+    pub fn ray_differential(
+        &self,
+        x: Float,
+        y: Float,
+        width: usize,
+        height: usize,
+    ) -> RayDifferential {
+        self.channel_ray_differential(x, y, width, height, 1, (0.0, 0.0))
+    }
+
+    // The red (channel 0), green or blue ray of chromatic aberration, leaving from
+    // lens_point as sample_aperture gives it
+    pub fn channel_ray_differential(
+        &self,
+        x: Float,
+        y: Float,
+        width: usize,
+        height: usize,
+        channel: usize,
+        lens_point: (Float, Float),
+    ) -> RayDifferential {
+        let magnification = 1.0 + self.lens.chromatic_aberration * (1.0 - channel as Float);
+        let ray = |x, y| self.magnified_ray(x, y, width, height, magnification, lens_point);
+        let (orig, dir) = ray(x, y);
+        let (rx_orig, rx_dir) = ray(x + 1.0, y);
+        let (ry_orig, ry_dir) = ray(x, y + 1.0);
+        RayDifferential {
+            orig,
+            dir,
+            aux: Some(AuxiliaryRays {
+                rx_orig,
+                rx_dir,
+                ry_orig,
+                ry_dir,
+            }),
+        }
+    }
+
+    // Bounds every primary ray of a width by height image can follow, with samples
+    // reaching margin pixels past its edges; None when the view is too wide to bound
+    // with planes. Lens distortion can push the widest rays inside the frame rather
+    // than on its edge, so directions are taken over a grid across the whole image.
+    pub fn frustum(&self, width: usize, height: usize, margin: Float) -> Option<Frustum> {
+        const STEPS: usize = 32;
+        let forward = self.forward();
+        let right = forward.cross(&self.up).normalized()?;
+        let up = right.cross(&forward);
+        let channels = if self.lens.chromatic_aberration == 0.0 {
+            1..2
+        } else {
+            0..3
+        };
+        // Extents of the directions' slopes across the view
+        let (mut x_min, mut x_max) = (Float::INFINITY, Float::NEG_INFINITY);
+        let (mut y_min, mut y_max) = (Float::INFINITY, Float::NEG_INFINITY);
+        for i in 0..=STEPS {
+            for j in 0..=STEPS {
+                let x = -margin + (width as Float + 2.0 * margin) * i as Float / STEPS as Float;
+                let y = -margin + (height as Float + 2.0 * margin) * j as Float / STEPS as Float;
+                for channel in channels.clone() {
+                    let dir = self
+                        .channel_ray_differential(x, y, width, height, channel, (0.0, 0.0))
+                        .dir;
+                    let depth = dir.dot(&forward);
+                    if depth <= 0.01 {
+                        return None;
+                    }
+                    let (sx, sy) = (dir.dot(&right) / depth, dir.dot(&up) / depth);
+                    x_min = x_min.min(sx);
+                    x_max = x_max.max(sx);
+                    y_min = y_min.min(sy);
+                    y_max = y_max.max(sy);
+                }
+            }
+        }
+        // Slack for the curve between grid points
+        let pad = 0.02 * (x_max - x_min).max(y_max - y_min) + 1e-4;
+        // An aperture moves ray origins up to its radius off the axis on each side and
+        // spreads them by radius over focus distance per unit of depth past the focus
+        // plane, as derived from origin + (focus point - origin) * s
+        let (offset, spread) = match &self.aperture {
+            Some(aperture) => (
+                aperture.radius,
+                aperture.radius / aperture.focus_distance.max(Float::EPSILON),
+            ),
+            None => (0.0, 0.0),
+        };
+        let slope = |side: Vec3f, along: Float| side - forward * (along + spread + pad);
+        let mut planes = vec![
+            (slope(right, x_max), offset),
+            (slope(-right, -x_min), offset),
+            (slope(up, y_max), offset),
+            (slope(-up, -y_min), offset),
+        ];
+        if self.far.is_finite() {
+            planes.push((forward, self.far));
+        }
+        Some(Frustum {
+            apex: self.position,
+            planes,
+        })
+    }
+}
+
+// A region of space bounded by planes, each (normal, offset) keeping the points where
+// normal · (point - apex) <= offset
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frustum {
+    apex: Vec3f,
+    planes: Vec<(Vec3f, Float)>,
+}
+
+impl Frustum {
+    // False only when bounds lies wholly outside one of the planes; a box can be outside
+    // the frustum and still pass, near its edges
+    pub fn may_contain(&self, bounds: &Aabb) -> bool {
+        let (lo, hi) = (bounds.min - self.apex, bounds.max - self.apex);
+        self.planes.iter().all(|(normal, offset)| {
+            // The corner furthest inside the plane
+            let pick = |n: Float, lo: Float, hi: Float| n * if n > 0.0 { lo } else { hi };
+            let nearest = pick(normal.0, lo.0, hi.0)
+                + pick(normal.1, lo.1, hi.1)
+                + pick(normal.2, lo.2, hi.2);
+            // NaN from infinite bounds keeps the box
+            nearest <= *offset || nearest.is_nan()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The angle between the rays through the middles of opposite edges of the image
+    fn spans(camera: &Camera, width: usize, height: usize) -> (Float, Float) {
+        let (w, h) = (width as Float, height as Float);
+        let angle = |a: (Float, Float), b: (Float, Float)| {
+            let (_, a) = camera.ray(a.0, a.1, width, height);
+            let (_, b) = camera.ray(b.0, b.1, width, height);
+            a.dot(&b).clamp(-1.0, 1.0).acos()
+        };
+        (
+            angle((0.0, h / 2.0), (w, h / 2.0)),
+            angle((w / 2.0, 0.0), (w / 2.0, h)),
+        )
+    }
+
+    #[test]
+    fn field_of_view_spans_the_axis_it_was_given() {
+        let close = |a: Float, b: Float| (a - b).abs() < 1e-4;
+        let vertical = Camera::new(Vec3f(0.0, 0.0, 0.0), PI / 3.0);
+        let horizontal = Camera::new(Vec3f(0.0, 0.0, 0.0), PI / 2.0).with_horizontal_fov(PI / 2.0);
+        for (width, height) in [(200, 100), (100, 200), (64, 64)] {
+            let (across, down) = spans(&vertical, width, height);
+            assert!(close(down, PI / 3.0), "{}x{}: {}", width, height, down);
+            assert!(close(across, vertical.horizontal_fov(width, height)));
+            let (across, down) = spans(&horizontal, width, height);
+            assert!(close(across, PI / 2.0), "{}x{}: {}", width, height, across);
+            assert!(close(down, horizontal.vertical_fov(width, height)));
+        }
+        // 90 degrees across a 2:1 frame leaves half the tangent down it
+        assert!(close(
+            horizontal.vertical_fov(200, 100),
+            2.0 * (0.5 as Float).atan()
+        ));
+        // Either way round, converting there and back lands where it started
+        let there = vertical.horizontal_fov(300, 100);
+        let back = Camera::new(Vec3f(0.0, 0.0, 0.0), PI / 2.0).with_horizontal_fov(there);
+        assert!(close(back.vertical_fov(300, 100), PI / 3.0));
+        assert!(close(vertical.horizontal_fov(64, 64), PI / 3.0));
+    }
+
+    #[test]
+    fn exposure_follows_stops_and_photographic_settings() {
+        let camera = Camera::new(Vec3f(0.0, 0.0, 0.0), PI / 3.0);
+        assert_eq!(camera.exposure, 1.0);
+        let close = |camera: Camera, expected: Float| {
+            assert!(
+                (camera.exposure - expected).abs() < 1e-4 * expected,
+                "{} against {}",
+                camera.exposure,
+                expected
+            );
+        };
+        let (iso, shutter, f_number) = SUNNY_16;
+        close(
+            camera
+                .clone()
+                .with_photographic_exposure(iso, shutter, f_number),
+            1.0,
+        );
+        close(camera.clone().with_exposure_compensation(1.0), 2.0);
+        close(camera.clone().with_exposure_compensation(-2.0), 0.25);
+        // Each of twice the sensitivity, twice the time and a stop wider doubles the light
+        close(
+            camera.clone().with_photographic_exposure(200.0, 0.01, 16.0),
+            2.0,
+        );
+        close(
+            camera.clone().with_photographic_exposure(100.0, 0.02, 16.0),
+            2.0,
+        );
+        close(
+            camera.clone().with_photographic_exposure(
+                100.0,
+                0.01,
+                16.0 / crate::vec3::consts::SQRT_2,
+            ),
+            2.0,
+        );
+        close(
+            camera
+                .clone()
+                .with_photographic_exposure(400.0, 0.01, 32.0)
+                .with_exposure_compensation(-1.0),
+            0.5,
+        );
+    }
+
+    #[test]
+    fn lenses_darken_bend_and_split_towards_the_corners() {
+        let camera = Camera::new(Vec3f(0.0, 0.0, 0.0), PI / 2.0);
+        let forward = Vec3f(0.0, 0.0, -1.0);
+        // Off axis, as the corner pixel of a 64 by 48 image is
+        let off_axis = |camera: &Camera, channel| {
+            let ray = camera.channel_ray_differential(0.0, 0.0, 64, 48, channel, (0.0, 0.0));
+            ray.dir.dot(&forward).acos()
+        };
+        let (_, corner) = camera.ray(0.0, 0.0, 64, 48);
+        let (_, middle) = camera.ray(32.0, 24.0, 64, 48);
+        assert_eq!(camera.falloff(&corner), 1.0);
+
+        let vignetted = |vignetting| Lens {
+            vignetting,
+            ..Lens::default()
+        };
+        let full = camera.clone().with_lens(vignetted(1.0));
+        let cos = corner.dot(&forward);
+        assert!((full.falloff(&corner) - cos.powi(4)).abs() < 1e-5);
+        assert!((full.falloff(&middle) - 1.0).abs() < 1e-5);
+        let half = camera.clone().with_lens(vignetted(0.5));
+        assert!((half.falloff(&corner) - (1.0 + cos.powi(4)) / 2.0).abs() < 1e-5);
+
+        // Barrel distortion fits more of the scene into the corners, pincushion less
+        let ideal = off_axis(&camera, 1);
+        let distorted = |distortion| {
+            let lens = Lens {
+                distortion,
+                ..Lens::default()
+            };
+            off_axis(&camera.clone().with_lens(lens), 1)
+        };
+        assert!(distorted(-0.2) > ideal && distorted(0.2) < ideal);
+        let (_, straight) = camera
+            .clone()
+            .with_lens(Lens {
+                distortion: -0.2,
+                ..Lens::default()
+            })
+            .ray(32.0, 24.0, 64, 48);
+        assert!((straight - middle).length() < 1e-6);
+
+        // The red image is the larger, so its corner sees less of the scene than blue's
+        let fringed = camera.clone().with_lens(Lens {
+            chromatic_aberration: 0.01,
+            ..Lens::default()
+        });
+        assert!(off_axis(&fringed, 0) < ideal && off_axis(&fringed, 2) > ideal);
+        assert!((off_axis(&fringed, 1) - ideal).abs() < 1e-6);
+    }
+
+    #[test]
+    fn apertures_sample_their_shape_and_focus_on_one_plane() {
+        let mut rng = Rng::new(7);
+        let points = |aperture: &Aperture, rng: &mut Rng| -> Vec<(Float, Float)> {
+            (0..4000).map(|_| aperture.sample(rng)).collect()
+        };
+        let reach =
+            |points: &[(Float, Float)]| points.iter().map(|p| p.0.hypot(p.1)).fold(0.0, Float::max);
+
+        // Uniform over the disk, so half the points lie within 1/√2 of the middle
+        let round = points(&Aperture::new(1.0, 1.0), &mut rng);
+        let r2 = round.iter().map(|p| p.0 * p.0 + p.1 * p.1).sum::<Float>() / 4000.0;
+        assert!((r2 - 0.5).abs() < 0.03, "{}", r2);
+        assert!(reach(&round) <= 1.0 && reach(&round) > 0.98);
+
+        // Inside the hexagon the blades leave, and out into its corners
+        let rotation = 0.3;
+        let hexagon = Aperture {
+            shape: ApertureShape::Blades { count: 6, rotation },
+            ..Aperture::new(1.0, 1.0)
+        };
+        let bladed = points(&hexagon, &mut rng);
+        let corner = |i: Float| {
+            let angle = rotation + PI / 3.0 * i;
+            (angle.cos(), angle.sin())
+        };
+        for p in &bladed {
+            for i in 0..6 {
+                let (a, b) = (corner(i as Float), corner(i as Float + 1.0));
+                let side = (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0);
+                assert!(side >= -1e-5, "{:?} outside edge {}", p, i);
+            }
+        }
+        assert!(reach(&bladed) > 0.95);
+
+        // Light only through the middle of the mask
+        let texels = (0..64)
+            .map(|i| {
+                let inside = (2..6).contains(&(i % 8)) && (2..6).contains(&(i / 8));
+                if inside {
+                    Vec3f(1.0, 1.0, 1.0)
+                } else {
+                    Vec3f(0.0, 0.0, 0.0)
+                }
+            })
+            .collect();
+        let mask = ApertureMask::new(Arc::new(ImageTexture::new(8, 8, texels)));
+        let masked = Aperture {
+            shape: ApertureShape::Mask(mask),
+            ..Aperture::new(1.0, 1.0)
+        };
+        let through = points(&masked, &mut rng);
+        assert!(through.iter().all(|p| p.0.abs() < 0.65 && p.1.abs() < 0.65));
+        assert!(through.iter().any(|p| p.0 > 0.4) && through.iter().any(|p| p.1 < -0.4));
+
+        // Rays through one pixel from anywhere on the lens cross the focus plane together
+        let camera = Camera {
+            aperture: Some(Aperture::new(0.5, 4.0)),
+            ..Camera::new(Vec3f(0.0, 0.0, 0.0), PI / 3.0)
+        };
+        let on_focus_plane = |lens_point| {
+            let ray = camera.channel_ray_differential(10.0, 5.0, 32, 24, 1, lens_point);
+            ray.orig + ray.dir * ((-4.0 - ray.orig.2) / ray.dir.2)
+        };
+        let sharp = on_focus_plane((0.0, 0.0));
+        for lens_point in [(1.0, 0.0), (0.0, -1.0), (-0.6, 0.8)] {
+            let ray = camera.channel_ray_differential(10.0, 5.0, 32, 24, 1, lens_point);
+            assert!(
+                (ray.orig - Vec3f(0.5 * lens_point.0, 0.5 * lens_point.1, 0.0)).length() < 1e-5
+            );
+            assert!((on_focus_plane(lens_point) - sharp).length() < 1e-4);
+        }
+    }
+}
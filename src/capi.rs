@@ -0,0 +1,271 @@
+// C interface for embedding the tracer; the matching declarations are in
+// include/rusty_rays.h. Everything crosses the boundary as float regardless of the
+// f64 feature, and failures are reported through rr_last_error.
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::slice;
+
+use crate::camera::Camera;
+use crate::light::Light;
+use crate::material::{Material, Sides};
+use crate::mesh::TriangleMesh;
+use crate::render::{render, Integrator, RenderSettings};
+use crate::scene::{Scene, Severity};
+use crate::scene_file::MATERIAL_NAMES;
+use crate::shapes::Sphere;
+use crate::vec3::{consts::PI, Float, Vec3f};
+
+// Returned by the add functions when the object could not be created
+pub const RR_INVALID_ID: u32 = u32::MAX;
+
+pub const RR_WHITTED: c_int = 0;
+pub const RR_PATH: c_int = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RrMaterial {
+    pub diffuse: [f32; 3],
+    pub albedo: [f32; 4],
+    pub specular_exponent: f32,
+    pub refractive_index: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RrCamera {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    pub up: [f32; 3],
+    // Vertical field of view in degrees
+    pub fov: f32,
+}
+
+// The opaque rr_scene handle: a scene and the camera it is rendered from
+pub struct RrScene {
+    scene: Scene,
+    camera: Camera,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn fail(message: String) {
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|error| *error.borrow_mut() = message);
+}
+
+fn vec3(v: &[f32; 3]) -> Vec3f {
+    Vec3f(v[0] as Float, v[1] as Float, v[2] as Float)
+}
+
+fn array3(v: &Vec3f) -> [f32; 3] {
+    [v.0 as f32, v.1 as f32, v.2 as f32]
+}
+
+impl From<&RrMaterial> for Material {
+    fn from(m: &RrMaterial) -> Material {
+        Material {
+            refractive_index: m.refractive_index as Float,
+            albedo: m.albedo.map(|a| a as Float),
+            diffuse_color: vec3(&m.diffuse),
+            specular_exponent: m.specular_exponent as Float,
+            thin_film: None,
+            anisotropy: None,
+            specular_color: None,
+            clearcoat: None,
+            sheen: None,
+            sides: Sides::Front,
+            dispersion: None,
+            shadow_catcher: None,
+            priority: 0,
+            emission: None,
+        }
+    }
+}
+
+// A null material means the first built-in one, as in the Python bindings
+unsafe fn material(material: *const RrMaterial) -> Material {
+    material
+        .as_ref()
+        .map_or(MATERIAL_NAMES[0].1, Material::from)
+}
+
+// The message for the last call on this thread that failed, valid until the next failure
+#[no_mangle]
+pub extern "C" fn rr_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ptr())
+}
+
+/// # Safety
+/// name must be a NUL-terminated string and out must point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn rr_material_named(name: *const c_char, out: *mut RrMaterial) -> c_int {
+    let name = CStr::from_ptr(name).to_string_lossy();
+    match MATERIAL_NAMES.iter().find(|(n, _)| *n == name) {
+        Some((_, m)) => {
+            *out = RrMaterial {
+                diffuse: array3(&m.diffuse_color),
+                albedo: m.albedo.map(|a| a as f32),
+                specular_exponent: m.specular_exponent as f32,
+                refractive_index: m.refractive_index as f32,
+            };
+            0
+        }
+        None => {
+            fail(format!("unknown material {}", name));
+            -1
+        }
+    }
+}
+
+// An empty scene seen from the origin down the negative Z axis
+#[no_mangle]
+pub extern "C" fn rr_scene_new() -> *mut RrScene {
+    Box::into_raw(Box::new(RrScene {
+        scene: Scene::new(),
+        camera: Camera::new(Vec3f(0.0, 0.0, 0.0), PI / 3.0),
+    }))
+}
+
+/// # Safety
+/// scene must come from rr_scene_new and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rr_scene_free(scene: *mut RrScene) {
+    if !scene.is_null() {
+        drop(Box::from_raw(scene));
+    }
+}
+
+/// # Safety
+/// scene must be live; material may be null.
+#[no_mangle]
+pub unsafe extern "C" fn rr_scene_add_sphere(
+    scene: *mut RrScene,
+    center: *const [f32; 3],
+    radius: f32,
+    material: *const RrMaterial,
+) -> u32 {
+    let scene = &mut (*scene).scene;
+    scene.add(
+        Sphere::new(vec3(&*center), radius as Float),
+        self::material(material),
+    )
+}
+
+/// Adds a triangle mesh from vertex_count xyz positions and triangle_count index triples.
+///
+/// # Safety
+/// scene must be live, positions must hold 3 * vertex_count floats and indices
+/// 3 * triangle_count integers; material may be null.
+#[no_mangle]
+pub unsafe extern "C" fn rr_scene_add_mesh(
+    scene: *mut RrScene,
+    positions: *const f32,
+    vertex_count: usize,
+    indices: *const u32,
+    triangle_count: usize,
+    material: *const RrMaterial,
+) -> u32 {
+    let positions = slice::from_raw_parts(positions, vertex_count * 3);
+    let indices = slice::from_raw_parts(indices, triangle_count * 3);
+    if let Some(index) = indices.iter().find(|&&i| i as usize >= vertex_count) {
+        fail(format!("vertex index {} out of range", index));
+        return RR_INVALID_ID;
+    }
+    let vertices = positions
+        .chunks_exact(3)
+        .map(|p| Vec3f(p[0] as Float, p[1] as Float, p[2] as Float))
+        .collect();
+    let faces = indices
+        .chunks_exact(3)
+        .map(|f| [f[0] as usize, f[1] as usize, f[2] as usize])
+        .collect();
+    let scene = &mut (*scene).scene;
+    scene.add(TriangleMesh::new(vertices, faces), self::material(material))
+}
+
+/// # Safety
+/// scene must be live.
+#[no_mangle]
+pub unsafe extern "C" fn rr_scene_add_light(
+    scene: *mut RrScene,
+    position: *const [f32; 3],
+    intensity: f32,
+) {
+    (*scene)
+        .scene
+        .add_light(Light::new(vec3(&*position), intensity as Float));
+}
+
+/// # Safety
+/// scene must be live.
+#[no_mangle]
+pub unsafe extern "C" fn rr_scene_set_background(scene: *mut RrScene, color: *const [f32; 3]) {
+    (*scene).scene.background = vec3(&*color);
+}
+
+/// # Safety
+/// scene and camera must be live.
+#[no_mangle]
+pub unsafe extern "C" fn rr_scene_set_camera(scene: *mut RrScene, camera: *const RrCamera) {
+    let camera = &*camera;
+    let mut view = Camera::new(vec3(&camera.position), (camera.fov as Float).to_radians())
+        .looking_at(vec3(&camera.target));
+    view.up = vec3(&camera.up);
+    (*scene).camera = view;
+}
+
+/// Renders width × height pixels into rgba as 8-bit straight-alpha RGBA, row-major from
+/// the top left, with RR_WHITTED or RR_PATH. Returns 0 on success.
+///
+/// # Safety
+/// scene must be live and rgba must point to rgba_len writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rr_scene_render(
+    scene: *const RrScene,
+    width: u32,
+    height: u32,
+    samples: u32,
+    integrator: c_int,
+    rgba: *mut u8,
+    rgba_len: usize,
+) -> c_int {
+    let RrScene { scene, camera } = &*scene;
+    let needed = width as usize * height as usize * 4;
+    if width == 0 || height == 0 || rgba_len < needed {
+        fail(format!(
+            "a {}x{} image needs {} bytes, got {}",
+            width, height, needed, rgba_len
+        ));
+        return -1;
+    }
+    let integrator = match integrator {
+        RR_WHITTED => Integrator::Whitted,
+        RR_PATH => Integrator::Path,
+        _ => {
+            fail(format!("unknown integrator {}", integrator));
+            return -1;
+        }
+    };
+    let errors: Vec<String> = scene
+        .validate()
+        .into_iter()
+        .filter(|d| d.severity == Severity::Error)
+        .map(|d| d.to_string())
+        .collect();
+    if !errors.is_empty() {
+        fail(errors.join("\n"));
+        return -1;
+    }
+    let settings = RenderSettings {
+        width: width as usize,
+        height: height as usize,
+        samples_per_pixel: samples.max(1),
+        integrator,
+        ..RenderSettings::default()
+    };
+    let pixels = render(scene, camera, &settings).to_rgba8();
+    slice::from_raw_parts_mut(rgba, needed).copy_from_slice(&pixels);
+    0
+}
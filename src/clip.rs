@@ -0,0 +1,140 @@
+// Planes that cut away the part of every object on the side their normal faces, for
+// cutaway and section views. Where a plane slices through a solid the cut can be capped
+// with a flat surface so the object does not look hollow; whether the ray is inside the
+// solid comes from the next face it meets being a back face, so caps need closed shapes.
+// The floor is never clipped.
+use crate::vec3::{Float, Vec3f};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ClipPlane {
+    pub point: Vec3f,
+    // Unit length, facing the side that is removed
+    pub normal: Vec3f,
+    // The color of the surface left where the plane cuts through a solid; None leaves
+    // the cut open
+    pub cap: Option<Vec3f>,
+}
+
+impl ClipPlane {
+    // None when normal has no direction
+    pub fn new(point: Vec3f, normal: Vec3f, cap: Option<Vec3f>) -> Option<ClipPlane> {
+        Some(ClipPlane {
+            point,
+            normal: normal.normalized()?,
+            cap,
+        })
+    }
+
+    pub fn removes(&self, point: &Vec3f) -> bool {
+        (*point - self.point).dot(&self.normal) > 0.0
+    }
+}
+
+// The stretch of a ray that every plane keeps, and the plane it enters that stretch
+// through if it starts out cut away
+#[derive(Clone, Copy, Debug)]
+pub struct ClipWindow {
+    pub near: Float,
+    pub far: Float,
+    pub entry: Option<ClipPlane>,
+}
+
+impl ClipWindow {
+    // None when the planes leave none of the ray
+    pub fn new(planes: &[ClipPlane], orig: &Vec3f, dir: &Vec3f) -> Option<ClipWindow> {
+        let mut window = ClipWindow {
+            near: 0.0,
+            far: Float::INFINITY,
+            entry: None,
+        };
+        for plane in planes {
+            // Along the ray the point at t is cut away once t * along > ahead
+            let ahead = (plane.point - *orig).dot(&plane.normal);
+            let along = dir.dot(&plane.normal);
+            if along == 0.0 {
+                if ahead < 0.0 {
+                    return None;
+                }
+                continue;
+            }
+            let t = ahead / along;
+            if along > 0.0 {
+                window.far = window.far.min(t);
+            } else if t > window.near {
+                window.near = t;
+                window.entry = Some(*plane);
+            }
+        }
+        (window.near < window.far).then_some(window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::RED_RUBBER;
+    use crate::scene::Scene;
+    use crate::shapes::Sphere;
+
+    #[test]
+    fn windows_keep_the_stretch_every_plane_keeps() {
+        // Everything past x = 1, and everything below y = -1
+        let planes = [
+            ClipPlane::new(Vec3f(1.0, 0.0, 0.0), Vec3f(2.0, 0.0, 0.0), None).unwrap(),
+            ClipPlane::new(Vec3f(0.0, -1.0, 0.0), Vec3f(0.0, -1.0, 0.0), None).unwrap(),
+        ];
+        assert_eq!(planes[0].normal, Vec3f(1.0, 0.0, 0.0));
+        assert!(planes[0].removes(&Vec3f(1.5, 0.0, 0.0)));
+        assert!(!planes[0].removes(&Vec3f(0.5, 0.0, 0.0)));
+        assert!(ClipPlane::new(Vec3f(0.0, 0.0, 0.0), Vec3f(0.0, 0.0, 0.0), None).is_none());
+
+        let leaving = ClipWindow::new(&planes, &Vec3f(-3.0, 0.0, 0.0), &Vec3f(1.0, 0.0, 0.0));
+        let leaving = leaving.unwrap();
+        assert_eq!((leaving.near, leaving.far), (0.0, 4.0));
+        assert!(leaving.entry.is_none());
+        // Starting cut away, the ray is kept once it comes back through the plane
+        let entering = ClipWindow::new(&planes, &Vec3f(5.0, 0.0, 0.0), &Vec3f(-1.0, 0.0, 0.0));
+        let entering = entering.unwrap();
+        assert_eq!((entering.near, entering.far), (4.0, Float::INFINITY));
+        assert_eq!(entering.entry.unwrap().normal, Vec3f(1.0, 0.0, 0.0));
+        // Running alongside a plane, on its removed side or not
+        let below = ClipWindow::new(&planes, &Vec3f(0.0, -2.0, 0.0), &Vec3f(0.0, 0.0, 1.0));
+        assert!(below.is_none());
+        assert!(ClipWindow::new(&planes, &Vec3f(0.0, 0.0, 0.0), &Vec3f(0.0, 0.0, 1.0)).is_some());
+        // Starting cut away and heading further in, nothing of the ray is left
+        let corner = Vec3f(1.0, -1.0, 0.0).normalized().unwrap();
+        assert!(ClipWindow::new(&planes, &Vec3f(2.0, -0.5, 0.0), &corner).is_none());
+    }
+
+    #[test]
+    fn cuts_solids_open_or_caps_them() {
+        let cut = |cap| {
+            let mut scene = Scene::new();
+            scene.add(Sphere::new(Vec3f(0.0, 0.0, 0.0), 1.0), RED_RUBBER);
+            let plane = ClipPlane::new(Vec3f(0.0, 0.0, 0.0), Vec3f(1.0, 0.0, 0.0), cap);
+            scene.clip_planes.push(plane.unwrap());
+            scene
+        };
+        let orig = Vec3f(5.0, 0.0, 0.0);
+        let dir = Vec3f(-1.0, 0.0, 0.0);
+
+        // Open, the ray passes the cut and meets the inside of the far half
+        let hit = cut(None).intersect(&orig, &dir).unwrap();
+        assert!((hit.record.t - 6.0).abs() < 1e-4 && !hit.record.front_face);
+        // Capped, it stops on the cut in the cap's color
+        let hit = cut(Some(Vec3f(0.0, 1.0, 0.0)))
+            .intersect(&orig, &dir)
+            .unwrap();
+        assert!((hit.record.t - 5.0).abs() < 1e-4 && hit.record.front_face);
+        assert_eq!(hit.material.diffuse_color, Vec3f(0.0, 1.0, 0.0));
+        assert_eq!(hit.record.normal, Vec3f(1.0, 0.0, 0.0));
+        // Off the solid the cap has nothing to close
+        let past = Vec3f(5.0, 2.0, 0.0);
+        assert!(cut(Some(Vec3f(0.0, 1.0, 0.0)))
+            .intersect(&past, &dir)
+            .is_none());
+        // And the half kept is untouched
+        let hit = cut(None).intersect(&Vec3f(-5.0, 0.0, 0.0), &-dir).unwrap();
+        assert!((hit.record.t - 4.0).abs() < 1e-4 && hit.record.front_face);
+    }
+}
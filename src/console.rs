@@ -0,0 +1,280 @@
+// A line-at-a-time command language for building up a scene by hand, the engine behind
+// --repl. Each command edits the scene, camera or render settings in place, so a
+// render reflects everything typed since the session began.
+use std::io;
+use std::path::Path;
+
+use crate::camera::Camera;
+use crate::light::Light;
+use crate::render::{parse_resolution, render, RenderSettings};
+use crate::scene::Scene;
+use crate::scene_file::{SceneFile, MATERIAL_NAMES};
+use crate::shapes::{Cone, Cube, Cylinder, RecgtangularPrism, Shape, Sphere, Torus};
+use crate::vec3::{Float, Vec3f};
+
+pub const HELP: &str = "\
+add sphere X Y Z RADIUS [MATERIAL]
+add cube X Y Z SIZE [MATERIAL]
+add box X0 Y0 Z0 X1 Y1 Z1 [MATERIAL]
+add cone X Y Z HEIGHT RADIUS [MATERIAL]      apex at X Y Z
+add cylinder X Y Z HEIGHT RADIUS [MATERIAL]  base at X Y Z
+add torus X Y Z TUBE_RADIUS RADIUS [MATERIAL]
+add light X Y Z INTENSITY [RADIUS]
+set camera fov DEGREES
+set camera position X Y Z
+set camera target X Y Z
+set camera exposure STOPS    relative to the lights as given
+set resolution WIDTHxHEIGHT
+set samples COUNT
+set background R G B
+list                   objects and lights in the scene
+materials              names add accepts
+clear                  remove every object and light
+load SCENE_FILE        replace the scene and camera with a file's
+render PATH            write the image as PNG or PPM by extension
+help
+quit";
+
+// What the caller should do after a command
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+    // Print this, if anything, and read the next command
+    Continue(Option<String>),
+    Quit,
+}
+
+pub struct Console {
+    pub scene: Scene,
+    pub camera: Camera,
+    pub settings: RenderSettings,
+}
+
+impl Console {
+    pub fn new(scene: Scene, camera: Camera, settings: RenderSettings) -> Console {
+        Console {
+            scene,
+            camera,
+            settings,
+        }
+    }
+
+    // Runs one command; blank lines and lines starting with # do nothing. A failed
+    // command leaves everything as it was.
+    pub fn execute(&mut self, line: &str) -> io::Result<Reply> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first().filter(|_| !line.starts_with('#')) else {
+            return Ok(Reply::Continue(None));
+        };
+        let reply = match command {
+            "add" => self.add(args)?,
+            "set" => self.set(args)?,
+            "list" => Some(self.list()),
+            "materials" => Some(
+                MATERIAL_NAMES
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            "clear" => {
+                let background = self.scene.background;
+                self.scene = Scene::new();
+                self.scene.background = background;
+                None
+            }
+            "load" => {
+                let [path] = args else {
+                    return Err(usage("load SCENE_FILE"));
+                };
+                let file = SceneFile::load(Path::new(path))?;
+                file.apply(&mut self.settings);
+                self.scene = file.scene;
+                self.camera = file.camera;
+                Some(format!("loaded {} objects", self.scene.objects().len()))
+            }
+            "render" => {
+                let [path] = args else {
+                    return Err(usage("render PATH"));
+                };
+                render(&self.scene, &self.camera, &self.settings).write_image(Path::new(path))?;
+                Some(format!(
+                    "wrote {}x{} to {}",
+                    self.settings.width, self.settings.height, path
+                ))
+            }
+            "help" | "?" => Some(HELP.to_string()),
+            "quit" | "exit" => return Ok(Reply::Quit),
+            other => return Err(invalid(format!("unknown command {}; try help", other))),
+        };
+        Ok(Reply::Continue(reply))
+    }
+
+    fn add(&mut self, args: &[&str]) -> io::Result<Option<String>> {
+        let Some((&kind, args)) = args.split_first() else {
+            return Err(usage("add sphere|cube|box|cone|cylinder|torus|light ..."));
+        };
+        if kind == "light" {
+            let form = "add light X Y Z INTENSITY [RADIUS]";
+            let (args, radius) = match args {
+                [rest @ .., radius] if args.len() == 5 => (rest, number(radius)?),
+                _ => (args, 0.0),
+            };
+            let [x, y, z, intensity] = numbers(args, form)?;
+            if radius < 0.0 {
+                return Err(invalid(format!("invalid light radius: {}", radius)));
+            }
+            self.scene
+                .add_light(Light::new(Vec3f(x, y, z), intensity).with_radius(radius));
+            return Ok(Some(format!("light {}", self.scene.lights.len() - 1)));
+        }
+        let (count, form) = match kind {
+            "sphere" => (4, "add sphere X Y Z RADIUS [MATERIAL]"),
+            "cube" => (4, "add cube X Y Z SIZE [MATERIAL]"),
+            "box" => (6, "add box X0 Y0 Z0 X1 Y1 Z1 [MATERIAL]"),
+            "cone" => (5, "add cone X Y Z HEIGHT RADIUS [MATERIAL]"),
+            "cylinder" => (5, "add cylinder X Y Z HEIGHT RADIUS [MATERIAL]"),
+            "torus" => (5, "add torus X Y Z TUBE_RADIUS RADIUS [MATERIAL]"),
+            other => return Err(invalid(format!("unknown object type {}", other))),
+        };
+        // The material is the one trailing word that is not a number
+        let (args, material) = match args {
+            [rest @ .., name] if args.len() == count + 1 => (rest, Some(*name)),
+            _ => (args, None),
+        };
+        let material = match material {
+            None => MATERIAL_NAMES[0].1,
+            Some(name) => MATERIAL_NAMES
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, m)| *m)
+                .ok_or_else(|| invalid(format!("unknown material {}; try materials", name)))?,
+        };
+        if args.len() != count {
+            return Err(usage(form));
+        }
+        let v = args
+            .iter()
+            .map(|w| number(w))
+            .collect::<io::Result<Vec<_>>>()?;
+        let at = Vec3f(v[0], v[1], v[2]);
+        let shape: Box<dyn Shape> = match kind {
+            "sphere" => Box::new(Sphere::new(at, v[3])),
+            "cube" => Box::new(Cube::new(at, v[3])),
+            "box" => Box::new(RecgtangularPrism::new(at, Vec3f(v[3], v[4], v[5]))),
+            "cone" => Box::new(Cone::new(at, v[3], v[4])),
+            "cylinder" => Box::new(Cylinder::new(at, v[3], v[4])),
+            _ => Box::new(Torus::new(at, v[3], v[4])),
+        };
+        let id = self.scene.add_boxed(shape, material);
+        Ok(Some(format!("object {}", id)))
+    }
+
+    fn set(&mut self, args: &[&str]) -> io::Result<Option<String>> {
+        match args {
+            ["camera", "fov", degrees] => {
+                let degrees = number(degrees)?;
+                if !(degrees > 0.0 && degrees < 180.0) {
+                    return Err(invalid(format!("invalid field of view: {}", degrees)));
+                }
+                self.camera.fov = degrees.to_radians();
+            }
+            ["camera", "position", rest @ ..] => {
+                let [x, y, z] = numbers(rest, "set camera position X Y Z")?;
+                // Keep looking the same way rather than at the old target
+                let position = Vec3f(x, y, z);
+                self.camera.target += position - self.camera.position;
+                self.camera.position = position;
+            }
+            ["camera", "target", rest @ ..] => {
+                let [x, y, z] = numbers(rest, "set camera target X Y Z")?;
+                self.camera = self.camera.clone().looking_at(Vec3f(x, y, z));
+            }
+            ["camera", "exposure", stops] => {
+                self.camera.exposure = (2.0 as Float).powf(number(stops)?);
+            }
+            ["resolution", value] => {
+                let (width, height) = parse_resolution(value)
+                    .ok_or_else(|| invalid(format!("invalid resolution {}", value)))?;
+                self.settings.width = width;
+                self.settings.height = height;
+            }
+            ["samples", count] => {
+                self.settings.samples_per_pixel = count
+                    .parse()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| invalid(format!("invalid sample count {}", count)))?;
+            }
+            ["background", rest @ ..] => {
+                let [r, g, b] = numbers(rest, "set background R G B")?;
+                self.scene.background = Vec3f(r, g, b);
+            }
+            _ => {
+                return Err(usage(
+                    "set camera fov|position|target|exposure ..., set resolution, set samples \
+                     or set background",
+                ))
+            }
+        }
+        Ok(None)
+    }
+
+    fn list(&self) -> String {
+        let mut lines: Vec<String> = self
+            .scene
+            .objects()
+            .iter()
+            .map(|object| {
+                let bounds = object.shape.bounds();
+                format!(
+                    "object {}{}: from {:?} to {:?}",
+                    object.id,
+                    object
+                        .name
+                        .as_ref()
+                        .map_or(String::new(), |name| format!(" ({})", name)),
+                    bounds.min,
+                    bounds.max
+                )
+            })
+            .collect();
+        for (i, light) in self.scene.lights.iter().enumerate() {
+            lines.push(format!(
+                "light {}: {:?} at intensity {}",
+                i, light.position, light.intensity
+            ));
+        }
+        if lines.is_empty() {
+            "the scene is empty".to_string()
+        } else {
+            lines.join("\n")
+        }
+    }
+}
+
+fn number(word: &str) -> io::Result<Float> {
+    word.parse()
+        .ok()
+        .filter(|v: &Float| v.is_finite())
+        .ok_or_else(|| invalid(format!("not a number: {}", word)))
+}
+
+// Exactly N numbers, or the usage form as the error
+fn numbers<const N: usize>(words: &[&str], form: &str) -> io::Result<[Float; N]> {
+    if words.len() != N {
+        return Err(usage(form));
+    }
+    let mut values = [0.0; N];
+    for (value, word) in values.iter_mut().zip(words) {
+        *value = number(word)?;
+    }
+    Ok(values)
+}
+
+fn usage(form: &str) -> io::Error {
+    invalid(format!("usage: {}", form))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
@@ -0,0 +1,121 @@
+// Look-development grids: thumbnails of one scene with a single parameter stepped across
+// a range, laid out row by row from the first value to the last in one image.
+use crate::camera::Camera;
+use crate::framebuffer::Framebuffer;
+use crate::material::roughness_exponent;
+use crate::scene::Scene;
+use crate::transform::Transform;
+use crate::vec3::{Float, Vec3f};
+
+// Pixels between thumbnails and around the sheet, and their color
+const GAP: usize = 4;
+const GAP_COLOR: Vec3f = Vec3f(0.1, 0.1, 0.1);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Parameter {
+    // Degrees the camera swings about the vertical through the middle of the scene
+    Orbit,
+    // Degrees every point light swings the same way
+    Light,
+    // From 0, polished, to 1, chalky, for every object's highlights
+    Roughness,
+    // A variable the scene file defines, set as --set would
+    Variable(String),
+}
+
+impl Parameter {
+    pub fn from_name(name: &str) -> Parameter {
+        match name {
+            "orbit" => Parameter::Orbit,
+            "light" => Parameter::Light,
+            "roughness" => Parameter::Roughness,
+            _ => Parameter::Variable(name.to_string()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Parameter::Orbit => "orbit",
+            Parameter::Light => "light",
+            Parameter::Roughness => "roughness",
+            Parameter::Variable(name) => name,
+        }
+    }
+
+    // Sets the parameter to value in a freshly loaded scene; variables are left to the
+    // loading
+    pub fn vary(&self, value: Float, scene: &mut Scene, camera: &mut Camera) {
+        let bounds = scene.bounds();
+        let center = if bounds.min.0 <= bounds.max.0 {
+            bounds.centroid()
+        } else {
+            camera.target
+        };
+        match self {
+            Parameter::Orbit => *camera = camera.clone().orbit(center, value.to_radians()),
+            Parameter::Light => {
+                let turn = Transform::around(center, camera.up_axis(), value.to_radians());
+                for light in &mut scene.lights {
+                    light.position = turn.point(&light.position);
+                }
+            }
+            Parameter::Roughness => {
+                let exponent = roughness_exponent(value.clamp(0.0, 1.0));
+                for material in scene.materials_mut() {
+                    material.specular_exponent = exponent;
+                }
+            }
+            Parameter::Variable(_) => {}
+        }
+    }
+}
+
+pub struct ContactSheet {
+    pub parameter: Parameter,
+    pub from: Float,
+    pub to: Float,
+    pub count: usize,
+    // Thumbnails per row
+    pub columns: usize,
+}
+
+impl ContactSheet {
+    // Lays the thumbnails out as near square as they fit
+    pub fn new(parameter: Parameter, from: Float, to: Float, count: usize) -> ContactSheet {
+        ContactSheet {
+            parameter,
+            from,
+            to,
+            count,
+            columns: (count as f64).sqrt().ceil().max(1.0) as usize,
+        }
+    }
+
+    // Evenly spaced from the first value to the last, both included
+    pub fn values(&self) -> Vec<Float> {
+        let steps = self.count.saturating_sub(1).max(1) as Float;
+        (0..self.count)
+            .map(|i| self.from + (self.to - self.from) * i as Float / steps)
+            .collect()
+    }
+
+    // The thumbnails, which should all be the same size, in rows of columns
+    pub fn compose(&self, thumbnails: &[Framebuffer]) -> Framebuffer {
+        let columns = self.columns.clamp(1, thumbnails.len().max(1));
+        let rows = thumbnails.len().div_ceil(columns);
+        let width = thumbnails.iter().map(|t| t.width).max().unwrap_or(0);
+        let height = thumbnails.iter().map(|t| t.height).max().unwrap_or(0);
+        let mut sheet =
+            Framebuffer::new(columns * (width + GAP) + GAP, rows * (height + GAP) + GAP);
+        sheet.pixels.fill(GAP_COLOR);
+        for (i, thumbnail) in thumbnails.iter().enumerate() {
+            let (column, row) = (i % columns, i / columns);
+            sheet.paste(
+                GAP + column * (width + GAP),
+                GAP + row * (height + GAP),
+                thumbnail,
+            );
+        }
+        sheet
+    }
+}
@@ -0,0 +1,245 @@
+// Smooths sampling noise away once the frame is done, by averaging each pixel with the
+// neighbours that look like it (non-local means, weighted by each pixel's own variance as
+// in Rousselle, Knaus and Zwicker 2012). Two pixels count as alike when the small patches
+// around them differ by no more than their noise explains, so edges and texture are kept
+// while flat noisy areas blur, and converged pixels are left alone. It runs over the whole
+// merged frame rather than tile by tile, so tile edges cannot show; a crop is rendered out
+// past its edges by reach() pixels so it matches the same region of a full render.
+use crate::framebuffer::Framebuffer;
+use crate::vec3::{Float, Vec3f};
+
+// Pixels either side of the center of the patches compared
+const PATCH: usize = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Denoise {
+    // How far away, in pixels, a neighbour can be and still be averaged in
+    pub radius: usize,
+    // How large a difference, in standard deviations of the noise, is still taken for
+    // noise; higher smooths more and blurs more detail
+    pub strength: Float,
+}
+
+impl Default for Denoise {
+    fn default() -> Denoise {
+        Denoise {
+            radius: 4,
+            strength: 1.0,
+        }
+    }
+}
+
+impl Denoise {
+    // How many pixels beyond its own the result at a pixel depends on
+    pub fn reach(&self) -> usize {
+        self.radius + PATCH
+    }
+
+    // Filters the pixels and alpha of image, rendered at samples_per_pixel, using the
+    // per-pixel spread of its samples; an image without one is left as it is
+    pub fn apply(&self, image: &mut Framebuffer, samples_per_pixel: u32) {
+        let (width, height) = (image.width, image.height);
+        let Some(deviation) = &image.deviation else {
+            return;
+        };
+        if width == 0 || height == 0 || self.radius == 0 {
+            return;
+        }
+        // The variance of each pixel's mean, averaged over its channels
+        let samples = samples_per_pixel.max(1) as Float;
+        let variance: Vec<Float> = deviation
+            .iter()
+            .map(|d| (d.0 * d.0 + d.1 * d.1 + d.2 * d.2) / (3.0 * samples))
+            .collect();
+        let pixels = &image.pixels;
+        let k2 = self.strength * self.strength;
+
+        let mut sum = vec![Vec3f(0.0, 0.0, 0.0); pixels.len()];
+        let mut alpha = image.alpha.as_ref().map(|_| vec![0.0; pixels.len()]);
+        let mut weights = vec![0.0; pixels.len()];
+        let mut distance = vec![0.0; pixels.len()];
+        let radius = self.radius as i64;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                // Each pixel's difference from the one at this offset, less what noise
+                // accounts for; patches then sum these over a square, so every offset
+                // costs a few passes over the frame whatever the patch size
+                for y in 0..height {
+                    for x in 0..width {
+                        let i = y * width + x;
+                        distance[i] = match offset(x, y, dx, dy, width, height) {
+                            Some(j) => {
+                                let d = pixels[i] - pixels[j];
+                                let noise = variance[i] + variance[j];
+                                let scatter = (d.0 * d.0 + d.1 * d.1 + d.2 * d.2) / 3.0;
+                                (scatter - (noise + variance[i].min(variance[j])))
+                                    / (1e-10 + k2 * noise)
+                            }
+                            None => Float::NAN,
+                        };
+                    }
+                }
+                for y in 0..height {
+                    for x in 0..width {
+                        let Some(j) = offset(x, y, dx, dy, width, height) else {
+                            continue;
+                        };
+                        let weight = (-patch_mean(&distance, x, y, width, height).max(0.0)).exp();
+                        let i = y * width + x;
+                        sum[i] += pixels[j] * weight;
+                        if let (Some(out), Some(alpha)) = (&mut alpha, &image.alpha) {
+                            out[i] += alpha[j] * weight;
+                        }
+                        weights[i] += weight;
+                    }
+                }
+            }
+        }
+        // The pixel's own weight is 1, so no total is zero
+        for (pixel, (sum, weight)) in image.pixels.iter_mut().zip(sum.iter().zip(&weights)) {
+            *pixel = *sum * (1.0 / weight);
+        }
+        if let (Some(out), Some(alpha)) = (alpha, &mut image.alpha) {
+            for (a, (sum, weight)) in alpha.iter_mut().zip(out.iter().zip(&weights)) {
+                *a = sum / weight;
+            }
+        }
+    }
+}
+
+// The index of the pixel (dx, dy) from (x, y), if it is in the frame
+fn offset(x: usize, y: usize, dx: i64, dy: i64, width: usize, height: usize) -> Option<usize> {
+    let (qx, qy) = (x as i64 + dx, y as i64 + dy);
+    ((0..width as i64).contains(&qx) && (0..height as i64).contains(&qy))
+        .then(|| qy as usize * width + qx as usize)
+}
+
+// The mean distance over the patch around (x, y), skipping pixels whose partner at the
+// offset is outside the frame
+fn patch_mean(distance: &[Float], x: usize, y: usize, width: usize, height: usize) -> Float {
+    let (mut total, mut count) = (0.0, 0);
+    for py in y.saturating_sub(PATCH)..(y + PATCH + 1).min(height) {
+        for px in x.saturating_sub(PATCH)..(x + PATCH + 1).min(width) {
+            let d = distance[py * width + px];
+            if !d.is_nan() {
+                total += d;
+                count += 1;
+            }
+        }
+    }
+    total / count.max(1) as Float
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    const SAMPLES: u32 = 16;
+
+    // A frame of the given true values with noise of deviation sigma per sample, as a
+    // render's mean of SAMPLES samples would have
+    fn noisy(
+        width: usize,
+        height: usize,
+        sigma: Float,
+        value: impl Fn(usize) -> Float,
+    ) -> Framebuffer {
+        let mut rng = Rng::new(3);
+        let mut image = Framebuffer::new(width, height);
+        let spread = sigma / (SAMPLES as Float).sqrt();
+        for (i, pixel) in image.pixels.iter_mut().enumerate() {
+            let noise = spread * (rng.next_float() - 0.5) * (12.0 as Float).sqrt();
+            *pixel = Vec3f(1.0, 1.0, 1.0) * (value(i % width) + noise);
+        }
+        image.deviation = Some(vec![Vec3f(sigma, sigma, sigma); width * height]);
+        image
+    }
+
+    fn error(image: &Framebuffer, value: impl Fn(usize) -> Float) -> Float {
+        let total: Float = image
+            .pixels
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.0 - value(i % image.width)).powi(2))
+            .sum();
+        (total / image.pixels.len() as Float).sqrt()
+    }
+
+    #[test]
+    fn smooths_flat_noise_and_keeps_edges() {
+        let flat = |_| 0.5;
+        let mut image = noisy(24, 24, 0.4, flat);
+        let before = error(&image, flat);
+        Denoise::default().apply(&mut image, SAMPLES);
+        let after = error(&image, flat);
+        assert!(after < 0.4 * before, "{} down from {}", after, before);
+
+        // A step far bigger than the noise stays a step
+        let step = |x: usize| if x < 12 { 0.1 } else { 0.9 };
+        let mut image = noisy(24, 24, 0.1, step);
+        let before = error(&image, step);
+        Denoise::default().apply(&mut image, SAMPLES);
+        let after = error(&image, step);
+        assert!(after < before, "{} up from {}", after, before);
+        for y in 0..24 {
+            assert!(
+                image.pixels[y * 24 + 11].0 < 0.2,
+                "{:?}",
+                image.pixels[y * 24 + 11]
+            );
+            assert!(
+                image.pixels[y * 24 + 12].0 > 0.8,
+                "{:?}",
+                image.pixels[y * 24 + 12]
+            );
+        }
+    }
+
+    #[test]
+    fn leaves_converged_pixels_and_reaches_no_further_than_it_says() {
+        // Nothing to go on, nothing to do, or nothing to smooth
+        let mut image = noisy(8, 8, 0.3, |x| x as Float);
+        let original = image.pixels.clone();
+        image.deviation = None;
+        Denoise::default().apply(&mut image, SAMPLES);
+        assert_eq!(image.pixels, original);
+        image.deviation = Some(vec![Vec3f(0.3, 0.3, 0.3); 64]);
+        Denoise {
+            radius: 0,
+            ..Denoise::default()
+        }
+        .apply(&mut image, SAMPLES);
+        assert_eq!(image.pixels, original);
+        image.deviation = Some(vec![Vec3f(0.0, 0.0, 0.0); 64]);
+        Denoise::default().apply(&mut image, SAMPLES);
+        for (a, b) in image.pixels.iter().zip(&original) {
+            assert!((*a - *b).length() < 1e-6);
+        }
+
+        // Changing a pixel at reach() of another changes it, and just past leaves it be
+        let denoise = Denoise {
+            radius: 2,
+            strength: 2.0,
+        };
+        let (width, reach) = (16, denoise.reach());
+        let base = noisy(width, 4, 0.5, |_| 0.5);
+        let mut before = base.clone();
+        denoise.apply(&mut before, SAMPLES);
+        for (x, changes) in [(reach, true), (reach + 1, false)] {
+            let mut changed = base.clone();
+            changed.pixels[width + x] = Vec3f(0.8, 0.8, 0.8);
+            denoise.apply(&mut changed, SAMPLES);
+            assert_eq!(
+                changed.pixels[width] != before.pixels[width],
+                changes,
+                "{}",
+                x
+            );
+        }
+        let mut alpha = base;
+        alpha.alpha = Some((0..width * 4).map(|i| (i % 2) as Float).collect());
+        denoise.apply(&mut alpha, SAMPLES);
+        assert!(alpha.alpha.unwrap().iter().all(|a| (0.0..=1.0).contains(a)));
+    }
+}
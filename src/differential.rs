@@ -0,0 +1,148 @@
+use crate::vec3::{Float, Vec3f};
+
+// Rays through the neighbouring pixels one step right and one step down
+#[derive(Clone, Copy, Debug)]
+pub struct AuxiliaryRays {
+    pub rx_orig: Vec3f,
+    pub rx_dir: Vec3f,
+    pub ry_orig: Vec3f,
+    pub ry_dir: Vec3f,
+}
+
+// A ray carrying how its origin and direction change across the image (Igehy 1999),
+// so the footprint it covers on a surface can be estimated for texture filtering.
+// Differentials are dropped after bounces that scatter too widely to track.
+#[derive(Clone, Copy, Debug)]
+pub struct RayDifferential {
+    pub orig: Vec3f,
+    pub dir: Vec3f,
+    pub aux: Option<AuxiliaryRays>,
+}
+
+// Change in hit position per pixel along the image x and y axes
+#[derive(Clone, Copy, Debug)]
+pub struct Footprint {
+    pub dpdx: Vec3f,
+    pub dpdy: Vec3f,
+}
+
+impl Footprint {
+    // Longest side of the footprint, a conservative filter width
+    pub fn width(&self) -> Float {
+        self.dpdx.length().max(self.dpdy.length())
+    }
+}
+
+impl RayDifferential {
+    pub fn new(orig: Vec3f, dir: Vec3f) -> RayDifferential {
+        RayDifferential {
+            orig,
+            dir,
+            aux: None,
+        }
+    }
+
+    // Shrinks the differentials to match pixels split into several samples
+    pub fn scale_differentials(&mut self, scale: Float) {
+        if let Some(aux) = &mut self.aux {
+            aux.rx_orig = self.orig + (aux.rx_orig - self.orig) * scale;
+            aux.ry_orig = self.orig + (aux.ry_orig - self.orig) * scale;
+            aux.rx_dir = self.dir + (aux.rx_dir - self.dir) * scale;
+            aux.ry_dir = self.dir + (aux.ry_dir - self.dir) * scale;
+        }
+    }
+
+    // Intersects the auxiliary rays with the tangent plane at a hit point
+    pub fn footprint(&self, point: &Vec3f, normal: &Vec3f) -> Option<Footprint> {
+        let aux = self.aux.as_ref()?;
+        let plane = |orig: &Vec3f, dir: &Vec3f| {
+            let t = (point.dot(normal) - orig.dot(normal)) / dir.dot(normal);
+            t.is_finite().then(|| *orig + *dir * t - *point)
+        };
+        Some(Footprint {
+            dpdx: plane(&aux.rx_orig, &aux.rx_dir)?,
+            dpdy: plane(&aux.ry_orig, &aux.ry_dir)?,
+        })
+    }
+
+    // The mirror-reflected ray leaving orig along dir. dndx and dndy are how the normal
+    // turns across the footprint; zero treats the surface as locally flat.
+    pub fn reflected(
+        &self,
+        point: &Vec3f,
+        normal: &Vec3f,
+        orig: Vec3f,
+        dir: Vec3f,
+        dndx: Vec3f,
+        dndy: Vec3f,
+    ) -> RayDifferential {
+        let aux = self.footprint(point, normal).map(|fp| {
+            let wo = -self.dir;
+            let aux = self.aux.unwrap();
+            let bounce = |dp: Vec3f, aux_dir: Vec3f, dn: Vec3f| {
+                let dwo = -aux_dir - wo;
+                let d_dn = dwo.dot(normal) + wo.dot(&dn);
+                (
+                    orig + dp,
+                    dir - dwo + (dn * wo.dot(normal) + *normal * d_dn) * 2.0,
+                )
+            };
+            let (rx_orig, rx_dir) = bounce(fp.dpdx, aux.rx_dir, dndx);
+            let (ry_orig, ry_dir) = bounce(fp.dpdy, aux.ry_dir, dndy);
+            AuxiliaryRays {
+                rx_orig,
+                rx_dir,
+                ry_orig,
+                ry_dir,
+            }
+        });
+        RayDifferential { orig, dir, aux }
+    }
+
+    // The ray refracted into a medium of index ior (outside taken as 1) leaving orig
+    // along dir; the normal may face either side
+    #[allow(clippy::too_many_arguments)]
+    pub fn refracted(
+        &self,
+        point: &Vec3f,
+        normal: &Vec3f,
+        ior: Float,
+        orig: Vec3f,
+        dir: Vec3f,
+        dndx: Vec3f,
+        dndy: Vec3f,
+    ) -> RayDifferential {
+        let aux = self.footprint(point, normal).map(|fp| {
+            let wo = -self.dir;
+            // Relative index and normal on the side the ray arrives from
+            let (eta, n, dndx, dndy) = if wo.dot(normal) >= 0.0 {
+                (1.0 / ior, *normal, dndx, dndy)
+            } else {
+                (ior, -*normal, -dndx, -dndy)
+            };
+            let aux = self.aux.unwrap();
+            let cos_o = wo.dot(&n);
+            let cos_i = dir.dot(&n).abs();
+            let bend = |dp: Vec3f, aux_dir: Vec3f, dn: Vec3f| {
+                let dwo = -aux_dir - wo;
+                let d_dn = dwo.dot(&n) + wo.dot(&dn);
+                let mu = eta * cos_o - cos_i;
+                let dmu = if cos_i > 0.0 {
+                    (eta - eta * eta * cos_o / cos_i) * d_dn
+                } else {
+                    0.0
+                };
+                (orig + dp, dir - dwo * eta + dn * mu + n * dmu)
+            };
+            let (rx_orig, rx_dir) = bend(fp.dpdx, aux.rx_dir, dndx);
+            let (ry_orig, ry_dir) = bend(fp.dpdy, aux.ry_dir, dndy);
+            AuxiliaryRays {
+                rx_orig,
+                rx_dir,
+                ry_orig,
+                ry_dir,
+            }
+        });
+        RayDifferential { orig, dir, aux }
+    }
+}
@@ -0,0 +1,139 @@
+// What rays escaping the scene see in the direction they leave it, all around it like a
+// sky: an equirectangular image laid out as sphere textures are, or the six faces of a
+// cube map as game tooling exports them. Camera rays show it wherever there is no
+// backplate, and in the path tracer it lights the scene through every bounce that
+// escapes; the Whitted integrator only sees it in reflections and refractions.
+use crate::shapes::sphere_uv;
+use crate::texture::{ImageTexture, Wrap};
+use crate::vec3::{Float, Vec3f};
+
+pub enum Environment {
+    LatLong(ImageTexture),
+    CubeMap(CubeMap),
+}
+
+impl Environment {
+    pub fn lat_long(mut image: ImageTexture) -> Environment {
+        image.wrap = Wrap::LatLong;
+        Environment::LatLong(image)
+    }
+
+    // The color seen along the unit direction dir
+    pub fn sample(&self, dir: &Vec3f) -> Vec3f {
+        match self {
+            Environment::LatLong(image) => {
+                let (u, v) = sphere_uv(dir);
+                image.sample_level(u, v, 0.0)
+            }
+            Environment::CubeMap(cube) => cube.sample(dir),
+        }
+    }
+}
+
+// Each face's axis, and the directions its images' columns and rows run to the right and
+// up, in the order the faces are given: +x, -x, +y, -y, +z, -z. Faces are seen from the
+// centre of the cube looking along their axis, the sides with +y up, the top with +z up
+// and the bottom with -z up, so the top and bottom meet the -z face along their lower
+// and upper edges.
+const FACES: [(Vec3f, Vec3f, Vec3f); 6] = [
+    (
+        Vec3f(1.0, 0.0, 0.0),
+        Vec3f(0.0, 0.0, 1.0),
+        Vec3f(0.0, 1.0, 0.0),
+    ),
+    (
+        Vec3f(-1.0, 0.0, 0.0),
+        Vec3f(0.0, 0.0, -1.0),
+        Vec3f(0.0, 1.0, 0.0),
+    ),
+    (
+        Vec3f(0.0, 1.0, 0.0),
+        Vec3f(1.0, 0.0, 0.0),
+        Vec3f(0.0, 0.0, 1.0),
+    ),
+    (
+        Vec3f(0.0, -1.0, 0.0),
+        Vec3f(1.0, 0.0, 0.0),
+        Vec3f(0.0, 0.0, -1.0),
+    ),
+    (
+        Vec3f(0.0, 0.0, 1.0),
+        Vec3f(-1.0, 0.0, 0.0),
+        Vec3f(0.0, 1.0, 0.0),
+    ),
+    (
+        Vec3f(0.0, 0.0, -1.0),
+        Vec3f(1.0, 0.0, 0.0),
+        Vec3f(0.0, 1.0, 0.0),
+    ),
+];
+
+// Six square images of one size, filtered bilinearly across the edges where they meet so
+// no seam shows between faces
+pub struct CubeMap {
+    faces: Box<[ImageTexture; 6]>,
+    size: usize,
+}
+
+impl CubeMap {
+    // None unless every face is square and the same size as the others
+    pub fn new(faces: [ImageTexture; 6]) -> Option<CubeMap> {
+        let size = faces[0].width();
+        faces
+            .iter()
+            .all(|face| face.width() == size && face.height() == size)
+            .then(|| CubeMap {
+                faces: Box::new(faces),
+                size,
+            })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn sample(&self, dir: &Vec3f) -> Vec3f {
+        let (face, a, b) = face_at(dir);
+        let size = self.size as Float;
+        // Texel centers sit at half-integer coordinates, rows counted from the top
+        let x = (a + 1.0) * 0.5 * size - 0.5;
+        let y = (1.0 - b) * 0.5 * size - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+        let texel = |dx, dy| self.texel(face, x0 + dx, y0 + dy);
+        let top = texel(0, 0) * (1.0 - fx) + texel(1, 0) * fx;
+        let bottom = texel(0, 1) * (1.0 - fx) + texel(1, 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    // The texel at column x and row y of face. One just off the face's edge is taken from
+    // the neighbouring face, at the texel its centre falls in once the face's plane is
+    // carried on past the edge.
+    fn texel(&self, face: usize, x: isize, y: isize) -> Vec3f {
+        let n = self.size as isize;
+        if (0..n).contains(&x) && (0..n).contains(&y) {
+            return self.faces[face].texel(x as usize, y as usize);
+        }
+        let size = self.size as Float;
+        let a = (x as Float + 0.5) / size * 2.0 - 1.0;
+        let b = 1.0 - (y as Float + 0.5) / size * 2.0;
+        let (forward, right, up) = FACES[face];
+        let (face, a, b) = face_at(&(forward + right * a + up * b));
+        let column = (((a + 1.0) * 0.5 * size) as isize).clamp(0, n - 1);
+        let row = (((1.0 - b) * 0.5 * size) as isize).clamp(0, n - 1);
+        self.faces[face].texel(column as usize, row as usize)
+    }
+}
+
+// The face dir points through, by its dominant axis, and where on the face in [-1, 1]
+// along the face's right and up directions
+fn face_at(dir: &Vec3f) -> (usize, Float, Float) {
+    let axis = (0..3)
+        .max_by(|&a, &b| dir[a].abs().total_cmp(&dir[b].abs()))
+        .unwrap_or(2);
+    let face = 2 * axis + (dir[axis] < 0.0) as usize;
+    let (forward, right, up) = FACES[face];
+    let depth = dir.dot(&forward);
+    (face, dir.dot(&right) / depth, dir.dot(&up) / depth)
+}
@@ -0,0 +1,334 @@
+// Classic test scenes, built in code so the example binaries, tests and benchmarks all
+// render exactly the same thing without shipping scene files alongside them
+use crate::camera::Camera;
+use crate::light::Light;
+use crate::material::{Material, GLASS, IVORY, METAL, MIRROR, RED_RUBBER};
+use crate::rng::Rng;
+use crate::scene::{Checkerboard, Scene};
+use crate::shapes::{Cube, RecgtangularPrism, Sphere};
+use crate::vec3::{consts::PI, Float, Vec3f};
+
+// A scene with a camera framing it
+pub type Example = (Scene, Camera);
+pub type ExampleBuilder = fn() -> Example;
+
+// By the names the example binaries accept; the random field uses seed 0
+pub const EXAMPLES: &[(&str, ExampleBuilder)] = &[
+    ("spheres", spheres_on_checkerboard),
+    ("cornell_box", cornell_box),
+    ("sphere_field", || sphere_field(0)),
+];
+
+pub fn example(name: &str) -> Option<Example> {
+    EXAMPLES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, build)| build())
+}
+
+// Scenes built from a seed, each seed giving a different one of the same kind, for
+// benchmarks and demos that want something busier than the fixed examples
+pub type Generator = fn(u64) -> Example;
+
+pub const GENERATORS: &[(&str, Generator)] = &[
+    ("sphere_field", sphere_field),
+    ("menger", menger_sponge),
+    ("city", city_blocks),
+];
+
+pub fn generate(name: &str, seed: u64) -> Option<Example> {
+    GENERATORS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, build)| build(seed))
+}
+
+// Ivory, glass, rubber and mirror spheres over a checkerboard, the scene rendered when
+// no scene file is given
+pub fn spheres_on_checkerboard() -> Example {
+    let mut scene = Scene::new();
+
+    scene.add(Sphere::new(Vec3f(-3.0, 0.0, -16.0), 2.0), IVORY);
+    scene.add(Sphere::new(Vec3f(-1.0, -1.5, -12.0), 2.0), GLASS);
+    scene.add(Sphere::new(Vec3f(1.5, -0.5, -18.0), 3.0), RED_RUBBER);
+    scene.add(Sphere::new(Vec3f(7.0, 5.0, -18.0), 4.0), MIRROR);
+
+    scene.floor = Some(Checkerboard {
+        height: -4.0,
+        min: (-10.0, -30.0),
+        max: (10.0, -10.0),
+        colors: [Vec3f(0.3, 0.3, 0.3), Vec3f(0.3, 0.2, 0.1)],
+    });
+
+    scene.add_light(Light::new(Vec3f(-20.0, 20.0, 20.0), 1.5));
+    scene.add_light(Light::new(Vec3f(30.0, 50.0, -25.0), 1.8));
+    scene.add_light(Light::new(Vec3f(30.0, 20.0, 30.0), 1.7));
+
+    (scene, Camera::new(Vec3f(0.0, 0.0, 0.0), PI / 3.0))
+}
+
+// A closed room with a red left wall and a green right one, lit from just under the
+// ceiling, holding a tall block and a short one
+pub fn cornell_box() -> Example {
+    // The inside spans [-5, 5] on every axis, centred twenty units in front of the camera
+    const HALF: Float = 5.0;
+    const WALL: Float = 0.2;
+    const DEPTH: Float = -20.0;
+    let white = Material {
+        diffuse_color: Vec3f(0.4, 0.4, 0.4),
+        ..RED_RUBBER
+    };
+    let red = Material {
+        diffuse_color: Vec3f(0.4, 0.05, 0.05),
+        ..RED_RUBBER
+    };
+    let green = Material {
+        diffuse_color: Vec3f(0.05, 0.35, 0.05),
+        ..RED_RUBBER
+    };
+    let (near, far) = (DEPTH + HALF, DEPTH - HALF);
+    let slab = |min: Vec3f, max: Vec3f| RecgtangularPrism::new(min, max);
+
+    let mut scene = Scene::new();
+    scene.background = Vec3f(0.0, 0.0, 0.0);
+    // Floor, ceiling and back wall
+    scene.add(
+        slab(
+            Vec3f(-HALF, -HALF - WALL, far),
+            Vec3f(HALF, -HALF, near + WALL),
+        ),
+        white,
+    );
+    scene.add(
+        slab(
+            Vec3f(-HALF, HALF, far),
+            Vec3f(HALF, HALF + WALL, near + WALL),
+        ),
+        white,
+    );
+    scene.add(
+        slab(Vec3f(-HALF, -HALF, far - WALL), Vec3f(HALF, HALF, far)),
+        white,
+    );
+    scene.add(
+        slab(
+            Vec3f(-HALF - WALL, -HALF - WALL, far - WALL),
+            Vec3f(-HALF, HALF + WALL, near + WALL),
+        ),
+        red,
+    );
+    scene.add(
+        slab(
+            Vec3f(HALF, -HALF - WALL, far - WALL),
+            Vec3f(HALF + WALL, HALF + WALL, near + WALL),
+        ),
+        green,
+    );
+
+    scene.add(
+        slab(Vec3f(-3.5, -HALF, DEPTH - 3.0), Vec3f(-0.5, 1.0, DEPTH)),
+        IVORY,
+    );
+    scene.add(
+        slab(Vec3f(0.5, -HALF, DEPTH), Vec3f(3.5, -2.0, DEPTH + 3.0)),
+        IVORY,
+    );
+
+    scene.add_light(Light::new(Vec3f(0.0, HALF - 0.5, DEPTH), 1.5));
+    // A dim fill at the camera lights the faces turned away from the ceiling light, and
+    // every shadow it casts is hidden behind what casts it
+    scene.add_light(Light::new(Vec3f(0.0, 0.0, 0.0), 0.6));
+
+    // Just wide enough to frame the open front of the box
+    let fov = 2.0 * (HALF / (-near + WALL)).atan();
+    (scene, Camera::new(Vec3f(0.0, 0.0, 0.0), fov))
+}
+
+// Hundreds of small spheres in random materials scattered on a grid around three large
+// ones, seen from low down; the same seed always scatters them the same way
+pub fn sphere_field(seed: u64) -> Example {
+    const RADIUS: Float = 0.2;
+    let mut rng = Rng::new(seed);
+    let mut scene = Scene::new();
+
+    let big = [
+        (Vec3f(0.0, 1.0, 0.0), GLASS),
+        (Vec3f(-4.0, 1.0, 0.0), RED_RUBBER),
+        (Vec3f(4.0, 1.0, 0.0), MIRROR),
+    ];
+    for (center, material) in big {
+        scene.add(Sphere::new(center, 1.0), material);
+    }
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let center = Vec3f(
+                a as Float + 0.9 * rng.next_float(),
+                RADIUS,
+                b as Float + 0.9 * rng.next_float(),
+            );
+            // Keep clear of the large spheres
+            if big
+                .iter()
+                .any(|(c, _)| (center - Vec3f(c.0, RADIUS, c.2)).length() < 1.0 + RADIUS)
+            {
+                continue;
+            }
+            let pick = rng.next_float();
+            let mut color = || Vec3f(rng.next_float(), rng.next_float(), rng.next_float());
+            let material = if pick < 0.7 {
+                let c = color();
+                Material {
+                    diffuse_color: Vec3f(c.0 * c.0, c.1 * c.1, c.2 * c.2) * 0.6,
+                    ..RED_RUBBER
+                }
+            } else if pick < 0.9 {
+                Material {
+                    diffuse_color: color() * 0.5 + Vec3f(0.25, 0.25, 0.25),
+                    ..METAL
+                }
+            } else {
+                GLASS
+            };
+            scene.add(Sphere::new(center, RADIUS), material);
+        }
+    }
+
+    scene.floor = Some(Checkerboard {
+        height: 0.0,
+        min: (-30.0, -30.0),
+        max: (30.0, 30.0),
+        colors: [Vec3f(0.3, 0.3, 0.3), Vec3f(0.1, 0.2, 0.1)],
+    });
+
+    scene.add_light(Light::new(Vec3f(-20.0, 30.0, 20.0), 1.5));
+    scene.add_light(Light::new(Vec3f(30.0, 40.0, -10.0), 1.2));
+
+    let camera = Camera::new(Vec3f(13.0, 2.0, 3.0), (20.0 as Float).to_radians())
+        .looking_at(Vec3f(0.0, 0.0, 0.0));
+    (scene, camera)
+}
+
+// A level three Menger sponge, 8000 cubes, each tinted a little around one color the
+// seed picks
+pub fn menger_sponge(seed: u64) -> Example {
+    const LEVEL: u32 = 3;
+    const SIZE: Float = 3.0;
+    let mut rng = Rng::new(seed);
+    let base = Vec3f(
+        0.2 + 0.6 * rng.next_float(),
+        0.2 + 0.6 * rng.next_float(),
+        0.2 + 0.6 * rng.next_float(),
+    );
+
+    // Each cube splits into 27 and keeps the 20 off the middle of every face and the center
+    let mut cubes = vec![(Vec3f(0.0, SIZE / 2.0, 0.0), SIZE)];
+    for _ in 0..LEVEL {
+        let mut smaller = Vec::with_capacity(cubes.len() * 20);
+        for (center, size) in cubes {
+            let step = size / 3.0;
+            for x in -1i32..=1 {
+                for y in -1i32..=1 {
+                    for z in -1i32..=1 {
+                        if x.abs() + y.abs() + z.abs() <= 1 {
+                            continue;
+                        }
+                        let offset = Vec3f(x as Float, y as Float, z as Float) * step;
+                        smaller.push((center + offset, step));
+                    }
+                }
+            }
+        }
+        cubes = smaller;
+    }
+
+    let mut scene = Scene::new();
+    for (center, size) in cubes {
+        let tint = 0.8 + 0.4 * rng.next_float();
+        scene.add(
+            Cube::new(center, size),
+            Material {
+                diffuse_color: base * (0.6 * tint),
+                ..IVORY
+            },
+        );
+    }
+    scene.floor = Some(Checkerboard {
+        height: 0.0,
+        min: (-20.0, -20.0),
+        max: (20.0, 20.0),
+        colors: [Vec3f(0.3, 0.3, 0.3), Vec3f(0.15, 0.15, 0.15)],
+    });
+    scene.add_light(Light::new(Vec3f(-10.0, 15.0, 12.0), 1.5));
+    scene.add_light(Light::new(Vec3f(12.0, 8.0, 6.0), 0.8));
+
+    let camera = Camera::new(Vec3f(4.5, 4.0, 5.5), (40.0 as Float).to_radians()).looking_at(Vec3f(
+        0.0,
+        SIZE / 2.0,
+        0.0,
+    ));
+    (scene, camera)
+}
+
+// City blocks between a grid of streets, each divided into lots holding a box of a
+// building. Most are low; a few towers rise far above them, and some of those are clad
+// in mirror glass.
+pub fn city_blocks(seed: u64) -> Example {
+    const BLOCKS: i32 = 8;
+    const BLOCK: Float = 4.0;
+    const STREET: Float = 1.0;
+    const LOTS: usize = 3;
+    const GAP: Float = 0.15;
+    let mut rng = Rng::new(seed);
+    let mut scene = Scene::new();
+
+    let pitch = BLOCK + STREET;
+    let start = -(BLOCKS as Float) * pitch / 2.0;
+    let lot = (BLOCK - GAP * (LOTS - 1) as Float) / LOTS as Float;
+    for a in 0..BLOCKS {
+        for b in 0..BLOCKS {
+            let corner = (start + a as Float * pitch, start + b as Float * pitch);
+            for i in 0..LOTS {
+                for j in 0..LOTS {
+                    // Some lots are left empty as squares and car parks
+                    if rng.next_float() < 0.1 {
+                        continue;
+                    }
+                    let x = corner.0 + i as Float * (lot + GAP);
+                    let z = corner.1 + j as Float * (lot + GAP);
+                    let tall = rng.next_float();
+                    let height = 0.5 + 1.5 * rng.next_float() + 12.0 * tall.powi(6);
+                    let shade = 0.25 + 0.35 * rng.next_float();
+                    let material = if height > 6.0 && rng.next_float() < 0.5 {
+                        MIRROR
+                    } else {
+                        Material {
+                            diffuse_color: Vec3f(shade, shade * 0.95, shade * 0.9),
+                            ..RED_RUBBER
+                        }
+                    };
+                    scene.add(
+                        RecgtangularPrism::new(Vec3f(x, 0.0, z), Vec3f(x + lot, height, z + lot)),
+                        material,
+                    );
+                }
+            }
+        }
+    }
+    // The ground, with the streets showing between the blocks
+    let extent = BLOCKS as Float * pitch / 2.0 + STREET;
+    scene.add(
+        RecgtangularPrism::new(Vec3f(-extent, -0.1, -extent), Vec3f(extent, 0.0, extent)),
+        Material {
+            diffuse_color: Vec3f(0.08, 0.08, 0.09),
+            ..RED_RUBBER
+        },
+    );
+    scene.background = Vec3f(0.55, 0.7, 0.9);
+    scene.add_light(Light::new(Vec3f(-60.0, 80.0, 40.0), 1.6));
+    scene.add_light(Light::new(Vec3f(50.0, 30.0, 60.0), 0.6));
+
+    let camera = Camera::new(Vec3f(34.0, 22.0, 38.0), (40.0 as Float).to_radians())
+        .looking_at(Vec3f(0.0, 1.0, 0.0));
+    (scene, camera)
+}
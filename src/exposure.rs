@@ -0,0 +1,281 @@
+// How well a render is exposed, for tuning lights and the camera: a histogram of its
+// luminance in stops, and a false-color map that paints each pixel by which band of
+// exposure it falls in, as on a cinema camera's monitor. Both look at the image as it
+// will be written, where anything at or above 1 is clipped to white and anything that
+// rounds to a zero byte is black. Auto-exposure sets the camera's exposure from a quick
+// low-resolution render instead, so a scene need not have its lights tuned to show up.
+use crate::camera::Camera;
+use crate::framebuffer::Framebuffer;
+use crate::render::{self, RenderSettings};
+use crate::scene::Scene;
+use crate::vec3::{Float, Vec3f};
+
+// The histogram spans this many stops either side of white, one bin per column
+const STOPS_BELOW: Float = 8.0;
+const STOPS_ABOVE: Float = 2.0;
+const BINS: usize = 320;
+const HEIGHT: usize = 120;
+
+// Below the smallest nonzero 8-bit value
+const CRUSHED: Float = 0.5 / 255.0;
+const MIDDLE_GRAY: Float = 0.18;
+
+// Exposes the frame so its average luminance lands on key, measured over a prepass of
+// the scene with the camera's own exposure left out. The average is a log average, the
+// geometric mean, so a bright window does not plunge the rest of the frame into shadow,
+// and leaves out pixels that are black, like an empty background, which no exposure
+// would lift. The camera's exposure then applies on top, as compensation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutoExposure {
+    pub key: Float,
+    // The prepass's height in pixels, its width following the frame's aspect
+    pub prepass_height: usize,
+}
+
+impl Default for AutoExposure {
+    fn default() -> AutoExposure {
+        AutoExposure {
+            key: MIDDLE_GRAY,
+            prepass_height: 64,
+        }
+    }
+}
+
+impl AutoExposure {
+    // The factor the camera's exposure is multiplied by for a frame with settings
+    pub fn measure(&self, scene: &Scene, camera: &Camera, settings: &RenderSettings) -> Float {
+        let height = self.prepass_height.clamp(1, settings.height.max(1));
+        let width = (settings.width * height / settings.height.max(1)).max(1);
+        let prepass = RenderSettings {
+            width,
+            height,
+            samples_per_pixel: settings.samples_per_pixel.min(4),
+            object_ids: false,
+            variance: false,
+            crop: None,
+            denoise: None,
+            bloom: None,
+            auto_exposure: None,
+            ..settings.clone()
+        };
+        let unexposed = Camera {
+            exposure: 1.0,
+            ..camera.clone()
+        };
+        let image = render::render(scene, &unexposed, &prepass);
+        let (mut log_sum, mut count) = (0.0, 0usize);
+        for color in &image.pixels {
+            let luminance = luminance(color);
+            if luminance > 0.0 {
+                log_sum += luminance.ln();
+                count += 1;
+            }
+        }
+        let average = (log_sum / count.max(1) as Float).exp();
+        // A black frame stays as it is
+        if count > 0 && average.is_finite() && average > 0.0 {
+            self.key / average
+        } else {
+            1.0
+        }
+    }
+}
+
+pub struct Histogram {
+    // Pixels by luminance, from STOPS_BELOW stops under white up to STOPS_ABOVE over it;
+    // the end bins also hold everything beyond them
+    pub bins: Vec<usize>,
+    // Pixels with any channel at or above 1, which lose detail to white
+    pub clipped: usize,
+    // Pixels too dark to leave black
+    pub crushed: usize,
+    pub total: usize,
+}
+
+impl Histogram {
+    pub fn new(image: &Framebuffer) -> Histogram {
+        let mut histogram = Histogram {
+            bins: vec![0; BINS],
+            clipped: 0,
+            crushed: 0,
+            total: image.pixels.len(),
+        };
+        for color in &image.pixels {
+            let luminance = luminance(color);
+            if clipped(color) {
+                histogram.clipped += 1;
+            } else if luminance < CRUSHED {
+                histogram.crushed += 1;
+            }
+            histogram.bins[bin(luminance)] += 1;
+        }
+        histogram
+    }
+
+    pub fn clipped_fraction(&self) -> Float {
+        self.clipped as Float / self.total.max(1) as Float
+    }
+
+    pub fn crushed_fraction(&self) -> Float {
+        self.crushed as Float / self.total.max(1) as Float
+    }
+
+    // The histogram as a bar chart, one column per bin, with white marked in red and
+    // middle gray in green. Bars grow with the logarithm of the count, so a flat sky
+    // filling most of the frame does not flatten every other tone.
+    pub fn to_image(&self) -> Framebuffer {
+        let mut image = Framebuffer::new(BINS, HEIGHT);
+        let scale = |count: usize| (count as Float).ln_1p();
+        let tallest = scale(self.bins.iter().copied().max().unwrap_or(0)).max(1.0);
+        let (white, gray) = (bin(1.0), bin(MIDDLE_GRAY));
+        for (x, &count) in self.bins.iter().enumerate() {
+            let bar = (scale(count) / tallest * HEIGHT as Float).ceil() as usize;
+            for y in 0..HEIGHT {
+                let color = if y >= HEIGHT - bar {
+                    Vec3f(0.85, 0.85, 0.85)
+                } else if x == white {
+                    Vec3f(0.8, 0.1, 0.1)
+                } else if x == gray {
+                    Vec3f(0.1, 0.6, 0.1)
+                } else {
+                    Vec3f(0.1, 0.1, 0.1)
+                };
+                image.set(x, y, color);
+            }
+        }
+        image
+    }
+}
+
+// Each pixel painted by its exposure: crushed blacks purple and clipped whites red, both
+// under diagonal zebra stripes, deep shadows blue, middle gray green, bright tones pink
+// and highlights near clipping yellow. Everything between stays gray at its own
+// luminance, so the scene can still be made out.
+pub fn false_color(image: &Framebuffer) -> Framebuffer {
+    let mut map = Framebuffer::new(image.width, image.height);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let color = image.get(x, y);
+            let luminance = luminance(&color);
+            let stripe = (x + y) / 4 % 2 == 0;
+            let stops = luminance.max(Float::MIN_POSITIVE).log2();
+            let painted = if clipped(&color) {
+                if stripe {
+                    Vec3f(1.0, 0.0, 0.0)
+                } else {
+                    Vec3f(1.0, 1.0, 1.0)
+                }
+            } else if luminance < CRUSHED {
+                if stripe {
+                    Vec3f(0.5, 0.0, 0.6)
+                } else {
+                    Vec3f(0.0, 0.0, 0.0)
+                }
+            } else if stops < -6.0 {
+                Vec3f(0.0, 0.2, 0.9)
+            } else if (stops - MIDDLE_GRAY.log2()).abs() < 0.25 {
+                Vec3f(0.1, 0.8, 0.1)
+            } else if stops > -0.25 {
+                Vec3f(1.0, 0.9, 0.0)
+            } else if (stops - (MIDDLE_GRAY * 2.0).log2()).abs() < 0.25 {
+                Vec3f(1.0, 0.5, 0.7)
+            } else {
+                Vec3f(luminance, luminance, luminance)
+            };
+            map.set(x, y, painted);
+        }
+    }
+    map
+}
+
+fn luminance(color: &Vec3f) -> Float {
+    0.2126 * color.0 + 0.7152 * color.1 + 0.0722 * color.2
+}
+
+fn clipped(color: &Vec3f) -> bool {
+    color.0 >= 1.0 || color.1 >= 1.0 || color.2 >= 1.0
+}
+
+fn bin(luminance: Float) -> usize {
+    let stops = luminance.max(Float::MIN_POSITIVE).log2();
+    let range = STOPS_BELOW + STOPS_ABOVE;
+    let at = (stops + STOPS_BELOW) / range * BINS as Float;
+    (at.max(0.0) as usize).min(BINS - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(value: Float) -> Vec3f {
+        Vec3f(value, value, value)
+    }
+
+    // A row with a pixel in each band, from crushed black to clipped white
+    fn bands() -> Framebuffer {
+        let mut image = Framebuffer::new(9, 1);
+        image.pixels = [0.0, 1.5, 0.01, 0.18, 0.36, 1.5, 0.0, 0.95, 0.05]
+            .into_iter()
+            .map(gray)
+            .collect();
+        image
+    }
+
+    #[test]
+    fn counts_pixels_by_stops_with_clipped_and_crushed_apart() {
+        let histogram = Histogram::new(&bands());
+        assert_eq!(histogram.total, 9);
+        assert_eq!(histogram.bins.len(), BINS);
+        assert_eq!(histogram.bins.iter().sum::<usize>(), 9);
+        assert_eq!((histogram.clipped, histogram.crushed), (2, 2));
+        assert!((histogram.clipped_fraction() - 2.0 / 9.0).abs() < 1e-6);
+        // 32 bins to a stop, white 8 stops up and the clipped pixels past it
+        assert_eq!(bin(1.0), 256);
+        assert_eq!(bin(0.5), 224);
+        assert_eq!(histogram.bins[0], 2);
+        assert_eq!(histogram.bins[bin(1.5)], 2);
+        assert!(bin(1.5) > bin(1.0));
+        assert_eq!(bin(1e6), BINS - 1);
+        // Clipping one channel is enough, and an empty image has nothing clipped
+        let clipped = Framebuffer {
+            pixels: vec![Vec3f(2.0, 0.0, 0.0)],
+            ..Framebuffer::new(1, 1)
+        };
+        assert_eq!(Histogram::new(&clipped).clipped_fraction(), 1.0);
+        assert_eq!(
+            Histogram::new(&Framebuffer::new(0, 0)).clipped_fraction(),
+            0.0
+        );
+
+        let chart = histogram.to_image();
+        assert_eq!((chart.width, chart.height), (BINS, HEIGHT));
+        // The tallest bars reach the top, empty bins are background with the markers
+        assert_eq!(chart.get(0, 0), gray(0.85));
+        assert_eq!(chart.get(bin(0.3), 0), gray(0.1));
+        assert_eq!(chart.get(bin(1.0), 0), Vec3f(0.8, 0.1, 0.1));
+        assert_eq!(chart.get(bin(MIDDLE_GRAY), HEIGHT - 1), gray(0.85));
+        assert_eq!(chart.get(bin(0.05), HEIGHT - 1), gray(0.85));
+        assert_eq!(chart.get(bin(0.05), 0), gray(0.1));
+    }
+
+    #[test]
+    fn false_colors_paint_each_band_of_exposure() {
+        let map = false_color(&bands());
+        let painted: Vec<Vec3f> = (0..9).map(|x| map.get(x, 0)).collect();
+        assert_eq!(
+            painted,
+            [
+                // Crushed and clipped pixels under stripes four pixels wide
+                Vec3f(0.5, 0.0, 0.6),
+                Vec3f(1.0, 0.0, 0.0),
+                Vec3f(0.0, 0.2, 0.9),
+                Vec3f(0.1, 0.8, 0.1),
+                Vec3f(1.0, 0.5, 0.7),
+                gray(1.0),
+                gray(0.0),
+                Vec3f(1.0, 0.9, 0.0),
+                gray(0.05),
+            ]
+        );
+    }
+}
@@ -0,0 +1,436 @@
+// OpenEXR scanline files, the format compositing tools read render layers from. Every
+// channel goes into the one image; names like "sigma.R" put a channel in a layer, which
+// tools such as Nuke and Natron show as its own image, so the beauty pass and its AOVs
+// travel together. Blocks of 16 rows are ZIP compressed, or stored raw where that would
+// not save anything.
+use std::io::{self, Write};
+
+use crate::png;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Precision {
+    // 16-bit floats, plenty for color and half the size
+    #[default]
+    Half,
+    Float,
+}
+
+impl Precision {
+    pub const NAMES: [(&'static str, Precision); 2] =
+        [("half", Precision::Half), ("float", Precision::Float)];
+
+    pub fn from_name(name: &str) -> Option<Precision> {
+        Precision::NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(_, precision)| precision)
+    }
+}
+
+pub enum Samples {
+    // Written at the file's precision
+    Float(Vec<f32>),
+    // Exact whole numbers, like object IDs
+    Uint(Vec<u32>),
+}
+
+pub struct Channel {
+    pub name: String,
+    // One per pixel, rows from the top down
+    pub samples: Samples,
+}
+
+const ROWS_PER_BLOCK: usize = 16;
+const ZIP_COMPRESSION: u8 = 3;
+
+// Writes width x height pixels of every channel, with each (name, text) pair as a string
+// attribute of the header
+pub fn write_exr<W: Write>(
+    out: &mut W,
+    width: usize,
+    height: usize,
+    channels: &[Channel],
+    precision: Precision,
+    text: &[(String, String)],
+) -> io::Result<()> {
+    for channel in channels {
+        let len = match &channel.samples {
+            Samples::Float(samples) => samples.len(),
+            Samples::Uint(samples) => samples.len(),
+        };
+        assert_eq!(len, width * height, "channel size does not match the image");
+    }
+    // Readers expect the channels sorted by name, and their samples in that order
+    let mut channels: Vec<&Channel> = channels.iter().collect();
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+    let pixel_type = |channel: &Channel| match (&channel.samples, precision) {
+        (Samples::Uint(_), _) => 0u32,
+        (Samples::Float(_), Precision::Half) => 1,
+        (Samples::Float(_), Precision::Float) => 2,
+    };
+
+    let mut header = Vec::new();
+    let mut list = Vec::new();
+    for channel in &channels {
+        list.extend_from_slice(channel.name.as_bytes());
+        list.push(0);
+        list.extend_from_slice(&pixel_type(channel).to_le_bytes());
+        // Not perceptually linear, three reserved bytes, then no subsampling either way
+        list.extend_from_slice(&[0, 0, 0, 0]);
+        list.extend_from_slice(&1u32.to_le_bytes());
+        list.extend_from_slice(&1u32.to_le_bytes());
+    }
+    list.push(0);
+    attribute(&mut header, "channels", "chlist", &list);
+    attribute(
+        &mut header,
+        "compression",
+        "compression",
+        &[ZIP_COMPRESSION],
+    );
+    let mut window = Vec::new();
+    for value in [0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    // Rows in increasing y, which is down the image
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    for (name, value) in text {
+        attribute(&mut header, name, "string", value.as_bytes());
+    }
+    header.push(0);
+
+    let mut blocks = Vec::new();
+    for y0 in (0..height).step_by(ROWS_PER_BLOCK) {
+        let rows = y0..(y0 + ROWS_PER_BLOCK).min(height);
+        let mut raw = Vec::new();
+        for y in rows {
+            for channel in &channels {
+                let row = y * width..(y + 1) * width;
+                match (&channel.samples, precision) {
+                    (Samples::Uint(samples), _) => {
+                        for &v in &samples[row] {
+                            raw.extend_from_slice(&v.to_le_bytes());
+                        }
+                    }
+                    (Samples::Float(samples), Precision::Half) => {
+                        for &v in &samples[row] {
+                            raw.extend_from_slice(&half(v).to_le_bytes());
+                        }
+                    }
+                    (Samples::Float(samples), Precision::Float) => {
+                        for &v in &samples[row] {
+                            raw.extend_from_slice(&v.to_le_bytes());
+                        }
+                    }
+                }
+            }
+        }
+        let compressed = zip(&raw);
+        // Readers take a block as raw when it is no smaller than the uncompressed rows
+        let data = if compressed.len() < raw.len() {
+            compressed
+        } else {
+            raw
+        };
+        blocks.push((y0 as i32, data));
+    }
+
+    // Names over 31 bytes need the long-names flag
+    let long_names =
+        channels.iter().any(|c| c.name.len() > 31) || text.iter().any(|(name, _)| name.len() > 31);
+    let flags: u32 = if long_names { 0x400 } else { 0 };
+    out.write_all(&[0x76, 0x2f, 0x31, 0x01])?;
+    out.write_all(&(2 | flags).to_le_bytes())?;
+    out.write_all(&header)?;
+    // Each block's position in the file, after the magic number, version and offset table
+    let mut offset = (8 + header.len() + 8 * blocks.len()) as u64;
+    for (_, data) in &blocks {
+        out.write_all(&offset.to_le_bytes())?;
+        offset += 8 + data.len() as u64;
+    }
+    for (y, data) in &blocks {
+        out.write_all(&y.to_le_bytes())?;
+        out.write_all(&(data.len() as u32).to_le_bytes())?;
+        out.write_all(data)?;
+    }
+    Ok(())
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    for text in [name, kind] {
+        header.extend(text.bytes().filter(|&b| b != 0));
+        header.push(0);
+    }
+    header.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// EXR's ZIP scheme: the low bytes of every sample ahead of the high bytes, then each byte
+// stored as its difference from the one before, which leaves smooth images mostly small
+// numbers for zlib
+fn zip(raw: &[u8]) -> Vec<u8> {
+    let mut split: Vec<u8> = raw.iter().step_by(2).copied().collect();
+    split.extend(raw.iter().skip(1).step_by(2));
+    for i in (1..split.len()).rev() {
+        split[i] = split[i].wrapping_sub(split[i - 1]).wrapping_add(128);
+    }
+    png::zlib_compress(&split)
+}
+
+// The nearest half-precision float, rounding ties to even; too large becomes infinity
+pub fn half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // Infinity stays infinity and NaN stays NaN
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        // Subnormal, or zero once shifted out entirely
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let rounded = round_shift(mantissa, shift);
+        return sign | rounded as u16;
+    }
+    // Rounding can carry into the exponent, which is the right answer
+    let rounded = round_shift(((exponent as u32) << 23) | mantissa, 13);
+    sign | rounded as u16
+}
+
+// value >> shift, rounded to nearest with ties to even
+fn round_shift(value: u32, shift: u32) -> u32 {
+    let truncated = value >> shift;
+    let remainder = value & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    if remainder > halfway || (remainder == halfway && truncated & 1 == 1) {
+        truncated + 1
+    } else {
+        truncated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Image {
+        version: u32,
+        attributes: Vec<(String, String, Vec<u8>)>,
+        // Name, pixel type and samples widened to u32 bits, in the file's order
+        channels: Vec<(String, u32, Vec<u32>)>,
+    }
+
+    fn cstr(data: &[u8], at: &mut usize) -> String {
+        let end = *at + data[*at..].iter().position(|&b| b == 0).unwrap();
+        let text = String::from_utf8(data[*at..end].to_vec()).unwrap();
+        *at = end + 1;
+        text
+    }
+
+    fn u32_at(data: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    // Enough of a reader for what write_exr writes
+    fn read(data: &[u8], width: usize, height: usize) -> Image {
+        assert_eq!(data[..4], [0x76, 0x2f, 0x31, 0x01]);
+        let version = u32_at(data, 4);
+        let mut at = 8;
+        let mut attributes = Vec::new();
+        while data[at] != 0 {
+            let name = cstr(data, &mut at);
+            let kind = cstr(data, &mut at);
+            let size = u32_at(data, at) as usize;
+            attributes.push((name, kind, data[at + 4..at + 4 + size].to_vec()));
+            at += 4 + size;
+        }
+        at += 1;
+
+        let list = &attributes.iter().find(|a| a.0 == "channels").unwrap().2;
+        let mut channels = Vec::new();
+        let mut i = 0;
+        while list[i] != 0 {
+            let name = cstr(list, &mut i);
+            channels.push((name, u32_at(list, i), Vec::new()));
+            i += 16;
+        }
+        let size = |pixel_type: u32| if pixel_type == 1 { 2 } else { 4 };
+        let row_bytes: usize = channels.iter().map(|c| width * size(c.1)).sum();
+
+        let blocks = height.div_ceil(ROWS_PER_BLOCK);
+        for block in 0..blocks {
+            let offset =
+                u64::from_le_bytes(data[at + 8 * block..at + 8 * block + 8].try_into().unwrap());
+            let offset = offset as usize;
+            assert_eq!(u32_at(data, offset) as usize, block * ROWS_PER_BLOCK);
+            let len = u32_at(data, offset + 4) as usize;
+            let stored = &data[offset + 8..offset + 8 + len];
+            let rows = ROWS_PER_BLOCK.min(height - block * ROWS_PER_BLOCK);
+            let expected = rows * row_bytes;
+            let raw = if len == expected {
+                stored.to_vec()
+            } else {
+                let mut split = png::zlib_decompress(stored, expected).unwrap();
+                for i in 1..split.len() {
+                    split[i] = split[i].wrapping_add(split[i - 1]).wrapping_sub(128);
+                }
+                let (low, high) = split.split_at(expected.div_ceil(2));
+                (0..expected)
+                    .map(|i| if i % 2 == 0 { low[i / 2] } else { high[i / 2] })
+                    .collect()
+            };
+            assert_eq!(raw.len(), expected);
+            let mut i = 0;
+            for _ in 0..rows {
+                for channel in &mut channels {
+                    for _ in 0..width {
+                        let sample = match size(channel.1) {
+                            2 => u16::from_le_bytes([raw[i], raw[i + 1]]) as u32,
+                            _ => u32_at(&raw, i),
+                        };
+                        channel.2.push(sample);
+                        i += size(channel.1);
+                    }
+                }
+            }
+        }
+        Image {
+            version,
+            attributes,
+            channels,
+        }
+    }
+
+    fn channel(name: &str, samples: Samples) -> Channel {
+        Channel {
+            name: name.to_string(),
+            samples,
+        }
+    }
+
+    #[test]
+    fn rounds_to_the_nearest_half() {
+        assert_eq!(half(0.0), 0);
+        assert_eq!(half(-0.0), 0x8000);
+        assert_eq!(half(1.0), 0x3c00);
+        assert_eq!(half(-2.0), 0xc000);
+        assert_eq!(half(0.333_333_34), 0x3555);
+        assert_eq!(half(65504.0), 0x7bff);
+        // Past the largest half, and halfway to the next, overflows
+        assert_eq!(half(65520.0), 0x7c00);
+        assert_eq!(half(f32::INFINITY), 0x7c00);
+        assert_eq!(half(f32::NEG_INFINITY), 0xfc00);
+        assert_eq!(half(f32::NAN) & 0x7c00, 0x7c00);
+        assert_ne!(half(f32::NAN) & 0x3ff, 0);
+        // The smallest subnormal, and a tie below it that rounds to even zero
+        assert_eq!(half(2f32.powi(-24)), 1);
+        assert_eq!(half(2f32.powi(-25)), 0);
+        assert_eq!(half(3.0 * 2f32.powi(-25)), 2);
+        // 1 + 2^-11 sits halfway between 1 and the next half, and goes to the even one
+        assert_eq!(half(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(half(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+    }
+
+    #[test]
+    fn writes_channels_that_read_back() {
+        let (width, height) = (3, 20);
+        let pixels = width * height;
+        // Smooth enough to compress in the first block, and noise in the last that at half
+        // precision does not, so blocks are read back both ways
+        let ramp: Vec<f32> = (0..pixels)
+            .map(|i| {
+                if i < 48 {
+                    0.25 * (i % 4) as f32
+                } else {
+                    (i * 7919 % 97) as f32 - 40.5
+                }
+            })
+            .collect();
+        let ids: Vec<u32> = (0..pixels as u32)
+            .map(|i| i.wrapping_mul(0x9e37_79b9))
+            .collect();
+        let channels = [
+            channel("R", Samples::Float(ramp.clone())),
+            channel("id", Samples::Uint(ids.clone())),
+            channel("B", Samples::Float(vec![1.5; pixels])),
+        ];
+        let text = [("renderer".to_string(), "rusty-rays".to_string())];
+
+        for precision in [Precision::Half, Precision::Float] {
+            let mut out = Vec::new();
+            write_exr(&mut out, width, height, &channels, precision, &text).unwrap();
+            let image = read(&out, width, height);
+            assert_eq!(image.version, 2);
+            let names: Vec<&str> = image.channels.iter().map(|c| c.0.as_str()).collect();
+            assert_eq!(names, ["B", "R", "id"]);
+            let float = |v: f32| match precision {
+                Precision::Half => half(v) as u32,
+                Precision::Float => v.to_bits(),
+            };
+            let float_type = if precision == Precision::Half { 1 } else { 2 };
+            assert_eq!(image.channels[0].1, float_type);
+            assert_eq!(image.channels[0].2, vec![float(1.5); pixels]);
+            assert_eq!(
+                image.channels[1].2,
+                ramp.iter().map(|&v| float(v)).collect::<Vec<_>>()
+            );
+            assert_eq!(image.channels[2].1, 0);
+            assert_eq!(image.channels[2].2, ids);
+
+            let attribute = |name: &str| image.attributes.iter().find(|a| a.0 == name).unwrap();
+            let window = &attribute("dataWindow").2;
+            let corners: Vec<i32> = window
+                .chunks(4)
+                .map(|b| i32::from_le_bytes(b.try_into().unwrap()))
+                .collect();
+            assert_eq!(corners, [0, 0, 2, 19]);
+            assert_eq!(attribute("compression").2, [ZIP_COMPRESSION]);
+            let renderer = attribute("renderer");
+            assert_eq!(
+                (renderer.1.as_str(), renderer.2.as_slice()),
+                ("string", &b"rusty-rays"[..])
+            );
+        }
+
+        // A long name sets the flag readers need to accept it
+        let long = "a".repeat(40);
+        let mut out = Vec::new();
+        write_exr(
+            &mut out,
+            1,
+            1,
+            &[channel(&long, Samples::Float(vec![0.0]))],
+            Precision::Half,
+            &[],
+        )
+        .unwrap();
+        let image = read(&out, 1, 1);
+        assert_eq!(image.version, 0x402);
+        assert_eq!(image.channels[0].0, long);
+    }
+}
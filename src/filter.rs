@@ -0,0 +1,121 @@
+use crate::vec3::Float;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelFilter {
+    // Averages the samples falling inside each pixel
+    Box,
+    Tent,
+    Gaussian { alpha: Float },
+    // Mitchell–Netravali with the usual B = C = 1/3 unless tuned
+    Mitchell { b: Float, c: Float },
+}
+
+impl PixelFilter {
+    pub fn gaussian() -> PixelFilter {
+        PixelFilter::Gaussian { alpha: 2.0 }
+    }
+
+    pub fn mitchell() -> PixelFilter {
+        PixelFilter::Mitchell {
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        }
+    }
+
+    // Support radius in pixels
+    pub fn radius(&self) -> Float {
+        match self {
+            PixelFilter::Box => 0.5,
+            PixelFilter::Tent => 1.0,
+            PixelFilter::Gaussian { .. } => 1.5,
+            PixelFilter::Mitchell { .. } => 2.0,
+        }
+    }
+
+    // Weight of a sample offset (dx, dy) pixels from a pixel center; all filters are separable
+    pub fn weight(&self, dx: Float, dy: Float) -> Float {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, x: Float) -> Float {
+        let radius = self.radius();
+        let x = x.abs();
+        if x > radius {
+            return 0.0;
+        }
+        match *self {
+            PixelFilter::Box => 1.0,
+            PixelFilter::Tent => radius - x,
+            PixelFilter::Gaussian { alpha } => {
+                ((-alpha * x * x).exp() - (-alpha * radius * radius).exp()).max(0.0)
+            }
+            PixelFilter::Mitchell { b, c } => {
+                // The canonical kernel spans [-2, 2]
+                let x = 2.0 * x / radius;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Midpoint rule over the filter's support along one axis
+    fn integral(filter: &PixelFilter) -> Float {
+        let steps = 20000;
+        let radius = filter.radius();
+        let dx = 2.0 * radius / steps as Float;
+        (0..steps)
+            .map(|i| filter.weight_1d(-radius + (i as Float + 0.5) * dx) * dx)
+            .sum()
+    }
+
+    #[test]
+    fn kernels_integrate_to_one() {
+        for filter in [PixelFilter::Box, PixelFilter::Tent, PixelFilter::mitchell()] {
+            let area = integral(&filter);
+            assert!((area - 1.0).abs() < 1e-3, "{:?}: {}", filter, area);
+        }
+        // Any B + 2C = 1 Mitchell filter still integrates to 1
+        let sharp = PixelFilter::Mitchell { b: 0.0, c: 0.5 };
+        assert!((integral(&sharp) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn kernels_fall_to_zero_at_their_radius() {
+        let filters = [
+            PixelFilter::Box,
+            PixelFilter::Tent,
+            PixelFilter::gaussian(),
+            PixelFilter::mitchell(),
+        ];
+        for filter in filters {
+            let radius = filter.radius();
+            assert_eq!(filter.weight(radius * 1.01, 0.0), 0.0, "{:?}", filter);
+            assert_eq!(filter.weight(0.0, -radius * 1.01), 0.0, "{:?}", filter);
+            // Symmetric, separable, and largest at the center
+            assert_eq!(filter.weight(0.3, -0.2), filter.weight(-0.3, 0.2));
+            let product = filter.weight_1d(0.3) * filter.weight_1d(0.2);
+            assert_eq!(filter.weight(0.3, 0.2), product);
+            assert!(filter.weight(0.0, 0.0) >= filter.weight(0.4, 0.1));
+        }
+        assert!(PixelFilter::Tent.weight(1.0, 0.0).abs() < 1e-6);
+        assert!(PixelFilter::gaussian().weight(1.5, 0.0).abs() < 1e-6);
+        assert!(PixelFilter::mitchell().weight(2.0, 0.0).abs() < 1e-6);
+        // Mitchell's negative lobes sharpen
+        assert!(PixelFilter::mitchell().weight(1.5, 0.0) < 0.0);
+    }
+}
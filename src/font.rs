@@ -0,0 +1,508 @@
+// TrueType fonts, read for their glyph outlines so text can be built as geometry. Only
+// what outlines need is parsed: the character map, glyph locations, advance widths and
+// line spacing. Quadratic curves are flattened into straight segments, composite glyphs
+// such as accented letters are assembled from their parts, and kerning and hinting are
+// ignored. OpenType fonts with CFF outlines are not supported. The tables are read here
+// rather than through ttf-parser to keep the renderer free of dependencies, as its image
+// and scene formats are; every read is bounds-checked, and tests/font.rs corrupts each
+// byte of a font to hold it to that.
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::log::{self, Level};
+use crate::vec3::Float;
+
+// A closed outline as points in em units, y up from the baseline; filled areas lie to
+// the right of the direction of travel, as TrueType draws them
+pub type Contour = Vec<(Float, Float)>;
+
+// Composite glyphs nest no deeper than this, so a font referring to itself cannot recurse forever
+const MAX_COMPONENT_DEPTH: u32 = 8;
+
+pub struct Font {
+    data: Vec<u8>,
+    units_per_em: Float,
+    // Byte range of each glyph's outline within data, empty for blank glyphs like space
+    glyphs: Vec<Range<usize>>,
+    // Per glyph, in font units; glyphs past the table share its last entry
+    advances: Vec<u16>,
+    // Where the chosen character map subtable starts, and its format, 4 or 12
+    cmap: (usize, u16),
+    // From the baseline, in ems; the descender is negative
+    pub ascender: Float,
+    pub descender: Float,
+    pub line_gap: Float,
+}
+
+impl Font {
+    pub fn load(path: &Path) -> io::Result<Font> {
+        let start = log::timer(Level::Debug);
+        let font = Font::parse(fs::read(path)?)?;
+        if let Some(start) = start {
+            crate::debug!(
+                "loaded {}: {} glyphs in {:.3}s",
+                path.display(),
+                font.glyphs.len(),
+                start.elapsed().as_secs_f64()
+            );
+        }
+        Ok(font)
+    }
+
+    pub fn parse(data: Vec<u8>) -> io::Result<Font> {
+        let reader = Reader { bytes: &data };
+        let version = reader.u32(0)?;
+        if version == u32::from_be_bytes(*b"OTTO") {
+            return Err(invalid_data(
+                "CFF outlines are not supported; use a TrueType font".to_string(),
+            ));
+        }
+        if version != 0x0001_0000 && version != u32::from_be_bytes(*b"true") {
+            return Err(invalid_data("not a TrueType font".to_string()));
+        }
+        let table = |tag: &[u8; 4]| -> io::Result<Range<usize>> {
+            let count = reader.u16(4)? as usize;
+            for i in 0..count {
+                let record = 12 + 16 * i;
+                if reader.take(record, 4)? == tag {
+                    let offset = reader.u32(record + 8)? as usize;
+                    let length = reader.u32(record + 12)? as usize;
+                    reader.take(offset, length)?;
+                    return Ok(offset..offset + length);
+                }
+            }
+            Err(invalid_data(format!(
+                "font has no {} table",
+                String::from_utf8_lossy(tag)
+            )))
+        };
+
+        let head = table(b"head")?.start;
+        let units_per_em = reader.u16(head + 18)? as Float;
+        if units_per_em == 0.0 {
+            return Err(invalid_data("font has zero units per em".to_string()));
+        }
+        let long_offsets = reader.i16(head + 50)? != 0;
+        let glyph_count = reader.u16(table(b"maxp")?.start + 4)? as usize;
+
+        let hhea = table(b"hhea")?.start;
+        let metric_count = reader.u16(hhea + 34)? as usize;
+        let hmtx = table(b"hmtx")?.start;
+        let advances = (0..metric_count.max(1))
+            .map(|i| reader.u16(hmtx + 4 * i))
+            .collect::<io::Result<Vec<u16>>>()?;
+
+        let loca = table(b"loca")?.start;
+        let glyf = table(b"glyf")?;
+        let glyphs = (0..glyph_count)
+            .map(|i| {
+                let (start, end) = if long_offsets {
+                    (
+                        reader.u32(loca + 4 * i)? as usize,
+                        reader.u32(loca + 4 * i + 4)? as usize,
+                    )
+                } else {
+                    (
+                        2 * reader.u16(loca + 2 * i)? as usize,
+                        2 * reader.u16(loca + 2 * i + 2)? as usize,
+                    )
+                };
+                if start > end || glyf.start + end > glyf.end {
+                    return Err(invalid_data(format!("glyph {} lies outside glyf", i)));
+                }
+                Ok(glyf.start + start..glyf.start + end)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        // Unicode subtables, preferring the full repertoire of format 12
+        let cmap = table(b"cmap")?.start;
+        let mut best: Option<(usize, u16)> = None;
+        for i in 0..reader.u16(cmap + 2)? as usize {
+            let record = cmap + 4 + 8 * i;
+            let (platform, encoding) = (reader.u16(record)?, reader.u16(record + 2)?);
+            let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+            let offset = cmap + reader.u32(record + 4)? as usize;
+            let format = reader.u16(offset)?;
+            if unicode && (format == 12 || (format == 4 && best.is_none())) {
+                best = Some((offset, format));
+            }
+        }
+        let cmap =
+            best.ok_or_else(|| invalid_data("font has no Unicode character map".to_string()))?;
+
+        let em = |units: i16| units as Float / units_per_em;
+        let (ascender, descender, line_gap) = (
+            em(reader.i16(hhea + 4)?),
+            em(reader.i16(hhea + 6)?),
+            em(reader.i16(hhea + 8)?),
+        );
+        Ok(Font {
+            data,
+            units_per_em,
+            glyphs,
+            advances,
+            cmap,
+            ascender,
+            descender,
+            line_gap,
+        })
+    }
+
+    // The glyph drawing c, or None where the font has no glyph for it
+    pub fn glyph(&self, c: char) -> Option<u16> {
+        let reader = Reader { bytes: &self.data };
+        let code = c as u32;
+        let (offset, format) = self.cmap;
+        let glyph = if format == 12 {
+            let groups = reader.u32(offset + 12).ok()? as usize;
+            (0..groups).find_map(|i| {
+                let group = offset + 16 + 12 * i;
+                let (first, last) = (reader.u32(group).ok()?, reader.u32(group + 4).ok()?);
+                if !(first..=last).contains(&code) {
+                    return None;
+                }
+                // A group running past the last glyph id maps to nothing
+                let glyph = reader.u32(group + 8).ok()?.checked_add(code - first);
+                Some(glyph.and_then(|g| u16::try_from(g).ok()).unwrap_or(0))
+            })?
+        } else {
+            let code = u16::try_from(code).ok()?;
+            let segments = reader.u16(offset + 6).ok()? as usize / 2;
+            let ends = offset + 14;
+            let starts = ends + 2 * segments + 2;
+            let deltas = starts + 2 * segments;
+            let ranges = deltas + 2 * segments;
+            let segment =
+                (0..segments).find(|&i| reader.u16(ends + 2 * i).is_ok_and(|e| e >= code))?;
+            let start = reader.u16(starts + 2 * segment).ok()?;
+            if code < start {
+                return None;
+            }
+            let delta = reader.u16(deltas + 2 * segment).ok()?;
+            let range = reader.u16(ranges + 2 * segment).ok()? as usize;
+            if range == 0 {
+                code.wrapping_add(delta)
+            } else {
+                // The offset counts from the range entry itself into the glyph array after it
+                let at = ranges + 2 * segment + range + 2 * (code - start) as usize;
+                match reader.u16(at).ok()? {
+                    0 => 0,
+                    glyph => glyph.wrapping_add(delta),
+                }
+            }
+        };
+        (glyph != 0 && (glyph as usize) < self.glyphs.len()).then_some(glyph)
+    }
+
+    // How far the pen moves on after the glyph, in ems
+    pub fn advance(&self, glyph: u16) -> Float {
+        let units = self
+            .advances
+            .get(glyph as usize)
+            .or(self.advances.last())
+            .copied()
+            .unwrap_or(0);
+        units as Float / self.units_per_em
+    }
+
+    // The glyph's contours in ems, each curve cut into curve_segments straight pieces
+    pub fn outline(&self, glyph: u16, curve_segments: usize) -> io::Result<Vec<Contour>> {
+        let mut contours = Vec::new();
+        let scale = 1.0 / self.units_per_em;
+        self.add_outline(
+            glyph,
+            [scale, 0.0, 0.0, scale, 0.0, 0.0],
+            curve_segments.max(1),
+            0,
+            &mut contours,
+        )?;
+        Ok(contours)
+    }
+
+    // Appends the glyph's contours mapped through the 2x3 matrix [a, b, c, d, e, f],
+    // taking (x, y) to (a x + c y + e, b x + d y + f)
+    fn add_outline(
+        &self,
+        glyph: u16,
+        matrix: [Float; 6],
+        curve_segments: usize,
+        depth: u32,
+        contours: &mut Vec<Contour>,
+    ) -> io::Result<()> {
+        let range = self
+            .glyphs
+            .get(glyph as usize)
+            .ok_or_else(|| invalid_data(format!("no glyph {}", glyph)))?
+            .clone();
+        if range.is_empty() {
+            return Ok(());
+        }
+        let reader = Reader {
+            bytes: &self.data[range],
+        };
+        let map = |x: Float, y: Float| {
+            let [a, b, c, d, e, f] = matrix;
+            (a * x + c * y + e, b * x + d * y + f)
+        };
+        let contour_count = reader.i16(0)?;
+        if contour_count >= 0 {
+            for points in simple_glyph(&reader, contour_count as usize)? {
+                let mapped: Vec<(Float, Float, bool)> = points
+                    .iter()
+                    .map(|&(x, y, on)| {
+                        let (x, y) = map(x as Float, y as Float);
+                        (x, y, on)
+                    })
+                    .collect();
+                let contour = flatten(&mapped, curve_segments);
+                if contour.len() >= 3 {
+                    contours.push(contour);
+                }
+            }
+            return Ok(());
+        }
+        if depth >= MAX_COMPONENT_DEPTH {
+            return Err(invalid_data("composite glyphs nest too deeply".to_string()));
+        }
+        // Component flags
+        const WORDS: u16 = 0x1;
+        const XY_VALUES: u16 = 0x2;
+        const SCALE: u16 = 0x8;
+        const MORE: u16 = 0x20;
+        const XY_SCALE: u16 = 0x40;
+        const TWO_BY_TWO: u16 = 0x80;
+        let mut at = 10;
+        loop {
+            let flags = reader.u16(at)?;
+            let component = reader.u16(at + 2)?;
+            at += 4;
+            let (dx, dy) = if flags & WORDS != 0 {
+                at += 4;
+                (reader.i16(at - 4)? as Float, reader.i16(at - 2)? as Float)
+            } else {
+                at += 2;
+                (
+                    reader.take(at - 2, 1)?[0] as i8 as Float,
+                    reader.take(at - 1, 1)?[0] as i8 as Float,
+                )
+            };
+            // Placing parts by matching up their points is rare and not supported, so
+            // such parts stay where they were drawn
+            let (dx, dy) = if flags & XY_VALUES != 0 {
+                (dx, dy)
+            } else {
+                (0.0, 0.0)
+            };
+            let f2dot14 =
+                |at: usize| -> io::Result<Float> { Ok(reader.i16(at)? as Float / 16384.0) };
+            let [a, b, c, d] = if flags & SCALE != 0 {
+                at += 2;
+                let s = f2dot14(at - 2)?;
+                [s, 0.0, 0.0, s]
+            } else if flags & XY_SCALE != 0 {
+                at += 4;
+                [f2dot14(at - 4)?, 0.0, 0.0, f2dot14(at - 2)?]
+            } else if flags & TWO_BY_TWO != 0 {
+                at += 8;
+                [
+                    f2dot14(at - 8)?,
+                    f2dot14(at - 6)?,
+                    f2dot14(at - 4)?,
+                    f2dot14(at - 2)?,
+                ]
+            } else {
+                [1.0, 0.0, 0.0, 1.0]
+            };
+            // The part's own transform, then this glyph's
+            let [pa, pb, pc, pd, pe, pf] = matrix;
+            let combined = [
+                pa * a + pc * b,
+                pb * a + pd * b,
+                pa * c + pc * d,
+                pb * c + pd * d,
+                pa * dx + pc * dy + pe,
+                pb * dx + pd * dy + pf,
+            ];
+            self.add_outline(component, combined, curve_segments, depth + 1, contours)?;
+            if flags & MORE == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// The points of each contour of a simple glyph in font units, flagged on or off the curve
+fn simple_glyph(reader: &Reader, contour_count: usize) -> io::Result<Vec<Vec<(i32, i32, bool)>>> {
+    const ON_CURVE: u8 = 0x1;
+    const X_SHORT: u8 = 0x2;
+    const Y_SHORT: u8 = 0x4;
+    const REPEAT: u8 = 0x8;
+    const X_SAME_OR_POSITIVE: u8 = 0x10;
+    const Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+    let ends = (0..contour_count)
+        .map(|i| reader.u16(10 + 2 * i).map(|e| e as usize))
+        .collect::<io::Result<Vec<usize>>>()?;
+    let point_count = ends.last().map_or(0, |&e| e + 1);
+    let instructions = reader.u16(10 + 2 * contour_count)? as usize;
+    let mut at = 12 + 2 * contour_count + instructions;
+
+    let mut flags = Vec::with_capacity(point_count);
+    while flags.len() < point_count {
+        let flag = reader.take(at, 1)?[0];
+        at += 1;
+        flags.push(flag);
+        if flag & REPEAT != 0 {
+            let repeats = reader.take(at, 1)?[0];
+            at += 1;
+            for _ in 0..repeats {
+                flags.push(flag);
+            }
+        }
+    }
+    flags.truncate(point_count);
+
+    // Coordinates are deltas from the point before, x for every point and then y
+    let mut coordinates = |short: u8, same_or_positive: u8| -> io::Result<Vec<i32>> {
+        let mut value = 0i32;
+        let mut values = Vec::with_capacity(point_count);
+        for &flag in &flags {
+            if flag & short != 0 {
+                let delta = reader.take(at, 1)?[0] as i32;
+                at += 1;
+                value += if flag & same_or_positive != 0 {
+                    delta
+                } else {
+                    -delta
+                };
+            } else if flag & same_or_positive == 0 {
+                value += reader.i16(at)? as i32;
+                at += 2;
+            }
+            values.push(value);
+        }
+        Ok(values)
+    };
+    let xs = coordinates(X_SHORT, X_SAME_OR_POSITIVE)?;
+    let ys = coordinates(Y_SHORT, Y_SAME_OR_POSITIVE)?;
+
+    let mut contours = Vec::with_capacity(contour_count);
+    let mut start = 0;
+    for end in ends {
+        if end < start || end >= point_count {
+            return Err(invalid_data("glyph contours out of order".to_string()));
+        }
+        contours.push(
+            (start..=end)
+                .map(|i| (xs[i], ys[i], flags[i] & ON_CURVE != 0))
+                .collect(),
+        );
+        start = end + 1;
+    }
+    Ok(contours)
+}
+
+// A contour of on- and off-curve points as straight segments, each quadratic curve cut
+// into segments pieces. Two off-curve points in a row imply an on-curve one halfway
+// between them.
+fn flatten(points: &[(Float, Float, bool)], segments: usize) -> Contour {
+    let n = points.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    // Start on the curve, at a point that is on it or else halfway from the last to the first
+    let (start, origin) = match points.iter().position(|p| p.2) {
+        Some(i) => (i, (points[i].0, points[i].1)),
+        None => (
+            n - 1,
+            (
+                (points[n - 1].0 + points[0].0) * 0.5,
+                (points[n - 1].1 + points[0].1) * 0.5,
+            ),
+        ),
+    };
+    let mut contour = vec![origin];
+    let mut pen = origin;
+    let mut control: Option<(Float, Float)> = None;
+    for k in 1..=n {
+        let p = points[(start + k) % n];
+        let here = (p.0, p.1);
+        match (p.2, control) {
+            (true, None) => {
+                contour.push(here);
+                pen = here;
+            }
+            (true, Some(c)) => {
+                curve(&mut contour, pen, c, here, segments);
+                pen = here;
+                control = None;
+            }
+            (false, None) => control = Some(here),
+            (false, Some(c)) => {
+                let mid = ((c.0 + here.0) * 0.5, (c.1 + here.1) * 0.5);
+                curve(&mut contour, pen, c, mid, segments);
+                pen = mid;
+                control = Some(here);
+            }
+        }
+    }
+    if let Some(c) = control {
+        curve(&mut contour, pen, c, origin, segments);
+    }
+    // The walk ends back where it started, and repeated points are of no use
+    contour.dedup();
+    if contour.len() > 1 && contour.first() == contour.last() {
+        contour.pop();
+    }
+    contour
+}
+
+fn curve(
+    contour: &mut Contour,
+    from: (Float, Float),
+    control: (Float, Float),
+    to: (Float, Float),
+    segments: usize,
+) {
+    for i in 1..=segments {
+        let t = i as Float / segments as Float;
+        let s = 1.0 - t;
+        contour.push((
+            s * s * from.0 + 2.0 * s * t * control.0 + t * t * to.0,
+            s * s * from.1 + 2.0 * s * t * control.1 + t * t * to.1,
+        ));
+    }
+}
+
+// Big-endian reads at absolute offsets, failing rather than panicking past the end
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&self, at: usize, count: usize) -> io::Result<&'a [u8]> {
+        at.checked_add(count)
+            .and_then(|end| self.bytes.get(at..end))
+            .ok_or_else(|| invalid_data("font file ended early".to_string()))
+    }
+
+    fn u16(&self, at: usize) -> io::Result<u16> {
+        let bytes = self.take(at, 2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i16(&self, at: usize) -> io::Result<i16> {
+        Ok(self.u16(at)? as i16)
+    }
+
+    fn u32(&self, at: usize) -> io::Result<u32> {
+        let bytes = self.take(at, 4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
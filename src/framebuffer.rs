@@ -0,0 +1,484 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::exr::{self, Channel, Precision, Samples};
+use crate::png::{self, ColorType};
+use crate::scene::BACKGROUND_ID;
+use crate::tiles::TileRect;
+use crate::vec3::{Float, Vec3f};
+
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vec3f>,
+    // Coverage already premultiplied into pixels; only present for transparent renders
+    pub alpha: Option<Vec<Float>>,
+    // Per pixel, the fraction covered by each object ID it saw, largest first
+    pub coverage: Option<Vec<Vec<(u32, Float)>>>,
+    // Per pixel, the standard deviation of its samples in each channel
+    pub deviation: Option<Vec<Vec3f>>,
+    // (key, value) notes saved with the image, like how it was rendered: tEXt chunks in a
+    // PNG, comment lines in a PPM
+    pub metadata: Vec<(String, String)>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Vec3f(0.0, 0.0, 0.0); width * height],
+            alpha: None,
+            coverage: None,
+            deviation: None,
+            metadata: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, x: usize, y: usize) -> Vec3f {
+        self.pixels[y * self.width + x]
+    }
+
+    #[inline]
+    pub fn set(&mut self, x: usize, y: usize, color: Vec3f) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    // Copy of the pixels inside rect, which must lie within the frame
+    pub fn cropped(&self, rect: &TileRect) -> Framebuffer {
+        let width = self.width;
+        let indices: Vec<usize> = (rect.y0..rect.y1)
+            .flat_map(|y| (rect.x0..rect.x1).map(move |x| y * width + x))
+            .collect();
+        Framebuffer {
+            width: rect.width(),
+            height: rect.height(),
+            pixels: indices.iter().map(|&i| self.pixels[i]).collect(),
+            alpha: self
+                .alpha
+                .as_ref()
+                .map(|alpha| indices.iter().map(|&i| alpha[i]).collect()),
+            coverage: self
+                .coverage
+                .as_ref()
+                .map(|coverage| indices.iter().map(|&i| coverage[i].clone()).collect()),
+            deviation: self
+                .deviation
+                .as_ref()
+                .map(|deviation| indices.iter().map(|&i| deviation[i]).collect()),
+            metadata: self.metadata.clone(),
+        }
+    }
+
+    // Overwrites the pixels under other placed with its corner at (x0, y0), clipping to the frame
+    pub fn paste(&mut self, x0: usize, y0: usize, other: &Framebuffer) {
+        if other.alpha.is_some() && self.alpha.is_none() {
+            self.alpha = Some(vec![0.0; self.pixels.len()]);
+        }
+        if other.coverage.is_some() && self.coverage.is_none() {
+            self.coverage = Some(vec![Vec::new(); self.pixels.len()]);
+        }
+        if other.deviation.is_some() && self.deviation.is_none() {
+            self.deviation = Some(vec![Vec3f(0.0, 0.0, 0.0); self.pixels.len()]);
+        }
+        for y in 0..other.height.min(self.height.saturating_sub(y0)) {
+            for x in 0..other.width.min(self.width.saturating_sub(x0)) {
+                let (i, j) = ((y0 + y) * self.width + x0 + x, y * other.width + x);
+                self.pixels[i] = other.pixels[j];
+                if let Some(alpha) = &mut self.alpha {
+                    alpha[i] = other.alpha.as_ref().map_or(1.0, |a| a[j]);
+                }
+                if let Some(coverage) = &mut self.coverage {
+                    coverage[i] = other.coverage.as_ref().map_or(Vec::new(), |c| c[j].clone());
+                }
+                if let Some(deviation) = &mut self.deviation {
+                    deviation[i] = other
+                        .deviation
+                        .as_ref()
+                        .map_or(Vec3f(0.0, 0.0, 0.0), |d| d[j]);
+                }
+            }
+        }
+    }
+
+    pub fn write_ppm(&self, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        writeln!(file, "P6")?;
+        // One note per line, so line breaks in a value would end it early
+        for (key, value) in &self.metadata {
+            writeln!(file, "# {}: {}", key, value.replace(['\r', '\n'], " "))?;
+        }
+        writeln!(file, "{} {}\n255", self.width, self.height)?;
+        for &Vec3f(r, g, b) in &self.pixels {
+            let max_value = 255.0;
+            file.write_all(&[
+                (max_value * r.clamp(0.0, 1.0)) as u8,
+                (max_value * g.clamp(0.0, 1.0)) as u8,
+                (max_value * b.clamp(0.0, 1.0)) as u8,
+            ])?;
+        }
+
+        file.flush()
+    }
+
+    // 8-bit RGBA with straight alpha, opaque where there is no alpha channel, the layout
+    // canvases and most image APIs take
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.pixels.len() * 4);
+        for (i, &Vec3f(r, g, b)) in self.pixels.iter().enumerate() {
+            let a = self.alpha.as_ref().map_or(1.0, |alpha| alpha[i]);
+            let unpremultiply = if a > 0.0 { 1.0 / a } else { 0.0 };
+            data.extend_from_slice(&[
+                to_byte(r * unpremultiply),
+                to_byte(g * unpremultiply),
+                to_byte(b * unpremultiply),
+                to_byte(a),
+            ]);
+        }
+        data
+    }
+
+    // Writes RGBA with straight alpha when the render has coverage, RGB otherwise
+    pub fn write_png(&self, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.encode_png(&mut file)?;
+        file.flush()
+    }
+
+    // The PNG write_png would save, written to out
+    pub fn encode_png<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let (color, data) = match &self.alpha {
+            Some(_) => (ColorType::Rgba, self.to_rgba8()),
+            None => {
+                let mut data = Vec::with_capacity(self.pixels.len() * 3);
+                for &Vec3f(r, g, b) in &self.pixels {
+                    data.extend_from_slice(&[to_byte(r), to_byte(g), to_byte(b)]);
+                }
+                (ColorType::Rgb, data)
+            }
+        };
+
+        png::write_png(out, self.width, self.height, color, &data, &self.metadata)
+    }
+
+    // The object covering most of each pixel
+    pub fn object_ids(&self) -> Option<Vec<u32>> {
+        let coverage = self.coverage.as_ref()?;
+        Some(
+            coverage
+                .iter()
+                .map(|entries| entries.first().map_or(BACKGROUND_ID, |&(id, _)| id))
+                .collect(),
+        )
+    }
+
+    // How much of each pixel the object with this ID covers
+    pub fn matte(&self, id: u32) -> Option<Vec<Float>> {
+        let coverage = self.coverage.as_ref()?;
+        Some(
+            coverage
+                .iter()
+                .map(|entries| {
+                    entries
+                        .iter()
+                        .find(|&&(entry, _)| entry == id)
+                        .map_or(0.0, |&(_, c)| c.clamp(0.0, 1.0))
+                })
+                .collect(),
+        )
+    }
+
+    // 16-bit grayscale PNG of the dominant object ID; IDs above 65535 saturate
+    pub fn write_object_ids(&self, path: &Path) -> io::Result<()> {
+        let ids = self.object_ids().ok_or_else(missing_coverage)?;
+        let mut data = Vec::with_capacity(ids.len() * 2);
+        for id in ids {
+            data.extend_from_slice(&(id.min(u16::MAX as u32) as u16).to_be_bytes());
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+        png::write_png(
+            &mut file,
+            self.width,
+            self.height,
+            ColorType::Gray16,
+            &data,
+            &self.metadata,
+        )?;
+        file.flush()
+    }
+
+    // 8-bit grayscale PNG of one object's coverage
+    pub fn write_matte(&self, path: &Path, id: u32) -> io::Result<()> {
+        let matte = self.matte(id).ok_or_else(missing_coverage)?;
+        let data: Vec<u8> = matte.iter().map(|&c| (255.0 * c) as u8).collect();
+        let mut file = BufWriter::new(File::create(path)?);
+        png::write_png(
+            &mut file,
+            self.width,
+            self.height,
+            ColorType::Gray,
+            &data,
+            &self.metadata,
+        )?;
+        file.flush()
+    }
+
+    // The per-pixel standard deviation as an image of its own, in the same units as the
+    // pixels, so noisy regions show up bright
+    pub fn write_deviation(&self, path: &Path) -> io::Result<()> {
+        let deviation = self.deviation.clone().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "render was made without variance tracking",
+            )
+        })?;
+        Framebuffer {
+            pixels: deviation,
+            ..Framebuffer::new(self.width, self.height)
+        }
+        .write_image(path)
+    }
+
+    // One multilayer EXR holding everything the render tracked: the color and alpha as
+    // R, G, B and A, the sample deviation as the sigma layer, the dominant object ID as
+    // an exact whole number in id.id, and the coverage of each object in mattes as
+    // matte<ID>.A. Colors are linear and unclamped, premultiplied as EXR expects.
+    pub fn write_exr(&self, path: &Path, precision: Precision, mattes: &[u32]) -> io::Result<()> {
+        let rgb = |values: &[Vec3f], prefix: &str| {
+            [("R", 0), ("G", 1), ("B", 2)].map(|(name, axis)| Channel {
+                name: format!("{}{}", prefix, name),
+                samples: Samples::Float(values.iter().map(|v| v[axis] as f32).collect()),
+            })
+        };
+        let mut channels = Vec::from(rgb(&self.pixels, ""));
+        if let Some(alpha) = &self.alpha {
+            channels.push(Channel {
+                name: "A".to_string(),
+                samples: Samples::Float(alpha.iter().map(|&a| a as f32).collect()),
+            });
+        }
+        if let Some(deviation) = &self.deviation {
+            channels.extend(rgb(deviation, "sigma."));
+        }
+        if let Some(ids) = self.object_ids() {
+            channels.push(Channel {
+                name: "id.id".to_string(),
+                samples: Samples::Uint(ids),
+            });
+        }
+        for &id in mattes {
+            let matte = self.matte(id).ok_or_else(missing_coverage)?;
+            channels.push(Channel {
+                name: format!("matte{}.A", id),
+                samples: Samples::Float(matte.iter().map(|&c| c as f32).collect()),
+            });
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+        exr::write_exr(
+            &mut file,
+            self.width,
+            self.height,
+            &channels,
+            precision,
+            &self.metadata,
+        )?;
+        file.flush()
+    }
+
+    // Loads a PNG or binary PPM; alpha is kept, premultiplied, when the image has any
+    pub fn read_image(path: &Path) -> io::Result<Framebuffer> {
+        let reader = BufReader::new(File::open(path)?);
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("png") => {
+                let image = png::read_png(reader)?;
+                let mut pixels = Vec::with_capacity(image.width * image.height);
+                let mut alpha = Vec::with_capacity(image.width * image.height);
+                for p in image.rgba.chunks_exact(4) {
+                    let a = p[3] as Float / 255.0;
+                    let channel = |c: u8| c as Float / 255.0 * a;
+                    pixels.push(Vec3f(channel(p[0]), channel(p[1]), channel(p[2])));
+                    alpha.push(a);
+                }
+                Ok(Framebuffer {
+                    width: image.width,
+                    height: image.height,
+                    pixels,
+                    alpha: alpha.iter().any(|&a| a < 1.0).then_some(alpha),
+                    coverage: None,
+                    deviation: None,
+                    metadata: image.text,
+                })
+            }
+            Some(ext) if ext.eq_ignore_ascii_case("ppm") => Framebuffer::read_ppm(reader),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported image format: {}", path.display()),
+            )),
+        }
+    }
+
+    // Binary (P6) PPM as written by write_ppm, any max value up to 65535
+    pub fn read_ppm<R: BufRead>(mut reader: R) -> io::Result<Framebuffer> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let mut fields = Vec::with_capacity(4);
+        let mut metadata = Vec::new();
+        let mut line = String::new();
+        while fields.len() < 4 {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(invalid("PPM header ended early"));
+            }
+            let (content, comment) = line.split_once('#').unwrap_or((&line, ""));
+            fields.extend(content.split_whitespace().map(str::to_string));
+            // Notes as write_ppm leaves them, "# key: value"
+            if let Some((key, value)) = comment.trim().split_once(": ") {
+                metadata.push((key.to_string(), value.to_string()));
+            }
+        }
+        if fields[0] != "P6" || fields.len() > 4 {
+            return Err(invalid("only binary P6 PPMs are supported"));
+        }
+        let number = |s: &str| s.parse::<usize>().map_err(|_| invalid("bad PPM header"));
+        let (width, height, max_value) = (
+            number(&fields[1])?,
+            number(&fields[2])?,
+            number(&fields[3])?,
+        );
+        if max_value == 0 || max_value > 65535 {
+            return Err(invalid("bad PPM max value"));
+        }
+
+        let sample_size = if max_value > 255 { 2 } else { 1 };
+        let mut data = vec![0u8; width * height * 3 * sample_size];
+        reader.read_exact(&mut data)?;
+        let value = |i: usize| -> Float {
+            let raw = if sample_size == 2 {
+                u16::from_be_bytes([data[i * 2], data[i * 2 + 1]]) as Float
+            } else {
+                data[i] as Float
+            };
+            raw / max_value as Float
+        };
+        let pixels = (0..width * height)
+            .map(|i| Vec3f(value(i * 3), value(i * 3 + 1), value(i * 3 + 2)))
+            .collect();
+        Ok(Framebuffer {
+            width,
+            height,
+            pixels,
+            alpha: None,
+            coverage: None,
+            deviation: None,
+            metadata,
+        })
+    }
+
+    // Picks the format from the file extension
+    pub fn write_image(&self, path: &Path) -> io::Result<()> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("png") => self.write_png(path),
+            Some(ext) if ext.eq_ignore_ascii_case("ppm") => self.write_ppm(path),
+            Some(ext) if ext.eq_ignore_ascii_case("exr") => {
+                self.write_exr(path, Precision::Half, &[])
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported image format: {}", path.display()),
+            )),
+        }
+    }
+}
+
+fn to_byte(v: Float) -> u8 {
+    (255.0 * v.clamp(0.0, 1.0)) as u8
+}
+
+fn missing_coverage() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "render was made without object ID tracking",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(image: &Framebuffer) -> png::PngImage {
+        let mut encoded = Vec::new();
+        image.encode_png(&mut encoded).unwrap();
+        png::read_png(&encoded[..]).unwrap()
+    }
+
+    #[test]
+    fn saves_coverage_as_straight_alpha() {
+        let mut image = Framebuffer::new(3, 1);
+        // A half-covered red pixel is stored premultiplied, and written back at full red
+        image.pixels = vec![
+            Vec3f(0.5, 0.0, 0.0),
+            Vec3f(0.0, 0.0, 0.0),
+            Vec3f(0.2, 0.4, 1.0),
+        ];
+        image.alpha = Some(vec![0.5, 0.0, 1.0]);
+        let rgba = image.to_rgba8();
+        assert_eq!(rgba, [255, 0, 0, 127, 0, 0, 0, 0, 51, 102, 255, 255]);
+        let png = decoded(&image);
+        assert_eq!((png.width, png.height), (3, 1));
+        assert_eq!(png.rgba, rgba);
+
+        // Without an alpha channel the file is RGB and reads back opaque
+        image.alpha = None;
+        assert_eq!(image.to_rgba8()[3], 255);
+        let png = decoded(&image);
+        assert_eq!(png.rgba, [127, 0, 0, 255, 0, 0, 0, 255, 51, 102, 255, 255]);
+
+        // Pasting an opaque image into a transparent one fills its alpha in as covered
+        let mut frame = Framebuffer::new(4, 2);
+        frame.alpha = Some(vec![0.0; 8]);
+        frame.paste(2, 1, &image);
+        assert_eq!(
+            frame.alpha.unwrap(),
+            [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn saves_metadata_with_pngs_and_ppms() {
+        let mut image = Framebuffer::new(2, 1);
+        image.pixels = vec![Vec3f(1.0, 0.0, 0.0), Vec3f(0.0, 0.0, 1.0)];
+        image.metadata = vec![
+            ("Samples".to_string(), "16".to_string()),
+            ("Scene".to_string(), "two\nlines".to_string()),
+        ];
+        assert_eq!(decoded(&image).text, image.metadata);
+
+        let dir = std::env::temp_dir().join("rusty_rays_metadata");
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["image.png", "image.ppm"] {
+            let path = dir.join(name);
+            image.write_image(&path).unwrap();
+            let read = Framebuffer::read_image(&path).unwrap();
+            assert_eq!(read.pixels, image.pixels, "{}", name);
+            // A PPM comment ends at its line, so the value's own break became a space
+            let scene = if name.ends_with("ppm") {
+                "two lines"
+            } else {
+                "two\nlines"
+            };
+            assert_eq!(
+                read.metadata,
+                [
+                    ("Samples".to_string(), "16".to_string()),
+                    ("Scene".to_string(), scene.to_string()),
+                ],
+                "{}",
+                name
+            );
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::material::{BlendMaterial, Material};
+use crate::scene::Visibility;
+use crate::shapes::Shape;
+use crate::texture::TextureMap;
+use crate::transform::Transform;
+
+// A subtree of the scene. Its transform applies on top of every ancestor's, and hiding
+// it hides everything below. Scene::add_group flattens it into ordinary objects while
+// remembering the hierarchy, so visibility can still be switched per group afterwards.
+pub struct Group {
+    pub name: Option<String>,
+    pub transform: Transform,
+    pub visible: bool,
+    pub(crate) children: Vec<Node>,
+}
+
+// Nodes only live until the group is added to a scene, so their size hardly matters
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Node {
+    Object {
+        shape: Box<dyn Shape>,
+        material: Material,
+        id: Option<u32>,
+        name: Option<String>,
+        texture: Option<TextureMap>,
+        blend: Option<BlendMaterial>,
+        visibility: Visibility,
+    },
+    Group(Group),
+}
+
+impl Default for Group {
+    fn default() -> Group {
+        Group::new()
+    }
+}
+
+impl Group {
+    pub fn new() -> Group {
+        Group::with_transform(Transform::identity())
+    }
+
+    pub fn with_transform(transform: Transform) -> Group {
+        Group {
+            name: None,
+            transform,
+            visible: true,
+            children: Vec::new(),
+        }
+    }
+
+    // Objects get their IDs when the group is added to a scene, in the order they were
+    // added here
+    pub fn add<S: Shape + 'static>(&mut self, shape: S, material: Material) {
+        self.add_boxed(Box::new(shape), material);
+    }
+
+    pub fn add_boxed(&mut self, shape: Box<dyn Shape>, material: Material) {
+        self.children.push(Node::Object {
+            shape,
+            material,
+            id: None,
+            name: None,
+            texture: None,
+            blend: None,
+            visibility: Visibility::ALL,
+        });
+    }
+
+    pub fn add_boxed_with_id(&mut self, shape: Box<dyn Shape>, material: Material, id: u32) {
+        self.children.push(Node::Object {
+            shape,
+            material,
+            id: Some(id),
+            name: None,
+            texture: None,
+            blend: None,
+            visibility: Visibility::ALL,
+        });
+    }
+
+    pub fn add_named<S: Shape + 'static>(&mut self, name: &str, shape: S, material: Material) {
+        self.children.push(Node::Object {
+            shape: Box::new(shape),
+            material,
+            id: None,
+            name: Some(name.to_string()),
+            texture: None,
+            blend: None,
+            visibility: Visibility::ALL,
+        });
+    }
+
+    pub fn add_group(&mut self, group: Group) {
+        self.children.push(Node::Group(group));
+    }
+
+    pub fn len(&self) -> usize {
+        self.children.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+}
@@ -0,0 +1,165 @@
+// Refractive indices of common transparent media as functions of wavelength, from the
+// dispersion formulas and coefficients optical catalogues publish for them. The
+// coefficients keep their published digits even where f32 cannot hold them all.
+#![allow(clippy::excessive_precision)]
+
+use crate::vec3::Float;
+
+// The sodium D line, where catalogues quote a medium's single refractive index
+pub const D_LINE: Float = 589.3;
+// The hydrogen F and C lines, either side of it, over which the Abbe number is taken
+pub const F_LINE: Float = 486.1;
+pub const C_LINE: Float = 656.3;
+
+// Air's density falls off by this factor every scale height, in metres
+const AIR_SCALE_HEIGHT: Float = 8500.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dispersion {
+    // n = a + b / λ² + c / λ⁴, with λ in micrometres
+    Cauchy { a: Float, b: Float, c: Float },
+    // n² = 1 + Σ bᵢ λ² / (λ² - cᵢ), with λ in micrometres and the cᵢ in µm²
+    Sellmeier { b: [Float; 3], c: [Float; 3] },
+    // Ciddor's formula for dry air at 15 °C, with n - 1 scaled by the density relative to
+    // sea level
+    Air { density: Float },
+}
+
+impl Dispersion {
+    // The refractive index at a wavelength in nanometres
+    pub fn index(&self, wavelength: Float) -> Float {
+        let microns = wavelength / 1000.0;
+        let l2 = microns * microns;
+        match *self {
+            Dispersion::Cauchy { a, b, c } => a + b / l2 + c / (l2 * l2),
+            Dispersion::Sellmeier { b, c } => {
+                let sum: Float = (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+            Dispersion::Air { density } => {
+                let k = 1.0 / l2;
+                let refractivity = 0.05792105 / (238.0185 - k) + 0.00167917 / (57.362 - k);
+                1.0 + refractivity * density
+            }
+        }
+    }
+
+    // The index at the D line, the one to use when tracing a single wavelength
+    pub fn nd(&self) -> Float {
+        self.index(D_LINE)
+    }
+
+    // (nd - 1) / (nF - nC); lower numbers spread colors further apart
+    pub fn abbe_number(&self) -> Float {
+        (self.nd() - 1.0) / (self.index(F_LINE) - self.index(C_LINE))
+    }
+}
+
+// Air at an altitude in metres, its density falling off exponentially with height
+pub fn air_at(altitude: Float) -> Dispersion {
+    Dispersion::Air {
+        density: (-altitude / AIR_SCALE_HEIGHT).exp(),
+    }
+}
+
+// By the names scene files refer to them with
+pub const MEDIA: &[(&str, Dispersion)] = &[
+    ("air", Dispersion::Air { density: 1.0 }),
+    // At 20 °C, fitted to nd = 1.333 and nF - nC = 0.006
+    (
+        "water",
+        Dispersion::Cauchy {
+            a: 1.3240,
+            b: 0.00314,
+            c: 0.0,
+        },
+    ),
+    // Schott N-BK7, the common crown glass of lenses and prisms
+    (
+        "crown_glass",
+        Dispersion::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        },
+    ),
+    // Schott SF11, a dense flint that spreads colors nearly four times as far
+    (
+        "flint_glass",
+        Dispersion::Sellmeier {
+            b: [1.73759695, 0.313747346, 1.89878101],
+            c: [0.013188707, 0.0623068142, 155.23629],
+        },
+    ),
+    // Malitson's fit for fused quartz
+    (
+        "fused_silica",
+        Dispersion::Sellmeier {
+            b: [0.6961663, 0.4079426, 0.8974794],
+            c: [0.00467914826, 0.0135120631, 97.9340025],
+        },
+    ),
+    // The ordinary ray of Malitson and Dodge's fit
+    (
+        "sapphire",
+        Dispersion::Sellmeier {
+            b: [1.4313493, 0.65054713, 5.3414021],
+            c: [0.0052799261, 0.0142382647, 325.017834],
+        },
+    ),
+    (
+        "diamond",
+        Dispersion::Sellmeier {
+            b: [0.3306, 4.3356, 0.0],
+            c: [0.030625, 0.011236, 0.0],
+        },
+    ),
+];
+
+pub fn medium(name: &str) -> Option<Dispersion> {
+    MEDIA.iter().find(|(n, _)| *n == name).map(|(_, d)| *d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::consts::E;
+
+    #[test]
+    fn media_match_their_catalogue_values() {
+        // (name, nd, Abbe number) as catalogues list them
+        let catalogue = [
+            ("water", 1.333, 55.5),
+            ("crown_glass", 1.5168, 64.17),
+            ("flint_glass", 1.78472, 25.68),
+            ("fused_silica", 1.4585, 67.8),
+            ("sapphire", 1.768, 72.2),
+            ("diamond", 2.4175, 55.3),
+        ];
+        for (name, nd, abbe) in catalogue {
+            let medium = medium(name).unwrap();
+            assert!(
+                (medium.nd() - nd).abs() < 2e-3,
+                "{} nd {}",
+                name,
+                medium.nd()
+            );
+            let found = medium.abbe_number();
+            assert!(
+                (found - abbe).abs() < 0.02 * abbe,
+                "{} abbe {}",
+                name,
+                found
+            );
+            // Blue bends further than red in all of them
+            assert!(medium.index(450.0) > medium.index(650.0), "{}", name);
+        }
+        assert!(medium("glass").is_none());
+
+        let air = medium("air").unwrap();
+        assert!((air.nd() - 1.000277).abs() < 1e-5, "{}", air.nd());
+        // A scale height up, the air is a factor of e thinner
+        let thin = air_at(AIR_SCALE_HEIGHT).nd() - 1.0;
+        assert!((thin * E - (air.nd() - 1.0)).abs() < 1e-6);
+        assert_eq!(air_at(0.0), air);
+    }
+}
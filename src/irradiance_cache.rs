@@ -0,0 +1,172 @@
+// Diffuse light bounced between surfaces, worked out at sparse points and blended in
+// between them (Ward, Rubinstein and Clear 1988). Indirect diffuse light changes slowly
+// except near other surfaces, so each record is reused out to a distance in proportion
+// to how far away the surfaces around it are: widely in open space, hardly at all in
+// corners. Interior scenes lit mostly by interreflection then shade smoothly from a few
+// thousand records instead of a noisy path per sample.
+use crate::bvh::{Aabb, Bvh};
+use crate::vec3::{Float, Vec3f};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IrradianceCaching {
+    // How far a record reaches, as a fraction of the distance to the surfaces around it;
+    // lower is smoother near contact and slower
+    pub accuracy: Float,
+    // Rays gathered over the hemisphere for each record
+    pub samples: u32,
+}
+
+impl Default for IrradianceCaching {
+    fn default() -> IrradianceCaching {
+        IrradianceCaching {
+            accuracy: 0.25,
+            samples: 128,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct IrradianceRecord {
+    pub point: Vec3f,
+    // Unit length, facing the side the light was gathered on
+    pub normal: Vec3f,
+    // The average radiance arriving over the hemisphere, cosine weighted, which a diffuse
+    // surface reflects scaled by its color
+    pub radiance: Vec3f,
+    // The harmonic mean distance to the surfaces the gathering rays met
+    pub radius: Float,
+}
+
+pub struct IrradianceCache {
+    records: Vec<IrradianceRecord>,
+    accuracy: Float,
+    // Over the cube each record reaches across
+    bvh: Bvh,
+}
+
+impl IrradianceCache {
+    pub fn new(accuracy: Float) -> IrradianceCache {
+        IrradianceCache {
+            records: Vec::new(),
+            accuracy,
+            bvh: Bvh::build(&[]),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    // Adds records in one go, since the tree over them is rebuilt each time
+    pub fn extend<I: IntoIterator<Item = IrradianceRecord>>(&mut self, records: I) {
+        self.records.extend(records);
+        let bounds: Vec<Aabb> = self
+            .records
+            .iter()
+            .map(|record| {
+                let reach = self.accuracy * record.radius;
+                Aabb::around(record.point, Vec3f(reach, reach, reach))
+            })
+            .collect();
+        self.bvh = Bvh::build(&bounds);
+    }
+
+    // The blended radiance of the records reaching point on a surface facing normal, or
+    // None when none do and the light has to be gathered afresh
+    pub fn lookup(&self, point: &Vec3f, normal: &Vec3f) -> Option<Vec3f> {
+        let mut total = Vec3f(0.0, 0.0, 0.0);
+        let mut weights = 0.0;
+        self.bvh.containing(point, |index| {
+            let record = &self.records[index];
+            if let Some(weight) = self.weight(record, point, normal) {
+                total += record.radiance * weight;
+                weights += weight;
+            }
+        });
+        (weights > 0.0).then(|| total * (1.0 / weights))
+    }
+
+    // Ward's weight, large where the record is close by and faces the same way; None
+    // beyond its reach or in front of point, where it may have seen what point cannot
+    fn weight(&self, record: &IrradianceRecord, point: &Vec3f, normal: &Vec3f) -> Option<Float> {
+        let offset = *point - record.point;
+        if offset.dot(&(*normal + record.normal)) < -0.1 * record.radius {
+            return None;
+        }
+        let error =
+            offset.length() / record.radius + (1.0 - normal.dot(&record.normal)).max(0.0).sqrt();
+        (error < self.accuracy).then(|| 1.0 / error.max(1e-6))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(point: Vec3f, radiance: Float, radius: Float) -> IrradianceRecord {
+        IrradianceRecord {
+            point,
+            normal: Vec3f(0.0, 1.0, 0.0),
+            radiance: Vec3f(radiance, radiance, radiance),
+            radius,
+        }
+    }
+
+    #[test]
+    fn blends_the_records_that_reach_a_point() {
+        let up = Vec3f(0.0, 1.0, 0.0);
+        let mut cache = IrradianceCache::new(0.5);
+        assert!(cache.is_empty());
+        assert!(cache.lookup(&Vec3f(0.0, 0.0, 0.0), &up).is_none());
+        cache.extend([record(Vec3f(0.0, 0.0, 0.0), 1.0, 2.0)]);
+        assert_eq!(
+            cache.lookup(&Vec3f(0.0, 0.0, 0.0), &up),
+            Some(Vec3f(1.0, 1.0, 1.0))
+        );
+        // Out to half the record's radius on the surface, and no further
+        assert!(cache.lookup(&Vec3f(0.9, 0.0, 0.0), &up).is_some());
+        assert!(cache.lookup(&Vec3f(1.1, 0.0, 0.0), &up).is_none());
+        // Nor onto surfaces turned well away, or behind the record's surface
+        let tilted = Vec3f(0.0, 1.0, 1.0).normalized().unwrap();
+        assert!(cache.lookup(&Vec3f(0.0, 0.0, 0.0), &tilted).is_none());
+        assert!(cache.lookup(&Vec3f(0.0, -0.5, 0.0), &up).is_none());
+
+        // Between two records the nearer one counts for more
+        cache.extend([record(Vec3f(1.0, 0.0, 0.0), 3.0, 2.0)]);
+        assert_eq!(cache.len(), 2);
+        let near_first = cache.lookup(&Vec3f(0.3, 0.0, 0.0), &up).unwrap();
+        assert!(near_first.0 > 1.0 && near_first.0 < 2.0, "{:?}", near_first);
+        let halfway = cache.lookup(&Vec3f(0.5, 0.0, 0.0), &up).unwrap();
+        assert!((halfway.0 - 2.0).abs() < 1e-5, "{:?}", halfway);
+
+        // The tree finds the same records as looking through them all would
+        let mut many = IrradianceCache::new(0.5);
+        let records: Vec<_> = (0..200)
+            .map(|i| {
+                let (x, z) = ((i % 20) as Float * 0.37, (i / 20) as Float * 0.53);
+                record(Vec3f(x, 0.0, z), i as Float, 0.2 + (i % 7) as Float * 0.3)
+            })
+            .collect();
+        many.extend(records.iter().copied());
+        for j in 0..50 {
+            let point = Vec3f(j as Float * 0.15, 0.0, j as Float * 0.1);
+            let (mut total, mut weights) = (0.0, 0.0);
+            for r in &records {
+                if let Some(w) = many.weight(r, &point, &up) {
+                    total += r.radiance.0 * w;
+                    weights += w;
+                }
+            }
+            let expected = (weights > 0.0).then(|| total / weights);
+            let found = many.lookup(&point, &up).map(|c| c.0);
+            match (found, expected) {
+                (Some(a), Some(b)) => assert!((a - b).abs() < 1e-3 * b.max(1.0), "{} {}", a, b),
+                (a, b) => assert_eq!(a, b),
+            }
+        }
+    }
+}
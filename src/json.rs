@@ -0,0 +1,481 @@
+use std::io;
+
+// Deepest nesting of arrays and objects accepted, far beyond any scene's, so hostile input
+// is refused before the parser's recursion runs out of stack
+const MAX_DEPTH: usize = 256;
+
+// A parsed JSON document. Object members keep their order, duplicates included.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> io::Result<Json> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            pos: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.bytes.len() {
+            return Err(parser.error("trailing characters after the document"));
+        }
+        Ok(value)
+    }
+
+    // The last member named key, if this is an object that has one
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().rev().find(|(k, _)| k == key).map(|m| &m.1),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Json)]> {
+        match self {
+            Json::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+
+    // Name of the value's type, for error messages
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(_) => "a boolean",
+            Json::Number(_) => "a number",
+            Json::String(_) => "a string",
+            Json::Array(_) => "an array",
+            Json::Object(_) => "an object",
+        }
+    }
+}
+
+// text as a JSON string, quotes and all
+pub fn quote(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    // Arrays and objects the parser is inside
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> io::Error {
+        let before = &self.bytes[..self.pos.min(self.bytes.len())];
+        let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+        // Counted in characters, so UTF-8 continuation bytes are skipped
+        let column = before
+            .iter()
+            .rev()
+            .take_while(|&&b| b != b'\n')
+            .filter(|&&b| b & 0xc0 != 0x80)
+            .count()
+            + 1;
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("JSON line {}, column {}: {}", line, column, message),
+        )
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> io::Result<()> {
+        if self.bytes.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", byte as char)))
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> io::Result<Json> {
+        if self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected character"))
+        }
+    }
+
+    fn value(&mut self) -> io::Result<Json> {
+        self.skip_whitespace();
+        match self.bytes.get(self.pos) {
+            None => Err(self.error("unexpected end of input")),
+            Some(b'{' | b'[') => {
+                if self.depth == MAX_DEPTH {
+                    return Err(self.error(&format!("nested more than {} deep", MAX_DEPTH)));
+                }
+                self.depth += 1;
+                let value = if self.bytes[self.pos] == b'{' {
+                    self.object()
+                } else {
+                    self.array()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+        }
+    }
+
+    fn object(&mut self) -> io::Result<Json> {
+        self.expect(b'{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> io::Result<Json> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        loop {
+            let start = self.pos;
+            while let Some(&b) = self.bytes.get(self.pos) {
+                if b == b'"' || b == b'\\' || b < 0x20 {
+                    break;
+                }
+                self.pos += 1;
+            }
+            // The input is a &str and runs stop at ASCII bytes, so slices stay valid UTF-8
+            out.push_str(std::str::from_utf8(&self.bytes[start..self.pos]).unwrap());
+            match self.bytes.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escape = *self
+                        .bytes
+                        .get(self.pos)
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    match escape {
+                        b'"' => out.push('"'),
+                        b'\\' => out.push('\\'),
+                        b'/' => out.push('/'),
+                        b'b' => out.push('\u{8}'),
+                        b'f' => out.push('\u{c}'),
+                        b'n' => out.push('\n'),
+                        b'r' => out.push('\r'),
+                        b't' => out.push('\t'),
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // Surrogate pairs encode characters outside the BMP; an escape
+                            // after a lone high surrogate is read again on its own
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.bytes[self.pos..].starts_with(b"\\u")
+                            {
+                                let next = self.pos;
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                if (0xdc00..0xe000).contains(&low) {
+                                    code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                                } else {
+                                    self.pos = next;
+                                }
+                            }
+                            out.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        _ => return Err(self.error("invalid escape sequence")),
+                    }
+                }
+                Some(_) => return Err(self.error("control character in string")),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> io::Result<u32> {
+        let digits = self
+            .bytes
+            .get(self.pos..self.pos + 4)
+            .filter(|d| d.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    // Digits from the current position on, and how many there were
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while let Some(b'0'..=b'9') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    // Only JSON's own grammar: no leading zeros, no '+', and digits on both sides of a '.'
+    fn number(&mut self) -> io::Result<Json> {
+        let start = self.pos;
+        if self.bytes.get(self.pos) == Some(&b'-') {
+            self.pos += 1;
+        }
+        let whole = self.pos;
+        let mut valid = match self.digits() {
+            0 => false,
+            n => n == 1 || self.bytes[whole] != b'0',
+        };
+        if self.bytes.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            valid &= self.digits() > 0;
+        }
+        if let Some(b'e' | b'E') = self.bytes.get(self.pos) {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.bytes.get(self.pos) {
+                self.pos += 1;
+            }
+            valid &= self.digits() > 0;
+        }
+        let number = std::str::from_utf8(&self.bytes[start..self.pos])
+            .ok()
+            .filter(|_| valid)
+            .and_then(|n| n.parse::<f64>().ok());
+        match number {
+            Some(n) if n.is_finite() => Ok(Json::Number(n)),
+            Some(_) => {
+                self.pos = start;
+                Err(self.error("number out of range"))
+            }
+            None => {
+                self.pos = start;
+                Err(self.error("invalid number"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(text: &str) -> String {
+        match Json::parse(text).unwrap() {
+            Json::String(s) => s,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    fn error(text: &str) -> String {
+        Json::parse(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn reads_string_escapes_and_surrogates() {
+        assert_eq!(string(r#""\"\\\/\b\f\n\r\t""#), "\"\\/\u{8}\u{c}\n\r\t");
+        assert_eq!(string(r#""café é""#), "café é");
+        assert_eq!(string(r#""😀""#), "\u{1f600}");
+        assert_eq!(string("\"naïve 😀\""), "naïve 😀");
+        // Lone halves of a pair stand in as U+FFFD, and what follows a lone high half
+        // is still read
+        assert_eq!(string(r#""\ud83d""#), "\u{fffd}");
+        assert_eq!(string(r#""\ude00x""#), "\u{fffd}x");
+        assert_eq!(string(r#""\ud83dA""#), "\u{fffd}A");
+        assert_eq!(string(r#""\ud83d😀""#), "\u{fffd}\u{1f600}");
+
+        for bad in [
+            r#""\x""#,
+            r#""\u12""#,
+            r#""\u+123""#,
+            r#""\ud83d\u12""#,
+            "\"tab\tinside\"",
+            r#""open"#,
+        ] {
+            assert!(Json::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn reads_numbers_by_the_json_grammar() {
+        for (text, value) in [
+            ("0", 0.0),
+            ("-0", 0.0),
+            ("12", 12.0),
+            ("-3.25", -3.25),
+            ("1e3", 1000.0),
+            ("2E-2", 0.02),
+            ("0.5e+1", 5.0),
+            ("1.7976931348623157e308", f64::MAX),
+            ("5e-324", 5e-324),
+        ] {
+            assert_eq!(Json::parse(text).unwrap(), Json::Number(value), "{}", text);
+        }
+        for bad in [
+            "01", "-", "+1", ".5", "1.", "1e", "1e+", "--1", "0x10", "-.5",
+        ] {
+            assert!(Json::parse(bad).is_err(), "{}", bad);
+        }
+        assert!(error("[1, 1e400]").contains("out of range"));
+        assert_eq!(
+            Json::parse("[1.5,-2]").unwrap(),
+            Json::Array(vec![Json::Number(1.5), Json::Number(-2.0)])
+        );
+    }
+
+    #[test]
+    fn places_errors_by_line_and_character() {
+        assert_eq!(
+            error("{\n  \"a\": 1,\n  \"b\" 2\n}"),
+            "JSON line 3, column 7: expected ':'"
+        );
+        assert_eq!(error("[1, 01]"), "JSON line 1, column 5: invalid number");
+        // Columns count characters, not UTF-8 bytes
+        assert_eq!(
+            error("[\"é😀\", tru]"),
+            "JSON line 1, column 8: unexpected character"
+        );
+        assert_eq!(
+            error("[1,"),
+            "JSON line 1, column 4: unexpected end of input"
+        );
+        assert_eq!(
+            error("{} x"),
+            "JSON line 1, column 4: trailing characters after the document"
+        );
+        assert!(error(&"[".repeat(MAX_DEPTH + 1)).contains("nested"));
+        assert!(Json::parse(&format!(
+            "{}{}",
+            "[".repeat(MAX_DEPTH),
+            "]".repeat(MAX_DEPTH)
+        ))
+        .is_ok());
+    }
+
+    #[test]
+    fn keeps_members_in_order_and_finds_the_last() {
+        let json = Json::parse(r#"{"a": null, "b": [true, false], "a": "again"}"#).unwrap();
+        let keys: Vec<&str> = json
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|m| m.0.as_str())
+            .collect();
+        assert_eq!(keys, ["a", "b", "a"]);
+        assert_eq!(json.get("a").and_then(Json::as_str), Some("again"));
+        assert_eq!(
+            json.get("b").and_then(Json::as_array).map(<[Json]>::len),
+            Some(2)
+        );
+        assert_eq!(json.get("c"), None);
+    }
+
+    #[test]
+    fn quotes_strings_that_parse_back() {
+        for text in [
+            "plain",
+            "\"quoted\" \\ back",
+            "line\nbreak\ttab\r",
+            "\u{1}\u{1f}",
+            "é😀",
+            "",
+        ] {
+            assert_eq!(string(&quote(text)), text);
+        }
+        assert_eq!(quote("a\"b\u{7}"), r#""a\"b\u0007""#);
+    }
+}
@@ -0,0 +1,24 @@
+// Render layers: the same scene drawn several times with a different part of it in
+// front each time, for a compositor to grade and blur the foreground apart from the
+// background. A layer's members are objects and groups by name; everything else is held
+// out or hidden. Held-out objects still cast their shadows and show in reflections, but
+// camera rays see a hole where they stand, black and, over a transparent background,
+// clear, so the layers stack back up into the whole picture. Hidden objects are left out
+// of the render altogether. The floor, not being an object, shows in every layer.
+
+// What a layer makes of the objects outside it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LayerRole {
+    #[default]
+    Shown,
+    Holdout,
+    Hidden,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderLayer {
+    pub name: String,
+    // Names of the objects and groups in the layer; a group brings everything under it
+    pub members: Vec<String>,
+    pub others: LayerRole,
+}
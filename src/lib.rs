@@ -0,0 +1,68 @@
+// Casts between Float and fixed-width types are no-ops in one of the two precisions
+#![allow(clippy::unnecessary_cast)]
+
+pub mod arena;
+pub mod bloom;
+pub mod bvh;
+pub mod camera;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod clip;
+pub mod console;
+pub mod contact_sheet;
+pub mod denoise;
+pub mod differential;
+pub mod environment;
+pub mod examples_scenes;
+pub mod exposure;
+pub mod exr;
+pub mod filter;
+pub mod font;
+pub mod framebuffer;
+pub mod group;
+pub mod ior;
+pub mod irradiance_cache;
+pub mod json;
+pub mod layers;
+pub mod light;
+pub mod lightmap;
+pub mod lod;
+pub mod log;
+pub mod material;
+pub mod medium_stack;
+pub mod mesh;
+pub mod nanovdb;
+pub mod occlusion;
+pub mod onb;
+pub mod path_debug;
+pub mod png;
+pub mod point_cloud;
+pub mod portal;
+pub mod preview;
+pub mod probes;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod quartic;
+pub mod render;
+pub mod rng;
+pub mod sampler;
+pub mod scatter;
+pub mod scene;
+pub mod scene_file;
+pub mod server;
+pub mod shapes;
+pub mod stats;
+pub mod terminal;
+pub mod text;
+pub mod texture;
+pub mod tiles;
+pub mod transform;
+pub mod turntable;
+pub mod variables;
+pub mod vec3;
+pub mod video;
+pub mod volume;
+pub mod vox;
+pub mod voxel;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
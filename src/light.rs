@@ -1,106 +1,310 @@
-const NEAREST_DIST_THRESHOLD: f32 = 1e10;
-const SMALL_NUMBER: f32 = 0.001;
+use crate::onb::Onb;
+use crate::vec3::{consts::PI, Float, Vec3f};
 
-pub struct Lights {
-    pub sources: [Vec3f; 3],
+#[derive(Clone, Debug)]
+pub struct Light {
+    pub position: Vec3f,
+    pub intensity: Float,
+    // Scales the intensity per channel, white by default
+    pub color: Vec3f,
+    pub links: LightLinks,
+    // Zero for a point light. A sphere this big around the position gives off as much
+    // light as the point would, spread over its surface, so its shadows soften; the
+    // Whitted integrator still treats it as the point.
+    pub radius: Float,
 }
 
-impl Lights {
-    pub fn reflect(&self, I: &Vec3f, N: &Vec3f) -> Vec3f {
-        I.subtract(&N.multiply_scalar(2.0 * I.dot(N)))
+impl Light {
+    pub fn new(position: Vec3f, intensity: Float) -> Light {
+        Light {
+            position,
+            intensity,
+            color: Vec3f(1.0, 1.0, 1.0),
+            links: LightLinks::All,
+            radius: 0.0,
+        }
+    }
+
+    pub fn with_links(mut self, links: LightLinks) -> Light {
+        self.links = links;
+        self
+    }
+
+    pub fn with_color(mut self, color: Vec3f) -> Light {
+        self.color = color;
+        self
+    }
+
+    pub fn with_radius(mut self, radius: Float) -> Light {
+        self.radius = radius;
+        self
     }
 
-    pub fn refract(&self, I: &Vec3f, N: &Vec3f, eta_t: f32, eta_i: f32) -> Vec3f {
-        let cosi = -f32::max(-1.0, f32::min(1.0, I.dot(N)));
-        if cosi < 0.0 {
-            return self.refract(I, &N.negate(), eta_i, eta_t);
+    // Seen from point, the cosine of the widest angle off the centre at which the sphere
+    // still shows, and the solid angle it covers; None for a point light or from inside
+    pub fn cone(&self, point: &Vec3f) -> Option<(Float, Float)> {
+        let to_center = self.position - *point;
+        let distance2 = to_center.dot(&to_center);
+        let radius2 = self.radius * self.radius;
+        if self.radius <= 0.0 || distance2 <= radius2 {
+            return None;
         }
-        let eta = eta_i / eta_t;
-        let k = 1.0 - eta * eta * (1.0 - cosi * cosi);
-        if k < 0.0 {
-            Vec3f(1.0, 0.0, 0.0)
-        } else {
-            I.multiply_scalar(eta)
-                .add(&N.multiply_scalar(eta * cosi - k.sqrt()))
+        let cos_max = (1.0 - radius2 / distance2).sqrt();
+        Some((cos_max, 2.0 * PI * (1.0 - cos_max)))
+    }
+
+    // A direction from point towards the sphere, uniform over its cone, and the distance
+    // to its surface that way
+    pub fn sample_cone(
+        &self,
+        point: &Vec3f,
+        cos_max: Float,
+        u1: Float,
+        u2: Float,
+    ) -> (Vec3f, Float) {
+        let to_center = self.position - *point;
+        let axis = to_center.normalized().unwrap_or(Vec3f(0.0, 1.0, 0.0));
+        let cos_theta = 1.0 - u1 * (1.0 - cos_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        let dir = Onb::from_normal(&axis).to_world(&Vec3f(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+        // Directions grazing the rim can just miss it by rounding; the tangent length
+        // is where they touch
+        let distance = self.surface_distance(point, &dir).unwrap_or_else(|| {
+            (to_center.dot(&to_center) - self.radius * self.radius)
+                .max(0.0)
+                .sqrt()
+        });
+        (dir, distance)
+    }
+
+    // How far along dir from point the sphere's surface is, if that way meets it
+    pub fn surface_distance(&self, point: &Vec3f, dir: &Vec3f) -> Option<Float> {
+        let to_center = self.position - *point;
+        let along = to_center.dot(dir);
+        let miss2 = to_center.dot(&to_center) - along * along;
+        let radius2 = self.radius * self.radius;
+        if along <= 0.0 || miss2 > radius2 {
+            return None;
         }
+        Some(along - (radius2 - miss2).sqrt())
     }
 }
 
-fn cast_ray(orig: &Vec3f, dir: &Vec3f, depth: i32) -> Vec3f {
-    let (hit, point, n, material) = scene_intersect(orig, dir);
-    if depth > 4 || !hit {
-        return Vec3f(0.2, 0.7, 0.8); // background color
+// Color temperatures blackbody accepts, in kelvin: from candlelight to the bluest sky
+pub const TEMPERATURE_RANGE: (Float, Float) = (1000.0, 40000.0);
+
+// The linear RGB color of a black body glowing at kelvin, scaled to unit luminance so a
+// light's intensity means the same whatever its temperature. Planck's law is summed
+// against the CIE 1931 observer, using the fit of Wyman, Sloan and Shirley (2013), and
+// taken to sRGB primaries; the deepest reds fall outside them and lose their blue.
+pub fn blackbody(kelvin: Float) -> Vec3f {
+    // Second radiation constant hc/k, in nanometre kelvins
+    const C2: Float = 1.4388e7;
+    let lobe = |x: Float, mean: Float, below: Float, above: Float| {
+        let spread = if x < mean { below } else { above };
+        (-0.5 * ((x - mean) / spread).powi(2)).exp()
+    };
+    let mut xyz = Vec3f(0.0, 0.0, 0.0);
+    for step in 0..=80 {
+        let wavelength = 380.0 + 5.0 * step as Float;
+        let radiance = wavelength.powi(-5) / ((C2 / (wavelength * kelvin)).exp() - 1.0);
+        let x = 1.056 * lobe(wavelength, 599.8, 37.9, 31.0)
+            + 0.362 * lobe(wavelength, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(wavelength, 501.1, 20.4, 26.2);
+        let y = 0.821 * lobe(wavelength, 568.8, 46.9, 40.5)
+            + 0.286 * lobe(wavelength, 530.9, 16.3, 31.1);
+        let z = 1.217 * lobe(wavelength, 437.0, 11.8, 36.0)
+            + 0.681 * lobe(wavelength, 459.0, 26.0, 13.8);
+        xyz += Vec3f(x, y, z) * radiance;
     }
+    let Vec3f(x, y, z) = xyz * (1.0 / xyz.1);
+    Vec3f(
+        (3.2406 * x - 1.5372 * y - 0.4986 * z).max(0.0),
+        (-0.9689 * x + 1.8758 * y + 0.0415 * z).max(0.0),
+        (0.0557 * x - 0.2040 * y + 1.0570 * z).max(0.0),
+    )
+}
 
-    let reflect_dir = reflect(dir, &n).normalized();
-    let refract_dir = refract(dir, &n, material.refractive_index).normalized();
-    let reflect_color = cast_ray(&point, &reflect_dir, depth + 1);
-    let refract_color = cast_ray(&point, &refract_dir, depth + 1);
+// Light of one color falling on every surface from all around, unshadowed, for flat fill
+// lighting without an environment map. Only diffuse surfaces show it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmbientLight {
+    pub color: Vec3f,
+}
 
-    let mut diffuse_light_intensity = 0.0;
-    let mut specular_light_intensity = 0.0;
-    for light in &LIGHTS {
-        let light_dir = light.subtract(&point).normalized();
-        let (shadow_hit, shadow_pt, _, _) = scene_intersect(&point, &light_dir);
-        if shadow_hit && (shadow_pt.subtract(&point).norm() < light.subtract(&point).norm()) {
-            continue;
-        }
-        diffuse_light_intensity += f32::max(0.0, light_dir.dot(&n));
-        specular_light_intensity += f32::powf(
-            f32::max(0.0, -reflect(&light_dir.negate(), &n).dot(dir)),
-            material.specular_exponent,
-        );
+// Ambient light that is the sky color on surfaces facing up and the ground color on those
+// facing down, blended in between by the normal, the usual quick stand-in for outdoor
+// light bounced off the ground
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HemisphereLight {
+    pub sky: Vec3f,
+    pub ground: Vec3f,
+    // Unit length
+    pub up: Vec3f,
+}
+
+impl HemisphereLight {
+    // None when up has no direction
+    pub fn new(sky: Vec3f, ground: Vec3f, up: Vec3f) -> Option<HemisphereLight> {
+        Some(HemisphereLight {
+            sky,
+            ground,
+            up: up.normalized()?,
+        })
+    }
+
+    pub fn color(&self, normal: &Vec3f) -> Vec3f {
+        let t = 0.5 + 0.5 * normal.dot(&self.up);
+        self.ground * (1.0 - t) + self.sky * t
     }
-    material
-        .diffuse_color
-        .multiply_scalar(diffuse_light_intensity * material.albedo[0])
-        .add(&Vec3f(1.0, 1.0, 1.0).multiply_scalar(specular_light_intensity * material.albedo[1]))
-        .add(&reflect_color.multiply_scalar(material.albedo[2]))
-        .add(&refract_color.multiply_scalar(material.albedo[3]))
 }
 
-pub fn scene_intersect(
-    orig: &Vec3f,
-    dir: &Vec3f,
-    spheres: &[Sphere],
-) -> (bool, Vec3f, Vec3f, Material) {
-    let mut pt = Vec3f(0.0, 0.0, 0.0);
-    let mut N = Vec3f(0.0, 0.0, 0.0);
-    let mut material = Material {
-        refractive_index: 1.0,
-        albedo: [1.0; 4],
-        diffuse_color: Vec3f(0.0, 0.0, 0.0),
-        specular_exponent: 0.0,
-    };
+// Which objects, by ID, a light shines on. Linking is an art-direction cheat: an
+// unlinked object is simply unlit by the light, whether or not it is in shadow.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LightLinks {
+    All,
+    Only(Vec<u32>),
+    Except(Vec<u32>),
+}
 
-    let mut nearest_dist = 1e10;
-
-    if dir.1.abs() > 0.001 {
-        let d = -(orig.1 + 4.0) / dir.1;
-        let p = orig.add(&dir.multiply_scalar(d));
-        if d > 0.001 && d < nearest_dist && p.0.abs() < 10.0 && p.2 < -10.0 && p.2 > -30.0 {
-            nearest_dist = d;
-            pt = p;
-            N = Vec3f(0.0, 1.0, 0.0);
-            material.diffuse_color =
-                if ((0.5 * pt.0 + 1000.0) as i32 + (0.5 * pt.2) as i32) & 1 == 0 {
-                    Vec3f(0.3, 0.3, 0.3)
-                } else {
-                    Vec3f(0.3, 0.2, 0.1)
-                };
+impl LightLinks {
+    pub fn illuminates(&self, object_id: u32) -> bool {
+        match self {
+            LightLinks::All => true,
+            LightLinks::Only(ids) => ids.contains(&object_id),
+            LightLinks::Except(ids) => !ids.contains(&object_id),
         }
     }
 
-    for s in spheres.iter() {
-        let (intersection, d) = s.ray_intersect(orig, dir);
-        if !intersection || d > nearest_dist {
-            continue;
+    pub fn ids(&self) -> &[u32] {
+        match self {
+            LightLinks::All => &[],
+            LightLinks::Only(ids) | LightLinks::Except(ids) => ids,
         }
-        nearest_dist = d;
-        pt = orig.add(&dir.multiply_scalar(nearest_dist));
-        N = pt.subtract(&s.center);
-        material = s.material;
+    }
+}
+
+#[allow(non_snake_case)]
+pub fn reflect(I: &Vec3f, N: &Vec3f) -> Vec3f {
+    *I - *N * (2.0 * I.dot(N))
+}
+
+// Snell's law; eta_i is the index of the medium the ray travels in before the interface
+#[allow(non_snake_case)]
+pub fn refract(I: &Vec3f, N: &Vec3f, eta_t: Float, eta_i: Float) -> Vec3f {
+    let cosi = -I.dot(N).clamp(-1.0, 1.0);
+    if cosi < 0.0 {
+        return refract(I, &-*N, eta_i, eta_t);
+    }
+    let eta = eta_i / eta_t;
+    let k = 1.0 - eta * eta * (1.0 - cosi * cosi);
+    if k < 0.0 {
+        Vec3f(1.0, 0.0, 0.0)
+    } else {
+        *I * eta + *N * (eta * cosi - k.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Scene;
+
+    #[test]
+    fn sphere_lights_are_sampled_over_the_cone_they_fill() {
+        let point = Vec3f(0.0, 0.0, 0.0);
+        assert!(Light::new(Vec3f(0.0, 3.0, 0.0), 1.0).cone(&point).is_none());
+        let light = Light::new(Vec3f(0.0, 2.0, 0.0), 1.0).with_radius(1.0);
+        assert!(light.cone(&Vec3f(0.0, 1.5, 0.0)).is_none());
+        // Seen from twice its radius away, the sphere fills 30 degrees about its centre
+        let (cos_max, solid_angle) = light.cone(&point).unwrap();
+        assert!((cos_max - Float::sqrt(0.75)).abs() < 1e-5);
+        assert!((solid_angle - 2.0 * PI * (1.0 - cos_max)).abs() < 1e-5);
+
+        let up = Vec3f(0.0, 1.0, 0.0);
+        assert!((light.surface_distance(&point, &up).unwrap() - 1.0).abs() < 1e-5);
+        assert!(light.surface_distance(&point, &-up).is_none());
+        assert!(light
+            .surface_distance(&point, &Vec3f(1.0, 1.0, 0.0).normalized().unwrap())
+            .is_none());
+
+        // Each direction is inside the cone and lands on the surface
+        let mut mean_cos = 0.0;
+        for i in 0..32 {
+            for j in 0..32 {
+                let (u1, u2) = ((i as Float + 0.5) / 32.0, (j as Float + 0.5) / 32.0);
+                let (dir, distance) = light.sample_cone(&point, cos_max, u1, u2);
+                assert!((dir.length() - 1.0).abs() < 1e-5);
+                assert!(dir.1 >= cos_max - 1e-5, "{:?}", dir);
+                let on = dir * distance - light.position;
+                assert!((on.length() - 1.0).abs() < 1e-3, "{:?}", on);
+                mean_cos += dir.1 / 1024.0;
+            }
+        }
+        // Uniform over the cone, the cosine is uniform between cos_max and one
+        assert!(
+            (mean_cos - (1.0 + cos_max) / 2.0).abs() < 1e-3,
+            "{}",
+            mean_cos
+        );
     }
 
-    (nearest_dist < 1000.0, pt, N, material)
+    #[test]
+    fn hemisphere_lights_blend_from_ground_to_sky() {
+        let sky = Vec3f(0.2, 0.4, 1.0);
+        let ground = Vec3f(0.4, 0.3, 0.0);
+        assert!(HemisphereLight::new(sky, ground, Vec3f(0.0, 0.0, 0.0)).is_none());
+        let light = HemisphereLight::new(sky, ground, Vec3f(0.0, 0.0, 2.0)).unwrap();
+        assert_eq!(light.up, Vec3f(0.0, 0.0, 1.0));
+        assert_eq!(light.color(&Vec3f(0.0, 0.0, 1.0)), sky);
+        assert_eq!(light.color(&Vec3f(0.0, 0.0, -1.0)), ground);
+        let side = light.color(&Vec3f(1.0, 0.0, 0.0));
+        assert!((side - (sky + ground) * 0.5).length() < 1e-6);
+
+        // The scene adds up every ambient and hemisphere light for a normal
+        let mut scene = Scene::new();
+        assert_eq!(scene.ambient(&Vec3f(0.0, 0.0, 1.0)), Vec3f(0.0, 0.0, 0.0));
+        scene.ambient_lights.push(AmbientLight {
+            color: Vec3f(0.1, 0.1, 0.1),
+        });
+        scene.hemisphere_lights.push(light);
+        let total = scene.ambient(&Vec3f(0.0, 0.0, -1.0));
+        assert!(
+            (total - Vec3f(0.5, 0.4, 0.1)).length() < 1e-6,
+            "{:?}",
+            total
+        );
+    }
+
+    #[test]
+    fn blackbodies_redden_as_they_cool_at_unit_luminance() {
+        let luminance = |c: Vec3f| 0.2126 * c.0 + 0.7152 * c.1 + 0.0722 * c.2;
+        let (low, high) = TEMPERATURE_RANGE;
+        let temperatures = [low, 1900.0, 2700.0, 4000.0, 5000.0, 6500.0, 10000.0, high];
+        let colors: Vec<Vec3f> = temperatures.iter().map(|&k| blackbody(k)).collect();
+        for (k, c) in temperatures.iter().zip(&colors) {
+            // Only the coolest lose luminance, with the blue they cannot show
+            let tolerance = if *k < 1900.0 { 1e-2 } else { 1e-3 };
+            assert!((luminance(*c) - 1.0).abs() < tolerance, "{} {:?}", k, c);
+        }
+        for pair in colors.windows(2) {
+            assert!(pair[1].0 < pair[0].0, "{:?}", pair);
+            assert!(pair[1].2 >= pair[0].2, "{:?}", pair);
+        }
+        assert_eq!(colors[0].2, 0.0);
+        // Daylight is close to white, and the hottest skies are blue
+        let daylight = blackbody(6500.0);
+        assert!(
+            (daylight - Vec3f(1.0, 1.0, 1.0)).length() < 0.1,
+            "{:?}",
+            daylight
+        );
+        assert!(colors[7].2 > 2.0 * colors[7].0);
+    }
 }
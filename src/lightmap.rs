@@ -0,0 +1,197 @@
+// Light baked into the texture coordinates of a mesh, for game engines and other
+// real-time renderers to multiply by the surface color instead of lighting it themselves.
+// Each texel a triangle covers in UV space gets what diffuse_irradiance finds at the
+// matching point on the surface. Texels no triangle covers are then filled from their
+// covered neighbours, a ring at a time out to the padding, so filtering and mipmaps
+// across a UV seam blend the baked light with more of the same instead of black.
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::framebuffer::Framebuffer;
+use crate::mesh::TriangleMesh;
+use crate::render::{diffuse_irradiance, run_workers, RenderSettings};
+use crate::rng::Rng;
+use crate::scene::{Object, Scene};
+use crate::vec3::{Float, Vec3f};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Lightmap {
+    pub width: usize,
+    pub height: usize,
+    // Rays gathered over the hemisphere above each texel
+    pub samples: u32,
+    // How many texels past the edge of each UV island get filled in
+    pub padding: usize,
+}
+
+impl Default for Lightmap {
+    fn default() -> Lightmap {
+        Lightmap {
+            width: 256,
+            height: 256,
+            samples: 64,
+            padding: 4,
+        }
+    }
+}
+
+impl Lightmap {
+    // Bakes the light falling on the object called name, which has to be a mesh with
+    // texture coordinates, as the rest of scene casts it; settings give the depth bounced
+    // light is followed to, the seed and the threads used
+    pub fn bake(
+        &self,
+        scene: &Scene,
+        name: &str,
+        settings: &RenderSettings,
+    ) -> io::Result<Framebuffer> {
+        let (object, mesh) = named_mesh(scene, name)?;
+        let uvs = mesh.uvs().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} has no texture coordinates", name),
+            )
+        })?;
+        let (width, height) = (self.width.max(1), self.height.max(1));
+
+        // Where on the surface each texel's center lies, and which way it faces there
+        let mut surface: Vec<Option<(Vec3f, Vec3f)>> = vec![None; width * height];
+        let vertices = mesh.vertices();
+        for &[a, b, c] in mesh.faces() {
+            // In texels, rows from the top as images store them
+            let texel = |i: usize| {
+                (
+                    uvs[i].0 * width as Float,
+                    (1.0 - uvs[i].1) * height as Float,
+                )
+            };
+            let (p0, p1, p2) = (texel(a), texel(b), texel(c));
+            let area = edge(p0, p1, p2);
+            if area.abs() <= Float::EPSILON || !area.is_finite() {
+                continue;
+            }
+            let normal = (vertices[b] - vertices[a]).cross(&(vertices[c] - vertices[a]));
+            let span = |lo: Float, hi: Float, size: usize| {
+                let from = (lo - 0.5).ceil().max(0.0) as usize;
+                let to = ((hi - 0.5).floor() + 1.0).clamp(0.0, size as Float) as usize;
+                from..to
+            };
+            let xs = span(p0.0.min(p1.0).min(p2.0), p0.0.max(p1.0).max(p2.0), width);
+            for y in span(p0.1.min(p1.1).min(p2.1), p0.1.max(p1.1).max(p2.1), height) {
+                for x in xs.clone() {
+                    let p = (x as Float + 0.5, y as Float + 0.5);
+                    let (w0, w1, w2) = (
+                        edge(p1, p2, p) / area,
+                        edge(p2, p0, p) / area,
+                        edge(p0, p1, p) / area,
+                    );
+                    // A little slack so texels on an edge shared by two faces are not lost
+                    if w0 < -1e-4 || w1 < -1e-4 || w2 < -1e-4 {
+                        continue;
+                    }
+                    let point = vertices[a] * w0 + vertices[b] * w1 + vertices[c] * w2;
+                    let n = match mesh.normals() {
+                        Some(normals) => normals[a] * w0 + normals[b] * w1 + normals[c] * w2,
+                        None => normal,
+                    };
+                    if let Some(n) = n.normalized() {
+                        surface[y * width + x] = Some((point, n));
+                    }
+                }
+            }
+        }
+
+        // A row at a time across the threads
+        let baked = Mutex::new(vec![None; width * height]);
+        let next_row = AtomicUsize::new(0);
+        let threads = settings
+            .threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+            .clamp(1, height);
+        run_workers(threads, None, || loop {
+            let y = next_row.fetch_add(1, Ordering::Relaxed);
+            if y >= height {
+                break;
+            }
+            let row: Vec<Option<Vec3f>> = (y * width..(y + 1) * width)
+                .map(|i| {
+                    let (point, n) = surface[i]?;
+                    let mut rng = Rng::for_stream(settings.seed, i as u64);
+                    Some(diffuse_irradiance(
+                        scene,
+                        &point,
+                        &n,
+                        object.id,
+                        settings,
+                        self.samples,
+                        &mut rng,
+                    ))
+                })
+                .collect();
+            let mut baked = baked.lock().unwrap();
+            baked[y * width..(y + 1) * width].copy_from_slice(&row);
+        });
+        let mut baked = baked.into_inner().unwrap();
+        dilate(&mut baked, width, height, self.padding);
+
+        let mut image = Framebuffer::new(width, height);
+        for (pixel, texel) in image.pixels.iter_mut().zip(baked) {
+            *pixel = texel.unwrap_or(Vec3f(0.0, 0.0, 0.0));
+        }
+        Ok(image)
+    }
+}
+
+// The object called name and its mesh in world space, for baking
+pub(crate) fn named_mesh<'a>(
+    scene: &'a Scene,
+    name: &str,
+) -> io::Result<(&'a Object, TriangleMesh)> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let object = scene
+        .get(name)
+        .ok_or_else(|| invalid(format!("no object named {}", name)))?;
+    let mesh = object
+        .shape
+        .mesh()
+        .ok_or_else(|| invalid(format!("{} is not a mesh", name)))?;
+    Ok((object, mesh))
+}
+
+// Twice the signed area of the triangle a, b, c
+fn edge(a: (Float, Float), b: (Float, Float), c: (Float, Float)) -> Float {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+// Gives each empty texel next to filled ones their average, rings times over
+fn dilate(texels: &mut [Option<Vec3f>], width: usize, height: usize, rings: usize) {
+    for _ in 0..rings {
+        let mut grown = texels.to_vec();
+        for y in 0..height {
+            for x in 0..width {
+                if texels[y * width + x].is_some() {
+                    continue;
+                }
+                let mut sum = Vec3f(0.0, 0.0, 0.0);
+                let mut count = 0;
+                for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                        if let Some(texel) = texels[ny * width + nx] {
+                            sum += texel;
+                            count += 1;
+                        }
+                    }
+                }
+                if count > 0 {
+                    grown[y * width + x] = Some(sum * (1.0 / count as Float));
+                }
+            }
+        }
+        if grown == texels {
+            break;
+        }
+        texels.copy_from_slice(&grown);
+    }
+}
@@ -0,0 +1,114 @@
+// A shape kept at several levels of detail, showing the finest only while it is big on
+// screen. Which level shows is picked once per frame from the camera, by how many pixels
+// across the shape's bounds appear, which is its size over the footprint a camera ray's
+// differentials cover at that distance. Picking per frame rather than per ray keeps
+// shadows and reflections of the shape matching the level the camera sees; picking per
+// ray would let a shadow ray leaving a coarse level hit the finer one inside it.
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::bvh::Aabb;
+use crate::camera::Camera;
+use crate::scene::Diagnostic;
+use crate::shapes::{HitRecord, Shape};
+use crate::vec3::{Float, Vec3f};
+
+// Where a frame is seen from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View {
+    pub position: Vec3f,
+    // The height one pixel spans at unit distance from the camera
+    pub pixel_footprint: Float,
+}
+
+impl View {
+    pub fn new(camera: &Camera, width: usize, height: usize) -> View {
+        let fov = camera.vertical_fov(width, height);
+        View {
+            position: camera.position,
+            pixel_footprint: 2.0 * (fov / 2.0).tan() / height.max(1) as Float,
+        }
+    }
+
+    // How many pixels across a ball of radius at center covers
+    pub fn pixels(&self, center: &Vec3f, radius: Float) -> Float {
+        let distance = (*center - self.position).length();
+        2.0 * radius / (distance * self.pixel_footprint)
+    }
+}
+
+pub struct Lod {
+    // Coarser levels take over once the shape spans fewer pixels than their threshold
+    levels: Vec<(Float, Box<dyn Shape>)>,
+    bounds: Aabb,
+    selected: AtomicUsize,
+}
+
+impl Lod {
+    // Starts from the finest level, shown until set_view picks another
+    pub fn new(finest: Box<dyn Shape>) -> Lod {
+        Lod {
+            bounds: finest.bounds(),
+            levels: vec![(Float::INFINITY, finest)],
+            selected: AtomicUsize::new(0),
+        }
+    }
+
+    // Adds a coarser level, shown once the shape spans fewer than below pixels
+    pub fn then(mut self, below: Float, coarser: Box<dyn Shape>) -> Lod {
+        self.bounds = self.bounds.union(&coarser.bounds());
+        self.levels.push((below, coarser));
+        self
+    }
+
+    // Which level shows, 0 being the finest
+    pub fn selected(&self) -> usize {
+        self.selected.load(Ordering::Relaxed)
+    }
+
+    fn level(&self) -> &dyn Shape {
+        &*self.levels[self.selected()].1
+    }
+}
+
+impl Shape for Lod {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        self.level().hit(orig, dir)
+    }
+
+    // Every level's, so the scene's hierarchy holds whichever is picked
+    fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    fn set_view(&self, view: &View) {
+        let radius = (self.bounds.max - self.bounds.min).length() / 2.0;
+        let pixels = view.pixels(&self.bounds.centroid(), radius);
+        let level = self
+            .levels
+            .iter()
+            .rposition(|(below, _)| pixels < *below)
+            .unwrap_or(0);
+        self.selected.store(level, Ordering::Relaxed);
+        for (_, shape) in &self.levels {
+            shape.set_view(view);
+        }
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut issues: Vec<Diagnostic> = self
+            .levels
+            .iter()
+            .flat_map(|(_, shape)| shape.diagnostics())
+            .collect();
+        if self
+            .levels
+            .windows(2)
+            .any(|pair| pair[1].0 >= pair[0].0 || pair[1].0.is_nan())
+        {
+            issues.push(Diagnostic::error(
+                "level thresholds must shrink from finer to coarser levels".to_string(),
+            ));
+        }
+        issues
+    }
+}
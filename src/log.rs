@@ -0,0 +1,89 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+// Messages go to stderr when their level is at or above the maximum, which defaults to
+// warnings. Errors and warnings read like the scene diagnostics; info is plain status
+// text; debug and trace lines carry the time since the first message and the module
+// they came from, for finding where a slow load or render spends its time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+static START: OnceLock<Instant> = OnceLock::new();
+
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+// Always false on wasm32, which has neither stderr nor a clock, so callers timing work
+// for a message can skip reading the clock there
+#[inline]
+pub fn enabled(level: Level) -> bool {
+    !cfg!(target_arch = "wasm32") && level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+// A clock reading for timing work a message at this level will report, taken only
+// when the message will be written
+pub fn timer(level: Level) -> Option<Instant> {
+    enabled(level).then(Instant::now)
+}
+
+pub fn write(level: Level, module: &str, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    let elapsed = START.get_or_init(Instant::now).elapsed().as_secs_f64();
+    let module = module.strip_prefix("rusty_rays::").unwrap_or(module);
+    let mut stderr = io::stderr().lock();
+    // A closed stderr is no reason to stop rendering
+    let _ = match level {
+        Level::Error => writeln!(stderr, "error: {}", args),
+        Level::Warn => writeln!(stderr, "warning: {}", args),
+        Level::Info => writeln!(stderr, "{}", args),
+        Level::Debug => writeln!(stderr, "[{:8.3}s] debug {}: {}", elapsed, module, args),
+        Level::Trace => writeln!(stderr, "[{:8.3}s] trace {}: {}", elapsed, module, args),
+    };
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write($level, module_path!(), format_args!($($arg)+))
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Trace, $($arg)+) };
+}
@@ -1,39 +1,2063 @@
-use std::fs::File;
-use std::io::{self, Write};
-use std::path::Path;
+// Casts between Float and fixed-width types are no-ops in one of the two precisions
+#![allow(clippy::unnecessary_cast)]
 
-mod vec3;
-use vec3::Vec3f;
+use std::env;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
-fn create_gradient_image(width: usize, height: usize) -> Vec<Vec3f> {
-    let mut framebuffer = Vec::with_capacity(width * height);
-    for j in 0..height {
-        for i in 0..width {
-            framebuffer.push(Vec3f(j as f32 / height as f32, i as f32 / width as f32, 0.0));
+use rusty_rays::camera::Camera;
+use rusty_rays::console::{Console, Reply};
+use rusty_rays::contact_sheet::{ContactSheet, Parameter};
+use rusty_rays::denoise::Denoise;
+use rusty_rays::examples_scenes::{generate, spheres_on_checkerboard, GENERATORS};
+use rusty_rays::exposure::{self, AutoExposure, Histogram};
+use rusty_rays::exr::Precision;
+use rusty_rays::framebuffer::Framebuffer;
+use rusty_rays::json::{self, Json};
+use rusty_rays::layers::RenderLayer;
+use rusty_rays::lightmap::Lightmap;
+use rusty_rays::log::{self, Level};
+use rusty_rays::material::MaterialOverride;
+use rusty_rays::occlusion::VertexOcclusion;
+use rusty_rays::path_debug::PathEvent;
+use rusty_rays::probes::ProbeGrid;
+use rusty_rays::render::{
+    parse_resolution, render, render_with, render_within, trace_pixel, Integrator, RenderObserver,
+    RenderSettings, TileRect, RESOLUTION_PRESETS,
+};
+use rusty_rays::sampler::Sampler;
+use rusty_rays::scene::{Scene, Severity, FLOOR_ID};
+use rusty_rays::scene_file::{BatchJob, FileWatcher, SceneFile};
+use rusty_rays::server::RenderServer;
+use rusty_rays::stats::{ObjectHits, ObjectStats, RayStats};
+use rusty_rays::terminal::{self, Protocol};
+use rusty_rays::turntable::{render_turntable, Turntable};
+use rusty_rays::variables;
+use rusty_rays::vec3::{Float, Vec3f};
+use rusty_rays::video::VideoEncoder;
+use rusty_rays::{debug, error, info};
+
+struct Args {
+    output: PathBuf,
+    transparent: bool,
+    id_pass: Option<PathBuf>,
+    mattes: Vec<(u32, PathBuf)>,
+    // Where to write each pixel's sample standard deviation
+    sigma: Option<PathBuf>,
+    // Where to write a chart of the render's luminance and a false-color exposure map
+    histogram: Option<PathBuf>,
+    false_color: Option<PathBuf>,
+    // How finely EXR output stores its channels
+    exr_precision: Precision,
+    crop: Option<TileRect>,
+    // Keep cropped renders full size instead of writing just the region
+    crop_full: bool,
+    resolution: Option<(usize, usize)>,
+    // Degrees, and whether they span the image horizontally
+    fov: Option<(Float, bool)>,
+    // Move the camera along its view until the whole scene fits the frame
+    frame: bool,
+    // Stops of exposure compensation on top of the camera's own exposure
+    exposure: Option<Float>,
+    // Expose the frame from a prepass of the scene, the stops above then compensating
+    auto_exposure: bool,
+    // Keep adding passes of the scene's sample count until this much wall-clock time is up
+    max_seconds: Option<Duration>,
+    // The least roughness surfaces take on after a path's first diffuse or glossy bounce
+    regularize: Option<Float>,
+    // Smooth away noise once rendered, averaging pixels up to this many pixels apart
+    denoise: Option<usize>,
+    // Trace just this pixel and describe every bounce instead of rendering
+    inspect: Option<(usize, usize)>,
+    // Render a grid of thumbnails stepping one parameter instead, each at the resolution
+    contact_sheet: Option<ContactSheet>,
+    // Or circle the camera around the scene, writing numbered images when the output is an
+    // image and a video otherwise
+    turntable: Option<Turntable>,
+    // Frames per second of video output
+    fps: u32,
+    // Or bake the light falling on the mesh of this name into its texture coordinates,
+    // at the resolution if one is given
+    bake_lightmap: Option<String>,
+    lightmap: Lightmap,
+    // Or bake ambient occlusion into the vertex colors of the named mesh, written as the
+    // PLY or OBJ the output path ends in
+    bake_ao: Option<String>,
+    occlusion: VertexOcclusion,
+    // Or trace a grid of light probes through the scene, written as JSON or the binary
+    // layout in probes.rs by the output's extension
+    probes: Option<ProbeGrid>,
+    // Render this scene file instead of the built-in scene
+    scene: Option<PathBuf>,
+    // Render through these of its named cameras instead of its default one, or "all"
+    cameras: Vec<String>,
+    // And of its render layers, each written to output_layer, or "all"
+    layers: Vec<String>,
+    // Values for variables the scene file defines, replacing its own
+    variables: Vec<(String, Json)>,
+    // Or build one of the generated scenes from this seed
+    generate: Option<String>,
+    seed: Option<u64>,
+    // Re-render whenever the scene file or an asset it uses changes
+    watch: bool,
+    sampler: Option<Sampler>,
+    // Render with every material replaced by clay or a debug view
+    material_override: Option<MaterialOverride>,
+    // Merge tiles in a fixed order so repeated renders match bit for bit
+    deterministic: bool,
+    // Also print each finished image to the terminal in colored character cells
+    preview_ascii: bool,
+    // Or show it inline through a terminal graphics protocol
+    preview_image: Option<Protocol>,
+    // Render every job in this manifest instead of a single image
+    batch: Option<PathBuf>,
+    // Render the scenes POSTed to this address instead
+    serve: Option<String>,
+    // Keep the server's job queue here, so it survives a restart
+    queue_dir: Option<PathBuf>,
+    // The directory served scenes may read images, fonts, models and includes from
+    asset_dir: Option<PathBuf>,
+    // Read commands from stdin that edit the scene and render it, starting from --scene or
+    // the built-in scene
+    repl: bool,
+    // Report ray counts and stage timings after rendering
+    stats: Option<StatsFormat>,
+    // Each -v shows more of what loading and rendering are doing; -q leaves only errors
+    verbosity: i32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StatsFormat {
+    Table,
+    Json,
+}
+
+fn parse_args() -> io::Result<Args> {
+    let mut args = Args {
+        output: PathBuf::from("out.ppm"),
+        transparent: false,
+        id_pass: None,
+        mattes: Vec::new(),
+        sigma: None,
+        histogram: None,
+        false_color: None,
+        exr_precision: Precision::Half,
+        crop: None,
+        crop_full: false,
+        resolution: None,
+        fov: None,
+        frame: false,
+        exposure: None,
+        auto_exposure: false,
+        max_seconds: None,
+        regularize: None,
+        denoise: None,
+        inspect: None,
+        contact_sheet: None,
+        turntable: None,
+        fps: 24,
+        bake_lightmap: None,
+        lightmap: Lightmap::default(),
+        bake_ao: None,
+        occlusion: VertexOcclusion::default(),
+        probes: None,
+        scene: None,
+        cameras: Vec::new(),
+        layers: Vec::new(),
+        variables: Vec::new(),
+        generate: None,
+        seed: None,
+        watch: false,
+        sampler: None,
+        material_override: None,
+        deterministic: false,
+        preview_ascii: false,
+        preview_image: None,
+        batch: None,
+        serve: None,
+        queue_dir: None,
+        asset_dir: None,
+        repl: false,
+        stats: None,
+        verbosity: 0,
+    };
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+    let mut iter = env::args().skip(1).peekable();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                let path = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a path", arg)))?;
+                args.output = PathBuf::from(path);
+            }
+            "--transparent" => args.transparent = true,
+            "--id-pass" => {
+                let path = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a path", arg)))?;
+                args.id_pass = Some(PathBuf::from(path));
+            }
+            "--matte" => {
+                let (id, path) = iter
+                    .next()
+                    .zip(iter.next())
+                    .ok_or_else(|| invalid(format!("{} needs an object ID and a path", arg)))?;
+                let id = id
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid object ID: {}", id)))?;
+                args.mattes.push((id, PathBuf::from(path)));
+            }
+            "--sigma" => {
+                let path = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a path", arg)))?;
+                args.sigma = Some(PathBuf::from(path));
+            }
+            "--histogram" => {
+                let path = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a path", arg)))?;
+                args.histogram = Some(PathBuf::from(path));
+            }
+            "--false-color" => {
+                let path = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a path", arg)))?;
+                args.false_color = Some(PathBuf::from(path));
+            }
+            "--exr-precision" => {
+                let names: Vec<&str> = Precision::NAMES.iter().map(|n| n.0).collect();
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs one of {}", arg, names.join(", "))))?;
+                args.exr_precision = Precision::from_name(&value)
+                    .ok_or_else(|| invalid(format!("unknown EXR precision: {}", value)))?;
+            }
+            "--crop" => {
+                let mut bounds = [0usize; 4];
+                for bound in &mut bounds {
+                    let value = iter
+                        .next()
+                        .ok_or_else(|| invalid(format!("{} needs x0 y0 x1 y1", arg)))?;
+                    *bound = value
+                        .parse()
+                        .map_err(|_| invalid(format!("invalid crop bound: {}", value)))?;
+                }
+                let [x0, y0, x1, y1] = bounds;
+                if x0 >= x1 || y0 >= y1 {
+                    return Err(invalid(format!("empty crop region: {:?}", bounds)));
+                }
+                args.crop = Some(TileRect { x0, y0, x1, y1 });
+            }
+            "--crop-full" => args.crop_full = true,
+            "--resolution" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a preset or WIDTHxHEIGHT", arg)))?;
+                let presets: Vec<&str> = RESOLUTION_PRESETS.iter().map(|p| p.0).collect();
+                args.resolution = Some(parse_resolution(&value).ok_or_else(|| {
+                    invalid(format!(
+                        "invalid resolution {}; expected WIDTHxHEIGHT or one of {}",
+                        value,
+                        presets.join(", ")
+                    ))
+                })?);
+            }
+            "--fov" | "--hfov" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs an angle in degrees", arg)))?;
+                let degrees: Float = value
+                    .parse()
+                    .ok()
+                    .filter(|d| *d > 0.0 && *d < 180.0)
+                    .ok_or_else(|| invalid(format!("invalid field of view: {}", value)))?;
+                args.fov = Some((degrees, arg == "--hfov"));
+            }
+            "--frame" => args.frame = true,
+            "--auto-exposure" => args.auto_exposure = true,
+            "--exposure" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a number of stops", arg)))?;
+                let ev: Float = value
+                    .parse()
+                    .ok()
+                    .filter(|ev: &Float| ev.is_finite())
+                    .ok_or_else(|| invalid(format!("invalid exposure: {}", value)))?;
+                args.exposure = Some(ev);
+            }
+            "--max-seconds" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a number of seconds", arg)))?;
+                let seconds = value
+                    .parse()
+                    .ok()
+                    .and_then(|s: f64| Duration::try_from_secs_f64(s).ok())
+                    .filter(|s| !s.is_zero())
+                    .ok_or_else(|| invalid(format!("invalid time budget: {}", value)))?;
+                args.max_seconds = Some(seconds);
+            }
+            "--regularize" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a roughness from 0 to 1", arg)))?;
+                let roughness = value
+                    .parse()
+                    .ok()
+                    .filter(|r: &Float| (0.0..=1.0).contains(r))
+                    .ok_or_else(|| invalid(format!("invalid regularization: {}", value)))?;
+                args.regularize = Some(roughness);
+            }
+            "--denoise" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a radius in pixels", arg)))?;
+                let radius = value
+                    .parse()
+                    .ok()
+                    .filter(|&r: &usize| r > 0)
+                    .ok_or_else(|| invalid(format!("invalid denoise radius: {}", value)))?;
+                args.denoise = Some(radius);
+            }
+            "--inspect" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a pixel x,y", arg)))?;
+                let pixel = value
+                    .split_once(',')
+                    .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)));
+                args.inspect =
+                    Some(pixel.ok_or_else(|| invalid(format!("invalid pixel: {}", value)))?);
+            }
+            "--contact-sheet" => {
+                let usage = || {
+                    invalid(format!(
+                        "{} needs orbit, light, roughness or a scene variable, then FROM TO COUNT",
+                        arg
+                    ))
+                };
+                let parameter = Parameter::from_name(&iter.next().ok_or_else(usage)?);
+                let mut range = [0.0; 2];
+                for bound in &mut range {
+                    let value = iter.next().ok_or_else(usage)?;
+                    *bound = value
+                        .parse()
+                        .ok()
+                        .filter(|v: &Float| v.is_finite())
+                        .ok_or_else(|| invalid(format!("invalid value: {}", value)))?;
+                }
+                let value = iter.next().ok_or_else(usage)?;
+                let count = value
+                    .parse()
+                    .ok()
+                    .filter(|&n: &usize| n > 0)
+                    .ok_or_else(|| invalid(format!("invalid thumbnail count: {}", value)))?;
+                args.contact_sheet = Some(ContactSheet::new(parameter, range[0], range[1], count));
+            }
+            "--turntable" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a number of frames", arg)))?;
+                let frames = value
+                    .parse()
+                    .ok()
+                    .filter(|&n: &usize| n > 0)
+                    .ok_or_else(|| invalid(format!("invalid frame count: {}", value)))?;
+                args.turntable.get_or_insert_with(Turntable::default).frames = frames;
+            }
+            "--elevation" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs an angle in degrees", arg)))?;
+                let degrees: Float = value
+                    .parse()
+                    .ok()
+                    .filter(|d: &Float| d.abs() < 90.0)
+                    .ok_or_else(|| invalid(format!("invalid elevation: {}", value)))?;
+                args.turntable
+                    .get_or_insert_with(Turntable::default)
+                    .elevation = degrees.to_radians();
+            }
+            "--orbit-radius" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a distance", arg)))?;
+                let radius = value
+                    .parse()
+                    .ok()
+                    .filter(|r: &Float| *r > 0.0 && r.is_finite())
+                    .ok_or_else(|| invalid(format!("invalid orbit radius: {}", value)))?;
+                args.turntable.get_or_insert_with(Turntable::default).radius = Some(radius);
+            }
+            "--fps" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a frame rate", arg)))?;
+                args.fps = value
+                    .parse()
+                    .ok()
+                    .filter(|&fps: &u32| fps > 0)
+                    .ok_or_else(|| invalid(format!("invalid frame rate: {}", value)))?;
+            }
+            "--bake-lightmap" => {
+                let name = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs an object name", arg)))?;
+                args.bake_lightmap = Some(name);
+            }
+            "--lightmap-samples" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a number of rays", arg)))?;
+                args.lightmap.samples = value
+                    .parse()
+                    .ok()
+                    .filter(|&n: &u32| n > 0)
+                    .ok_or_else(|| invalid(format!("invalid sample count: {}", value)))?;
+            }
+            "--bake-ao" => {
+                let name = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs an object name", arg)))?;
+                args.bake_ao = Some(name);
+            }
+            "--ao-samples" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a number of rays", arg)))?;
+                args.occlusion.samples = value
+                    .parse()
+                    .ok()
+                    .filter(|&n: &u32| n > 0)
+                    .ok_or_else(|| invalid(format!("invalid sample count: {}", value)))?;
+            }
+            "--ao-distance" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a distance", arg)))?;
+                let distance = value
+                    .parse()
+                    .ok()
+                    .filter(|d: &Float| *d > 0.0)
+                    .ok_or_else(|| invalid(format!("invalid occlusion distance: {}", value)))?;
+                args.occlusion.distance = Some(distance);
+            }
+            "--probes" => {
+                let mut counts = [0usize; 3];
+                for count in &mut counts {
+                    let value = iter
+                        .next()
+                        .ok_or_else(|| invalid(format!("{} needs probe counts NX NY NZ", arg)))?;
+                    *count = value
+                        .parse()
+                        .ok()
+                        .filter(|&n: &usize| n > 0)
+                        .ok_or_else(|| invalid(format!("invalid probe count: {}", value)))?;
+                }
+                args.probes.get_or_insert_with(ProbeGrid::default).counts = counts;
+            }
+            "--probe-order" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs 1 or 2", arg)))?;
+                args.probes.get_or_insert_with(ProbeGrid::default).order = value
+                    .parse()
+                    .ok()
+                    .filter(|n| (1..=2).contains(n))
+                    .ok_or_else(|| invalid(format!("invalid harmonics order: {}", value)))?;
+            }
+            "--probe-samples" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a number of rays", arg)))?;
+                args.probes.get_or_insert_with(ProbeGrid::default).samples = value
+                    .parse()
+                    .ok()
+                    .filter(|&n: &u32| n > 0)
+                    .ok_or_else(|| invalid(format!("invalid sample count: {}", value)))?;
+            }
+            "--lightmap-padding" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a number of texels", arg)))?;
+                args.lightmap.padding = value
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid padding: {}", value)))?;
+            }
+            "--camera" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs camera names or all", arg)))?;
+                args.cameras = value
+                    .split(',')
+                    .map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty())
+                    .collect();
+                if args.cameras.is_empty() {
+                    return Err(invalid(format!("{} needs camera names or all", arg)));
+                }
+            }
+            "--layers" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs layer names or all", arg)))?;
+                args.layers = value
+                    .split(',')
+                    .map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty())
+                    .collect();
+                if args.layers.is_empty() {
+                    return Err(invalid(format!("{} needs layer names or all", arg)));
+                }
+            }
+            "--scene" => {
+                let path = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a path", arg)))?;
+                args.scene = Some(PathBuf::from(path));
+            }
+            "--set" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a NAME=VALUE", arg)))?;
+                args.variables.push(
+                    variables::parse_assignment(&value)
+                        .ok_or_else(|| invalid(format!("invalid assignment: {}", value)))?,
+                );
+            }
+            "--generate" => {
+                let names: Vec<&str> = GENERATORS.iter().map(|g| g.0).collect();
+                let name = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs one of {}", arg, names.join(", "))))?;
+                if !names.contains(&name.as_str()) {
+                    return Err(invalid(format!(
+                        "unknown generator: {}; expected one of {}",
+                        name,
+                        names.join(", ")
+                    )));
+                }
+                args.generate = Some(name);
+            }
+            "--seed" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a number", arg)))?;
+                args.seed = Some(
+                    value
+                        .parse()
+                        .map_err(|_| invalid(format!("invalid seed: {}", value)))?,
+                );
+            }
+            "--watch" => args.watch = true,
+            "--repl" => args.repl = true,
+            "--deterministic" => args.deterministic = true,
+            "--preview-ascii" => args.preview_ascii = true,
+            "--preview-image" => {
+                let names: Vec<&str> = Protocol::NAMES.iter().map(|n| n.0).collect();
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs one of {}", arg, names.join(", "))))?;
+                args.preview_image = Some(Protocol::from_name(&value).ok_or_else(|| {
+                    invalid(format!("unknown terminal graphics protocol: {}", value))
+                })?);
+            }
+            "-v" | "--verbose" => args.verbosity += 1,
+            "-vv" => args.verbosity += 2,
+            "-q" | "--quiet" => args.verbosity = -1,
+            // The format is optional, so only a following word naming one is taken
+            "--stats" => {
+                let format = match iter.peek().map(String::as_str) {
+                    Some("json") => Some(StatsFormat::Json),
+                    Some("table") => Some(StatsFormat::Table),
+                    _ => None,
+                };
+                if format.is_some() {
+                    iter.next();
+                }
+                args.stats = Some(format.unwrap_or(StatsFormat::Table));
+            }
+            "--batch" => {
+                let path = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a manifest path", arg)))?;
+                args.batch = Some(PathBuf::from(path));
+            }
+            // A bare port listens on this machine only
+            "--serve" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a port or address:port", arg)))?;
+                args.serve = Some(match value.parse::<u16>() {
+                    Ok(port) => format!("127.0.0.1:{}", port),
+                    Err(_) if value.contains(':') => value,
+                    Err(_) => return Err(invalid(format!("invalid port: {}", value))),
+                });
+            }
+            "--queue-dir" => {
+                let path = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a directory", arg)))?;
+                args.queue_dir = Some(PathBuf::from(path));
+            }
+            "--asset-dir" => {
+                let path = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs a directory", arg)))?;
+                args.asset_dir = Some(PathBuf::from(path));
+            }
+            "--sampler" => {
+                let names: Vec<&str> = Sampler::NAMES.iter().map(|n| n.0).collect();
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs one of {}", arg, names.join(", "))))?;
+                args.sampler = Some(
+                    Sampler::from_name(&value)
+                        .ok_or_else(|| invalid(format!("unknown sampler: {}", value)))?,
+                );
+            }
+            "--override-material" => {
+                let names: Vec<&str> = MaterialOverride::NAMES.iter().map(|n| n.0).collect();
+                let value = iter
+                    .next()
+                    .ok_or_else(|| invalid(format!("{} needs one of {}", arg, names.join(", "))))?;
+                args.material_override = Some(
+                    MaterialOverride::from_name(&value)
+                        .ok_or_else(|| invalid(format!("unknown material override: {}", value)))?,
+                );
+            }
+            _ => return Err(invalid(format!("unknown argument: {}", arg))),
+        }
+    }
+    if args.generate.is_some() && args.scene.is_some() {
+        return Err(invalid(
+            "--generate cannot be combined with --scene".to_string(),
+        ));
+    }
+    if args.seed.is_some() && args.generate.is_none() {
+        return Err(invalid("--seed needs a scene to --generate".to_string()));
+    }
+    for (flag, names, what, several) in [
+        ("--camera", &args.cameras, "named cameras", "cameras"),
+        ("--layers", &args.layers, "render layers", "layers"),
+    ] {
+        if names.is_empty() {
+            continue;
+        }
+        if args.scene.is_none() {
+            return Err(invalid(format!(
+                "{} needs a --scene file with {}",
+                flag, what
+            )));
+        }
+        let conflicts = [
+            ("--batch", args.batch.is_some()),
+            ("--repl", args.repl),
+            ("--contact-sheet", args.contact_sheet.is_some()),
+            ("--turntable", args.turntable.is_some()),
+            ("--bake-lightmap", args.bake_lightmap.is_some()),
+            ("--bake-ao", args.bake_ao.is_some()),
+            ("--probes", args.probes.is_some()),
+        ];
+        if let Some((conflict, _)) = conflicts.iter().find(|(_, set)| *set) {
+            return Err(invalid(format!(
+                "{} cannot be combined with {}",
+                conflict, flag
+            )));
+        }
+        // Several views each write output_name, but one file of each pass would only
+        // hold the last
+        if names.len() > 1 || names[0] == "all" {
+            let conflicts = [
+                ("--inspect", args.inspect.is_some()),
+                ("--id-pass", args.id_pass.is_some()),
+                ("--matte", !args.mattes.is_empty()),
+                ("--sigma", args.sigma.is_some()),
+                ("--histogram", args.histogram.is_some()),
+                ("--false-color", args.false_color.is_some()),
+            ];
+            if let Some((conflict, _)) = conflicts.iter().find(|(_, set)| *set) {
+                return Err(invalid(format!(
+                    "{} cannot be combined with several {}",
+                    conflict, several
+                )));
+            }
         }
     }
-    framebuffer
+    if !args.variables.is_empty() && args.scene.is_none() {
+        return Err(invalid(
+            "--set needs a --scene file to set variables in".to_string(),
+        ));
+    }
+    if args.watch && args.scene.is_none() {
+        return Err(invalid("--watch needs a --scene file to watch".to_string()));
+    }
+    if args.preview_ascii && args.preview_image.is_some() {
+        return Err(invalid(
+            "--preview-ascii cannot be combined with --preview-image".to_string(),
+        ));
+    }
+    if args.stats.is_some() && args.inspect.is_some() {
+        return Err(invalid(
+            "--stats cannot be combined with --inspect, which renders nothing".to_string(),
+        ));
+    }
+    if args.batch.is_some() {
+        // Each job names its own scene and output, and the passes would overwrite each other
+        let conflicts = [
+            ("--scene", args.scene.is_some()),
+            ("--generate", args.generate.is_some()),
+            ("--watch", args.watch),
+            ("--inspect", args.inspect.is_some()),
+            ("--id-pass", args.id_pass.is_some()),
+            ("--matte", !args.mattes.is_empty()),
+            ("--sigma", args.sigma.is_some()),
+            ("--histogram", args.histogram.is_some()),
+            ("--false-color", args.false_color.is_some()),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, set)| *set) {
+            return Err(invalid(format!("{} cannot be combined with --batch", flag)));
+        }
+    }
+    if let Some(sheet) = &args.contact_sheet {
+        if matches!(sheet.parameter, Parameter::Variable(_)) && args.scene.is_none() {
+            return Err(invalid(format!(
+                "--contact-sheet {} needs a --scene file defining the variable",
+                sheet.parameter.name()
+            )));
+        }
+        // Thumbnails are rendered plainly and pasted into one image
+        let conflicts = [
+            ("--batch", args.batch.is_some()),
+            ("--inspect", args.inspect.is_some()),
+            ("--stats", args.stats.is_some()),
+            ("--crop", args.crop.is_some()),
+            ("--id-pass", args.id_pass.is_some()),
+            ("--matte", !args.mattes.is_empty()),
+            ("--sigma", args.sigma.is_some()),
+            ("--histogram", args.histogram.is_some()),
+            ("--false-color", args.false_color.is_some()),
+            ("--max-seconds", args.max_seconds.is_some()),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, set)| *set) {
+            return Err(invalid(format!(
+                "{} cannot be combined with --contact-sheet",
+                flag
+            )));
+        }
+    }
+    if args.turntable.is_some() {
+        // Each frame is rendered plainly and written as it finishes
+        let conflicts = [
+            ("--batch", args.batch.is_some()),
+            ("--inspect", args.inspect.is_some()),
+            ("--contact-sheet", args.contact_sheet.is_some()),
+            ("--frame", args.frame),
+            ("--stats", args.stats.is_some()),
+            ("--crop", args.crop.is_some()),
+            ("--id-pass", args.id_pass.is_some()),
+            ("--matte", !args.mattes.is_empty()),
+            ("--sigma", args.sigma.is_some()),
+            ("--histogram", args.histogram.is_some()),
+            ("--false-color", args.false_color.is_some()),
+            ("--max-seconds", args.max_seconds.is_some()),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, set)| *set) {
+            return Err(invalid(format!(
+                "{} cannot be combined with --turntable",
+                flag
+            )));
+        }
+    }
+    let bakes = [
+        ("--bake-lightmap", args.bake_lightmap.is_some()),
+        ("--bake-ao", args.bake_ao.is_some()),
+        ("--probes", args.probes.is_some()),
+    ];
+    let mut baking = bakes.iter().filter(|(_, set)| *set).map(|(flag, _)| flag);
+    if let Some(bake) = baking.next() {
+        if let Some(other) = baking.next() {
+            return Err(invalid(format!(
+                "{} cannot be combined with {}",
+                other, bake
+            )));
+        }
+        // Only the scene's light is wanted, none of the camera's view of it
+        let conflicts = [
+            ("--batch", args.batch.is_some()),
+            ("--inspect", args.inspect.is_some()),
+            ("--contact-sheet", args.contact_sheet.is_some()),
+            ("--turntable", args.turntable.is_some()),
+            ("--frame", args.frame),
+            ("--stats", args.stats.is_some()),
+            ("--crop", args.crop.is_some()),
+            ("--id-pass", args.id_pass.is_some()),
+            ("--matte", !args.mattes.is_empty()),
+            ("--sigma", args.sigma.is_some()),
+            ("--histogram", args.histogram.is_some()),
+            ("--false-color", args.false_color.is_some()),
+            ("--max-seconds", args.max_seconds.is_some()),
+            ("--override-material", args.material_override.is_some()),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, set)| *set) {
+            return Err(invalid(format!(
+                "{} cannot be combined with {}",
+                flag, bake
+            )));
+        }
+    }
+    if args.probes.is_some() {
+        let extension = args.output.extension().and_then(|e| e.to_str());
+        if !extension
+            .is_some_and(|e| e.eq_ignore_ascii_case("json") || e.eq_ignore_ascii_case("bin"))
+        {
+            return Err(invalid(format!(
+                "--probes writes a .json or .bin file, not {}",
+                args.output.display()
+            )));
+        }
+    }
+    if args.bake_ao.is_some() {
+        let extension = args.output.extension().and_then(|e| e.to_str());
+        if !extension
+            .is_some_and(|e| e.eq_ignore_ascii_case("ply") || e.eq_ignore_ascii_case("obj"))
+        {
+            return Err(invalid(format!(
+                "--bake-ao writes a .ply or .obj mesh, not {}",
+                args.output.display()
+            )));
+        }
+    }
+    if args.queue_dir.is_some() && args.serve.is_none() {
+        return Err(invalid(
+            "--queue-dir needs --serve to queue jobs for".to_string(),
+        ));
+    }
+    if args.asset_dir.is_some() && args.serve.is_none() {
+        return Err(invalid(
+            "--asset-dir needs --serve; scenes loaded with --scene read any file".to_string(),
+        ));
+    }
+    if args.serve.is_some() {
+        // Each request brings its own scene, and the reply is the only output
+        let conflicts = [
+            ("--scene", args.scene.is_some()),
+            ("--generate", args.generate.is_some()),
+            ("--batch", args.batch.is_some()),
+            ("--repl", args.repl),
+            ("--watch", args.watch),
+            ("--inspect", args.inspect.is_some()),
+            ("--id-pass", args.id_pass.is_some()),
+            ("--matte", !args.mattes.is_empty()),
+            ("--sigma", args.sigma.is_some()),
+            ("--histogram", args.histogram.is_some()),
+            ("--false-color", args.false_color.is_some()),
+            ("--contact-sheet", args.contact_sheet.is_some()),
+            ("--turntable", args.turntable.is_some()),
+            ("--bake-lightmap", args.bake_lightmap.is_some()),
+            ("--bake-ao", args.bake_ao.is_some()),
+            ("--probes", args.probes.is_some()),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, set)| *set) {
+            return Err(invalid(format!("{} cannot be combined with --serve", flag)));
+        }
+    }
+    if args.repl {
+        // The session decides what to render and where
+        let conflicts = [
+            ("--batch", args.batch.is_some()),
+            ("--watch", args.watch),
+            ("--inspect", args.inspect.is_some()),
+            ("--stats", args.stats.is_some()),
+            ("--id-pass", args.id_pass.is_some()),
+            ("--matte", !args.mattes.is_empty()),
+            ("--sigma", args.sigma.is_some()),
+            ("--histogram", args.histogram.is_some()),
+            ("--false-color", args.false_color.is_some()),
+            ("--max-seconds", args.max_seconds.is_some()),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, set)| *set) {
+            return Err(invalid(format!("{} cannot be combined with --repl", flag)));
+        }
+    }
+    Ok(args)
 }
 
-fn render(width: usize, height: usize, path: &Path) -> io::Result<()> {
-    let framebuffer = create_gradient_image(width, height);
-    let mut file = File::create(path)?;
+fn main() -> Result<(), io::Error> {
+    let args = parse_args()?;
+    log::set_max_level(match args.verbosity {
+        i32::MIN..=-1 => Level::Error,
+        0 => Level::Info,
+        1 => Level::Debug,
+        _ => Level::Trace,
+    });
+    if args.repl {
+        return run_repl(&args);
+    }
+    if let Some(address) = &args.serve {
+        return run_server(&args, address);
+    }
+    catch_interrupt();
+    if let Some(manifest) = &args.batch {
+        return run_batch(&args, manifest);
+    }
+    let Some(path) = args.scene.as_ref().filter(|_| args.watch) else {
+        return run(&args).map(|_| ());
+    };
+
+    // The scene file and, once it has loaded, every asset it uses
+    let mut watcher = FileWatcher::new(std::slice::from_ref(path));
+    loop {
+        // A broken save should not end the session, so report it and keep watching what
+        // the last good load used
+        match run(&args) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => return Err(e),
+            Ok(files) => {
+                watcher.watch(&files);
+                info!(
+                    "wrote {}; watching {} and its assets for changes",
+                    args.output.display(),
+                    path.display()
+                );
+            }
+            Err(e) => error!("{}; watching {} for changes", e, path.display()),
+        }
+        while !watcher.changed() {
+            if INTERRUPTED.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+}
+
+// What to render: a scene file, a generated scene or the built-in one
+#[derive(Clone, Copy)]
+enum Source<'a> {
+    // With values for some of its variables
+    File(&'a Path, &'a [(String, Json)]),
+    Generated(&'a str, u64),
+    BuiltIn,
+}
 
-    writeln!(file, "P6\n{} {}\n255", width, height)?;
-    for Vec3f(r, g, b) in framebuffer {
-        let max_value = 255.0;
-        file.write_all(&[
-            (max_value * r.clamp(0.0, 1.0)) as u8,
-            (max_value * g.clamp(0.0, 1.0)) as u8,
-            (max_value * b.clamp(0.0, 1.0)) as u8,
-        ])?;
+impl Args {
+    fn source(&self) -> Source<'_> {
+        match (&self.scene, &self.generate) {
+            (Some(path), _) => Source::File(path, &self.variables),
+            (None, Some(name)) => Source::Generated(name, self.seed.unwrap_or(0)),
+            (None, None) => Source::BuiltIn,
+        }
     }
+}
 
+// The scene, camera and render settings of the source, once the scene has passed
+// validation, and the files it was read from
+fn load_scene(source: Source) -> io::Result<(Scene, Camera, RenderSettings, Vec<PathBuf>)> {
+    let (scene, camera, _, defaults, files) = load_scene_views(source)?;
+    Ok((scene, camera, defaults, files))
+}
+
+// What a scene file offers to render besides its default view
+#[derive(Default)]
+struct Views {
+    cameras: Vec<(String, Camera)>,
+    layers: Vec<RenderLayer>,
+}
+
+// As load_scene, with the named cameras and render layers of a scene file too
+fn load_scene_views(
+    source: Source,
+) -> io::Result<(Scene, Camera, Views, RenderSettings, Vec<PathBuf>)> {
+    let defaults = RenderSettings::default();
+    let mut files = Vec::new();
+    let mut views = Views::default();
+    let (scene, camera, defaults) = match source {
+        Source::File(path, variables) => {
+            let file = SceneFile::load_with(path, variables)?;
+            let mut settings = defaults;
+            file.apply(&mut settings);
+            files.push(path.to_path_buf());
+            files.extend(file.assets);
+            views = Views {
+                cameras: file.cameras,
+                layers: file.layers,
+            };
+            (file.scene, file.camera, settings)
+        }
+        Source::Generated(name, seed) => {
+            let (scene, camera) = generate(name, seed).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unknown generator: {}", name),
+                )
+            })?;
+            (scene, camera, defaults)
+        }
+        Source::BuiltIn => {
+            let (scene, camera) = spheres_on_checkerboard();
+            (scene, camera, defaults)
+        }
+    };
+    let diagnostics = scene.validate();
+    for diagnostic in &diagnostics {
+        diagnostic.log();
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "scene failed validation",
+        ));
+    }
+    Ok((scene, camera, views, defaults, files))
+}
+
+// The cameras or layers, by kind, that names picks out of the scene's, each with the
+// name to add to the output path, which a single one leaves as it is
+fn select_named<T: Clone>(
+    kind: &str,
+    names: &[String],
+    named: Vec<(String, T)>,
+) -> io::Result<Vec<(Option<String>, T)>> {
+    let known: Vec<&str> = named.iter().map(|(name, _)| name.as_str()).collect();
+    if names[0] == "all" && names.len() == 1 {
+        if named.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} all needs a scene with {}s", kind, kind),
+            ));
+        }
+        return Ok(named.into_iter().map(|(n, c)| (Some(n), c)).collect());
+    }
+    let mut selected = Vec::with_capacity(names.len());
+    for name in names {
+        let Some((_, item)) = named.iter().find(|(n, _)| n == name) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the scene has no {} named {}; it has {}",
+                    kind,
+                    name,
+                    if known.is_empty() {
+                        "none".to_string()
+                    } else {
+                        known.join(", ")
+                    }
+                ),
+            ));
+        };
+        let suffix = (names.len() > 1).then(|| name.clone());
+        selected.push((suffix, item.clone()));
+    }
+    Ok(selected)
+}
+
+// path with name before its extension, out.png becoming out_left.png
+fn with_suffix(path: &Path, name: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{}_{}.{}", stem, name, ext.to_string_lossy())),
+        None => path.with_file_name(format!("{}_{}", stem, name)),
+    }
+}
+
+// Room --frame leaves around the scene, as a fraction of its size
+const FRAME_PADDING: Float = 0.1;
+
+fn apply_fov(camera: &mut Camera, fov: Option<(Float, bool)>) {
+    match fov {
+        Some((degrees, true)) => *camera = camera.clone().with_horizontal_fov(degrees.to_radians()),
+        Some((degrees, false)) => camera.fov = degrees.to_radians(),
+        None => {}
+    }
+}
+
+// The command line's overrides on top of the scene's own settings
+fn settings_for(args: &Args, defaults: &RenderSettings) -> RenderSettings {
+    let (width, height) = args.resolution.unwrap_or((defaults.width, defaults.height));
+    RenderSettings {
+        width,
+        height,
+        transparent_background: args.transparent,
+        object_ids: args.id_pass.is_some() || !args.mattes.is_empty(),
+        variance: args.sigma.is_some(),
+        crop: args.crop,
+        sampler: args.sampler.unwrap_or(defaults.sampler),
+        regularize: args.regularize.unwrap_or(defaults.regularize),
+        denoise: args
+            .denoise
+            .map(|radius| Denoise {
+                radius,
+                ..defaults.denoise.unwrap_or_default()
+            })
+            .or(defaults.denoise),
+        auto_exposure: defaults
+            .auto_exposure
+            .or(args.auto_exposure.then(AutoExposure::default)),
+        deterministic: args.deterministic,
+        ..defaults.clone()
+    }
+}
+
+// Renders what the arguments ask for, returning the files the scene was read from
+fn run(args: &Args) -> io::Result<Vec<PathBuf>> {
+    if let Some(sheet) = &args.contact_sheet {
+        return run_contact_sheet(args, sheet);
+    }
+    if let Some(turntable) = &args.turntable {
+        return run_turntable(args, turntable);
+    }
+    if let Some(name) = &args.bake_lightmap {
+        return run_bake_lightmap(args, name);
+    }
+    if let Some(name) = &args.bake_ao {
+        return run_bake_ao(args, name);
+    }
+    if let Some(grid) = &args.probes {
+        return run_probes(args, grid);
+    }
+    let mut timings = Timings::default();
+    let start = Instant::now();
+    let (mut scene, camera, named, defaults, files) = load_scene_views(args.source())?;
+    timings.load = start.elapsed();
+    scene.material_override = args.material_override;
+    let cameras = match args.cameras.as_slice() {
+        [] => vec![(None, camera)],
+        names => select_named("camera", names, named.cameras)?,
+    };
+    let layers = match args.layers.as_slice() {
+        [] => vec![(None, None)],
+        names => {
+            let layers = named.layers.into_iter().map(|l| (l.name.clone(), Some(l)));
+            select_named("layer", names, layers.collect())?
+        }
+    };
+    let start = Instant::now();
+    scene.build_bvh();
+    timings.build = start.elapsed();
+    let settings = settings_for(args, &defaults);
+    // The one load and BVH serve every view; a layer only changes how objects are drawn
+    for (camera_name, camera) in cameras {
+        for (layer_name, layer) in &layers {
+            scene.set_layer(layer.as_ref())?;
+            let mut output = args.output.clone();
+            let mut view = Vec::new();
+            for (kind, name) in [("camera", &camera_name), ("layer", layer_name)] {
+                if let Some(name) = name {
+                    output = with_suffix(&output, name);
+                    view.push(format!("{} {}", kind, name));
+                }
+            }
+            if !view.is_empty() {
+                info!("rendering {} to {}", view.join(", "), output.display());
+            }
+            render_view(
+                args,
+                &scene,
+                camera.clone(),
+                &settings,
+                &output,
+                &mut timings,
+            )?;
+        }
+    }
+    Ok(files)
+}
+
+// Renders the scene through camera to output, along with the passes the arguments ask for
+fn render_view(
+    args: &Args,
+    scene: &Scene,
+    mut camera: Camera,
+    settings: &RenderSettings,
+    output: &Path,
+    timings: &mut Timings,
+) -> io::Result<()> {
+    apply_fov(&mut camera, args.fov);
+    if args.frame {
+        camera = camera.frame(scene, FRAME_PADDING);
+    }
+    if let Some(ev) = args.exposure {
+        camera = camera.with_exposure_compensation(ev);
+    }
+    let (width, height) = (settings.width, settings.height);
+
+    if let Some((x, y)) = args.inspect {
+        if x >= width || y >= height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "pixel {},{} lies outside the {}x{} frame",
+                    x, y, width, height
+                ),
+            ));
+        }
+        inspect_pixel(scene, &camera, settings, x, y);
+        return Ok(());
+    }
+
+    let (image, stats, objects) = render_counted(
+        scene,
+        &camera,
+        settings,
+        args.scene.as_deref(),
+        args.max_seconds,
+        timings,
+    );
+    let start = Instant::now();
+    let image = crop_output(args, image)?;
+    write_render(&image, output, args)?;
+    if let Some(path) = &args.id_pass {
+        image.write_object_ids(path)?;
+    }
+    for (id, path) in &args.mattes {
+        image.write_matte(path, *id)?;
+    }
+    if let Some(path) = &args.sigma {
+        image.write_deviation(path)?;
+    }
+    write_exposure_analysis(args, &image)?;
+    timings.write = start.elapsed();
+    debug!(
+        "wrote {} in {:.3}s",
+        output.display(),
+        timings.write.as_secs_f64()
+    );
+    if let Some(format) = args.stats {
+        print_stats(format, &stats, &objects, scene, timings);
+    }
     Ok(())
 }
 
-fn main() -> Result<(), io::Error> {
-    render(1024, 768, Path::new("out.ppm"))?;
+// Renders a thumbnail of the scene at each of the sheet's values and writes them out as
+// one image
+fn run_contact_sheet(args: &Args, sheet: &ContactSheet) -> io::Result<Vec<PathBuf>> {
+    let mut thumbnails = Vec::with_capacity(sheet.count);
+    let mut files = Vec::new();
+    for value in sheet.values() {
+        // Variables take a fresh load each; the rest start from one
+        let mut variables = args.variables.clone();
+        if let Parameter::Variable(name) = &sheet.parameter {
+            variables.push((name.clone(), Json::Number(value as f64)));
+        }
+        let source = match args.source() {
+            Source::File(path, _) => Source::File(path, &variables),
+            other => other,
+        };
+        let (mut scene, mut camera, defaults, read) = load_scene(source)?;
+        files = read;
+        scene.material_override = args.material_override;
+        apply_fov(&mut camera, args.fov);
+        if args.frame {
+            camera = camera.frame(&scene, FRAME_PADDING);
+        }
+        if let Some(ev) = args.exposure {
+            camera = camera.with_exposure_compensation(ev);
+        }
+        sheet.parameter.vary(value, &mut scene, &mut camera);
+        scene.build_bvh();
+        info!(
+            "thumbnail {}/{}: {} {}",
+            thumbnails.len() + 1,
+            sheet.count,
+            sheet.parameter.name(),
+            value
+        );
+        thumbnails.push(render(&scene, &camera, &settings_for(args, &defaults)));
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "interrupted before the contact sheet was finished",
+            ));
+        }
+    }
+    write_render(&sheet.compose(&thumbnails), &args.output, args)?;
+    Ok(files)
+}
+
+// Bakes the light on the named mesh to the output path
+fn run_bake_lightmap(args: &Args, name: &str) -> io::Result<Vec<PathBuf>> {
+    let (scene, _, defaults, files) = load_scene(args.source())?;
+    scene.build_bvh();
+    let mut lightmap = args.lightmap;
+    if let Some((width, height)) = args.resolution {
+        lightmap.width = width;
+        lightmap.height = height;
+    }
+    info!(
+        "baking {} into a {}x{} lightmap",
+        name, lightmap.width, lightmap.height
+    );
+    let image = lightmap.bake(&scene, name, &settings_for(args, &defaults))?;
+    write_render(&image, &args.output, args)?;
+    Ok(files)
+}
+
+// Bakes the occlusion at the named mesh's vertices and writes the mesh out with it as
+// grey vertex colors
+fn run_bake_ao(args: &Args, name: &str) -> io::Result<Vec<PathBuf>> {
+    let (scene, _, defaults, files) = load_scene(args.source())?;
+    scene.build_bvh();
+    let settings = settings_for(args, &defaults);
+    let (mesh, open) = args.occlusion.bake(&scene, name, &settings)?;
+    info!(
+        "baked occlusion at {} vertices of {}",
+        mesh.vertices().len(),
+        name
+    );
+    let colors: Vec<Vec3f> = open.iter().map(|&o| Vec3f(o, o, o)).collect();
+    let is_ply = args
+        .output
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("ply"));
+    if is_ply {
+        mesh.write_ply(&args.output, Some(&colors))?;
+    } else {
+        mesh.write_obj(&args.output, Some(&colors))?;
+    }
+    Ok(files)
+}
+
+// Traces the probe grid and writes out its coefficients
+fn run_probes(args: &Args, grid: &ProbeGrid) -> io::Result<Vec<PathBuf>> {
+    let (scene, _, defaults, files) = load_scene(args.source())?;
+    scene.build_bvh();
+    let [nx, ny, nz] = grid.counts;
+    info!(
+        "tracing {}x{}x{} probes of order {}",
+        nx, ny, nz, grid.order
+    );
+    let probes = grid.bake(&scene, &settings_for(args, &defaults))?;
+    let is_json = args
+        .output
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"));
+    if is_json {
+        probes.write_json(&args.output)?;
+    } else {
+        probes.write_binary(&args.output)?;
+    }
+    Ok(files)
+}
+
+// Renders the turn around the scene to numbered images beside the output path, spin.png
+// becoming spin_0000.png and on, or to the video it names
+fn run_turntable(args: &Args, turntable: &Turntable) -> io::Result<Vec<PathBuf>> {
+    let (mut scene, mut camera, defaults, files) = load_scene(args.source())?;
+    scene.material_override = args.material_override;
+    scene.build_bvh();
+    apply_fov(&mut camera, args.fov);
+    if let Some(ev) = args.exposure {
+        camera = camera.with_exposure_compensation(ev);
+    }
+    let settings = settings_for(args, &defaults);
+    let frames = turntable.frames;
+    let output = &args.output;
+    let extension = output.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let is_image = ["png", "ppm", "exr"]
+        .iter()
+        .any(|e| extension.eq_ignore_ascii_case(e));
+    if is_image {
+        let digits = (frames - 1).to_string().len().max(4);
+        let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+        render_turntable(&scene, &camera, &settings, turntable, |i, image| {
+            let path = output.with_file_name(format!("{}_{:0digits$}.{}", stem, i, extension));
+            write_render(&image, &path, args)?;
+            info!("frame {}/{}: wrote {}", i + 1, frames, path.display());
+            // Each frame is a file of its own, so those written so far are kept
+            if INTERRUPTED.load(Ordering::SeqCst) && i + 1 < frames {
+                return Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    format!("interrupted after {} of {} frames", i + 1, frames),
+                ));
+            }
+            Ok(())
+        })?;
+    } else {
+        let mut video = VideoEncoder::create(output, args.fps)?;
+        render_turntable(&scene, &camera, &settings, turntable, |i, image| {
+            if INTERRUPTED.load(Ordering::SeqCst) {
+                return Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    format!("interrupted after {} of {} frames", i, frames),
+                ));
+            }
+            video.write_frame(&image)?;
+            info!("frame {}/{}", i + 1, frames);
+            Ok(())
+        })?;
+        video.finish()?;
+        info!("wrote {}", output.display());
+    }
+    Ok(files)
+}
+
+// The histogram and false-color map the arguments ask for, with how much of the image is
+// clipped or crushed
+fn write_exposure_analysis(args: &Args, image: &Framebuffer) -> io::Result<()> {
+    if args.histogram.is_none() && args.false_color.is_none() {
+        return Ok(());
+    }
+    let histogram = Histogram::new(image);
+    info!(
+        "{:.1}% of pixels clipped to white, {:.1}% crushed to black",
+        100.0 * histogram.clipped_fraction(),
+        100.0 * histogram.crushed_fraction()
+    );
+    if let Some(path) = &args.histogram {
+        histogram.to_image().write_image(path)?;
+    }
+    if let Some(path) = &args.false_color {
+        exposure::false_color(image).write_image(path)?;
+    }
+    Ok(())
+}
+
+fn run_server(args: &Args, address: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    let settings = settings_for(args, &RenderSettings::default());
+    let defaults = RenderServer::default();
+    // The limits are on what scenes ask for, never below what the server itself is set to
+    let server = RenderServer {
+        max_pixels: defaults
+            .max_pixels
+            .max(settings.width.saturating_mul(settings.height)),
+        max_samples: defaults
+            .max_samples
+            .max(settings.samples_per_pixel)
+            .max(settings.irradiance_cache.map_or(0, |c| c.samples)),
+        max_depth: defaults.max_depth.max(settings.max_depth),
+        max_radius: [
+            settings.denoise.map(|d| d.radius),
+            settings.bloom.map(|b| b.radius.ceil() as usize),
+        ]
+        .into_iter()
+        .flatten()
+        .fold(defaults.max_radius, usize::max),
+        settings,
+        material_override: args.material_override,
+        asset_dir: args.asset_dir.clone(),
+        queue_dir: args.queue_dir.clone(),
+        ..defaults
+    };
+    info!(
+        "listening on http://{}; POST scene JSON to /render or /jobs",
+        listener.local_addr()?
+    );
+    server.serve(&listener)
+}
+
+fn run_repl(args: &Args) -> io::Result<()> {
+    let (mut scene, mut camera, defaults, _) = load_scene(args.source())?;
+    scene.material_override = args.material_override;
+    apply_fov(&mut camera, args.fov);
+    if args.frame {
+        camera = camera.frame(&scene, FRAME_PADDING);
+    }
+    if let Some(ev) = args.exposure {
+        camera = camera.with_exposure_compensation(ev);
+    }
+    let mut console = Console::new(scene, camera, settings_for(args, &defaults));
+    // Only prompt a person; piped scripts get just the replies
+    let interactive = io::stdin().is_terminal();
+    if interactive {
+        println!("type help for the commands, quit to leave");
+    }
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        if interactive {
+            print!("> ");
+            io::stdout().flush()?;
+        }
+        let Some(line) = lines.next().transpose()? else {
+            return Ok(());
+        };
+        // A mistyped command should not end the session
+        match console.execute(&line) {
+            Ok(Reply::Continue(Some(reply))) => println!("{}", reply),
+            Ok(Reply::Continue(None)) => {}
+            Ok(Reply::Quit) => return Ok(()),
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+// Totals the ray counts renders report as they end, and stops them on Ctrl-C
+#[derive(Default)]
+struct StatsCollector {
+    rays: Mutex<RayStats>,
+    objects: Mutex<ObjectStats>,
+}
+
+impl RenderObserver for StatsCollector {
+    fn on_render_end(&self, stats: &RayStats) {
+        *self.rays.lock().unwrap() += *stats;
+    }
+
+    fn on_object_stats(&self, stats: &ObjectStats) {
+        *self.objects.lock().unwrap() += stats;
+    }
+
+    fn cancelled(&self) -> bool {
+        INTERRUPTED.load(Ordering::SeqCst)
+    }
+}
+
+// Set by the first Ctrl-C
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Catches the first Ctrl-C so the render in flight stops at its next tile and is saved;
+// a second one ends the process as usual
+#[cfg(unix)]
+fn catch_interrupt() {
+    const SIGINT: i32 = 2;
+    const SIG_DFL: usize = 0;
+    extern "C" {
+        fn signal(signum: i32, handler: usize) -> usize;
+    }
+    extern "C" fn on_interrupt(_: i32) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        // signal is async-signal-safe, and the default disposition is always valid
+        unsafe { signal(SIGINT, SIG_DFL) };
+    }
+    // The handler does nothing but store an atomic and call signal
+    unsafe { signal(SIGINT, on_interrupt as extern "C" fn(i32) as usize) };
+}
+
+#[cfg(not(unix))]
+fn catch_interrupt() {}
+
+// The size of the terminal in character cells, and in pixels where it says; a line is
+// left below an image for the prompt
+struct TerminalSize {
+    columns: usize,
+    rows: usize,
+    pixels: Option<(usize, usize)>,
+}
+
+impl TerminalSize {
+    // Columns and rows as COLUMNS and LINES give them or else as the terminal on stdout,
+    // stderr or stdin reports, assuming 80 by 24 when neither does
+    fn get() -> TerminalSize {
+        let var = |name| {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|&n| n > 0)
+        };
+        let window = window_size();
+        let (columns, rows) = window.map_or((80, 24), |w| (w.0, w.1));
+        let pixels = window
+            .map(|w| (w.2, w.3 - w.3 / w.1))
+            .filter(|&(width, height)| width > 0 && height > 0);
+        TerminalSize {
+            columns: var("COLUMNS").unwrap_or(columns),
+            rows: var("LINES").unwrap_or(rows).saturating_sub(1),
+            pixels,
+        }
+    }
+}
+
+// Columns, rows, width and height in pixels, the last two zero if the terminal keeps them
+// to itself
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn window_size() -> Option<(usize, usize, usize, usize)> {
+    #[cfg(target_os = "linux")]
+    const TIOCGWINSZ: std::ffi::c_ulong = 0x5413;
+    #[cfg(target_os = "macos")]
+    const TIOCGWINSZ: std::ffi::c_ulong = 0x4008_7468;
+    #[repr(C)]
+    #[derive(Default)]
+    struct WinSize {
+        rows: u16,
+        columns: u16,
+        x_pixels: u16,
+        y_pixels: u16,
+    }
+    extern "C" {
+        fn ioctl(fd: i32, request: std::ffi::c_ulong, ...) -> i32;
+    }
+    (0..3).find_map(|fd| {
+        let mut size = WinSize::default();
+        // TIOCGWINSZ only fills in the struct it is given
+        let ok = unsafe { ioctl(fd, TIOCGWINSZ, &mut size as *mut WinSize) } == 0;
+        (ok && size.columns > 0 && size.rows > 0).then_some((
+            size.columns as usize,
+            size.rows as usize,
+            size.x_pixels as usize,
+            size.y_pixels as usize,
+        ))
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn window_size() -> Option<(usize, usize, usize, usize)> {
+    None
+}
+
+// Writes a finished render to path; an interrupted one goes beside it as NAME.partial.EXT
+// instead, so it never replaces a complete image, and comes back as an Interrupted error.
+// An EXR carries the mattes asked for as layers of its own.
+fn write_render(image: &Framebuffer, path: &Path, args: &Args) -> io::Result<()> {
+    if args.preview_ascii || args.preview_image.is_some() {
+        let size = TerminalSize::get();
+        let preview = match args.preview_image {
+            Some(protocol) => terminal::inline_image(image, protocol, size.pixels)?,
+            None => terminal::half_blocks(image, size.columns, size.rows),
+        };
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(preview.as_bytes())?;
+        stdout.flush()?;
+    }
+    let write = |path: &Path| match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("exr") => {
+            let ids: Vec<u32> = args.mattes.iter().map(|(id, _)| *id).collect();
+            image.write_exr(path, args.exr_precision, &ids)
+        }
+        _ => image.write_image(path),
+    };
+    if !INTERRUPTED.load(Ordering::SeqCst) {
+        return write(path);
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let partial = match path.extension() {
+        Some(ext) => path.with_file_name(format!("{}.partial.{}", stem, ext.to_string_lossy())),
+        None => path.with_file_name(format!("{}.partial", stem)),
+    };
+    write(&partial)?;
+    Err(io::Error::new(
+        io::ErrorKind::Interrupted,
+        format!(
+            "interrupted; wrote the partial render to {}",
+            partial.display()
+        ),
+    ))
+}
+
+// A single render, or as many passes as fit in the time budget, noting in the image how
+// it was made from the scene file at source, or the built-in scene
+fn render_counted(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    source: Option<&Path>,
+    budget: Option<Duration>,
+    timings: &mut Timings,
+) -> (Framebuffer, RayStats, ObjectStats) {
+    let collector = StatsCollector::default();
+    let start = Instant::now();
+    let (mut image, samples) = match budget {
+        Some(budget) => {
+            let (image, passes) = render_within(scene, camera, settings, budget, &collector);
+            let samples = passes * settings.samples_per_pixel.max(1);
+            info!(
+                "{} passes, {} spp in {:.2}s",
+                passes,
+                samples,
+                start.elapsed().as_secs_f64()
+            );
+            (image, samples)
+        }
+        None => (
+            render_with(scene, camera, settings, &collector),
+            settings.samples_per_pixel.max(1),
+        ),
+    };
+    timings.render = start.elapsed();
+    image.metadata = render_metadata(settings, source, samples, timings.render);
+    (
+        image,
+        collector.rays.into_inner().unwrap(),
+        collector.objects.into_inner().unwrap(),
+    )
+}
+
+// Enough to tell later exactly how an image was produced: the renderer's version, the
+// settings, the scene and a hash of its file, and the time the render took
+fn render_metadata(
+    settings: &RenderSettings,
+    source: Option<&Path>,
+    samples: u32,
+    time: Duration,
+) -> Vec<(String, String)> {
+    let software = match option_env!("RUSTY_RAYS_COMMIT") {
+        Some(commit) => format!("rusty-rays {} ({})", env!("CARGO_PKG_VERSION"), commit),
+        None => format!("rusty-rays {}", env!("CARGO_PKG_VERSION")),
+    };
+    let integrator = match settings.integrator {
+        Integrator::Whitted => "whitted",
+        Integrator::Path => "path",
+    };
+    let mut metadata = vec![
+        ("Software".to_string(), software),
+        (
+            "Resolution".to_string(),
+            format!("{}x{}", settings.width, settings.height),
+        ),
+        ("Samples".to_string(), samples.to_string()),
+        ("Integrator".to_string(), integrator.to_string()),
+        ("Max depth".to_string(), settings.max_depth.to_string()),
+        ("Sampler".to_string(), settings.sampler.name().to_string()),
+        ("Seed".to_string(), settings.seed.to_string()),
+        (
+            "Scene".to_string(),
+            source.map_or("built-in".to_string(), |path| path.display().to_string()),
+        ),
+    ];
+    // The file has been read once already, so failing now only loses the hash
+    if let Some(bytes) = source.and_then(|path| std::fs::read(path).ok()) {
+        metadata.push(("Scene hash".to_string(), format!("{:016x}", fnv1a(&bytes))));
+    }
+    metadata.push((
+        "Render time".to_string(),
+        format!("{:.3}s", time.as_secs_f64()),
+    ));
+    metadata
+}
+
+// 64-bit FNV-1a, enough to tell whether a scene file has changed since an image was made
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+// Where a render's time went: parsing the scene and building its meshes, building the
+// scene BVH, tracing, and writing the images
+#[derive(Default)]
+struct Timings {
+    load: Duration,
+    build: Duration,
+    render: Duration,
+    write: Duration,
+}
+
+// Every object in the scene with the rays it stopped, the floor included when there is one
+fn object_rows(scene: &Scene, objects: &ObjectStats) -> Vec<(u32, String, ObjectHits)> {
+    let mut rows: Vec<(u32, String, ObjectHits)> = scene
+        .objects()
+        .iter()
+        .map(|o| (o.id, o.name.clone().unwrap_or_default(), objects.get(o.id)))
+        .collect();
+    if scene.floor.is_some() {
+        rows.push((FLOOR_ID, "floor".to_string(), objects.get(FLOOR_ID)));
+    }
+    rows
+}
+
+fn print_stats(
+    format: StatsFormat,
+    stats: &RayStats,
+    objects: &ObjectStats,
+    scene: &Scene,
+    timings: &Timings,
+) {
+    // Only counted with the stats feature
+    let rows = if cfg!(feature = "stats") {
+        object_rows(scene, objects)
+    } else {
+        Vec::new()
+    };
+    let seconds = timings.render.as_secs_f64();
+    let rays_per_second = if seconds > 0.0 {
+        stats.total_rays() as f64 / seconds
+    } else {
+        0.0
+    };
+    let stages = [
+        ("load", timings.load),
+        ("build", timings.build),
+        ("render", timings.render),
+        ("write", timings.write),
+    ];
+    match format {
+        StatsFormat::Json => {
+            let stages: Vec<String> = stages
+                .iter()
+                .map(|(name, time)| format!("\"{}\": {:.6}", name, time.as_secs_f64()))
+                .collect();
+            let objects = if cfg!(feature = "stats") {
+                let rows: Vec<String> = rows
+                    .iter()
+                    .map(|(id, name, hits)| {
+                        format!(
+                            "{{\"id\": {}, \"name\": {}, \"camera\": {}, \"secondary\": {}, \"shadow\": {}}}",
+                            id,
+                            json::quote(name),
+                            hits.camera,
+                            hits.secondary,
+                            hits.shadow
+                        )
+                    })
+                    .collect();
+                format!(", \"objects\": [{}]", rows.join(", "))
+            } else {
+                String::new()
+            };
+            println!(
+                "{{\"rays\": {{\"total\": {}, \"camera\": {}, \"secondary\": {}, \"shadow\": {}}}, \
+                 \"rays_per_second\": {:.1}, \"average_bounces\": {:.4}, \"shadow_fraction\": {:.4}, \
+                 \"bvh_node_tests\": {}, \"bvh_node_tests_per_ray\": {:.4}, \"seconds\": {{{}}}{}}}",
+                stats.total_rays(),
+                stats.camera_rays,
+                stats.secondary_rays,
+                stats.shadow_rays,
+                rays_per_second,
+                stats.average_bounces(),
+                stats.shadow_fraction(),
+                stats.bvh_node_tests,
+                stats.node_tests_per_ray(),
+                stages.join(", "),
+                objects
+            );
+        }
+        StatsFormat::Table => {
+            println!("rays traced          {:>14}", stats.total_rays());
+            println!("  camera             {:>14}", stats.camera_rays);
+            println!("  secondary          {:>14}", stats.secondary_rays);
+            println!(
+                "  shadow             {:>14} ({:.1}%)",
+                stats.shadow_rays,
+                100.0 * stats.shadow_fraction()
+            );
+            println!("rays per second      {:>14.0}", rays_per_second);
+            println!("average bounces      {:>14.3}", stats.average_bounces());
+            println!("BVH node tests/ray   {:>14.2}", stats.node_tests_per_ray());
+            let total: f64 = stages.iter().map(|(_, time)| time.as_secs_f64()).sum();
+            for (name, time) in stages {
+                let share = if total > 0.0 {
+                    100.0 * time.as_secs_f64() / total
+                } else {
+                    0.0
+                };
+                let label = format!("{} time", name);
+                println!("{:<21}{:>13.3}s ({:.1}%)", label, time.as_secs_f64(), share);
+            }
+            if !rows.is_empty() {
+                println!(
+                    "{:<21}{:>14}{:>14}{:>14}",
+                    "rays stopped by", "camera", "secondary", "shadow"
+                );
+                let label = |id: u32, name: &str| match id {
+                    FLOOR_ID => "floor".to_string(),
+                    _ => format!("#{} {}", id, name).trim_end().to_string(),
+                };
+                for (id, name, hits) in &rows {
+                    let label = format!("  {}", label(*id, name));
+                    println!(
+                        "{:<21}{:>14}{:>14}{:>14}",
+                        label, hits.camera, hits.secondary, hits.shadow
+                    );
+                }
+                let unseen: Vec<String> = rows
+                    .iter()
+                    .filter(|(_, _, hits)| hits.total() == 0)
+                    .map(|(id, name, _)| label(*id, name))
+                    .collect();
+                if !unseen.is_empty() {
+                    println!(
+                        "never hit            {:>14} ({})",
+                        unseen.len(),
+                        unseen.join(", ")
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn crop_output(args: &Args, image: Framebuffer) -> io::Result<Framebuffer> {
+    let Some(crop) = args.crop.filter(|_| !args.crop_full) else {
+        return Ok(image);
+    };
+    let frame = TileRect {
+        x0: 0,
+        y0: 0,
+        x1: image.width,
+        y1: image.height,
+    };
+    let region = crop.intersect(&frame).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "crop region lies outside the {}x{} frame",
+                frame.x1, frame.y1
+            ),
+        )
+    })?;
+    Ok(image.cropped(&region))
+}
+
+// Renders every job in turn, keeping a scene loaded for as long as consecutive jobs
+// share it. A failed job is reported and skipped so one typo does not cost the rest.
+fn run_batch(args: &Args, manifest: &Path) -> io::Result<()> {
+    let jobs = BatchJob::load_manifest(manifest)?;
+    let mut loaded: Option<(Option<PathBuf>, Scene, Camera, RenderSettings)> = None;
+    let mut failed = 0;
+    let start = Instant::now();
+
+    for (i, job) in jobs.iter().enumerate() {
+        let label = format!("job {}/{} {}", i + 1, jobs.len(), job.output.display());
+        let mut timings = Timings::default();
+        if loaded.as_ref().is_none_or(|(path, ..)| *path != job.scene) {
+            let start = Instant::now();
+            match load_scene(
+                job.scene
+                    .as_deref()
+                    .map_or(Source::BuiltIn, |path| Source::File(path, &[])),
+            ) {
+                Ok((mut scene, camera, defaults, _)) => {
+                    timings.load = start.elapsed();
+                    scene.material_override = args.material_override;
+                    let start = Instant::now();
+                    scene.build_bvh();
+                    timings.build = start.elapsed();
+                    loaded = Some((job.scene.clone(), scene, camera, defaults))
+                }
+                Err(e) => {
+                    error!("{}: {}", label, e);
+                    failed += 1;
+                    continue;
+                }
+            }
+        }
+        let (_, scene, camera, defaults) = loaded.as_ref().expect("scene loaded above");
+
+        let mut camera = camera.clone();
+        apply_fov(&mut camera, args.fov);
+        apply_fov(&mut camera, job.fov);
+        if args.frame {
+            camera = camera.frame(scene, FRAME_PADDING);
+        }
+        if let Some(ev) = args.exposure {
+            camera = camera.with_exposure_compensation(ev);
+        }
+        let mut settings = settings_for(args, defaults);
+        job.render.apply(&mut settings);
+
+        let (image, stats, objects) = render_counted(
+            scene,
+            &camera,
+            &settings,
+            job.scene.as_deref(),
+            args.max_seconds,
+            &mut timings,
+        );
+        let start = Instant::now();
+        let result =
+            crop_output(args, image).and_then(|image| write_render(&image, &job.output, args));
+        timings.write = start.elapsed();
+        match result {
+            // The rest of the jobs are abandoned along with this one
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => return Err(e),
+            Ok(()) => info!(
+                "{}: {}x{}, {} spp in {:.2}s",
+                label,
+                settings.width,
+                settings.height,
+                settings.samples_per_pixel,
+                (timings.render + timings.write).as_secs_f64()
+            ),
+            Err(e) => {
+                error!("{}: {}", label, e);
+                failed += 1;
+                continue;
+            }
+        }
+        if let Some(format) = args.stats {
+            print_stats(format, &stats, &objects, scene, &timings);
+        }
+    }
+
+    info!(
+        "rendered {} of {} jobs in {:.2}s",
+        jobs.len() - failed,
+        jobs.len(),
+        start.elapsed().as_secs_f64()
+    );
+    if failed > 0 {
+        return Err(io::Error::other(format!("{} batch job(s) failed", failed)));
+    }
     Ok(())
 }
 
+fn inspect_pixel(scene: &Scene, camera: &Camera, settings: &RenderSettings, x: usize, y: usize) {
+    let traces = trace_pixel(scene, camera, settings, x, y);
+    let vec = |v: &Vec3f| format!("({:.4}, {:.4}, {:.4})", v.0, v.1, v.2);
+    println!("pixel {},{}: {} sample(s)", x, y, traces.len());
+
+    let mut total = Vec3f(0.0, 0.0, 0.0);
+    for (i, trace) in traces.iter().enumerate() {
+        println!(
+            "sample {} at ({:.3}, {:.3})",
+            i, trace.sample.0, trace.sample.1
+        );
+        for vertex in &trace.vertices {
+            let indent = "  ".repeat(vertex.depth as usize + 1);
+            match vertex.event {
+                PathEvent::Camera => println!("{}camera at {}", indent, vec(&vertex.position)),
+                PathEvent::Escaped => println!("{}depth {}: escaped", indent, vertex.depth),
+                PathEvent::Medium => println!(
+                    "{}depth {}: medium scatter at {}",
+                    indent,
+                    vertex.depth,
+                    vec(&vertex.position)
+                ),
+                PathEvent::Surface => {
+                    let object = match vertex.object_id {
+                        Some(FLOOR_ID) => "floor".to_string(),
+                        Some(id) => format!("object {}", id),
+                        None => "unknown object".to_string(),
+                    };
+                    println!(
+                        "{}depth {}: {} at {}, normal {}",
+                        indent,
+                        vertex.depth,
+                        object,
+                        vec(&vertex.position),
+                        vec(&vertex.normal.unwrap_or(Vec3f(0.0, 0.0, 0.0)))
+                    );
+                    if let Some(material) = &vertex.material {
+                        println!(
+                            "{}  material: diffuse {}, albedo {:?}, specular exponent {}, ior {}",
+                            indent,
+                            vec(&material.diffuse_color),
+                            material.albedo,
+                            material.specular_exponent,
+                            material.refractive_index
+                        );
+                    }
+                }
+            }
+            if vertex.event != PathEvent::Camera {
+                let pdf = vertex
+                    .pdf
+                    .map_or("-".to_string(), |pdf| format!("{:.4}", pdf));
+                println!(
+                    "{}  emitted {}, throughput {}, pdf {}, contribution {}",
+                    indent,
+                    vec(&vertex.emitted),
+                    vec(&vertex.throughput),
+                    pdf,
+                    vec(&vertex.contribution)
+                );
+            }
+        }
+        println!("  color {}, alpha {:.4}", vec(&trace.color), trace.alpha);
+        total += trace.color;
+    }
+    if !traces.is_empty() {
+        println!(
+            "final color {}",
+            vec(&(total * (1.0 / traces.len() as Float)))
+        );
+    }
+}
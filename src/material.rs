@@ -1,80 +1,945 @@
-mod vec3;
-use vec3::Vec3f;
+use crate::ior::Dispersion;
+use crate::onb::Onb;
+use crate::shapes::HitRecord;
+use crate::texture::TextureMap;
+use crate::vec3::{consts::PI, Float, Vec3f};
 
+// albedo weights the diffuse, specular, reflected and refracted contributions in that order
 #[derive(Clone, Copy, Debug)]
-struct Material {
-    refractive_index: f32,
-    albedo: [f32; 4],
-    diffuse_color: Vec3,
-    specular_exponent: f32,
+pub struct Material {
+    pub refractive_index: Float,
+    pub albedo: [Float; 4],
+    pub diffuse_color: Vec3f,
+    pub specular_exponent: Float,
+    // A coating that colors the highlights and reflections by interference
+    pub thin_film: Option<ThinFilm>,
+    // Stretches the highlight, and in the path tracer blurs reflections, along one
+    // surface direction instead of the round specular_exponent lobe
+    pub anisotropy: Option<Anisotropy>,
+    // Colors the highlights and reflections, as metals do; white when unset
+    pub specular_color: Option<Vec3f>,
+    // A clear varnish over everything else, as on car paint
+    pub clearcoat: Option<Clearcoat>,
+    // A soft rim of light at grazing angles, as on cloth
+    pub sheen: Option<Sheen>,
+    pub sides: Sides,
+    // How refractive_index, which holds its value at the D line, varies with wavelength
+    pub dispersion: Option<Dispersion>,
+    // Shows camera rays what lies behind the surface, darkened where it is in shadow, so
+    // rendered objects can be laid over a photograph of the ground they stand on
+    pub shadow_catcher: Option<ShadowCatcher>,
+    // Where transparent solids overlap, the one of highest priority fills the overlap and
+    // the others' surfaces inside it are passed straight through, as for water poured
+    // into a glass modelled overlapping its walls
+    pub priority: u32,
+    // Light the surface gives off itself. Only the path tracer's bounces carry it on to
+    // light other surfaces; the Whitted integrator just shows the surface glowing.
+    pub emission: Option<Emission>,
 }
 
-const IVORY: Material = Material {
+// Which sides of a surface rays meet. Normals are turned to face every ray either way;
+// this decides whether a hit from behind counts as leaving a solid.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Sides {
+    // Both sides are hit, and hits on the back face leave the solid, which is what
+    // refraction needs to bend the right way
+    #[default]
+    Front,
+    // Either side counts as the front, so both sides shade and refract alike, as for
+    // leaves and paper
+    Both,
+    // Camera rays pass through back faces, which for closed meshes are hidden anyway
+    // unless the camera is inside; shadows and reflections still see them
+    Cull,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowCatcher {
+    // In [0, 1], how strongly the objects above show mirrored in the surface; 0 catches
+    // shadows alone
+    pub reflections: Float,
+}
+
+// Schlick's approximation of the Fresnel reflectance of a coat with index 1.5 is taken
+// from this at normal incidence
+const CLEARCOAT_F0: Float = 0.04;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Clearcoat {
+    // In [0, 1]
+    pub weight: Float,
+    pub exponent: Float,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sheen {
+    // Reflected at grazing angles, on top of the diffuse color
+    pub color: Vec3f,
+}
+
+// Light given off by a surface, as by the panel of an area light
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Emission {
+    // Radiance, which can go well above one
+    pub color: Vec3f,
+    // Whether the back of the surface glows too, rather than only the side its normal
+    // points out of
+    pub two_sided: bool,
+}
+
+// The Blinn-Phong exponent whose lobe is about as wide as a microfacet lobe of this
+// roughness in [0, 1], with roughness squared as the microfacet width (Burley 2012)
+pub fn roughness_exponent(roughness: Float) -> Float {
+    let alpha = (roughness * roughness).clamp(1e-3, 1.0);
+    2.0 / (alpha * alpha) - 2.0
+}
+
+fn schlick_weight(cos: Float) -> Float {
+    (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+// Roughness that differs along and across a surface direction, as on brushed metal or
+// the grooves of a record, using the Ashikhmin-Shirley lobe around the half vector
+#[derive(Clone, Copy, Debug)]
+pub struct Anisotropy {
+    // In (0, 1], along the shape's tangent and across it; lower is shinier
+    pub roughness: (Float, Float),
+    // Turns the tangent about the normal, in radians
+    pub rotation: Float,
+}
+
+impl Anisotropy {
+    // Blinn-Phong exponents matching the roughnesses, which are microfacet widths here
+    fn exponents(&self) -> (Float, Float) {
+        let exponent = |roughness: Float| roughness_exponent(roughness.sqrt());
+        (exponent(self.roughness.0), exponent(self.roughness.1))
+    }
+
+    // Shading frame with the tangent turned by the rotation; shapes without a tangent
+    // get an arbitrary but consistent one
+    pub fn frame(&self, normal: &Vec3f, tangent: Option<Vec3f>) -> Onb {
+        let tangent = tangent
+            .and_then(|t| (t - *normal * normal.dot(&t)).normalized())
+            .unwrap_or_else(|| Onb::from_normal(normal).tangent);
+        let bitangent = normal.cross(&tangent);
+        let (sin, cos) = self.rotation.sin_cos();
+        let tangent = tangent * cos + bitangent * sin;
+        Onb {
+            tangent,
+            bitangent: normal.cross(&tangent),
+            normal: *normal,
+        }
+    }
+
+    // Highlight strength for a half vector, peaking at one where it meets the normal
+    pub fn highlight(&self, frame: &Onb, half: &Vec3f) -> Float {
+        let h = frame.to_local(half);
+        if h.2 <= 0.0 {
+            return 0.0;
+        }
+        let (nu, nv) = self.exponents();
+        let sin2 = (1.0 - h.2 * h.2).max(Float::EPSILON);
+        h.2.powf((nu * h.0 * h.0 + nv * h.1 * h.1) / sin2)
+    }
+
+    // How far the exponents squeeze the lobe: it covers about 2π / sharpness of solid
+    // angle, as a Phong lobe with exponent sharpness - 1 does
+    pub fn sharpness(&self) -> Float {
+        let (nu, nv) = self.exponents();
+        ((nu + 1.0) * (nv + 1.0)).sqrt()
+    }
+
+    // The density per solid angle that sample_half draws half with
+    pub fn half_pdf(&self, frame: &Onb, half: &Vec3f) -> Float {
+        self.sharpness() / (2.0 * PI) * self.highlight(frame, half)
+    }
+
+    // A half vector distributed like the lobe, for scattering glossy reflections
+    pub fn sample_half(&self, frame: &Onb, u1: Float, u2: Float) -> Vec3f {
+        let (nu, nv) = self.exponents();
+        let quadrant = (u1 * 4.0).min(3.0);
+        let angle = (((nu + 1.0) / (nv + 1.0)).sqrt() * (PI / 2.0 * quadrant.fract()).tan()).atan();
+        let phi = match quadrant as u32 {
+            0 => angle,
+            1 => PI - angle,
+            2 => PI + angle,
+            _ => 2.0 * PI - angle,
+        };
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let exponent = nu * cos_phi * cos_phi + nv * sin_phi * sin_phi;
+        let cos_theta = (1.0 - u2).powf(1.0 / (exponent + 1.0));
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        frame.to_world(&Vec3f(sin_theta * cos_phi, sin_theta * sin_phi, cos_theta))
+    }
+}
+
+// A transparent layer a few hundred nanometres thick, like soap or oil, over the surface.
+// Light reflected off its top and bottom interferes, brightening some wavelengths and
+// cancelling others depending on the thickness and the viewing angle.
+#[derive(Clone, Copy, Debug)]
+pub struct ThinFilm {
+    // In nanometres
+    pub thickness: Float,
+    pub refractive_index: Float,
+}
+
+// Wavelengths in nanometres standing in for the red, green and blue channels
+pub const RGB_WAVELENGTHS: [Float; 3] = [650.0, 532.0, 450.0];
+
+impl ThinFilm {
+    // Reflectance per channel at this cosine of the incidence angle, relative to what the
+    // layer would reflect without interference so the color shifts but the brightness
+    // roughly holds. base_index is that of the material under the film.
+    pub fn tint(&self, cos_incident: Float, base_index: Float) -> Vec3f {
+        let cos_incident = cos_incident.abs().min(1.0);
+        let film = self.refractive_index;
+        let sin2 = (1.0 - cos_incident * cos_incident) / (film * film);
+        if sin2 >= 1.0 {
+            return Vec3f(1.0, 1.0, 1.0);
+        }
+        let cos_film = (1.0 - sin2).sqrt();
+        let r12 = fresnel_amplitude(1.0, film, cos_incident);
+        // Past the critical angle into the base everything comes back up
+        let r23 = if base_index * base_index < film * film * sin2 {
+            1.0
+        } else {
+            fresnel_amplitude(film, base_index, cos_film)
+        };
+        let incoherent =
+            (r12 * r12 + r23 * r23 - 2.0 * r12 * r12 * r23 * r23) / (1.0 - r12 * r12 * r23 * r23);
+        if incoherent <= 0.0 {
+            return Vec3f(1.0, 1.0, 1.0);
+        }
+        let [r, g, b] = RGB_WAVELENGTHS.map(|wavelength| {
+            // Airy's formula for the two interfaces, phase from the path through the film
+            let phase = 4.0 * PI * film * self.thickness * cos_film / wavelength;
+            let cross = 2.0 * r12 * r23 * phase.cos();
+            (r12 * r12 + r23 * r23 + cross) / (1.0 + r12 * r12 * r23 * r23 + cross) / incoherent
+        });
+        Vec3f(r, g, b)
+    }
+}
+
+// Amplitude reflection coefficient from index n1 into n2, averaged over polarizations,
+// with both taken in the sign convention where they agree at normal incidence
+fn fresnel_amplitude(n1: Float, n2: Float, cos_i: Float) -> Float {
+    let sin2_t = (n1 / n2) * (n1 / n2) * (1.0 - cos_i * cos_i);
+    let cos_t = (1.0 - sin2_t).max(0.0).sqrt();
+    let s = (n1 * cos_i - n2 * cos_t) / (n1 * cos_i + n2 * cos_t);
+    let p = (n1 * cos_t - n2 * cos_i) / (n1 * cos_t + n2 * cos_i);
+    (s + p) * 0.5
+}
+
+impl Material {
+    // Color of the specular highlight and mirror reflection seen at this cosine of the
+    // incidence angle: white unless a thin film tints it
+    pub fn specular_tint(&self, cos_incident: Float) -> Vec3f {
+        let tint = match &self.thin_film {
+            Some(film) => film.tint(cos_incident, self.refractive_index),
+            None => Vec3f(1.0, 1.0, 1.0),
+        };
+        match &self.specular_color {
+            Some(color) => tint.multiply(color),
+            None => tint,
+        }
+    }
+
+    // How much of the mirror direction a clearcoat reflects at this cosine of the
+    // incidence angle, on top of albedo[2]
+    pub fn clearcoat_reflectance(&self, cos_incident: Float) -> Float {
+        self.clearcoat.map_or(0.0, |coat| {
+            let f = CLEARCOAT_F0 + (1.0 - CLEARCOAT_F0) * schlick_weight(cos_incident.abs());
+            coat.weight * f
+        })
+    }
+
+    // The clearcoat's own highlight for a half vector, white and a quarter as strong as
+    // the base one at most
+    pub fn clearcoat_highlight(&self, n: &Vec3f, half: &Vec3f) -> Float {
+        self.clearcoat.map_or(0.0, |coat| {
+            0.25 * coat.weight * Float::max(0.0, n.dot(half)).powf(coat.exponent)
+        })
+    }
+
+    // Sheen reflected towards the viewer for a half vector between it and the light
+    pub fn sheen(&self, light_dir: &Vec3f, half: &Vec3f) -> Vec3f {
+        match &self.sheen {
+            Some(sheen) => sheen.color * schlick_weight(light_dir.dot(half)),
+            None => Vec3f(0.0, 0.0, 0.0),
+        }
+    }
+
+    // The index that light of one color channel, 0 to 2 for red to blue, is bent by;
+    // with no channel, or no dispersion, the index at the D line
+    pub fn channel_index(&self, channel: Option<usize>) -> Float {
+        match (self.dispersion, channel) {
+            (Some(dispersion), Some(channel)) => dispersion.index(RGB_WAVELENGTHS[channel]),
+            _ => self.refractive_index,
+        }
+    }
+}
+
+impl Material {
+    // The light the surface gives off towards a ray that met its front face or its back
+    pub fn emitted(&self, front_face: bool) -> Vec3f {
+        match self.emission {
+            Some(emission) if front_face || emission.two_sided => emission.color,
+            _ => Vec3f(0.0, 0.0, 0.0),
+        }
+    }
+
+    // Linear mix towards other by t in [0, 1]. Optional lobes fade in and out with their
+    // weights; thin films and anisotropy cannot be meaningfully halved, so they come
+    // from whichever material dominates.
+    pub fn mix(&self, other: &Material, t: Float) -> Material {
+        let t = t.clamp(0.0, 1.0);
+        let lerp = |a: Float, b: Float| a * (1.0 - t) + b * t;
+        let lerp3 = |a: Vec3f, b: Vec3f| a * (1.0 - t) + b * t;
+        let dominant = if t < 0.5 { self } else { other };
+        let white = Vec3f(1.0, 1.0, 1.0);
+        let specular_color = match (self.specular_color, other.specular_color) {
+            (None, None) => None,
+            (a, b) => Some(lerp3(a.unwrap_or(white), b.unwrap_or(white))),
+        };
+        let clearcoat = match (self.clearcoat, other.clearcoat) {
+            (None, None) => None,
+            (a, b) => {
+                let exponent = |c: Option<Clearcoat>| c.map_or(0.0, |c| c.exponent);
+                let weight = |c: Option<Clearcoat>| c.map_or(0.0, |c| c.weight);
+                Some(Clearcoat {
+                    weight: lerp(weight(a), weight(b)),
+                    exponent: match (a, b) {
+                        (Some(_), Some(_)) => lerp(exponent(a), exponent(b)),
+                        _ => exponent(a).max(exponent(b)),
+                    },
+                })
+            }
+        };
+        let emission = match (self.emission, other.emission) {
+            (None, None) => None,
+            (a, b) => {
+                let color = |e: Option<Emission>| e.map_or(Vec3f(0.0, 0.0, 0.0), |e| e.color);
+                let two_sided = |e: Option<Emission>| e.is_some_and(|e| e.two_sided);
+                Some(Emission {
+                    color: lerp3(color(a), color(b)),
+                    two_sided: two_sided(a) || two_sided(b),
+                })
+            }
+        };
+        let sheen = match (self.sheen, other.sheen) {
+            (None, None) => None,
+            (a, b) => {
+                let color = |s: Option<Sheen>| s.map_or(Vec3f(0.0, 0.0, 0.0), |s| s.color);
+                Some(Sheen {
+                    color: lerp3(color(a), color(b)),
+                })
+            }
+        };
+        Material {
+            refractive_index: lerp(self.refractive_index, other.refractive_index),
+            albedo: [0, 1, 2, 3].map(|i| lerp(self.albedo[i], other.albedo[i])),
+            diffuse_color: lerp3(self.diffuse_color, other.diffuse_color),
+            specular_exponent: lerp(self.specular_exponent, other.specular_exponent),
+            thin_film: dominant.thin_film,
+            anisotropy: dominant.anisotropy,
+            specular_color,
+            clearcoat,
+            sheen,
+            sides: self.sides,
+            dispersion: dominant.dispersion,
+            shadow_catcher: dominant.shadow_catcher,
+            priority: dominant.priority,
+            emission,
+        }
+    }
+}
+
+// Mixes an object's own material with another, everywhere by factor or patchily where a
+// mask texture is bright, for rust on metal or paint worn off wood
+#[derive(Clone)]
+pub struct BlendMaterial {
+    pub other: Material,
+    // How much of other shows, in [0, 1]; a mask scales it by its brightness
+    pub factor: Float,
+    pub mask: Option<TextureMap>,
+}
+
+impl BlendMaterial {
+    pub fn new(other: Material, factor: Float) -> BlendMaterial {
+        BlendMaterial {
+            other,
+            factor,
+            mask: None,
+        }
+    }
+
+    pub fn with_mask(mut self, mask: TextureMap) -> BlendMaterial {
+        self.mask = Some(mask);
+        self
+    }
+
+    // The blended material at a hit; hits with no place in the mask get the factor alone
+    pub fn resolve(&self, base: &Material, record: &HitRecord) -> Material {
+        let mask = self
+            .mask
+            .as_ref()
+            .and_then(|mask| mask.lookup(record))
+            .map_or(1.0, |c| (c.0 + c.1 + c.2) / 3.0);
+        base.mix(&self.other, self.factor * mask)
+    }
+}
+
+// Stand-ins for every material in the scene, floor included, to judge the lighting and the
+// modelling apart from the shading. Clay is a plain gray lit as usual, which keeps what
+// glows glowing so area lights still light it. Normal and uv show, unlit, the outward
+// normal mapped from [-1, 1] to [0, 1] and the texture coordinates' fractions in red and
+// green, black where a shape has none.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaterialOverride {
+    Clay,
+    Normal,
+    Uv,
+}
+
+impl MaterialOverride {
+    pub const NAMES: [(&'static str, MaterialOverride); 3] = [
+        ("clay", MaterialOverride::Clay),
+        ("normal", MaterialOverride::Normal),
+        ("uv", MaterialOverride::Uv),
+    ];
+
+    pub fn from_name(name: &str) -> Option<MaterialOverride> {
+        MaterialOverride::NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(_, replace)| replace)
+    }
+
+    // What replaces material at a hit whose normal already faces the ray
+    pub fn apply(&self, material: &Material, record: &HitRecord) -> Material {
+        let flat = |color: Vec3f| Material {
+            albedo: [0.0; 4],
+            diffuse_color: Vec3f(0.0, 0.0, 0.0),
+            emission: Some(Emission {
+                color,
+                two_sided: true,
+            }),
+            ..CLAY
+        };
+        match self {
+            MaterialOverride::Clay => Material {
+                sides: material.sides,
+                emission: material.emission,
+                ..CLAY
+            },
+            MaterialOverride::Normal => {
+                let outward = if record.front_face {
+                    record.normal
+                } else {
+                    -record.normal
+                };
+                flat((outward + Vec3f(1.0, 1.0, 1.0)) * 0.5)
+            }
+            MaterialOverride::Uv => flat(record.uv.map_or(Vec3f(0.0, 0.0, 0.0), |(u, v)| {
+                Vec3f(u.rem_euclid(1.0), v.rem_euclid(1.0), 0.0)
+            })),
+        }
+    }
+}
+
+// The neutral gray of clay renders, with a faint broad highlight to show the forms
+pub const CLAY: Material = Material {
+    refractive_index: 1.0,
+    albedo: [0.9, 0.05, 0.0, 0.0],
+    diffuse_color: Vec3f(0.3, 0.3, 0.3),
+    specular_exponent: 10.0,
+    thin_film: None,
+    anisotropy: None,
+    specular_color: None,
+    clearcoat: None,
+    sheen: None,
+    sides: Sides::Front,
+    dispersion: None,
+    shadow_catcher: None,
+    priority: 0,
+    emission: None,
+};
+
+// The parameters of Burley's principled BRDF as Blender and Substance expose them, for
+// porting materials without translating them to albedo weights by hand. Converting maps
+// them onto the lobes Material has.
+#[derive(Clone, Copy, Debug)]
+pub struct Principled {
+    pub base_color: Vec3f,
+    // Each in [0, 1]
+    pub metallic: Float,
+    pub roughness: Float,
+    // Dielectric reflectance, 0.5 being the usual 4%
+    pub specular: Float,
+    pub clearcoat: Float,
+    pub clearcoat_roughness: Float,
+    pub sheen: Float,
+    // How much of the base color tints the sheen
+    pub sheen_tint: Float,
+    pub transmission: Float,
+    pub refractive_index: Float,
+}
+
+// Blender's defaults
+impl Default for Principled {
+    fn default() -> Principled {
+        Principled {
+            base_color: Vec3f(0.8, 0.8, 0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            transmission: 0.0,
+            refractive_index: 1.45,
+        }
+    }
+}
+
+impl From<Principled> for Material {
+    fn from(p: Principled) -> Material {
+        let unit = |v: Float| v.clamp(0.0, 1.0);
+        let (metallic, transmission) = (unit(p.metallic), unit(p.transmission));
+        let roughness = unit(p.roughness);
+        let dielectric = 1.0 - metallic;
+        let f0 = 0.08 * unit(p.specular);
+        // Only smooth surfaces show a sharp mirror image
+        let smoothness = (1.0 - roughness) * (1.0 - roughness);
+        let mix = |a: Vec3f, b: Vec3f, t: Float| a * (1.0 - t) + b * t;
+        let white = Vec3f(1.0, 1.0, 1.0);
+        let luminance = 0.3 * p.base_color.0 + 0.6 * p.base_color.1 + 0.1 * p.base_color.2;
+        let hue = if luminance > 0.0 {
+            p.base_color * (1.0 / luminance)
+        } else {
+            white
+        };
+        Material {
+            refractive_index: p.refractive_index,
+            albedo: [
+                dielectric * (1.0 - transmission),
+                dielectric * unit(p.specular) + metallic,
+                smoothness * (dielectric * f0 + metallic),
+                dielectric * transmission * (1.0 - f0),
+            ],
+            diffuse_color: p.base_color,
+            specular_exponent: roughness_exponent(roughness),
+            thin_film: None,
+            anisotropy: None,
+            specular_color: (metallic > 0.0).then(|| mix(white, p.base_color, metallic)),
+            clearcoat: (p.clearcoat > 0.0).then(|| Clearcoat {
+                weight: unit(p.clearcoat),
+                exponent: roughness_exponent(unit(p.clearcoat_roughness)),
+            }),
+            sheen: (p.sheen > 0.0).then(|| Sheen {
+                color: mix(white, hue, unit(p.sheen_tint)) * (p.sheen.max(0.0) * dielectric),
+            }),
+            sides: Sides::Front,
+            dispersion: None,
+            shadow_catcher: None,
+            priority: 0,
+            emission: None,
+        }
+    }
+}
+
+pub const IVORY: Material = Material {
     refractive_index: 1.0,
     albedo: [0.9, 0.5, 0.1, 0.0],
-    diffuse_color: Vec3(0.4, 0.4, 0.3),
+    diffuse_color: Vec3f(0.4, 0.4, 0.3),
     specular_exponent: 50.0,
+    thin_film: None,
+    anisotropy: None,
+    specular_color: None,
+    clearcoat: None,
+    sheen: None,
+    sides: Sides::Front,
+    dispersion: None,
+    shadow_catcher: None,
+    priority: 0,
+    emission: None,
 };
 
-const GLASS: Material = Material {
+pub const GLASS: Material = Material {
     refractive_index: 1.5,
     albedo: [0.0, 0.9, 0.1, 0.8],
-    diffuse_color: Vec3(0.6, 0.7, 0.8),
+    diffuse_color: Vec3f(0.6, 0.7, 0.8),
     specular_exponent: 125.0,
+    thin_film: None,
+    anisotropy: None,
+    specular_color: None,
+    clearcoat: None,
+    sheen: None,
+    sides: Sides::Front,
+    dispersion: None,
+    shadow_catcher: None,
+    priority: 0,
+    emission: None,
 };
 
-const RED_RUBBER: Material = Material {
+pub const RED_RUBBER: Material = Material {
     refractive_index: 1.0,
     albedo: [1.4, 0.3, 0.0, 0.0],
-    diffuse_color: Vec3(0.3, 0.1, 0.1),
+    diffuse_color: Vec3f(0.3, 0.1, 0.1),
     specular_exponent: 10.0,
+    thin_film: None,
+    anisotropy: None,
+    specular_color: None,
+    clearcoat: None,
+    sheen: None,
+    sides: Sides::Front,
+    dispersion: None,
+    shadow_catcher: None,
+    priority: 0,
+    emission: None,
 };
 
-const MIRROR: Material = Material {
+pub const MIRROR: Material = Material {
     refractive_index: 1.0,
     albedo: [0.0, 16.0, 0.8, 0.0],
-    diffuse_color: Vec3(1.0, 1.0, 1.0),
+    diffuse_color: Vec3f(1.0, 1.0, 1.0),
     specular_exponent: 1425.0,
+    thin_film: None,
+    anisotropy: None,
+    specular_color: None,
+    clearcoat: None,
+    sheen: None,
+    sides: Sides::Front,
+    dispersion: None,
+    shadow_catcher: None,
+    priority: 0,
+    emission: None,
 };
 
-const METAL: Material = Material {
+pub const METAL: Material = Material {
     refractive_index: 1.0,
     albedo: [0.7, 0.3, 0.1, 0.0],
-    diffuse_color: Vec3(0.6, 0.6, 0.7),
+    diffuse_color: Vec3f(0.6, 0.6, 0.7),
     specular_exponent: 200.0,
+    thin_film: None,
+    anisotropy: None,
+    specular_color: None,
+    clearcoat: None,
+    sheen: None,
+    sides: Sides::Front,
+    dispersion: None,
+    shadow_catcher: None,
+    priority: 0,
+    emission: None,
 };
 
-const DARK_WOOD: Material = Material {
+pub const DARK_WOOD: Material = Material {
     refractive_index: 1.0,
     albedo: [0.8, 0.1, 0.05, 0.0],
-    diffuse_color: Vec3(0.2, 0.1, 0.0),
+    diffuse_color: Vec3f(0.2, 0.1, 0.0),
     specular_exponent: 20.0,
+    thin_film: None,
+    anisotropy: None,
+    specular_color: None,
+    clearcoat: None,
+    sheen: None,
+    sides: Sides::Front,
+    dispersion: None,
+    shadow_catcher: None,
+    priority: 0,
+    emission: None,
 };
 
-const MARBLE: Material = Material {
+pub const MARBLE: Material = Material {
     refractive_index: 1.5,
     albedo: [0.9, 0.2, 0.05, 0.0],
-    diffuse_color: Vec3(0.7, 0.7, 0.9),
+    diffuse_color: Vec3f(0.7, 0.7, 0.9),
     specular_exponent: 100.0,
+    thin_film: None,
+    anisotropy: None,
+    specular_color: None,
+    clearcoat: None,
+    sheen: None,
+    sides: Sides::Front,
+    dispersion: None,
+    shadow_catcher: None,
+    priority: 0,
+    emission: None,
 };
 
-const GOLD: Material = Material {
+pub const GOLD: Material = Material {
     refractive_index: 0.47,
     albedo: [0.8, 1.0, 0.1, 0.0],
-    diffuse_color: Vec3(1.0, 0.8, 0.0),
+    diffuse_color: Vec3f(1.0, 0.8, 0.0),
     specular_exponent: 300.0,
+    thin_film: None,
+    anisotropy: None,
+    specular_color: None,
+    clearcoat: None,
+    sheen: None,
+    sides: Sides::Front,
+    dispersion: None,
+    shadow_catcher: None,
+    priority: 0,
+    emission: None,
 };
 
-const VELVET: Material = Material {
+pub const VELVET: Material = Material {
     refractive_index: 1.0,
     albedo: [0.9, 0.1, 0.0, 0.0],
-    diffuse_color: Vec3(0.5, 0.0, 0.5),
+    diffuse_color: Vec3f(0.5, 0.0, 0.5),
     specular_exponent: 5.0,
+    thin_film: None,
+    anisotropy: None,
+    specular_color: None,
+    clearcoat: None,
+    sheen: None,
+    sides: Sides::Front,
+    dispersion: None,
+    shadow_catcher: None,
+    priority: 0,
+    emission: None,
 };
 
-const CORTEN_STEEL: Material = Material {
+pub const CORTEN_STEEL: Material = Material {
     refractive_index: 2.5,
     albedo: [0.8, 0.3, 0.05, 0.0],
-    diffuse_color: Vec3(0.7, 0.5, 0.4),
+    diffuse_color: Vec3f(0.7, 0.5, 0.4),
     specular_exponent: 20.0,
+    thin_film: None,
+    anisotropy: None,
+    specular_color: None,
+    clearcoat: None,
+    sheen: None,
+    sides: Sides::Front,
+    dispersion: None,
+    shadow_catcher: None,
+    priority: 0,
+    emission: None,
 };
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::texture::{ImageTexture, Mapping};
+
+    fn close(a: Vec3f, b: Vec3f) -> bool {
+        (a - b).length() < 1e-4
+    }
+
+    #[test]
+    fn thin_films_interfere_with_their_thickness_and_angle() {
+        let soap = |thickness| ThinFilm {
+            thickness,
+            refractive_index: 1.33,
+        };
+        // Too thin to shift any wavelength more than another
+        let gray = soap(0.0).tint(1.0, 1.5);
+        assert!((gray.0 - gray.1).abs() < 1e-5 && (gray.1 - gray.2).abs() < 1e-5);
+        // A quarter of green's wavelength inside the film cancels green most of all
+        let quarter = soap(532.0 / (4.0 * 1.33)).tint(1.0, 1.5);
+        assert!(
+            quarter.1 < quarter.0 && quarter.1 < quarter.2,
+            "{:?}",
+            quarter
+        );
+        // Seen at an angle the path through the film shortens, and the color moves
+        let tilted = soap(532.0 / (4.0 * 1.33)).tint(0.5, 1.5);
+        assert!(!close(quarter, tilted), "{:?}", tilted);
+        assert_eq!(soap(400.0).tint(-0.5, 1.5), soap(400.0).tint(0.5, 1.5));
+        // With nothing to reflect off beneath it, the film has nothing to interfere with
+        let matched = ThinFilm {
+            thickness: 400.0,
+            refractive_index: 1.5,
+        };
+        assert!(close(matched.tint(0.7, 1.5), Vec3f(1.0, 1.0, 1.0)));
+
+        let plain = Material {
+            specular_color: Some(Vec3f(1.0, 0.5, 0.25)),
+            ..IVORY
+        };
+        assert_eq!(plain.specular_tint(0.3), Vec3f(1.0, 0.5, 0.25));
+        let filmed = Material {
+            thin_film: Some(soap(300.0)),
+            ..plain
+        };
+        let expected = soap(300.0).tint(0.3, 1.0).multiply(&Vec3f(1.0, 0.5, 0.25));
+        assert!(close(filmed.specular_tint(0.3), expected));
+    }
+
+    #[test]
+    fn anisotropic_lobes_stretch_across_the_tangent() {
+        let brushed = Anisotropy {
+            roughness: (0.3, 0.6),
+            rotation: 0.0,
+        };
+        let normal = Vec3f(0.0, 0.0, 1.0);
+        // The tangent is laid flat on the surface, and the rotation turns it about the normal
+        let frame = brushed.frame(&normal, Some(Vec3f(1.0, 0.0, 1.0)));
+        assert!(close(frame.tangent, Vec3f(1.0, 0.0, 0.0)));
+        assert!(close(frame.bitangent, Vec3f(0.0, 1.0, 0.0)));
+        let turned = Anisotropy {
+            rotation: PI / 2.0,
+            ..brushed
+        };
+        let turned = turned.frame(&normal, Some(Vec3f(1.0, 0.0, 0.0)));
+        assert!(close(turned.tangent, Vec3f(0.0, 1.0, 0.0)));
+        assert!(close(turned.bitangent, Vec3f(-1.0, 0.0, 0.0)));
+
+        // Rougher across the tangent, so the highlight falls off slower tilting that way
+        assert!((brushed.highlight(&frame, &normal) - 1.0).abs() < 1e-5);
+        let along = brushed.highlight(&frame, &Vec3f(0.3, 0.0, 1.0).normalized().unwrap());
+        let across = brushed.highlight(&frame, &Vec3f(0.0, 0.3, 1.0).normalized().unwrap());
+        assert!(along < across && across < 1.0, "{} {}", along, across);
+        assert_eq!(brushed.highlight(&frame, &Vec3f(0.0, 0.6, -0.8)), 0.0);
+
+        // The density integrates to one over the hemisphere
+        let steps = 400;
+        let mut total = 0.0;
+        for i in 0..steps {
+            let theta = (i as Float + 0.5) / steps as Float * PI / 2.0;
+            for j in 0..steps {
+                let phi = (j as Float + 0.5) / steps as Float * 2.0 * PI;
+                let half = Vec3f(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                let area = theta.sin() * (PI / 2.0 / steps as Float) * (2.0 * PI / steps as Float);
+                total += brushed.half_pdf(&frame, &half) * area;
+            }
+        }
+        assert!((total - 1.0).abs() < 1e-2, "{}", total);
+
+        // And the samples drawn from it spread wider across than along
+        let (mut spread_along, mut spread_across) = (0.0, 0.0);
+        for i in 0..64 {
+            for j in 0..64 {
+                let u1 = (i as Float + 0.5) / 64.0;
+                let u2 = (j as Float + 0.5) / 64.0;
+                let half = brushed.sample_half(&frame, u1, u2);
+                assert!(
+                    (half.length() - 1.0).abs() < 1e-4 && half.2 > 0.0,
+                    "{:?}",
+                    half
+                );
+                spread_along += half.0 * half.0;
+                spread_across += half.1 * half.1;
+            }
+        }
+        assert!(
+            spread_along < 0.8 * spread_across,
+            "{} {}",
+            spread_along,
+            spread_across
+        );
+    }
+
+    #[test]
+    fn clearcoats_and_sheen_rise_towards_grazing_angles() {
+        assert_eq!(IVORY.clearcoat_reflectance(0.1), 0.0);
+        assert_eq!(
+            IVORY.sheen(&Vec3f(0.0, 0.0, 1.0), &Vec3f(1.0, 0.0, 0.0)),
+            Vec3f(0.0, 0.0, 0.0)
+        );
+        let coated = Material {
+            clearcoat: Some(Clearcoat {
+                weight: 0.5,
+                exponent: 100.0,
+            }),
+            sheen: Some(Sheen {
+                color: Vec3f(0.2, 0.4, 0.8),
+            }),
+            ..IVORY
+        };
+        // Head on the coat reflects as little as varnish does, and edge on all of its weight
+        assert!((coated.clearcoat_reflectance(1.0) - 0.5 * CLEARCOAT_F0).abs() < 1e-6);
+        assert!((coated.clearcoat_reflectance(-1.0) - 0.5 * CLEARCOAT_F0).abs() < 1e-6);
+        assert!((coated.clearcoat_reflectance(0.0) - 0.5).abs() < 1e-6);
+        assert!(coated.clearcoat_reflectance(0.5) < coated.clearcoat_reflectance(0.2));
+
+        let n = Vec3f(0.0, 0.0, 1.0);
+        assert!((coated.clearcoat_highlight(&n, &n) - 0.125).abs() < 1e-6);
+        let off = Vec3f(0.2, 0.0, 1.0).normalized().unwrap();
+        assert!(coated.clearcoat_highlight(&n, &off) < 0.125 * 0.5);
+        assert_eq!(coated.clearcoat_highlight(&n, &-n), 0.0);
+
+        // None of the sheen where the light meets the half vector square on, all at 90 degrees
+        let light = Vec3f(0.0, 0.0, 1.0);
+        assert!(close(coated.sheen(&light, &light), Vec3f(0.0, 0.0, 0.0)));
+        assert!(close(
+            coated.sheen(&light, &Vec3f(1.0, 0.0, 0.0)),
+            Vec3f(0.2, 0.4, 0.8)
+        ));
+        let half = Vec3f(1.0, 0.0, 1.0).normalized().unwrap();
+        let partly = coated.sheen(&light, &half);
+        assert!(partly.2 > 0.0 && partly.2 < 0.8 * 0.1, "{:?}", partly);
+    }
+
+    #[test]
+    fn blends_mix_parameters_and_follow_their_masks() {
+        let coat = Clearcoat {
+            weight: 0.8,
+            exponent: 50.0,
+        };
+        let rust = Material {
+            diffuse_color: Vec3f(0.6, 0.2, 0.0),
+            clearcoat: Some(coat),
+            thin_film: Some(ThinFilm {
+                thickness: 300.0,
+                refractive_index: 1.3,
+            }),
+            ..RED_RUBBER
+        };
+        let quarter = IVORY.mix(&rust, 0.25);
+        assert!(close(
+            quarter.diffuse_color,
+            IVORY.diffuse_color * 0.75 + rust.diffuse_color * 0.25
+        ));
+        for i in 0..4 {
+            let expected = IVORY.albedo[i] * 0.75 + RED_RUBBER.albedo[i] * 0.25;
+            assert!((quarter.albedo[i] - expected).abs() < 1e-6);
+        }
+        // A lobe only one side has fades in by its weight at its own sharpness
+        let fading = quarter.clearcoat.unwrap();
+        assert!((fading.weight - 0.2).abs() < 1e-6 && fading.exponent == 50.0);
+        // What cannot be halved comes from the side showing most
+        assert!(quarter.thin_film.is_none());
+        assert!(IVORY.mix(&rust, 0.75).thin_film.is_some());
+        // And the ends are the materials themselves, past which the factor is held
+        assert!(close(
+            IVORY.mix(&rust, -1.0).diffuse_color,
+            IVORY.diffuse_color
+        ));
+        assert!(close(
+            IVORY.mix(&rust, 2.0).diffuse_color,
+            rust.diffuse_color
+        ));
+
+        let mask = ImageTexture::new(2, 1, vec![Vec3f(0.0, 0.0, 0.0), Vec3f(1.0, 1.0, 1.0)]);
+        let blend =
+            BlendMaterial::new(rust, 0.5).with_mask(TextureMap::new(Arc::new(mask), Mapping::Uv));
+        let at = |uv| HitRecord {
+            t: 1.0,
+            point: Vec3f(0.0, 0.0, 0.0),
+            normal: Vec3f(0.0, 0.0, 1.0),
+            uv,
+            tangent: None,
+            color: None,
+            front_face: true,
+        };
+        let bare = blend.resolve(&IVORY, &at(Some((0.25, 0.5))));
+        assert!(close(bare.diffuse_color, IVORY.diffuse_color));
+        let rusted = blend.resolve(&IVORY, &at(Some((0.75, 0.5))));
+        assert!(close(
+            rusted.diffuse_color,
+            IVORY.mix(&rust, 0.5).diffuse_color
+        ));
+        // Off the mask the factor holds alone
+        let unmapped = blend.resolve(&IVORY, &at(None));
+        assert!(close(unmapped.diffuse_color, rusted.diffuse_color));
+    }
+}
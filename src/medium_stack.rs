@@ -0,0 +1,114 @@
+// The transparent solids a ray is inside, so refraction where solids touch or overlap
+// bends light from one into the other instead of assuming air outside each, as for ice
+// in water in a glass. Where solids overlap, the one of highest priority fills the
+// overlap: surfaces of the others inside it are false hits the ray passes straight
+// through, noting only that it went in or out. Solids of equal priority all show their
+// surfaces, each taken to lie inside those entered before it, so scenes that never set a
+// priority refract as they always have.
+use crate::material::{Material, Sides};
+use crate::scene::Intersection;
+use crate::vec3::Float;
+
+// Solids nested deeper than this are not tracked, and refract as though in the one
+// around them
+const MAX_DEPTH: usize = 8;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MediumStack {
+    entries: [Medium; MAX_DEPTH],
+    len: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Medium {
+    object_id: u32,
+    priority: u32,
+    // The index at the D line, then for each color channel
+    indices: [Float; 4],
+}
+
+impl Medium {
+    fn new(object_id: u32, material: &Material) -> Medium {
+        Medium {
+            object_id,
+            priority: material.priority,
+            indices: [
+                material.channel_index(None),
+                material.channel_index(Some(0)),
+                material.channel_index(Some(1)),
+                material.channel_index(Some(2)),
+            ],
+        }
+    }
+
+    fn index(&self, channel: Option<usize>) -> Float {
+        self.indices[channel.map_or(0, |channel| channel + 1)]
+    }
+}
+
+impl MediumStack {
+    // Starting out in air
+    pub fn new() -> MediumStack {
+        MediumStack::default()
+    }
+
+    // Whether hit is a surface the ray meets, rather than one inside a solid of higher
+    // priority that it passes through
+    pub fn shows(&self, hit: &Intersection) -> bool {
+        !encloses(&hit.material)
+            || self
+                .beyond(hit.object_id)
+                .is_none_or(|medium| medium.priority <= hit.material.priority)
+    }
+
+    // The refractive indices for channel inside the solid hit is on and beyond its
+    // surface, in whatever the ray would go on into or came from; air has index 1
+    pub fn indices(&self, hit: &Intersection, channel: Option<usize>) -> (Float, Float) {
+        (
+            hit.material.channel_index(channel),
+            self.beyond(hit.object_id)
+                .map_or(1.0, |medium| medium.index(channel)),
+        )
+    }
+
+    // Notes the ray passing through hit's surface, into its solid or out of it
+    pub fn cross(&mut self, hit: &Intersection) {
+        if !encloses(&hit.material) {
+            return;
+        }
+        if hit.record.front_face {
+            if self.len < MAX_DEPTH {
+                self.entries[self.len] = Medium::new(hit.object_id, &hit.material);
+                self.len += 1;
+            }
+        } else if let Some(at) = self.entries[..self.len]
+            .iter()
+            .rposition(|medium| medium.object_id == hit.object_id)
+        {
+            self.entries.copy_within(at + 1..self.len, at);
+            self.len -= 1;
+        }
+    }
+
+    // The solid other than object_id the ray is in that fills where they overlap: the
+    // highest priority, and of those the last entered
+    fn beyond(&self, object_id: u32) -> Option<&Medium> {
+        self.entries[..self.len]
+            .iter()
+            .rev()
+            .filter(|medium| medium.object_id != object_id)
+            .reduce(|best, medium| {
+                if medium.priority > best.priority {
+                    medium
+                } else {
+                    best
+                }
+            })
+    }
+}
+
+// Whether rays passing through a surface of material go into a solid of it; surfaces
+// showing both sides alike are thin sheets with nothing inside
+fn encloses(material: &Material) -> bool {
+    material.albedo[3] > 0.0 && material.sides != Sides::Both
+}
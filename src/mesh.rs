@@ -0,0 +1,739 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::bvh::{Aabb, Bvh};
+use crate::scene::Diagnostic;
+use crate::shapes::{check_point, HitRecord, Shape};
+use crate::texture::ImageTexture;
+use crate::vec3::{Float, Vec3f};
+
+// Watertight ray/triangle test (Woop, Benthin and Wald 2013). The ray is sheared so it
+// runs along +Z and the edge functions are evaluated in 2D; every edge is computed the
+// same way from both of its triangles, so rays cannot slip between neighbours. Returns
+// t and the barycentric weights of v0, v1 and v2.
+pub fn intersect_triangle(
+    orig: &Vec3f,
+    dir: &Vec3f,
+    v0: &Vec3f,
+    v1: &Vec3f,
+    v2: &Vec3f,
+) -> Option<(Float, [Float; 3])> {
+    let kz = (0..3)
+        .max_by(|&a, &b| dir[a].abs().total_cmp(&dir[b].abs()))
+        .unwrap_or(2);
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    // Keep the winding of the sheared triangle
+    if dir[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+    let sx = dir[kx] / dir[kz];
+    let sy = dir[ky] / dir[kz];
+    let sz = 1.0 / dir[kz];
+
+    let a = *v0 - *orig;
+    let b = *v1 - *orig;
+    let c = *v2 - *orig;
+    let (ax, ay) = (a[kx] - sx * a[kz], a[ky] - sy * a[kz]);
+    let (bx, by) = (b[kx] - sx * b[kz], b[ky] - sy * b[kz]);
+    let (cx, cy) = (c[kx] - sx * c[kz], c[ky] - sy * c[kz]);
+
+    let mut u = cx * by - cy * bx;
+    let mut v = ax * cy - ay * cx;
+    let mut w = bx * ay - by * ax;
+    // An edge function of exactly zero is ambiguous in Float; settle it in f64
+    if u == 0.0 || v == 0.0 || w == 0.0 {
+        let edge = |px: Float, py: Float, qx: Float, qy: Float| {
+            (px as f64 * qy as f64 - py as f64 * qx as f64) as Float
+        };
+        u = edge(cx, cy, bx, by);
+        v = edge(ax, ay, cx, cy);
+        w = edge(bx, by, ax, ay);
+    }
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    let t_scaled = u * (sz * a[kz]) + v * (sz * b[kz]) + w * (sz * c[kz]);
+    if (det < 0.0 && t_scaled >= 0.0) || (det > 0.0 && t_scaled <= 0.0) {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    Some((t_scaled * inv_det, [u * inv_det, v * inv_det, w * inv_det]))
+}
+
+pub struct Triangle {
+    vertices: [Vec3f; 3],
+}
+
+impl Triangle {
+    pub fn new(v0: Vec3f, v1: Vec3f, v2: Vec3f) -> Triangle {
+        Triangle {
+            vertices: [v0, v1, v2],
+        }
+    }
+
+    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<Float> {
+        let [v0, v1, v2] = &self.vertices;
+        intersect_triangle(orig, dir, v0, v1, v2).map(|(t, _)| t)
+    }
+}
+
+impl Shape for Triangle {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        let t = self.ray_intersect(orig, dir)?;
+        let [v0, v1, v2] = self.vertices;
+        let normal = (v1 - v0)
+            .cross(&(v2 - v0))
+            .normalized()
+            .unwrap_or(Vec3f(0.0, 1.0, 0.0));
+        Some(HitRecord {
+            t,
+            point: *orig + *dir * t,
+            normal,
+            uv: None,
+            tangent: (v1 - v0).normalized(),
+            color: None,
+            front_face: normal.dot(dir) < 0.0,
+        })
+    }
+
+    fn bounds(&self) -> Aabb {
+        let [v0, v1, v2] = self.vertices;
+        Aabb::new(v0.min(&v1).min(&v2), v0.max(&v1).max(&v2))
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let [v0, v1, v2] = self.vertices;
+        let mut issues: Vec<Diagnostic> = ["first", "second", "third"]
+            .iter()
+            .zip(&self.vertices)
+            .flat_map(|(name, v)| check_point(&format!("{} vertex", name), v))
+            .collect();
+        if issues.is_empty() && (v1 - v0).cross(&(v2 - v0)).length() == 0.0 {
+            issues.push(Diagnostic::error("triangle has zero area".to_string()));
+        }
+        issues
+    }
+}
+
+// Indexed triangles sharing one vertex list, with a BVH over the faces
+pub struct TriangleMesh {
+    vertices: Vec<Vec3f>,
+    // Per-vertex shading normals, interpolated across each face
+    normals: Option<Vec<Vec3f>>,
+    // Per-vertex texture coordinates, interpolated the same way
+    uvs: Option<Vec<(Float, Float)>>,
+    faces: Vec<[usize; 3]>,
+    bvh: Bvh,
+}
+
+impl TriangleMesh {
+    pub fn new(vertices: Vec<Vec3f>, faces: Vec<[usize; 3]>) -> TriangleMesh {
+        TriangleMesh::build(vertices, None, faces)
+    }
+
+    pub fn with_normals(
+        vertices: Vec<Vec3f>,
+        normals: Vec<Vec3f>,
+        faces: Vec<[usize; 3]>,
+    ) -> TriangleMesh {
+        assert_eq!(vertices.len(), normals.len(), "every vertex needs a normal");
+        TriangleMesh::build(vertices, Some(normals), faces)
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<(Float, Float)>) -> TriangleMesh {
+        assert_eq!(self.vertices.len(), uvs.len(), "every vertex needs a uv");
+        self.uvs = Some(uvs);
+        self
+    }
+
+    fn build(
+        vertices: Vec<Vec3f>,
+        normals: Option<Vec<Vec3f>>,
+        faces: Vec<[usize; 3]>,
+    ) -> TriangleMesh {
+        assert!(
+            faces.iter().flatten().all(|&i| i < vertices.len()),
+            "face index out of range"
+        );
+        let bounds: Vec<Aabb> = faces
+            .iter()
+            .map(|&[a, b, c]| {
+                let (v0, v1, v2) = (vertices[a], vertices[b], vertices[c]);
+                Aabb::new(v0.min(&v1).min(&v2), v0.max(&v1).max(&v2))
+            })
+            .collect();
+        let bvh = Bvh::build(&bounds);
+        TriangleMesh {
+            vertices,
+            normals,
+            uvs: None,
+            faces,
+            bvh,
+        }
+    }
+
+    pub fn vertices(&self) -> &[Vec3f] {
+        &self.vertices
+    }
+
+    pub fn normals(&self) -> Option<&[Vec3f]> {
+        self.normals.as_deref()
+    }
+
+    pub fn uvs(&self) -> Option<&[(Float, Float)]> {
+        self.uvs.as_deref()
+    }
+
+    pub fn faces(&self) -> &[[usize; 3]] {
+        &self.faces
+    }
+
+    pub fn len(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.faces.is_empty()
+    }
+
+    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<Float> {
+        self.intersect(orig, dir).map(|(_, t, _)| t)
+    }
+
+    // The mesh's own normals, or else each vertex's faces' normals averaged by area
+    pub fn vertex_normals(&self) -> Vec<Vec3f> {
+        if let Some(normals) = &self.normals {
+            return normals.clone();
+        }
+        let mut normals = vec![Vec3f(0.0, 0.0, 0.0); self.vertices.len()];
+        for &[a, b, c] in &self.faces {
+            let (v0, v1, v2) = (self.vertices[a], self.vertices[b], self.vertices[c]);
+            let weighted = (v1 - v0).cross(&(v2 - v0));
+            for i in [a, b, c] {
+                normals[i] += weighted;
+            }
+        }
+        normals
+            .into_iter()
+            .map(|n| n.normalized().unwrap_or(Vec3f(0.0, 1.0, 0.0)))
+            .collect()
+    }
+
+    // ASCII PLY with the normals and texture coordinates the mesh has, and colors one per
+    // vertex if given, as bytes without the sRGB curve images get
+    pub fn write_ply(&self, path: &Path, colors: Option<&[Vec3f]>) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "ply\nformat ascii 1.0\ncomment written by rusty-rays")?;
+        writeln!(file, "element vertex {}", self.vertices.len())?;
+        writeln!(file, "property float x\nproperty float y\nproperty float z")?;
+        if self.normals.is_some() {
+            writeln!(
+                file,
+                "property float nx\nproperty float ny\nproperty float nz"
+            )?;
+        }
+        if self.uvs.is_some() {
+            writeln!(file, "property float s\nproperty float t")?;
+        }
+        if colors.is_some() {
+            writeln!(
+                file,
+                "property uchar red\nproperty uchar green\nproperty uchar blue"
+            )?;
+        }
+        writeln!(file, "element face {}", self.faces.len())?;
+        writeln!(file, "property list uchar int vertex_indices\nend_header")?;
+        let byte = |c: Float| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        for (i, v) in self.vertices.iter().enumerate() {
+            write!(file, "{} {} {}", v.0, v.1, v.2)?;
+            if let Some(normals) = &self.normals {
+                let n = normals[i];
+                write!(file, " {} {} {}", n.0, n.1, n.2)?;
+            }
+            if let Some(uvs) = &self.uvs {
+                write!(file, " {} {}", uvs[i].0, uvs[i].1)?;
+            }
+            if let Some(colors) = colors {
+                let c = colors[i];
+                write!(file, " {} {} {}", byte(c.0), byte(c.1), byte(c.2))?;
+            }
+            writeln!(file)?;
+        }
+        for [a, b, c] in &self.faces {
+            writeln!(file, "3 {} {} {}", a, b, c)?;
+        }
+        file.flush()
+    }
+
+    // Wavefront OBJ, with colors one per vertex if given as the r g b after each vertex's
+    // position that most tools read
+    pub fn write_obj(&self, path: &Path, colors: Option<&[Vec3f]>) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "# written by rusty-rays")?;
+        for (i, v) in self.vertices.iter().enumerate() {
+            write!(file, "v {} {} {}", v.0, v.1, v.2)?;
+            if let Some(colors) = colors {
+                let c = colors[i];
+                write!(file, " {} {} {}", c.0, c.1, c.2)?;
+            }
+            writeln!(file)?;
+        }
+        for n in self.normals.iter().flatten() {
+            writeln!(file, "vn {} {} {}", n.0, n.1, n.2)?;
+        }
+        for (u, v) in self.uvs.iter().flatten() {
+            writeln!(file, "vt {} {}", u, v)?;
+        }
+        // Indices count from one, and each vertex's normal and uv share its index
+        let corner = |i: usize| match (self.uvs.is_some(), self.normals.is_some()) {
+            (true, true) => format!("{0}/{0}/{0}", i + 1),
+            (true, false) => format!("{0}/{0}", i + 1),
+            (false, true) => format!("{0}//{0}", i + 1),
+            (false, false) => format!("{}", i + 1),
+        };
+        for &[a, b, c] in &self.faces {
+            writeln!(file, "f {} {} {}", corner(a), corner(b), corner(c))?;
+        }
+        file.flush()
+    }
+
+    // Everything but the lack of normals, which a displaced mesh makes up for
+    fn geometry_diagnostics(&self) -> Vec<Diagnostic> {
+        let mut issues = Vec::new();
+        let non_finite = self
+            .vertices
+            .iter()
+            .filter(|v| !check_point("vertex", v).is_empty())
+            .count();
+        if non_finite > 0 {
+            issues.push(Diagnostic::error(format!(
+                "{} vertices are not finite",
+                non_finite
+            )));
+        }
+        let degenerate = self
+            .faces
+            .iter()
+            .filter(|&&[a, b, c]| {
+                let (v0, v1, v2) = (self.vertices[a], self.vertices[b], self.vertices[c]);
+                (v1 - v0).cross(&(v2 - v0)).length() == 0.0
+            })
+            .count();
+        if degenerate > 0 {
+            issues.push(Diagnostic::warning(format!(
+                "{} of {} faces have zero area",
+                degenerate,
+                self.len()
+            )));
+        }
+        if let Some(normals) = &self.normals {
+            let bad = normals
+                .iter()
+                .filter(|n| !(n.length() > 0.0 && n.length().is_finite()))
+                .count();
+            if bad > 0 {
+                issues.push(Diagnostic::error(format!(
+                    "{} vertex normals are zero or not finite",
+                    bad
+                )));
+            }
+        }
+        issues
+    }
+
+    fn intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<(usize, Float, [Float; 3])> {
+        let mut barycentrics = [0.0; 3];
+        let (face, t) = self.bvh.traverse(orig, dir, |i, t_max| {
+            let [a, b, c] = self.faces[i];
+            let (t, weights) = intersect_triangle(
+                orig,
+                dir,
+                &self.vertices[a],
+                &self.vertices[b],
+                &self.vertices[c],
+            )?;
+            if t >= t_max {
+                return None;
+            }
+            barycentrics = weights;
+            Some(t)
+        })?;
+        Some((face, t, barycentrics))
+    }
+}
+
+impl Shape for TriangleMesh {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        let (face, t, [w0, w1, w2]) = self.intersect(orig, dir)?;
+        let [a, b, c] = self.faces[face];
+        let normal = match &self.normals {
+            Some(normals) => normals[a] * w0 + normals[b] * w1 + normals[c] * w2,
+            None => {
+                (self.vertices[b] - self.vertices[a]).cross(&(self.vertices[c] - self.vertices[a]))
+            }
+        };
+        Some(HitRecord {
+            t,
+            point: *orig + *dir * t,
+            normal: normal.normalized().unwrap_or(Vec3f(0.0, 1.0, 0.0)),
+            uv: self.uvs.as_ref().map(|uvs| {
+                let ((ua, va), (ub, vb), (uc, vc)) = (uvs[a], uvs[b], uvs[c]);
+                (ua * w0 + ub * w1 + uc * w2, va * w0 + vb * w1 + vc * w2)
+            }),
+            tangent: (self.vertices[b] - self.vertices[a]).normalized(),
+            color: None,
+            front_face: normal.dot(dir) < 0.0,
+        })
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut issues = self.geometry_diagnostics();
+        if self.normals.is_none() {
+            issues.push(Diagnostic::warning(
+                "mesh has no vertex normals, so every face shades flat".to_string(),
+            ));
+        }
+        issues
+    }
+
+    fn mesh(&self) -> Option<TriangleMesh> {
+        let mesh = TriangleMesh::build(
+            self.vertices.clone(),
+            self.normals.clone(),
+            self.faces.clone(),
+        );
+        Some(TriangleMesh {
+            uvs: self.uvs.clone(),
+            ..mesh
+        })
+    }
+}
+
+thread_local! {
+    // Displaced vertices of the face being diced
+    static DISPLACED_GRID: RefCell<Vec<Vec3f>> = const { RefCell::new(Vec::new()) };
+}
+
+// A mesh whose surface is pushed along its smooth normals by a height map. Nothing is
+// stored beyond the base mesh: each face is diced into micro-triangles when a ray
+// reaches its bounds, which are padded by the largest possible displacement.
+pub struct DisplacedMesh {
+    base: TriangleMesh,
+    normals: Vec<Vec3f>,
+    uvs: Vec<(Float, Float)>,
+    height_map: ImageTexture,
+    scale: Float,
+    padding: Float,
+    // Every base face is split into subdivisions^2 micro-triangles
+    pub subdivisions: usize,
+    bvh: Bvh,
+}
+
+impl DisplacedMesh {
+    // Height is the mean of the map's channels times scale; a mesh without normals is
+    // displaced along area-weighted vertex normals
+    pub fn new(
+        base: TriangleMesh,
+        uvs: Vec<(Float, Float)>,
+        height_map: ImageTexture,
+        scale: Float,
+    ) -> DisplacedMesh {
+        assert_eq!(base.vertices.len(), uvs.len(), "every vertex needs a uv");
+        let normals = match &base.normals {
+            Some(normals) => normals
+                .iter()
+                .map(|n| n.normalized().unwrap_or(Vec3f(0.0, 1.0, 0.0)))
+                .collect(),
+            None => vertex_normals(&base.vertices, &base.faces),
+        };
+        let (lo, hi) = height_map.range();
+        let padding = (mean(&lo) * scale).abs().max((mean(&hi) * scale).abs());
+        let pad = Vec3f(padding, padding, padding);
+        let bounds: Vec<Aabb> = base
+            .faces
+            .iter()
+            .map(|&[a, b, c]| {
+                let (v0, v1, v2) = (base.vertices[a], base.vertices[b], base.vertices[c]);
+                Aabb::new(v0.min(&v1).min(&v2) - pad, v0.max(&v1).max(&v2) + pad)
+            })
+            .collect();
+        let bvh = Bvh::build(&bounds);
+        DisplacedMesh {
+            base,
+            normals,
+            uvs,
+            height_map,
+            scale,
+            padding,
+            subdivisions: 16,
+            bvh,
+        }
+    }
+
+    // How far any point can move off the base surface
+    pub fn padding(&self) -> Float {
+        self.padding
+    }
+
+    // Displaced position at barycentric weights (w1, w2) of a base face
+    fn displace(&self, face: usize, w1: Float, w2: Float) -> Vec3f {
+        let [a, b, c] = self.base.faces[face];
+        let w0 = 1.0 - w1 - w2;
+        let v = &self.base.vertices;
+        let point = v[a] * w0 + v[b] * w1 + v[c] * w2;
+        let normal = (self.normals[a] * w0 + self.normals[b] * w1 + self.normals[c] * w2)
+            .normalized()
+            .unwrap_or(Vec3f(0.0, 1.0, 0.0));
+        let u = self.uvs[a].0 * w0 + self.uvs[b].0 * w1 + self.uvs[c].0 * w2;
+        let v = self.uvs[a].1 * w0 + self.uvs[b].1 * w1 + self.uvs[c].1 * w2;
+        point + normal * (mean(&self.height_map.sample_level(u, v, 0.0)) * self.scale)
+    }
+
+    // Closest micro-triangle of one base face closer than t_max, with its geometric normal
+    fn intersect_face(
+        &self,
+        face: usize,
+        orig: &Vec3f,
+        dir: &Vec3f,
+        t_max: Float,
+    ) -> Option<(Float, Vec3f)> {
+        let n = self.subdivisions.max(1);
+        let step = 1.0 / n as Float;
+        // Rows of the barycentric grid, row i holding n - i + 1 points and starting
+        // after the i rows before it
+        let row_start = |i: usize| i * (n + 1) - i * i.saturating_sub(1) / 2;
+        DISPLACED_GRID.with_borrow_mut(|grid| {
+            // Reused across rays so intersection does not allocate once it has grown
+            grid.clear();
+            for i in 0..=n {
+                for j in 0..=n - i {
+                    grid.push(self.displace(face, j as Float * step, i as Float * step));
+                }
+            }
+
+            let mut closest: Option<(Float, Vec3f)> = None;
+            let mut test = |p0: usize, p1: usize, p2: usize| {
+                let (v0, v1, v2) = (&grid[p0], &grid[p1], &grid[p2]);
+                if let Some((t, _)) = intersect_triangle(orig, dir, v0, v1, v2) {
+                    if t < closest.map_or(t_max, |(t, _)| t) {
+                        closest = Some((t, (*v1 - *v0).cross(&(*v2 - *v0))));
+                    }
+                }
+            };
+            for i in 0..n {
+                let (lower, upper) = (row_start(i), row_start(i + 1));
+                for j in 0..n - i {
+                    test(lower + j, lower + j + 1, upper + j);
+                    if j + 1 < n - i {
+                        test(lower + j + 1, upper + j + 1, upper + j);
+                    }
+                }
+            }
+            closest
+        })
+    }
+}
+
+impl Shape for DisplacedMesh {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        let mut normal = Vec3f(0.0, 1.0, 0.0);
+        let (_, t) = self.bvh.traverse(orig, dir, |face, t_max| {
+            let (t, n) = self.intersect_face(face, orig, dir, t_max)?;
+            normal = n;
+            Some(t)
+        })?;
+        Some(HitRecord {
+            t,
+            point: *orig + *dir * t,
+            normal: normal.normalized().unwrap_or(Vec3f(0.0, 1.0, 0.0)),
+            uv: None,
+            tangent: None,
+            color: None,
+            front_face: normal.dot(dir) < 0.0,
+        })
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut issues = self.base.geometry_diagnostics();
+        if !self.scale.is_finite() {
+            issues.push(Diagnostic::error(format!(
+                "displacement scale is not finite: {}",
+                self.scale
+            )));
+        }
+        issues
+    }
+}
+
+fn mean(color: &Vec3f) -> Float {
+    (color.0 + color.1 + color.2) / 3.0
+}
+
+fn vertex_normals(vertices: &[Vec3f], faces: &[[usize; 3]]) -> Vec<Vec3f> {
+    let mut normals = vec![Vec3f(0.0, 0.0, 0.0); vertices.len()];
+    for &[a, b, c] in faces {
+        // Unnormalized, so larger faces weigh more
+        let n = (vertices[b] - vertices[a]).cross(&(vertices[c] - vertices[a]));
+        normals[a] += n;
+        normals[b] += n;
+        normals[c] += n;
+    }
+    normals
+        .iter()
+        .map(|n| n.normalized().unwrap_or(Vec3f(0.0, 1.0, 0.0)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+    use crate::vec3::consts::PI;
+
+    #[test]
+    fn finds_the_hit_and_its_weights() {
+        let (v0, v1, v2) = (
+            Vec3f(0.0, 0.0, -2.0),
+            Vec3f(1.0, 0.0, -2.0),
+            Vec3f(0.0, 1.0, -2.0),
+        );
+        let orig = Vec3f(0.25, 0.5, 0.0);
+        let (t, weights) =
+            intersect_triangle(&orig, &Vec3f(0.0, 0.0, -1.0), &v0, &v1, &v2).unwrap();
+        assert!((t - 2.0).abs() < 1e-6);
+        for (weight, expected) in weights.iter().zip([0.25, 0.25, 0.5]) {
+            assert!((weight - expected).abs() < 1e-6, "{:?}", weights);
+        }
+        // Either winding, and from behind
+        assert!(intersect_triangle(&orig, &Vec3f(0.0, 0.0, -1.0), &v0, &v2, &v1).is_some());
+        let below = Vec3f(0.25, 0.5, -4.0);
+        assert!(intersect_triangle(&below, &Vec3f(0.0, 0.0, 1.0), &v0, &v1, &v2).is_some());
+        // Pointing away, passing by, or running along the plane
+        assert!(intersect_triangle(&orig, &Vec3f(0.0, 0.0, 1.0), &v0, &v1, &v2).is_none());
+        let outside = Vec3f(0.75, 0.75, 0.0);
+        assert!(intersect_triangle(&outside, &Vec3f(0.0, 0.0, -1.0), &v0, &v1, &v2).is_none());
+        let level = Vec3f(-1.0, 0.25, -2.0);
+        assert!(intersect_triangle(&level, &Vec3f(1.0, 0.0, 0.0), &v0, &v1, &v2).is_none());
+        // Degenerate triangles are never hit
+        assert!(intersect_triangle(&orig, &Vec3f(0.0, 0.0, -1.0), &v0, &v1, &v1).is_none());
+    }
+
+    #[test]
+    fn rays_cannot_slip_between_neighbours() {
+        // A fan of triangles round a shared center, tilted so nothing lines up with an axis
+        let center = Vec3f(0.3, -0.2, -3.0);
+        let rim: Vec<Vec3f> = (0..7)
+            .map(|i| {
+                let angle = i as Float * 2.0 * PI / 7.0;
+                center
+                    + Vec3f(
+                        angle.cos(),
+                        angle.sin(),
+                        0.3 * angle.sin() - 0.2 * angle.cos(),
+                    )
+            })
+            .collect();
+        let mut vertices = vec![center];
+        vertices.extend(&rim);
+        let faces: Vec<[usize; 3]> = (0..7).map(|i| [0, 1 + i, 1 + (i + 1) % 7]).collect();
+        let mesh = TriangleMesh::new(vertices, faces);
+
+        let mut rng = Rng::new(11);
+        for i in 0..20000 {
+            let orig = Vec3f(
+                rng.next_float() * 4.0 - 2.0,
+                rng.next_float() * 4.0 - 2.0,
+                rng.next_float() * 2.0,
+            );
+            // Aimed at the shared center, or at a point along a shared edge
+            let edge = rim[i % 7];
+            let target = center + (edge - center) * rng.next_float();
+            let target = if i % 3 == 0 { center } else { target };
+            let dir = (target - orig).normalized().unwrap();
+            assert!(
+                mesh.ray_intersect(&orig, &dir).is_some(),
+                "{:?} towards {:?} slipped through",
+                orig,
+                target
+            );
+        }
+    }
+
+    // A unit square on the ground, its uvs following x and z
+    fn square() -> (TriangleMesh, Vec<(Float, Float)>) {
+        let vertices = vec![
+            Vec3f(0.0, 0.0, 0.0),
+            Vec3f(1.0, 0.0, 0.0),
+            Vec3f(1.0, 0.0, 1.0),
+            Vec3f(0.0, 0.0, 1.0),
+        ];
+        let uvs = vertices.iter().map(|v| (v.0, v.2)).collect();
+        (TriangleMesh::new(vertices, vec![[0, 2, 1], [0, 3, 2]]), uvs)
+    }
+
+    #[test]
+    fn displaces_along_the_normals_by_the_height_map() {
+        let down = Vec3f(0.0, -1.0, 0.0);
+        let (base, uvs) = square();
+        let flat = ImageTexture::new(1, 1, vec![Vec3f(0.5, 0.5, 0.5)]);
+        let mesh = DisplacedMesh::new(base, uvs, flat, 2.0);
+        assert_eq!(mesh.padding(), 1.0);
+        assert!(mesh.bounds().max.1 >= 1.0 && mesh.bounds().min.1 <= -1.0);
+        let hit = mesh.hit(&Vec3f(0.3, 5.0, 0.6), &down).unwrap();
+        assert!((hit.point.1 - 1.0).abs() < 1e-4, "{:?}", hit.point);
+        assert!((hit.normal - Vec3f(0.0, 1.0, 0.0)).length() < 1e-4);
+        assert!(hit.front_face);
+        assert!(mesh.hit(&Vec3f(1.5, 5.0, 0.5), &down).is_none());
+
+        // Heights rising with u, from 0 at the left to 1 at the right
+        let ramp: Vec<Vec3f> = (0..64)
+            .map(|i| Vec3f(1.0, 1.0, 1.0) * ((i % 8) as Float / 7.0))
+            .collect();
+        let (base, uvs) = square();
+        let mut mesh = DisplacedMesh::new(base, uvs, ImageTexture::new(8, 8, ramp), 1.0);
+        mesh.subdivisions = 32;
+        let heights: Vec<Float> = [0.2, 0.4, 0.6, 0.8]
+            .iter()
+            .map(|&x| mesh.hit(&Vec3f(x, 5.0, 0.5), &down).unwrap().point.1)
+            .collect();
+        assert!(
+            heights.windows(2).all(|pair| pair[0] < pair[1]),
+            "{:?}",
+            heights
+        );
+        // The slope tilts the surface's normal back towards -x
+        let hit = mesh.hit(&Vec3f(0.5, 5.0, 0.5), &down).unwrap();
+        assert!(
+            hit.normal.0 < -0.1 && hit.normal.1 > 0.0,
+            "{:?}",
+            hit.normal
+        );
+
+        // No displacement leaves the base surface where it was
+        let (base, uvs) = square();
+        let mesh = DisplacedMesh::new(
+            base,
+            uvs,
+            ImageTexture::new(1, 1, vec![Vec3f(1.0, 1.0, 1.0)]),
+            0.0,
+        );
+        let hit = mesh.hit(&Vec3f(0.7, 2.0, 0.2), &down).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,550 @@
+// NanoVDB files (.nvdb) as density grids for volumes. NanoVDB flattens a VDB tree into
+// one buffer: a root table of tiles, each a constant or an upper node of 32^3 children;
+// those are constants or lower nodes of 16^3; and those constants or leaves of 8^3
+// voxels. Float grids in the layout of NanoVDB 32, what current OpenVDB writes, are
+// read from files written without compression. The tree's bounding box of active voxels
+// becomes the grid's extent and its leaves the grid's bricks, so the grid is as sparse as
+// the file, and the index-to-world map places the box in the scene, rotations aside.
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use crate::bvh::Aabb;
+use crate::vec3::{Float, Vec3f};
+use crate::volume::DensityGrid;
+
+// Sizes in bytes of the fixed parts of a file and of the grid buffer in it
+const FILE_HEADER: usize = 16;
+const FILE_META: usize = 176;
+const GRID_DATA: usize = 672;
+const ROOT_DATA: usize = 64;
+const ROOT_TILE: usize = 32;
+// Where each node's table of children and constants, or a leaf's values, starts
+const UPPER_TABLE: usize = 8256;
+const LOWER_TABLE: usize = 1088;
+const LEAF_VALUES: usize = 96;
+const UPPER_SIZE: usize = UPPER_TABLE + 8 * 32 * 32 * 32;
+const LOWER_SIZE: usize = LOWER_TABLE + 8 * 16 * 16 * 16;
+const LEAF_SIZE: usize = LEAF_VALUES + 4 * 8 * 8 * 8;
+
+const FLOAT_GRID: u32 = 1;
+// Most 8^3 bricks a grid may span, so a file claiming a vast box cannot exhaust memory
+const MAX_BRICKS: usize = 1 << 24;
+
+pub struct NanoVdbGrid {
+    pub name: String,
+    pub grid: DensityGrid,
+    // Where the grid's voxels lie in world space
+    pub bounds: Aabb,
+}
+
+pub fn load_nanovdb(path: &Path, name: Option<&str>) -> io::Result<NanoVdbGrid> {
+    read_nanovdb(BufReader::new(File::open(path)?), name)
+}
+
+// The grid called name, or without one the file's first float grid
+pub fn read_nanovdb<R: Read>(mut reader: R, name: Option<&str>) -> io::Result<NanoVdbGrid> {
+    let header = read_bytes(&mut reader, FILE_HEADER as u64)?;
+    let header = Bytes(&header);
+    if !matches!(header.take(0, 8)?, b"NanoVDB0" | b"NanoVDB2") {
+        return Err(invalid("not a NanoVDB file".to_string()));
+    }
+    supported(header.u32(8)?)?;
+    let codec = header.u16(14)?;
+
+    for _ in 0..header.u16(12)? {
+        let meta = read_bytes(&mut reader, FILE_META as u64)?;
+        let meta = Bytes(&meta);
+        let file_size = meta.u64(8)?;
+        let grid_type = meta.u32(32)?;
+        let raw_name = read_bytes(&mut reader, meta.u32(136)? as u64)?;
+        let end = raw_name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(raw_name.len());
+        let grid_name = String::from_utf8_lossy(&raw_name[..end]).into_owned();
+
+        let wanted = match name {
+            Some(name) => grid_name == name,
+            None => grid_type == FLOAT_GRID,
+        };
+        if !wanted {
+            io::copy(&mut reader.by_ref().take(file_size), &mut io::sink())?;
+            continue;
+        }
+        if grid_type != FLOAT_GRID {
+            return Err(invalid(format!(
+                "NanoVDB grid {} holds type {} values, not floats",
+                grid_name, grid_type
+            )));
+        }
+        if codec != 0 || meta.u16(168)? != 0 {
+            return Err(invalid(format!(
+                "NanoVDB grid {} is compressed; write it uncompressed to load it",
+                grid_name
+            )));
+        }
+        let data = read_bytes(&mut reader, file_size)?;
+        let (grid, bounds) = parse_grid(Bytes(&data))?;
+        return Ok(NanoVdbGrid {
+            name: grid_name,
+            grid,
+            bounds,
+        });
+    }
+    Err(invalid(match name {
+        Some(name) => format!("the NanoVDB file has no grid named {}", name),
+        None => "the NanoVDB file has no float grid".to_string(),
+    }))
+}
+
+fn parse_grid(data: Bytes) -> io::Result<(DensityGrid, Aabb)> {
+    if !matches!(data.take(0, 8)?, b"NanoVDB0" | b"NanoVDB1") {
+        return Err(invalid("NanoVDB grid has a bad magic number".to_string()));
+    }
+    supported(data.u32(16)?)?;
+    if data.u32(636)? != FLOAT_GRID {
+        return Err(invalid("NanoVDB grid does not hold floats".to_string()));
+    }
+    let root = offset(GRID_DATA, data.i64(GRID_DATA + 24)?)?;
+    let min = [data.i32(root)?, data.i32(root + 4)?, data.i32(root + 8)?];
+    let max = [
+        data.i32(root + 12)?,
+        data.i32(root + 16)?,
+        data.i32(root + 20)?,
+    ];
+    if (0..3).any(|a| min[a] > max[a]) {
+        return Err(invalid("NanoVDB grid has no active voxels".to_string()));
+    }
+
+    // Started on a leaf boundary, so every leaf is exactly one brick
+    let origin = min.map(|m| (m & !7) as i64);
+    let resolution = [0, 1, 2].map(|a| (max[a] as i64 + 1 - origin[a]) as usize);
+    let bricks_per_axis = resolution.map(|r| r.div_ceil(8));
+    let bricks = bricks_per_axis
+        .iter()
+        .try_fold(1usize, |n, &b| n.checked_mul(b))
+        .filter(|&n| n <= MAX_BRICKS)
+        .ok_or_else(|| {
+            invalid(format!(
+                "NanoVDB grid spans {}x{}x{} voxels, too many to load",
+                resolution[0], resolution[1], resolution[2]
+            ))
+        })?;
+
+    let mut tree = Tree {
+        data,
+        origin,
+        resolution,
+        bricks_per_axis,
+        bricks: vec![None; bricks],
+        read: 0,
+    };
+    let tiles = data.u32(root + 24)? as usize;
+    data.take(root + ROOT_DATA, tiles.saturating_mul(ROOT_TILE))?;
+    for i in 0..tiles {
+        let tile = root + ROOT_DATA + i * ROOT_TILE;
+        let key = data.u64(tile)?;
+        let at =
+            [42, 21, 0].map(|shift| ((((key >> shift) & 0x1f_ffff) as u32) << 12) as i32 as i64);
+        match data.i64(tile + 8)? {
+            0 => tree.fill(at, 4096, data.f32(tile + 20)?),
+            child => tree.internal(offset(root, child)?, at, 5)?,
+        }
+    }
+
+    // Voxel centers sit on whole index coordinates, so the box reaches half a voxel past
+    let matrix: Vec<f64> = (0..9)
+        .map(|i| data.f64(384 + 8 * i))
+        .collect::<io::Result<_>>()?;
+    let translation: Vec<f64> = (0..3)
+        .map(|i| data.f64(528 + 8 * i))
+        .collect::<io::Result<_>>()?;
+    let (mut low, mut high) = ([f64::MAX; 3], [f64::MIN; 3]);
+    for corner in 0..8 {
+        let index: [f64; 3] = [0, 1, 2].map(|a| {
+            let side = if corner >> a & 1 == 0 {
+                0
+            } else {
+                resolution[a]
+            };
+            (origin[a] + side as i64) as f64 - 0.5
+        });
+        for a in 0..3 {
+            let world = (0..3).map(|b| matrix[3 * a + b] * index[b]).sum::<f64>() + translation[a];
+            low[a] = low[a].min(world);
+            high[a] = high[a].max(world);
+        }
+    }
+    if (0..3).any(|a| !(low[a].is_finite() && high[a].is_finite() && low[a] < high[a])) {
+        return Err(invalid(
+            "NanoVDB grid has no usable index-to-world map".to_string(),
+        ));
+    }
+    let bounds = Aabb {
+        min: Vec3f(low[0] as Float, low[1] as Float, low[2] as Float),
+        max: Vec3f(high[0] as Float, high[1] as Float, high[2] as Float),
+    };
+    Ok((DensityGrid::from_bricks(resolution, 8, tree.bricks), bounds))
+}
+
+// The tree being copied into bricks of the grid's box, origin its first voxel
+struct Tree<'a> {
+    data: Bytes<'a>,
+    origin: [i64; 3],
+    resolution: [usize; 3],
+    bricks_per_axis: [usize; 3],
+    bricks: Vec<Option<Box<[f32]>>>,
+    // Bytes of nodes read so far. Nodes never overlap in a sound file, so this can only
+    // pass the buffer's length when children are shared, which would otherwise let a small
+    // file be walked again and again
+    read: usize,
+}
+
+impl Tree<'_> {
+    fn visit(&mut self, size: usize) -> io::Result<()> {
+        self.read += size;
+        if self.read > self.data.0.len() {
+            return Err(invalid("NanoVDB grid's nodes overlap".to_string()));
+        }
+        Ok(())
+    }
+
+    // Whether the cube of side size at corner reaches into the grid's box
+    fn overlaps(&self, corner: [i64; 3], size: i64) -> bool {
+        (0..3).all(|a| {
+            corner[a] + size > self.origin[a]
+                && corner[a] < self.origin[a] + self.resolution[a] as i64
+        })
+    }
+
+    // The brick for the voxel at local grid coordinates, made empty if there was none
+    fn brick(&mut self, local: [usize; 3]) -> &mut [f32] {
+        let [bx, by, bz] = local.map(|l| l / 8);
+        let index = (bz * self.bricks_per_axis[1] + by) * self.bricks_per_axis[0] + bx;
+        self.bricks[index].get_or_insert_with(|| vec![0.0; 512].into_boxed_slice())
+    }
+
+    // An upper node, log2 32 children a side, or a lower node, 16
+    fn internal(&mut self, node: usize, corner: [i64; 3], log2: usize) -> io::Result<()> {
+        self.visit(if log2 == 5 { UPPER_SIZE } else { LOWER_SIZE })?;
+        let table = if log2 == 5 { UPPER_TABLE } else { LOWER_TABLE };
+        let child_masks = node + 32 + (1 << (3 * log2)) / 8;
+        let child_size = if log2 == 5 { 128 } else { 8 };
+        let side = 1 << log2;
+        for n in 0..1usize << (3 * log2) {
+            let at = [
+                corner[0] + ((n >> (2 * log2)) & (side - 1)) as i64 * child_size,
+                corner[1] + ((n >> log2) & (side - 1)) as i64 * child_size,
+                corner[2] + (n & (side - 1)) as i64 * child_size,
+            ];
+            if !self.overlaps(at, child_size) {
+                continue;
+            }
+            let entry = node + table + 8 * n;
+            let word = self.data.u64(child_masks + 8 * (n / 64))?;
+            if word >> (n % 64) & 1 == 0 {
+                self.fill(at, child_size, self.data.f32(entry)?);
+            } else if log2 == 5 {
+                self.internal(offset(node, self.data.i64(entry)?)?, at, 4)?;
+            } else {
+                self.leaf(offset(node, self.data.i64(entry)?)?, at)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Leaf values run z fastest, the grid's bricks x fastest
+    fn leaf(&mut self, node: usize, corner: [i64; 3]) -> io::Result<()> {
+        self.visit(LEAF_SIZE)?;
+        let values = self.data.take(node + LEAF_VALUES, 4 * 512)?;
+        let local = [0, 1, 2].map(|a| (corner[a] - self.origin[a]) as usize);
+        let brick = self.brick(local);
+        for (n, value) in values.chunks_exact(4).enumerate() {
+            let (x, y, z) = (n >> 6, (n >> 3) & 7, n & 7);
+            brick[(z * 8 + y) * 8 + x] = f32::from_le_bytes(value.try_into().unwrap());
+        }
+        Ok(())
+    }
+
+    // A constant over the cube of side size at corner, as far as it is inside the box
+    fn fill(&mut self, corner: [i64; 3], size: i64, value: f32) {
+        if value == 0.0 {
+            return;
+        }
+        let [low, high] = [corner, corner.map(|c| c + size)];
+        let [xs, ys, zs] = [0, 1, 2].map(|a| {
+            let start = (low[a] - self.origin[a]).max(0) as usize;
+            let end = ((high[a] - self.origin[a]).max(0) as usize).min(self.resolution[a]);
+            start..end
+        });
+        for z in zs {
+            for y in ys.clone() {
+                for x in xs.clone() {
+                    self.brick([x, y, z])[((z % 8) * 8 + y % 8) * 8 + x % 8] = value;
+                }
+            }
+        }
+    }
+}
+
+fn supported(version: u32) -> io::Result<()> {
+    let major = version >> 21;
+    if major != 32 {
+        return Err(invalid(format!(
+            "NanoVDB version {}.{}.{} is not supported; only 32.x is",
+            major,
+            (version >> 10) & 0x7ff,
+            version & 0x3ff
+        )));
+    }
+    Ok(())
+}
+
+// Where a node's signed byte offset from base leads
+fn offset(base: usize, offset: i64) -> io::Result<usize> {
+    usize::try_from(base as i64 + offset)
+        .ok()
+        .filter(|_| offset != 0)
+        .ok_or_else(|| invalid("NanoVDB grid has a bad node offset".to_string()))
+}
+
+// count bytes from reader, read as they come so a bad size cannot allocate ahead of them
+fn read_bytes<R: Read>(reader: &mut R, count: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(count).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < count {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "NanoVDB file ended early",
+        ));
+    }
+    Ok(bytes)
+}
+
+#[derive(Clone, Copy)]
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Bytes<'a> {
+    fn take(&self, at: usize, count: usize) -> io::Result<&'a [u8]> {
+        at.checked_add(count)
+            .and_then(|end| self.0.get(at..end))
+            .ok_or_else(|| invalid("NanoVDB grid ended early".to_string()))
+    }
+
+    fn array<const N: usize>(&self, at: usize) -> io::Result<[u8; N]> {
+        Ok(self.take(at, N)?.try_into().unwrap())
+    }
+
+    fn u16(&self, at: usize) -> io::Result<u16> {
+        self.array(at).map(u16::from_le_bytes)
+    }
+
+    fn u32(&self, at: usize) -> io::Result<u32> {
+        self.array(at).map(u32::from_le_bytes)
+    }
+
+    fn i32(&self, at: usize) -> io::Result<i32> {
+        self.array(at).map(i32::from_le_bytes)
+    }
+
+    fn u64(&self, at: usize) -> io::Result<u64> {
+        self.array(at).map(u64::from_le_bytes)
+    }
+
+    fn i64(&self, at: usize) -> io::Result<i64> {
+        self.array(at).map(i64::from_le_bytes)
+    }
+
+    fn f32(&self, at: usize) -> io::Result<f32> {
+        self.array(at).map(f32::from_le_bytes)
+    }
+
+    fn f64(&self, at: usize) -> io::Result<f64> {
+        self.array(at).map(f64::from_le_bytes)
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put(buffer: &mut [u8], at: usize, bytes: &[u8]) {
+        buffer[at..at + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn set_child(buffer: &mut [u8], masks: usize, n: usize, offset: i64, entry: usize) {
+        buffer[masks + n / 8] |= 1 << (n % 8);
+        put(buffer, entry, &offset.to_le_bytes());
+    }
+
+    // A grid of voxels from (-8, 0, 0) to (-1, 7, 15) behind one root tile, an upper and a
+    // lower node: a leaf holding 0.75 at (-7, 2, 3), and a constant 0.5 from z = 8 on. A
+    // second root tile of 2.0 lies outside the box. Voxels are half a unit, moved by (1, 2, 3)
+    fn grid() -> Vec<u8> {
+        let tree = GRID_DATA;
+        let root = tree + 64;
+        let upper = root + ROOT_DATA + 2 * ROOT_TILE;
+        let lower = upper + UPPER_SIZE;
+        let leaf = lower + LOWER_SIZE;
+        let mut buffer = vec![0; leaf + LEAF_SIZE];
+        put(&mut buffer, 0, b"NanoVDB0");
+        put(&mut buffer, 16, &(32u32 << 21 | 6 << 10).to_le_bytes());
+        put(&mut buffer, 636, &FLOAT_GRID.to_le_bytes());
+        for (i, value) in [
+            0.5f64, 0.0, 0.0, 0.0, 0.5, 0.0, 0.0, 0.0, 0.5, 1.0, 2.0, 3.0,
+        ]
+        .iter()
+        .enumerate()
+        {
+            let at = if i < 9 {
+                384 + 8 * i
+            } else {
+                528 + 8 * (i - 9)
+            };
+            put(&mut buffer, at, &value.to_le_bytes());
+        }
+        put(&mut buffer, tree + 24, &64i64.to_le_bytes());
+
+        for (i, value) in [-8, 0, 0, -1, 7, 15].iter().enumerate() {
+            put(&mut buffer, root + 4 * i, &(*value as i32).to_le_bytes());
+        }
+        put(&mut buffer, root + 24, &2u32.to_le_bytes());
+        let tile = root + ROOT_DATA;
+        put(&mut buffer, tile, &(0xf_ffffu64 << 42).to_le_bytes());
+        put(
+            &mut buffer,
+            tile + 8,
+            &((upper - root) as i64).to_le_bytes(),
+        );
+        put(&mut buffer, tile + ROOT_TILE, &(1u64 << 42).to_le_bytes());
+        put(&mut buffer, tile + ROOT_TILE + 20, &2.0f32.to_le_bytes());
+
+        // The lower node at x = -128 is the upper's last slot along x
+        let n = 31 << 10;
+        let entry = upper + UPPER_TABLE + 8 * n;
+        set_child(
+            &mut buffer,
+            upper + 32 + 4096,
+            n,
+            (lower - upper) as i64,
+            entry,
+        );
+        let n = 15 << 8;
+        let entry = lower + LOWER_TABLE + 8 * n;
+        set_child(
+            &mut buffer,
+            lower + 32 + 512,
+            n,
+            (leaf - lower) as i64,
+            entry,
+        );
+        put(&mut buffer, entry + 8, &0.5f32.to_le_bytes());
+        put(
+            &mut buffer,
+            leaf + LEAF_VALUES + 4 * (1 << 6 | 2 << 3 | 3),
+            &0.75f32.to_le_bytes(),
+        );
+        buffer
+    }
+
+    fn file(grids: &[(&str, u32, &[u8])]) -> Vec<u8> {
+        let mut file = Vec::new();
+        file.extend_from_slice(b"NanoVDB2");
+        file.extend_from_slice(&(32u32 << 21 | 6 << 10).to_le_bytes());
+        file.extend_from_slice(&(grids.len() as u16).to_le_bytes());
+        file.extend_from_slice(&0u16.to_le_bytes());
+        for (name, grid_type, data) in grids {
+            let mut meta = vec![0; FILE_META];
+            put(&mut meta, 0, &(data.len() as u64).to_le_bytes());
+            put(&mut meta, 8, &(data.len() as u64).to_le_bytes());
+            put(&mut meta, 32, &grid_type.to_le_bytes());
+            put(&mut meta, 136, &(name.len() as u32 + 1).to_le_bytes());
+            file.extend_from_slice(&meta);
+            file.extend_from_slice(name.as_bytes());
+            file.push(0);
+            file.extend_from_slice(data);
+        }
+        file
+    }
+
+    fn error(file: &[u8], name: Option<&str>) -> String {
+        match read_nanovdb(file, name) {
+            Ok(_) => panic!("the grid loaded"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn reads_the_tree_into_bricks() {
+        let grid = grid();
+        let file = file(&[("temperature", 2, &[0; 40]), ("density", FLOAT_GRID, &grid)]);
+        let loaded = read_nanovdb(&file[..], None).unwrap();
+        assert_eq!(loaded.name, "density");
+        let grid = &loaded.grid;
+        assert_eq!(grid.resolution(), [8, 8, 16]);
+        assert_eq!(grid.max_density(), 0.75);
+        for z in 0..16 {
+            for y in 0..8 {
+                for x in 0..8 {
+                    let expected = match (x, y, z) {
+                        (1, 2, 3) => 0.75,
+                        (_, _, 8..) => 0.5,
+                        _ => 0.0,
+                    };
+                    assert_eq!(grid.voxel(x, y, z), expected, "({}, {}, {})", x, y, z);
+                }
+            }
+        }
+        let close = |a: Vec3f, b: Vec3f| (a - b).length() < 1e-9;
+        assert!(close(loaded.bounds.min, Vec3f(-3.25, 1.75, 2.75)));
+        assert!(close(loaded.bounds.max, Vec3f(0.75, 5.75, 10.75)));
+        assert!(read_nanovdb(&file[..], Some("density")).is_ok());
+    }
+
+    #[test]
+    fn refuses_what_it_cannot_read() {
+        let grid = grid();
+        let good = file(&[("temperature", 2, &[0; 40]), ("density", FLOAT_GRID, &grid)]);
+        // Cut short, whether the file or the grid within it
+        for end in (0..good.len()).step_by(61) {
+            assert!(read_nanovdb(&good[..end], None).is_err(), "{} bytes", end);
+        }
+        for end in (0..grid.len()).step_by(61) {
+            let cut = file(&[("density", FLOAT_GRID, &grid[..end])]);
+            assert!(read_nanovdb(&cut[..], None).is_err(), "{} bytes", end);
+        }
+        assert!(error(&good, Some("temperature")).contains("not floats"));
+        assert!(error(&good, Some("smoke")).contains("no grid named smoke"));
+        assert!(error(&file(&[("temperature", 2, &[0; 40])]), None).contains("no float grid"));
+
+        let mut bad = good.clone();
+        bad[7] = b'X';
+        assert!(error(&bad, None).contains("not a NanoVDB file"));
+        let mut old = good.clone();
+        put(&mut old, 8, &(31u32 << 21).to_le_bytes());
+        assert!(error(&old, None).contains("31.0.0 is not supported"));
+        let mut compressed = good.clone();
+        put(&mut compressed, 14, &1u16.to_le_bytes());
+        assert!(error(&compressed, None).contains("compressed"));
+
+        // Both root tiles leading to the same upper node would walk it twice
+        let mut shared = grid.clone();
+        let tile = GRID_DATA + 64 + ROOT_DATA;
+        shared.copy_within(tile..tile + ROOT_TILE, tile + ROOT_TILE);
+        let message = error(&file(&[("density", FLOAT_GRID, &shared)]), None);
+        assert!(message.contains("nodes overlap"), "{}", message);
+        let mut backwards = grid.clone();
+        put(&mut backwards, tile + 8, &(-100_000i64).to_le_bytes());
+        let message = error(&file(&[("density", FLOAT_GRID, &backwards)]), None);
+        assert!(message.contains("bad node offset"), "{}", message);
+        let mut vast = grid;
+        put(&mut vast, GRID_DATA + 64 + 12, &i32::MAX.to_le_bytes());
+        let message = error(&file(&[("density", FLOAT_GRID, &vast)]), None);
+        assert!(message.contains("too many to load"), "{}", message);
+    }
+}
@@ -0,0 +1,78 @@
+// Ambient occlusion baked into a mesh's vertices, for real-time engines to darken creases
+// and contact points with as vertex colors. Each vertex sends rays over the hemisphere
+// about its normal and keeps the fraction that get away, so the bake tracks the mesh's
+// resolution rather than a texture's and needs no UV layout.
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::lightmap::named_mesh;
+use crate::mesh::TriangleMesh;
+use crate::render::{ambient_occlusion, run_workers, RenderSettings};
+use crate::rng::Rng;
+use crate::scene::Scene;
+use crate::vec3::Float;
+
+// Vertices handed to a thread at a time
+const CHUNK: usize = 256;
+
+#[derive(Clone, Copy, Debug)]
+pub struct VertexOcclusion {
+    // Rays sent from each vertex
+    pub samples: u32,
+    // How far away surfaces still shade a vertex; None counts everything in the scene,
+    // which leaves the inside of a room dark all over
+    pub distance: Option<Float>,
+}
+
+impl Default for VertexOcclusion {
+    fn default() -> VertexOcclusion {
+        VertexOcclusion {
+            samples: 64,
+            distance: None,
+        }
+    }
+}
+
+impl VertexOcclusion {
+    // The mesh of the object called name, in world space, with how open each of its
+    // vertices is from 0 to 1; the whole scene occludes, the mesh itself included, and
+    // settings give the seed and the threads used
+    pub fn bake(
+        &self,
+        scene: &Scene,
+        name: &str,
+        settings: &RenderSettings,
+    ) -> io::Result<(TriangleMesh, Vec<Float>)> {
+        let (_, mesh) = named_mesh(scene, name)?;
+        let normals = mesh.vertex_normals();
+        let vertices = mesh.vertices();
+        let distance = self.distance.unwrap_or(Float::INFINITY);
+        let chunks = vertices.len().div_ceil(CHUNK);
+        let baked = Mutex::new(vec![0.0; vertices.len()]);
+        let next_chunk = AtomicUsize::new(0);
+        let threads = settings
+            .threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+            .clamp(1, chunks.max(1));
+        run_workers(threads, None, || loop {
+            let chunk = next_chunk.fetch_add(1, Ordering::Relaxed);
+            if chunk >= chunks {
+                break;
+            }
+            let range = chunk * CHUNK..((chunk + 1) * CHUNK).min(vertices.len());
+            let open: Vec<Float> = range
+                .clone()
+                .map(|i| {
+                    let mut rng = Rng::for_stream(settings.seed, i as u64);
+                    let n = &normals[i];
+                    ambient_occlusion(scene, &vertices[i], n, self.samples, distance, &mut rng)
+                })
+                .collect();
+            baked.lock().unwrap()[range].copy_from_slice(&open);
+        });
+        let baked = baked.into_inner().unwrap();
+        Ok((mesh, baked))
+    }
+}
@@ -0,0 +1,264 @@
+// Orthonormal bases and the warps that turn pairs of uniform numbers in [0, 1) into
+// directions and points. Local directions have z along the basis normal.
+use crate::vec3::{consts::PI, Float, Vec3f};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub tangent: Vec3f,
+    pub bitangent: Vec3f,
+    pub normal: Vec3f,
+}
+
+impl Onb {
+    // Frisvad's construction as revised by Duff et al.; n must be unit length
+    pub fn from_normal(n: &Vec3f) -> Onb {
+        let sign = if n.2 >= 0.0 { 1.0 } else { -1.0 };
+        let a = -1.0 / (sign + n.2);
+        let b = n.0 * n.1 * a;
+        Onb {
+            tangent: Vec3f(1.0 + sign * n.0 * n.0 * a, sign * b, -sign * n.0),
+            bitangent: Vec3f(b, sign + n.1 * n.1 * a, -n.1),
+            normal: *n,
+        }
+    }
+
+    pub fn to_world(&self, local: &Vec3f) -> Vec3f {
+        self.tangent * local.0 + self.bitangent * local.1 + self.normal * local.2
+    }
+
+    pub fn to_local(&self, v: &Vec3f) -> Vec3f {
+        Vec3f(
+            v.dot(&self.tangent),
+            v.dot(&self.bitangent),
+            v.dot(&self.normal),
+        )
+    }
+}
+
+// Point on the unit disk by radius and angle; simple, but squashes strata near the rim
+pub fn uniform_disk(u1: Float, u2: Float) -> (Float, Float) {
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    (r * phi.cos(), r * phi.sin())
+}
+
+// Shirley and Chiu's mapping of the square onto the unit disk, which keeps neighbouring
+// samples together and so preserves stratification, e.g. for lens samples
+pub fn concentric_disk(u1: Float, u2: Float) -> (Float, Float) {
+    let (a, b) = (2.0 * u1 - 1.0, 2.0 * u2 - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (r, phi) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    (r * phi.cos(), r * phi.sin())
+}
+
+// Local direction with density cos(theta) / pi, by lifting a disk sample (Malley)
+pub fn cosine_hemisphere(u1: Float, u2: Float) -> Vec3f {
+    let (x, y) = concentric_disk(u1, u2);
+    Vec3f(x, y, (1.0 - x * x - y * y).max(0.0).sqrt())
+}
+
+pub fn cosine_hemisphere_pdf(cos_theta: Float) -> Float {
+    cos_theta.max(0.0) / PI
+}
+
+pub fn uniform_hemisphere(u1: Float, u2: Float) -> Vec3f {
+    let z = u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3f(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub const UNIFORM_HEMISPHERE_PDF: Float = 1.0 / (2.0 * PI);
+
+pub fn uniform_sphere(u1: Float, u2: Float) -> Vec3f {
+    let z = 1.0 - 2.0 * u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3f(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub const UNIFORM_SPHERE_PDF: Float = 1.0 / (4.0 * PI);
+
+// Barycentric weights (b0, b1) of an area-uniform point; the third is 1 - b0 - b1
+pub fn uniform_triangle(u1: Float, u2: Float) -> (Float, Float) {
+    let su = u1.sqrt();
+    (1.0 - su, u2 * su)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    const SAMPLES: usize = 100_000;
+
+    fn pairs(seed: u64) -> impl Iterator<Item = (Float, Float)> {
+        let mut rng = Rng::new(seed);
+        (0..SAMPLES).map(move |_| (rng.next_float(), rng.next_float()))
+    }
+
+    // Pearson's chi-squared statistic for values that should be uniform in [0, 1). With
+    // 16 bins, 50 is far beyond the 99.99th percentile of the distribution (about 40).
+    fn assert_uniform(values: impl Iterator<Item = Float>, what: &str) {
+        const BINS: usize = 16;
+        let mut counts = [0usize; BINS];
+        let mut total = 0;
+        for v in values {
+            assert!((0.0..=1.0).contains(&v), "{} out of range: {}", what, v);
+            counts[((v * BINS as Float) as usize).min(BINS - 1)] += 1;
+            total += 1;
+        }
+        let expected = total as f64 / BINS as f64;
+        let chi2: f64 = counts
+            .iter()
+            .map(|&c| (c as f64 - expected).powi(2) / expected)
+            .sum();
+        assert!(chi2 < 50.0, "{} is not uniform: chi2 = {}", what, chi2);
+    }
+
+    fn assert_close(actual: Float, expected: Float, what: &str) {
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "{}: {} != {}",
+            what,
+            actual,
+            expected
+        );
+    }
+
+    fn angle(x: Float, y: Float) -> Float {
+        (y.atan2(x) / (2.0 * PI)).rem_euclid(1.0)
+    }
+
+    #[test]
+    fn basis_is_orthonormal() {
+        let mut normals: Vec<Vec3f> = pairs(1)
+            .take(1000)
+            .map(|(a, b)| uniform_sphere(a, b))
+            .collect();
+        normals.extend([
+            Vec3f(0.0, 0.0, 1.0),
+            Vec3f(0.0, 0.0, -1.0),
+            Vec3f(1.0, 0.0, 0.0),
+            Vec3f(0.0, -1.0, 0.0),
+        ]);
+        for n in normals {
+            let onb = Onb::from_normal(&n);
+            for (a, b) in [
+                (onb.tangent, onb.bitangent),
+                (onb.tangent, onb.normal),
+                (onb.bitangent, onb.normal),
+            ] {
+                assert!(a.dot(&b).abs() < 1e-4, "not orthogonal around {:?}", n);
+            }
+            for axis in [onb.tangent, onb.bitangent, onb.normal] {
+                assert!(
+                    (axis.length() - 1.0).abs() < 1e-4,
+                    "not unit length around {:?}",
+                    n
+                );
+            }
+            // Right-handed, so local z really is the normal side
+            assert!((onb.tangent.cross(&onb.bitangent) - n).length() < 1e-4);
+            let v = Vec3f(0.3, -0.5, 0.8);
+            assert!((onb.to_local(&onb.to_world(&v)) - v).length() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn cosine_hemisphere_is_cosine_weighted() {
+        let dirs: Vec<Vec3f> = pairs(2).map(|(a, b)| cosine_hemisphere(a, b)).collect();
+        for d in &dirs {
+            assert!(d.2 >= 0.0 && (d.length() - 1.0).abs() < 1e-4);
+        }
+        // With density cos(theta) / pi, cos^2(theta) is uniform and azimuth is too
+        assert_uniform(dirs.iter().map(|d| d.2 * d.2), "cos^2 theta");
+        assert_uniform(dirs.iter().map(|d| angle(d.0, d.1)), "azimuth");
+        let mean_cos = dirs.iter().map(|d| d.2).sum::<Float>() / SAMPLES as Float;
+        assert_close(mean_cos, 2.0 / 3.0, "mean cos theta");
+        assert_close(cosine_hemisphere_pdf(1.0) * PI, 1.0, "pdf at the pole");
+    }
+
+    #[test]
+    fn uniform_hemisphere_and_sphere_are_uniform() {
+        let hemisphere: Vec<Vec3f> = pairs(3).map(|(a, b)| uniform_hemisphere(a, b)).collect();
+        // Archimedes: z is uniform for area-uniform points on a sphere
+        assert_uniform(hemisphere.iter().map(|d| d.2), "hemisphere z");
+        assert_uniform(
+            hemisphere.iter().map(|d| angle(d.0, d.1)),
+            "hemisphere azimuth",
+        );
+
+        let sphere: Vec<Vec3f> = pairs(4).map(|(a, b)| uniform_sphere(a, b)).collect();
+        for d in &sphere {
+            assert!((d.length() - 1.0).abs() < 1e-4);
+        }
+        assert_uniform(sphere.iter().map(|d| (d.2 + 1.0) / 2.0), "sphere z");
+        assert_uniform(sphere.iter().map(|d| angle(d.0, d.1)), "sphere azimuth");
+        let mean =
+            sphere.iter().fold(Vec3f(0.0, 0.0, 0.0), |acc, d| acc + *d) * (1.0 / SAMPLES as Float);
+        assert!(
+            mean.length() < 0.01,
+            "sphere samples are biased: {:?}",
+            mean
+        );
+        assert_close(UNIFORM_SPHERE_PDF * 4.0 * PI, 1.0, "sphere pdf");
+        assert_close(UNIFORM_HEMISPHERE_PDF * 2.0 * PI, 1.0, "hemisphere pdf");
+    }
+
+    #[test]
+    fn disk_mappings_are_area_uniform() {
+        for (name, map) in [
+            (
+                "uniform disk",
+                uniform_disk as fn(Float, Float) -> (Float, Float),
+            ),
+            ("concentric disk", concentric_disk),
+        ] {
+            let points: Vec<(Float, Float)> = pairs(5).map(|(a, b)| map(a, b)).collect();
+            // Area-uniform on the unit disk means r^2 and the angle are uniform
+            assert_uniform(
+                points.iter().map(|&(x, y)| x * x + y * y),
+                &format!("{} r^2", name),
+            );
+            assert_uniform(
+                points.iter().map(|&(x, y)| angle(x, y)),
+                &format!("{} angle", name),
+            );
+        }
+        assert_eq!(concentric_disk(0.5, 0.5), (0.0, 0.0));
+    }
+
+    #[test]
+    fn concentric_disk_keeps_strata_together() {
+        // The four quadrants of the square land in four quarter-disk wedges
+        for (u1, u2, quadrant) in [(0.9, 0.5, 0), (0.5, 0.9, 1), (0.1, 0.5, 2), (0.5, 0.1, 3)] {
+            let (x, y) = concentric_disk(u1, u2);
+            let wedge = (((angle(x, y) + 0.125) * 4.0) as usize) % 4;
+            assert_eq!(wedge, quadrant, "({}, {}) mapped to ({}, {})", u1, u2, x, y);
+        }
+    }
+
+    #[test]
+    fn triangle_samples_are_area_uniform() {
+        let weights: Vec<(Float, Float)> = pairs(6).map(|(a, b)| uniform_triangle(a, b)).collect();
+        for &(b0, b1) in &weights {
+            assert!(b0 >= 0.0 && b1 >= 0.0 && b0 + b1 <= 1.0 + 1e-6);
+        }
+        // The fraction within distance d of the opposite edge grows as 1 - (1 - d)^2, so
+        // (1 - b0)^2 is uniform, and so for each of the other weights
+        assert_uniform(weights.iter().map(|&(b0, _)| (1.0 - b0).powi(2)), "b0");
+        assert_uniform(weights.iter().map(|&(_, b1)| (1.0 - b1).powi(2)), "b1");
+        assert_uniform(weights.iter().map(|&(b0, b1)| (b0 + b1).powi(2)), "b2");
+        let mean_b0 = weights.iter().map(|w| w.0).sum::<Float>() / SAMPLES as Float;
+        let mean_b1 = weights.iter().map(|w| w.1).sum::<Float>() / SAMPLES as Float;
+        assert_close(mean_b0, 1.0 / 3.0, "mean b0");
+        assert_close(mean_b1, 1.0 / 3.0, "mean b1");
+    }
+}
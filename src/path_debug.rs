@@ -0,0 +1,268 @@
+use std::io::{self, Write};
+
+use crate::material::Material;
+use crate::vec3::{Float, Vec3f};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathEvent {
+    // Where the camera ray starts
+    Camera,
+    Surface,
+    // A scattering event inside a volume
+    Medium,
+    // The ray left the scene; the vertex sits one unit along its direction
+    Escaped,
+}
+
+impl PathEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            PathEvent::Camera => "camera",
+            PathEvent::Surface => "surface",
+            PathEvent::Medium => "medium",
+            PathEvent::Escaped => "escaped",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PathVertex {
+    // The vertex the ray arriving here left from; the Whitted integrator branches, so
+    // the vertices of one sample form a tree rooted at the camera
+    pub parent: Option<usize>,
+    pub depth: u32,
+    pub event: PathEvent,
+    pub position: Vec3f,
+    pub normal: Option<Vec3f>,
+    pub object_id: Option<u32>,
+    pub material: Option<Material>,
+    // Weight of light leaving this vertex in the final pixel color
+    pub throughput: Vec3f,
+    // Probability (density, for continuous lobes) of the direction the path continued
+    // in, including lobe selection and Russian roulette; None where nothing was sampled
+    pub pdf: Option<Float>,
+    // Light gathered at this vertex (direct lighting or background), before throughput
+    pub emitted: Vec3f,
+    // What this vertex adds to the pixel, emitted times throughput
+    pub contribution: Vec3f,
+}
+
+// Every vertex of one camera sample, in the order they were visited
+#[derive(Clone, Debug)]
+pub struct PathTrace {
+    pub sample: (Float, Float),
+    pub vertices: Vec<PathVertex>,
+    pub color: Vec3f,
+    pub alpha: Float,
+    // Where the next vertex attaches and with what weight
+    cursor: Option<usize>,
+    throughput: Vec3f,
+}
+
+impl PathTrace {
+    pub(crate) fn new(sample: (Float, Float), camera: Vec3f) -> PathTrace {
+        let mut trace = PathTrace {
+            sample,
+            vertices: Vec::new(),
+            color: Vec3f(0.0, 0.0, 0.0),
+            alpha: 1.0,
+            cursor: None,
+            throughput: Vec3f(1.0, 1.0, 1.0),
+        };
+        let root = trace.push(0, PathEvent::Camera, camera);
+        trace.cursor = Some(root);
+        trace
+    }
+
+    // Adds a vertex below the cursor with the current throughput and returns its index
+    pub(crate) fn push(&mut self, depth: u32, event: PathEvent, position: Vec3f) -> usize {
+        self.vertices.push(PathVertex {
+            parent: self.cursor,
+            depth,
+            event,
+            position,
+            normal: None,
+            object_id: None,
+            material: None,
+            throughput: self.throughput,
+            pdf: None,
+            emitted: Vec3f(0.0, 0.0, 0.0),
+            contribution: Vec3f(0.0, 0.0, 0.0),
+        });
+        self.vertices.len() - 1
+    }
+
+    pub(crate) fn vertex(&mut self, index: usize) -> &mut PathVertex {
+        &mut self.vertices[index]
+    }
+
+    pub(crate) fn set_emitted(&mut self, index: usize, emitted: Vec3f) {
+        let vertex = &mut self.vertices[index];
+        vertex.emitted = emitted;
+        vertex.contribution = emitted.multiply(&vertex.throughput);
+    }
+
+    // Moves the cursor for the next vertices, returning the previous one to restore
+    pub(crate) fn descend(&mut self, parent: usize, throughput: Vec3f) -> (Option<usize>, Vec3f) {
+        let saved = (self.cursor, self.throughput);
+        self.cursor = Some(parent);
+        self.throughput = throughput;
+        saved
+    }
+
+    pub(crate) fn restore(&mut self, (cursor, throughput): (Option<usize>, Vec3f)) {
+        self.cursor = cursor;
+        self.throughput = throughput;
+    }
+}
+
+// Writes every path as an OBJ line set, one group per sample, so the rays can be
+// overlaid on the scene in a model viewer
+pub fn write_obj<W: Write>(mut out: W, traces: &[PathTrace]) -> io::Result<()> {
+    let mut first_index = 1;
+    for (i, trace) in traces.iter().enumerate() {
+        writeln!(
+            out,
+            "g sample_{} # at ({}, {}), color {} {} {}",
+            i, trace.sample.0, trace.sample.1, trace.color.0, trace.color.1, trace.color.2
+        )?;
+        for vertex in &trace.vertices {
+            let p = vertex.position;
+            writeln!(out, "v {} {} {} # {}", p.0, p.1, p.2, vertex.event.name())?;
+        }
+        for (index, vertex) in trace.vertices.iter().enumerate() {
+            if let Some(parent) = vertex.parent {
+                writeln!(out, "l {} {}", first_index + parent, first_index + index)?;
+            }
+        }
+        first_index += trace.vertices.len();
+    }
+    Ok(())
+}
+
+pub fn write_json<W: Write>(mut out: W, traces: &[PathTrace]) -> io::Result<()> {
+    let vec = |v: &Vec3f| {
+        format!(
+            "[{}, {}, {}]",
+            json_number(v.0),
+            json_number(v.1),
+            json_number(v.2)
+        )
+    };
+    writeln!(out, "[")?;
+    for (i, trace) in traces.iter().enumerate() {
+        writeln!(out, "  {{")?;
+        writeln!(
+            out,
+            "    \"sample\": [{}, {}],",
+            json_number(trace.sample.0),
+            json_number(trace.sample.1)
+        )?;
+        writeln!(out, "    \"color\": {},", vec(&trace.color))?;
+        writeln!(out, "    \"alpha\": {},", json_number(trace.alpha))?;
+        writeln!(out, "    \"vertices\": [")?;
+        for (j, vertex) in trace.vertices.iter().enumerate() {
+            let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+            write!(
+                out,
+                "      {{\"parent\": {}, \"depth\": {}, \"event\": \"{}\", \"position\": {}, \
+                 \"normal\": {}, \"object_id\": {}, \"pdf\": {}, \"throughput\": {}, \
+                 \"emitted\": {}, \"contribution\": {}}}",
+                optional(vertex.parent.map(|p| p.to_string())),
+                vertex.depth,
+                vertex.event.name(),
+                vec(&vertex.position),
+                optional(vertex.normal.as_ref().map(vec)),
+                optional(vertex.object_id.map(|id| id.to_string())),
+                optional(vertex.pdf.map(json_number)),
+                vec(&vertex.throughput),
+                vec(&vertex.emitted),
+                vec(&vertex.contribution),
+            )?;
+            writeln!(
+                out,
+                "{}",
+                if j + 1 < trace.vertices.len() {
+                    ","
+                } else {
+                    ""
+                }
+            )?;
+        }
+        writeln!(out, "    ]")?;
+        writeln!(out, "  }}{}", if i + 1 < traces.len() { "," } else { "" })?;
+    }
+    writeln!(out, "]")
+}
+
+// JSON has no infinities or NaN, which are exactly what a broken path tends to produce
+pub(crate) fn json_number(value: Float) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::Json;
+
+    // A camera ray splitting at a surface into a reflection that escapes and a
+    // refraction with an infinite pdf
+    fn branching() -> PathTrace {
+        let mut trace = PathTrace::new((1.5, 2.5), Vec3f(0.0, 0.0, 0.0));
+        let surface = trace.push(0, PathEvent::Surface, Vec3f(0.0, 0.0, -4.0));
+        trace.vertex(surface).object_id = Some(3);
+        trace.set_emitted(surface, Vec3f(0.5, 0.5, 0.5));
+        let saved = trace.descend(surface, Vec3f(0.25, 0.25, 0.25));
+        let reflected = trace.push(1, PathEvent::Escaped, Vec3f(0.0, 1.0, -4.0));
+        trace.set_emitted(reflected, Vec3f(2.0, 0.0, 0.0));
+        let refracted = trace.push(1, PathEvent::Medium, Vec3f(0.0, 0.0, -5.0));
+        trace.vertex(refracted).pdf = Some(Float::INFINITY);
+        trace.restore(saved);
+        trace
+    }
+
+    #[test]
+    fn vertices_hang_off_the_vertex_they_left() {
+        let trace = branching();
+        let parents: Vec<_> = trace.vertices.iter().map(|v| v.parent).collect();
+        assert_eq!(parents, [None, Some(0), Some(1), Some(1)]);
+        assert_eq!(trace.vertices[2].throughput, Vec3f(0.25, 0.25, 0.25));
+        assert_eq!(trace.vertices[2].contribution, Vec3f(0.5, 0.0, 0.0));
+        assert_eq!(trace.vertices[1].contribution, Vec3f(0.5, 0.5, 0.5));
+
+        let mut obj = Vec::new();
+        write_obj(&mut obj, &[trace.clone(), trace]).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let lines: Vec<&str> = obj.lines().filter(|l| l.starts_with("l ")).collect();
+        // OBJ indices count from 1 across the whole file
+        assert_eq!(
+            lines,
+            ["l 1 2", "l 2 3", "l 2 4", "l 5 6", "l 6 7", "l 6 8"]
+        );
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 8);
+    }
+
+    #[test]
+    fn writes_traces_as_valid_json() {
+        let mut out = Vec::new();
+        write_json(&mut out, &[branching(), branching()]).unwrap();
+        let json = Json::parse(std::str::from_utf8(&out).unwrap()).unwrap();
+        let traces = json.as_array().unwrap();
+        assert_eq!(traces.len(), 2);
+        let vertices = traces[0].get("vertices").unwrap().as_array().unwrap();
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(vertices[0].get("parent"), Some(&Json::Null));
+        assert_eq!(vertices[2].get("parent").unwrap().as_f64(), Some(1.0));
+        assert_eq!(vertices[2].get("event").unwrap().as_str(), Some("escaped"));
+        assert_eq!(vertices[1].get("object_id").unwrap().as_f64(), Some(3.0));
+        // Non-finite numbers, which JSON cannot hold, are written as strings
+        assert_eq!(vertices[3].get("pdf").unwrap().as_str(), Some("inf"));
+        let sample = traces[1].get("sample").unwrap().as_array().unwrap();
+        assert_eq!(sample[0].as_f64(), Some(1.5));
+        assert_eq!(json_number(Float::NAN), "\"NaN\"");
+    }
+}
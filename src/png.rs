@@ -0,0 +1,893 @@
+use std::io::{self, Read, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorType {
+    Gray,
+    // Big-endian samples
+    Gray16,
+    Rgb,
+    Rgba,
+}
+
+impl ColorType {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            ColorType::Gray => 1,
+            ColorType::Gray16 => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    fn bit_depth(self) -> u8 {
+        match self {
+            ColorType::Gray16 => 16,
+            _ => 8,
+        }
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            ColorType::Gray | ColorType::Gray16 => 0,
+            ColorType::Rgb => 2,
+            ColorType::Rgba => 6,
+        }
+    }
+}
+
+// Writes a PNG from tightly packed rows of `data`, with each (keyword, text) pair in a
+// tEXt chunk ahead of the pixels. Both are Latin-1, so other characters become '?', and
+// keywords are cut to the 79 bytes the format allows.
+pub fn write_png<W: Write>(
+    out: &mut W,
+    width: usize,
+    height: usize,
+    color: ColorType,
+    data: &[u8],
+    text: &[(String, String)],
+) -> io::Result<()> {
+    let stride = width * color.bytes_per_pixel();
+    assert_eq!(
+        data.len(),
+        stride * height,
+        "pixel data does not match size"
+    );
+
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[color.bit_depth(), color.code(), 0, 0, 0]);
+    write_chunk(out, b"IHDR", &header)?;
+    for (keyword, value) in text {
+        let mut chunk: Vec<u8> = latin1(keyword).take(79).collect();
+        if chunk.is_empty() {
+            continue;
+        }
+        chunk.push(0);
+        chunk.extend(latin1(value));
+        write_chunk(out, b"tEXt", &chunk)?;
+    }
+
+    // Sub filter on every row; cheap and helps smooth gradients compress
+    let bpp = color.bytes_per_pixel();
+    let mut filtered = Vec::with_capacity((stride + 1) * height);
+    for row in data.chunks_exact(stride.max(1)).take(height) {
+        filtered.push(1);
+        for (i, &byte) in row.iter().enumerate() {
+            let left = if i >= bpp { row[i - bpp] } else { 0 };
+            filtered.push(byte.wrapping_sub(left));
+        }
+    }
+
+    write_chunk(out, b"IDAT", &zlib_compress(&filtered))?;
+    write_chunk(out, b"IEND", &[])
+}
+
+// Null bytes would end a tEXt keyword early, so they go the same way as characters
+// Latin-1 lacks
+fn latin1(text: &str) -> impl Iterator<Item = u8> + '_ {
+    text.chars().map(|c| match c as u32 {
+        1..=0xff => c as u8,
+        _ => b'?',
+    })
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = crc32(crc32_update(0xffff_ffff, kind), data);
+    out.write_all(&crc.to_be_bytes())
+}
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    crc32_update(crc, data) ^ 0xffff_ffff
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, bits: u32, count: u32) {
+        self.buffer |= (bits as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes are packed starting from their most significant bit
+    fn write_code(&mut self, code: u32, length: u32) {
+        self.write(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+const WINDOW: usize = 32768;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 48;
+const HASH_BITS: u32 = 15;
+
+fn write_literal(bits: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => bits.write_code(0x30 + symbol, 8),
+        144..=255 => bits.write_code(0x190 + symbol - 144, 9),
+        256..=279 => bits.write_code(symbol - 256, 7),
+        _ => bits.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(bits: &mut BitWriter, length: usize, distance: usize) {
+    let li = LENGTH_BASE
+        .iter()
+        .rposition(|&b| b as usize <= length)
+        .unwrap();
+    write_literal(bits, 257 + li as u32);
+    bits.write(
+        (length - LENGTH_BASE[li] as usize) as u32,
+        LENGTH_EXTRA[li] as u32,
+    );
+
+    let di = DIST_BASE
+        .iter()
+        .rposition(|&b| b as usize <= distance)
+        .unwrap();
+    bits.write_code(di as u32, 5);
+    bits.write(
+        (distance - DIST_BASE[di] as usize) as u32,
+        DIST_EXTRA[di] as u32,
+    );
+}
+
+fn hash(data: &[u8], i: usize) -> usize {
+    let v = (data[i] as u32) | (data[i + 1] as u32) << 8 | (data[i + 2] as u32) << 16;
+    (v.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+fn insert(data: &[u8], head: &mut [usize], prev: &mut [usize], i: usize) {
+    if i + 2 < data.len() {
+        let h = hash(data, i);
+        prev[i] = head[h];
+        head[h] = i;
+    }
+}
+
+// zlib stream holding a single fixed-Huffman deflate block with greedy LZ77 matching
+pub(crate) fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut bits = BitWriter {
+        bytes: vec![0x78, 0x01],
+        buffer: 0,
+        count: 0,
+    };
+    bits.write(1, 1); // final block
+    bits.write(1, 2); // fixed Huffman codes
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; data.len()];
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+        if i + 2 < data.len() {
+            let mut candidate = head[hash(data, i)];
+            let mut chain = 0;
+            let limit = (data.len() - i).min(MAX_MATCH);
+            while candidate != usize::MAX && i - candidate <= WINDOW && chain < MAX_CHAIN {
+                let length = data[candidate..]
+                    .iter()
+                    .zip(&data[i..i + limit])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = i - candidate;
+                    if length == limit {
+                        break;
+                    }
+                }
+                candidate = prev[candidate];
+                chain += 1;
+            }
+        }
+
+        if best_length >= 3 {
+            write_match(&mut bits, best_length, best_distance);
+            for j in i..i + best_length {
+                insert(data, &mut head, &mut prev, j);
+            }
+            i += best_length;
+        } else {
+            write_literal(&mut bits, data[i] as u32);
+            insert(data, &mut head, &mut prev, i);
+            i += 1;
+        }
+    }
+    write_literal(&mut bits, 256);
+
+    let mut bytes = bits.finish();
+    bytes.extend_from_slice(&adler32(data).to_be_bytes());
+    bytes
+}
+
+// A decoded image expanded to 8-bit RGBA
+pub struct PngImage {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+    // The (keyword, text) pairs of its tEXt chunks
+    pub text: Vec<(String, String)>,
+}
+
+// Reads a non-interlaced PNG of any standard color type; 16-bit samples round to 8 bits
+pub fn read_png<R: Read>(mut reader: R) -> io::Result<PngImage> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if !bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Err(invalid("not a PNG file"));
+    }
+
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut compressed = Vec::new();
+    let mut text = Vec::new();
+    let mut pos = 8;
+    while pos + 8 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind = &bytes[pos + 4..pos + 8];
+        let data = bytes
+            .get(pos + 8..pos + 8 + length)
+            .ok_or_else(|| invalid("truncated PNG chunk"))?;
+        match kind {
+            b"IHDR" if length >= 13 => header = Some(data),
+            b"PLTE" => palette = data,
+            b"tRNS" => transparency = data,
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"tEXt" => {
+                if let Some(split) = data.iter().position(|&b| b == 0) {
+                    let decode = |bytes: &[u8]| bytes.iter().map(|&b| b as char).collect();
+                    text.push((decode(&data[..split]), decode(&data[split + 1..])));
+                }
+            }
+            b"IEND" => break,
+            _ => {}
+        }
+        pos += 12 + length;
+    }
+
+    let header = header.ok_or_else(|| invalid("PNG has no IHDR chunk"))?;
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+    let (depth, color_type, interlace) = (header[8], header[9], header[12]);
+    if interlace != 0 {
+        return Err(invalid("interlaced PNGs are not supported"));
+    }
+    let channels = match color_type {
+        0 => 1,
+        2 => 3,
+        3 => 1,
+        4 => 2,
+        6 => 4,
+        _ => return Err(invalid("unknown PNG color type")),
+    };
+    if !matches!(depth, 1 | 2 | 4 | 8 | 16) || (depth < 8 && channels != 1) {
+        return Err(invalid("unsupported PNG bit depth"));
+    }
+
+    let bits_per_pixel = channels * depth as usize;
+    let bpp = bits_per_pixel.div_ceil(8);
+    // The header's width and height are untrusted, so neither the rows nor the RGBA they
+    // expand to may wrap round
+    let stride = width
+        .checked_mul(bits_per_pixel)
+        .map(|bits| bits.div_ceil(8))
+        .ok_or_else(|| invalid("PNG image is too large"))?;
+    let expected = (stride + 1)
+        .checked_mul(height)
+        .filter(|_| {
+            width
+                .checked_mul(height)
+                .and_then(|n| n.checked_mul(4))
+                .is_some()
+        })
+        .ok_or_else(|| invalid("PNG image is too large"))?;
+    let raw = zlib_decompress(&compressed, expected)?;
+    if raw.len() < expected {
+        return Err(invalid("PNG image data is truncated"));
+    }
+
+    let mut previous = vec![0u8; stride];
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        let filter = raw[y * (stride + 1)];
+        let mut row = raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)].to_vec();
+        unfilter(filter, &mut row, &previous, bpp)?;
+
+        for x in 0..width {
+            // Samples as (value, max value) pairs, one per channel
+            let sample = |c: usize| -> u16 {
+                match depth {
+                    16 => u16::from_be_bytes([
+                        row[(x * channels + c) * 2],
+                        row[(x * channels + c) * 2 + 1],
+                    ]),
+                    8 => row[x * channels + c] as u16,
+                    _ => {
+                        let bit = x * depth as usize;
+                        let byte = row[bit / 8];
+                        let shift = 8 - depth as usize - bit % 8;
+                        ((byte >> shift) & ((1u8 << depth) - 1)) as u16
+                    }
+                }
+            };
+            let max = (1u32 << depth) - 1;
+            let to_u8 = |v: u16| ((v as u32 * 255 + max / 2) / max) as u8;
+            let pixel = match color_type {
+                0 => {
+                    let g = to_u8(sample(0));
+                    [g, g, g, 255]
+                }
+                2 => [to_u8(sample(0)), to_u8(sample(1)), to_u8(sample(2)), 255],
+                3 => {
+                    let i = sample(0) as usize;
+                    let entry = palette
+                        .get(i * 3..i * 3 + 3)
+                        .ok_or_else(|| invalid("PNG palette index out of range"))?;
+                    [
+                        entry[0],
+                        entry[1],
+                        entry[2],
+                        *transparency.get(i).unwrap_or(&255),
+                    ]
+                }
+                4 => {
+                    let g = to_u8(sample(0));
+                    [g, g, g, to_u8(sample(1))]
+                }
+                _ => [
+                    to_u8(sample(0)),
+                    to_u8(sample(1)),
+                    to_u8(sample(2)),
+                    to_u8(sample(3)),
+                ],
+            };
+            rgba.extend_from_slice(&pixel);
+        }
+        previous = row;
+    }
+
+    Ok(PngImage {
+        width,
+        height,
+        rgba,
+        text,
+    })
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn unfilter(filter: u8, row: &mut [u8], previous: &[u8], bpp: usize) -> io::Result<()> {
+    for i in 0..row.len() {
+        let left = if i >= bpp { row[i - bpp] as i16 } else { 0 };
+        let up = previous[i] as i16;
+        let up_left = if i >= bpp {
+            previous[i - bpp] as i16
+        } else {
+            0
+        };
+        let predictor = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => (left + up) / 2,
+            4 => {
+                let p = left + up - up_left;
+                let (pa, pb, pc) = ((p - left).abs(), (p - up).abs(), (p - up_left).abs());
+                if pa <= pb && pa <= pc {
+                    left
+                } else if pb <= pc {
+                    up
+                } else {
+                    up_left
+                }
+            }
+            _ => return Err(invalid("unknown PNG filter type")),
+        };
+        row[i] = row[i].wrapping_add(predictor as u8);
+    }
+    Ok(())
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u32) -> io::Result<u32> {
+        while self.count < count {
+            let byte = *self
+                .bytes
+                .get(self.pos)
+                .ok_or_else(|| invalid("deflate stream ended early"))?;
+            self.buffer |= (byte as u64) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+        let value = (self.buffer & ((1u64 << count) - 1)) as u32;
+        self.buffer >>= count;
+        self.count -= count;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        let extra = self.count % 8;
+        self.buffer >>= extra;
+        self.count -= extra;
+    }
+}
+
+// Canonical Huffman decoding table: code counts per length and symbols in code order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for length in 1..16 {
+            offsets[length] = offsets[length - 1] + counts[length - 1];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut BitReader) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= bits.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("invalid Huffman code in deflate stream"))
+    }
+}
+
+// Stops once `limit` bytes are out, so a stream that would inflate past what the image
+// needs is never expanded
+pub(crate) fn zlib_decompress(data: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    if data.len() < 2
+        || data[0] & 0x0f != 8
+        || !u16::from_be_bytes([data[0], data[1]]).is_multiple_of(31)
+    {
+        return Err(invalid("invalid zlib header"));
+    }
+    let mut bits = BitReader {
+        bytes: &data[2..],
+        pos: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let mut out = Vec::new();
+
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                bits.align_to_byte();
+                let length = bits.bits(16)?;
+                let complement = bits.bits(16)?;
+                if length != !complement & 0xffff {
+                    return Err(invalid("corrupt stored deflate block"));
+                }
+                for _ in 0..(length as usize).min(limit.saturating_sub(out.len())) {
+                    out.push(bits.bits(8)? as u8);
+                }
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut bits, &mut out, limit, &literals, &distances)?;
+            }
+            2 => {
+                let literal_count = bits.bits(5)? as usize + 257;
+                let distance_count = bits.bits(5)? as usize + 1;
+                let code_count = bits.bits(4)? as usize + 4;
+                const ORDER: [usize; 19] = [
+                    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+                ];
+                let mut code_lengths = [0u8; 19];
+                for &i in &ORDER[..code_count] {
+                    code_lengths[i] = bits.bits(3)? as u8;
+                }
+                let codes = Huffman::new(&code_lengths);
+
+                let mut lengths = vec![0u8; literal_count + distance_count];
+                let mut i = 0;
+                while i < lengths.len() {
+                    let symbol = codes.decode(&mut bits)?;
+                    let (value, repeat) = match symbol {
+                        0..=15 => (symbol as u8, 1),
+                        16 => {
+                            let previous = *lengths[..i]
+                                .last()
+                                .ok_or_else(|| invalid("deflate length repeat with no previous"))?;
+                            (previous, 3 + bits.bits(2)? as usize)
+                        }
+                        17 => (0, 3 + bits.bits(3)? as usize),
+                        _ => (0, 11 + bits.bits(7)? as usize),
+                    };
+                    if i + repeat > lengths.len() {
+                        return Err(invalid("deflate code lengths overflow"));
+                    }
+                    lengths[i..i + repeat].fill(value);
+                    i += repeat;
+                }
+                let literals = Huffman::new(&lengths[..literal_count]);
+                let distances = Huffman::new(&lengths[literal_count..]);
+                inflate_block(&mut bits, &mut out, limit, &literals, &distances)?;
+            }
+            _ => return Err(invalid("invalid deflate block type")),
+        }
+        if last || out.len() >= limit {
+            break;
+        }
+    }
+    Ok(out)
+}
+
+fn inflate_block(
+    bits: &mut BitReader,
+    out: &mut Vec<u8>,
+    limit: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> io::Result<()> {
+    while out.len() < limit {
+        let symbol = literals.decode(bits)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let li = symbol - 257;
+                if li >= LENGTH_BASE.len() {
+                    return Err(invalid("invalid deflate length code"));
+                }
+                let length =
+                    LENGTH_BASE[li] as usize + bits.bits(LENGTH_EXTRA[li] as u32)? as usize;
+                let di = distances.decode(bits)? as usize;
+                if di >= DIST_BASE.len() {
+                    return Err(invalid("invalid deflate distance code"));
+                }
+                let distance = DIST_BASE[di] as usize + bits.bits(DIST_EXTRA[di] as u32)? as usize;
+                if distance > out.len() {
+                    return Err(invalid("deflate distance reaches before the start"));
+                }
+                let start = out.len() - distance;
+                for i in 0..length {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32, depth: u8, color_type: u8, idat: &[u8]) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[depth, color_type, 0, 0, 0]);
+        let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+        write_chunk(&mut out, b"IHDR", &header).unwrap();
+        write_chunk(&mut out, b"IDAT", idat).unwrap();
+        write_chunk(&mut out, b"IEND", &[]).unwrap();
+        out
+    }
+
+    // A big-endian 16-bit sample as read_png scales it
+    fn scaled(sample: &[u8]) -> u8 {
+        ((u16::from_be_bytes([sample[0], sample[1]]) as u32 * 255 + 32767) / 65535) as u8
+    }
+
+    // The inverse of unfilter, worked from the unfiltered rows
+    fn filter(kind: u8, row: &[u8], previous: &[u8], bpp: usize) -> Vec<u8> {
+        (0..row.len())
+            .map(|i| {
+                let left = if i >= bpp { row[i - bpp] as i16 } else { 0 };
+                let up = previous[i] as i16;
+                let up_left = if i >= bpp {
+                    previous[i - bpp] as i16
+                } else {
+                    0
+                };
+                let predictor = match kind {
+                    0 => 0,
+                    1 => left,
+                    2 => up,
+                    3 => (left + up) / 2,
+                    _ => {
+                        let p = left + up - up_left;
+                        let (pa, pb, pc) = ((p - left).abs(), (p - up).abs(), (p - up_left).abs());
+                        if pa <= pb && pa <= pc {
+                            left
+                        } else if pb <= pc {
+                            up
+                        } else {
+                            up_left
+                        }
+                    }
+                };
+                row[i].wrapping_sub(predictor as u8)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_back_what_write_png_wrote() {
+        let (width, height) = (5, 3);
+        let text = vec![("Software".to_string(), "rusty-rays".to_string())];
+        for color in [
+            ColorType::Gray,
+            ColorType::Gray16,
+            ColorType::Rgb,
+            ColorType::Rgba,
+        ] {
+            let data: Vec<u8> = (0..width * height * color.bytes_per_pixel())
+                .map(|i| (i * 37 % 251) as u8)
+                .collect();
+            let mut file = Vec::new();
+            write_png(&mut file, width, height, color, &data, &text).unwrap();
+            let image = read_png(&file[..]).unwrap();
+            assert_eq!((image.width, image.height), (width, height));
+            assert_eq!(image.text, text);
+            let expected: Vec<u8> = match color {
+                ColorType::Gray => data.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+                ColorType::Gray16 => data
+                    .chunks_exact(2)
+                    .map(scaled)
+                    .flat_map(|g| [g, g, g, 255])
+                    .collect(),
+                ColorType::Rgb => data
+                    .chunks_exact(3)
+                    .flat_map(|p| [p[0], p[1], p[2], 255])
+                    .collect(),
+                ColorType::Rgba => data.clone(),
+            };
+            assert_eq!(image.rgba, expected, "{:?}", color);
+        }
+    }
+
+    #[test]
+    fn undoes_every_filter_type() {
+        // (channels, depth, color type), so the filters see one, three and eight bytes a pixel
+        for (channels, depth, color_type) in [(1, 8, 0), (3, 8, 2), (4, 16, 6)] {
+            let (width, height) = (4, 5);
+            let stride = width * channels * depth / 8;
+            let bpp = channels * depth / 8;
+            let rows: Vec<Vec<u8>> = (0..height)
+                .map(|y| {
+                    (0..stride)
+                        .map(|i| (i * 29 + y * 71 + i * i * y) as u8)
+                        .collect()
+                })
+                .collect();
+            // One row per filter type, each worked against the row above it
+            let mut raw = Vec::new();
+            let mut previous = vec![0; stride];
+            for (kind, row) in rows.iter().enumerate() {
+                raw.push(kind as u8);
+                raw.extend(filter(kind as u8, row, &previous, bpp));
+                previous = row.clone();
+            }
+            let file = png(
+                width as u32,
+                height as u32,
+                depth as u8,
+                color_type,
+                &zlib_compress(&raw),
+            );
+            let image = read_png(&file[..]).unwrap();
+            let step = depth / 8;
+            for (y, row) in rows.iter().enumerate() {
+                for x in 0..width {
+                    let sample = |c: usize| {
+                        let at = (x * channels + c) * step;
+                        if step == 2 {
+                            scaled(&row[at..at + 2])
+                        } else {
+                            row[at]
+                        }
+                    };
+                    let pixel = match channels {
+                        1 => [sample(0), sample(0), sample(0), 255],
+                        3 => [sample(0), sample(1), sample(2), 255],
+                        _ => [sample(0), sample(1), sample(2), sample(3)],
+                    };
+                    let at = (y * width + x) * 4;
+                    assert_eq!(image.rgba[at..at + 4], pixel, "{} {} {}", channels, x, y);
+                }
+            }
+
+            raw[0] = 5;
+            let file = png(
+                width as u32,
+                height as u32,
+                depth as u8,
+                color_type,
+                &zlib_compress(&raw),
+            );
+            assert!(read_png(&file[..]).is_err());
+        }
+    }
+
+    #[test]
+    fn refuses_truncated_and_corrupt_image_data() {
+        let raw: Vec<u8> = (0..6 * 9)
+            .map(|i| if i % 9 == 0 { 0 } else { i as u8 })
+            .collect();
+        let idat = zlib_compress(&raw);
+        let whole = read_png(&png(8, 6, 8, 0, &idat)[..]).unwrap().rgba;
+        // Cut short, the stream fails unless all it lost came after the last row
+        for end in 0..idat.len() {
+            if let Ok(image) = read_png(&png(8, 6, 8, 0, &idat[..end])[..]) {
+                assert_eq!(image.rgba, whole, "{}", end);
+            }
+        }
+        let half = read_png(&png(8, 6, 8, 0, &idat[..idat.len() / 2])[..]);
+        assert!(half.is_err());
+        // Flipping bits may still decode to something, but never panics
+        for i in 0..idat.len() {
+            let mut corrupt = idat.clone();
+            corrupt[i] ^= 0x5a;
+            let _ = read_png(&png(8, 6, 8, 0, &corrupt)[..]);
+        }
+        // A header whose rows would wrap usize before any data is looked at
+        let huge = png(u32::MAX, u32::MAX, 16, 6, &idat);
+        let error = read_png(&huge[..]).err().unwrap();
+        assert!(error.to_string().contains("too large"), "{}", error);
+        let mut file = png(8, 6, 8, 0, &idat);
+        file.truncate(file.len() - 20);
+        assert!(read_png(&file[..]).is_err());
+    }
+
+    #[test]
+    fn stops_inflating_once_the_image_is_full() {
+        // A megabyte of zeros inflates from a few kilobytes; a 2x2 image needs six bytes
+        let bomb = zlib_compress(&vec![0; 1 << 20]);
+        assert!(zlib_decompress(&bomb, 6).unwrap().len() < 300);
+        let image = read_png(&png(2, 2, 8, 0, &bomb)[..]).unwrap();
+        assert_eq!(image.rgba, [0, 0, 0, 255].repeat(4));
+        assert_eq!(zlib_decompress(&bomb, usize::MAX).unwrap().len(), 1 << 20);
+    }
+
+    #[test]
+    fn keeps_text_to_what_text_chunks_hold() {
+        let long = "k".repeat(100);
+        let text = vec![
+            ("Scene".to_string(), "café \u{263a}".to_string()),
+            (long.clone(), "cut".to_string()),
+            (String::new(), "no keyword".to_string()),
+            ("Null".to_string(), "a\0b".to_string()),
+        ];
+        let mut file = Vec::new();
+        write_png(&mut file, 1, 1, ColorType::Gray, &[0], &text).unwrap();
+        let image = read_png(&file[..]).unwrap();
+        // Latin-1 keeps the accent but not the face, and empty keywords are left out
+        assert_eq!(
+            image.text,
+            [
+                ("Scene".to_string(), "café ?".to_string()),
+                (long[..79].to_string(), "cut".to_string()),
+                ("Null".to_string(), "a?b".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,536 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::bvh::{Aabb, Bvh};
+use crate::log::{self, Level};
+use crate::scene::Diagnostic;
+use crate::shapes::{HitRecord, Shape};
+use crate::vec3::{Float, Vec3f};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Splat {
+    Sphere,
+    Disk,
+}
+
+pub struct PointCloud {
+    points: Vec<Vec3f>,
+    normals: Option<Vec<Vec3f>>,
+    radius: Float,
+    splat: Splat,
+    bvh: Bvh,
+}
+
+impl PointCloud {
+    pub fn new(points: Vec<Vec3f>, radius: Float) -> PointCloud {
+        PointCloud::build(points, None, radius, Splat::Sphere)
+    }
+
+    // Disks need an orientation, so they are only available when normals are known.
+    pub fn with_normals(points: Vec<Vec3f>, normals: Vec<Vec3f>, radius: Float) -> PointCloud {
+        assert_eq!(points.len(), normals.len(), "every point needs a normal");
+        let normals = normals
+            .into_iter()
+            .map(|n| n.normalized().unwrap_or(Vec3f(0.0, 1.0, 0.0)))
+            .collect();
+        PointCloud::build(points, Some(normals), radius, Splat::Disk)
+    }
+
+    fn build(
+        points: Vec<Vec3f>,
+        normals: Option<Vec<Vec3f>>,
+        radius: Float,
+        splat: Splat,
+    ) -> PointCloud {
+        let half_extent = Vec3f(radius, radius, radius);
+        let bounds: Vec<Aabb> = points
+            .iter()
+            .map(|&p| Aabb::around(p, half_extent))
+            .collect();
+        let bvh = Bvh::build(&bounds);
+        PointCloud {
+            points,
+            normals,
+            radius,
+            splat,
+            bvh,
+        }
+    }
+
+    pub fn load(path: &Path, radius: Float) -> io::Result<PointCloud> {
+        let start = log::timer(Level::Debug);
+        let reader = BufReader::new(File::open(path)?);
+        let cloud = match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("ply") => PointCloud::from_ply(reader, radius)?,
+            Some(ext) if ext.eq_ignore_ascii_case("xyz") => PointCloud::from_xyz(reader, radius)?,
+            _ => {
+                return Err(invalid_data(format!(
+                    "unsupported point cloud format: {}",
+                    path.display()
+                )))
+            }
+        };
+        if let Some(start) = start {
+            crate::debug!(
+                "loaded {}: {} points in {:.3}s",
+                path.display(),
+                cloud.len(),
+                start.elapsed().as_secs_f64()
+            );
+        }
+        Ok(cloud)
+    }
+
+    // One point per line: `x y z` optionally followed by `nx ny nz`.
+    pub fn from_xyz<R: BufRead>(reader: R, radius: Float) -> io::Result<PointCloud> {
+        let mut points = Vec::new();
+        let mut normals = Vec::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values = line
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|v| !v.is_empty())
+                .map(|v| {
+                    v.parse::<Float>()
+                        .map_err(|_| invalid_data(format!("invalid number in xyz file: {}", v)))
+                })
+                .collect::<io::Result<Vec<Float>>>()?;
+            if values.len() < 3 {
+                return Err(invalid_data(format!("expected x y z, got: {}", line)));
+            }
+            points.push(Vec3f(values[0], values[1], values[2]));
+            if values.len() >= 6 {
+                normals.push(Vec3f(values[3], values[4], values[5]));
+            }
+        }
+
+        if !normals.is_empty() && normals.len() == points.len() {
+            Ok(PointCloud::with_normals(points, normals, radius))
+        } else {
+            Ok(PointCloud::new(points, radius))
+        }
+    }
+
+    // Reads the vertex element of ascii and binary PLY files; other elements are ignored.
+    pub fn from_ply<R: BufRead>(mut reader: R, radius: Float) -> io::Result<PointCloud> {
+        let header = PlyHeader::parse(&mut reader)?;
+        let columns = |name: &str| header.properties.iter().position(|p| p.name == name);
+        let (x, y, z) = match (columns("x"), columns("y"), columns("z")) {
+            (Some(x), Some(y), Some(z)) => (x, y, z),
+            _ => return Err(invalid_data("ply vertex element lacks x/y/z".to_string())),
+        };
+        let normal_columns = match (columns("nx"), columns("ny"), columns("nz")) {
+            (Some(nx), Some(ny), Some(nz)) => Some((nx, ny, nz)),
+            _ => None,
+        };
+
+        // The count is only the header's word, so a file cannot make it reserve more
+        // than a modest start; a real cloud that big grows the rest as it is read
+        let mut points = Vec::with_capacity(header.vertex_count.min(1 << 16));
+        let mut normals = Vec::new();
+        let mut row = vec![0.0f64; header.properties.len()];
+        let mut lines = String::new();
+
+        for _ in 0..header.vertex_count {
+            match header.format {
+                PlyFormat::Ascii => {
+                    lines.clear();
+                    if reader.read_line(&mut lines)? == 0 {
+                        return Err(invalid_data("ply file ended early".to_string()));
+                    }
+                    let mut values = lines.split_whitespace();
+                    for value in row.iter_mut() {
+                        *value = values
+                            .next()
+                            .and_then(|v| v.parse().ok())
+                            .ok_or_else(|| invalid_data("malformed ply vertex".to_string()))?;
+                    }
+                }
+                PlyFormat::BinaryLittleEndian | PlyFormat::BinaryBigEndian => {
+                    let little = header.format == PlyFormat::BinaryLittleEndian;
+                    for (value, property) in row.iter_mut().zip(&header.properties) {
+                        *value = property.kind.read(&mut reader, little)?;
+                    }
+                }
+            }
+
+            points.push(Vec3f(row[x] as Float, row[y] as Float, row[z] as Float));
+            if let Some((nx, ny, nz)) = normal_columns {
+                normals.push(Vec3f(row[nx] as Float, row[ny] as Float, row[nz] as Float));
+            }
+        }
+
+        if normal_columns.is_some() {
+            Ok(PointCloud::with_normals(points, normals, radius))
+        } else {
+            Ok(PointCloud::new(points, radius))
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn splat(&self) -> Splat {
+        self.splat
+    }
+
+    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<Float> {
+        self.intersect(orig, dir).map(|(_, t)| t)
+    }
+
+    fn intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<(usize, Float)> {
+        self.bvh
+            .traverse(orig, dir, |i, _| self.intersect_point(i, orig, dir))
+    }
+
+    fn intersect_point(&self, i: usize, orig: &Vec3f, dir: &Vec3f) -> Option<Float> {
+        let center = self.points[i];
+        match (&self.normals, self.splat) {
+            (Some(normals), Splat::Disk) => {
+                let n = normals[i];
+                let denom = n.dot(dir);
+                if denom.abs() < 1e-8 {
+                    return None;
+                }
+                let t = (center - *orig).dot(&n) / denom;
+                if t <= 0.0 {
+                    return None;
+                }
+                let offset = *orig + *dir * t - center;
+                if offset.dot(&offset) > self.radius * self.radius {
+                    return None;
+                }
+                Some(t)
+            }
+            _ => {
+                let l = center - *orig;
+                let tca = l.dot(dir);
+                let d2 = l.dot(&l) - tca * tca;
+                let r2 = self.radius * self.radius;
+                if d2 > r2 {
+                    return None;
+                }
+                let thc = (r2 - d2).sqrt();
+                let t = if tca - thc > 0.0 {
+                    tca - thc
+                } else {
+                    tca + thc
+                };
+                if t > 0.0 {
+                    Some(t)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+impl Shape for PointCloud {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        let (i, t) = self.intersect(orig, dir)?;
+        let point = *orig + *dir * t;
+        let normal = match &self.normals {
+            // Disks are two-sided, so face the normal towards the viewer
+            Some(normals) if normals[i].dot(dir) > 0.0 => -normals[i],
+            Some(normals) => normals[i],
+            None => (point - self.points[i])
+                .normalized()
+                .unwrap_or(Vec3f(0.0, 1.0, 0.0)),
+        };
+        Some(HitRecord {
+            t,
+            point,
+            normal,
+            uv: None,
+            tangent: None,
+            color: None,
+            front_face: normal.dot(dir) < 0.0,
+        })
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        if self.radius.is_finite() && self.radius > 0.0 {
+            Vec::new()
+        } else {
+            vec![Diagnostic::error(format!(
+                "splat radius must be finite and positive, got {}",
+                self.radius
+            ))]
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum PlyScalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyScalar {
+    fn parse(name: &str) -> Option<PlyScalar> {
+        match name {
+            "char" | "int8" => Some(PlyScalar::I8),
+            "uchar" | "uint8" => Some(PlyScalar::U8),
+            "short" | "int16" => Some(PlyScalar::I16),
+            "ushort" | "uint16" => Some(PlyScalar::U16),
+            "int" | "int32" => Some(PlyScalar::I32),
+            "uint" | "uint32" => Some(PlyScalar::U32),
+            "float" | "float32" => Some(PlyScalar::F32),
+            "double" | "float64" => Some(PlyScalar::F64),
+            _ => None,
+        }
+    }
+
+    fn read<R: Read>(self, reader: &mut R, little: bool) -> io::Result<f64> {
+        macro_rules! read_as {
+            ($t:ty) => {{
+                let mut buf = [0u8; std::mem::size_of::<$t>()];
+                reader.read_exact(&mut buf)?;
+                if little {
+                    <$t>::from_le_bytes(buf) as f64
+                } else {
+                    <$t>::from_be_bytes(buf) as f64
+                }
+            }};
+        }
+        Ok(match self {
+            PlyScalar::I8 => read_as!(i8),
+            PlyScalar::U8 => read_as!(u8),
+            PlyScalar::I16 => read_as!(i16),
+            PlyScalar::U16 => read_as!(u16),
+            PlyScalar::I32 => read_as!(i32),
+            PlyScalar::U32 => read_as!(u32),
+            PlyScalar::F32 => read_as!(f32),
+            PlyScalar::F64 => read_as!(f64),
+        })
+    }
+}
+
+struct PlyProperty {
+    name: String,
+    kind: PlyScalar,
+}
+
+struct PlyHeader {
+    format: PlyFormat,
+    vertex_count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+impl PlyHeader {
+    fn parse<R: BufRead>(reader: &mut R) -> io::Result<PlyHeader> {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if line.trim() != "ply" {
+            return Err(invalid_data("missing ply magic".to_string()));
+        }
+
+        let mut format = None;
+        let mut vertex_count = None;
+        let mut properties = Vec::new();
+        let mut current_element = String::new();
+        let mut elements_seen = 0;
+
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(invalid_data("ply header is not terminated".to_string()));
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["end_header"] => break,
+                ["format", kind, _] => {
+                    format = Some(match *kind {
+                        "ascii" => PlyFormat::Ascii,
+                        "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                        "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                        _ => return Err(invalid_data(format!("unknown ply format: {}", kind))),
+                    });
+                }
+                ["element", name, count] => {
+                    current_element = name.to_string();
+                    if *name == "vertex" {
+                        if elements_seen > 0 {
+                            return Err(invalid_data(
+                                "ply vertex element must come first".to_string(),
+                            ));
+                        }
+                        vertex_count = Some(count.parse().map_err(|_| {
+                            invalid_data(format!("invalid vertex count: {}", count))
+                        })?);
+                    }
+                    elements_seen += 1;
+                }
+                ["property", "list", ..] if current_element == "vertex" => {
+                    return Err(invalid_data(
+                        "list properties on vertices are not supported".to_string(),
+                    ));
+                }
+                ["property", kind, name] if current_element == "vertex" => {
+                    let kind = PlyScalar::parse(kind)
+                        .ok_or_else(|| invalid_data(format!("unknown ply type: {}", kind)))?;
+                    properties.push(PlyProperty {
+                        name: name.to_string(),
+                        kind,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        Ok(PlyHeader {
+            format: format.ok_or_else(|| invalid_data("ply format line missing".to_string()))?,
+            vertex_count: vertex_count
+                .ok_or_else(|| invalid_data("ply vertex element missing".to_string()))?,
+            properties,
+        })
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A binary PLY of the given points, each as float32 x y z between a uchar and a double
+    fn binary_ply(points: &[[f32; 3]], little: bool) -> Vec<u8> {
+        let format = if little {
+            "binary_little_endian"
+        } else {
+            "binary_big_endian"
+        };
+        let mut bytes = format!(
+            "ply\nformat {} 1.0\nelement vertex {}\nproperty uchar flag\n\
+             property float x\nproperty float y\nproperty float z\nproperty double weight\n\
+             element face 0\nproperty list uchar int vertex_indices\nend_header\n",
+            format,
+            points.len()
+        )
+        .into_bytes();
+        for point in points {
+            bytes.push(7);
+            for v in point {
+                bytes.extend(if little {
+                    v.to_le_bytes()
+                } else {
+                    v.to_be_bytes()
+                });
+            }
+            let weight = 0.5f64;
+            bytes.extend(if little {
+                weight.to_le_bytes()
+            } else {
+                weight.to_be_bytes()
+            });
+        }
+        bytes
+    }
+
+    #[test]
+    fn reads_binary_ply_in_either_byte_order() {
+        let points = [[1.5, -2.25, 3.0], [0.0, 100.125, -0.5]];
+        for little in [true, false] {
+            let cloud = PointCloud::from_ply(&binary_ply(&points, little)[..], 0.1).unwrap();
+            let read: Vec<[Float; 3]> = cloud.points.iter().map(|p| [p.0, p.1, p.2]).collect();
+            let expected: Vec<[Float; 3]> = points.iter().map(|p| p.map(Float::from)).collect();
+            assert_eq!(read, expected, "little endian: {}", little);
+        }
+    }
+
+    #[test]
+    fn reads_ascii_ply_and_xyz() {
+        let ply = "ply\nformat ascii 1.0\ncomment made by hand\nelement vertex 2\n\
+                   property float x\nproperty float y\nproperty float z\n\
+                   property float nx\nproperty float ny\nproperty float nz\nend_header\n\
+                   1 2 3 0 0 2\n-1 0.5 4 0 1 0\n";
+        let cloud = PointCloud::from_ply(ply.as_bytes(), 0.1).unwrap();
+        assert_eq!(cloud.points, [Vec3f(1.0, 2.0, 3.0), Vec3f(-1.0, 0.5, 4.0)]);
+        assert_eq!(cloud.splat(), Splat::Disk);
+        assert_eq!(cloud.normals.unwrap()[0], Vec3f(0.0, 0.0, 1.0));
+
+        let xyz = "# x y z\n1 2 3\n\n4,5,6\n";
+        let cloud = PointCloud::from_xyz(xyz.as_bytes(), 0.1).unwrap();
+        assert_eq!(cloud.points, [Vec3f(1.0, 2.0, 3.0), Vec3f(4.0, 5.0, 6.0)]);
+        assert_eq!(cloud.splat(), Splat::Sphere);
+        let normals = PointCloud::from_xyz("0 0 0 0 3 0\n".as_bytes(), 0.1).unwrap();
+        assert_eq!(normals.normals.unwrap(), [Vec3f(0.0, 1.0, 0.0)]);
+        for bad in ["1 2\n", "1 2 three\n"] {
+            assert!(
+                PointCloud::from_xyz(bad.as_bytes(), 0.1).is_err(),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_truncated_and_malformed_ply() {
+        let mut truncated = binary_ply(&[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]], true);
+        truncated.truncate(truncated.len() - 5);
+        assert!(PointCloud::from_ply(&truncated[..], 0.1).is_err());
+
+        let header = |lines: &str| format!("ply\nformat ascii 1.0\n{}end_header\n", lines);
+        let xyz = "property float x\nproperty float y\nproperty float z\n";
+        let bad = [
+            "plx\nformat ascii 1.0\nend_header\n".to_string(),
+            "ply\nformat ascii 1.0\nelement vertex 1\n".to_string(),
+            "ply\nformat utf16 1.0\nend_header\n".to_string(),
+            header(&format!("element vertex 3\n{}1 2 3\n", xyz)),
+            header(&format!("element vertex 1\n{}", xyz)) + "1 2 x\n",
+            header(&format!("element vertex many\n{}", xyz)),
+            header("element vertex 1\nproperty float x\nproperty float y\n"),
+            header(&format!(
+                "element vertex 1\n{}property list uchar int i\n",
+                xyz
+            )),
+            header(&format!("element vertex 1\n{}property half w\n", xyz)),
+            header(&format!("element face 0\nelement vertex 1\n{}", xyz)),
+            header(xyz),
+        ];
+        for text in &bad {
+            let error = PointCloud::from_ply(text.as_bytes(), 0.1).err();
+            assert!(error.is_some(), "{}", text);
+        }
+
+        // A count no file could hold is an error once the data runs out, not an abort
+        let huge = header(&format!("element vertex {}\n{}", u64::MAX, xyz)) + "1 2 3\n";
+        let error = PointCloud::from_ply(huge.as_bytes(), 0.1).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let mut huge = binary_ply(&[[1.0, 2.0, 3.0]], true);
+        let at = huge.windows(3).position(|w| w == b" 1\n").unwrap();
+        huge.splice(at + 1..at + 2, u64::MAX.to_string().bytes());
+        assert!(PointCloud::from_ply(&huge[..], 0.1).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+// Two surfaces joined so that a ray meeting one carries on out of the other. The
+// transform takes the entrance's side of space onto the exit's, so a ray arriving at a
+// point on the entrance leaves from where that point maps to, turned the same way; rays
+// meeting the exit go back through the inverse. Pairs facing each other repeat forever,
+// like mirrors, and the integrator gives up after MAX_PORTAL_HOPS. Flat ends such as
+// triangle meshes work best, since a ray leaving a solid end meets the rest of it.
+use crate::differential::{AuxiliaryRays, RayDifferential};
+use crate::transform::Transform;
+use crate::vec3::Float;
+
+// How many portals one ray is followed through before the last one shows as a surface
+pub const MAX_PORTAL_HOPS: u32 = 16;
+
+// Rays leave the far portal this many meters out, so they do not land on it again straight away
+const EXIT_STEP: Float = 1e-3;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Portal {
+    pub entrance: u32,
+    pub exit: u32,
+    transform: Transform,
+    inverse: Transform,
+}
+
+impl Portal {
+    // None when the transform squashes space flat and so cannot be walked back
+    pub fn new(entrance: u32, exit: u32, transform: Transform) -> Option<Portal> {
+        Some(Portal {
+            entrance,
+            exit,
+            transform,
+            inverse: transform.inverse()?,
+        })
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    // The ray leaving the other end once ray meets object_id at distance t, if the object
+    // is either end, in a scene with this many units to the meter
+    pub fn pass(
+        &self,
+        object_id: u32,
+        ray: &RayDifferential,
+        t: Float,
+        units_per_meter: Float,
+    ) -> Option<RayDifferential> {
+        let transform = if object_id == self.entrance {
+            &self.transform
+        } else if object_id == self.exit {
+            &self.inverse
+        } else {
+            return None;
+        };
+        let dir = transform.vector(&ray.dir).normalized()?;
+        let orig = transform.point(&(ray.orig + ray.dir * t)) + dir * (EXIT_STEP * units_per_meter);
+        // The neighbouring pixels' rays pass through the same way
+        let aux = ray.aux.map(|aux| AuxiliaryRays {
+            rx_orig: transform.point(&aux.rx_orig),
+            rx_dir: transform.vector(&aux.rx_dir),
+            ry_orig: transform.point(&aux.ry_orig),
+            ry_dir: transform.vector(&aux.ry_dir),
+        });
+        Some(RayDifferential { orig, dir, aux })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::RED_RUBBER;
+    use crate::scene::Scene;
+    use crate::shapes::Sphere;
+    use crate::vec3::{consts::PI, Vec3f};
+
+    fn close(a: Vec3f, b: Vec3f) -> bool {
+        (a - b).length() < 1e-4
+    }
+
+    #[test]
+    fn carries_rays_out_of_the_other_end_and_back() {
+        // Step through a door at z = 0 and come out of one at x = 10, turned a quarter left
+        let transform = Transform::rotation(Vec3f(0.0, 1.0, 0.0), PI / 2.0)
+            .then(&Transform::translation(Vec3f(10.0, 0.0, 0.0)));
+        let portal = Portal::new(1, 2, transform).unwrap();
+        let ray = RayDifferential::new(Vec3f(0.5, 0.0, 2.0), Vec3f(0.0, 0.0, -1.0));
+        assert!(portal.pass(3, &ray, 2.0, 1.0).is_none());
+
+        let out = portal.pass(1, &ray, 2.0, 1.0).unwrap();
+        let turned = transform.vector(&ray.dir);
+        assert!(close(out.dir, turned));
+        let landing = transform.point(&Vec3f(0.5, 0.0, 0.0));
+        assert!(close(out.orig, landing + turned * EXIT_STEP));
+        // The step out is in meters, so it grows with the scene's units
+        let centimeters = portal.pass(1, &ray, 2.0, 100.0).unwrap();
+        assert!(close(
+            centimeters.orig,
+            landing + turned * (100.0 * EXIT_STEP)
+        ));
+
+        // Going back in through the exit undoes both the turn and the step out
+        let back = RayDifferential::new(out.orig + out.dir, -out.dir);
+        let returned = portal.pass(2, &back, 1.0, 1.0).unwrap();
+        assert!(close(returned.dir, Vec3f(0.0, 0.0, 1.0)));
+        assert!(close(returned.orig, Vec3f(0.5, 0.0, 0.0)));
+
+        // Nothing can walk back through space squashed flat
+        let flat = Transform::scaling(Vec3f(1.0, 0.0, 1.0));
+        assert!(Portal::new(1, 2, flat).is_none());
+    }
+
+    #[test]
+    fn joins_only_objects_that_exist_and_stops_their_shadows() {
+        let mut scene = Scene::new();
+        let a = scene.add(Sphere::new(Vec3f(0.0, 0.0, -5.0), 1.0), RED_RUBBER);
+        let b = scene.add(Sphere::new(Vec3f(5.0, 0.0, -5.0), 1.0), RED_RUBBER);
+        let shift = Transform::translation(Vec3f(5.0, 0.0, 0.0));
+        assert!(!scene.add_portal(Portal::new(a, 99, shift).unwrap()));
+        assert!(scene.objects().iter().all(|o| o.visibility.shadow));
+        assert!(scene.add_portal(Portal::new(a, b, shift).unwrap()));
+        assert!(scene.objects().iter().all(|o| !o.visibility.shadow));
+        let below = Vec3f(0.0, -3.0, -5.0);
+        assert!(!scene.occluded(&below, &Vec3f(0.0, 1.0, 0.0), 10.0));
+    }
+}
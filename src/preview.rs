@@ -0,0 +1,127 @@
+// Material swatches: one material on a ball in a small photo studio, a gray backdrop
+// sweeping from a checkered floor up a back wall under key, fill and rim lights, so a
+// library of materials renders into thumbnails that compare side by side.
+use crate::camera::Camera;
+use crate::examples_scenes::Example;
+use crate::framebuffer::Framebuffer;
+use crate::light::{AmbientLight, Light};
+use crate::material::{Material, RED_RUBBER};
+use crate::render::{render, RenderSettings};
+use crate::scene::{Checkerboard, Scene};
+use crate::shapes::{Quad, Sphere};
+use crate::vec3::{consts::PI, Vec3f};
+
+// The width and height of a preview in pixels
+pub const PREVIEW_SIZE: usize = 160;
+
+const BACKDROP: Material = Material {
+    albedo: [1.0, 0.05, 0.0, 0.0],
+    diffuse_color: Vec3f(0.5, 0.5, 0.5),
+    ..RED_RUBBER
+};
+
+// The studio with a ball of material three units across resting on the floor at the
+// origin, big beside the floor's two-unit checks
+pub fn shader_ball(material: Material) -> Example {
+    let mut scene = Scene::new();
+    scene.background = Vec3f(0.45, 0.45, 0.45);
+    scene.add(Sphere::new(Vec3f(0.0, 1.5, 0.0), 1.5), material);
+    scene.add(
+        Quad::new(
+            Vec3f(-12.0, 0.0, -5.0),
+            Vec3f(24.0, 0.0, 0.0),
+            Vec3f(0.0, 15.0, 0.0),
+        ),
+        BACKDROP,
+    );
+    // Checks show what a clear or mirrored ball bends and reflects
+    scene.floor = Some(Checkerboard {
+        height: 0.0,
+        min: (-12.0, -5.0),
+        max: (12.0, 12.0),
+        colors: [Vec3f(0.55, 0.55, 0.55), Vec3f(0.3, 0.3, 0.3)],
+    });
+
+    let light = |position: Vec3f, intensity, radius| Light {
+        radius,
+        ..Light::new(position, intensity)
+    };
+    scene.add_light(light(Vec3f(-6.0, 7.5, 6.0), 1.2, 1.2));
+    scene.add_light(light(Vec3f(7.5, 3.0, 4.5), 0.5, 2.0));
+    scene.add_light(light(Vec3f(3.0, 6.0, -4.0), 0.8, 0.8));
+    // Keeps the side away from the lights from going black
+    scene.ambient_lights.push(AmbientLight {
+        color: Vec3f(0.08, 0.08, 0.08),
+    });
+
+    let camera = Camera::new(Vec3f(0.0, 2.8, 9.5), PI / 6.0).looking_at(Vec3f(0.0, 1.3, 0.0));
+    (scene, camera)
+}
+
+// A PREVIEW_SIZE square swatch of material, antialiased
+pub fn preview_material(material: &Material) -> Framebuffer {
+    let (scene, camera) = shader_ball(*material);
+    scene.build_bvh();
+    let settings = RenderSettings {
+        width: PREVIEW_SIZE,
+        height: PREVIEW_SIZE,
+        samples_per_pixel: 4,
+        ..RenderSettings::default()
+    };
+    render(&scene, &camera, &settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_studio_frames_the_ball_resting_on_its_floor() {
+        let (scene, camera) = shader_ball(RED_RUBBER);
+        assert!(scene.validate().is_empty(), "{:?}", scene.validate());
+        let ball = scene.objects()[0].shape.bounds();
+        assert!(ball.min.1.abs() < 1e-5 && (ball.max.1 - 3.0).abs() < 1e-5);
+        // The camera looks at the ball from in front, with the light on it from above
+        let dir = (camera.target - camera.position).normalized().unwrap();
+        let hit = scene.intersect(&camera.position, &dir).unwrap();
+        assert_eq!(hit.object_id, scene.objects()[0].id);
+        assert!(scene.lights.iter().all(|light| light.position.1 > 1.5));
+    }
+
+    #[test]
+    fn previews_differ_only_where_the_ball_is() {
+        let preview = preview_material(&RED_RUBBER);
+        assert_eq!(
+            (preview.width, preview.height),
+            (PREVIEW_SIZE, PREVIEW_SIZE)
+        );
+        let middle = preview.get(PREVIEW_SIZE / 2, PREVIEW_SIZE / 2);
+        assert!(middle.0 > 2.0 * middle.2, "{:?}", middle);
+
+        // The whole ball fits, so the studio round the edges renders the same for any
+        let size = 40;
+        let settings = RenderSettings {
+            width: size,
+            height: size,
+            threads: Some(2),
+            ..RenderSettings::default()
+        };
+        let swatch = |material| {
+            let (scene, camera) = shader_ball(material);
+            render(&scene, &camera, &settings)
+        };
+        let blue = Material {
+            diffuse_color: Vec3f(0.1, 0.2, 0.9),
+            ..RED_RUBBER
+        };
+        let (red, blue) = (swatch(RED_RUBBER), swatch(blue));
+        let (r, b) = (red.get(size / 2, size / 2), blue.get(size / 2, size / 2));
+        assert!(b.2 > b.0, "{:?} {:?}", r, b);
+        let last = size - 1;
+        for i in 0..size {
+            for (x, y) in [(i, 0), (i, last), (0, i), (last, i)] {
+                assert_eq!(red.get(x, y), blue.get(x, y), "at {} {}", x, y);
+            }
+        }
+    }
+}
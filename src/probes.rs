@@ -0,0 +1,213 @@
+// Light probes for real-time global illumination: a grid of points through the scene,
+// each with the light arriving at it from every direction squeezed into a few spherical
+// harmonic coefficients per color channel (Ramamoorthi and Hanrahan 2001). Order 1 keeps
+// the 4 coefficients of bands 0 and 1, which hold the average and the direction most light
+// comes from; order 2 adds band 2's 5 for 9, as much as diffuse lighting needs.
+//
+// Coefficients are of radiance, projected over uniformly sampled directions in world
+// space, in the usual real basis order: Y00, then Y1-1 Y10 Y11 (y, z, x), then Y2-2 Y2-1
+// Y20 Y21 Y22. Engines convolve them with the cosine lobe to light a surface.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::bvh::Aabb;
+use crate::onb;
+use crate::path_debug::json_number;
+use crate::render::{incoming_radiance, run_workers, RenderSettings};
+use crate::rng::Rng;
+use crate::scene::Scene;
+use crate::vec3::{consts::PI, Float, Vec3f};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ProbeGrid {
+    // Probes along x, y and z, each at the middle of its cell of bounds
+    pub counts: [usize; 3],
+    // None spreads them over the scene's bounds
+    pub bounds: Option<Aabb>,
+    // 1 or 2
+    pub order: usize,
+    // Rays traced from each probe
+    pub samples: u32,
+}
+
+impl Default for ProbeGrid {
+    fn default() -> ProbeGrid {
+        ProbeGrid {
+            counts: [4, 4, 4],
+            bounds: None,
+            order: 2,
+            samples: 256,
+        }
+    }
+}
+
+pub struct Probe {
+    pub position: Vec3f,
+    // (order + 1)^2 of them, red, green and blue together
+    pub coefficients: Vec<Vec3f>,
+}
+
+// The probes of a grid, x varying fastest, then y, then z
+pub struct Probes {
+    pub counts: [usize; 3],
+    pub bounds: Aabb,
+    pub order: usize,
+    pub probes: Vec<Probe>,
+}
+
+impl ProbeGrid {
+    // Traces the light arriving at every probe in scene; settings give the depth bounced
+    // light is followed to, the seed and the threads used
+    pub fn bake(&self, scene: &Scene, settings: &RenderSettings) -> io::Result<Probes> {
+        if !(1..=2).contains(&self.order) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("probes hold order 1 or 2 harmonics, not {}", self.order),
+            ));
+        }
+        let bounds = self.bounds.unwrap_or_else(|| scene.bounds());
+        if bounds.min.0 > bounds.max.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the scene has nothing to place probes around",
+            ));
+        }
+        let [nx, ny, nz] = self.counts.map(|n| n.max(1));
+        let size = bounds.max - bounds.min;
+        let positions: Vec<Vec3f> = (0..nx * ny * nz)
+            .map(|i| {
+                let (x, y, z) = (i % nx, i / nx % ny, i / (nx * ny));
+                let at = |i: usize, n: usize| (i as Float + 0.5) / n as Float;
+                bounds.min + Vec3f(size.0 * at(x, nx), size.1 * at(y, ny), size.2 * at(z, nz))
+            })
+            .collect();
+
+        let count = (self.order + 1) * (self.order + 1);
+        let side = ((self.samples.max(1) as Float).sqrt().ceil() as u32).max(1);
+        // Each direction stands for an equal share of the sphere
+        let weight = 4.0 * PI / (side * side) as Float;
+        let baked = Mutex::new((0..positions.len()).map(|_| Vec::new()).collect::<Vec<_>>());
+        let next = AtomicUsize::new(0);
+        let threads = settings
+            .threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+            .clamp(1, positions.len());
+        run_workers(threads, None, || loop {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            if i >= positions.len() {
+                break;
+            }
+            let mut rng = Rng::for_stream(settings.seed, i as u64);
+            let mut coefficients = vec![Vec3f(0.0, 0.0, 0.0); count];
+            for s in 0..side * side {
+                let u1 = ((s % side) as Float + rng.next_float()) / side as Float;
+                let u2 = ((s / side) as Float + rng.next_float()) / side as Float;
+                let dir = onb::uniform_sphere(u1, u2);
+                let radiance = incoming_radiance(scene, &positions[i], &dir, settings, &mut rng);
+                for (c, y) in coefficients.iter_mut().zip(basis(&dir)) {
+                    *c += radiance * (y * weight);
+                }
+            }
+            baked.lock().unwrap()[i] = coefficients;
+        });
+        let probes = positions
+            .into_iter()
+            .zip(baked.into_inner().unwrap())
+            .map(|(position, coefficients)| Probe {
+                position,
+                coefficients,
+            })
+            .collect();
+        Ok(Probes {
+            counts: [nx, ny, nz],
+            bounds,
+            order: self.order,
+            probes,
+        })
+    }
+}
+
+impl Probes {
+    pub fn write_json(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let vec = |v: &Vec3f| {
+            format!(
+                "[{}, {}, {}]",
+                json_number(v.0),
+                json_number(v.1),
+                json_number(v.2)
+            )
+        };
+        let [nx, ny, nz] = self.counts;
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"order\": {},", self.order)?;
+        writeln!(out, "  \"counts\": [{}, {}, {}],", nx, ny, nz)?;
+        writeln!(out, "  \"min\": {},", vec(&self.bounds.min))?;
+        writeln!(out, "  \"max\": {},", vec(&self.bounds.max))?;
+        writeln!(out, "  \"probes\": [")?;
+        for (i, probe) in self.probes.iter().enumerate() {
+            let coefficients: Vec<String> = probe.coefficients.iter().map(vec).collect();
+            writeln!(
+                out,
+                "    {{\"position\": {}, \"sh\": [{}]}}{}",
+                vec(&probe.position),
+                coefficients.join(", "),
+                if i + 1 < self.probes.len() { "," } else { "" }
+            )?;
+        }
+        writeln!(out, "  ]\n}}")?;
+        out.flush()
+    }
+
+    // Little-endian: the bytes "RRSH", then as u32s a version of 1, the order and the
+    // three counts, then the bounds' min and max as six f32s, then for each probe in turn
+    // its position and its coefficients as f32 red, green, blue triples
+    pub fn write_binary(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(b"RRSH")?;
+        for n in [
+            1,
+            self.order,
+            self.counts[0],
+            self.counts[1],
+            self.counts[2],
+        ] {
+            out.write_all(&(n as u32).to_le_bytes())?;
+        }
+        let mut floats = |v: &Vec3f| -> io::Result<()> {
+            for x in [v.0, v.1, v.2] {
+                out.write_all(&(x as f32).to_le_bytes())?;
+            }
+            Ok(())
+        };
+        floats(&self.bounds.min)?;
+        floats(&self.bounds.max)?;
+        for probe in &self.probes {
+            floats(&probe.position)?;
+            for c in &probe.coefficients {
+                floats(c)?;
+            }
+        }
+        out.flush()
+    }
+}
+
+// The real spherical harmonics up to band 2 at the unit direction d
+fn basis(d: &Vec3f) -> [Float; 9] {
+    let Vec3f(x, y, z) = *d;
+    [
+        0.282_095,
+        0.488_603 * y,
+        0.488_603 * z,
+        0.488_603 * x,
+        1.092_548 * x * y,
+        1.092_548 * y * z,
+        0.315_392 * (3.0 * z * z - 1.0),
+        1.092_548 * x * z,
+        0.546_274 * (x * x - y * y),
+    ]
+}
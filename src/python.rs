@@ -0,0 +1,1048 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::bloom::Bloom;
+use crate::camera::{Aperture, ApertureMask, ApertureShape, Camera, Lens};
+use crate::clip::ClipPlane;
+use crate::denoise::Denoise;
+use crate::environment::{CubeMap, Environment};
+use crate::exposure::AutoExposure;
+use crate::framebuffer::Framebuffer;
+use crate::ior;
+use crate::irradiance_cache::IrradianceCaching;
+use crate::light::{
+    blackbody, AmbientLight, HemisphereLight, Light, LightLinks, TEMPERATURE_RANGE,
+};
+use crate::material::{
+    Anisotropy, BlendMaterial, Emission, Material, Principled, ShadowCatcher, Sides, ThinFilm,
+};
+use crate::mesh::TriangleMesh;
+use crate::portal::Portal;
+use crate::preview;
+use crate::render::{render, Integrator, RenderSettings};
+use crate::sampler::Sampler;
+use crate::scene::{Checkerboard, Scene, Visibility};
+use crate::scene_file::{SceneFile, MATERIAL_NAMES};
+use crate::shapes::{
+    BoxUv, Cone, Cube, Cylinder, Ovoid, Pyramid, Quad, RecgtangularPrism, Sphere, Torus,
+};
+use crate::texture::{ImageTexture, Mapping, TextureMap, Wrap};
+use crate::transform::Transform;
+use crate::turntable::Turntable;
+use crate::vec3::{Float, Vec3f};
+use crate::vox::VoxFile;
+use crate::voxel::VoxelOctree;
+
+// Points and colors arrive as any sequence of three numbers
+fn vec3(value: Vec<Float>) -> PyResult<Vec3f> {
+    match value.as_slice() {
+        [x, y, z] => Ok(Vec3f(*x, *y, *z)),
+        _ => Err(PyValueError::new_err(format!(
+            "expected three numbers, got {}",
+            value.len()
+        ))),
+    }
+}
+
+fn sides(name: &str) -> PyResult<Sides> {
+    match name {
+        "front" => Ok(Sides::Front),
+        "both" => Ok(Sides::Both),
+        "cull" => Ok(Sides::Cull),
+        other => Err(PyValueError::new_err(format!(
+            "unknown sides {}; expected front, both or cull",
+            other
+        ))),
+    }
+}
+
+fn box_uv(name: &str) -> PyResult<BoxUv> {
+    match name {
+        "faces" => Ok(BoxUv::Faces),
+        "cross" => Ok(BoxUv::Cross),
+        other => Err(PyValueError::new_err(format!(
+            "unknown uv layout {}; expected faces or cross",
+            other
+        ))),
+    }
+}
+
+fn tuple(v: &Vec3f) -> (Float, Float, Float) {
+    (v.0, v.1, v.2)
+}
+
+#[pyclass(name = "Material", module = "rusty_rays", skip_from_py_object)]
+struct PyMaterial {
+    inner: Material,
+}
+
+#[pymethods]
+impl PyMaterial {
+    #[new]
+    // thin_film is (thickness in nanometres, refractive index), anisotropy (roughness
+    // along the tangent, roughness across it, tangent rotation in degrees); medium names
+    // one of the presets in ior::MEDIA and replaces refractive_index; emission is the
+    // color the surface glows, from behind as well when two_sided_emission is set
+    #[pyo3(signature = (diffuse = vec![0.8, 0.8, 0.8], albedo = [1.0, 0.0, 0.0, 0.0], specular_exponent = 1.0, refractive_index = 1.0, thin_film = None, anisotropy = None, sides = "front", medium = None, emission = None, two_sided_emission = false))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        diffuse: Vec<Float>,
+        albedo: [Float; 4],
+        specular_exponent: Float,
+        refractive_index: Float,
+        thin_film: Option<(Float, Float)>,
+        anisotropy: Option<(Float, Float, Float)>,
+        sides: &str,
+        medium: Option<&str>,
+        emission: Option<Vec<Float>>,
+        two_sided_emission: bool,
+    ) -> PyResult<PyMaterial> {
+        let dispersion = medium
+            .map(|name| {
+                ior::medium(name)
+                    .ok_or_else(|| PyValueError::new_err(format!("unknown medium {}", name)))
+            })
+            .transpose()?;
+        let emission = match emission {
+            Some(color) => Some(Emission {
+                color: vec3(color)?,
+                two_sided: two_sided_emission,
+            }),
+            None => None,
+        };
+        Ok(PyMaterial {
+            inner: Material {
+                refractive_index: dispersion.map_or(refractive_index, |d| d.nd()),
+                albedo,
+                diffuse_color: vec3(diffuse)?,
+                specular_exponent,
+                thin_film: thin_film.map(|(thickness, refractive_index)| ThinFilm {
+                    thickness,
+                    refractive_index,
+                }),
+                anisotropy: anisotropy.map(|(along, across, rotation)| Anisotropy {
+                    roughness: (along, across),
+                    rotation: rotation.to_radians(),
+                }),
+                specular_color: None,
+                clearcoat: None,
+                sheen: None,
+                sides: self::sides(sides)?,
+                dispersion,
+                shadow_catcher: None,
+                priority: 0,
+                emission,
+            },
+        })
+    }
+
+    // Burley's principled parameters, with Blender's defaults
+    #[staticmethod]
+    #[pyo3(signature = (base_color = vec![0.8, 0.8, 0.8], metallic = 0.0, roughness = 0.5, specular = 0.5, clearcoat = 0.0, clearcoat_roughness = 0.03, sheen = 0.0, sheen_tint = 0.5, transmission = 0.0, refractive_index = 1.45))]
+    #[allow(clippy::too_many_arguments)]
+    fn principled(
+        base_color: Vec<Float>,
+        metallic: Float,
+        roughness: Float,
+        specular: Float,
+        clearcoat: Float,
+        clearcoat_roughness: Float,
+        sheen: Float,
+        sheen_tint: Float,
+        transmission: Float,
+        refractive_index: Float,
+    ) -> PyResult<PyMaterial> {
+        let principled = Principled {
+            base_color: vec3(base_color)?,
+            metallic,
+            roughness,
+            specular,
+            clearcoat,
+            clearcoat_roughness,
+            sheen,
+            sheen_tint,
+            transmission,
+            refractive_index,
+        };
+        Ok(PyMaterial {
+            inner: principled.into(),
+        })
+    }
+
+    // An invisible ground for compositing: camera rays see only the shadows cast on it,
+    // and with reflections above 0 the objects mirrored in it
+    #[staticmethod]
+    #[pyo3(signature = (reflections = 0.0))]
+    fn shadow_catcher(reflections: Float) -> PyResult<PyMaterial> {
+        if !(0.0..=1.0).contains(&reflections) {
+            return Err(PyValueError::new_err("reflections must be between 0 and 1"));
+        }
+        Ok(PyMaterial {
+            inner: Material {
+                shadow_catcher: Some(ShadowCatcher { reflections }),
+                ..Material::from(Principled {
+                    roughness: 1.0,
+                    specular: 0.0,
+                    ..Principled::default()
+                })
+            },
+        })
+    }
+
+    // One of the built-in materials, by its scene-file name
+    #[staticmethod]
+    fn named(name: &str) -> PyResult<PyMaterial> {
+        MATERIAL_NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, material)| PyMaterial { inner: *material })
+            .ok_or_else(|| PyValueError::new_err(format!("unknown material {}", name)))
+    }
+
+    #[getter]
+    fn diffuse(&self) -> (Float, Float, Float) {
+        tuple(&self.inner.diffuse_color)
+    }
+
+    #[getter]
+    fn albedo(&self) -> [Float; 4] {
+        self.inner.albedo
+    }
+
+    #[getter]
+    fn specular_exponent(&self) -> Float {
+        self.inner.specular_exponent
+    }
+
+    #[getter]
+    fn refractive_index(&self) -> Float {
+        self.inner.refractive_index
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.inner)
+    }
+}
+
+#[pyclass(name = "Camera", module = "rusty_rays", skip_from_py_object)]
+struct PyCamera {
+    inner: Camera,
+}
+
+#[pymethods]
+impl PyCamera {
+    // Angles in degrees; hfov fixes the horizontal field of view instead of the vertical.
+    // exposure is compensation in stops; the lens flaws are as in camera::Lens.
+    #[new]
+    #[pyo3(signature = (position = vec![0.0, 0.0, 0.0], fov = 60.0, target = None, up = None, hfov = None, near = 0.0, far = None, exposure = 0.0, vignetting = 0.0, distortion = 0.0, chromatic_aberration = 0.0))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        position: Vec<Float>,
+        fov: Float,
+        target: Option<Vec<Float>>,
+        up: Option<Vec<Float>>,
+        hfov: Option<Float>,
+        near: Float,
+        far: Option<Float>,
+        exposure: Float,
+        vignetting: Float,
+        distortion: Float,
+        chromatic_aberration: Float,
+    ) -> PyResult<PyCamera> {
+        let far = far.unwrap_or(Float::INFINITY);
+        if near < 0.0 || far <= near {
+            return Err(PyValueError::new_err("clipping needs 0 <= near < far"));
+        }
+        let mut camera = Camera::new(vec3(position)?, fov.to_radians());
+        if let Some(target) = target {
+            camera = camera.looking_at(vec3(target)?);
+        }
+        if let Some(up) = up {
+            camera.up = vec3(up)?;
+        }
+        if let Some(hfov) = hfov {
+            camera = camera.with_horizontal_fov(hfov.to_radians());
+        }
+        Ok(PyCamera {
+            inner: camera
+                .with_clipping(near, far)
+                .with_exposure_compensation(exposure)
+                .with_lens(Lens {
+                    vignetting,
+                    distortion,
+                    chromatic_aberration,
+                }),
+        })
+    }
+
+    // Opens the lens up from a pinhole so only the focus plane is sharp; focus_distance
+    // defaults to the target's, and blades or a mask image shape the out-of-focus blur
+    #[pyo3(signature = (radius, focus_distance = None, blades = None, rotation = 0.0, mask = None))]
+    fn set_aperture(
+        &mut self,
+        radius: Float,
+        focus_distance: Option<Float>,
+        blades: Option<u32>,
+        rotation: Float,
+        mask: Option<PathBuf>,
+    ) -> PyResult<()> {
+        let camera = &self.inner;
+        let focus_distance =
+            focus_distance.unwrap_or_else(|| (camera.target - camera.position).length());
+        if radius < 0.0 || focus_distance <= 0.0 {
+            return Err(PyValueError::new_err(
+                "aperture needs a radius of at least 0 and a positive focus_distance",
+            ));
+        }
+        let mut aperture = Aperture::new(radius, focus_distance);
+        match (blades, mask) {
+            (Some(_), Some(_)) => {
+                return Err(PyValueError::new_err("give blades or mask, not both"))
+            }
+            (Some(count), None) if count < 3 => {
+                return Err(PyValueError::new_err("blades must be at least 3"))
+            }
+            (Some(count), None) => {
+                aperture.shape = ApertureShape::Blades {
+                    count,
+                    rotation: rotation.to_radians(),
+                }
+            }
+            (None, Some(path)) => {
+                let image =
+                    ImageTexture::load(&path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+                aperture.shape = ApertureShape::Mask(ApertureMask::new(Arc::new(image)));
+            }
+            (None, None) => {}
+        }
+        self.inner.aperture = Some(aperture);
+        Ok(())
+    }
+
+    // A camera for each frame of a turn around the scene, to render in order; elevation is
+    // in degrees, and without a radius the camera backs off until the scene fits
+    #[pyo3(signature = (scene, frames = 36, radius = None, elevation = 20.0))]
+    fn turntable(
+        &self,
+        scene: PyRef<'_, PyScene>,
+        frames: usize,
+        radius: Option<Float>,
+        elevation: Float,
+    ) -> PyResult<Vec<PyCamera>> {
+        if frames == 0 || radius.is_some_and(|r| r <= 0.0) || elevation.abs() >= 90.0 {
+            return Err(PyValueError::new_err(
+                "a turntable needs a frame, a positive radius and an elevation under 90 degrees",
+            ));
+        }
+        let turntable = Turntable {
+            frames,
+            radius,
+            elevation: elevation.to_radians(),
+        };
+        Ok(turntable
+            .cameras(&scene.inner, &self.inner)
+            .into_iter()
+            .map(|inner| PyCamera { inner })
+            .collect())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.inner)
+    }
+}
+
+#[pyclass(name = "Scene", module = "rusty_rays")]
+struct PyScene {
+    inner: Scene,
+}
+
+impl PyScene {
+    fn material(material: Option<PyRef<PyMaterial>>) -> Material {
+        material.map_or(MATERIAL_NAMES[0].1, |m| m.inner)
+    }
+}
+
+// Every add_* method returns the new object's ID
+#[pymethods]
+impl PyScene {
+    #[new]
+    fn new() -> PyScene {
+        PyScene {
+            inner: Scene::new(),
+        }
+    }
+
+    // A scene file's scene and camera
+    #[staticmethod]
+    fn load(path: PathBuf) -> PyResult<(PyScene, PyCamera)> {
+        let file = SceneFile::load(&path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        Ok((
+            PyScene { inner: file.scene },
+            PyCamera { inner: file.camera },
+        ))
+    }
+
+    #[pyo3(signature = (center, radius, material = None))]
+    fn add_sphere(
+        &mut self,
+        center: Vec<Float>,
+        radius: Float,
+        material: Option<PyRef<PyMaterial>>,
+    ) -> PyResult<u32> {
+        let shape = Sphere::new(vec3(center)?, radius);
+        Ok(self.inner.add(shape, PyScene::material(material)))
+    }
+
+    // uv is "faces" or "cross", for the layout textures take on the faces
+    #[pyo3(signature = (min, max, material = None, uv = "faces"))]
+    fn add_box(
+        &mut self,
+        min: Vec<Float>,
+        max: Vec<Float>,
+        material: Option<PyRef<PyMaterial>>,
+        uv: &str,
+    ) -> PyResult<u32> {
+        let shape = RecgtangularPrism::new(vec3(min)?, vec3(max)?).with_uv_layout(box_uv(uv)?);
+        Ok(self.inner.add(shape, PyScene::material(material)))
+    }
+
+    #[pyo3(signature = (center, size, material = None, uv = "faces"))]
+    fn add_cube(
+        &mut self,
+        center: Vec<Float>,
+        size: Float,
+        material: Option<PyRef<PyMaterial>>,
+        uv: &str,
+    ) -> PyResult<u32> {
+        let shape = Cube::new(vec3(center)?, size).with_uv_layout(box_uv(uv)?);
+        Ok(self.inner.add(shape, PyScene::material(material)))
+    }
+
+    // A parallelogram from corner along the edges u and v, facing along u crossed with v
+    #[pyo3(signature = (corner, u, v, material = None))]
+    fn add_quad(
+        &mut self,
+        corner: Vec<Float>,
+        u: Vec<Float>,
+        v: Vec<Float>,
+        material: Option<PyRef<PyMaterial>>,
+    ) -> PyResult<u32> {
+        let shape = Quad::new(vec3(corner)?, vec3(u)?, vec3(v)?);
+        Ok(self.inner.add(shape, PyScene::material(material)))
+    }
+
+    #[pyo3(signature = (apex, height, radius, material = None))]
+    fn add_cone(
+        &mut self,
+        apex: Vec<Float>,
+        height: Float,
+        radius: Float,
+        material: Option<PyRef<PyMaterial>>,
+    ) -> PyResult<u32> {
+        let shape = Cone::new(vec3(apex)?, height, radius);
+        Ok(self.inner.add(shape, PyScene::material(material)))
+    }
+
+    #[pyo3(signature = (base, height, radius, material = None))]
+    fn add_cylinder(
+        &mut self,
+        base: Vec<Float>,
+        height: Float,
+        radius: Float,
+        material: Option<PyRef<PyMaterial>>,
+    ) -> PyResult<u32> {
+        let shape = Cylinder::new(vec3(base)?, height, radius);
+        Ok(self.inner.add(shape, PyScene::material(material)))
+    }
+
+    #[pyo3(signature = (base, height, half_base_length, material = None))]
+    fn add_pyramid(
+        &mut self,
+        base: Vec<Float>,
+        height: Float,
+        half_base_length: Float,
+        material: Option<PyRef<PyMaterial>>,
+    ) -> PyResult<u32> {
+        let shape = Pyramid::new(vec3(base)?, height, half_base_length);
+        Ok(self.inner.add(shape, PyScene::material(material)))
+    }
+
+    #[pyo3(signature = (center, radii, material = None))]
+    fn add_ovoid(
+        &mut self,
+        center: Vec<Float>,
+        radii: Vec<Float>,
+        material: Option<PyRef<PyMaterial>>,
+    ) -> PyResult<u32> {
+        let shape = Ovoid::new(vec3(center)?, vec3(radii)?);
+        Ok(self.inner.add(shape, PyScene::material(material)))
+    }
+
+    #[pyo3(signature = (center, tube_radius, radius, material = None))]
+    fn add_torus(
+        &mut self,
+        center: Vec<Float>,
+        tube_radius: Float,
+        radius: Float,
+        material: Option<PyRef<PyMaterial>>,
+    ) -> PyResult<u32> {
+        let shape = Torus::new(vec3(center)?, tube_radius, radius);
+        Ok(self.inner.add(shape, PyScene::material(material)))
+    }
+
+    #[pyo3(signature = (vertices, faces, normals = None, material = None))]
+    fn add_mesh(
+        &mut self,
+        vertices: Vec<Vec<Float>>,
+        faces: Vec<[usize; 3]>,
+        normals: Option<Vec<Vec<Float>>>,
+        material: Option<PyRef<PyMaterial>>,
+    ) -> PyResult<u32> {
+        let vertices = vertices
+            .into_iter()
+            .map(vec3)
+            .collect::<PyResult<Vec<_>>>()?;
+        if let Some(face) = faces.iter().flatten().find(|&&i| i >= vertices.len()) {
+            return Err(PyValueError::new_err(format!(
+                "face index {} out of range",
+                face
+            )));
+        }
+        let shape = match normals {
+            Some(normals) => {
+                let normals = normals
+                    .into_iter()
+                    .map(vec3)
+                    .collect::<PyResult<Vec<_>>>()?;
+                if normals.len() != vertices.len() {
+                    return Err(PyValueError::new_err("every vertex needs a normal"));
+                }
+                TriangleMesh::with_normals(vertices, normals, faces)
+            }
+            None => TriangleMesh::new(vertices, faces),
+        };
+        Ok(self.inner.add(shape, PyScene::material(material)))
+    }
+
+    // Whole-number grid positions, with one color each or all white; origin is the outer
+    // corner of voxel (0, 0, 0)
+    #[pyo3(signature = (positions, colors = None, origin = vec![0.0, 0.0, 0.0], size = 1.0, material = None))]
+    fn add_voxels(
+        &mut self,
+        positions: Vec<[u32; 3]>,
+        colors: Option<Vec<Vec<Float>>>,
+        origin: Vec<Float>,
+        size: Float,
+        material: Option<PyRef<PyMaterial>>,
+    ) -> PyResult<u32> {
+        let colors = match colors {
+            Some(colors) if colors.len() != positions.len() => {
+                return Err(PyValueError::new_err("every voxel needs a color"))
+            }
+            Some(colors) => colors.into_iter().map(vec3).collect::<PyResult<Vec<_>>>()?,
+            None => vec![Vec3f(1.0, 1.0, 1.0); positions.len()],
+        };
+        let voxels: Vec<_> = positions.into_iter().zip(colors).collect();
+        let shape = VoxelOctree::new(&voxels, vec3(origin)?, size);
+        Ok(self.inner.add(shape, PyScene::material(material)))
+    }
+
+    // A model from a MagicaVoxel file as one octree, colored by the file's palette on top
+    // of material
+    #[pyo3(signature = (path, model = 0, origin = vec![0.0, 0.0, 0.0], size = 1.0, material = None))]
+    fn add_vox(
+        &mut self,
+        path: PathBuf,
+        model: usize,
+        origin: Vec<Float>,
+        size: Float,
+        material: Option<PyRef<PyMaterial>>,
+    ) -> PyResult<u32> {
+        let file = VoxFile::load(&path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        if model >= file.models.len() {
+            return Err(PyValueError::new_err(format!(
+                "model {} out of range; the file has {}",
+                model,
+                file.models.len()
+            )));
+        }
+        let shape = file.octree(model, vec3(origin)?, size);
+        Ok(self.inner.add(shape, PyScene::material(material)))
+    }
+
+    // include or exclude restricts the light to, or keeps it off, the listed object IDs;
+    // a radius makes it a sphere light, and its color is RGB or a temperature in kelvin
+    #[pyo3(signature = (position, intensity = 1.0, include = None, exclude = None, radius = 0.0, color = None, temperature = None))]
+    #[allow(clippy::too_many_arguments)]
+    fn add_light(
+        &mut self,
+        position: Vec<Float>,
+        intensity: Float,
+        include: Option<Vec<u32>>,
+        exclude: Option<Vec<u32>>,
+        radius: Float,
+        color: Option<Vec<Float>>,
+        temperature: Option<Float>,
+    ) -> PyResult<()> {
+        let color = match (color, temperature) {
+            (Some(_), Some(_)) => {
+                return Err(PyValueError::new_err("give color or temperature, not both"))
+            }
+            (Some(color), None) => vec3(color)?,
+            (None, Some(kelvin)) => {
+                let (low, high) = TEMPERATURE_RANGE;
+                if !(low..=high).contains(&kelvin) {
+                    return Err(PyValueError::new_err(format!(
+                        "temperature must be between {} and {} kelvin",
+                        low, high
+                    )));
+                }
+                blackbody(kelvin)
+            }
+            (None, None) => Vec3f(1.0, 1.0, 1.0),
+        };
+        if radius < 0.0 {
+            return Err(PyValueError::new_err("radius must not be negative"));
+        }
+        let links = match (include, exclude) {
+            (Some(_), Some(_)) => {
+                return Err(PyValueError::new_err("give include or exclude, not both"))
+            }
+            (Some(ids), None) => LightLinks::Only(ids),
+            (None, Some(ids)) => LightLinks::Except(ids),
+            (None, None) => LightLinks::All,
+        };
+        self.inner.add_light(
+            Light::new(vec3(position)?, intensity)
+                .with_links(links)
+                .with_color(color)
+                .with_radius(radius),
+        );
+        Ok(())
+    }
+
+    fn add_ambient_light(&mut self, color: Vec<Float>) -> PyResult<()> {
+        let color = vec3(color)?;
+        self.inner.ambient_lights.push(AmbientLight { color });
+        Ok(())
+    }
+
+    #[pyo3(signature = (sky, ground, up = vec![0.0, 1.0, 0.0]))]
+    fn add_hemisphere_light(
+        &mut self,
+        sky: Vec<Float>,
+        ground: Vec<Float>,
+        up: Vec<Float>,
+    ) -> PyResult<()> {
+        let light = HemisphereLight::new(vec3(sky)?, vec3(ground)?, vec3(up)?)
+            .ok_or_else(|| PyValueError::new_err("up has no direction"))?;
+        self.inner.hemisphere_lights.push(light);
+        Ok(())
+    }
+
+    // Links two objects so rays meeting one carry on out of the other, moved by rotate
+    // (degrees about x, y and z) then translate; raises unless both IDs exist
+    #[pyo3(signature = (entrance, exit, translate = vec![0.0, 0.0, 0.0], rotate = vec![0.0, 0.0, 0.0]))]
+    fn add_portal(
+        &mut self,
+        entrance: u32,
+        exit: u32,
+        translate: Vec<Float>,
+        rotate: Vec<Float>,
+    ) -> PyResult<()> {
+        let rotate = vec3(rotate)?;
+        let transform = Transform::euler(Vec3f(
+            rotate.0.to_radians(),
+            rotate.1.to_radians(),
+            rotate.2.to_radians(),
+        ))
+        .then(&Transform::translation(vec3(translate)?));
+        let portal = Portal::new(entrance, exit, transform)
+            .ok_or_else(|| PyValueError::new_err("the transform cannot be inverted"))?;
+        if entrance != exit && self.inner.add_portal(portal) {
+            Ok(())
+        } else {
+            Err(PyValueError::new_err(format!(
+                "no portal between objects {} and {}",
+                entrance, exit
+            )))
+        }
+    }
+
+    // Cuts away every object on the side of the plane normal faces, covering the cut
+    // through solids in the cap color if one is given
+    #[pyo3(signature = (point, normal, cap = None))]
+    fn add_clip_plane(
+        &mut self,
+        point: Vec<Float>,
+        normal: Vec<Float>,
+        cap: Option<Vec<Float>>,
+    ) -> PyResult<()> {
+        let plane = ClipPlane::new(vec3(point)?, vec3(normal)?, cap.map(vec3).transpose()?)
+            .ok_or_else(|| PyValueError::new_err("normal must not be zero"))?;
+        self.inner.clip_planes.push(plane);
+        Ok(())
+    }
+
+    // The checkerboard floor; min and max are (x, z) corners
+    #[pyo3(signature = (height = -4.0, min = (-10.0, -30.0), max = (10.0, -10.0), colors = None))]
+    fn set_floor(
+        &mut self,
+        height: Float,
+        min: (Float, Float),
+        max: (Float, Float),
+        colors: Option<(Vec<Float>, Vec<Float>)>,
+    ) -> PyResult<()> {
+        let colors = match colors {
+            Some((a, b)) => [vec3(a)?, vec3(b)?],
+            None => [Vec3f(0.3, 0.3, 0.3), Vec3f(0.3, 0.2, 0.1)],
+        };
+        self.inner.floor = Some(Checkerboard {
+            height,
+            min,
+            max,
+            colors,
+        });
+        Ok(())
+    }
+
+    fn remove_floor(&mut self) {
+        self.inner.floor = None;
+    }
+
+    // Scene units in a meter, which the renderer scales its tolerances by
+    #[getter]
+    fn units_per_meter(&self) -> Float {
+        self.inner.units_per_meter
+    }
+
+    #[setter]
+    fn set_units_per_meter(&mut self, value: Float) -> PyResult<()> {
+        if !(value > 0.0 && value.is_finite()) {
+            return Err(PyValueError::new_err("units per meter must be positive"));
+        }
+        self.inner.units_per_meter = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn background(&self) -> (Float, Float, Float) {
+        tuple(&self.inner.background)
+    }
+
+    #[setter]
+    fn set_background(&mut self, color: Vec<Float>) -> PyResult<()> {
+        self.inner.background = vec3(color)?;
+        Ok(())
+    }
+
+    // A photograph stretched over the frame behind everything the camera sees, for
+    // compositing onto; None removes it
+    #[pyo3(signature = (path = None))]
+    fn set_backplate(&mut self, path: Option<PathBuf>) -> PyResult<()> {
+        self.inner.backplate = match path {
+            Some(path) => {
+                let mut plate =
+                    ImageTexture::load(&path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+                plate.wrap = Wrap::Clamp;
+                Some(Arc::new(plate))
+            }
+            None => None,
+        };
+        Ok(())
+    }
+
+    // The sky escaping rays see: one path to an equirectangular image, or six to the
+    // cube map faces looking along +x, -x, +y, -y, +z and -z; no paths removes it
+    #[pyo3(signature = (*paths))]
+    fn set_environment(&mut self, paths: Vec<PathBuf>) -> PyResult<()> {
+        let load = |path: &PathBuf| {
+            ImageTexture::load(path).map_err(|e| PyIOError::new_err(e.to_string()))
+        };
+        self.inner.environment = match paths.as_slice() {
+            [] => None,
+            [path] => Some(Environment::lat_long(load(path)?)),
+            [px, nx, py, ny, pz, nz] => {
+                let faces = [
+                    load(px)?,
+                    load(nx)?,
+                    load(py)?,
+                    load(ny)?,
+                    load(pz)?,
+                    load(nz)?,
+                ];
+                let cube = CubeMap::new(faces).ok_or_else(|| {
+                    PyValueError::new_err("cube map faces must be square and all the same size")
+                })?;
+                Some(Environment::CubeMap(cube))
+            }
+            _ => {
+                return Err(PyValueError::new_err(
+                    "give one equirectangular image or six cube map faces",
+                ))
+            }
+        };
+        Ok(())
+    }
+
+    // Which rays see the object; raises if there is no object with this ID
+    #[pyo3(signature = (id, camera = true, shadow = true, reflection = true))]
+    fn set_visibility(
+        &mut self,
+        id: u32,
+        camera: bool,
+        shadow: bool,
+        reflection: bool,
+    ) -> PyResult<()> {
+        let visibility = Visibility {
+            camera,
+            shadow,
+            reflection,
+        };
+        if self.inner.set_visibility(id, visibility) {
+            Ok(())
+        } else {
+            Err(PyValueError::new_err(format!("no object with ID {}", id)))
+        }
+    }
+
+    // Raises if there is no object with this ID
+    fn set_name(&mut self, id: u32, name: &str) -> PyResult<()> {
+        if self.inner.set_name(id, name) {
+            Ok(())
+        } else {
+            Err(PyValueError::new_err(format!("no object with ID {}", id)))
+        }
+    }
+
+    // The ID of the object with this name, or None
+    fn find(&self, name: &str) -> Option<u32> {
+        self.inner.get(name).map(|object| object.id)
+    }
+
+    // Raises if there is no object with this name
+    fn set_material(&mut self, name: &str, material: PyRef<PyMaterial>) -> PyResult<()> {
+        match self.inner.get_mut(name) {
+            Some(object) => {
+                object.material = material.inner;
+                Ok(())
+            }
+            None => Err(PyValueError::new_err(format!("no object named {:?}", name))),
+        }
+    }
+
+    // Textures the named object with an image file, wrapped at the seam and clamped at
+    // the poles when latlong is set, as sphere maps want. Giving triplanar projects the
+    // image along the world axes instead, one copy covering that distance.
+    #[pyo3(signature = (name, path, latlong = false, triplanar = None))]
+    fn set_texture(
+        &mut self,
+        name: &str,
+        path: PathBuf,
+        latlong: bool,
+        triplanar: Option<Float>,
+    ) -> PyResult<()> {
+        let id = self
+            .inner
+            .get(name)
+            .map(|object| object.id)
+            .ok_or_else(|| PyValueError::new_err(format!("no object named {:?}", name)))?;
+        let mut texture =
+            ImageTexture::load(&path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        if latlong {
+            texture.wrap = Wrap::LatLong;
+        }
+        let mapping = match triplanar {
+            Some(size) if size > 0.0 => Mapping::triplanar(size),
+            Some(_) => return Err(PyValueError::new_err("triplanar size must be positive")),
+            None => Mapping::Uv,
+        };
+        self.inner
+            .set_texture(id, TextureMap::new(Arc::new(texture), mapping));
+        Ok(())
+    }
+
+    // Mixes material into the named object's own by factor, scaled by the brightness of
+    // the mask image at each hit when one is given
+    #[pyo3(signature = (name, material, factor = 1.0, mask = None))]
+    fn set_blend(
+        &mut self,
+        name: &str,
+        material: PyRef<PyMaterial>,
+        factor: Float,
+        mask: Option<PathBuf>,
+    ) -> PyResult<()> {
+        let id = self
+            .inner
+            .get(name)
+            .map(|object| object.id)
+            .ok_or_else(|| PyValueError::new_err(format!("no object named {:?}", name)))?;
+        let mut blend = BlendMaterial::new(material.inner, factor);
+        if let Some(path) = mask {
+            let texture =
+                ImageTexture::load(&path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+            blend = blend.with_mask(TextureMap::new(Arc::new(texture), Mapping::Uv));
+        }
+        self.inner.set_blend(id, blend);
+        Ok(())
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.objects().len()
+    }
+
+    // Scene::validate's findings, one line each
+    fn validate(&self) -> Vec<String> {
+        self.inner
+            .validate()
+            .iter()
+            .map(|d| d.to_string())
+            .collect()
+    }
+}
+
+// The image as a float32 numpy array of shape (height, width, 3), or 4 channels with
+// straight alpha when the background is transparent
+fn to_numpy<'py>(py: Python<'py>, image: &Framebuffer) -> PyResult<Bound<'py, PyAny>> {
+    let channels = if image.alpha.is_some() { 4 } else { 3 };
+    let mut bytes = Vec::with_capacity(image.width * image.height * channels * 4);
+    for (i, pixel) in image.pixels.iter().enumerate() {
+        let alpha = image.alpha.as_ref().map(|alpha| alpha[i] as f32);
+        // Colors are stored premultiplied; numpy users expect the straight value
+        let scale = match alpha {
+            Some(a) if a > 0.0 => 1.0 / a,
+            _ => 1.0,
+        };
+        for value in [pixel.0, pixel.1, pixel.2] {
+            bytes.extend_from_slice(&(value as f32 * scale).to_ne_bytes());
+        }
+        if let Some(a) = alpha {
+            bytes.extend_from_slice(&a.to_ne_bytes());
+        }
+    }
+    let numpy = py.import("numpy")?;
+    let flat = numpy.call_method1("frombuffer", (PyBytes::new(py, &bytes), "float32"))?;
+    flat.call_method1("reshape", ((image.height, image.width, channels),))
+}
+
+// bloom is (threshold, radius in pixels, intensity); regularize is the least roughness
+// surfaces take on once a path has bounced off something diffuse or glossy;
+// irradiance_cache is (accuracy, samples per record); denoise is (radius in pixels,
+// strength); auto_exposure is the luminance the frame's average is exposed to
+#[pyfunction]
+#[pyo3(name = "render", signature = (scene, camera, width = 1024, height = 768, samples = 1, max_depth = 4, integrator = "whitted", sampler = "random", seed = 0, transparent = false, threads = None, deterministic = false, bloom = None, regularize = 0.0, irradiance_cache = None, denoise = None, auto_exposure = None))]
+#[allow(clippy::too_many_arguments)]
+fn render_scene<'py>(
+    py: Python<'py>,
+    scene: PyRef<'py, PyScene>,
+    camera: PyRef<'py, PyCamera>,
+    width: usize,
+    height: usize,
+    samples: u32,
+    max_depth: u32,
+    integrator: &str,
+    sampler: &str,
+    seed: u64,
+    transparent: bool,
+    threads: Option<usize>,
+    deterministic: bool,
+    bloom: Option<(Float, Float, Float)>,
+    regularize: Float,
+    irradiance_cache: Option<(Float, u32)>,
+    denoise: Option<(usize, Float)>,
+    auto_exposure: Option<Float>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let integrator = match integrator {
+        "whitted" => Integrator::Whitted,
+        "path" => Integrator::Path,
+        other => {
+            return Err(PyValueError::new_err(format!(
+                "unknown integrator {}; expected whitted or path",
+                other
+            )))
+        }
+    };
+    let sampler = Sampler::from_name(sampler)
+        .ok_or_else(|| PyValueError::new_err(format!("unknown sampler {}", sampler)))?;
+    if width == 0 || height == 0 {
+        return Err(PyValueError::new_err("width and height must be positive"));
+    }
+    if irradiance_cache.is_some_and(|(accuracy, samples)| accuracy <= 0.0 || samples == 0) {
+        return Err(PyValueError::new_err(
+            "irradiance_cache needs a positive accuracy and samples",
+        ));
+    }
+    if denoise.is_some_and(|(radius, strength)| radius == 0 || strength <= 0.0) {
+        return Err(PyValueError::new_err(
+            "denoise needs a positive radius and strength",
+        ));
+    }
+    if !(0.0..=1.0).contains(&regularize) {
+        return Err(PyValueError::new_err("regularize must be between 0 and 1"));
+    }
+    if auto_exposure.is_some_and(|key| key <= 0.0) {
+        return Err(PyValueError::new_err("auto_exposure needs a positive key"));
+    }
+    let settings = RenderSettings {
+        width,
+        height,
+        samples_per_pixel: samples,
+        max_depth,
+        integrator,
+        sampler,
+        seed,
+        threads,
+        regularize,
+        irradiance_cache: irradiance_cache
+            .map(|(accuracy, samples)| IrradianceCaching { accuracy, samples }),
+        transparent_background: transparent,
+        deterministic,
+        denoise: denoise.map(|(radius, strength)| Denoise { radius, strength }),
+        bloom: bloom.map(|(threshold, radius, intensity)| Bloom {
+            threshold,
+            radius,
+            intensity,
+        }),
+        auto_exposure: auto_exposure.map(|key| AutoExposure {
+            key,
+            ..AutoExposure::default()
+        }),
+        ..RenderSettings::default()
+    };
+    let (scene, camera) = (&scene.inner, &camera.inner);
+    // Other Python threads keep running while the workers render
+    let image = py.detach(|| render(scene, camera, &settings));
+    to_numpy(py, &image)
+}
+
+// The material on a ball in a small studio, as preview::preview_material renders it
+#[pyfunction]
+fn preview_material<'py>(
+    py: Python<'py>,
+    material: PyRef<'py, PyMaterial>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let material = material.inner;
+    let image = py.detach(|| preview::preview_material(&material));
+    to_numpy(py, &image)
+}
+
+#[pymodule]
+fn rusty_rays(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyMaterial>()?;
+    module.add_class::<PyCamera>()?;
+    module.add_class::<PyScene>()?;
+    module.add_function(wrap_pyfunction!(render_scene, module)?)?;
+    module.add_function(wrap_pyfunction!(preview_material, module)?)?;
+    module.add(
+        "MATERIAL_NAMES",
+        MATERIAL_NAMES
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>(),
+    )?;
+    Ok(())
+}
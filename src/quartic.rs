@@ -1,9 +1,43 @@
-const EPSILON: f32 = 1e-12;
+use std::ops::Deref;
 
-fn solve_quartic(coeffs: &[f32; 5], tolerance: f32 = 1e-12) -> Vec<f32> {
-    let [a, b, c, d, e] = *coeffs;
-    
-    if a.abs() < tolerance {
+use crate::vec3::Float;
+
+const EPSILON: f64 = 1e-12;
+
+// Up to four real roots held inline, since the solvers run once per ray
+#[derive(Clone, Copy, Debug)]
+pub struct Roots<T> {
+    values: [T; 4],
+    len: usize,
+}
+
+impl<T: Copy + Default> Roots<T> {
+    fn new() -> Roots<T> {
+        Roots {
+            values: [T::default(); 4],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, root: T) {
+        self.values[self.len] = root;
+        self.len += 1;
+    }
+}
+
+impl<T> Deref for Roots<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.values[..self.len]
+    }
+}
+
+// Roots are computed in f64 and polished with Newton steps; Float Ferrari is too noisy for tori.
+pub fn solve_quartic(coeffs: &[Float; 5]) -> Roots<Float> {
+    let [a, b, c, d, e] = coeffs.map(f64::from);
+
+    if a.abs() < EPSILON {
         panic!("The leading coefficient must not be zero.");
     }
 
@@ -14,91 +48,110 @@ fn solve_quartic(coeffs: &[f32; 5], tolerance: f32 = 1e-12) -> Vec<f32> {
 
     let sq = b * b;
 
+    // Depressed quartic y^4 + p*y^2 + q*y + r with x = y - b/4
     let p = -3.0 / 8.0 * sq + c;
     let q = 1.0 / 8.0 * sq * b - 0.5 * b * c + d;
     let r = -3.0 / 256.0 * sq * sq + c * sq / 16.0 - 1.0 / 4.0 * b * d + e;
 
-    // Degenerate case: quartic reduces to cubic
-    if r.abs() < tolerance {
-        return solve_cubic(&[1.0, b, c, d]);
-    }
-
-    let cubic_coeffs = [
-        1.0,
-        0.5 * p,
-        -r,
-        -0.25 * q * q,
-    ];
-
-    let z = solve_cubic(&cubic_coeffs).into_iter().next().unwrap_or(0.0);
-
-    let d1 = 2.0 * z - p;
-    let d2 = if d1.abs() < tolerance {
-        -q / (2.0 * z).sqrt()
+    let mut roots = Roots::new();
+
+    if q.abs() < EPSILON {
+        // Biquadratic: solve for y^2
+        for &z in solve_quadratic(&[1.0, p, r]).iter() {
+            if z >= 0.0 {
+                let y = z.sqrt();
+                roots.push(y);
+                roots.push(-y);
+            }
+        }
     } else {
-        q / (2.0 * z)
-    };
-
-    let quadratic1 = [
-        1.0,
-        -z.sqrt(),
-        z - d2,
-    ];
-
-    let quadratic2 = [
-        1.0,
-        z.sqrt(),
-        z + d2,
-    ];
-
-    let mut roots = vec![];
+        // Ferrari's resolvent cubic always has a positive root when q != 0
+        let cubic_coeffs = [1.0, p, 0.25 * p * p - r, -0.125 * q * q];
+        let m = solve_cubic(&cubic_coeffs)
+            .iter()
+            .fold(0.0f64, |a, &b| a.max(b));
+        if m <= 0.0 {
+            return Roots::new();
+        }
+
+        let s = (2.0 * m).sqrt();
+        let quadratic1 = [1.0, s, 0.5 * p + m - q / (2.0 * s)];
+        let quadratic2 = [1.0, -s, 0.5 * p + m + q / (2.0 * s)];
+
+        for &y in solve_quadratic(&quadratic1)
+            .iter()
+            .chain(solve_quadratic(&quadratic2).iter())
+        {
+            roots.push(y);
+        }
+    }
 
-    roots.extend(solve_quadratic(&quadratic1));
-    roots.extend(solve_quadratic(&quadratic2));
+    let mut polished = Roots::new();
+    for &y in roots.iter() {
+        polished.push(polish(&[1.0, b, c, d, e], y - 0.25 * b) as Float);
+    }
+    polished
+}
 
-    roots
+fn polish(coeffs: &[f64; 5], mut x: f64) -> f64 {
+    for _ in 0..2 {
+        let f = (((coeffs[0] * x + coeffs[1]) * x + coeffs[2]) * x + coeffs[3]) * x + coeffs[4];
+        let df = ((4.0 * coeffs[0] * x + 3.0 * coeffs[1]) * x + 2.0 * coeffs[2]) * x + coeffs[3];
+        if df.abs() < EPSILON {
+            break;
+        }
+        x -= f / df;
+    }
+    x
 }
 
-fn solve_cubic(coeffs: &[f32; 4]) -> Vec<f32> {
+fn solve_cubic(coeffs: &[f64; 4]) -> Roots<f64> {
     let a = coeffs[0];
     let b = coeffs[1] / a;
     let c = coeffs[2] / a;
     let d = coeffs[3] / a;
 
-    let delta_0 = c * c - 3.0 * b * d + 12.0 * a * e;
-    let delta_1 = 2.0 * c * c * c - 9.0 * b * c * d + 27.0 * a * d * d + 27.0 * b * b * e - 72.0 * a * c * e;
+    // Depressed cubic t^3 + p*t + q with x = t - b/3
+    let p = c - b * b / 3.0;
+    let q = 2.0 * b * b * b / 27.0 - b * c / 3.0 + d;
+    let shift = b / 3.0;
 
-    let discriminant = 18.0 * a * b * c * d - 4.0 * b.powi(3) * d + b.powi(2) * c.powi(2) - 4.0 * a * c.powi(3) - 27.0 * a.powi(2) * d.powi(2);
+    let discriminant = 0.25 * q * q + p * p * p / 27.0;
 
-    let mut roots = Vec::new();
+    let mut roots = Roots::new();
 
-    if discriminant.abs() > EPSILON {
-        // 3 real roots
+    if discriminant > EPSILON {
+        // 1 real root
         let sd = discriminant.sqrt();
-        let u = cbrt(-q / 2.0 + sd);
-        let v = cbrt(-q / 2.0 - sd);
+        let u = (-0.5 * q + sd).cbrt();
+        let v = (-0.5 * q - sd).cbrt();
 
-        roots.push(u + v - b / (3.0 * a));
-    } else if discriminant.abs() < EPSILON {
+        roots.push(u + v - shift);
+    } else if discriminant.abs() <= EPSILON {
         // 2 real roots (1 double root and 1 single root)
-        let double_root = 9.0 * a * d - b * c;
-        let single_root = (9.0 * a * c - b.powi(2)) / double_root;
-    
-        roots.push(double_root);
-        roots.push(single_root);
-    }
-
+        if p.abs() < EPSILON {
+            roots.push(-shift);
+        } else {
+            let single_root = 3.0 * q / p;
+            let double_root = -1.5 * q / p;
+
+            roots.push(single_root - shift);
+            roots.push(double_root - shift);
+        }
     } else {
-        // 1 real root
-        let c_cube_root = ((delta_1 + (delta_1 * delta_1 - 4.0 * delta_0 * delta_0 * delta_0).sqrt()).powf(1.0/3.0)) / (3.0f32.cbrt() * 2.0f32.powf(1.0/3.0));
-        roots.push((-1.0/(3.0*a))*(b + c_cube_root + delta_0/c_cube_root));
+        // 3 real roots
+        let radius = 2.0 * (-p / 3.0).sqrt();
+        let theta = ((3.0 * q / (p * radius)).clamp(-1.0, 1.0)).acos() / 3.0;
+        for k in 0..3 {
+            let angle = theta - 2.0 * std::f64::consts::PI * k as f64 / 3.0;
+            roots.push(radius * angle.cos() - shift);
+        }
     }
 
     roots
 }
 
-
-fn solve_quadratic(coeffs: &[f32; 3]) -> Vec<f32> {
+fn solve_quadratic(coeffs: &[f64; 3]) -> Roots<f64> {
     let (a, b, c) = (coeffs[0], coeffs[1], coeffs[2]);
 
     if a.abs() < EPSILON {
@@ -107,21 +160,14 @@ fn solve_quadratic(coeffs: &[f32; 3]) -> Vec<f32> {
 
     let discriminant = b * b - 4.0 * a * c;
 
-    if discriminant < 0.0 {
-        vec![]
-    } else if discriminant.abs() < EPSILON {
-        vec![-b / (2.0 * a)]
-    } else {
+    let mut roots = Roots::new();
+    if discriminant >= EPSILON {
         let sqrt_discriminant = discriminant.sqrt();
         let denominator = 2.0 * a;
-        vec![
-            (-b + sqrt_discriminant) / denominator,
-            (-b - sqrt_discriminant) / denominator,
-        ]
+        roots.push((-b + sqrt_discriminant) / denominator);
+        roots.push((-b - sqrt_discriminant) / denominator);
+    } else if discriminant >= 0.0 {
+        roots.push(-b / (2.0 * a));
     }
-}
-
-
-fn cbrt(x: f32) -> f32 {
-    x.signum() * x.abs().powf(1.0 / 3.0)
+    roots
 }
@@ -0,0 +1,2944 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bloom::Bloom;
+use crate::camera::Camera;
+use crate::denoise::Denoise;
+use crate::differential::RayDifferential;
+use crate::exposure::AutoExposure;
+use crate::filter::PixelFilter;
+use crate::framebuffer::Framebuffer;
+use crate::irradiance_cache::{IrradianceCache, IrradianceCaching, IrradianceRecord};
+use crate::light::{reflect, refract};
+use crate::lod::View;
+use crate::log::{self, Level};
+use crate::material::{Anisotropy, Material, ShadowCatcher};
+use crate::medium_stack::MediumStack;
+use crate::onb::{self, Onb};
+use crate::path_debug::{PathEvent, PathTrace};
+use crate::portal::MAX_PORTAL_HOPS;
+use crate::rng::Rng;
+use crate::sampler::Sampler;
+use crate::scene::{
+    with_camera_culling, CameraCulling, Intersection, RayKind, Scene, BACKGROUND_ID,
+};
+use crate::shapes::HitRecord;
+use crate::stats::{self, ObjectStats, RayStats};
+use crate::tiles::tile_grid;
+pub use crate::tiles::{TileOrder, TileRect};
+use crate::vec3::{consts::PI, Float, Vec3f};
+use crate::volume::Volume;
+
+const SMALL_NUMBER: Float = 0.001;
+// Isotropic phase function, scaled by pi like the Lambert term of the light model
+const ISOTROPIC_PHASE: Float = 0.25;
+
+// How many surfaces inside overlapping solids a ray passes through before it gives up
+// and takes what it lands on
+const MAX_FALSE_HITS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    // Deterministic recursive reflection/refraction; ignores participating media
+    Whitted,
+    // Monte Carlo path tracing with diffuse interreflection and volumes
+    Path,
+}
+
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    pub integrator: Integrator,
+    // In [0, 1]: once a path has bounced off something diffuse or glossy, no surface it
+    // meets afterwards is smoother than this roughness. Sharp lobes seen only through other
+    // bounces are what throw fireflies, and blurring them trades a little accuracy for
+    // much less noise. Zero keeps every bounce exact.
+    pub regularize: Float,
+    // Path tracing only: diffuse light bounced onto the surfaces the camera sees comes
+    // from a cache filled before the frame, rather than a path per sample
+    pub irradiance_cache: Option<IrradianceCaching>,
+    pub seed: u64,
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    // Worker threads; None uses every available core
+    pub threads: Option<usize>,
+    pub filter: PixelFilter,
+    pub sampler: Sampler,
+    // Primary rays that escape the scene leave alpha at zero instead of showing the background
+    pub transparent_background: bool,
+    // Track how much of each pixel every object covers, for the ID pass and mattes
+    pub object_ids: bool,
+    // Track the spread of each pixel's samples, for the standard deviation AOV
+    pub variance: bool,
+    // Only render this pixel region; the rest of the frame is left empty
+    pub crop: Option<TileRect>,
+    // Merge tiles in grid order rather than as they finish, so overlapping filter
+    // footprints always sum in the same order and repeated renders match bit for bit
+    pub deterministic: bool,
+    // Smooths away sampling noise once the frame is done, before any bloom
+    pub denoise: Option<Denoise>,
+    // Spreads the brightest light into a glow once the frame is done
+    pub bloom: Option<Bloom>,
+    // Scales the camera's exposure to suit the scene, from a prepass before the frame
+    pub auto_exposure: Option<AutoExposure>,
+}
+
+// Named output sizes accepted wherever a resolution is
+pub const RESOLUTION_PRESETS: [(&str, usize, usize); 4] = [
+    ("720p", 1280, 720),
+    ("1080p", 1920, 1080),
+    ("4k", 3840, 2160),
+    ("square", 1024, 1024),
+];
+
+// Width and height for a preset name or an explicit "WIDTHxHEIGHT"
+pub fn parse_resolution(value: &str) -> Option<(usize, usize)> {
+    if let Some(&(_, width, height)) = RESOLUTION_PRESETS
+        .iter()
+        .find(|(name, _, _)| name.eq_ignore_ascii_case(value))
+    {
+        return Some((width, height));
+    }
+    let (width, height) = value.split_once(['x', 'X'])?;
+    let (width, height) = (width.parse().ok()?, height.parse().ok()?);
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            width: 1024,
+            height: 768,
+            samples_per_pixel: 1,
+            max_depth: 4,
+            integrator: Integrator::Whitted,
+            regularize: 0.0,
+            irradiance_cache: None,
+            seed: 0,
+            tile_size: 16,
+            tile_order: TileOrder::Scanline,
+            threads: None,
+            filter: PixelFilter::Box,
+            sampler: Sampler::Random,
+            transparent_background: false,
+            object_ids: false,
+            variance: false,
+            crop: None,
+            deterministic: false,
+            denoise: None,
+            bloom: None,
+            auto_exposure: None,
+        }
+    }
+}
+
+// Hooks called from the render workers while a frame is in flight. Tiles arrive in
+// completion order with their pixels row-major; a scanline is reported once every tile
+// covering it is done, so rows can arrive out of order across tile bands. With a crop
+// only the cropped span of each row is reported. The end hook runs once the workers
+// have stopped, with the rays they traced. Workers ask whether the render is cancelled
+// before each tile, and once it is they stop and the frame comes back with only the
+// tiles already done.
+pub trait RenderObserver: Sync {
+    fn on_render_start(&self, _width: usize, _height: usize, _tile_count: usize) {}
+    fn on_tile_complete(&self, _tile: &TileRect, _pixels: &[Vec3f]) {}
+    fn on_scanline_complete(&self, _y: usize, _pixels: &[Vec3f]) {}
+    fn on_render_end(&self, _stats: &RayStats) {}
+    // Which objects the rays ended on, reported after on_render_end when built with the
+    // stats feature
+    fn on_object_stats(&self, _stats: &ObjectStats) {}
+    fn cancelled(&self) -> bool {
+        false
+    }
+}
+
+impl RenderObserver for () {}
+
+impl<F: Fn(&TileRect, &[Vec3f]) + Sync> RenderObserver for F {
+    fn on_tile_complete(&self, tile: &TileRect, pixels: &[Vec3f]) {
+        self(tile, pixels)
+    }
+}
+
+pub fn render(scene: &Scene, camera: &Camera, settings: &RenderSettings) -> Framebuffer {
+    render_with(scene, camera, settings, &())
+}
+
+pub fn render_with(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    observer: &dyn RenderObserver,
+) -> Framebuffer {
+    if settings.auto_exposure.is_some() {
+        let (camera, settings) = auto_exposed(scene, camera, settings);
+        return render_with(scene, &camera, &settings, observer);
+    }
+    let (width, height) = (settings.width, settings.height);
+    scene.set_view(&View::new(camera, width, height));
+    let culling = camera_culling(scene, camera, width, height);
+    let tile_size = settings.tile_size.max(1);
+    let full = TileRect {
+        x0: 0,
+        y0: 0,
+        x1: width,
+        y1: height,
+    };
+
+    // Samples splat into neighbouring pixels, so tiles accumulate into a padded
+    // local buffer and a band of scanlines is final only once nearby bands are done.
+    // A crop region is sampled out to the filter radius, and resolved out as far as the
+    // denoiser looks, so its edges match a full render.
+    let margin = settings.filter.radius().ceil() as usize;
+    let reach = settings.denoise.map_or(0, |denoise| denoise.reach());
+    let band_margin = margin.div_ceil(tile_size);
+    let region = settings
+        .crop
+        .map_or(Some(full), |crop| crop.intersect(&full))
+        .unwrap_or(TileRect {
+            x0: 0,
+            y0: 0,
+            x1: 0,
+            y1: 0,
+        });
+    let resolved = region.expand(reach, &full);
+    let sampled = resolved.expand(margin, &full);
+    // The denoiser weighs pixels by how noisy they are
+    let variance = settings.variance || settings.denoise.is_some();
+
+    let tiles: Vec<TileRect> = tile_grid(width, height, tile_size, settings.tile_order)
+        .iter()
+        .filter_map(|tile| tile.intersect(&sampled))
+        .collect();
+    let tiles_y = height.div_ceil(tile_size);
+    let mut tiles_per_band = vec![0usize; tiles_y];
+    for tile in &tiles {
+        tiles_per_band[tile.y0 / tile_size] += 1;
+    }
+    let tile_count = tiles.len();
+    let next_tile = AtomicUsize::new(0);
+    let threads = settings
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+        .clamp(1, tile_count.max(1));
+
+    let shared = Mutex::new(Progress {
+        accumulator: Accumulator::new(sampled, settings.object_ids, variance),
+        tiles_done: vec![0; tiles_y],
+        emitted: vec![false; tiles_y],
+        pending: (0..tile_count).map(|_| None).collect(),
+        next_merge: 0,
+    });
+
+    // Indices into tiles in the order workers pick them up. Timing probes needs a clock,
+    // which wasm32-unknown-unknown does not have.
+    let mut schedule: Vec<usize> = (0..tile_count).collect();
+    if settings.tile_order == TileOrder::CostPredicted && !cfg!(target_arch = "wasm32") {
+        let costs = predict_tile_costs(scene, camera, settings, &tiles, threads);
+        schedule.sort_by(|&a, &b| costs[b].total_cmp(&costs[a]));
+    }
+
+    let cache = match settings.irradiance_cache {
+        Some(caching) if settings.integrator == Integrator::Path => Some(build_irradiance_cache(
+            scene, camera, settings, &caching, &region, threads, observer,
+        )),
+        _ => None,
+    };
+    let cache = cache.as_ref();
+
+    crate::debug!(
+        "rendering {}x{} at {} spp with {:?} and {:?} sampling: {} tiles on {} threads",
+        width,
+        height,
+        settings.samples_per_pixel.max(1),
+        settings.integrator,
+        settings.sampler,
+        tile_count,
+        threads
+    );
+    let start = log::timer(Level::Debug);
+    observer.on_render_start(width, height, tile_count);
+
+    let finish_tile = |progress: &mut Progress, rect: &TileRect, local: &Accumulator| {
+        progress.accumulator.merge(local);
+        crate::trace!(
+            "tile {}..{} x {}..{} done",
+            rect.x0,
+            rect.x1,
+            rect.y0,
+            rect.y1
+        );
+        observer.on_tile_complete(rect, &progress.accumulator.resolve_rect(rect));
+
+        let band = rect.y0 / tile_size;
+        progress.tiles_done[band] += 1;
+        let first = band.saturating_sub(band_margin);
+        let last = (band + band_margin).min(tiles_y - 1);
+        let ready: Vec<usize> = (first..=last)
+            .filter(|&candidate| {
+                let lo = candidate.saturating_sub(band_margin);
+                let hi = (candidate + band_margin).min(tiles_y - 1);
+                !progress.emitted[candidate]
+                    && (lo..=hi).all(|b| progress.tiles_done[b] == tiles_per_band[b])
+            })
+            .collect();
+        for candidate in ready {
+            progress.emitted[candidate] = true;
+            let rows = (candidate * tile_size).max(region.y0)
+                ..((candidate + 1) * tile_size).min(region.y1);
+            for y in rows {
+                let row = TileRect {
+                    x0: region.x0,
+                    y0: y,
+                    x1: region.x1,
+                    y1: y + 1,
+                };
+                observer.on_scanline_complete(y, &progress.accumulator.resolve_rect(&row));
+            }
+        }
+    };
+
+    let render_tiles = || loop {
+        if observer.cancelled() {
+            break;
+        }
+        let slot = next_tile.fetch_add(1, Ordering::Relaxed);
+        if slot >= tile_count {
+            break;
+        }
+        let index = schedule[slot];
+        let rect = tiles[index];
+        let padded = rect.expand(margin, &sampled);
+
+        let mut local = Accumulator::new(padded, settings.object_ids, variance);
+        for y in rect.y0..rect.y1 {
+            for x in rect.x0..rect.x1 {
+                sample_pixel(
+                    scene,
+                    camera,
+                    settings,
+                    cache,
+                    x,
+                    y,
+                    None,
+                    |sx, sy, sample| local.splat(&settings.filter, sx, sy, &sample),
+                );
+            }
+        }
+
+        let mut guard = shared.lock().unwrap();
+        let progress = &mut *guard;
+        if !settings.deterministic {
+            finish_tile(progress, &rect, &local);
+            continue;
+        }
+        // Hold finished tiles back until every tile before them in the grid is merged
+        progress.pending[index] = Some(local);
+        while let Some(local) = progress
+            .pending
+            .get_mut(progress.next_merge)
+            .and_then(Option::take)
+        {
+            let rect = tiles[progress.next_merge];
+            progress.next_merge += 1;
+            finish_tile(progress, &rect, &local);
+        }
+    };
+
+    let totals = Mutex::new(RayStats::ZERO);
+    let object_totals = Mutex::new(ObjectStats::default());
+    run_workers(threads, culling.as_ref(), || {
+        // Drop whatever this thread counted before the frame, cost probes included
+        stats::take();
+        stats::take_object_hits();
+        render_tiles();
+        *totals.lock().unwrap() += stats::take();
+        *object_totals.lock().unwrap() += &stats::take_object_hits();
+    });
+    let totals = totals.into_inner().unwrap();
+    if let Some(start) = start {
+        crate::debug!(
+            "rendered in {:.3}s, {} rays",
+            start.elapsed().as_secs_f64(),
+            totals.total_rays()
+        );
+    }
+    observer.on_render_end(&totals);
+    if cfg!(feature = "stats") {
+        observer.on_object_stats(&object_totals.into_inner().unwrap());
+    }
+
+    let progress = shared.into_inner().unwrap();
+    let mut accumulator = progress.accumulator;
+    // A cancelled deterministic render can leave finished tiles waiting on one that never ran
+    for local in progress.pending.iter().flatten() {
+        accumulator.merge(local);
+    }
+    let mut rendered = Framebuffer {
+        width: resolved.width(),
+        height: resolved.height(),
+        pixels: accumulator.resolve_rect(&resolved),
+        alpha: settings
+            .transparent_background
+            .then(|| accumulator.resolve_alpha(&resolved)),
+        coverage: accumulator.resolve_coverage(&resolved),
+        deviation: accumulator.resolve_deviation(&resolved),
+        metadata: Vec::new(),
+    };
+    if let Some(denoise) = &settings.denoise {
+        denoise.apply(&mut rendered, settings.samples_per_pixel);
+        if !settings.variance {
+            rendered.deviation = None;
+        }
+    }
+    if resolved != region {
+        rendered = rendered.cropped(&TileRect {
+            x0: region.x0 - resolved.x0,
+            y0: region.y0 - resolved.y0,
+            x1: region.x1 - resolved.x0,
+            y1: region.y1 - resolved.y0,
+        });
+    }
+    if let Some(bloom) = &settings.bloom {
+        bloom.apply(&mut rendered);
+    }
+    if region == full {
+        return rendered;
+    }
+    let mut image = Framebuffer::new(width, height);
+    image.paste(region.x0, region.y0, &rendered);
+    image
+}
+
+// Renders pass after pass of settings.samples_per_pixel, each under its own seed, for as
+// long as another pass looks like it fits in budget, and averages them with the denoiser
+// and bloom applied once at the end. The first pass always runs and matches a plain
+// render, and a pass cut short by cancelling is dropped. Returns the image and how many
+// passes went into it. Needs a clock, so not for wasm32.
+pub fn render_within(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    budget: Duration,
+    observer: &dyn RenderObserver,
+) -> (Framebuffer, u32) {
+    if settings.auto_exposure.is_some() {
+        let (camera, settings) = auto_exposed(scene, camera, settings);
+        return render_within(scene, &camera, &settings, budget, observer);
+    }
+    let start = Instant::now();
+    let pass_settings = |pass: u32| RenderSettings {
+        seed: settings.seed.wrapping_add(pass as u64),
+        variance: settings.variance || settings.denoise.is_some(),
+        denoise: None,
+        bloom: None,
+        ..settings.clone()
+    };
+    let mut image = render_with(scene, camera, &pass_settings(0), observer);
+    // Until the end deviation holds the mean square of the samples rather than their spread
+    let second_moments = |image: &mut Framebuffer| {
+        if let Some(deviation) = &mut image.deviation {
+            for (d, p) in deviation.iter_mut().zip(&image.pixels) {
+                *d = d.multiply(d) + p.multiply(p);
+            }
+        }
+    };
+    second_moments(&mut image);
+    let mut passes = 1;
+    while !observer.cancelled()
+        && start.elapsed().as_secs_f64() * (passes + 1) as f64 / passes as f64
+            <= budget.as_secs_f64()
+    {
+        let mut pass = render_with(scene, camera, &pass_settings(passes), observer);
+        // Half a pass would leave its missing tiles darker than the rest
+        if observer.cancelled() {
+            break;
+        }
+        second_moments(&mut pass);
+        passes += 1;
+        blend_pass(&mut image, &pass, 1.0 / passes as Float);
+    }
+    if let Some(deviation) = &mut image.deviation {
+        for (d, p) in deviation.iter_mut().zip(&image.pixels) {
+            let sigma = |square: Float, mean: Float| (square - mean * mean).max(0.0).sqrt();
+            *d = Vec3f(sigma(d.0, p.0), sigma(d.1, p.1), sigma(d.2, p.2));
+        }
+    }
+    if let Some(denoise) = &settings.denoise {
+        denoise.apply(&mut image, settings.samples_per_pixel * passes);
+        if !settings.variance {
+            image.deviation = None;
+        }
+    }
+    if let Some(bloom) = &settings.bloom {
+        bloom.apply(&mut image);
+    }
+    crate::debug!(
+        "rendered {} passes in {:.3}s of a {:.3}s budget",
+        passes,
+        start.elapsed().as_secs_f64(),
+        budget.as_secs_f64()
+    );
+    (image, passes)
+}
+
+// Moves every pixel of image the fraction share of the way towards pass's
+fn blend_pass(image: &mut Framebuffer, pass: &Framebuffer, share: Float) {
+    let mix = |a: Vec3f, b: Vec3f| a * (1.0 - share) + b * share;
+    for (a, b) in image.pixels.iter_mut().zip(&pass.pixels) {
+        *a = mix(*a, *b);
+    }
+    if let (Some(ours), Some(theirs)) = (&mut image.alpha, &pass.alpha) {
+        for (a, b) in ours.iter_mut().zip(theirs) {
+            *a = *a * (1.0 - share) + b * share;
+        }
+    }
+    if let (Some(ours), Some(theirs)) = (&mut image.deviation, &pass.deviation) {
+        for (a, b) in ours.iter_mut().zip(theirs) {
+            *a = mix(*a, *b);
+        }
+    }
+    if let (Some(ours), Some(theirs)) = (&mut image.coverage, &pass.coverage) {
+        for (entries, new) in ours.iter_mut().zip(theirs) {
+            for entry in entries.iter_mut() {
+                entry.1 *= 1.0 - share;
+            }
+            for &(id, weight) in new {
+                add_coverage(entries, id, weight * share);
+            }
+            entries.sort_by(|a, b| b.1.total_cmp(&a.1));
+        }
+    }
+}
+
+// The camera and settings a frame asking for auto-exposure renders with once it is
+// measured, so every pass of it and any pixel inspected after match
+fn auto_exposed(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+) -> (Camera, RenderSettings) {
+    let exposure = settings
+        .auto_exposure
+        .map_or(1.0, |auto| auto.measure(scene, camera, settings));
+    crate::debug!(
+        "auto-exposure scales the camera's exposure by {:.3}",
+        exposure
+    );
+    (
+        Camera {
+            exposure: camera.exposure * exposure,
+            ..camera.clone()
+        },
+        RenderSettings {
+            auto_exposure: None,
+            ..settings.clone()
+        },
+    )
+}
+
+// Runs work on that many scoped threads, or inline when one will do or none can be spawned,
+// with culling for their camera rays
+pub(crate) fn run_workers<F: Fn() + Sync>(
+    threads: usize,
+    culling: Option<&Arc<CameraCulling>>,
+    work: F,
+) {
+    if threads <= 1 || cfg!(target_arch = "wasm32") {
+        with_camera_culling(culling, work);
+        return;
+    }
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| with_camera_culling(culling, &work));
+        }
+    });
+}
+
+// The objects in view of a frame's camera rays, see Scene::camera_culling. Samples stay
+// inside their pixels; the pixel of slack keeps rays on the image's edge well inside.
+fn camera_culling(
+    scene: &Scene,
+    camera: &Camera,
+    width: usize,
+    height: usize,
+) -> Option<Arc<CameraCulling>> {
+    camera
+        .frustum(width, height, 1.0)
+        .and_then(|frustum| scene.camera_culling(&frustum))
+}
+
+// What the render workers share while a frame is in flight
+struct Progress {
+    accumulator: Accumulator,
+    // Finished tiles per band of tile rows, and which bands' scanlines went out
+    tiles_done: Vec<usize>,
+    emitted: Vec<bool>,
+    // Deterministic renders only: finished tiles waiting for those before them
+    pending: Vec<Option<Accumulator>>,
+    next_merge: usize,
+}
+
+// One camera sample's contribution to the image
+struct Sample {
+    color: Vec3f,
+    alpha: Float,
+    object_id: u32,
+}
+
+// Filter-weighted sample sums over a rectangle of pixels
+struct Accumulator {
+    rect: TileRect,
+    sums: Vec<Vec3f>,
+    alpha_sums: Vec<Float>,
+    weights: Vec<Float>,
+    // Summed weight per object ID, per pixel
+    coverage: Option<Vec<Vec<(u32, Float)>>>,
+    // Filter-weighted sums of each channel squared, per pixel
+    square_sums: Option<Vec<Vec3f>>,
+}
+
+impl Accumulator {
+    fn new(rect: TileRect, track_ids: bool, track_variance: bool) -> Accumulator {
+        let len = rect.width() * rect.height();
+        Accumulator {
+            rect,
+            sums: vec![Vec3f(0.0, 0.0, 0.0); len],
+            alpha_sums: vec![0.0; len],
+            weights: vec![0.0; len],
+            coverage: track_ids.then(|| vec![Vec::new(); len]),
+            square_sums: track_variance.then(|| vec![Vec3f(0.0, 0.0, 0.0); len]),
+        }
+    }
+
+    #[inline]
+    fn index(&self, x: usize, y: usize) -> usize {
+        (y - self.rect.y0) * self.rect.width() + (x - self.rect.x0)
+    }
+
+    // Adds a sample at continuous image position (sx, sy) to every pixel in the filter's support
+    fn splat(&mut self, filter: &PixelFilter, sx: Float, sy: Float, sample: &Sample) {
+        let radius = filter.radius();
+        let range = |s: Float, lo: usize, hi: usize| {
+            let first = (s - 0.5 - radius).ceil().max(lo as Float);
+            let last = (s - 0.5 + radius).floor().min(hi as Float - 1.0);
+            (first as usize, last as isize)
+        };
+        let (x_first, x_last) = range(sx, self.rect.x0, self.rect.x1);
+        let (y_first, y_last) = range(sy, self.rect.y0, self.rect.y1);
+
+        for y in y_first as isize..=y_last {
+            for x in x_first as isize..=x_last {
+                let (x, y) = (x as usize, y as usize);
+                let weight = filter.weight(x as Float + 0.5 - sx, y as Float + 0.5 - sy);
+                if weight != 0.0 {
+                    let i = self.index(x, y);
+                    self.sums[i] += sample.color * weight;
+                    self.alpha_sums[i] += sample.alpha * weight;
+                    self.weights[i] += weight;
+                    if let Some(coverage) = &mut self.coverage {
+                        add_coverage(&mut coverage[i], sample.object_id, weight);
+                    }
+                    if let Some(square_sums) = &mut self.square_sums {
+                        square_sums[i] += sample.color.multiply(&sample.color) * weight;
+                    }
+                }
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &Accumulator) {
+        for y in other.rect.y0..other.rect.y1 {
+            for x in other.rect.x0..other.rect.x1 {
+                let (i, j) = (self.index(x, y), other.index(x, y));
+                self.sums[i] += other.sums[j];
+                self.alpha_sums[i] += other.alpha_sums[j];
+                self.weights[i] += other.weights[j];
+                if let (Some(ours), Some(theirs)) = (&mut self.coverage, &other.coverage) {
+                    for &(id, weight) in &theirs[j] {
+                        add_coverage(&mut ours[i], id, weight);
+                    }
+                }
+                if let (Some(ours), Some(theirs)) = (&mut self.square_sums, &other.square_sums) {
+                    ours[i] += theirs[j];
+                }
+            }
+        }
+    }
+
+    // Normalized pixels of rect, row-major
+    fn resolve_rect(&self, rect: &TileRect) -> Vec<Vec3f> {
+        let mut pixels = Vec::with_capacity(rect.width() * rect.height());
+        for y in rect.y0..rect.y1 {
+            for x in rect.x0..rect.x1 {
+                let i = self.index(x, y);
+                let weight = self.weights[i];
+                pixels.push(if weight.abs() > 1e-8 {
+                    self.sums[i] * (1.0 / weight)
+                } else {
+                    Vec3f(0.0, 0.0, 0.0)
+                });
+            }
+        }
+        pixels
+    }
+
+    fn resolve_alpha(&self, rect: &TileRect) -> Vec<Float> {
+        let mut alpha = Vec::with_capacity(rect.width() * rect.height());
+        for y in rect.y0..rect.y1 {
+            for x in rect.x0..rect.x1 {
+                let i = self.index(x, y);
+                let weight = self.weights[i];
+                alpha.push(if weight.abs() > 1e-8 {
+                    self.alpha_sums[i] / weight
+                } else {
+                    0.0
+                });
+            }
+        }
+        alpha
+    }
+
+    // Fraction of each pixel covered by every object it saw, largest first
+    fn resolve_coverage(&self, rect: &TileRect) -> Option<Vec<Vec<(u32, Float)>>> {
+        let coverage = self.coverage.as_ref()?;
+        let mut pixels = Vec::with_capacity(rect.width() * rect.height());
+        for y in rect.y0..rect.y1 {
+            for x in rect.x0..rect.x1 {
+                let i = self.index(x, y);
+                let weight = self.weights[i];
+                let mut entries: Vec<(u32, Float)> = if weight.abs() > 1e-8 {
+                    coverage[i]
+                        .iter()
+                        .map(|&(id, w)| (id, w / weight))
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+                entries.sort_by(|a, b| b.1.total_cmp(&a.1));
+                pixels.push(entries);
+            }
+        }
+        Some(pixels)
+    }
+
+    // Standard deviation of each pixel's samples per channel, from the weighted mean of
+    // their squares less the square of their mean
+    fn resolve_deviation(&self, rect: &TileRect) -> Option<Vec<Vec3f>> {
+        let square_sums = self.square_sums.as_ref()?;
+        let mut pixels = Vec::with_capacity(rect.width() * rect.height());
+        for y in rect.y0..rect.y1 {
+            for x in rect.x0..rect.x1 {
+                let i = self.index(x, y);
+                let weight = self.weights[i];
+                if weight.abs() <= 1e-8 {
+                    pixels.push(Vec3f(0.0, 0.0, 0.0));
+                    continue;
+                }
+                let mean = self.sums[i] * (1.0 / weight);
+                let squares = square_sums[i] * (1.0 / weight);
+                let sigma = |square: Float, mean: Float| (square - mean * mean).max(0.0).sqrt();
+                pixels.push(Vec3f(
+                    sigma(squares.0, mean.0),
+                    sigma(squares.1, mean.1),
+                    sigma(squares.2, mean.2),
+                ));
+            }
+        }
+        Some(pixels)
+    }
+}
+
+fn add_coverage(entries: &mut Vec<(u32, Float)>, id: u32, weight: Float) {
+    match entries.iter_mut().find(|(entry, _)| *entry == id) {
+        Some(entry) => entry.1 += weight,
+        None => entries.push((id, weight)),
+    }
+}
+
+// Times a handful of single-sample probe pixels per tile as a stand-in for its full cost
+fn predict_tile_costs(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    tiles: &[TileRect],
+    threads: usize,
+) -> Vec<f32> {
+    const PROBES: [(Float, Float); 4] = [(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)];
+    let probe_settings = RenderSettings {
+        samples_per_pixel: 1,
+        ..settings.clone()
+    };
+    let next_tile = AtomicUsize::new(0);
+    let costs = Mutex::new(vec![0.0f32; tiles.len()]);
+    let culling = camera_culling(scene, camera, settings.width, settings.height);
+
+    run_workers(threads, culling.as_ref(), || loop {
+        let tile = next_tile.fetch_add(1, Ordering::Relaxed);
+        if tile >= tiles.len() {
+            break;
+        }
+        let rect = tiles[tile];
+        let start = Instant::now();
+        for (u, v) in PROBES {
+            let x = rect.x0 + (u * rect.width() as Float) as usize;
+            let y = rect.y0 + (v * rect.height() as Float) as usize;
+            sample_pixel(
+                scene,
+                camera,
+                &probe_settings,
+                None,
+                x,
+                y,
+                None,
+                |_, _, _| {},
+            );
+        }
+        costs.lock().unwrap()[tile] = start.elapsed().as_secs_f32();
+    });
+
+    costs.into_inner().unwrap()
+}
+
+// Records every vertex of every sample of pixel (x, y), tracing exactly the rays the
+// render itself would
+pub fn trace_pixel(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    x: usize,
+    y: usize,
+) -> Vec<PathTrace> {
+    if settings.auto_exposure.is_some() {
+        let (camera, settings) = auto_exposed(scene, camera, settings);
+        return trace_pixel(scene, &camera, &settings, x, y);
+    }
+    scene.set_view(&View::new(camera, settings.width, settings.height));
+    let culling = camera_culling(scene, camera, settings.width, settings.height);
+    let mut traces = Vec::new();
+    with_camera_culling(culling.as_ref(), || {
+        sample_pixel(
+            scene,
+            camera,
+            settings,
+            None,
+            x,
+            y,
+            Some(&mut traces),
+            |_, _, _| {},
+        )
+    });
+    traces
+}
+
+// Traces every sample of pixel (x, y), handing each to splat with its image position
+#[allow(clippy::too_many_arguments)]
+fn sample_pixel<F: FnMut(Float, Float, Sample)>(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    cache: Option<&IrradianceCache>,
+    x: usize,
+    y: usize,
+    mut traces: Option<&mut Vec<PathTrace>>,
+    mut splat: F,
+) {
+    let mut rng = Rng::for_stream(settings.seed, (y * settings.width + x) as u64);
+    let samples = settings.samples_per_pixel.max(1);
+    let pattern = settings.sampler.for_pixel(x, y, settings.seed, &mut rng);
+
+    for index in 0..samples {
+        let (jx, jy) = pattern.jitter(index, samples, &mut rng);
+        let (sx, sy) = (x as Float + jx, y as Float + jy);
+        // Channel 1 is green, the one ray a lens without chromatic aberration traces. All
+        // three leave the same point of the aperture.
+        let lens_point = camera.sample_aperture(&mut rng);
+        let primary = |channel| {
+            let mut ray = camera.channel_ray_differential(
+                sx,
+                sy,
+                settings.width,
+                settings.height,
+                channel,
+                lens_point,
+            );
+            if samples > 1 {
+                ray.scale_differentials(1.0 / (samples as Float).sqrt());
+            }
+            let clip = camera.clip_distance(&ray.dir);
+            (ray, clip)
+        };
+        let (ray, clip) = primary(1);
+        let mut trace = traces.is_some().then(|| PathTrace::new((sx, sy), ray.orig));
+        let falloff = camera.falloff(&ray.dir);
+        let backdrop = match &scene.backplate {
+            Some(plate) => {
+                let (width, height) = (settings.width as Float, settings.height as Float);
+                // One pixel's footprint on the plate, so a big photograph is filtered down
+                let footprint = Some(((1.0 / width, 0.0), (0.0, 1.0 / height)));
+                plate.sample(sx / width, 1.0 - sy / height, footprint)
+            }
+            None => scene.sky(&ray.dir),
+        };
+        let mut sample = trace_camera_ray(
+            scene,
+            settings,
+            cache,
+            ray,
+            clip,
+            backdrop,
+            &mut rng,
+            trace.as_mut(),
+        );
+        // The red and blue images land apart from the green one, so those channels come
+        // from rays of their own
+        if camera.lens.chromatic_aberration != 0.0 {
+            let mut channel = |channel| {
+                let (ray, clip) = primary(channel);
+                trace_camera_ray(scene, settings, cache, ray, clip, backdrop, &mut rng, None).color
+            };
+            sample.color = Vec3f(channel(0).0, sample.color.1, channel(2).2);
+        }
+        let sample = Sample {
+            color: sample.color * (camera.exposure * falloff),
+            ..sample
+        };
+        if let (Some(traces), Some(mut trace)) = (traces.as_deref_mut(), trace) {
+            trace.color = sample.color;
+            trace.alpha = sample.alpha;
+            traces.push(trace);
+        }
+        splat(sx, sy, sample);
+    }
+}
+
+// What one primary ray brings back, under whichever integrator the settings ask for;
+// backdrop is what it shows if it escapes, the backplate or the sky
+#[allow(clippy::too_many_arguments)]
+fn trace_camera_ray(
+    scene: &Scene,
+    settings: &RenderSettings,
+    cache: Option<&IrradianceCache>,
+    ray: RayDifferential,
+    clip: Option<Float>,
+    backdrop: Vec3f,
+    rng: &mut Rng,
+    mut trace: Option<&mut PathTrace>,
+) -> Sample {
+    let transparent = settings.transparent_background;
+    match settings.integrator {
+        Integrator::Whitted => match camera_hit(scene, &ray, clip) {
+            (Some(hit), _) if hit.holdout => Sample {
+                color: Vec3f(0.0, 0.0, 0.0),
+                alpha: 0.0,
+                object_id: BACKGROUND_ID,
+            },
+            (Some(hit), ray) if hit.material.shadow_catcher.is_some() => {
+                let catcher = hit.material.shadow_catcher.unwrap();
+                catch_shadows(
+                    scene, settings, cache, &ray, &hit, catcher, clip, backdrop, rng, trace,
+                )
+            }
+            (Some(hit), ray) => Sample {
+                color: shade(
+                    scene,
+                    &ray,
+                    &hit,
+                    0,
+                    settings.max_depth,
+                    None,
+                    &MediumStack::new(),
+                    trace,
+                ),
+                alpha: 1.0,
+                object_id: hit.object_id,
+            },
+            (None, ray) => {
+                let color = if transparent {
+                    Vec3f(0.0, 0.0, 0.0)
+                } else {
+                    backdrop
+                };
+                if let Some(trace) = &mut trace {
+                    let escaped = trace.push(0, PathEvent::Escaped, ray.orig + ray.dir);
+                    trace.set_emitted(escaped, color);
+                }
+                Sample {
+                    color,
+                    alpha: if transparent { 0.0 } else { 1.0 },
+                    object_id: BACKGROUND_ID,
+                }
+            }
+        },
+        Integrator::Path => trace_path(scene, ray, clip, backdrop, settings, cache, 0, rng, trace),
+    }
+}
+
+// What a camera ray meeting a shadow catcher shows: whatever lies beyond it, under a
+// black layer as opaque as the share of direct light the catcher's shadows take away,
+// and under the objects mirrored in it if it reflects. Over a transparent background the
+// shadows are left as coverage alone, to darken the photograph the render goes over.
+#[allow(clippy::too_many_arguments)]
+fn catch_shadows(
+    scene: &Scene,
+    settings: &RenderSettings,
+    cache: Option<&IrradianceCache>,
+    ray: &RayDifferential,
+    hit: &Intersection,
+    catcher: ShadowCatcher,
+    clip: Option<Float>,
+    backdrop: Vec3f,
+    rng: &mut Rng,
+    trace: Option<&mut PathTrace>,
+) -> Sample {
+    // Either side of the surface catches shadows
+    let (point, n) = (hit.record.point, hit.record.normal);
+    let soft = settings.integrator == Integrator::Path;
+    let lit = unshadowed(scene, &point, &n, hit.object_id, soft, rng);
+    // The ray carries on past the catcher's other faces, and any other catchers behind
+    // it, whose shadows are this one's or would be lit through it
+    let mut beyond = *ray;
+    let mut clip = clip;
+    let mut passed = hit.record;
+    loop {
+        clip = clip.map(|clip| clip - passed.t);
+        beyond.orig = offset_origin(scene, &passed.point, &passed.normal, &ray.dir);
+        match camera_hit(scene, &beyond, clip) {
+            (Some(next), _) if next.material.shadow_catcher.is_some() => passed = next.record,
+            _ => break,
+        }
+    }
+    let behind = trace_camera_ray(scene, settings, cache, beyond, clip, backdrop, rng, trace);
+    let mut sample = Sample {
+        color: behind.color * lit,
+        alpha: 1.0 - lit * (1.0 - behind.alpha),
+        object_id: behind.object_id,
+    };
+    if catcher.reflections <= 0.0 {
+        return sample;
+    }
+    let reflect_dir = reflect(&ray.dir, &n).normalized().unwrap_or(n);
+    let flat = Vec3f(0.0, 0.0, 0.0);
+    let reflect_ray = ray.reflected(
+        &point,
+        &n,
+        offset_origin(scene, &point, &n, &reflect_dir),
+        reflect_dir,
+        flat,
+        flat,
+    );
+    // Only objects show in the reflection, not the background or other catchers
+    if let (Some(mirrored), leg) = land(
+        scene,
+        RayKind::Reflection,
+        &reflect_ray,
+        scene.max_distance(),
+    ) {
+        if mirrored.material.shadow_catcher.is_none() {
+            let color = match settings.integrator {
+                Integrator::Whitted => {
+                    let media = MediumStack::new();
+                    shade(
+                        scene,
+                        &leg,
+                        &mirrored,
+                        1,
+                        settings.max_depth,
+                        None,
+                        &media,
+                        None,
+                    )
+                }
+                Integrator::Path => {
+                    trace_path(
+                        scene,
+                        reflect_ray,
+                        None,
+                        scene.background,
+                        settings,
+                        None,
+                        1,
+                        rng,
+                        None,
+                    )
+                    .color
+                }
+            };
+            let strength = catcher.reflections;
+            sample.color = color * strength + sample.color * (1.0 - strength);
+            sample.alpha = strength + sample.alpha * (1.0 - strength);
+        }
+    }
+    sample
+}
+
+// The share of the direct light on a surface that gets past whatever is in its way, each
+// light weighed by how brightly it would light the surface; one where no light falls.
+// Sphere lights are sampled at a point on them when soft, and as points otherwise.
+fn unshadowed(
+    scene: &Scene,
+    point: &Vec3f,
+    n: &Vec3f,
+    object_id: u32,
+    soft: bool,
+    rng: &mut Rng,
+) -> Float {
+    let luminance = |c: Vec3f| 0.2126 * c.0 + 0.7152 * c.1 + 0.0722 * c.2;
+    // Ambient light is never shadowed, so it lightens every shadow alike
+    let ambient = luminance(scene.ambient(n));
+    let (mut lit, mut total) = (ambient, ambient);
+    for light in &scene.lights {
+        if !light.links.illuminates(object_id) {
+            continue;
+        }
+        let (light_dir, distance) = match light.cone(point).filter(|_| soft) {
+            Some((cos_max, _)) => {
+                light.sample_cone(point, cos_max, rng.next_float(), rng.next_float())
+            }
+            None => {
+                let to_light = light.position - *point;
+                let distance = to_light.length();
+                (to_light * (1.0 / distance), distance)
+            }
+        };
+        let strength = light.intensity * luminance(light.color) * light_dir.dot(n);
+        if strength <= 0.0 {
+            continue;
+        }
+        total += strength;
+        if let Some(transmittance) =
+            light_arriving(scene, point, Some(n), &light_dir, distance, rng)
+        {
+            lit += strength * transmittance;
+        }
+    }
+    if total > 0.0 {
+        lit / total
+    } else {
+        1.0
+    }
+}
+
+// Fills a cache for the frame from where the camera's rays land on diffuse surfaces within
+// region: a coarse grid of pixels first, then finer ones wherever the records so far do
+// not reach
+fn build_irradiance_cache(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    caching: &IrradianceCaching,
+    region: &TileRect,
+    threads: usize,
+    observer: &dyn RenderObserver,
+) -> IrradianceCache {
+    let start = log::timer(Level::Debug);
+    let mut cache = IrradianceCache::new(caching.accuracy);
+    // The width of a pixel one unit away, to keep the reach of records to a sensible
+    // number of pixels
+    let culling = camera_culling(scene, camera, settings.width, settings.height);
+    let pixel_angle = 2.0 * (camera.vertical_fov(settings.width, settings.height) / 2.0).tan()
+        / settings.height as Float;
+    for spacing in [32, 16, 8, 4] {
+        let pixels: Vec<(usize, usize)> = (region.y0 + spacing / 2..region.y1)
+            .step_by(spacing)
+            .flat_map(|y| {
+                (region.x0 + spacing / 2..region.x1)
+                    .step_by(spacing)
+                    .map(move |x| (x, y))
+            })
+            .collect();
+        let next = AtomicUsize::new(0);
+        let gathered = Mutex::new(vec![None; pixels.len()]);
+        run_workers(threads, culling.as_ref(), || loop {
+            if observer.cancelled() {
+                break;
+            }
+            let slot = next.fetch_add(1, Ordering::Relaxed);
+            if slot >= pixels.len() {
+                break;
+            }
+            let record = gather_irradiance(
+                scene,
+                camera,
+                settings,
+                &cache,
+                caching,
+                pixels[slot],
+                pixel_angle,
+            );
+            gathered.lock().unwrap()[slot] = record;
+        });
+        // Records join in pixel order, so the cache comes out the same on any thread count
+        cache.extend(gathered.into_inner().unwrap().into_iter().flatten());
+    }
+    if let Some(start) = start {
+        crate::debug!(
+            "irradiance cache: {} records in {:.3}s",
+            cache.len(),
+            start.elapsed().as_secs_f64()
+        );
+    }
+    cache
+}
+
+// A new record where the ray through the middle of pixel lands, unless that is not a
+// diffuse surface or the cache already reaches it
+fn gather_irradiance(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    cache: &IrradianceCache,
+    caching: &IrradianceCaching,
+    pixel: (usize, usize),
+    pixel_angle: Float,
+) -> Option<IrradianceRecord> {
+    let (x, y) = pixel;
+    let ray = camera.ray_differential(
+        x as Float + 0.5,
+        y as Float + 0.5,
+        settings.width,
+        settings.height,
+    );
+    let (hit, _) = camera_hit(scene, &ray, camera.clip_distance(&ray.dir));
+    let hit = hit?;
+    let diffuse = hit.material.diffuse_color * hit.material.albedo[0];
+    if diffuse.0 + diffuse.1 + diffuse.2 <= 0.0 {
+        return None;
+    }
+    let (point, n) = (hit.record.point, hit.record.normal);
+    if cache.lookup(&point, &n).is_some() {
+        return None;
+    }
+
+    // Stratified over the hemisphere, on streams apart from the pixels' own
+    let mut rng = Rng::for_stream(!settings.seed, (y * settings.width + x) as u64);
+    let side = ((caching.samples.max(1) as Float).sqrt().ceil() as u32).max(1);
+    let frame = Onb::from_normal(&n);
+    let mut radiance = Vec3f(0.0, 0.0, 0.0);
+    let mut inverse_distances = 0.0;
+    for i in 0..side * side {
+        let u1 = ((i % side) as Float + rng.next_float()) / side as Float;
+        let u2 = ((i / side) as Float + rng.next_float()) / side as Float;
+        let dir = frame.to_world(&onb::cosine_hemisphere(u1, u2));
+        let bounce = RayDifferential::new(offset_origin(scene, &point, &n, &dir), dir);
+        if let (Some(next), _) = land(scene, RayKind::Reflection, &bounce, scene.max_distance()) {
+            inverse_distances += 1.0 / next.record.t.max(Float::EPSILON);
+        }
+        radiance += trace_path(
+            scene,
+            bounce,
+            None,
+            scene.background,
+            settings,
+            None,
+            1,
+            &mut rng,
+            None,
+        )
+        .color;
+    }
+    let count = (side * side) as Float;
+    // Kept between a few pixels and a few dozen across on screen
+    let footprint = pixel_angle * hit.record.t;
+    let radius = (count / inverse_distances).clamp(4.0 * footprint, 64.0 * footprint);
+    Some(IrradianceRecord {
+        point,
+        normal: n,
+        radiance: radiance * (1.0 / count),
+        radius,
+    })
+}
+
+// The light a white diffuse surface at point facing n would reflect, so a surface's
+// diffuse shading there is this times its diffuse color: the scene's lights and ambient
+// light plus what samples cosine-weighted rays bring back from the surfaces around,
+// which the path tracer follows on for the rest of its depth. object_id is the surface's
+// own, for light linking.
+pub fn diffuse_irradiance(
+    scene: &Scene,
+    point: &Vec3f,
+    n: &Vec3f,
+    object_id: u32,
+    settings: &RenderSettings,
+    samples: u32,
+    rng: &mut Rng,
+) -> Vec3f {
+    let side = ((samples.max(1) as Float).sqrt().ceil() as u32).max(1);
+    let frame = Onb::from_normal(n);
+    let mut total = Vec3f(0.0, 0.0, 0.0);
+    for i in 0..side * side {
+        total += direct_light(scene, point, Some((n, object_id)), None, rng, |l| {
+            let cos = Float::max(0.0, l.dot(n));
+            Vec3f(cos, cos, cos)
+        });
+        let u1 = ((i % side) as Float + rng.next_float()) / side as Float;
+        let u2 = ((i / side) as Float + rng.next_float()) / side as Float;
+        let dir = frame.to_world(&onb::cosine_hemisphere(u1, u2));
+        let bounce = RayDifferential::new(offset_origin(scene, point, n, &dir), dir);
+        let background = scene.background;
+        total += trace_path(
+            scene, bounce, None, background, settings, None, 1, rng, None,
+        )
+        .color;
+    }
+    total * (1.0 / (side * side) as Float) + scene.ambient(n)
+}
+
+// The light arriving at origin from the direction opposite dir, including whatever it
+// bounced off on the way, as the path tracer follows it
+pub fn incoming_radiance(
+    scene: &Scene,
+    origin: &Vec3f,
+    dir: &Vec3f,
+    settings: &RenderSettings,
+    rng: &mut Rng,
+) -> Vec3f {
+    let ray = RayDifferential::new(*origin, *dir);
+    trace_path(
+        scene,
+        ray,
+        None,
+        scene.background,
+        settings,
+        None,
+        1,
+        rng,
+        None,
+    )
+    .color
+}
+
+// The fraction of samples cosine-weighted rays from point about n that travel distance
+// without meeting anything that casts shadows: 1 out in the open, 0 shut in a box
+pub fn ambient_occlusion(
+    scene: &Scene,
+    point: &Vec3f,
+    n: &Vec3f,
+    samples: u32,
+    distance: Float,
+    rng: &mut Rng,
+) -> Float {
+    let side = ((samples.max(1) as Float).sqrt().ceil() as u32).max(1);
+    let frame = Onb::from_normal(n);
+    let mut open = 0;
+    for i in 0..side * side {
+        let u1 = ((i % side) as Float + rng.next_float()) / side as Float;
+        let u2 = ((i / side) as Float + rng.next_float()) / side as Float;
+        let dir = frame.to_world(&onb::cosine_hemisphere(u1, u2));
+        if !scene.occluded(&offset_origin(scene, point, n, &dir), &dir, distance) {
+            open += 1;
+        }
+    }
+    open as Float / (side * side) as Float
+}
+
+// Nudges a secondary ray origin off the surface to the side the ray leaves towards, by a
+// distance in proportion to the scene's scale
+// The normal pointing out of the solid a hit is on, which refraction bends against to
+// tell entering from leaving
+fn outward_normal(record: &HitRecord) -> Vec3f {
+    if record.front_face {
+        record.normal
+    } else {
+        -record.normal
+    }
+}
+
+fn offset_origin(scene: &Scene, point: &Vec3f, normal: &Vec3f, dir: &Vec3f) -> Vec3f {
+    let offset = SMALL_NUMBER * scene.units_per_meter;
+    if dir.dot(normal) < 0.0 {
+        *point - *normal * offset
+    } else {
+        *point + *normal * offset
+    }
+}
+
+pub fn cast_ray(scene: &Scene, orig: &Vec3f, dir: &Vec3f, depth: u32, max_depth: u32) -> Vec3f {
+    trace_ray(
+        scene,
+        &RayDifferential::new(*orig, *dir),
+        depth,
+        max_depth,
+        None,
+        &MediumStack::new(),
+        None,
+    )
+}
+
+// channel is the one color a ray split off by dispersion carries, which the rest of its
+// refractions bend by; None for white light. media holds the solids the ray is in.
+fn trace_ray(
+    scene: &Scene,
+    ray: &RayDifferential,
+    depth: u32,
+    max_depth: u32,
+    channel: Option<usize>,
+    media: &MediumStack,
+    trace: Option<&mut PathTrace>,
+) -> Vec3f {
+    let mut media = *media;
+    match land_in(scene, ray, &mut media) {
+        (Some(hit), ray) if depth <= max_depth => {
+            shade(scene, &ray, &hit, depth, max_depth, channel, &media, trace)
+        }
+        (_, ray) => {
+            let sky = scene.sky(&ray.dir);
+            if let Some(trace) = trace {
+                let escaped = trace.push(depth, PathEvent::Escaped, ray.orig + ray.dir);
+                trace.set_emitted(escaped, sky);
+            }
+            sky
+        }
+    }
+}
+
+// A secondary Whitted ray, recorded below vertex parent with its throughput scaled by weight
+#[allow(clippy::too_many_arguments)]
+fn trace_branch(
+    scene: &Scene,
+    ray: &RayDifferential,
+    depth: u32,
+    max_depth: u32,
+    channel: Option<usize>,
+    media: &MediumStack,
+    trace: Option<(&mut PathTrace, usize)>,
+    weight: Float,
+) -> Vec3f {
+    match trace {
+        Some((trace, parent)) => {
+            let throughput = trace.vertices[parent].throughput * weight;
+            let saved = trace.descend(parent, throughput);
+            let color = trace_ray(scene, ray, depth, max_depth, channel, media, Some(trace));
+            trace.restore(saved);
+            color
+        }
+        None => trace_ray(scene, ray, depth, max_depth, channel, media, None),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn shade(
+    scene: &Scene,
+    ray: &RayDifferential,
+    hit: &Intersection,
+    depth: u32,
+    max_depth: u32,
+    channel: Option<usize>,
+    media: &MediumStack,
+    trace: Option<&mut PathTrace>,
+) -> Vec3f {
+    let dir = &ray.dir;
+    let (point, n, material) = (hit.record.point, hit.record.normal, hit.material);
+    let mut trace = trace.map(|trace| {
+        let here = trace.push(depth, PathEvent::Surface, point);
+        let vertex = trace.vertex(here);
+        vertex.normal = Some(n);
+        vertex.object_id = Some(hit.object_id);
+        vertex.material = Some(material);
+        (trace, here)
+    });
+    let coat = material.clearcoat_reflectance(dir.dot(&n));
+    // No shape reports how its normal varies yet, so surfaces count as locally flat
+    let flat = Vec3f(0.0, 0.0, 0.0);
+
+    let reflect_dir = reflect(dir, &n).normalized().unwrap_or(n);
+    let reflect_ray = ray.reflected(
+        &point,
+        &n,
+        offset_origin(scene, &point, &n, &reflect_dir),
+        reflect_dir,
+        flat,
+        flat,
+    );
+    let reflect_color = trace_branch(
+        scene,
+        &reflect_ray,
+        depth + 1,
+        max_depth,
+        channel,
+        media,
+        trace.as_mut().map(|(trace, here)| (&mut **trace, *here)),
+        material.albedo[2] + coat,
+    );
+    let outward = outward_normal(&hit.record);
+    let mut through = *media;
+    through.cross(hit);
+    let mut refracted = |channel| {
+        let (ior, beyond) = media.indices(hit, channel);
+        let refract_dir = refract(dir, &outward, ior, beyond)
+            .normalized()
+            .unwrap_or(*dir);
+        let refract_ray = ray.refracted(
+            &point,
+            &outward,
+            ior / beyond,
+            offset_origin(scene, &point, &n, &refract_dir),
+            refract_dir,
+            flat,
+            flat,
+        );
+        trace_branch(
+            scene,
+            &refract_ray,
+            depth + 1,
+            max_depth,
+            channel,
+            &through,
+            trace.as_mut().map(|(trace, here)| (&mut **trace, *here)),
+            material.albedo[3],
+        )
+    };
+    // White light meeting a dispersive material splits into its colors, which each bend
+    // by their own index from here on
+    let refract_color = if material.dispersion.is_some() && channel.is_none() {
+        let [r, g, b] = [0, 1, 2].map(|channel| refracted(Some(channel)));
+        Vec3f(r.0, g.1, b.2)
+    } else {
+        refracted(channel)
+    };
+
+    let glossy = anisotropic_lobe(&material, &n, hit.record.tangent);
+    let mut diffuse_light = Vec3f(0.0, 0.0, 0.0);
+    let mut specular_light = Vec3f(0.0, 0.0, 0.0);
+    let mut layer_light = Vec3f(0.0, 0.0, 0.0);
+    for light in &scene.lights {
+        if !light.links.illuminates(hit.object_id) {
+            continue;
+        }
+        let to_light = light.position - point;
+        let light_distance = to_light.length();
+        let light_dir = to_light * (1.0 / light_distance);
+        let shadow_orig = offset_origin(scene, &point, &n, &light_dir);
+        if scene.occluded(&shadow_orig, &light_dir, light_distance) {
+            continue;
+        }
+        diffuse_light += light.color * (light.intensity * Float::max(0.0, light_dir.dot(&n)));
+        specular_light += light.color
+            * (light.intensity * highlight(&material, glossy.as_ref(), &n, &light_dir, dir));
+        layer_light +=
+            layers(&material, &n, &light_dir, dir).multiply(&(light.color * light.intensity));
+    }
+
+    let tint = material.specular_tint(dir.dot(&n));
+    let local = material
+        .diffuse_color
+        .multiply(&(diffuse_light * material.albedo[0]))
+        + material.diffuse_color.multiply(&scene.ambient(&n)) * material.albedo[0]
+        + tint.multiply(&(specular_light * material.albedo[1]))
+        + layer_light
+        + material.emitted(hit.record.front_face);
+    if let Some((trace, here)) = trace {
+        trace.set_emitted(here, local);
+    }
+    local
+        + reflect_color.multiply(&tint) * material.albedo[2]
+        + reflect_color * coat
+        + refract_color * material.albedo[3]
+}
+
+// The anisotropic specular lobe of a material and the frame it is oriented in at a hit
+fn anisotropic_lobe(
+    material: &Material,
+    n: &Vec3f,
+    tangent: Option<Vec3f>,
+) -> Option<(Anisotropy, Onb)> {
+    material
+        .anisotropy
+        .map(|anisotropy| (anisotropy, anisotropy.frame(n, tangent)))
+}
+
+// The material with no lobe smoother than roughness. A mirror turns glossy, no sharper
+// than its highlight already was, and a widened highlight is dimmed to reflect the same
+// light in all as before.
+fn roughened(material: &Material, roughness: Float) -> Material {
+    let width = roughness * roughness;
+    let sharpness = material
+        .anisotropy
+        .map_or(material.specular_exponent + 1.0, |a| a.sharpness());
+    let anisotropy = match material.anisotropy {
+        Some(anisotropy) => Anisotropy {
+            roughness: (
+                anisotropy.roughness.0.max(width),
+                anisotropy.roughness.1.max(width),
+            ),
+            ..anisotropy
+        },
+        None => {
+            // The microfacet width whose exponent is the Phong one
+            let highlight = (2.0 / (material.specular_exponent + 2.0)).sqrt();
+            let width = width.max(highlight).min(1.0);
+            Anisotropy {
+                roughness: (width, width),
+                rotation: 0.0,
+            }
+        }
+    };
+    let mut albedo = material.albedo;
+    albedo[1] *= anisotropy.sharpness() / sharpness;
+    Material {
+        anisotropy: Some(anisotropy),
+        albedo,
+        ..*material
+    }
+}
+
+// Specular response seen along dir from a light in light_dir: the round Phong lobe, or
+// the anisotropic one when the material has it
+fn highlight(
+    material: &Material,
+    lobe: Option<&(Anisotropy, Onb)>,
+    n: &Vec3f,
+    light_dir: &Vec3f,
+    dir: &Vec3f,
+) -> Float {
+    match lobe {
+        Some((anisotropy, frame)) => (*light_dir - *dir)
+            .normalized()
+            .map_or(0.0, |half| anisotropy.highlight(frame, &half)),
+        None => {
+            Float::max(0.0, -reflect(&-*light_dir, n).dot(dir)).powf(material.specular_exponent)
+        }
+    }
+}
+
+// The clearcoat highlight and sheen lit from light_dir and seen along dir, which sit on
+// top of the material's own lobes
+fn layers(material: &Material, n: &Vec3f, light_dir: &Vec3f, dir: &Vec3f) -> Vec3f {
+    if material.clearcoat.is_none() && material.sheen.is_none() {
+        return Vec3f(0.0, 0.0, 0.0);
+    }
+    let Some(half) = (*light_dir - *dir).normalized() else {
+        return Vec3f(0.0, 0.0, 0.0);
+    };
+    let coat = material.clearcoat_highlight(n, &half);
+    Vec3f(coat, coat, coat) + material.sheen(light_dir, &half) * Float::max(0.0, light_dir.dot(n))
+}
+
+// Primary rays only see as far as the camera's far plane, if it has one, on their way to
+// the first portal
+fn camera_hit(
+    scene: &Scene,
+    ray: &RayDifferential,
+    clip: Option<Float>,
+) -> (Option<Intersection>, RayDifferential) {
+    let t_max = clip.unwrap_or(scene.max_distance());
+    let hit = scene.intersect_camera(&ray.orig, &ray.dir, t_max);
+    through_portals(scene, RayKind::Camera, hit, ray)
+}
+
+// The surface ray lands on after passing through whatever portals are in its way, and
+// the leg of the ray that reaches it
+fn land(
+    scene: &Scene,
+    kind: RayKind,
+    ray: &RayDifferential,
+    t_max: Float,
+) -> (Option<Intersection>, RayDifferential) {
+    let hit = scene.intersect_as(kind, &ray.orig, &ray.dir, t_max);
+    through_portals(scene, kind, hit, ray)
+}
+
+// Follows ray on from hit through each portal it lands on. Legs past a portal start
+// wherever its exit is, so they search the whole scene even for camera rays.
+fn through_portals(
+    scene: &Scene,
+    kind: RayKind,
+    mut hit: Option<Intersection>,
+    ray: &RayDifferential,
+) -> (Option<Intersection>, RayDifferential) {
+    let mut ray = *ray;
+    if scene.portals.is_empty() {
+        return (hit, ray);
+    }
+    for _ in 0..MAX_PORTAL_HOPS {
+        let Some(next) = hit.as_ref().and_then(|h| scene.portal_exit(h, &ray)) else {
+            break;
+        };
+        ray = next;
+        hit = scene.intersect_as(kind, &ray.orig, &ray.dir, scene.max_distance());
+    }
+    (hit, ray)
+}
+
+// The surface a ray inside the solids in media lands on, as land finds it for a
+// reflection ray, passing through the false hits on solids overlapped by one of higher
+// priority and noting in media that it went through them
+fn land_in(
+    scene: &Scene,
+    ray: &RayDifferential,
+    media: &mut MediumStack,
+) -> (Option<Intersection>, RayDifferential) {
+    let (mut hit, mut ray) = land(scene, RayKind::Reflection, ray, scene.max_distance());
+    for _ in 0..MAX_FALSE_HITS {
+        let Some(false_hit) = hit.as_ref().filter(|hit| !media.shows(hit)) else {
+            break;
+        };
+        media.cross(false_hit);
+        let mut beyond = ray;
+        beyond.orig = offset_origin(
+            scene,
+            &false_hit.record.point,
+            &false_hit.record.normal,
+            &ray.dir,
+        );
+        (hit, ray) = land(scene, RayKind::Reflection, &beyond, scene.max_distance());
+    }
+    (hit, ray)
+}
+
+// The sample's coverage and object come from whatever the primary ray lands on first
+//
+// Paths start at first_depth, zero for camera rays, which show backdrop if they escape;
+// cache stands in for the diffuse bounce off the first surface wherever it has records.
+#[allow(clippy::too_many_arguments)]
+fn trace_path(
+    scene: &Scene,
+    mut ray: RayDifferential,
+    clip: Option<Float>,
+    backdrop: Vec3f,
+    settings: &RenderSettings,
+    cache: Option<&IrradianceCache>,
+    first_depth: u32,
+    rng: &mut Rng,
+    mut trace: Option<&mut PathTrace>,
+) -> Sample {
+    let mut radiance = Vec3f(0.0, 0.0, 0.0);
+    let mut object_id = BACKGROUND_ID;
+    let mut throughput = Vec3f(1.0, 1.0, 1.0);
+    let flat = Vec3f(0.0, 0.0, 0.0);
+    // The one color the path carries once a dispersive material has split it
+    let mut channel = None;
+    let mut media = MediumStack::new();
+    // The least roughness surfaces are given from here on; a path carrying on from a
+    // bounce has already been off something diffuse
+    let mut blur: Float = if first_depth > 0 {
+        settings.regularize
+    } else {
+        0.0
+    };
+
+    for depth in first_depth..=settings.max_depth {
+        // The vertex recorded for this bounce, if tracing
+        let vertex;
+        let (hit, t_limit) = if depth == 0 {
+            let (hit, leg) = camera_hit(scene, &ray, clip);
+            // Past a portal the far plane no longer applies
+            let t_limit = if leg.orig == ray.orig {
+                clip.unwrap_or(Float::MAX)
+            } else {
+                Float::MAX
+            };
+            ray = leg;
+            (hit, t_limit)
+        } else {
+            let (hit, leg) = land_in(scene, &ray, &mut media);
+            ray = leg;
+            (hit, Float::MAX)
+        };
+        let (orig, dir) = (ray.orig, ray.dir);
+        let t_surface = hit.as_ref().map_or(t_limit, |h| h.record.t);
+
+        if let Some((volume, t)) = sample_medium(scene, &orig, &dir, t_surface, rng) {
+            let point = orig + dir * t;
+            throughput = throughput.multiply(&volume.albedo);
+            blur = settings.regularize;
+            let direct = direct_light(scene, &point, None, None, rng, |_| {
+                Vec3f(ISOTROPIC_PHASE, ISOTROPIC_PHASE, ISOTROPIC_PHASE)
+            });
+            radiance += throughput.multiply(&direct);
+            ray = RayDifferential::new(
+                point,
+                onb::uniform_sphere(rng.next_float(), rng.next_float()),
+            );
+            vertex = trace.as_deref_mut().map(|trace| {
+                let index = trace.push(depth, PathEvent::Medium, point);
+                trace.vertex(index).throughput = throughput;
+                trace.vertex(index).pdf = Some(onb::UNIFORM_SPHERE_PDF);
+                trace.set_emitted(index, direct);
+                index
+            });
+        } else {
+            let hit = match hit {
+                Some(hit) => hit,
+                None => {
+                    let transparent = depth == 0 && settings.transparent_background;
+                    let emitted = if transparent {
+                        Vec3f(0.0, 0.0, 0.0)
+                    } else if depth == 0 {
+                        backdrop
+                    } else {
+                        scene.sky(&dir)
+                    };
+                    if let Some(trace) = trace.as_deref_mut() {
+                        let index = trace.push(depth, PathEvent::Escaped, orig + dir);
+                        trace.vertex(index).throughput = throughput;
+                        trace.set_emitted(index, emitted);
+                    }
+                    if transparent {
+                        return Sample {
+                            color: radiance,
+                            alpha: 0.0,
+                            object_id,
+                        };
+                    }
+                    radiance += throughput.multiply(&emitted);
+                    break;
+                }
+            };
+            // Held out of the render layer: a hole for the camera, there as usual for
+            // shadows and reflections
+            if depth == 0 && hit.holdout {
+                return Sample {
+                    color: radiance,
+                    alpha: 0.0,
+                    object_id,
+                };
+            }
+            if let Some(catcher) = hit.material.shadow_catcher.filter(|_| depth == 0) {
+                return catch_shadows(
+                    scene, settings, cache, &ray, &hit, catcher, clip, backdrop, rng, trace,
+                );
+            }
+            let point = hit.record.point;
+            let material = if blur > 0.0 {
+                roughened(&hit.material, blur)
+            } else {
+                hit.material
+            };
+            if depth == 0 {
+                object_id = hit.object_id;
+            }
+            let n = hit.record.normal;
+
+            let tint = material.specular_tint(dir.dot(&n));
+            let glossy = anisotropic_lobe(&material, &n, hit.record.tangent);
+            let lobes = SurfaceLobes::new(&material, glossy.as_ref(), &n, &dir);
+            let surface = Some((&n, hit.object_id));
+            let direct = direct_light(scene, &point, surface, lobes.as_ref(), rng, |l| {
+                let specular = highlight(&material, glossy.as_ref(), &n, l, &dir);
+                material.diffuse_color * (Float::max(0.0, l.dot(&n)) * material.albedo[0])
+                    + tint * (specular * material.albedo[1])
+                    + layers(&material, &n, l, &dir)
+            }) + material.diffuse_color.multiply(&scene.ambient(&n))
+                * material.albedo[0]
+                + material.emitted(hit.record.front_face);
+            radiance += throughput.multiply(&direct);
+            vertex = trace.as_deref_mut().map(|trace| {
+                let index = trace.push(depth, PathEvent::Surface, point);
+                let recorded = trace.vertex(index);
+                recorded.throughput = throughput;
+                recorded.normal = Some(hit.record.normal);
+                recorded.object_id = Some(hit.object_id);
+                recorded.material = Some(material);
+                trace.set_emitted(index, direct);
+                index
+            });
+
+            // Pick one continuation lobe in proportion to its weight
+            let diffuse = material.diffuse_color * material.albedo[0];
+            let coat = material.clearcoat_reflectance(dir.dot(&n));
+            let mut weights = [
+                (diffuse.0 + diffuse.1 + diffuse.2) / 3.0,
+                material.albedo[2] + coat,
+                material.albedo[3],
+            ];
+            if depth == 0 && weights[0] > 0.0 {
+                if let Some(bounced) = cache.and_then(|cache| cache.lookup(&point, &n)) {
+                    radiance += throughput.multiply(&diffuse.multiply(&bounced));
+                    weights[0] = 0.0;
+                }
+            }
+            let total: Float = weights.iter().sum();
+            if total <= 0.0 {
+                break;
+            }
+            let choice = rng.next_float() * total;
+            let lobe = if choice < weights[0] {
+                0
+            } else if choice < weights[0] + weights[1] {
+                1
+            } else {
+                2
+            };
+            if let (Some(trace), Some(index)) = (trace.as_deref_mut(), vertex) {
+                trace.vertex(index).pdf = Some(weights[lobe] / total);
+            }
+            if lobe == 0 {
+                blur = settings.regularize;
+                throughput = throughput.multiply(&diffuse) * (total / weights[0]);
+                let local = onb::cosine_hemisphere(rng.next_float(), rng.next_float());
+                let new_dir = Onb::from_normal(&n).to_world(&local);
+                if let (Some(trace), Some(index)) = (trace.as_deref_mut(), vertex) {
+                    let pdf = trace.vertex(index).pdf.unwrap_or(1.0);
+                    trace.vertex(index).pdf = Some(pdf * onb::cosine_hemisphere_pdf(local.2));
+                }
+                // Diffuse bounces scatter too widely for differentials to stay meaningful
+                ray = RayDifferential::new(offset_origin(scene, &point, &n, &new_dir), new_dir);
+            } else if lobe == 1 {
+                let reflectance = tint * material.albedo[2] + Vec3f(coat, coat, coat);
+                throughput = throughput.multiply(&reflectance) * (total / weights[1]);
+                if let Some((anisotropy, frame)) = &glossy {
+                    // Glossy rather than mirror reflection, scattered about a sampled half
+                    // vector; directions that end up below the surface are absorbed
+                    blur = settings.regularize;
+                    let half = anisotropy.sample_half(frame, rng.next_float(), rng.next_float());
+                    let new_dir = reflect(&dir, &half).normalized().unwrap_or(n);
+                    if new_dir.dot(&n) <= 0.0 {
+                        break;
+                    }
+                    ray = RayDifferential::new(offset_origin(scene, &point, &n, &new_dir), new_dir);
+                } else {
+                    let new_dir = reflect(&dir, &n).normalized().unwrap_or(n);
+                    let new_orig = offset_origin(scene, &point, &n, &new_dir);
+                    ray = ray.reflected(&point, &n, new_orig, new_dir, flat, flat);
+                }
+            } else {
+                throughput = throughput * (material.albedo[3] * total / weights[2]);
+                // Keep one color, picked at random, and scale it up by the three it stands for
+                if material.dispersion.is_some() && channel.is_none() {
+                    let picked = ((rng.next_float() * 3.0) as usize).min(2);
+                    let mut mask = [0.0; 3];
+                    mask[picked] = 3.0;
+                    throughput = throughput.multiply(&Vec3f(mask[0], mask[1], mask[2]));
+                    channel = Some(picked);
+                }
+                let (ior, beyond) = media.indices(&hit, channel);
+                media.cross(&hit);
+                let outward = outward_normal(&hit.record);
+                let new_dir = refract(&dir, &outward, ior, beyond)
+                    .normalized()
+                    .unwrap_or(dir);
+                let new_orig = offset_origin(scene, &point, &n, &new_dir);
+                ray = ray.refracted(
+                    &point,
+                    &outward,
+                    ior / beyond,
+                    new_orig,
+                    new_dir,
+                    flat,
+                    flat,
+                );
+            }
+        }
+
+        // Russian roulette once the path has had a few bounces
+        if depth >= 3 {
+            let survival = throughput.0.max(throughput.1).max(throughput.2).min(1.0);
+            if let (Some(trace), Some(index)) = (trace.as_deref_mut(), vertex) {
+                let pdf = trace.vertex(index).pdf.unwrap_or(1.0);
+                trace.vertex(index).pdf = Some(pdf * survival);
+            }
+            if survival <= 0.0 || rng.next_float() >= survival {
+                break;
+            }
+            throughput = throughput * (1.0 / survival);
+        }
+        if let (Some(trace), Some(index)) = (trace.as_deref_mut(), vertex) {
+            trace.descend(index, throughput);
+        }
+    }
+
+    Sample {
+        color: radiance,
+        alpha: 1.0,
+        object_id,
+    }
+}
+
+// Light arriving at point from every light source, attenuated by surfaces and media
+// and weighted by the response towards each light direction. Surfaces pass their
+// normal and object ID so lights behind them or not linked to them are skipped; media
+// scatter light from all directions.
+//
+// Sphere lights are sampled twice, once by direction towards the light and once by the
+// surface's lobes when it passes them, and the two are combined with the power
+// heuristic: light sampling finds small lights seen by rough surfaces, lobe sampling
+// finds big lights seen in sharp highlights, and neither alone manages both without
+// noise. A sphere gives off the light its centre point would, so its radiance is the
+// intensity spread over the solid angle it covers.
+fn direct_light<F: Fn(&Vec3f) -> Vec3f>(
+    scene: &Scene,
+    point: &Vec3f,
+    surface: Option<(&Vec3f, u32)>,
+    lobes: Option<&SurfaceLobes>,
+    rng: &mut Rng,
+    response: F,
+) -> Vec3f {
+    let mut total = Vec3f(0.0, 0.0, 0.0);
+    let normal = surface.map(|(n, _)| n);
+    for light in &scene.lights {
+        if let Some((_, id)) = surface {
+            if !light.links.illuminates(id) {
+                continue;
+            }
+        }
+        let Some((cos_max, solid_angle)) = light.cone(point) else {
+            let to_light = light.position - *point;
+            let light_distance = to_light.length();
+            let light_dir = to_light * (1.0 / light_distance);
+            if let Some(transmittance) =
+                light_arriving(scene, point, normal, &light_dir, light_distance, rng)
+            {
+                total += response(&light_dir)
+                    .multiply(&(light.color * (light.intensity * transmittance)));
+            }
+            continue;
+        };
+        let light_pdf = 1.0 / solid_angle;
+        let (light_dir, light_distance) =
+            light.sample_cone(point, cos_max, rng.next_float(), rng.next_float());
+        if let Some(transmittance) =
+            light_arriving(scene, point, normal, &light_dir, light_distance, rng)
+        {
+            let lobe_pdf = lobes.map_or(0.0, |lobes| lobes.pdf(&light_dir));
+            let weight = power_heuristic(light_pdf, lobe_pdf);
+            total += response(&light_dir)
+                .multiply(&(light.color * (light.intensity * transmittance * weight)));
+        }
+        let Some(lobes) = lobes else {
+            continue;
+        };
+        let light_dir = lobes.sample(rng.next_float(), rng.next_float(), rng.next_float());
+        let lobe_pdf = lobes.pdf(&light_dir);
+        let Some(light_distance) = light.surface_distance(point, &light_dir) else {
+            continue;
+        };
+        if lobe_pdf <= 0.0 {
+            continue;
+        }
+        if let Some(transmittance) =
+            light_arriving(scene, point, normal, &light_dir, light_distance, rng)
+        {
+            let weight = light_pdf / lobe_pdf * power_heuristic(lobe_pdf, light_pdf);
+            total += response(&light_dir)
+                .multiply(&(light.color * (light.intensity * transmittance * weight)));
+        }
+    }
+    total
+}
+
+// How much of a light gets from point to distance along light_dir, after media; None
+// when it is behind the surface with normal n or something is in the way
+fn light_arriving(
+    scene: &Scene,
+    point: &Vec3f,
+    n: Option<&Vec3f>,
+    light_dir: &Vec3f,
+    distance: Float,
+    rng: &mut Rng,
+) -> Option<Float> {
+    let shadow_orig = match n {
+        Some(n) if light_dir.dot(n) <= 0.0 => return None,
+        Some(n) => offset_origin(scene, point, n, light_dir),
+        None => *point,
+    };
+    if scene.occluded(&shadow_orig, light_dir, distance) {
+        return None;
+    }
+    Some(medium_transmittance(
+        scene,
+        &shadow_orig,
+        light_dir,
+        distance,
+        rng,
+    ))
+}
+
+// Weight for a sample drawn with density a that a strategy with density b could also
+// have drawn
+fn power_heuristic(a: Float, b: Float) -> Float {
+    let (a2, b2) = (a * a, b * b);
+    a2 / (a2 + b2)
+}
+
+// The diffuse and highlight lobes of a surface's response to light, as directions to
+// sample when looking for lights; the clearcoat and sheen are left to light sampling
+struct SurfaceLobes<'a> {
+    n: Vec3f,
+    dir: Vec3f,
+    // The chance of sampling the diffuse lobe rather than the highlight
+    diffuse: Float,
+    specular_exponent: Float,
+    glossy: Option<&'a (Anisotropy, Onb)>,
+}
+
+impl<'a> SurfaceLobes<'a> {
+    // None when the surface has neither lobe
+    fn new(
+        material: &Material,
+        glossy: Option<&'a (Anisotropy, Onb)>,
+        n: &Vec3f,
+        dir: &Vec3f,
+    ) -> Option<SurfaceLobes<'a>> {
+        let diffuse = material.diffuse_color * material.albedo[0];
+        let diffuse = (diffuse.0 + diffuse.1 + diffuse.2) / 3.0;
+        let total = diffuse + material.albedo[1];
+        (total > 0.0).then_some(SurfaceLobes {
+            n: *n,
+            dir: *dir,
+            diffuse: diffuse / total,
+            specular_exponent: material.specular_exponent,
+            glossy,
+        })
+    }
+
+    fn sample(&self, choice: Float, u1: Float, u2: Float) -> Vec3f {
+        if choice < self.diffuse {
+            return Onb::from_normal(&self.n).to_world(&onb::cosine_hemisphere(u1, u2));
+        }
+        match self.glossy {
+            Some((anisotropy, frame)) => {
+                let half = anisotropy.sample_half(frame, u1, u2);
+                reflect(&self.dir, &half).normalized().unwrap_or(self.n)
+            }
+            None => {
+                // Phong's lobe is a power of the cosine about the mirror direction
+                let cos = u1.powf(1.0 / (self.specular_exponent + 1.0));
+                let sin = (1.0 - cos * cos).max(0.0).sqrt();
+                let phi = 2.0 * PI * u2;
+                let mirror = reflect(&self.dir, &self.n);
+                Onb::from_normal(&mirror).to_world(&Vec3f(sin * phi.cos(), sin * phi.sin(), cos))
+            }
+        }
+    }
+
+    // The density per solid angle that sample draws light_dir with
+    fn pdf(&self, light_dir: &Vec3f) -> Float {
+        let diffuse = onb::cosine_hemisphere_pdf(light_dir.dot(&self.n));
+        let highlight = match self.glossy {
+            Some((anisotropy, frame)) => (*light_dir - self.dir).normalized().map_or(0.0, |half| {
+                anisotropy.half_pdf(frame, &half) / (4.0 * light_dir.dot(&half).abs())
+            }),
+            None => {
+                let cos = light_dir.dot(&reflect(&self.dir, &self.n)).max(0.0);
+                (self.specular_exponent + 1.0) / (2.0 * PI) * cos.powf(self.specular_exponent)
+            }
+        };
+        self.diffuse * diffuse + (1.0 - self.diffuse) * highlight
+    }
+}
+
+// Nearest real collision over all volumes before t_max
+fn sample_medium<'a>(
+    scene: &'a Scene,
+    orig: &Vec3f,
+    dir: &Vec3f,
+    t_max: Float,
+    rng: &mut Rng,
+) -> Option<(&'a Volume, Float)> {
+    let mut nearest: Option<(&Volume, Float)> = None;
+    for volume in &scene.volumes {
+        let limit = nearest.map_or(t_max, |(_, t)| t);
+        if let Some(t) = volume.sample_collision(orig, dir, limit, rng) {
+            nearest = Some((volume, t));
+        }
+    }
+    nearest
+}
+
+fn medium_transmittance(
+    scene: &Scene,
+    orig: &Vec3f,
+    dir: &Vec3f,
+    t_max: Float,
+    rng: &mut Rng,
+) -> Float {
+    scene
+        .volumes
+        .iter()
+        .map(|volume| volume.transmittance(orig, dir, t_max, rng))
+        .product()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ior::{medium, Dispersion};
+    use crate::light::{AmbientLight, Light};
+    use crate::material::{GLASS, RED_RUBBER};
+    use crate::scene::Checkerboard;
+    use crate::scene_file::SceneFile;
+    use crate::texture::{ImageTexture, Wrap};
+
+    // A sphere filling the middle of a square frame, with the background in the corners
+    fn ball() -> SceneFile {
+        SceneFile::parse(
+            r#"{"camera": {"position": [0, 0, 0], "target": [0, 0, -1], "fov": 60},
+                "background": [0.2, 0.3, 0.5],
+                "objects": [{"type": "sphere", "name": "ball", "center": [0, 0, -5],
+                             "radius": 1.5, "material": "red_rubber"}],
+                "lights": [{"position": [-10, 10, 10]}]}"#,
+        )
+        .unwrap()
+    }
+
+    fn small(width: usize, height: usize) -> RenderSettings {
+        RenderSettings {
+            width,
+            height,
+            threads: Some(2),
+            ..RenderSettings::default()
+        }
+    }
+
+    fn sample(color: Vec3f) -> Sample {
+        Sample {
+            color,
+            alpha: 1.0,
+            object_id: 0,
+        }
+    }
+
+    #[test]
+    fn splats_spread_each_sample_by_its_filter() {
+        let rect = TileRect {
+            x0: 0,
+            y0: 0,
+            x1: 8,
+            y1: 8,
+        };
+        let filters = [
+            PixelFilter::Box,
+            PixelFilter::Tent,
+            PixelFilter::gaussian(),
+            PixelFilter::mitchell(),
+        ];
+        let color = Vec3f(0.25, 0.5, 2.0);
+        for filter in filters {
+            // Four by four samples a pixel over the whole rectangle
+            let mut accumulator = Accumulator::new(rect, false, false);
+            for j in 0..32 {
+                for i in 0..32 {
+                    let (sx, sy) = ((i as Float + 0.5) / 4.0, (j as Float + 0.5) / 4.0);
+                    accumulator.splat(&filter, sx, sy, &sample(color));
+                }
+            }
+            // However the weights fall, a constant image stays constant
+            for pixel in accumulator.resolve_rect(&rect) {
+                assert!((pixel - color).length() < 1e-5, "{:?}: {:?}", filter, pixel);
+            }
+            // Kernels integrating to 1 weigh each pixel well inside by its 16 samples
+            if !matches!(filter, PixelFilter::Gaussian { .. }) {
+                let weight = accumulator.weights[accumulator.index(4, 3)];
+                assert!((weight - 16.0).abs() < 0.3, "{:?}: {}", filter, weight);
+            }
+        }
+
+        // A box-filtered sample lands in its own pixel only; a tent reaches the nearest four
+        let mut accumulator = Accumulator::new(rect, false, false);
+        accumulator.splat(&PixelFilter::Box, 3.3, 4.6, &sample(color));
+        let touched: Vec<usize> = (0..64).filter(|&i| accumulator.weights[i] != 0.0).collect();
+        assert_eq!(touched, [accumulator.index(3, 4)]);
+        let mut accumulator = Accumulator::new(rect, false, false);
+        accumulator.splat(&PixelFilter::Tent, 3.3, 4.6, &sample(color));
+        let touched = (0..64).filter(|&i| accumulator.weights[i] != 0.0).count();
+        assert_eq!(touched, 4);
+        let total: Float = accumulator.weights.iter().sum();
+        // (0.8 + 0.2) * (0.9 + 0.1): the four pixel centers' tent weights
+        assert!((total - 1.0).abs() < 1e-5, "{}", total);
+        // Past the rectangle's edge the sample is cut off rather than wrapped
+        let mut accumulator = Accumulator::new(rect, false, false);
+        accumulator.splat(&PixelFilter::mitchell(), 0.1, 7.9, &sample(color));
+        let touched = (0..64).filter(|&i| accumulator.weights[i] != 0.0).count();
+        assert_eq!(touched, 4);
+    }
+
+    #[test]
+    fn deterministic_renders_match_across_thread_counts() {
+        let file = SceneFile::parse(
+            r#"{"camera": {"position": [0, 0, 0], "target": [0, 0, -1], "fov": 60},
+                "background": [0.2, 0.3, 0.5],
+                "objects": [
+                    {"type": "sphere", "center": [-1, 0, -5], "radius": 1.5, "material": "ivory"},
+                    {"type": "sphere", "center": [1.5, 0.5, -6], "radius": 1, "material": "glass"},
+                    {"type": "sphere", "center": [0, -1002, -5], "radius": 1000,
+                     "material": "red_rubber"}],
+                "lights": [{"position": [-10, 10, 10], "intensity": 1.5}]}"#,
+        )
+        .unwrap();
+        let scene = file.scene;
+        // Small tiles in spiral order under a wide filter, so footprints overlap many tiles
+        // and finish in whatever order the threads reach them
+        let settings = RenderSettings {
+            width: 23,
+            height: 17,
+            samples_per_pixel: 3,
+            integrator: Integrator::Path,
+            tile_size: 4,
+            tile_order: TileOrder::Spiral,
+            filter: PixelFilter::mitchell(),
+            object_ids: true,
+            variance: true,
+            deterministic: true,
+            ..RenderSettings::default()
+        };
+        let bits = |image: &Framebuffer| {
+            let (mut bits, mut ids) = (Vec::new(), Vec::new());
+            for v in image.pixels.iter().chain(image.deviation.as_ref().unwrap()) {
+                bits.extend([v.0, v.1, v.2].map(Float::to_bits));
+            }
+            for pixel in image.coverage.as_ref().unwrap() {
+                for &(id, weight) in pixel {
+                    bits.push(weight.to_bits());
+                    ids.push(id);
+                }
+            }
+            (bits, ids)
+        };
+        let render_on = |threads| {
+            render(
+                &scene,
+                &file.camera,
+                &RenderSettings {
+                    threads: Some(threads),
+                    ..settings.clone()
+                },
+            )
+        };
+        let single = render_on(1);
+        assert!(single.pixels.iter().any(|p| *p != single.pixels[0]));
+        let expected = bits(&single);
+        for threads in [2, 3, 8] {
+            assert!(bits(&render_on(threads)) == expected, "{} threads", threads);
+        }
+    }
+
+    #[test]
+    fn transparent_backgrounds_leave_the_scene_over_nothing() {
+        let file = ball();
+        let settings = RenderSettings {
+            samples_per_pixel: 4,
+            ..small(16, 16)
+        };
+        let opaque = render(&file.scene, &file.camera, &settings);
+        assert!(opaque.alpha.is_none());
+        assert_eq!(opaque.get(0, 0), Vec3f(0.2, 0.3, 0.5));
+
+        let settings = RenderSettings {
+            transparent_background: true,
+            ..settings
+        };
+        let image = render(&file.scene, &file.camera, &settings);
+        let alpha = image.alpha.as_ref().unwrap();
+        // Nothing but background in the corner, all ball in the middle
+        assert_eq!(alpha[0], 0.0);
+        assert_eq!(image.get(0, 0), Vec3f(0.0, 0.0, 0.0));
+        assert_eq!(alpha[8 * 16 + 8], 1.0);
+        assert_eq!(image.get(8, 8), opaque.get(8, 8));
+        // The ball's edge is partly covered, and color stays premultiplied by coverage
+        assert!(alpha.iter().any(|&a| a > 0.0 && a < 1.0));
+        for (pixel, &a) in image.pixels.iter().zip(alpha) {
+            assert!(pixel.0 <= a + 1e-5, "{:?} at alpha {}", pixel, a);
+        }
+    }
+
+    #[test]
+    fn object_ids_cover_each_pixel_once() {
+        let file = ball();
+        let id = file.scene.get("ball").unwrap().id;
+        assert_ne!(id, BACKGROUND_ID);
+        let settings = RenderSettings {
+            object_ids: true,
+            samples_per_pixel: 9,
+            sampler: Sampler::Halton,
+            ..small(16, 16)
+        };
+        let image = render(&file.scene, &file.camera, &settings);
+        let coverage = image.coverage.as_ref().unwrap();
+        for entries in coverage {
+            let total: Float = entries.iter().map(|&(_, c)| c).sum();
+            assert!((total - 1.0).abs() < 1e-5, "{:?}", entries);
+            assert!(entries.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        }
+        let ids = image.object_ids().unwrap();
+        assert_eq!(ids[0], BACKGROUND_ID);
+        assert_eq!(ids[8 * 16 + 8], id);
+        let edge = coverage
+            .iter()
+            .position(|entries| entries.len() == 2)
+            .unwrap();
+        let (matte, background) = (
+            image.matte(id).unwrap(),
+            image.matte(BACKGROUND_ID).unwrap(),
+        );
+        assert!(matte[edge] > 0.0 && matte[edge] < 1.0);
+        assert!((matte[edge] + background[edge] - 1.0).abs() < 1e-5);
+        assert_eq!(matte[8 * 16 + 8], 1.0);
+        assert_eq!(image.matte(id + 1).unwrap(), vec![0.0; 256]);
+        // Untracked renders have no ID pass to give
+        let plain = render(&file.scene, &file.camera, &small(4, 4));
+        assert!(plain.object_ids().is_none());
+        let path = std::env::temp_dir().join("rusty_rays_untracked_ids.png");
+        assert!(plain.write_object_ids(&path).is_err());
+    }
+
+    #[test]
+    fn crops_match_the_same_region_of_a_full_render() {
+        let file = ball();
+        // Footprints and the denoiser both reach past the crop, so it has to render beyond
+        let settings = RenderSettings {
+            samples_per_pixel: 4,
+            filter: PixelFilter::mitchell(),
+            variance: true,
+            denoise: Some(Denoise::default()),
+            ..small(24, 20)
+        };
+        let full = render(&file.scene, &file.camera, &settings);
+        let crop = TileRect {
+            x0: 5,
+            y0: 3,
+            x1: 14,
+            y1: 11,
+        };
+        let cropped = render(
+            &file.scene,
+            &file.camera,
+            &RenderSettings {
+                crop: Some(crop),
+                ..settings.clone()
+            },
+        );
+        assert_eq!((cropped.width, cropped.height), (24, 20));
+        for y in 0..20 {
+            for x in 0..24 {
+                let inside = (crop.x0..crop.x1).contains(&x) && (crop.y0..crop.y1).contains(&y);
+                let expected = if inside {
+                    full.get(x, y)
+                } else {
+                    Vec3f(0.0, 0.0, 0.0)
+                };
+                assert!(
+                    (cropped.get(x, y) - expected).length() < 1e-5,
+                    "({}, {}): {:?} against {:?}",
+                    x,
+                    y,
+                    cropped.get(x, y),
+                    expected
+                );
+            }
+        }
+        // A crop entirely off the frame renders nothing
+        let outside = RenderSettings {
+            crop: Some(TileRect {
+                x0: 30,
+                y0: 0,
+                x1: 40,
+                y1: 5,
+            }),
+            ..settings
+        };
+        let image = render(&file.scene, &file.camera, &outside);
+        assert!(image.pixels.iter().all(|p| *p == Vec3f(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn reads_resolution_presets_and_sizes() {
+        assert_eq!(parse_resolution("1080p"), Some((1920, 1080)));
+        assert_eq!(parse_resolution("4K"), Some((3840, 2160)));
+        assert_eq!(parse_resolution("Square"), Some((1024, 1024)));
+        assert_eq!(parse_resolution("640x480"), Some((640, 480)));
+        assert_eq!(parse_resolution("8X6"), Some((8, 6)));
+        for bad in [
+            "",
+            "640",
+            "640x",
+            "x480",
+            "0x480",
+            "640x0",
+            "-640x480",
+            "640x480x2",
+            "8k",
+        ] {
+            assert_eq!(parse_resolution(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn traced_samples_are_the_ones_rendered() {
+        let file = ball();
+        for integrator in [Integrator::Whitted, Integrator::Path] {
+            let settings = RenderSettings {
+                samples_per_pixel: 5,
+                integrator,
+                ..small(12, 12)
+            };
+            let image = render(&file.scene, &file.camera, &settings);
+            for (x, y) in [(0, 0), (6, 6), (3, 8)] {
+                let traces = trace_pixel(&file.scene, &file.camera, &settings, x, y);
+                assert_eq!(traces.len(), 5);
+                let mean = traces
+                    .iter()
+                    .fold(Vec3f(0.0, 0.0, 0.0), |sum, trace| sum + trace.color)
+                    * (1.0 / 5.0);
+                assert!(
+                    (mean - image.get(x, y)).length() < 1e-5,
+                    "{:?} ({}, {}): {:?} against {:?}",
+                    integrator,
+                    x,
+                    y,
+                    mean,
+                    image.get(x, y)
+                );
+                for trace in &traces {
+                    assert_eq!(trace.vertices[0].event, PathEvent::Camera);
+                    let (sx, sy) = trace.sample;
+                    assert!(sx >= x as Float && sx < x as Float + 1.0);
+                    assert!(sy >= y as Float && sy < y as Float + 1.0);
+                    assert!(trace.vertices[1..].iter().all(|v| v.parent.is_some()));
+                }
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct Counted {
+        rays: std::sync::Mutex<Vec<RayStats>>,
+        objects: std::sync::Mutex<Vec<ObjectStats>>,
+    }
+
+    impl RenderObserver for Counted {
+        fn on_render_end(&self, stats: &RayStats) {
+            self.rays.lock().unwrap().push(*stats);
+        }
+
+        fn on_object_stats(&self, stats: &ObjectStats) {
+            self.objects.lock().unwrap().push(stats.clone());
+        }
+    }
+
+    #[test]
+    fn counts_the_rays_of_each_render_alone() {
+        let file = ball();
+        let settings = RenderSettings {
+            samples_per_pixel: 2,
+            ..small(16, 16)
+        };
+        let counted = Counted::default();
+        for _ in 0..2 {
+            render_with(&file.scene, &file.camera, &settings, &counted);
+        }
+        // Counts start afresh each render rather than adding to the last one's
+        let rays = counted.rays.lock().unwrap();
+        assert_eq!(rays.len(), 2);
+        assert_eq!(rays[0], rays[1]);
+        let stats = rays[0];
+        assert_eq!(stats.camera_rays, 16 * 16 * 2);
+        assert!(
+            stats.secondary_rays > 0 && stats.shadow_rays > 0,
+            "{:?}",
+            stats
+        );
+        assert!(stats.bvh_node_tests > 0);
+        assert_eq!(
+            stats.total_rays(),
+            stats.camera_rays + stats.secondary_rays + stats.shadow_rays
+        );
+        let bounces = stats.secondary_rays as f64 / stats.camera_rays as f64;
+        assert!((stats.average_bounces() - bounces).abs() < 1e-12);
+        assert_eq!(RayStats::default().average_bounces(), 0.0);
+
+        let objects = counted.objects.lock().unwrap();
+        if cfg!(feature = "stats") {
+            assert_eq!(objects.len(), 2);
+            assert_eq!(objects[0], objects[1]);
+            // Only the ball is there to hit, and the rays round its edge miss it
+            let hits = objects[0].get(file.scene.get("ball").unwrap().id);
+            assert_eq!(objects[0].hits.len(), 1);
+            assert!(
+                hits.camera > 0 && hits.camera < stats.camera_rays,
+                "{:?}",
+                hits
+            );
+            assert!(hits.shadow <= stats.shadow_rays);
+            assert!(hits.secondary <= stats.secondary_rays);
+        } else {
+            assert!(objects.iter().all(|o| o.hits.is_empty()));
+        }
+    }
+
+    #[test]
+    fn exposure_scales_every_sample() {
+        let file = ball();
+        let settings = small(16, 16);
+        let image = render(&file.scene, &file.camera, &settings);
+        let camera = file.camera.clone().with_exposure_compensation(-1.0);
+        let darker = render(&file.scene, &camera, &settings);
+        for (a, b) in image.pixels.iter().zip(&darker.pixels) {
+            assert!((*a * 0.5 - *b).length() < 1e-5, "{:?} and {:?}", a, b);
+        }
+        assert_eq!(darker.get(0, 0), Vec3f(0.1, 0.15, 0.25));
+    }
+
+    #[test]
+    fn dispersive_glass_splits_only_what_it_bends_apart() {
+        let with_glass = |dispersion| {
+            let mut file = ball();
+            file.scene.floor = Some(Checkerboard {
+                height: -1.6,
+                min: (-10.0, -30.0),
+                max: (10.0, 0.0),
+                colors: [Vec3f(1.0, 1.0, 1.0), Vec3f(0.0, 0.0, 0.0)],
+            });
+            file.scene.get_mut("ball").unwrap().material = Material {
+                dispersion,
+                ..GLASS
+            };
+            file
+        };
+        let settings = small(24, 24);
+        let image = |file: &SceneFile| render(&file.scene, &file.camera, &settings);
+        let plain = image(&with_glass(None));
+
+        // One index for every wavelength bends every channel alike
+        let flat = Dispersion::Cauchy {
+            a: GLASS.refractive_index,
+            b: 0.0,
+            c: 0.0,
+        };
+        assert_eq!(image(&with_glass(Some(flat))).pixels, plain.pixels);
+
+        let flint = medium("flint_glass").unwrap();
+        let material = Material {
+            dispersion: Some(flint),
+            ..GLASS
+        };
+        assert!(material.channel_index(Some(2)) > material.channel_index(Some(0)));
+        assert_eq!(material.channel_index(None), GLASS.refractive_index);
+        let split = image(&with_glass(Some(flint)));
+        // The background around the ball is untouched, but what is seen through it shifts
+        assert_eq!(split.get(0, 0), plain.get(0, 0));
+        let changed = split
+            .pixels
+            .iter()
+            .zip(&plain.pixels)
+            .filter(|(a, b)| (**a - **b).length() > 1e-3)
+            .count();
+        assert!(changed > 0 && changed < 24 * 24 / 2, "{}", changed);
+    }
+
+    #[test]
+    fn deviations_spread_with_each_pixels_samples() {
+        let rect = TileRect {
+            x0: 0,
+            y0: 0,
+            x1: 4,
+            y1: 4,
+        };
+        assert!(Accumulator::new(rect, false, false)
+            .resolve_deviation(&rect)
+            .is_none());
+        let mut accumulator = Accumulator::new(rect, false, true);
+        for (i, red) in [0.0, 1.0, 0.0, 1.0].into_iter().enumerate() {
+            let at = 2.2 + 0.2 * i as Float;
+            accumulator.splat(&PixelFilter::Box, at, at, &sample(Vec3f(red, 3.0, 0.0)));
+        }
+        let deviation = accumulator.resolve_deviation(&rect).unwrap();
+        let spread = deviation[2 * 4 + 2];
+        assert!((spread.0 - 0.5).abs() < 1e-5, "{:?}", spread);
+        assert!(spread.1.abs() < 1e-3 && spread.2 == 0.0, "{:?}", spread);
+        assert_eq!(deviation[0], Vec3f(0.0, 0.0, 0.0));
+
+        // Over a render, only pixels whose samples see different things spread at all
+        let file = ball();
+        let settings = RenderSettings {
+            samples_per_pixel: 8,
+            variance: true,
+            ..small(16, 16)
+        };
+        let image = render(&file.scene, &file.camera, &settings);
+        let deviation = image.deviation.as_ref().unwrap();
+        // Bar the rounding of a mean of squares less a squared mean
+        assert!(deviation[0].length() < 1e-3, "{:?}", deviation[0]);
+        let edges = deviation.iter().filter(|d| d.0 > 0.05).count();
+        assert!(edges > 0 && edges < 16 * 16 / 2, "{}", edges);
+        assert!(render(&file.scene, &file.camera, &small(16, 16))
+            .deviation
+            .is_none());
+    }
+
+    #[test]
+    fn budgets_average_as_many_passes_as_fit() {
+        let file = ball();
+        let settings = RenderSettings {
+            variance: true,
+            ..small(8, 8)
+        };
+        // The first pass runs however short the budget, and is a plain render
+        let (image, passes) =
+            render_within(&file.scene, &file.camera, &settings, Duration::ZERO, &());
+        assert_eq!(passes, 1);
+        let plain = render(&file.scene, &file.camera, &settings);
+        assert_eq!(image.pixels, plain.pixels);
+
+        let start = Instant::now();
+        let budget = Duration::from_millis(200);
+        let (image, passes) = render_within(&file.scene, &file.camera, &settings, budget, &());
+        assert!(passes > 1);
+        assert!(start.elapsed() < budget * 3, "{:?}", start.elapsed());
+        // The mean of the passes, each under the next seed
+        let mut mean = vec![Vec3f(0.0, 0.0, 0.0); 64];
+        for pass in 0..passes {
+            let seeded = RenderSettings {
+                seed: settings.seed.wrapping_add(pass as u64),
+                ..settings.clone()
+            };
+            for (m, p) in mean
+                .iter_mut()
+                .zip(render(&file.scene, &file.camera, &seeded).pixels)
+            {
+                *m += p * (1.0 / passes as Float);
+            }
+        }
+        for (a, b) in image.pixels.iter().zip(&mean) {
+            assert!((*a - *b).length() < 1e-4, "{:?} against {:?}", a, b);
+        }
+        // One sample a pass, so the spread is now that of the passes' samples
+        let deviation = image.deviation.as_ref().unwrap();
+        assert!(deviation.iter().any(|d| d.0 > 0.0));
+        assert!(deviation[0].length() < 1e-3);
+    }
+
+    #[test]
+    fn units_scale_the_distances_rays_work_to() {
+        let millimeters = |units: &str| {
+            SceneFile::parse(&format!(
+                r#"{{"camera": {{"position": [0, 0, 0], "target": [0, 0, -1000], "fov": 60}},
+                    "background": [0.2, 0.3, 0.5],{}
+                    "objects": [{{"type": "sphere", "name": "ball", "center": [0, 0, -5000],
+                                 "radius": 1500, "material": "red_rubber"}}],
+                    "lights": [{{"position": [-10000, 10000, 10000]}}]}}"#,
+                units
+            ))
+            .unwrap()
+        };
+        let settings = small(16, 16);
+        let meters = ball();
+        let expected = render(&meters.scene, &meters.camera, &settings);
+        let file = millimeters(r#" "units": "mm","#);
+        assert_eq!(file.scene.units_per_meter, 1000.0);
+        assert_eq!(
+            file.scene.max_distance(),
+            1000.0 * meters.scene.max_distance()
+        );
+        let image = render(&file.scene, &file.camera, &settings);
+        let differ = image
+            .pixels
+            .iter()
+            .zip(&expected.pixels)
+            .filter(|(a, b)| (**a - **b).length() > 1e-2)
+            .count();
+        assert!(differ <= 2, "{} pixels differ", differ);
+
+        // Read as meters, the ball is past where rays give up
+        let file = millimeters("");
+        let image = render(&file.scene, &file.camera, &settings);
+        assert!(image.pixels.iter().all(|p| *p == Vec3f(0.2, 0.3, 0.5)));
+    }
+
+    #[test]
+    fn both_light_strategies_add_up_to_the_whole_light() {
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(Vec3f(0.0, 3.0, 0.0), 2.0).with_radius(1.0));
+        let point = Vec3f(0.0, 0.0, 0.0);
+        let n = Vec3f(0.0, 1.0, 0.0);
+        let white = |_: &Vec3f| Vec3f(1.0, 1.0, 1.0);
+        let mean = |scene: &Scene, lobes: Option<&SurfaceLobes>| {
+            let mut rng = Rng::new(3);
+            let count = 20000;
+            let total: Float = (0..count)
+                .map(|_| direct_light(scene, &point, Some((&n, 1)), lobes, &mut rng, white).0)
+                .sum();
+            total / count as Float
+        };
+        // However the samples are shared between the light and a surface's lobes, the
+        // weights sum to one and every way arrives at the light's intensity
+        assert!((mean(&scene, None) - 2.0).abs() < 1e-3);
+        let looking_down = Vec3f(0.0, -1.0, 0.0);
+        for exponent in [1.0, 50.0, 2000.0] {
+            let material = Material {
+                specular_exponent: exponent,
+                ..RED_RUBBER
+            };
+            let lobes = SurfaceLobes::new(&material, None, &n, &looking_down).unwrap();
+            let found = mean(&scene, Some(&lobes));
+            assert!(
+                (found - 2.0).abs() < 0.04,
+                "exponent {}: {}",
+                exponent,
+                found
+            );
+        }
+
+        // Point lights are exact, and the weights favour the sharper strategy
+        scene.lights[0].radius = 0.0;
+        assert_eq!(mean(&scene, None), 2.0);
+        assert!(power_heuristic(4.0, 1.0) > 0.9);
+        assert!((power_heuristic(2.0, 2.0) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn regularizing_widens_sharp_lobes_and_keeps_their_energy() {
+        let energy = |m: &Material| {
+            let sharpness = m
+                .anisotropy
+                .map_or(m.specular_exponent + 1.0, |a| a.sharpness());
+            m.albedo[1] / sharpness
+        };
+        let shiny = Material {
+            specular_exponent: 1000.0,
+            ..RED_RUBBER
+        };
+        let blurred = roughened(&shiny, 0.5);
+        let anisotropy = blurred.anisotropy.unwrap();
+        assert_eq!(anisotropy.roughness, (0.25, 0.25));
+        assert!(blurred.albedo[1] < shiny.albedo[1]);
+        assert!((energy(&blurred) / energy(&shiny) - 1.0).abs() < 1e-4);
+        assert_eq!(blurred.diffuse_color, shiny.diffuse_color);
+        // No roughness at all keeps the highlight as wide as it was
+        let kept = roughened(&shiny, 0.0);
+        let (nu, nv) = (
+            kept.anisotropy.unwrap().sharpness(),
+            shiny.specular_exponent + 1.0,
+        );
+        assert!((nu / nv - 1.0).abs() < 0.05, "{} against {}", nu, nv);
+        // Lobes rougher than asked are left alone, and only the smoother axis widens
+        let brushed = Material {
+            anisotropy: Some(Anisotropy {
+                roughness: (0.1, 0.6),
+                rotation: 0.4,
+            }),
+            ..shiny
+        };
+        let widened = roughened(&brushed, 0.5).anisotropy.unwrap();
+        assert_eq!(widened.roughness, (0.25, 0.6));
+        assert_eq!(widened.rotation, 0.4);
+        let rough = roughened(&brushed, 0.9).anisotropy.unwrap();
+        let again = roughened(&roughened(&brushed, 0.9), 0.5)
+            .anisotropy
+            .unwrap();
+        assert_eq!(again.roughness, rough.roughness);
+    }
+
+    #[test]
+    fn ambient_light_alone_shades_diffuse_surfaces_flat() {
+        let mut file = ball();
+        file.scene.lights.clear();
+        let ambient = Vec3f(0.5, 0.25, 1.0);
+        file.scene
+            .ambient_lights
+            .push(AmbientLight { color: ambient });
+        let image = render(&file.scene, &file.camera, &small(16, 16));
+        let lit = RED_RUBBER.diffuse_color.multiply(&ambient) * RED_RUBBER.albedo[0];
+        for (x, y) in [(8, 8), (6, 9), (10, 7)] {
+            assert!(
+                (image.get(x, y) - lit).length() < 1e-5,
+                "{:?}",
+                image.get(x, y)
+            );
+        }
+        assert_eq!(image.get(0, 0), Vec3f(0.2, 0.3, 0.5));
+    }
+
+    #[test]
+    fn shadow_catchers_show_only_shadows_and_reflections() {
+        let file = |catcher: &str| {
+            SceneFile::parse(&format!(
+                r#"{{"camera": {{"position": [0, 0, 0], "target": [0, 0, -1], "fov": 60}},
+                    "background": [0.2, 0.3, 0.5],
+                    "materials": {{"catcher": {{"shadow_catcher": {}}}}},
+                    "objects": [{{"type": "sphere", "center": [0, 0, -5], "radius": 1,
+                                  "material": "red_rubber"}},
+                                {{"type": "quad", "corner": [-10, -1, 0], "u": [20, 0, 0],
+                                  "v": [0, 0, -20], "material": "catcher"}}],
+                    "lights": [{{"position": [10, 10, -5]}}]}}"#,
+                catcher
+            ))
+            .unwrap()
+        };
+        let settings = small(16, 16);
+        let transparent = RenderSettings {
+            transparent_background: true,
+            ..settings.clone()
+        };
+        let background = Vec3f(0.2, 0.3, 0.5);
+        // The ball's shadow falls to its left; in front of it the ground is lit
+        let (shadow, ground, below) = ((3, 11), 3 + 14 * 16, (8, 13));
+
+        let plain = file("true");
+        let opaque = render(&plain.scene, &plain.camera, &settings);
+        assert_eq!(opaque.get(shadow.0, shadow.1), Vec3f(0.0, 0.0, 0.0));
+        assert_eq!(opaque.get(3, 14), background);
+        assert_eq!(opaque.get(below.0, below.1), background);
+        let image = render(&plain.scene, &plain.camera, &transparent);
+        let alpha = image.alpha.as_ref().unwrap();
+        assert_eq!(alpha[shadow.0 + shadow.1 * 16], 1.0);
+        assert_eq!(image.get(shadow.0, shadow.1), Vec3f(0.0, 0.0, 0.0));
+        assert_eq!(alpha[ground], 0.0);
+        assert_eq!(alpha[below.0 + below.1 * 16], 0.0);
+
+        // Reflections cover the ground under the ball by their strength, but not the shadows
+        let mirror = file(r#"{"reflections": 0.5}"#);
+        let opaque = render(&mirror.scene, &mirror.camera, &settings);
+        assert_eq!(opaque.get(3, 14), background);
+        // Its underside is unlit, so half the background shows through black
+        let reflected = opaque.get(below.0, below.1);
+        assert!(
+            (reflected - background * 0.5).length() < 1e-6,
+            "{:?}",
+            reflected
+        );
+        let image = render(&mirror.scene, &mirror.camera, &transparent);
+        let alpha = image.alpha.as_ref().unwrap();
+        assert!((alpha[below.0 + below.1 * 16] - 0.5).abs() < 1e-6);
+        assert_eq!(alpha[ground], 0.0);
+        assert_eq!(alpha[shadow.0 + shadow.1 * 16], 1.0);
+    }
+
+    #[test]
+    fn backplates_fill_the_frame_behind_camera_rays() {
+        let mut file = ball();
+        let plain = render(&file.scene, &file.camera, &small(16, 16));
+        let corners = [
+            Vec3f(1.0, 0.0, 0.0),
+            Vec3f(0.0, 1.0, 0.0),
+            Vec3f(0.0, 0.0, 1.0),
+            Vec3f(1.0, 1.0, 1.0),
+        ];
+        let mut plate = ImageTexture::new(2, 2, corners.to_vec());
+        plate.wrap = Wrap::Clamp;
+        file.scene.backplate = Some(Arc::new(plate));
+
+        for integrator in [Integrator::Whitted, Integrator::Path] {
+            let settings = RenderSettings {
+                integrator,
+                ..small(16, 16)
+            };
+            let image = render(&file.scene, &file.camera, &settings);
+            // Stretched over the frame the right way up, whatever its size
+            for ((x, y), corner) in [(0, 0), (15, 0), (0, 15), (15, 15)]
+                .into_iter()
+                .zip(corners)
+            {
+                assert!(
+                    (image.get(x, y) - corner).length() < 1e-5,
+                    "{:?}",
+                    image.get(x, y)
+                );
+            }
+            if integrator == Integrator::Whitted {
+                assert_eq!(image.get(8, 8), plain.get(8, 8));
+            }
+            let settings = RenderSettings {
+                transparent_background: true,
+                ..settings
+            };
+            let image = render(&file.scene, &file.camera, &settings);
+            assert_eq!(image.get(0, 0), Vec3f(0.0, 0.0, 0.0));
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use crate::vec3::Float;
+
+// xorshift64* seeded through splitmix64, good enough for Monte Carlo sampling
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        let state = splitmix64(seed);
+        Rng {
+            state: if state == 0 {
+                0x9e37_79b9_7f4a_7c15
+            } else {
+                state
+            },
+        }
+    }
+
+    // Derives an independent stream for e.g. one pixel of one frame
+    pub fn for_stream(seed: u64, stream: u64) -> Rng {
+        Rng::new(seed ^ splitmix64(stream.wrapping_add(0x632b_e59b_d9b4_e019)))
+    }
+
+    #[inline]
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // Uniform in [0, 1)
+    #[inline]
+    pub fn next_float(&mut self) -> Float {
+        (self.next_u64() >> (64 - Float::MANTISSA_DIGITS)) as Float
+            / (1u64 << Float::MANTISSA_DIGITS) as Float
+    }
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
@@ -0,0 +1,210 @@
+use std::sync::OnceLock;
+
+use crate::rng::Rng;
+use crate::vec3::Float;
+
+// How sample positions are spread within and across pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sampler {
+    // Independent uniform jitter; a single sample sits at the pixel centre
+    Random,
+    // The Halton sequence, shifted by a random amount in every pixel
+    Halton,
+    // The Halton sequence, shifted per pixel by a blue-noise mask so neighbouring pixels
+    // get dissimilar offsets; the error left at low sample counts is then high-frequency
+    // noise the eye forgives more readily than white noise
+    BlueNoise,
+}
+
+impl Sampler {
+    pub const NAMES: [(&'static str, Sampler); 3] = [
+        ("random", Sampler::Random),
+        ("halton", Sampler::Halton),
+        ("blue_noise", Sampler::BlueNoise),
+    ];
+
+    pub fn name(self) -> &'static str {
+        Sampler::NAMES
+            .iter()
+            .find(|(_, sampler)| *sampler == self)
+            .map_or("", |(name, _)| name)
+    }
+
+    pub fn from_name(name: &str) -> Option<Sampler> {
+        Sampler::NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(_, sampler)| sampler)
+    }
+
+    pub(crate) fn for_pixel(self, x: usize, y: usize, seed: u64, rng: &mut Rng) -> PixelSampler {
+        let shift = match self {
+            Sampler::Random => (0.0, 0.0),
+            Sampler::Halton => (rng.next_float(), rng.next_float()),
+            Sampler::BlueNoise => {
+                // Each seed views the tiled masks from a different offset, its low half
+                // across and high half down; kept as u64 so 32-bit targets see the same
+                let masks = blue_noise_masks();
+                let offset = Rng::new(seed).next_u64();
+                let size = MASK_SIZE as u64;
+                let mx = ((x as u64 + offset % size) % size) as usize;
+                let my = ((y as u64 + (offset >> 32) % size) % size) as usize;
+                (masks[0][my * MASK_SIZE + mx], masks[1][my * MASK_SIZE + mx])
+            }
+        };
+        PixelSampler {
+            sampler: self,
+            shift,
+        }
+    }
+}
+
+pub(crate) struct PixelSampler {
+    sampler: Sampler,
+    shift: (Float, Float),
+}
+
+impl PixelSampler {
+    // Position within the pixel of sample index out of count
+    pub(crate) fn jitter(&self, index: u32, count: u32, rng: &mut Rng) -> (Float, Float) {
+        match self.sampler {
+            Sampler::Random if count == 1 => (0.5, 0.5),
+            Sampler::Random => (rng.next_float(), rng.next_float()),
+            Sampler::Halton | Sampler::BlueNoise => {
+                // Cranley–Patterson rotation of the Halton points by the pixel's shift
+                let u = radical_inverse(2, index as u64) + self.shift.0;
+                let v = radical_inverse(3, index as u64) + self.shift.1;
+                (wrap(u), wrap(v))
+            }
+        }
+    }
+}
+
+// Folds a value in [0, 2) back into [0, 1)
+fn wrap(v: Float) -> Float {
+    let v = if v >= 1.0 { v - 1.0 } else { v };
+    v.min(1.0 - Float::EPSILON)
+}
+
+// The digits of index in base, mirrored around the radix point
+pub fn radical_inverse(base: u64, mut index: u64) -> Float {
+    let inverse_base = 1.0 / base as f64;
+    let mut scale = inverse_base;
+    let mut result = 0.0;
+    while index > 0 {
+        result += (index % base) as f64 * scale;
+        index /= base;
+        scale *= inverse_base;
+    }
+    result as Float
+}
+
+const MASK_SIZE: usize = 64;
+
+// Two independent blue-noise masks, one per pixel axis, built on first use
+fn blue_noise_masks() -> &'static [Vec<Float>; 2] {
+    static MASKS: OnceLock<[Vec<Float>; 2]> = OnceLock::new();
+    MASKS.get_or_init(|| [void_and_cluster(1), void_and_cluster(2)])
+}
+
+// Ulichney's void-and-cluster method: ranks every cell of a toroidal grid so that the
+// cells below any threshold are spread as evenly as possible, then maps ranks to (0, 1)
+fn void_and_cluster(seed: u64) -> Vec<Float> {
+    const CELLS: usize = MASK_SIZE * MASK_SIZE;
+    const SIGMA: f64 = 1.5;
+
+    // Gaussian energy every point contributes, cut off where it is negligible
+    const RADIUS: isize = 8;
+    let mut kernel = Vec::new();
+    for dy in -RADIUS..=RADIUS {
+        for dx in -RADIUS..=RADIUS {
+            let d2 = (dx * dx + dy * dy) as f64;
+            kernel.push((dx, dy, (-d2 / (2.0 * SIGMA * SIGMA)).exp()));
+        }
+    }
+    let splat = |energy: &mut [f64], cell: usize, sign: f64| {
+        let (cx, cy) = ((cell % MASK_SIZE) as isize, (cell / MASK_SIZE) as isize);
+        let size = MASK_SIZE as isize;
+        for &(dx, dy, weight) in &kernel {
+            let x = (cx + dx).rem_euclid(size) as usize;
+            let y = (cy + dy).rem_euclid(size) as usize;
+            energy[y * MASK_SIZE + x] += sign * weight;
+        }
+    };
+    // The densest occupied cell, or the emptiest free one
+    let extreme = |energy: &[f64], points: &[bool], occupied: bool| {
+        let candidates = (0..CELLS).filter(|&c| points[c] == occupied);
+        let key = |&c: &usize| energy[c];
+        if occupied {
+            candidates.max_by(|a, b| key(a).total_cmp(&key(b)))
+        } else {
+            candidates.min_by(|a, b| key(a).total_cmp(&key(b)))
+        }
+        .expect("grid has a cell of each kind")
+    };
+
+    // Start from a random tenth of the cells and relax it until moving the densest point
+    // into the largest void no longer changes anything
+    let mut rng = Rng::new(seed);
+    let mut points = vec![false; CELLS];
+    let mut energy = vec![0.0; CELLS];
+    let initial = CELLS / 10;
+    let mut placed = 0;
+    while placed < initial {
+        let cell = (rng.next_u64() % CELLS as u64) as usize;
+        if !points[cell] {
+            points[cell] = true;
+            splat(&mut energy, cell, 1.0);
+            placed += 1;
+        }
+    }
+    loop {
+        let cluster = extreme(&energy, &points, true);
+        points[cluster] = false;
+        splat(&mut energy, cluster, -1.0);
+        let void = extreme(&energy, &points, false);
+        points[void] = true;
+        splat(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0usize; CELLS];
+    // Ranks below the initial pattern: remove its densest points first, so they rank last
+    {
+        let mut points = points.clone();
+        let mut energy = energy.clone();
+        for rank in (0..initial).rev() {
+            let cluster = extreme(&energy, &points, true);
+            points[cluster] = false;
+            splat(&mut energy, cluster, -1.0);
+            ranks[cluster] = rank;
+        }
+    }
+    // Ranks above it: keep filling the largest void
+    for rank in initial..CELLS {
+        let void = extreme(&energy, &points, false);
+        points[void] = true;
+        splat(&mut energy, void, 1.0);
+        ranks[void] = rank;
+    }
+
+    ranks
+        .into_iter()
+        .map(|rank| ((rank as f64 + 0.5) / CELLS as f64) as Float)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_read_back_as_their_samplers() {
+        for (name, sampler) in Sampler::NAMES {
+            assert_eq!(sampler.name(), name);
+            assert_eq!(Sampler::from_name(name), Some(sampler));
+        }
+    }
+}
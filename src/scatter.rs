@@ -0,0 +1,246 @@
+// Places many copies of one shape at random over a surface or through a box, for grass,
+// rocks, forests and asteroid belts. Placements are spread evenly by area or volume,
+// optionally thinned by a density map and kept a minimum distance apart (Poisson-disk
+// dart throwing), and each gets its own random scale and rotation. The copies share the
+// one shape, so a thousand blades of grass cost one mesh.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::bvh::Aabb;
+use crate::group::Group;
+use crate::material::Material;
+use crate::mesh::TriangleMesh;
+use crate::rng::Rng;
+use crate::shapes::Shape;
+use crate::texture::ImageTexture;
+use crate::transform::{Transform, Transformed};
+use crate::vec3::{consts::PI, Float, Vec3f};
+
+// Candidates tried for each placement wanted before giving up on a crowded or sparse
+// region, which then gets fewer than count
+const ATTEMPTS: usize = 30;
+
+#[derive(Clone)]
+pub struct Scatter {
+    pub count: usize,
+    pub seed: u64,
+    // No two placements closer than this, measured between their origins
+    pub min_distance: Option<Float>,
+    // Keeps a candidate with the probability of the map's luminance under it, clamped to
+    // [0, 1]. Surfaces are looked up by their texture coordinates, and boxes and meshes
+    // without any by x and z across the region's bounds, as a map seen from above.
+    pub density: Option<Arc<ImageTexture>>,
+    // Each copy is scaled by a uniform factor drawn from this range
+    pub scale: (Float, Float),
+    // Random turns about x, y and z of up to this many radians either way; (0, PI, 0)
+    // spins each copy to any heading
+    pub rotation: Vec3f,
+    // On a surface, turns each copy's +y to the surface normal before placing it;
+    // otherwise +y stays up
+    pub align_to_normal: bool,
+}
+
+impl Default for Scatter {
+    fn default() -> Scatter {
+        Scatter {
+            count: 100,
+            seed: 0,
+            min_distance: None,
+            density: None,
+            scale: (1.0, 1.0),
+            rotation: Vec3f(0.0, 0.0, 0.0),
+            align_to_normal: false,
+        }
+    }
+}
+
+impl Scatter {
+    // Placements on the surface of mesh, for shapes modelled standing on the origin
+    pub fn on_surface(&self, mesh: &TriangleMesh) -> Vec<Transform> {
+        let vertices = mesh.vertices();
+        let faces = mesh.faces();
+        // Running total of triangle areas, to pick triangles in proportion to their area
+        let mut areas = Vec::with_capacity(faces.len());
+        let mut total = 0.0;
+        for &[a, b, c] in faces {
+            total += (vertices[b] - vertices[a])
+                .cross(&(vertices[c] - vertices[a]))
+                .length()
+                * 0.5;
+            areas.push(total);
+        }
+        if total <= 0.0 || !total.is_finite() {
+            return Vec::new();
+        }
+        let bounds = mesh.bounds();
+        self.place(|rng| {
+            let target = rng.next_float() * total;
+            let face = areas
+                .partition_point(|&area| area <= target)
+                .min(faces.len() - 1);
+            let [a, b, c] = faces[face];
+            // Uniform over the triangle
+            let (r1, r2) = (rng.next_float().sqrt(), rng.next_float());
+            let (wa, wb, wc) = (1.0 - r1, r1 * (1.0 - r2), r1 * r2);
+            let point = vertices[a] * wa + vertices[b] * wb + vertices[c] * wc;
+            let normal = match mesh.normals() {
+                Some(normals) => normals[a] * wa + normals[b] * wb + normals[c] * wc,
+                None => (vertices[b] - vertices[a]).cross(&(vertices[c] - vertices[a])),
+            };
+            let uv = match mesh.uvs() {
+                Some(uvs) => (
+                    uvs[a].0 * wa + uvs[b].0 * wb + uvs[c].0 * wc,
+                    uvs[a].1 * wa + uvs[b].1 * wb + uvs[c].1 * wc,
+                ),
+                None => from_above(&bounds, &point),
+            };
+            (point, normal.normalized(), uv)
+        })
+    }
+
+    // Placements filling bounds
+    pub fn in_volume(&self, bounds: &Aabb) -> Vec<Transform> {
+        let size = bounds.max - bounds.min;
+        if !(size.0 >= 0.0 && size.1 >= 0.0 && size.2 >= 0.0) {
+            return Vec::new();
+        }
+        self.place(|rng| {
+            let point = bounds.min
+                + Vec3f(
+                    rng.next_float() * size.0,
+                    rng.next_float() * size.1,
+                    rng.next_float() * size.2,
+                );
+            (point, None, from_above(bounds, &point))
+        })
+    }
+
+    // Draws candidates from sample, a point with its normal and density map coordinates,
+    // until count are placed or the attempts run out
+    fn place<F>(&self, mut sample: F) -> Vec<Transform>
+    where
+        F: FnMut(&mut Rng) -> (Vec3f, Option<Vec3f>, (Float, Float)),
+    {
+        let mut rng = Rng::new(self.seed);
+        let mut placed = Vec::with_capacity(self.count);
+        let mut grid = self.min_distance.map(PoissonGrid::new);
+        for _ in 0..self.count.saturating_mul(ATTEMPTS) {
+            if placed.len() == self.count {
+                break;
+            }
+            let (point, normal, (u, v)) = sample(&mut rng);
+            if let Some(density) = &self.density {
+                let texel = density.sample_level(u, v, 0.0);
+                let luminance = 0.2126 * texel.0 + 0.7152 * texel.1 + 0.0722 * texel.2;
+                if rng.next_float() >= luminance {
+                    continue;
+                }
+            }
+            if let Some(grid) = &mut grid {
+                if !grid.insert(point) {
+                    continue;
+                }
+            }
+            let (low, high) = self.scale;
+            let scale = low + (high - low) * rng.next_float();
+            let mut jitter = || rng.next_float() * 2.0 - 1.0;
+            let turn = Vec3f(
+                self.rotation.0 * jitter(),
+                self.rotation.1 * jitter(),
+                self.rotation.2 * jitter(),
+            );
+            let mut to_world =
+                Transform::scaling(Vec3f(scale, scale, scale)).then(&Transform::euler(turn));
+            if let (true, Some(normal)) = (self.align_to_normal, normal) {
+                to_world = to_world.then(&up_to(normal));
+            }
+            placed.push(to_world.then(&Transform::translation(point)));
+        }
+        placed
+    }
+}
+
+// A group holding a copy of shape at each placement
+pub fn instances(placements: &[Transform], shape: Arc<dyn Shape>, material: Material) -> Group {
+    let mut group = Group::new();
+    for placement in placements {
+        group.add(
+            Transformed::new(Box::new(Arc::clone(&shape)), *placement),
+            material,
+        );
+    }
+    group
+}
+
+// Coordinates of point across the x and z extent of bounds, so the map lies over them
+// as seen from above with -z at its top
+fn from_above(bounds: &Aabb, point: &Vec3f) -> (Float, Float) {
+    let size = bounds.max - bounds.min;
+    let across = |at: Float, lo: Float, extent: Float| {
+        if extent > 0.0 {
+            (at - lo) / extent
+        } else {
+            0.5
+        }
+    };
+    (
+        across(point.0, bounds.min.0, size.0),
+        1.0 - across(point.2, bounds.min.2, size.2),
+    )
+}
+
+// The shortest turn taking +y to the unit vector normal
+fn up_to(normal: Vec3f) -> Transform {
+    let up = Vec3f(0.0, 1.0, 0.0);
+    match up.cross(&normal).normalized() {
+        Some(axis) => Transform::rotation(axis, normal.1.clamp(-1.0, 1.0).acos()),
+        None if normal.1 < 0.0 => Transform::rotation(Vec3f(1.0, 0.0, 0.0), PI),
+        None => Transform::identity(),
+    }
+}
+
+// Accepted points bucketed into cells min_distance across, so a candidate need only be
+// checked against the cells around its own
+struct PoissonGrid {
+    min_distance: Float,
+    cells: HashMap<(i64, i64, i64), Vec<Vec3f>>,
+}
+
+impl PoissonGrid {
+    fn new(min_distance: Float) -> PoissonGrid {
+        PoissonGrid {
+            min_distance,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, point: &Vec3f) -> (i64, i64, i64) {
+        let at = |x: Float| (x / self.min_distance).floor() as i64;
+        (at(point.0), at(point.1), at(point.2))
+    }
+
+    // Adds point unless it is too close to one already added
+    fn insert(&mut self, point: Vec3f) -> bool {
+        if self.min_distance <= 0.0 || self.min_distance.is_nan() {
+            return true;
+        }
+        let (x, y, z) = self.cell(&point);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let Some(near) = self.cells.get(&(x + dx, y + dy, z + dz)) else {
+                        continue;
+                    };
+                    if near
+                        .iter()
+                        .any(|other| (*other - point).length() < self.min_distance)
+                    {
+                        return false;
+                    }
+                }
+            }
+        }
+        self.cells.entry((x, y, z)).or_default().push(point);
+        true
+    }
+}
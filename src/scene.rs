@@ -0,0 +1,1234 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::bvh::{Aabb, Bvh};
+use crate::camera::Frustum;
+use crate::clip::{ClipPlane, ClipWindow};
+use crate::differential::RayDifferential;
+use crate::environment::Environment;
+use crate::group::{Group, Node};
+use crate::layers::{LayerRole, RenderLayer};
+use crate::light::{AmbientLight, HemisphereLight, Light};
+use crate::lod::View;
+use crate::log::{self, Level};
+use crate::material::{BlendMaterial, Material, MaterialOverride, Sides};
+use crate::portal::Portal;
+use crate::shapes::{HitRecord, Shape};
+use crate::stats;
+use crate::texture::{ImageTexture, TextureMap};
+use crate::transform::{Transform, Transformed};
+use crate::vec3::{Float, Vec3f};
+use crate::volume::Volume;
+
+// Hits farther away than this many meters are ignored unless a camera far plane says
+// otherwise
+const NEAREST_DIST_THRESHOLD: Float = 1000.0;
+
+// Object IDs reserved for rays that hit nothing and for the checkerboard floor
+pub const BACKGROUND_ID: u32 = 0;
+pub const FLOOR_ID: u32 = u32::MAX;
+
+// How many meters past a culled back face the search for a front face resumes, and how
+// many back faces in a row it steps through before giving up
+const CULL_STEP: Float = 1e-3;
+const MAX_CULLED_FACES: usize = 8;
+
+pub struct Object {
+    pub shape: Box<dyn Shape>,
+    pub material: Material,
+    pub id: u32,
+    // For finding the object again with Scene::get, e.g. to tweak a loaded scene
+    pub name: Option<String>,
+    // The innermost group the object was added through, if any
+    pub group: Option<GroupId>,
+    pub visibility: Visibility,
+    // Multiplies the diffuse color wherever its mapping places the hit
+    pub texture: Option<TextureMap>,
+    // Mixed into material before the texture applies
+    pub blend: Option<BlendMaterial>,
+    // How the render layer being drawn treats the object
+    pub role: LayerRole,
+}
+
+// Which kinds of ray see an object, for cheats like a ground plane that only shows up
+// in the shadows it casts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Visibility {
+    pub camera: bool,
+    pub shadow: bool,
+    // Reflection, refraction and indirect bounce rays
+    pub reflection: bool,
+}
+
+impl Visibility {
+    pub const ALL: Visibility = Visibility {
+        camera: true,
+        shadow: true,
+        reflection: true,
+    };
+
+    pub fn sees(&self, kind: RayKind) -> bool {
+        match kind {
+            RayKind::Camera => self.camera,
+            RayKind::Shadow => self.shadow,
+            RayKind::Reflection => self.reflection,
+        }
+    }
+}
+
+impl Default for Visibility {
+    fn default() -> Visibility {
+        Visibility::ALL
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RayKind {
+    Camera,
+    Shadow,
+    Reflection,
+}
+
+// Refers to a group added with Scene::add_group
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GroupId(usize);
+
+struct GroupNode {
+    parent: Option<GroupId>,
+    name: Option<String>,
+    visible: bool,
+}
+
+// The checkerboard floor of the classic scene: a bounded plane at a fixed height
+#[derive(Clone, Copy, Debug)]
+pub struct Checkerboard {
+    pub height: Float,
+    pub min: (Float, Float),
+    pub max: (Float, Float),
+    pub colors: [Vec3f; 2],
+}
+
+impl Checkerboard {
+    fn intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<(Float, Vec3f)> {
+        if dir.1.abs() <= 0.001 {
+            return None;
+        }
+        let d = -(orig.1 - self.height) / dir.1;
+        let p = *orig + *dir * d;
+        if d > 0.001 && p.0 > self.min.0 && p.0 < self.max.0 && p.2 > self.min.1 && p.2 < self.max.1
+        {
+            let color = if ((0.5 * p.0 + 1000.0) as i32 + (0.5 * p.2) as i32) & 1 == 0 {
+                self.colors[0]
+            } else {
+                self.colors[1]
+            };
+            return Some((d, color));
+        }
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    // Renders, but probably not as intended
+    Warning,
+    // Renders garbage or not at all
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // The object concerned, if any
+    pub object_id: Option<u32>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn warning(message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            object_id: None,
+            message,
+        }
+    }
+
+    pub fn error(message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            object_id: None,
+            message,
+        }
+    }
+}
+
+impl Diagnostic {
+    // Reports the diagnostic through the log at the matching level
+    pub fn log(&self) {
+        let level = match self.severity {
+            Severity::Warning => Level::Warn,
+            Severity::Error => Level::Error,
+        };
+        crate::log!(level, "{}", self.subject());
+    }
+
+    // The object and message, without the severity
+    fn subject(&self) -> String {
+        match self.object_id {
+            Some(FLOOR_ID) => format!("floor: {}", self.message),
+            Some(id) => format!("object {}: {}", id, self.message),
+            None => self.message.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}", severity, self.subject())
+    }
+}
+
+pub struct Intersection {
+    pub record: HitRecord,
+    pub material: Material,
+    pub object_id: u32,
+    // Hit an object the render layer holds out, which a camera ray shows as a hole
+    pub holdout: bool,
+}
+
+pub struct Scene {
+    objects: Vec<Object>,
+    groups: Vec<GroupNode>,
+    pub lights: Vec<Light>,
+    pub ambient_lights: Vec<AmbientLight>,
+    pub hemisphere_lights: Vec<HemisphereLight>,
+    pub volumes: Vec<Volume>,
+    pub portals: Vec<Portal>,
+    pub clip_planes: Vec<ClipPlane>,
+    // Scene units in a meter, e.g. 1000 for a model in millimeters. The distances rays
+    // are kept off surfaces by and given up after are set in meters and scale with it.
+    pub units_per_meter: Float,
+    pub floor: Option<Checkerboard>,
+    pub background: Vec3f,
+    // A photograph camera rays show where they escape, stretched over the frame; rays
+    // scattered off surfaces still see the background color
+    pub backplate: Option<Arc<ImageTexture>>,
+    // Seen by rays escaping the scene in place of the background color
+    pub environment: Option<Environment>,
+    // Replaces every material the rays meet, for clay and debug renders
+    pub material_override: Option<MaterialOverride>,
+    bvh: OnceLock<Bvh>,
+    // The last frame's view-culled objects, kept while the camera holds still
+    camera_culling: Mutex<Option<Arc<CameraCulling>>>,
+    next_id: u32,
+}
+
+// The objects a camera's primary rays can reach, under a hierarchy of their own, so
+// camera rays skip straight past everything out of view. Rays that bounce, shadow and
+// portal rays still search the whole scene.
+pub struct CameraCulling {
+    frustum: Frustum,
+    // Which scene this was culled from, by address, as it is only used while the scene
+    // is borrowed for a frame
+    scene: usize,
+    // Indices into the scene's objects, in order
+    kept: Vec<usize>,
+    bvh: Bvh,
+}
+
+impl CameraCulling {
+    // Indices into Scene::objects of the objects kept
+    pub fn kept(&self) -> &[usize] {
+        &self.kept
+    }
+}
+
+thread_local! {
+    // The culling the frame this thread works on traces camera rays with
+    static CAMERA_CULLING: RefCell<Option<Arc<CameraCulling>>> = const { RefCell::new(None) };
+}
+
+// Runs work with Scene::intersect_camera using culling on this thread, then restores
+// whatever was in use before
+pub fn with_camera_culling<R>(culling: Option<&Arc<CameraCulling>>, work: impl FnOnce() -> R) -> R {
+    let previous = CAMERA_CULLING.replace(culling.cloned());
+    let result = work();
+    CAMERA_CULLING.set(previous);
+    result
+}
+
+impl Default for Scene {
+    fn default() -> Scene {
+        Scene::new()
+    }
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene {
+            objects: Vec::new(),
+            groups: Vec::new(),
+            lights: Vec::new(),
+            ambient_lights: Vec::new(),
+            hemisphere_lights: Vec::new(),
+            volumes: Vec::new(),
+            portals: Vec::new(),
+            clip_planes: Vec::new(),
+            units_per_meter: 1.0,
+            floor: None,
+            background: Vec3f(0.2, 0.7, 0.8),
+            backplate: None,
+            environment: None,
+            material_override: None,
+            bvh: OnceLock::new(),
+            camera_culling: Mutex::new(None),
+            next_id: BACKGROUND_ID + 1,
+        }
+    }
+
+    // Adds an object under the next free ID, counting up from 1, and returns that ID
+    pub fn add<S: Shape + 'static>(&mut self, shape: S, material: Material) -> u32 {
+        let id = self.next_id;
+        self.add_with_id(shape, material, id);
+        id
+    }
+
+    pub fn add_with_id<S: Shape + 'static>(&mut self, shape: S, material: Material, id: u32) {
+        self.add_boxed_with_id(Box::new(shape), material, id);
+    }
+
+    // For shapes whose type is only known at run time, e.g. loaded from a scene file
+    pub fn add_boxed(&mut self, shape: Box<dyn Shape>, material: Material) -> u32 {
+        let id = self.next_id;
+        self.add_boxed_with_id(shape, material, id);
+        id
+    }
+
+    pub fn add_boxed_with_id(&mut self, shape: Box<dyn Shape>, material: Material, id: u32) {
+        self.push_object(Object {
+            shape,
+            material,
+            id,
+            name: None,
+            group: None,
+            visibility: Visibility::ALL,
+            texture: None,
+            blend: None,
+            role: LayerRole::Shown,
+        });
+    }
+
+    pub fn add_named<S: Shape + 'static>(
+        &mut self,
+        name: &str,
+        shape: S,
+        material: Material,
+    ) -> u32 {
+        let id = self.add(shape, material);
+        self.set_name(id, name);
+        id
+    }
+
+    fn push_object(&mut self, object: Object) {
+        if object.id != FLOOR_ID {
+            self.next_id = self.next_id.max(object.id + 1);
+        }
+        self.objects.push(object);
+        self.bvh = OnceLock::new();
+        self.camera_culling = Mutex::new(None);
+    }
+
+    // Adds every object in the group, placed by the transforms of the groups around it,
+    // and returns the ID of the group for toggling its visibility later
+    pub fn add_group(&mut self, group: Group) -> GroupId {
+        self.insert_group(group, None, &Transform::identity())
+    }
+
+    fn insert_group(
+        &mut self,
+        group: Group,
+        parent: Option<GroupId>,
+        parent_to_world: &Transform,
+    ) -> GroupId {
+        let id = GroupId(self.groups.len());
+        self.groups.push(GroupNode {
+            parent,
+            name: group.name,
+            visible: group.visible,
+        });
+        let to_world = group.transform.then(parent_to_world);
+        for child in group.children {
+            self.insert_node(child, Some(id), &to_world);
+        }
+        id
+    }
+
+    pub(crate) fn insert_node(&mut self, node: Node, group: Option<GroupId>, to_world: &Transform) {
+        match node {
+            Node::Object {
+                shape,
+                material,
+                id,
+                name,
+                texture,
+                blend,
+                visibility,
+            } => {
+                let shape: Box<dyn Shape> = if to_world.is_identity() {
+                    shape
+                } else {
+                    Box::new(Transformed::new(shape, *to_world))
+                };
+                self.push_object(Object {
+                    shape,
+                    material,
+                    id: id.unwrap_or(self.next_id),
+                    name,
+                    group,
+                    visibility,
+                    texture,
+                    blend,
+                    role: LayerRole::Shown,
+                });
+            }
+            Node::Group(child) => {
+                self.insert_group(child, group, to_world);
+            }
+        }
+    }
+
+    // Hides or shows the group's own subtree; it stays hidden while an ancestor is
+    pub fn set_group_visible(&mut self, group: GroupId, visible: bool) {
+        self.groups[group.0].visible = visible;
+    }
+
+    // Whether the group and every group around it are visible
+    pub fn group_visible(&self, group: GroupId) -> bool {
+        let mut current = Some(group);
+        while let Some(GroupId(index)) = current {
+            if !self.groups[index].visible {
+                return false;
+            }
+            current = self.groups[index].parent;
+        }
+        true
+    }
+
+    // Names every object with this ID; false if there is none
+    pub fn set_name(&mut self, id: u32, name: &str) -> bool {
+        let mut found = false;
+        for object in self.objects.iter_mut().filter(|o| o.id == id) {
+            object.name = Some(name.to_string());
+            found = true;
+        }
+        found
+    }
+
+    // The first object added under this name
+    pub fn get(&self, name: &str) -> Option<&Object> {
+        self.objects
+            .iter()
+            .find(|o| o.name.as_deref() == Some(name))
+    }
+
+    // Gives access to the whole object, shape included, so the BVH is rebuilt before
+    // the next intersection in case it moved
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Object> {
+        self.bvh = OnceLock::new();
+        self.camera_culling = Mutex::new(None);
+        self.objects
+            .iter_mut()
+            .find(|o| o.name.as_deref() == Some(name))
+    }
+
+    pub fn find_group(&self, name: &str) -> Option<GroupId> {
+        self.groups
+            .iter()
+            .position(|g| g.name.as_deref() == Some(name))
+            .map(GroupId)
+    }
+
+    // Changes which rays see every object with this ID; false if there is none
+    pub fn set_visibility(&mut self, id: u32, visibility: Visibility) -> bool {
+        let mut found = false;
+        for object in self.objects.iter_mut().filter(|o| o.id == id) {
+            object.visibility = visibility;
+            found = true;
+        }
+        found
+    }
+
+    // Textures every object with this ID; false if there is none
+    pub fn set_texture(&mut self, id: u32, texture: TextureMap) -> bool {
+        let mut found = false;
+        for object in self.objects.iter_mut().filter(|o| o.id == id) {
+            object.texture = Some(texture.clone());
+            found = true;
+        }
+        found
+    }
+
+    // Blends another material into every object with this ID; false if there is none
+    pub fn set_blend(&mut self, id: u32, blend: BlendMaterial) -> bool {
+        let mut found = false;
+        for object in self.objects.iter_mut().filter(|o| o.id == id) {
+            object.blend = Some(blend.clone());
+            found = true;
+        }
+        found
+    }
+
+    pub fn is_visible(&self, object: &Object) -> bool {
+        object.role != LayerRole::Hidden
+            && object.group.is_none_or(|group| self.group_visible(group))
+    }
+
+    // Draws the objects and groups layer names as they are and gives every other object
+    // the layer's role for the others; None shows everything again. Naming something the
+    // scene does not have is an error, leaving the roles as they were.
+    pub fn set_layer(&mut self, layer: Option<&RenderLayer>) -> io::Result<()> {
+        let Some(layer) = layer else {
+            for object in &mut self.objects {
+                object.role = LayerRole::Shown;
+            }
+            return Ok(());
+        };
+        if let Some(missing) = layer
+            .members
+            .iter()
+            .find(|m| self.get(m).is_none() && self.find_group(m).is_none())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("layer {}: no object or group named {}", layer.name, missing),
+            ));
+        }
+        let roles: Vec<LayerRole> = self
+            .objects
+            .iter()
+            .map(|object| {
+                let named =
+                    |name: Option<&str>| name.is_some_and(|n| layer.members.iter().any(|m| m == n));
+                let mut member = named(object.name.as_deref());
+                let mut current = object.group;
+                while let (false, Some(GroupId(index))) = (member, current) {
+                    member = named(self.groups[index].name.as_deref());
+                    current = self.groups[index].parent;
+                }
+                if member {
+                    LayerRole::Shown
+                } else {
+                    layer.others
+                }
+            })
+            .collect();
+        for (object, role) in self.objects.iter_mut().zip(roles) {
+            object.role = role;
+        }
+        Ok(())
+    }
+
+    pub fn add_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+
+    // The ambient and hemisphere lights together on a surface facing normal
+    // What a ray escaping the scene along dir sees
+    pub fn sky(&self, dir: &Vec3f) -> Vec3f {
+        match &self.environment {
+            Some(environment) => environment.sample(dir),
+            None => self.background,
+        }
+    }
+
+    pub fn ambient(&self, normal: &Vec3f) -> Vec3f {
+        let mut total = Vec3f(0.0, 0.0, 0.0);
+        for light in &self.ambient_lights {
+            total += light.color;
+        }
+        for light in &self.hemisphere_lights {
+            total += light.color(normal);
+        }
+        total
+    }
+
+    pub fn add_volume(&mut self, volume: Volume) {
+        self.volumes.push(volume);
+    }
+
+    // Joins the portal's two objects; false unless both exist. Light does not pass
+    // through portals, so neither end casts shadows either.
+    pub fn add_portal(&mut self, portal: Portal) -> bool {
+        let ends = [portal.entrance, portal.exit];
+        if !ends
+            .iter()
+            .all(|id| self.objects.iter().any(|o| o.id == *id))
+        {
+            return false;
+        }
+        for object in self.objects.iter_mut().filter(|o| ends.contains(&o.id)) {
+            object.visibility.shadow = false;
+        }
+        self.portals.push(portal);
+        true
+    }
+
+    // Where ray carries on once it meets hit, if that is a portal
+    pub fn portal_exit(
+        &self,
+        hit: &Intersection,
+        ray: &RayDifferential,
+    ) -> Option<RayDifferential> {
+        self.portals
+            .iter()
+            .find_map(|portal| portal.pass(hit.object_id, ray, hit.record.t, self.units_per_meter))
+    }
+
+    // Hits farther away than this are ignored unless a camera far plane says otherwise
+    pub fn max_distance(&self) -> Float {
+        NEAREST_DIST_THRESHOLD * self.units_per_meter
+    }
+
+    pub fn objects(&self) -> &[Object] {
+        &self.objects
+    }
+
+    // Every object's own material, to restyle the whole scene at once
+    pub fn materials_mut(&mut self) -> impl Iterator<Item = &mut Material> {
+        self.objects.iter_mut().map(|object| &mut object.material)
+    }
+
+    // The box around every object the camera can show, leaving out those without finite
+    // bounds like infinite planes, and the floor; empty when nothing is left
+    pub fn bounds(&self) -> Aabb {
+        self.objects
+            .iter()
+            .filter(|o| o.visibility.sees(RayKind::Camera) && self.is_visible(o))
+            .map(|o| o.shape.bounds())
+            .filter(|b| {
+                [b.min.0, b.min.1, b.min.2, b.max.0, b.max.1, b.max.2]
+                    .iter()
+                    .all(|x| x.is_finite())
+            })
+            .fold(Aabb::empty(), |all, b| all.union(&b))
+    }
+
+    // Lets shapes that change with how large they appear, like levels of detail, settle
+    // on how to show themselves for a frame seen from view
+    pub fn set_view(&self, view: &View) {
+        for object in &self.objects {
+            object.shape.set_view(view);
+        }
+    }
+
+    // The BVH is built on first use and dropped whenever the object list changes
+    // Builds the BVH now rather than on the first ray, e.g. to time it apart from rendering
+    pub fn build_bvh(&self) {
+        self.bvh();
+    }
+
+    fn bvh(&self) -> &Bvh {
+        self.bvh.get_or_init(|| {
+            let start = log::timer(Level::Debug);
+            let bounds: Vec<Aabb> = self.objects.iter().map(|o| o.shape.bounds()).collect();
+            let bvh = Bvh::build(&bounds);
+            if let Some(start) = start {
+                crate::debug!(
+                    "built the scene BVH over {} objects in {:.3}s",
+                    self.objects.len(),
+                    start.elapsed().as_secs_f64()
+                );
+            }
+            bvh
+        })
+    }
+
+    // The objects whose bounds reach into frustum, or None when that is all of them and
+    // culling would gain nothing. The result is kept for the next frame with the same
+    // frustum, as the objects only change through &mut self.
+    pub fn camera_culling(&self, frustum: &Frustum) -> Option<Arc<CameraCulling>> {
+        let mut cached = self.camera_culling.lock().unwrap();
+        if let Some(culling) = cached.as_ref().filter(|c| c.frustum == *frustum) {
+            return (culling.kept.len() < self.objects.len()).then(|| Arc::clone(culling));
+        }
+        let kept: Vec<usize> = (0..self.objects.len())
+            .filter(|&i| frustum.may_contain(&self.objects[i].shape.bounds()))
+            .collect();
+        let bounds: Vec<Aabb> = kept
+            .iter()
+            .map(|&i| self.objects[i].shape.bounds())
+            .collect();
+        crate::debug!(
+            "camera culling keeps {} of {} objects",
+            kept.len(),
+            self.objects.len()
+        );
+        let culling = Arc::new(CameraCulling {
+            frustum: frustum.clone(),
+            scene: self as *const Scene as usize,
+            bvh: Bvh::build(&bounds),
+            kept,
+        });
+        *cached = Some(Arc::clone(&culling));
+        (culling.kept.len() < self.objects.len()).then_some(culling)
+    }
+
+    // The nearest hit closer than t_max for a primary ray, searching only the objects in
+    // view when the frame's culling is in use on this thread
+    pub fn intersect_camera(
+        &self,
+        orig: &Vec3f,
+        dir: &Vec3f,
+        t_max: Float,
+    ) -> Option<Intersection> {
+        CAMERA_CULLING.with_borrow(|culling| match culling {
+            Some(culling) if culling.scene == self as *const Scene as usize => self.intersect_in(
+                &culling.bvh,
+                Some(&culling.kept),
+                RayKind::Camera,
+                orig,
+                dir,
+                t_max,
+            ),
+            _ => self.intersect_as(RayKind::Camera, orig, dir, t_max),
+        })
+    }
+
+    // The nearest hit for a reflection ray
+    pub fn intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<Intersection> {
+        self.intersect_as(RayKind::Reflection, orig, dir, self.max_distance())
+    }
+
+    // The nearest hit closer than t_max among the objects this kind of ray sees; t_max
+    // replaces the usual distance cutoff
+    pub fn intersect_as(
+        &self,
+        kind: RayKind,
+        orig: &Vec3f,
+        dir: &Vec3f,
+        t_max: Float,
+    ) -> Option<Intersection> {
+        self.intersect_in(self.bvh(), None, kind, orig, dir, t_max)
+    }
+
+    // Searches the objects under bvh, whose items index kept, or the objects themselves
+    // when there is no kept
+    fn intersect_in(
+        &self,
+        bvh: &Bvh,
+        kept: Option<&[usize]>,
+        kind: RayKind,
+        orig: &Vec3f,
+        dir: &Vec3f,
+        t_max: Float,
+    ) -> Option<Intersection> {
+        stats::record(|s| match kind {
+            RayKind::Camera => s.camera_rays += 1,
+            RayKind::Shadow => s.shadow_rays += 1,
+            RayKind::Reflection => s.secondary_rays += 1,
+        });
+        let mut nearest: Option<Intersection> = None;
+
+        if let Some(floor) = &self.floor {
+            if let Some((t, color)) = floor.intersect(orig, dir).filter(|hit| hit.0 < t_max) {
+                nearest = Some(Intersection {
+                    record: HitRecord {
+                        t,
+                        point: *orig + *dir * t,
+                        normal: Vec3f(0.0, 1.0, 0.0),
+                        uv: None,
+                        tangent: None,
+                        color: None,
+                        front_face: dir.1 < 0.0,
+                    },
+                    material: Material {
+                        refractive_index: 1.0,
+                        albedo: [1.0, 0.0, 0.0, 0.0],
+                        diffuse_color: color,
+                        specular_exponent: 0.0,
+                        thin_film: None,
+                        anisotropy: None,
+                        specular_color: None,
+                        clearcoat: None,
+                        sheen: None,
+                        sides: Sides::Front,
+                        dispersion: None,
+                        shadow_catcher: None,
+                        priority: 0,
+                        emission: None,
+                    },
+                    object_id: FLOOR_ID,
+                    holdout: false,
+                });
+            }
+        }
+
+        let t_floor = nearest.as_ref().map_or(t_max, |n| n.record.t);
+        let clipped = !self.clip_planes.is_empty();
+        let window = ClipWindow::new(&self.clip_planes, orig, dir);
+        let cull_step = CULL_STEP * self.units_per_meter;
+        let mut best = None;
+        if !clipped || window.is_some() {
+            bvh.traverse(orig, dir, |i, t_max| {
+                let i = kept.map_or(i, |kept| kept[i]);
+                let object = &self.objects[i];
+                if !object.visibility.sees(kind) || !self.is_visible(object) {
+                    return None;
+                }
+                let cull = kind == RayKind::Camera && object.material.sides == Sides::Cull;
+                let (record, cap) = match window.filter(|_| clipped) {
+                    Some(window) => {
+                        clipped_hit(object.shape.as_ref(), &window, orig, dir, cull, cull_step)?
+                    }
+                    None if cull => (
+                        front_hit(object.shape.as_ref(), orig, dir, cull_step)?,
+                        None,
+                    ),
+                    None => (object.shape.hit(orig, dir)?, None),
+                };
+                if record.t >= t_max.min(t_floor) {
+                    return None;
+                }
+                best = Some((i, record, cap));
+                Some(record.t)
+            });
+        }
+        if let Some((i, mut record, Some(color))) = best {
+            record.face(dir);
+            // Caps are flat color in the object's material
+            nearest = Some(Intersection {
+                record,
+                material: Material {
+                    diffuse_color: color,
+                    ..self.objects[i].material
+                },
+                object_id: self.objects[i].id,
+                holdout: self.objects[i].role == LayerRole::Holdout,
+            });
+        } else if let Some((i, mut record, None)) = best {
+            let object = &self.objects[i];
+            let mut material = match &object.blend {
+                Some(blend) => blend.resolve(&object.material, &record),
+                None => object.material,
+            };
+            if let Some(color) = record.color {
+                material.diffuse_color = material.diffuse_color.multiply(&color);
+            }
+            if let Some(color) = object.texture.as_ref().and_then(|t| t.lookup(&record)) {
+                material.diffuse_color = material.diffuse_color.multiply(&color);
+            }
+            // Shading wants the normal on the ray's side; a surface showing both sides
+            // alike is entered from whichever side the ray comes
+            record.face(dir);
+            if object.material.sides == Sides::Both {
+                record.front_face = true;
+            }
+            nearest = Some(Intersection {
+                record,
+                material,
+                object_id: object.id,
+                holdout: object.role == LayerRole::Holdout,
+            });
+        }
+
+        let mut nearest = nearest.filter(|n| n.record.t < t_max);
+        if let (Some(hit), Some(replace)) = (&mut nearest, self.material_override) {
+            hit.material = replace.apply(&hit.material, &hit.record);
+        }
+        if let Some(hit) = &nearest {
+            stats::record_hit(hit.object_id, kind);
+        }
+        nearest
+    }
+
+    // Checks the scene for mistakes that are cheap to find before rendering: broken
+    // shape parameters, lights buried inside objects and materials that add energy
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut report = |severity, object_id, message: String| {
+            diagnostics.push(Diagnostic {
+                severity,
+                object_id,
+                message,
+            })
+        };
+
+        for object in &self.objects {
+            let id = Some(object.id);
+            let issues = object.shape.diagnostics();
+            let broken = issues.iter().any(|d| d.severity == Severity::Error);
+            for issue in issues {
+                report(issue.severity, id, issue.message);
+            }
+            let bounds = object.shape.bounds();
+            let extent = bounds.max - bounds.min;
+            let extents = [extent.0, extent.1, extent.2];
+            if broken {
+                // The shape's own complaints already explain its bounds
+            } else if extents.iter().any(|e| e.is_nan() || *e < 0.0) {
+                report(
+                    Severity::Error,
+                    id,
+                    format!("bounds are invalid: {:?} to {:?}", bounds.min, bounds.max),
+                );
+            } else if extents.iter().filter(|e| **e == 0.0).count() >= 2 {
+                // A flat shape may have one zero extent, but not a line or a point
+                report(
+                    Severity::Error,
+                    id,
+                    "bounds have no area, so the shape can never be hit".to_string(),
+                );
+            }
+            for issue in check_material(&object.material) {
+                report(Severity::Warning, id, issue);
+            }
+            if let Some(blend) = &object.blend {
+                for issue in check_material(&blend.other) {
+                    report(Severity::Warning, id, format!("blended {}", issue));
+                }
+                if !(0.0..=1.0).contains(&blend.factor) {
+                    report(
+                        Severity::Warning,
+                        id,
+                        format!("blend factor must be between 0 and 1, got {}", blend.factor),
+                    );
+                }
+            }
+        }
+
+        // Parts sharing an ID share its name too; only a second ID makes it ambiguous
+        let mut names: Vec<(&str, u32)> = Vec::new();
+        for object in &self.objects {
+            let Some(name) = object.name.as_deref() else {
+                continue;
+            };
+            match names.iter().find(|(n, _)| *n == name) {
+                Some(&(_, first)) if first != object.id => report(
+                    Severity::Warning,
+                    Some(object.id),
+                    format!(
+                        "name {:?} is also used by object {}; lookups find that one",
+                        name, first
+                    ),
+                ),
+                Some(_) => {}
+                None => names.push((name, object.id)),
+            }
+        }
+
+        for (i, light) in self.lights.iter().enumerate() {
+            let p = light.position;
+            if !(p.0.is_finite() && p.1.is_finite() && p.2.is_finite()) {
+                report(
+                    Severity::Error,
+                    None,
+                    format!("light {} position is not finite: {:?}", i, p),
+                );
+                continue;
+            }
+            if !light.intensity.is_finite() || light.intensity < 0.0 {
+                report(
+                    Severity::Error,
+                    None,
+                    format!("light {} intensity is invalid: {}", i, light.intensity),
+                );
+            }
+            let c = light.color;
+            if [c.0, c.1, c.2].iter().any(|v| !v.is_finite() || *v < 0.0) {
+                report(
+                    Severity::Error,
+                    None,
+                    format!("light {} color is invalid: {:?}", i, c),
+                );
+            }
+            if let Some(object) = self.objects.iter().find(|o| encloses(&*o.shape, &p)) {
+                report(
+                    Severity::Warning,
+                    Some(object.id),
+                    format!("light {} at {:?} is inside this object", i, p),
+                );
+            }
+            for &id in light.links.ids() {
+                if id != FLOOR_ID && !self.objects.iter().any(|o| o.id == id) {
+                    report(
+                        Severity::Warning,
+                        None,
+                        format!(
+                            "light {} is linked to object {}, which does not exist",
+                            i, id
+                        ),
+                    );
+                }
+            }
+        }
+
+        diagnostics
+    }
+
+    // True when any surface blocks the segment from orig along dir up to max_dist
+    pub fn occluded(&self, orig: &Vec3f, dir: &Vec3f, max_dist: Float) -> bool {
+        // Searching no further than max_dist keeps what lies beyond it out of the stats
+        self.intersect_as(
+            RayKind::Shadow,
+            orig,
+            dir,
+            max_dist.min(self.max_distance()),
+        )
+        .is_some()
+    }
+}
+
+// Albedo terms that carry light on to other surfaces; over one they amplify it
+fn check_material(material: &Material) -> Vec<String> {
+    let mut issues = Vec::new();
+    let [a0, a1, a2, a3] = material.albedo;
+    let diffuse = material.diffuse_color;
+    let values = [
+        a0,
+        a1,
+        a2,
+        a3,
+        material.refractive_index,
+        material.specular_exponent,
+        diffuse.0,
+        diffuse.1,
+        diffuse.2,
+    ];
+    if values.iter().any(|v| !v.is_finite()) {
+        issues.push("material has non-finite parameters".to_string());
+        return issues;
+    }
+    if values.iter().any(|v| *v < 0.0) {
+        issues.push("material has negative parameters".to_string());
+    }
+    if material.refractive_index <= 0.0 && material.albedo[3] > 0.0 {
+        issues.push(format!(
+            "refractive index must be positive, got {}",
+            material.refractive_index
+        ));
+    }
+    if let Some(film) = &material.thin_film {
+        if !(film.thickness.is_finite() && film.thickness >= 0.0) {
+            issues.push(format!(
+                "thin film thickness must be finite and not negative, got {}",
+                film.thickness
+            ));
+        }
+        if !(film.refractive_index.is_finite() && film.refractive_index > 0.0) {
+            issues.push(format!(
+                "thin film refractive index must be positive, got {}",
+                film.refractive_index
+            ));
+        }
+    }
+    if let Some(anisotropy) = &material.anisotropy {
+        let (along, across) = anisotropy.roughness;
+        if !(along > 0.0 && along <= 1.0 && across > 0.0 && across <= 1.0) {
+            issues.push(format!(
+                "anisotropic roughness must be in (0, 1], got {} and {}",
+                along, across
+            ));
+        }
+    }
+    let scattered = diffuse.0.max(diffuse.1).max(diffuse.2) * material.albedo[0]
+        + material.albedo[2]
+        + material.albedo[3];
+    if scattered > 1.0 {
+        issues.push(format!(
+            "diffuse, reflected and refracted albedo sum to {}, so light gains energy",
+            scattered
+        ));
+    }
+    issues
+}
+
+// The nearest hit on what the clip planes leave of shape, with the cap color if the ray
+// meets the cut a plane makes through it
+fn clipped_hit(
+    shape: &dyn Shape,
+    window: &ClipWindow,
+    orig: &Vec3f,
+    dir: &Vec3f,
+    cull: bool,
+    cull_step: Float,
+) -> Option<(HitRecord, Option<Vec3f>)> {
+    let start = *orig + *dir * window.near;
+    let mut record = shape.hit(&start, dir)?;
+    // Leaving through a back face means the ray crossed the plane inside the solid
+    let inside = !record.front_face;
+    if let Some(color) = window.entry.and_then(|plane| plane.cap).filter(|_| inside) {
+        let normal = window.entry?.normal;
+        let cap = HitRecord {
+            t: window.near,
+            point: start,
+            normal,
+            uv: None,
+            tangent: None,
+            color: None,
+            front_face: normal.dot(dir) < 0.0,
+        };
+        return Some((cap, Some(color)));
+    }
+    if cull && inside {
+        record = front_hit(shape, &start, dir, cull_step)?;
+    }
+    record.t += window.near;
+    (record.t < window.far).then_some((record, None))
+}
+
+// The nearest hit on a face of shape turned towards the ray, stepping past back faces
+// and step beyond each
+fn front_hit(shape: &dyn Shape, orig: &Vec3f, dir: &Vec3f, step: Float) -> Option<HitRecord> {
+    let mut start = *orig;
+    let mut travelled = 0.0;
+    for _ in 0..MAX_CULLED_FACES {
+        let mut record = shape.hit(&start, dir)?;
+        if record.front_face {
+            record.t += travelled;
+            return Some(record);
+        }
+        let skip = record.t + step;
+        start += *dir * skip;
+        travelled += skip;
+    }
+    None
+}
+
+// Whether rays from point in every axis direction leave shape through its back faces
+fn encloses(shape: &dyn Shape, point: &Vec3f) -> bool {
+    let bounds = shape.bounds();
+    let inside_bounds =
+        (0..3).all(|axis| bounds.min[axis] <= point[axis] && point[axis] <= bounds.max[axis]);
+    inside_bounds
+        && [
+            Vec3f(1.0, 0.0, 0.0),
+            Vec3f(-1.0, 0.0, 0.0),
+            Vec3f(0.0, 1.0, 0.0),
+            Vec3f(0.0, -1.0, 0.0),
+            Vec3f(0.0, 0.0, 1.0),
+            Vec3f(0.0, 0.0, -1.0),
+        ]
+        .iter()
+        .all(|dir| shape.hit(point, dir).is_some_and(|hit| !hit.front_face))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::LightLinks;
+    use crate::material::RED_RUBBER;
+    use crate::shapes::Sphere;
+
+    fn found<'a>(diagnostics: &'a [Diagnostic], text: &str) -> &'a Diagnostic {
+        diagnostics
+            .iter()
+            .find(|d| d.message.contains(text))
+            .unwrap_or_else(|| panic!("no {:?} in {:?}", text, diagnostics))
+    }
+
+    #[test]
+    fn reports_broken_shapes_materials_names_and_lights() {
+        let mut scene = Scene::new();
+        let ball = scene.add_named("ball", Sphere::new(Vec3f(0.0, 0.0, -5.0), 1.0), RED_RUBBER);
+        scene.add_light(Light::new(Vec3f(0.0, 5.0, 0.0), 1.0));
+        assert!(scene.validate().is_empty(), "{:?}", scene.validate());
+
+        let broken = scene.add(Sphere::new(Vec3f(3.0, 0.0, -5.0), -1.0), RED_RUBBER);
+        let bright = Material {
+            albedo: [0.0, 0.0, 0.75, 0.75],
+            ..RED_RUBBER
+        };
+        let twin = scene.add_named("ball", Sphere::new(Vec3f(-3.0, 0.0, -5.0), 1.0), bright);
+        scene.add_light(Light::new(Vec3f(0.0, 0.0, -5.0), 1.0));
+        scene.add_light(Light::new(Vec3f(0.0, 5.0, 0.0), -2.0));
+        scene.add_light(Light::new(Vec3f(Float::NAN, 5.0, 0.0), 1.0));
+        scene.add_light(
+            Light::new(Vec3f(0.0, 5.0, 0.0), 1.0).with_links(LightLinks::Only(vec![99])),
+        );
+
+        let diagnostics = scene.validate();
+        let radius = found(&diagnostics, "radius must be finite and positive, got -1");
+        assert_eq!(
+            (radius.severity, radius.object_id),
+            (Severity::Error, Some(broken))
+        );
+        // The radius explains the bounds, so they are not reported as well
+        assert!(!diagnostics.iter().any(|d| d.message.contains("bounds")));
+        let albedo = found(&diagnostics, "albedo sum to 1.5");
+        assert_eq!(
+            (albedo.severity, albedo.object_id),
+            (Severity::Warning, Some(twin))
+        );
+        let name = found(&diagnostics, "name \"ball\" is also used by object");
+        assert_eq!(
+            (name.severity, name.object_id),
+            (Severity::Warning, Some(twin))
+        );
+        assert!(name.message.contains(&ball.to_string()));
+        let inside = found(&diagnostics, "light 1 at");
+        assert_eq!(
+            (inside.severity, inside.object_id),
+            (Severity::Warning, Some(ball))
+        );
+        assert_eq!(
+            found(&diagnostics, "light 2 intensity").severity,
+            Severity::Error
+        );
+        assert_eq!(
+            found(&diagnostics, "light 3 position").severity,
+            Severity::Error
+        );
+        let link = found(&diagnostics, "light 4 is linked to object 99");
+        assert_eq!((link.severity, link.object_id), (Severity::Warning, None));
+        assert_eq!(diagnostics.len(), 7, "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn sides_decide_which_faces_rays_see_and_enter() {
+        let inside = Vec3f(0.0, 0.0, 0.0);
+        let ahead = Vec3f(0.0, 0.0, 1.0);
+        let with_sides = |sides| {
+            let mut scene = Scene::new();
+            let material = Material {
+                sides,
+                ..RED_RUBBER
+            };
+            scene.add(Sphere::new(inside, 1.0), material);
+            scene
+        };
+
+        // Leaving the solid through its back face, with the normal turned to the ray
+        let front = with_sides(Sides::Front);
+        let hit = front.intersect(&inside, &ahead).unwrap();
+        assert!((hit.record.t - 1.0).abs() < 1e-5);
+        assert!(!hit.record.front_face);
+        assert!(hit.record.normal.dot(&ahead) < 0.0);
+        // Both sides count as the front, facing the ray all the same
+        let both = with_sides(Sides::Both);
+        let hit = both.intersect(&inside, &ahead).unwrap();
+        assert!(hit.record.front_face);
+        assert!(hit.record.normal.dot(&ahead) < 0.0);
+
+        // Camera rays pass through back faces alone; other rays still meet them
+        let culled = with_sides(Sides::Cull);
+        let far = culled.max_distance();
+        assert!(culled
+            .intersect_as(RayKind::Camera, &inside, &ahead, far)
+            .is_none());
+        assert!(culled
+            .intersect_as(RayKind::Shadow, &inside, &ahead, far)
+            .is_some());
+        assert!(culled.intersect(&inside, &ahead).is_some());
+        let outside = Vec3f(0.0, 0.0, -5.0);
+        let hit = culled
+            .intersect_as(RayKind::Camera, &outside, &ahead, far)
+            .unwrap();
+        assert!((hit.record.t - 4.0).abs() < 1e-4 && hit.record.front_face);
+    }
+}
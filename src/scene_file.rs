@@ -0,0 +1,2468 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::bloom::Bloom;
+use crate::camera::{Aperture, ApertureMask, ApertureShape, Camera, Lens, SUNNY_16};
+use crate::clip::ClipPlane;
+use crate::denoise::Denoise;
+use crate::environment::{CubeMap, Environment};
+use crate::exposure::AutoExposure;
+use crate::font::Font;
+use crate::group::{Group, Node};
+use crate::ior;
+use crate::irradiance_cache::IrradianceCaching;
+use crate::json::Json;
+use crate::layers::{LayerRole, RenderLayer};
+use crate::light::{
+    blackbody, AmbientLight, HemisphereLight, Light, LightLinks, TEMPERATURE_RANGE,
+};
+use crate::lod::Lod;
+use crate::log::{self, Level};
+use crate::material::{
+    Anisotropy, BlendMaterial, Emission, Material, Principled, ShadowCatcher, Sides, ThinFilm,
+    CORTEN_STEEL, DARK_WOOD, GLASS, GOLD, IVORY, MARBLE, METAL, MIRROR, RED_RUBBER, VELVET,
+};
+use crate::mesh::{Triangle, TriangleMesh};
+use crate::portal::Portal;
+use crate::render::{parse_resolution, Integrator, RenderSettings};
+use crate::sampler::Sampler;
+use crate::scene::{Checkerboard, Scene, Visibility, FLOOR_ID};
+use crate::shapes::{
+    BoxUv, Cone, Cube, Cylinder, Ovoid, Pyramid, Quad, RecgtangularPrism, Shape, Sphere, Torus,
+};
+use crate::text::Text3D;
+use crate::texture::{ImageTexture, Mapping, TextureMap, Wrap};
+use crate::transform::{Transform, Transformed};
+use crate::variables::Variables;
+use crate::vec3::{consts::PI, Float, Vec3f};
+use crate::vox::VoxFile;
+use crate::voxel::VoxelOctree;
+
+pub const MATERIAL_NAMES: [(&str, Material); 10] = [
+    ("ivory", IVORY),
+    ("glass", GLASS),
+    ("red_rubber", RED_RUBBER),
+    ("mirror", MIRROR),
+    ("metal", METAL),
+    ("dark_wood", DARK_WOOD),
+    ("marble", MARBLE),
+    ("gold", GOLD),
+    ("velvet", VELVET),
+    ("corten_steel", CORTEN_STEEL),
+];
+
+// Scene units by name, as how many of them make a meter
+const UNITS: [(&str, Float); 6] = [
+    ("m", 1.0),
+    ("cm", 100.0),
+    ("mm", 1000.0),
+    ("km", 0.001),
+    ("in", 1.0 / 0.0254),
+    ("ft", 1.0 / 0.3048),
+];
+
+// Inline materials start from this and override what they name
+const DEFAULT_MATERIAL: Material = Material {
+    refractive_index: 1.0,
+    albedo: [1.0, 0.0, 0.0, 0.0],
+    diffuse_color: Vec3f(0.8, 0.8, 0.8),
+    specular_exponent: 1.0,
+    thin_film: None,
+    anisotropy: None,
+    specular_color: None,
+    clearcoat: None,
+    sheen: None,
+    sides: Sides::Front,
+    dispersion: None,
+    shadow_catcher: None,
+    priority: 0,
+    emission: None,
+};
+
+// A scene description loaded from JSON:
+//
+//   {
+//     "variables": {"radius": 2, "texture": "earth", "lift": "${radius / 2 + 1}"},
+//     "render": {"resolution": "720p", "samples": 4, "max_depth": 4, "integrator": "path",
+//                "regularize": 0.3, "irradiance_cache": {"accuracy": 0.25}, "seed": 7,
+//                "sampler": "blue_noise", "denoise": {"radius": 4},
+//                "bloom": {"threshold": 1, "radius": 8}, "auto_exposure": {"key": 0.18}},
+//     "camera": {"position": [0, 0, 0], "target": [0, 0, -1], "fov": 60, "near": 0.1,
+//                "exposure": 0.5, "iso": 100, "shutter": 0.01, "f_stop": 16,
+//                "lens": {"vignetting": 0.5, "distortion": -0.1, "chromatic_aberration": 0.005},
+//                "aperture": {"radius": 0.2, "focus_distance": 10, "blades": 6}},
+//     "cameras": {"left": {"position": [-0.03, 0, 0], "target": [-0.03, 0, -1]},
+//                 "right": {"position": [0.03, 0, 0], "target": [0.03, 0, -1]}},
+//     "background": [0.2, 0.7, 0.8],
+//     "backplate": "street.jpg",
+//     "environment": {"cube_map": ["px.png", "nx.png", "py.png", "ny.png", "pz.png", "nz.png"]},
+//     "materials": {"red": {"base": "ivory", "diffuse": [0.8, 0.1, 0.1]},
+//                   "bubble": {"base": "glass", "thin_film": {"thickness": 380}},
+//                   "gem": {"base": "glass", "refractive_index": "diamond"},
+//                   "brushed": {"base": "metal", "anisotropy": {"roughness": [0.05, 0.4]}},
+//                   "paint": {"principled": {"base_color": [0.6, 0, 0], "clearcoat": 1}},
+//                   "leaf": {"diffuse": [0.2, 0.5, 0.1], "sides": "both"},
+//                   "vessel": {"base": "glass", "priority": 1},
+//                   "lamp": {"diffuse": [0, 0, 0], "emission": {"color": [8, 8, 8]}}},
+//     "objects": [{"type": "sphere", "center": [0, "${lift}", -10], "radius": "${radius}",
+//                  "material": "red", "texture": {"image": "${texture}.png", "wrap": "latlong"}},
+//                 {"type": "group", "transform": {"translate": [0, 2, 0]}, "children": []},
+//                 {"type": "include", "file": "props/chair.json", "name": "chair",
+//                  "transform": {"translate": [2, -4, -14]}},
+//                 {"type": "box", "min": [-9, -5, -30], "max": [9, -4, -5],
+//                  "visibility": {"camera": false}},
+//                 {"type": "cube", "center": [4, 0, -12], "size": 2, "uv": "cross",
+//                  "texture": "dice.png"},
+//                 {"type": "sphere", "center": [-4, 0, -12], "radius": 1, "material": "metal",
+//                  "blend": {"material": "red", "mask": "rust.png"}},
+//                 {"type": "quad", "corner": [-1, 5, -11], "u": [0, 0, -2], "v": [2, 0, 0],
+//                  "material": "lamp"},
+//                 {"type": "voxels", "positions": [[0, 0, 0], [1, 0, 0]], "size": 0.5},
+//                 {"type": "vox", "file": "castle.vox", "size": 0.25, "as": "cubes"},
+//                 {"type": "mesh", "name": "wall", "vertices": [[0, 0, -20], [4, 0, -20],
+//                  [0, 4, -20]], "uvs": [[0, 0], [1, 0], [0, 1]], "faces": [[0, 1, 2]]},
+//                 {"type": "lod", "levels": [{"object": {"type": "mesh", ...}},
+//                                            {"below": 40, "object": {"type": "sphere", ...}}]}],
+//     "lights": [{"position": [-20, 20, 20], "intensity": 1.5, "exclude": ["floor"]},
+//                {"position": [0, 30, 0], "radius": 2, "temperature": 3200},
+//                {"type": "hemisphere", "sky": [0.2, 0.25, 0.3], "ground": [0.1, 0.08, 0.05]},
+//                {"type": "ambient", "color": [0.05, 0.05, 0.05]}],
+//     "floor": {"height": -4},
+//     "portals": [{"entrance": "door", "exit": 3, "transform": {"translate": [0, 0, -8]}}],
+//     "clip": [{"point": [0, 0, -12], "normal": [1, 0, 0], "cap": [0.8, 0.2, 0.2]}],
+//     "layers": {"hero": {"objects": ["wall", "chair"]},
+//                "set": {"objects": ["wall"], "others": "hidden"}},
+//     "up_axis": "y",
+//     "units": "mm"
+//   }
+//
+// Angles are in degrees, and texture, font and .vox paths are relative to the file. An
+// include places the objects of a component file, one holding just materials and
+// objects, as a group; its own paths are relative to it, and its objects can use the
+// including file's materials as well as the ones it defines. Lights are points unless
+// given a radius, which makes them spheres with soft shadows in the path tracer; ambient
+// and hemisphere lights are unshadowed fill light that diffuse surfaces pick up wherever
+// they are, the hemisphere one by which way they face. The environment, the path of an
+// equirectangular image or six cube map faces, is what rays see where they escape the
+// scene instead of the background color. Named cameras are read like the camera, which
+// is the first of them if the file leaves it out. Layers name objects and groups to
+// render apart from the rest, which they hold out by default or else hide. Every section
+// is optional, and a key the loader does not know is an error so typos surface instead of
+// being silently ignored.
+//
+// Variables are substituted into every string before the rest is read, each defined in
+// terms of those before it: "${name}" alone stands for the variable's value, whatever its
+// type, "${expression}" for the number it comes to, and ${...} within longer text for its
+// value as text. Loading with values for some of them, as --set radius=2.5 does, lets
+// one file drive a sweep of renders; included components use the including file's.
+//
+// A file exported with "up_axis": "z" has its objects, camera, lights, portals and clip
+// planes turned upright, and cones, cylinders, pyramids, tori and .vox models stand along
+// its z axis; the floor stays horizontal either way. Units, a name like "mm" or a number
+// of units per meter, scale the tolerances the renderer works to, so a model in
+// millimeters keeps its shadows and is not cut off a meter from the camera.
+pub struct SceneFile {
+    pub scene: Scene,
+    pub camera: Camera,
+    // Other views of the scene by name, in the order the file gives them
+    pub cameras: Vec<(String, Camera)>,
+    // Parts of the scene to render on their own, in the order the file gives them
+    pub layers: Vec<RenderLayer>,
+    // Render settings the file asks for; anything unset keeps the caller's value
+    pub render: RenderOverrides,
+    // Every other file the scene was built from: images, fonts, .vox models and included
+    // components, missing ones included
+    pub assets: Vec<PathBuf>,
+}
+
+// Which files a scene's asset paths may name: any, as for a file the user loads, none, or
+// only those under a directory, as for a scene sent by someone else over the network
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum AssetAccess {
+    #[default]
+    Any,
+    Denied,
+    Within(PathBuf),
+}
+
+// Render settings a scene file or batch job asks for; anything unset keeps the caller's value
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderOverrides {
+    pub resolution: Option<(usize, usize)>,
+    pub samples_per_pixel: Option<u32>,
+    pub max_depth: Option<u32>,
+    pub integrator: Option<Integrator>,
+    pub regularize: Option<Float>,
+    pub irradiance_cache: Option<IrradianceCaching>,
+    pub sampler: Option<Sampler>,
+    pub seed: Option<u64>,
+    pub denoise: Option<Denoise>,
+    pub bloom: Option<Bloom>,
+    pub auto_exposure: Option<AutoExposure>,
+}
+
+impl RenderOverrides {
+    pub fn apply(&self, settings: &mut RenderSettings) {
+        if let Some((width, height)) = self.resolution {
+            settings.width = width;
+            settings.height = height;
+        }
+        if let Some(samples) = self.samples_per_pixel {
+            settings.samples_per_pixel = samples;
+        }
+        if let Some(max_depth) = self.max_depth {
+            settings.max_depth = max_depth;
+        }
+        if let Some(integrator) = self.integrator {
+            settings.integrator = integrator;
+        }
+        if let Some(regularize) = self.regularize {
+            settings.regularize = regularize;
+        }
+        if let Some(caching) = self.irradiance_cache {
+            settings.irradiance_cache = Some(caching);
+        }
+        if let Some(sampler) = self.sampler {
+            settings.sampler = sampler;
+        }
+        if let Some(seed) = self.seed {
+            settings.seed = seed;
+        }
+        if let Some(denoise) = self.denoise {
+            settings.denoise = Some(denoise);
+        }
+        if let Some(bloom) = self.bloom {
+            settings.bloom = Some(bloom);
+        }
+        if let Some(auto) = self.auto_exposure {
+            settings.auto_exposure = Some(auto);
+        }
+    }
+}
+
+impl SceneFile {
+    pub fn load(path: &Path) -> io::Result<SceneFile> {
+        SceneFile::load_with(path, &[])
+    }
+
+    // Loads the file with the values of some of its variables given
+    pub fn load_with(path: &Path, variables: &[(String, Json)]) -> io::Result<SceneFile> {
+        let start = log::timer(Level::Debug);
+        let base = path.parent().unwrap_or(Path::new(""));
+        let file = fs::read_to_string(path)
+            .and_then(|text| {
+                SceneFile::parse_in(&text, base, variables, AssetAccess::Any, Some(path))
+            })
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        if let Some(start) = start {
+            crate::debug!(
+                "loaded {}: {} objects, {} lights in {:.3}s",
+                path.display(),
+                file.scene.objects().len(),
+                file.scene.lights.len(),
+                start.elapsed().as_secs_f64()
+            );
+        }
+        Ok(file)
+    }
+
+    pub fn parse(text: &str) -> io::Result<SceneFile> {
+        SceneFile::parse_relative_to(text, Path::new(""))
+    }
+
+    // Texture paths in the file are relative to base
+    pub fn parse_relative_to(text: &str, base: &Path) -> io::Result<SceneFile> {
+        SceneFile::parse_with(text, base, &[])
+    }
+
+    pub fn parse_with(
+        text: &str,
+        base: &Path,
+        variables: &[(String, Json)],
+    ) -> io::Result<SceneFile> {
+        SceneFile::parse_in(text, base, variables, AssetAccess::Any, None)
+    }
+
+    // A scene from somewhere less trusted, whose asset paths may only name files under
+    // assets, relative to it, or no files at all without one
+    pub fn parse_confined(text: &str, assets: Option<&Path>) -> io::Result<SceneFile> {
+        match assets {
+            None => SceneFile::parse_in(text, Path::new(""), &[], AssetAccess::Denied, None),
+            Some(dir) => {
+                let root = fs::canonicalize(dir)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", dir.display(), e)))?;
+                let access = AssetAccess::Within(root.clone());
+                SceneFile::parse_in(text, &root, &[], access, None)
+            }
+        }
+    }
+
+    // source is the file text was read from, if it came from one, so including it is caught
+    fn parse_in(
+        text: &str,
+        base: &Path,
+        variables: &[(String, Json)],
+        access: AssetAccess,
+        source: Option<&Path>,
+    ) -> io::Result<SceneFile> {
+        let mut root = Json::parse(text)?;
+        let variables = Variables::take(&mut root, variables)?;
+        variables.substitute(&mut root, "scene")?;
+        let root = Fields::new(&root, "scene")?;
+        root.only(&[
+            "render",
+            "camera",
+            "cameras",
+            "background",
+            "backplate",
+            "environment",
+            "materials",
+            "objects",
+            "lights",
+            "floor",
+            "portals",
+            "clip",
+            "layers",
+            "up_axis",
+            "units",
+        ])?;
+        // Everything positioned in the file is turned to y-up as it is read
+        let axes = match root.string("up_axis")? {
+            None | Some("y") => Transform::identity(),
+            Some("z") => Transform::z_up(),
+            Some(other) => {
+                return Err(root.error(&format!("up_axis must be \"y\" or \"z\", got {}", other)))
+            }
+        };
+        let point = |p: Vec3f| axes.point(&p);
+        let vector = |v: Vec3f| axes.vector(&v);
+
+        let mut file = SceneFile {
+            scene: Scene::new(),
+            camera: Camera::new(Vec3f(0.0, 0.0, 0.0), PI / 3.0),
+            cameras: Vec::new(),
+            layers: Vec::new(),
+            render: RenderOverrides::default(),
+            assets: Vec::new(),
+        };
+
+        if let Some(render) = root.object("render")? {
+            render.only(RENDER_KEYS)?;
+            file.render = parse_render(&render)?;
+        }
+
+        let mut textures = Textures {
+            base: base.to_path_buf(),
+            loaded: Vec::new(),
+            including: source
+                .and_then(|path| fs::canonicalize(path).ok())
+                .into_iter()
+                .collect(),
+            variables,
+            assets: Vec::new(),
+            access,
+        };
+
+        if let Some(camera) = root.object("camera")? {
+            file.camera = parse_camera(&camera, &axes, &mut textures)?;
+        }
+        if let Some(cameras) = root.object("cameras")? {
+            for (name, _) in cameras.members() {
+                let camera = cameras.required(Fields::object, name)?;
+                file.cameras
+                    .push((name.clone(), parse_camera(&camera, &axes, &mut textures)?));
+            }
+            // Without a camera of its own the file looks through the first named one
+            if root.get("camera").is_none() {
+                if let Some((_, first)) = file.cameras.first() {
+                    file.camera = first.clone();
+                }
+            }
+        }
+
+        file.scene.units_per_meter = match root.get("units") {
+            None => 1.0,
+            Some(Json::Number(n)) if *n > 0.0 && n.is_finite() => *n as Float,
+            Some(Json::String(name)) => UNITS
+                .iter()
+                .find(|(unit, _)| unit == name)
+                .map(|(_, per_meter)| *per_meter)
+                .ok_or_else(|| root.error(&format!("unknown unit {}", name)))?,
+            Some(_) => return Err(root.error("units must be a unit name or units per meter")),
+        };
+
+        if let Some(background) = root.vec3("background")? {
+            file.scene.background = background;
+        }
+        if let Some(backplate) = root.string("backplate")? {
+            let image = textures.asset(backplate, &root.child("backplate"))?;
+            let mut plate = ImageTexture::load(&image).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("{}: {}: {}", root.child("backplate"), image.display(), e),
+                )
+            })?;
+            plate.wrap = Wrap::Clamp;
+            file.scene.backplate = Some(Arc::new(plate));
+        }
+        if root.get("environment").is_some() {
+            file.scene.environment = Some(parse_environment(&root, &mut textures)?);
+        }
+
+        let mut materials: Vec<(String, Material)> = MATERIAL_NAMES
+            .iter()
+            .map(|(name, material)| (name.to_string(), *material))
+            .collect();
+        parse_materials(&root, &mut materials)?;
+
+        for (i, object) in root
+            .array("objects")?
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+        {
+            let object = Fields::new(object, &root.child(&format!("objects[{}]", i)))?;
+            let node = parse_object(&object, &materials, &mut textures, &axes)?;
+            file.scene.insert_node(node, None, &axes);
+        }
+
+        for (i, light) in root.array("lights")?.unwrap_or_default().iter().enumerate() {
+            let light = Fields::new(light, &root.child(&format!("lights[{}]", i)))?;
+            match light.string("type")?.unwrap_or("point") {
+                "point" => {}
+                "ambient" => {
+                    light.only(&["type", "color"])?;
+                    file.scene.ambient_lights.push(AmbientLight {
+                        color: light.required(Fields::vec3, "color")?,
+                    });
+                    continue;
+                }
+                "hemisphere" => {
+                    light.only(&["type", "sky", "ground", "up"])?;
+                    let up = light.vec3("up")?.map_or(Vec3f(0.0, 1.0, 0.0), &vector);
+                    let hemisphere = HemisphereLight::new(
+                        light.required(Fields::vec3, "sky")?,
+                        light.required(Fields::vec3, "ground")?,
+                        up,
+                    )
+                    .ok_or_else(|| light.error("up has no direction"))?;
+                    file.scene.hemisphere_lights.push(hemisphere);
+                    continue;
+                }
+                other => {
+                    return Err(light.error(&format!(
+                        "unknown light type {}; expected point, ambient or hemisphere",
+                        other
+                    )))
+                }
+            }
+            light.only(&[
+                "type",
+                "position",
+                "intensity",
+                "color",
+                "temperature",
+                "radius",
+                "include",
+                "exclude",
+            ])?;
+            // An RGB color, or a color temperature in kelvin
+            let color = match (light.vec3("color")?, light.number("temperature")?) {
+                (Some(_), Some(_)) => {
+                    return Err(light.error("give color or temperature, not both"))
+                }
+                (Some(color), None) => color,
+                (None, Some(kelvin)) => {
+                    let (low, high) = TEMPERATURE_RANGE;
+                    if !(low..=high).contains(&kelvin) {
+                        return Err(light.error(&format!(
+                            "temperature must be between {} and {} kelvin",
+                            low, high
+                        )));
+                    }
+                    blackbody(kelvin)
+                }
+                (None, None) => Vec3f(1.0, 1.0, 1.0),
+            };
+            let links = match (link_ids(&light, "include")?, link_ids(&light, "exclude")?) {
+                (Some(_), Some(_)) => return Err(light.error("give include or exclude, not both")),
+                (Some(ids), None) => LightLinks::Only(ids),
+                (None, Some(ids)) => LightLinks::Except(ids),
+                (None, None) => LightLinks::All,
+            };
+            let radius = light.number("radius")?.unwrap_or(0.0);
+            if radius < 0.0 {
+                return Err(light.error("radius must not be negative"));
+            }
+            file.scene.add_light(
+                Light::new(
+                    point(light.required(Fields::vec3, "position")?),
+                    light.number("intensity")?.unwrap_or(1.0),
+                )
+                .with_links(links)
+                .with_color(color)
+                .with_radius(radius),
+            );
+        }
+
+        for (i, portal) in root
+            .array("portals")?
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+        {
+            let portal = Fields::new(portal, &root.child(&format!("portals[{}]", i)))?;
+            portal.only(&["entrance", "exit", "transform"])?;
+            let end = |key| -> io::Result<u32> {
+                let path = portal.child(key);
+                match portal.get(key) {
+                    Some(Json::String(name)) => file
+                        .scene
+                        .get(name)
+                        .map(|o| o.id)
+                        .ok_or_else(|| invalid(&path, &format!("no object named {}", name))),
+                    Some(Json::Number(n)) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as u32),
+                    _ => Err(invalid(&path, "expected an object name or ID")),
+                }
+            };
+            let (entrance, exit) = (end("entrance")?, end("exit")?);
+            if entrance == exit {
+                return Err(portal.error("a portal needs two different ends"));
+            }
+            // The file's transform works in its own axes
+            let transform = match portal.object("transform")? {
+                Some(transform) => axes
+                    .inverse()
+                    .expect("axis changes are rotations")
+                    .then(&parse_transform(&transform)?)
+                    .then(&axes),
+                None => Transform::identity(),
+            };
+            let portal_pair = Portal::new(entrance, exit, transform)
+                .ok_or_else(|| portal.error("the transform cannot be inverted"))?;
+            if !file.scene.add_portal(portal_pair) {
+                return Err(portal.error("both ends must be objects in the scene"));
+            }
+        }
+
+        for (i, plane) in root.array("clip")?.unwrap_or_default().iter().enumerate() {
+            let plane = Fields::new(plane, &root.child(&format!("clip[{}]", i)))?;
+            plane.only(&["point", "normal", "cap"])?;
+            let clip = ClipPlane::new(
+                point(plane.required(Fields::vec3, "point")?),
+                vector(plane.required(Fields::vec3, "normal")?),
+                plane.vec3("cap")?,
+            )
+            .ok_or_else(|| plane.error("normal must not be zero"))?;
+            file.scene.clip_planes.push(clip);
+        }
+
+        if let Some(layers) = root.object("layers")? {
+            for (name, _) in layers.members() {
+                let layer = layers.required(Fields::object, name)?;
+                layer.only(&["objects", "others"])?;
+                let mut members = Vec::new();
+                for (i, member) in layer.required(Fields::array, "objects")?.iter().enumerate() {
+                    let path = layer.child(&format!("objects[{}]", i));
+                    let member = member
+                        .as_str()
+                        .ok_or_else(|| invalid(&path, "expected an object or group name"))?;
+                    if file.scene.get(member).is_none() && file.scene.find_group(member).is_none() {
+                        return Err(invalid(
+                            &path,
+                            &format!("no object or group named {}", member),
+                        ));
+                    }
+                    members.push(member.to_string());
+                }
+                let others = match layer.string("others")? {
+                    None | Some("holdout") => LayerRole::Holdout,
+                    Some("hidden") => LayerRole::Hidden,
+                    Some(other) => {
+                        return Err(layer.error(&format!(
+                            "others must be \"holdout\" or \"hidden\", got {}",
+                            other
+                        )))
+                    }
+                };
+                file.layers.push(RenderLayer {
+                    name: name.clone(),
+                    members,
+                    others,
+                });
+            }
+        }
+
+        if let Some(floor) = root.object("floor")? {
+            floor.only(&["height", "min", "max", "colors"])?;
+            let pair = |key| -> io::Result<Option<(Float, Float)>> {
+                Ok(match floor.numbers(key)? {
+                    None => None,
+                    Some(v) if v.len() == 2 => Some((v[0], v[1])),
+                    Some(_) => return Err(floor.error(&format!("{} must be [x, z]", key))),
+                })
+            };
+            let colors = match floor.array("colors")? {
+                None => [Vec3f(0.3, 0.3, 0.3), Vec3f(0.3, 0.2, 0.1)],
+                Some([a, b]) => [
+                    vec3(a, &floor.child("colors[0]"))?,
+                    vec3(b, &floor.child("colors[1]"))?,
+                ],
+                Some(_) => return Err(floor.error("colors must hold two colors")),
+            };
+            file.scene.floor = Some(Checkerboard {
+                height: floor.number("height")?.unwrap_or(-4.0),
+                min: pair("min")?.unwrap_or((-10.0, -30.0)),
+                max: pair("max")?.unwrap_or((10.0, -10.0)),
+                colors,
+            });
+        }
+
+        file.assets = textures.assets;
+        Ok(file)
+    }
+
+    // Copies whatever render settings the file specifies
+    pub fn apply(&self, settings: &mut RenderSettings) {
+        self.render.apply(settings);
+    }
+}
+
+// One render of a batch manifest:
+//
+//   {
+//     "defaults": {"samples": 16, "integrator": "path"},
+//     "jobs": [{"scene": "room.json", "output": "room.png"},
+//              {"output": "classic_{fov}_{samples}.png",
+//               "sweep": {"fov": [30, 60], "samples": [1, 16]}}]
+//   }
+//
+// Jobs take the render keys of a scene file plus fov or hfov, overriding the scene's own.
+// Paths are relative to the manifest, and a job without a scene renders the built-in one.
+// A sweep expands a job into every combination of its values, so the output path must
+// name each swept key in braces to keep the renders from overwriting each other.
+#[derive(Clone, Debug)]
+pub struct BatchJob {
+    pub scene: Option<PathBuf>,
+    pub output: PathBuf,
+    pub render: RenderOverrides,
+    // Degrees, and whether they span the image horizontally
+    pub fov: Option<(Float, bool)>,
+}
+
+// Keys that defaults and sweeps may set, besides the render keys
+const JOB_KEYS: &[&str] = &["scene", "fov", "hfov"];
+
+impl BatchJob {
+    pub fn load_manifest(path: &Path) -> io::Result<Vec<BatchJob>> {
+        let base = path.parent().unwrap_or(Path::new(""));
+        let jobs = fs::read_to_string(path)
+            .and_then(|text| BatchJob::parse_manifest(&text, base))
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        crate::debug!("{}: {} jobs", path.display(), jobs.len());
+        Ok(jobs)
+    }
+
+    pub fn parse_manifest(text: &str, base: &Path) -> io::Result<Vec<BatchJob>> {
+        let root = Json::parse(text)?;
+        let root = Fields::new(&root, "manifest")?;
+        root.only(&["defaults", "jobs"])?;
+        let mut shared = JOB_KEYS.to_vec();
+        shared.extend_from_slice(RENDER_KEYS);
+        let mut job_keys = shared.clone();
+        job_keys.extend_from_slice(&["output", "sweep"]);
+
+        let defaults = match root.object("defaults")? {
+            Some(defaults) => {
+                defaults.only(&shared)?;
+                defaults.members()
+            }
+            None => &[],
+        };
+        let jobs = root.required(Fields::array, "jobs")?;
+        if jobs.is_empty() {
+            return Err(root.error("jobs is empty"));
+        }
+
+        let mut expanded = Vec::new();
+        for (i, job) in jobs.iter().enumerate() {
+            let path = root.child(&format!("jobs[{}]", i));
+            let job = Fields::new(job, &path)?;
+            job.only(&job_keys)?;
+            let output = job.required(Fields::string, "output")?;
+
+            let mut axes: Vec<(&str, &[Json])> = Vec::new();
+            if let Some(sweep) = job.object("sweep")? {
+                sweep.only(&shared)?;
+                for (key, _) in sweep.members() {
+                    let values = sweep.required(Fields::array, key)?;
+                    if values.is_empty() {
+                        return Err(sweep.error(&format!("{} has no values", key)));
+                    }
+                    if !output.contains(&format!("{{{}}}", key)) {
+                        return Err(job.error(&format!(
+                            "output must contain {{{}}} to tell the sweep's renders apart",
+                            key
+                        )));
+                    }
+                    axes.push((key, values));
+                }
+            }
+            let members: Vec<(String, Json)> = defaults
+                .iter()
+                .chain(job.members().iter().filter(|(k, _)| k != "sweep"))
+                .cloned()
+                .collect();
+
+            // Odometer over the sweep values, the last key turning fastest
+            let mut counters = vec![0; axes.len()];
+            loop {
+                let mut members = members.clone();
+                let mut output = output.to_string();
+                for (&(key, values), &n) in axes.iter().zip(&counters) {
+                    let label =
+                        sweep_label(&values[n], &job.child(&format!("sweep.{}[{}]", key, n)))?;
+                    output = output.replace(&format!("{{{}}}", key), &label);
+                    members.push((key.to_string(), values[n].clone()));
+                }
+                members.push(("output".to_string(), Json::String(output)));
+                let combined = Json::Object(members);
+                expanded.push(parse_job(&Fields::new(&combined, &path)?, base)?);
+
+                let Some(axis) = (0..axes.len())
+                    .rev()
+                    .find(|&a| counters[a] + 1 < axes[a].1.len())
+                else {
+                    break;
+                };
+                counters[axis] += 1;
+                counters[axis + 1..].fill(0);
+            }
+        }
+        Ok(expanded)
+    }
+}
+
+fn parse_job(job: &Fields, base: &Path) -> io::Result<BatchJob> {
+    let fov = match (job.number("fov")?, job.number("hfov")?) {
+        (Some(_), Some(_)) => return Err(job.error("give fov or hfov, not both")),
+        (Some(degrees), None) => Some((degrees, false)),
+        (None, Some(degrees)) => Some((degrees, true)),
+        (None, None) => None,
+    };
+    if fov.is_some_and(|(degrees, _)| degrees <= 0.0 || degrees >= 180.0) {
+        return Err(job.error("field of view must lie between 0 and 180 degrees"));
+    }
+    Ok(BatchJob {
+        scene: job.string("scene")?.map(|scene| base.join(scene)),
+        output: base.join(job.required(Fields::string, "output")?),
+        render: parse_render(job)?,
+        fov,
+    })
+}
+
+// How a swept value appears in output paths: whole numbers without a fraction, and
+// strings such as scene paths by their file stem
+fn sweep_label(value: &Json, path: &str) -> io::Result<String> {
+    match value {
+        Json::Number(n) if n.fract() == 0.0 => Ok(format!("{}", *n as i64)),
+        Json::Number(n) => Ok(n.to_string()),
+        Json::String(s) => Ok(Path::new(s)
+            .file_stem()
+            .map_or(s.clone(), |stem| stem.to_string_lossy().into_owned())),
+        other => Err(invalid(
+            path,
+            &format!(
+                "sweep values must be numbers or strings, found {}",
+                other.kind()
+            ),
+        )),
+    }
+}
+
+const RENDER_KEYS: &[&str] = &[
+    "resolution",
+    "width",
+    "height",
+    "samples",
+    "max_depth",
+    "integrator",
+    "regularize",
+    "irradiance_cache",
+    "sampler",
+    "seed",
+    "denoise",
+    "bloom",
+    "auto_exposure",
+];
+
+fn parse_render(render: &Fields) -> io::Result<RenderOverrides> {
+    let mut overrides = RenderOverrides::default();
+    if let Some(value) = render.string("resolution")? {
+        overrides.resolution = Some(
+            parse_resolution(value)
+                .ok_or_else(|| render.error(&format!("invalid resolution {}", value)))?,
+        );
+    }
+    match (render.count("width")?, render.count("height")?) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => {
+            overrides.resolution = Some((width, height))
+        }
+        (None, None) => {}
+        _ => return Err(render.error("width and height must both be positive")),
+    }
+    overrides.samples_per_pixel = render.count("samples")?.map(|n| n as u32);
+    overrides.max_depth = render.count("max_depth")?.map(|n| n as u32);
+    overrides.integrator = match render.string("integrator")? {
+        None => None,
+        Some("whitted") => Some(Integrator::Whitted),
+        Some("path") => Some(Integrator::Path),
+        Some(other) => {
+            return Err(render.error(&format!(
+                "unknown integrator {}; expected whitted or path",
+                other
+            )))
+        }
+    };
+    overrides.regularize = render.number("regularize")?;
+    if overrides
+        .regularize
+        .is_some_and(|r| !(0.0..=1.0).contains(&r))
+    {
+        return Err(render.error("regularize must be between 0 and 1"));
+    }
+    if let Some(name) = render.string("sampler")? {
+        let names: Vec<&str> = Sampler::NAMES.iter().map(|n| n.0).collect();
+        overrides.sampler = Some(Sampler::from_name(name).ok_or_else(|| {
+            render.error(&format!(
+                "unknown sampler {}; expected one of {}",
+                name,
+                names.join(", ")
+            ))
+        })?);
+    }
+    overrides.seed = render.count("seed")?.map(|n| n as u64);
+    // true, or {"radius": 4, "strength": 1}, the radius in pixels
+    let defaults = Denoise::default();
+    overrides.denoise = match render.get("denoise") {
+        None | Some(Json::Bool(false)) => None,
+        Some(Json::Bool(true)) => Some(defaults),
+        Some(_) => {
+            let denoise = render.required(Fields::object, "denoise")?;
+            denoise.only(&["radius", "strength"])?;
+            let built = Denoise {
+                radius: denoise.count("radius")?.unwrap_or(defaults.radius),
+                strength: denoise.number("strength")?.unwrap_or(defaults.strength),
+            };
+            if built.radius == 0 || built.strength <= 0.0 {
+                return Err(denoise.error("denoise needs a positive radius and strength"));
+            }
+            Some(built)
+        }
+    };
+    // {"threshold": 1, "radius": 8, "intensity": 0.5}, the radius in pixels
+    if let Some(bloom) = render.object("bloom")? {
+        bloom.only(&["threshold", "radius", "intensity"])?;
+        let defaults = Bloom::default();
+        let built = Bloom {
+            threshold: bloom.number("threshold")?.unwrap_or(defaults.threshold),
+            radius: bloom.number("radius")?.unwrap_or(defaults.radius),
+            intensity: bloom.number("intensity")?.unwrap_or(defaults.intensity),
+        };
+        if built.threshold < 0.0 || built.radius <= 0.0 || built.intensity < 0.0 {
+            return Err(bloom.error(
+                "bloom needs a threshold and intensity of at least 0 and a positive radius",
+            ));
+        }
+        overrides.bloom = Some(built);
+    }
+    // true, or {"key": 0.18, "prepass_height": 64}, the key being the luminance the
+    // frame's average is exposed to
+    let defaults = AutoExposure::default();
+    overrides.auto_exposure = match render.get("auto_exposure") {
+        None | Some(Json::Bool(false)) => None,
+        Some(Json::Bool(true)) => Some(defaults),
+        Some(_) => {
+            let auto = render.required(Fields::object, "auto_exposure")?;
+            auto.only(&["key", "prepass_height"])?;
+            let built = AutoExposure {
+                key: auto.number("key")?.unwrap_or(defaults.key),
+                prepass_height: auto
+                    .count("prepass_height")?
+                    .unwrap_or(defaults.prepass_height),
+            };
+            if built.key <= 0.0 || built.prepass_height == 0 {
+                return Err(auto.error("auto_exposure needs a positive key and prepass_height"));
+            }
+            Some(built)
+        }
+    };
+    // true, or {"accuracy": 0.25, "samples": 128}
+    let defaults = IrradianceCaching::default();
+    overrides.irradiance_cache = match render.get("irradiance_cache") {
+        None | Some(Json::Bool(false)) => None,
+        Some(Json::Bool(true)) => Some(defaults),
+        Some(_) => {
+            let caching = render.required(Fields::object, "irradiance_cache")?;
+            caching.only(&["accuracy", "samples"])?;
+            let built = IrradianceCaching {
+                accuracy: caching.number("accuracy")?.unwrap_or(defaults.accuracy),
+                samples: caching
+                    .count("samples")?
+                    .map_or(defaults.samples, |n| n as u32),
+            };
+            if built.accuracy <= 0.0 || built.samples == 0 {
+                return Err(caching.error("irradiance_cache needs a positive accuracy and samples"));
+            }
+            Some(built)
+        }
+    };
+    Ok(overrides)
+}
+
+// Adds the materials a file defines to those it can use, each able to build on the ones
+// before it
+fn parse_materials(root: &Fields, known: &mut Vec<(String, Material)>) -> io::Result<()> {
+    if let Some(defined) = root.object("materials")? {
+        for (name, value) in defined.members() {
+            let material = parse_material(&Fields::new(value, &defined.child(name))?, known)?;
+            known.push((name.clone(), material));
+        }
+    }
+    Ok(())
+}
+
+fn parse_material(fields: &Fields, known: &[(String, Material)]) -> io::Result<Material> {
+    fields.only(&[
+        "base",
+        "diffuse",
+        "albedo",
+        "specular_exponent",
+        "refractive_index",
+        "thin_film",
+        "anisotropy",
+        "principled",
+        "sides",
+        "shadow_catcher",
+        "priority",
+        "emission",
+    ])?;
+    let mut material = match (fields.string("base")?, fields.object("principled")?) {
+        (Some(_), Some(_)) => return Err(fields.error("give base or principled, not both")),
+        (Some(name), None) => lookup_material(name, known)
+            .ok_or_else(|| fields.error(&format!("unknown base material {}", name)))?,
+        (None, Some(principled)) => parse_principled(&principled)?.into(),
+        (None, None) => DEFAULT_MATERIAL,
+    };
+    if let Some(diffuse) = fields.vec3("diffuse")? {
+        material.diffuse_color = diffuse;
+    }
+    if let Some(albedo) = fields.numbers("albedo")? {
+        material.albedo = albedo
+            .try_into()
+            .map_err(|_| fields.error("albedo must hold four weights"))?;
+    }
+    if let Some(exponent) = fields.number("specular_exponent")? {
+        material.specular_exponent = exponent;
+    }
+    // A number, or the name of a medium in ior::MEDIA, which brings its dispersion along
+    match fields.get("refractive_index") {
+        Some(Json::String(name)) => {
+            let dispersion = ior::medium(name)
+                .ok_or_else(|| fields.error(&format!("unknown medium {}", name)))?;
+            material.refractive_index = dispersion.nd();
+            material.dispersion = Some(dispersion);
+        }
+        Some(_) => {
+            material.refractive_index = fields.required(Fields::number, "refractive_index")?;
+            material.dispersion = None;
+        }
+        None => {}
+    }
+    if let Some(sides) = fields.string("sides")? {
+        material.sides = match sides {
+            "front" => Sides::Front,
+            "both" => Sides::Both,
+            "cull" => Sides::Cull,
+            other => {
+                return Err(fields.error(&format!(
+                    "unknown sides {}; expected front, both or cull",
+                    other
+                )))
+            }
+        };
+    }
+    if let Some(priority) = fields.count("priority")? {
+        material.priority = priority as u32;
+    }
+    // {"thickness": 400, "refractive_index": 1.33}, the thickness in nanometres
+    if let Some(film) = fields.object("thin_film")? {
+        film.only(&["thickness", "refractive_index"])?;
+        material.thin_film = Some(ThinFilm {
+            thickness: film.required(Fields::number, "thickness")?,
+            refractive_index: film.number("refractive_index")?.unwrap_or(1.33),
+        });
+    }
+    // {"roughness": [0.05, 0.4], "rotation": 90}, roughness along the shape's tangent and
+    // across it, the rotation of the tangent in degrees
+    if let Some(anisotropy) = fields.object("anisotropy")? {
+        anisotropy.only(&["roughness", "rotation"])?;
+        let roughness = match anisotropy
+            .required(Fields::numbers, "roughness")?
+            .as_slice()
+        {
+            [along, across] => (*along, *across),
+            _ => return Err(anisotropy.error("roughness must be [along, across]")),
+        };
+        material.anisotropy = Some(Anisotropy {
+            roughness,
+            rotation: anisotropy.number("rotation")?.unwrap_or(0.0).to_radians(),
+        });
+    }
+    // A color, or {"color": [4, 4, 4], "two_sided": true} to glow from behind as well
+    match fields.get("emission") {
+        None => {}
+        Some(Json::Array(_)) => {
+            material.emission = Some(Emission {
+                color: fields.required(Fields::vec3, "emission")?,
+                two_sided: false,
+            });
+        }
+        Some(_) => {
+            let emission = fields.required(Fields::object, "emission")?;
+            emission.only(&["color", "two_sided"])?;
+            material.emission = Some(Emission {
+                color: emission.required(Fields::vec3, "color")?,
+                two_sided: emission.bool("two_sided")?.unwrap_or(false),
+            });
+        }
+    }
+    // true, or {"reflections": 0.3} to show the scene mirrored in it as well
+    material.shadow_catcher = match fields.get("shadow_catcher") {
+        None | Some(Json::Bool(false)) => None,
+        Some(Json::Bool(true)) => Some(ShadowCatcher { reflections: 0.0 }),
+        Some(_) => {
+            let catcher = fields.required(Fields::object, "shadow_catcher")?;
+            catcher.only(&["reflections"])?;
+            let reflections = catcher.number("reflections")?.unwrap_or(0.0);
+            if !(0.0..=1.0).contains(&reflections) {
+                return Err(catcher.error("reflections must be between 0 and 1"));
+            }
+            Some(ShadowCatcher { reflections })
+        }
+    };
+    Ok(material)
+}
+
+// {"base_color": [0.8, 0.1, 0.1], "metallic": 0, "roughness": 0.5, "specular": 0.5,
+//  "clearcoat": 0, "clearcoat_roughness": 0.03, "sheen": 0, "sheen_tint": 0.5,
+//  "transmission": 0, "ior": 1.45}, anything left out taking Blender's default
+fn parse_principled(fields: &Fields) -> io::Result<Principled> {
+    fields.only(&[
+        "base_color",
+        "metallic",
+        "roughness",
+        "specular",
+        "clearcoat",
+        "clearcoat_roughness",
+        "sheen",
+        "sheen_tint",
+        "transmission",
+        "ior",
+    ])?;
+    let defaults = Principled::default();
+    let unit = |key| -> io::Result<Option<Float>> {
+        match fields.number(key)? {
+            Some(v) if !(0.0..=1.0).contains(&v) => {
+                Err(fields.error(&format!("{} must be between 0 and 1, got {}", key, v)))
+            }
+            v => Ok(v),
+        }
+    };
+    Ok(Principled {
+        base_color: fields.vec3("base_color")?.unwrap_or(defaults.base_color),
+        metallic: unit("metallic")?.unwrap_or(defaults.metallic),
+        roughness: unit("roughness")?.unwrap_or(defaults.roughness),
+        specular: unit("specular")?.unwrap_or(defaults.specular),
+        clearcoat: unit("clearcoat")?.unwrap_or(defaults.clearcoat),
+        clearcoat_roughness: unit("clearcoat_roughness")?.unwrap_or(defaults.clearcoat_roughness),
+        sheen: unit("sheen")?.unwrap_or(defaults.sheen),
+        sheen_tint: unit("sheen_tint")?.unwrap_or(defaults.sheen_tint),
+        transmission: unit("transmission")?.unwrap_or(defaults.transmission),
+        refractive_index: fields.number("ior")?.unwrap_or(defaults.refractive_index),
+    })
+}
+
+// A material given by name or inline
+fn material_ref(value: &Json, path: &str, known: &[(String, Material)]) -> io::Result<Material> {
+    match value {
+        Json::String(name) => lookup_material(name, known)
+            .ok_or_else(|| invalid(path, &format!("unknown material {}", name))),
+        _ => parse_material(&Fields::new(value, path)?, known),
+    }
+}
+
+// Later definitions shadow earlier ones, built-ins included
+fn lookup_material(name: &str, known: &[(String, Material)]) -> Option<Material> {
+    known.iter().rev().find(|(n, _)| n == name).map(|(_, m)| *m)
+}
+
+// Images load once per file however many objects use them with the same wrap mode,
+// included files sharing the includer's
+struct Textures {
+    // The directory of the file being read, which asset paths are relative to
+    base: PathBuf,
+    loaded: Vec<(PathBuf, Wrap, Arc<ImageTexture>)>,
+    // Every file being included, outermost first, so none can include itself
+    including: Vec<PathBuf>,
+    // The scene file's, which the components it includes share
+    variables: Variables,
+    // Every file asked for so far
+    assets: Vec<PathBuf>,
+    access: AssetAccess,
+}
+
+impl Textures {
+    // Where a path at key at in the file being read points, noted as one of the scene's
+    // assets, unless the scene may not read it. A confined path must lead to an existing
+    // file once links and .. are followed, so none can reach outside the directory.
+    fn asset(&mut self, path: &str, at: &str) -> io::Result<PathBuf> {
+        let joined = self.base.join(path);
+        match &self.access {
+            AssetAccess::Any => {}
+            AssetAccess::Denied => {
+                return Err(invalid(
+                    at,
+                    &format!("{}: this scene may not read files", path),
+                ))
+            }
+            AssetAccess::Within(root) => {
+                let inside = fs::canonicalize(&joined).is_ok_and(|real| real.starts_with(root));
+                if !inside {
+                    return Err(invalid(
+                        at,
+                        &format!("{}: no such file in {}", path, root.display()),
+                    ));
+                }
+            }
+        }
+        if !self.assets.contains(&joined) {
+            self.assets.push(joined.clone());
+        }
+        Ok(joined)
+    }
+
+    // "earth.png", or {"image": "earth.png", "wrap": "latlong", "mapping": "uv"} with wrap
+    // one of repeat (the default), clamp or latlong. A triplanar mapping takes the size
+    // one copy of the image covers and optionally the sharpness of the blend:
+    // {"image": "rock.png", "mapping": "triplanar", "size": 2, "sharpness": 4}
+    fn parse(&mut self, value: &Json, path: &str) -> io::Result<TextureMap> {
+        let (image, wrap, mapping) = match value {
+            Json::String(image) => (image.as_str(), Wrap::Repeat, Mapping::Uv),
+            _ => {
+                let fields = Fields::new(value, path)?;
+                fields.only(&["image", "wrap", "mapping", "size", "sharpness"])?;
+                let wrap = match fields.string("wrap")? {
+                    None | Some("repeat") => Wrap::Repeat,
+                    Some("clamp") => Wrap::Clamp,
+                    Some("latlong") => Wrap::LatLong,
+                    Some(other) => {
+                        return Err(fields.error(&format!(
+                            "unknown wrap {}; expected repeat, clamp or latlong",
+                            other
+                        )))
+                    }
+                };
+                let mapping = match fields.string("mapping")? {
+                    None | Some("uv") => {
+                        if fields.get("size").is_some() || fields.get("sharpness").is_some() {
+                            return Err(fields.error("size and sharpness need triplanar mapping"));
+                        }
+                        Mapping::Uv
+                    }
+                    Some("triplanar") => {
+                        let size = fields.number("size")?.unwrap_or(1.0);
+                        let sharpness = fields.number("sharpness")?.unwrap_or(4.0);
+                        if !(size > 0.0 && sharpness > 0.0) {
+                            return Err(fields.error("size and sharpness must be positive"));
+                        }
+                        Mapping::Triplanar { size, sharpness }
+                    }
+                    Some(other) => {
+                        return Err(fields.error(&format!(
+                            "unknown mapping {}; expected uv or triplanar",
+                            other
+                        )))
+                    }
+                };
+                (fields.required(Fields::string, "image")?, wrap, mapping)
+            }
+        };
+        let image = self.asset(image, path)?;
+        if let Some((_, _, texture)) = self
+            .loaded
+            .iter()
+            .find(|(p, w, _)| *p == image && *w == wrap)
+        {
+            return Ok(TextureMap::new(Arc::clone(texture), mapping));
+        }
+        let mut texture = ImageTexture::load(&image).map_err(|e| {
+            io::Error::new(e.kind(), format!("{}: {}: {}", path, image.display(), e))
+        })?;
+        texture.wrap = wrap;
+        let texture = Arc::new(texture);
+        self.loaded.push((image, wrap, Arc::clone(&texture)));
+        Ok(TextureMap::new(texture, mapping))
+    }
+}
+
+// The objects of a component file, a scene file holding only materials and objects, as a
+// group. Its objects can use the including file's materials as well as its own, and
+// its asset paths are relative to where it is.
+fn parse_include(
+    object: &Fields,
+    materials: &[(String, Material)],
+    textures: &mut Textures,
+    axes: &Transform,
+) -> io::Result<Group> {
+    let at = object.child("file");
+    let path = textures.asset(object.required(Fields::string, "file")?, &at)?;
+    let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+    if textures.including.contains(&canonical) {
+        return Err(invalid(&at, &format!("{} includes itself", path.display())));
+    }
+    let text = fs::read_to_string(&path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}: {}", at, path.display(), e)))?;
+
+    let outer = std::mem::replace(
+        &mut textures.base,
+        path.parent().unwrap_or(Path::new("")).to_path_buf(),
+    );
+    textures.including.push(canonical);
+    let parsed = parse_component(&text, materials, textures, axes);
+    textures.including.pop();
+    textures.base = outer;
+    parsed.map_err(|e| io::Error::new(e.kind(), format!("{}: {}: {}", at, path.display(), e)))
+}
+
+fn parse_component(
+    text: &str,
+    materials: &[(String, Material)],
+    textures: &mut Textures,
+    axes: &Transform,
+) -> io::Result<Group> {
+    let mut root = Json::parse(text)?;
+    textures.variables.substitute(&mut root, "scene")?;
+    let root = Fields::new(&root, "scene")?;
+    root.only(&["materials", "objects"])?;
+    let mut materials = materials.to_vec();
+    parse_materials(&root, &mut materials)?;
+    let mut group = Group::new();
+    for (i, child) in root
+        .array("objects")?
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+    {
+        let child = Fields::new(child, &root.child(&format!("objects[{}]", i)))?;
+        group
+            .children
+            .push(parse_object(&child, &materials, textures, axes)?);
+    }
+    Ok(group)
+}
+
+// axes is the turn the whole file gets to make it y-up
+fn parse_object(
+    object: &Fields,
+    materials: &[(String, Material)],
+    textures: &mut Textures,
+    axes: &Transform,
+) -> io::Result<Node> {
+    let kind = object.required(Fields::string, "type")?;
+    let transform = match object.object("transform")? {
+        Some(transform) => parse_transform(&transform)?,
+        None => Transform::identity(),
+    };
+    let transform = upright(object, kind, axes)?.then(&transform);
+    if kind == "group" {
+        object.only(&["type", "name", "transform", "visible", "children"])?;
+        let mut group = Group::with_transform(transform);
+        group.name = object.string("name")?.map(str::to_string);
+        group.visible = object.bool("visible")?.unwrap_or(true);
+        for (i, child) in object
+            .array("children")?
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+        {
+            let child = Fields::new(child, &object.child(&format!("children[{}]", i)))?;
+            group
+                .children
+                .push(parse_object(&child, materials, textures, axes)?);
+        }
+        return Ok(Node::Group(group));
+    }
+    if kind == "include" {
+        object.only(&["type", "name", "transform", "visible", "file"])?;
+        let mut group = parse_include(object, materials, textures, axes)?;
+        group.transform = transform;
+        group.name = object.string("name")?.map(str::to_string);
+        group.visible = object.bool("visible")?.unwrap_or(true);
+        return Ok(Node::Group(group));
+    }
+    let vox_keys = ["file", "model", "origin", "size", "as"];
+    let as_cubes = match (kind, object.string("as")?) {
+        ("vox", None | Some("octree")) => false,
+        ("vox", Some("cubes")) => true,
+        ("vox", Some(other)) => {
+            return Err(object.error(&format!(
+                "unknown voxel representation {}; expected octree or cubes",
+                other
+            )))
+        }
+        _ => false,
+    };
+    if as_cubes {
+        let mut keys = vec!["type", "name", "transform", "visible"];
+        keys.extend_from_slice(&vox_keys);
+        object.only(&keys)?;
+        let (file, model, origin, size) = parse_vox(object, textures)?;
+        let mut group = file.cubes(model, origin, size);
+        group.transform = transform;
+        group.name = object.string("name")?.map(str::to_string);
+        group.visible = object.bool("visible")?.unwrap_or(true);
+        return Ok(Node::Group(group));
+    }
+
+    let shape_keys: &[&str] = match kind {
+        "sphere" => &["center", "radius"],
+        "box" => &["min", "max", "uv"],
+        "cube" => &["center", "size", "uv"],
+        "cone" => &["apex", "height", "radius"],
+        "cylinder" | "pyramid" => &["base", "height", "radius"],
+        "ovoid" => &["center", "radii"],
+        "torus" => &["center", "tube_radius", "radius"],
+        "quad" => &["corner", "u", "v"],
+        "triangle" => &["vertices"],
+        "mesh" => &["vertices", "normals", "uvs", "faces"],
+        "voxels" => &["positions", "colors", "origin", "size"],
+        "vox" => &vox_keys,
+        "lod" => &["levels"],
+        "text" => &[
+            "text",
+            "font",
+            "origin",
+            "size",
+            "depth",
+            "bevel",
+            "curve_segments",
+        ],
+        other => return Err(object.error(&format!("unknown object type {}", other))),
+    };
+    let mut keys = vec![
+        "type",
+        "name",
+        "material",
+        "texture",
+        "blend",
+        "id",
+        "transform",
+        "visibility",
+    ];
+    keys.extend_from_slice(shape_keys);
+    object.only(&keys)?;
+
+    let material = match object.get("material") {
+        None => DEFAULT_MATERIAL,
+        Some(value) => material_ref(value, &object.child("material"), materials)?,
+    };
+    let texture = match object.get("texture") {
+        Some(value) => Some(textures.parse(value, &object.child("texture"))?),
+        None => None,
+    };
+    // {"material": "rust", "factor": 0.5, "mask": "rust_mask.png"}, the mask a texture
+    // like the object's own and the factor defaulting to 1
+    let blend = match object.object("blend")? {
+        Some(blend) => {
+            blend.only(&["material", "factor", "mask"])?;
+            let other = match blend.get("material") {
+                Some(value) => material_ref(value, &blend.child("material"), materials)?,
+                None => return Err(blend.error("missing material")),
+            };
+            let factor = blend.number("factor")?.unwrap_or(1.0);
+            if !(0.0..=1.0).contains(&factor) {
+                return Err(blend.error(&format!("factor must be between 0 and 1, got {}", factor)));
+            }
+            let mut built = BlendMaterial::new(other, factor);
+            if let Some(mask) = blend.get("mask") {
+                built = built.with_mask(textures.parse(mask, &blend.child("mask"))?);
+            }
+            Some(built)
+        }
+        None => None,
+    };
+    let id = object.count("id")?.map(|id| id as u32);
+    let name = object.string("name")?.map(str::to_string);
+    let mut visibility = Visibility::ALL;
+    if let Some(fields) = object.object("visibility")? {
+        fields.only(&["camera", "shadow", "reflection"])?;
+        visibility = Visibility {
+            camera: fields.bool("camera")?.unwrap_or(true),
+            shadow: fields.bool("shadow")?.unwrap_or(true),
+            reflection: fields.bool("reflection")?.unwrap_or(true),
+        };
+    }
+
+    let num = |key| object.required(Fields::number, key);
+    let point = |key| object.required(Fields::vec3, key);
+    let box_uv = || match object.string("uv")? {
+        None | Some("faces") => Ok(BoxUv::Faces),
+        Some("cross") => Ok(BoxUv::Cross),
+        Some(other) => Err(object.error(&format!(
+            "unknown uv layout {}; expected faces or cross",
+            other
+        ))),
+    };
+    let shape: Box<dyn Shape> = match kind {
+        "sphere" => Box::new(Sphere::new(point("center")?, num("radius")?)),
+        "box" => {
+            Box::new(RecgtangularPrism::new(point("min")?, point("max")?).with_uv_layout(box_uv()?))
+        }
+        "cube" => Box::new(Cube::new(point("center")?, num("size")?).with_uv_layout(box_uv()?)),
+        "cone" => Box::new(Cone::new(point("apex")?, num("height")?, num("radius")?)),
+        "cylinder" => Box::new(Cylinder::new(
+            point("base")?,
+            num("height")?,
+            num("radius")?,
+        )),
+        "pyramid" => Box::new(Pyramid::new(point("base")?, num("height")?, num("radius")?)),
+        "ovoid" => Box::new(Ovoid::new(point("center")?, point("radii")?)),
+        "quad" => Box::new(Quad::new(point("corner")?, point("u")?, point("v")?)),
+        "torus" => Box::new(Torus::new(
+            point("center")?,
+            num("tube_radius")?,
+            num("radius")?,
+        )),
+        "voxels" => Box::new(parse_voxels(object)?),
+        "text" => Box::new(parse_text(object, textures)?),
+        "lod" => Box::new(parse_lod(object, materials, textures, axes)?),
+        "vox" => {
+            let (file, model, origin, size) = parse_vox(object, textures)?;
+            Box::new(file.octree(model, origin, size))
+        }
+        "triangle" => match object.required(Fields::points, "vertices")?.as_slice() {
+            [a, b, c] => Box::new(Triangle::new(*a, *b, *c)),
+            _ => return Err(object.error("a triangle needs three vertices")),
+        },
+        _ => {
+            let vertices = object.required(Fields::points, "vertices")?;
+            let faces = object
+                .required(Fields::array, "faces")?
+                .iter()
+                .enumerate()
+                .map(|(i, face)| {
+                    let path = object.child(&format!("faces[{}]", i));
+                    let face = numbers(face, &path)?;
+                    match face.as_slice() {
+                        [a, b, c] => [*a, *b, *c]
+                            .iter()
+                            .map(|&i| index(i, vertices.len(), &path))
+                            .collect::<io::Result<Vec<usize>>>()
+                            .map(|f| [f[0], f[1], f[2]]),
+                        _ => Err(invalid(&path, "a face needs three vertex indices")),
+                    }
+                })
+                .collect::<io::Result<Vec<[usize; 3]>>>()?;
+            let uvs = match object.array("uvs")? {
+                Some(uvs) if uvs.len() != vertices.len() => {
+                    return Err(object.error("uvs must match vertices one to one"))
+                }
+                Some(uvs) => Some(
+                    uvs.iter()
+                        .enumerate()
+                        .map(|(i, uv)| {
+                            let path = object.child(&format!("uvs[{}]", i));
+                            match numbers(uv, &path)?.as_slice() {
+                                [u, v] => Ok((*u, *v)),
+                                _ => Err(invalid(&path, "expected two numbers")),
+                            }
+                        })
+                        .collect::<io::Result<Vec<(Float, Float)>>>()?,
+                ),
+                None => None,
+            };
+            let mesh = match object.points("normals")? {
+                Some(normals) if normals.len() != vertices.len() => {
+                    return Err(object.error("normals must match vertices one to one"))
+                }
+                Some(normals) => TriangleMesh::with_normals(vertices, normals, faces),
+                None => TriangleMesh::new(vertices, faces),
+            };
+            match uvs {
+                Some(uvs) => Box::new(mesh.with_uvs(uvs)),
+                None => Box::new(mesh),
+            }
+        }
+    };
+    let shape: Box<dyn Shape> = if transform.is_identity() {
+        shape
+    } else {
+        Box::new(Transformed::new(shape, transform))
+    };
+    Ok(Node::Object {
+        shape,
+        material,
+        id,
+        name,
+        texture,
+        blend,
+        visibility,
+    })
+}
+
+// {"positions": [[0, 0, 0], [1, 0, 0]], "colors": [[1, 0, 0], [0, 0, 1]], "origin": [0, -4, -12],
+// "size": 0.5}: whole-number grid positions from 0 up, the colors one per position and
+// white when left out, and the origin the grid's outer corner
+fn parse_voxels(object: &Fields) -> io::Result<VoxelOctree> {
+    let positions = object.required(Fields::points, "positions")?;
+    let colors = match object.points("colors")? {
+        Some(colors) if colors.len() != positions.len() => {
+            return Err(object.error("colors must match positions one to one"))
+        }
+        Some(colors) => colors,
+        None => vec![Vec3f(1.0, 1.0, 1.0); positions.len()],
+    };
+    let voxels = positions
+        .iter()
+        .zip(colors)
+        .enumerate()
+        .map(|(i, (p, color))| {
+            let coordinate = |v: Float| {
+                (v >= 0.0 && v.fract() == 0.0 && v < (1u32 << 21) as Float)
+                    .then_some(v as u32)
+                    .ok_or_else(|| {
+                        invalid(
+                            &object.child(&format!("positions[{}]", i)),
+                            &format!("voxel coordinate {} is not a whole number from 0", v),
+                        )
+                    })
+            };
+            Ok((
+                [coordinate(p.0)?, coordinate(p.1)?, coordinate(p.2)?],
+                color,
+            ))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let origin = object.vec3("origin")?.unwrap_or(Vec3f(0.0, 0.0, 0.0));
+    let size = object.number("size")?.unwrap_or(1.0);
+    Ok(VoxelOctree::new(&voxels, origin, size))
+}
+
+// {"position": [0, 0, 0], "target": [0, 0, -1], "fov": 60, ...}, as "camera" and each of
+// "cameras" take it
+fn parse_camera(camera: &Fields, axes: &Transform, textures: &mut Textures) -> io::Result<Camera> {
+    camera.only(&[
+        "position", "target", "up", "fov", "hfov", "near", "far", "exposure", "iso", "shutter",
+        "f_stop", "lens", "aperture",
+    ])?;
+    let position = camera
+        .vec3("position")?
+        .map_or(Vec3f(0.0, 0.0, 0.0), |p| axes.point(&p));
+    let mut built = Camera::new(position, PI / 3.0);
+    if let Some(target) = camera.vec3("target")? {
+        built = built.looking_at(axes.point(&target));
+    }
+    if let Some(up) = camera.vec3("up")? {
+        built.up = axes.vector(&up);
+    }
+    match (camera.number("fov")?, camera.number("hfov")?) {
+        (Some(_), Some(_)) => return Err(camera.error("give fov or hfov, not both")),
+        (Some(fov), None) => built.fov = fov.to_radians(),
+        (None, Some(hfov)) => built = built.with_horizontal_fov(hfov.to_radians()),
+        (None, None) => {}
+    }
+    let near = camera.number("near")?.unwrap_or(0.0);
+    let far = camera.number("far")?.unwrap_or(Float::INFINITY);
+    if near < 0.0 || far <= near {
+        return Err(camera.error("clipping needs 0 <= near < far"));
+    }
+    built = built.with_clipping(near, far);
+    // Exposure as a photographer's three settings, each defaulting to the sunny 16
+    // value, then compensation in stops on top
+    let (iso, shutter, f_stop) = (
+        camera.number("iso")?,
+        camera.number("shutter")?,
+        camera.number("f_stop")?,
+    );
+    if iso.is_some() || shutter.is_some() || f_stop.is_some() {
+        let (base_iso, base_shutter, base_f_stop) = SUNNY_16;
+        let (iso, shutter, f_stop) = (
+            iso.unwrap_or(base_iso),
+            shutter.unwrap_or(base_shutter),
+            f_stop.unwrap_or(base_f_stop),
+        );
+        if iso <= 0.0 || shutter <= 0.0 || f_stop <= 0.0 {
+            return Err(camera.error("iso, shutter and f_stop must be positive"));
+        }
+        built = built.with_photographic_exposure(iso, shutter, f_stop);
+    }
+    if let Some(ev) = camera.number("exposure")? {
+        built = built.with_exposure_compensation(ev);
+    }
+    if let Some(lens) = camera.object("lens")? {
+        lens.only(&["vignetting", "distortion", "chromatic_aberration"])?;
+        let vignetting = lens.number("vignetting")?.unwrap_or(0.0);
+        if !(0.0..=1.0).contains(&vignetting) {
+            return Err(lens.error(&format!(
+                "vignetting must be between 0 and 1, got {}",
+                vignetting
+            )));
+        }
+        built = built.with_lens(Lens {
+            vignetting,
+            distortion: lens.number("distortion")?.unwrap_or(0.0),
+            chromatic_aberration: lens.number("chromatic_aberration")?.unwrap_or(0.0),
+        });
+    }
+    if let Some(aperture) = camera.object("aperture")? {
+        let focus = (built.target - built.position).length();
+        built = built.with_aperture(parse_aperture(&aperture, focus, textures)?);
+    }
+    Ok(built)
+}
+
+// {"levels": [{"object": {"type": "mesh", ...}}, {"below": 80, "object": {...}},
+// {"below": 20, "object": {...}}]}: the finest level first, each later one shown once
+// the whole spans fewer pixels across than its below. Levels are single shapes, which
+// may have transforms of their own, and all take the lod object's material.
+fn parse_lod(
+    object: &Fields,
+    materials: &[(String, Material)],
+    textures: &mut Textures,
+    axes: &Transform,
+) -> io::Result<Lod> {
+    let levels = object.required(Fields::array, "levels")?;
+    let mut lod: Option<Lod> = None;
+    let mut previous = Float::INFINITY;
+    for (i, level) in levels.iter().enumerate() {
+        let level = Fields::new(level, &object.child(&format!("levels[{}]", i)))?;
+        level.only(&["below", "object"])?;
+        let shape_fields = level.required(Fields::object, "object")?;
+        let owned = ["material", "texture", "blend", "id", "name", "visibility"];
+        if let Some(key) = owned.iter().find(|key| shape_fields.get(key).is_some()) {
+            return Err(shape_fields.error(&format!(
+                "{} belongs on the lod object, not its levels",
+                key
+            )));
+        }
+        let shape = match parse_object(&shape_fields, materials, textures, axes)? {
+            Node::Object { shape, .. } => shape,
+            Node::Group(_) => return Err(shape_fields.error("a level must be a single shape")),
+        };
+        lod = Some(match (lod, level.number("below")?) {
+            (None, None) => Lod::new(shape),
+            (None, Some(_)) => {
+                return Err(level.error("the first level is the finest and takes no below"))
+            }
+            (Some(_), None) => return Err(level.error("missing below")),
+            (Some(lod), Some(below)) => {
+                if !(below > 0.0 && below < previous) {
+                    return Err(level.error(&format!(
+                        "below must be positive and less than the level before's, got {}",
+                        below
+                    )));
+                }
+                previous = below;
+                lod.then(below, shape)
+            }
+        });
+    }
+    lod.ok_or_else(|| object.error("a lod needs at least one level"))
+}
+
+// {"text": "Hello", "font": "DejaVuSans.ttf", "origin": [0, 0, -10], "size": 1,
+// "depth": 0.2, "bevel": 0.02, "curve_segments": 8}: the font a TrueType file relative to
+// the scene, and the origin where the first line's baseline starts, with the text reading
+// along +x and facing +z
+fn parse_text(object: &Fields, textures: &mut Textures) -> io::Result<TriangleMesh> {
+    let text = object.required(Fields::string, "text")?;
+    let path = textures.asset(
+        object.required(Fields::string, "font")?,
+        &object.child("font"),
+    )?;
+    let font = Font::load(&path)
+        .map_err(|e| object.error(&format!("cannot load {}: {}", path.display(), e)))?;
+    let defaults = Text3D::default();
+    let built = Text3D {
+        origin: object.vec3("origin")?.unwrap_or(defaults.origin),
+        size: object.number("size")?.unwrap_or(defaults.size),
+        depth: object.number("depth")?.unwrap_or(defaults.depth),
+        bevel: object.number("bevel")?.unwrap_or(defaults.bevel),
+        curve_segments: object
+            .count("curve_segments")?
+            .unwrap_or(defaults.curve_segments),
+    };
+    if built.size <= 0.0 || built.depth <= 0.0 || built.bevel < 0.0 || built.curve_segments == 0 {
+        return Err(object.error(
+            "text needs a positive size, depth and curve_segments and a bevel of at least 0",
+        ));
+    }
+    built
+        .to_mesh(&font, text)
+        .map_err(|e| object.error(&format!("cannot outline {}: {}", path.display(), e)))
+}
+
+// {"file": "castle.vox", "model": 0, "origin": [-4, -4, -20], "size": 0.25, "as": "cubes"}:
+// the file relative to the scene, the model index into it and the origin the grid's outer
+// corner. An octree, the default, takes the object's material tinted by the palette; cubes
+// become a group with one cube per visible voxel in its palette material.
+fn parse_vox(
+    object: &Fields,
+    textures: &mut Textures,
+) -> io::Result<(VoxFile, usize, Vec3f, Float)> {
+    let path = textures.asset(
+        object.required(Fields::string, "file")?,
+        &object.child("file"),
+    )?;
+    let file = VoxFile::load(&path)
+        .map_err(|e| object.error(&format!("cannot load {}: {}", path.display(), e)))?;
+    let model = object.count("model")?.unwrap_or(0);
+    if model >= file.models.len() {
+        return Err(object.error(&format!(
+            "model {} out of range; the file has {}",
+            model,
+            file.models.len()
+        )));
+    }
+    let origin = object.vec3("origin")?.unwrap_or(Vec3f(0.0, 0.0, 0.0));
+    let size = object.number("size")?.unwrap_or(1.0);
+    Ok((file, model, origin, size))
+}
+
+// {"radius": 0.2, "focus_distance": 10, "blades": 6, "rotation": 15, "mask": "star.png"},
+// the focus distance defaulting to the target's and the rotation in degrees; blades and
+// mask exclude each other
+fn parse_aperture(
+    fields: &Fields,
+    default_focus: Float,
+    textures: &mut Textures,
+) -> io::Result<Aperture> {
+    fields.only(&["radius", "focus_distance", "blades", "rotation", "mask"])?;
+    let radius = fields.required(Fields::number, "radius")?;
+    let focus_distance = fields.number("focus_distance")?.unwrap_or(default_focus);
+    if radius < 0.0 || focus_distance <= 0.0 {
+        return Err(
+            fields.error("aperture needs a radius of at least 0 and a positive focus_distance")
+        );
+    }
+    let mut aperture = Aperture::new(radius, focus_distance);
+    match (fields.count("blades")?, fields.get("mask")) {
+        (Some(_), Some(_)) => return Err(fields.error("give blades or mask, not both")),
+        (Some(count), None) => {
+            if count < 3 {
+                return Err(fields.error(&format!("blades must be at least 3, got {}", count)));
+            }
+            aperture.shape = ApertureShape::Blades {
+                count: count as u32,
+                rotation: fields.number("rotation")?.unwrap_or(0.0).to_radians(),
+            };
+        }
+        (None, Some(mask)) => {
+            let mask = textures.parse(mask, &fields.child("mask"))?;
+            aperture.shape = ApertureShape::Mask(ApertureMask::new(mask.texture));
+        }
+        (None, None) => {}
+    }
+    Ok(aperture)
+}
+
+// An equirectangular image's path, or {"cube_map": [px, nx, py, ny, pz, nz]} giving the
+// faces looking along +x, -x, +y, -y, +z and -z as environment::CubeMap lays them out
+fn parse_environment(root: &Fields, textures: &mut Textures) -> io::Result<Environment> {
+    let mut load = |path: &str, at: &str| {
+        let image = textures.asset(path, at)?;
+        ImageTexture::load(&image)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}: {}", at, image.display(), e)))
+    };
+    if let Some(Json::String(path)) = root.get("environment") {
+        return load(path, &root.child("environment")).map(Environment::lat_long);
+    }
+    let fields = root.required(Fields::object, "environment")?;
+    fields.only(&["cube_map"])?;
+    let paths = fields.required(Fields::array, "cube_map")?;
+    if paths.len() != 6 {
+        return Err(fields.error("a cube map needs six faces: +x, -x, +y, -y, +z and -z"));
+    }
+    let mut faces = Vec::with_capacity(6);
+    for (i, path) in paths.iter().enumerate() {
+        let at = fields.child(&format!("cube_map[{}]", i));
+        match path {
+            Json::String(path) => faces.push(load(path, &at)?),
+            _ => return Err(invalid(&at, "expected an image path")),
+        }
+    }
+    let Ok(faces) = <[ImageTexture; 6]>::try_from(faces) else {
+        unreachable!("six faces were checked for");
+    };
+    CubeMap::new(faces)
+        .map(Environment::CubeMap)
+        .ok_or_else(|| fields.error("cube map faces must be square and all the same size"))
+}
+
+// Shapes built around the y axis keep the file's up axis as theirs, so they are turned
+// back about the point they stand on to undo the turn the whole file gets
+fn upright(object: &Fields, kind: &str, axes: &Transform) -> io::Result<Transform> {
+    let key = match kind {
+        "cone" => "apex",
+        "cylinder" | "pyramid" => "base",
+        "torus" => "center",
+        "vox" | "text" => "origin",
+        _ => return Ok(Transform::identity()),
+    };
+    if axes.is_identity() {
+        return Ok(Transform::identity());
+    }
+    let anchor = object.vec3(key)?.unwrap_or(Vec3f(0.0, 0.0, 0.0));
+    Ok(Transform::translation(-anchor)
+        .then(&axes.inverse().expect("axis changes are rotations"))
+        .then(&Transform::translation(anchor)))
+}
+
+// {"scale": 2 or [x, y, z], "rotate": [x, y, z] in degrees, "translate": [x, y, z]},
+// applied in that order
+fn parse_transform(fields: &Fields) -> io::Result<Transform> {
+    fields.only(&["scale", "rotate", "translate"])?;
+    let scale = match fields.get("scale") {
+        None => Vec3f(1.0, 1.0, 1.0),
+        Some(Json::Number(s)) => Vec3f(*s as Float, *s as Float, *s as Float),
+        Some(_) => fields.required(Fields::vec3, "scale")?,
+    };
+    let rotate = fields.vec3("rotate")?.unwrap_or(Vec3f(0.0, 0.0, 0.0));
+    let translate = fields.vec3("translate")?.unwrap_or(Vec3f(0.0, 0.0, 0.0));
+    let radians = Vec3f(
+        rotate.0.to_radians(),
+        rotate.1.to_radians(),
+        rotate.2.to_radians(),
+    );
+    Ok(Transform::scaling(scale)
+        .then(&Transform::euler(radians))
+        .then(&Transform::translation(translate)))
+}
+
+// Object IDs a light is linked to; "floor" names the checkerboard floor
+fn link_ids(fields: &Fields, key: &str) -> io::Result<Option<Vec<u32>>> {
+    let Some(items) = fields.array(key)? else {
+        return Ok(None);
+    };
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let path = fields.child(&format!("{}[{}]", key, i));
+            match item {
+                Json::String(name) if name == "floor" => Ok(FLOOR_ID),
+                Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 && *n < FLOOR_ID as f64 => {
+                    Ok(*n as u32)
+                }
+                _ => Err(invalid(&path, "expected an object ID or \"floor\"")),
+            }
+        })
+        .collect::<io::Result<Vec<u32>>>()
+        .map(Some)
+}
+
+fn index(value: Float, len: usize, path: &str) -> io::Result<usize> {
+    if value >= 0.0 && value.fract() == 0.0 && (value as usize) < len {
+        Ok(value as usize)
+    } else {
+        Err(invalid(
+            path,
+            &format!("vertex index {} out of range", value),
+        ))
+    }
+}
+
+fn invalid(path: &str, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, message))
+}
+
+fn number(value: &Json, path: &str) -> io::Result<Float> {
+    value
+        .as_f64()
+        .map(|n| n as Float)
+        .ok_or_else(|| invalid(path, &format!("expected a number, found {}", value.kind())))
+}
+
+fn numbers(value: &Json, path: &str) -> io::Result<Vec<Float>> {
+    value
+        .as_array()
+        .ok_or_else(|| invalid(path, &format!("expected an array, found {}", value.kind())))?
+        .iter()
+        .enumerate()
+        .map(|(i, n)| number(n, &format!("{}[{}]", path, i)))
+        .collect()
+}
+
+fn vec3(value: &Json, path: &str) -> io::Result<Vec3f> {
+    match numbers(value, path)?.as_slice() {
+        [x, y, z] => Ok(Vec3f(*x, *y, *z)),
+        _ => Err(invalid(path, "expected three numbers")),
+    }
+}
+
+// An object being read, with the path to it for error messages
+struct Fields<'a> {
+    members: &'a [(String, Json)],
+    path: String,
+}
+
+impl<'a> Fields<'a> {
+    fn new(value: &'a Json, path: &str) -> io::Result<Fields<'a>> {
+        let members = value
+            .as_object()
+            .ok_or_else(|| invalid(path, &format!("expected an object, found {}", value.kind())))?;
+        Ok(Fields {
+            members,
+            path: path.to_string(),
+        })
+    }
+
+    fn error(&self, message: &str) -> io::Error {
+        invalid(&self.path, message)
+    }
+
+    fn child(&self, key: &str) -> String {
+        if key.starts_with('[') {
+            format!("{}{}", self.path, key)
+        } else {
+            format!("{}.{}", self.path, key)
+        }
+    }
+
+    fn members(&self) -> &'a [(String, Json)] {
+        self.members
+    }
+
+    fn get(&self, key: &str) -> Option<&'a Json> {
+        self.members
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|m| &m.1)
+    }
+
+    fn only(&self, allowed: &[&str]) -> io::Result<()> {
+        match self
+            .members
+            .iter()
+            .find(|(k, _)| !allowed.contains(&k.as_str()))
+        {
+            Some((key, _)) => Err(self.error(&format!(
+                "unknown key {}; expected one of {}",
+                key,
+                allowed.join(", ")
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    fn required<T>(
+        &self,
+        read: fn(&Self, &str) -> io::Result<Option<T>>,
+        key: &str,
+    ) -> io::Result<T> {
+        read(self, key)?.ok_or_else(|| self.error(&format!("missing {}", key)))
+    }
+
+    fn number(&self, key: &str) -> io::Result<Option<Float>> {
+        self.get(key)
+            .map(|v| number(v, &self.child(key)))
+            .transpose()
+    }
+
+    fn numbers(&self, key: &str) -> io::Result<Option<Vec<Float>>> {
+        self.get(key)
+            .map(|v| numbers(v, &self.child(key)))
+            .transpose()
+    }
+
+    // A non-negative whole number
+    fn count(&self, key: &str) -> io::Result<Option<usize>> {
+        match self.number(key)? {
+            Some(n) if n >= 0.0 && n.fract() == 0.0 => Ok(Some(n as usize)),
+            Some(n) => Err(invalid(
+                &self.child(key),
+                &format!("expected a whole number, found {}", n),
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn vec3(&self, key: &str) -> io::Result<Option<Vec3f>> {
+        self.get(key).map(|v| vec3(v, &self.child(key))).transpose()
+    }
+
+    fn points(&self, key: &str) -> io::Result<Option<Vec<Vec3f>>> {
+        let Some(items) = self.array(key)? else {
+            return Ok(None);
+        };
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, v)| vec3(v, &self.child(&format!("{}[{}]", key, i))))
+            .collect::<io::Result<Vec<Vec3f>>>()
+            .map(Some)
+    }
+
+    fn string(&self, key: &str) -> io::Result<Option<&'a str>> {
+        self.get(key)
+            .map(|v| {
+                v.as_str().ok_or_else(|| {
+                    invalid(
+                        &self.child(key),
+                        &format!("expected a string, found {}", v.kind()),
+                    )
+                })
+            })
+            .transpose()
+    }
+
+    fn bool(&self, key: &str) -> io::Result<Option<bool>> {
+        self.get(key)
+            .map(|v| {
+                v.as_bool().ok_or_else(|| {
+                    invalid(
+                        &self.child(key),
+                        &format!("expected true or false, found {}", v.kind()),
+                    )
+                })
+            })
+            .transpose()
+    }
+
+    fn array(&self, key: &str) -> io::Result<Option<&'a [Json]>> {
+        self.get(key)
+            .map(|v| {
+                v.as_array().ok_or_else(|| {
+                    invalid(
+                        &self.child(key),
+                        &format!("expected an array, found {}", v.kind()),
+                    )
+                })
+            })
+            .transpose()
+    }
+
+    fn object(&self, key: &str) -> io::Result<Option<Fields<'a>>> {
+        self.get(key)
+            .map(|v| Fields::new(v, &self.child(key)))
+            .transpose()
+    }
+}
+
+// Polls files for modification, for re-rendering when a scene is saved
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl FileWatcher {
+    pub fn new(paths: &[PathBuf]) -> FileWatcher {
+        FileWatcher {
+            files: paths
+                .iter()
+                .map(|path| (path.clone(), modified(path)))
+                .collect(),
+        }
+    }
+
+    // Watches paths instead, files watched already keeping track of changes since the last
+    // call to changed
+    pub fn watch(&mut self, paths: &[PathBuf]) {
+        let mut files = Vec::with_capacity(paths.len());
+        for path in paths {
+            let file = match self.files.iter().position(|(p, _)| p == path) {
+                Some(i) => self.files.swap_remove(i),
+                None => (path.clone(), modified(path)),
+            };
+            files.push(file);
+        }
+        self.files = files;
+    }
+
+    // True once for each change to any file since the last call, a file appearing or
+    // disappearing included
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, seen) in &mut self.files {
+            let now = modified(path);
+            if now != *seen {
+                *seen = now;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::Framebuffer;
+
+    fn error(text: &str) -> String {
+        match SceneFile::parse(text) {
+            Ok(_) => panic!("{} parsed", text),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_keys_where_they_are() {
+        assert!(error(r#"{"objets": []}"#).starts_with(
+            "scene: unknown key objets; expected one of render, camera, cameras, background"
+        ));
+        for (text, start) in [
+            (
+                r#"{"render": {"resolution": "8x6", "sample": 4}}"#,
+                "scene.render: unknown key sample;",
+            ),
+            (
+                r#"{"camera": {"position": [0, 0, 0], "zoom": 2}}"#,
+                "scene.camera: unknown key zoom;",
+            ),
+            (
+                r#"{"materials": {"red": {"diffuse": [1, 0, 0], "shine": 1}}}"#,
+                "scene.materials.red: unknown key shine;",
+            ),
+            (
+                r#"{"objects": [{"type": "sphere", "center": [0, 0, 0], "radius": 1,
+                    "colour": [1, 0, 0]}]}"#,
+                "scene.objects[0]: unknown key colour;",
+            ),
+            (
+                r#"{"objects": [{"type": "group", "children": [{"type": "group", "size": 1}]}]}"#,
+                "scene.objects[0].children[0]: unknown key size;",
+            ),
+            (
+                r#"{"lights": [{"type": "ambient", "position": [0, 1, 0]}]}"#,
+                "scene.lights[0]: unknown key position;",
+            ),
+        ] {
+            let message = error(text);
+            assert!(message.starts_with(start), "{}", message);
+        }
+        // Keys that belong elsewhere are no better than misspelt ones
+        let message = error(r#"{"render": {"fov": 40}}"#);
+        assert!(message.contains("unknown key fov"), "{}", message);
+
+        let manifest = |text| BatchJob::parse_manifest(text, Path::new("")).unwrap_err();
+        let message = manifest(r#"{"jobs": [{"scene": "a.json", "output": "a.png", "x": 1}]}"#);
+        assert!(
+            message
+                .to_string()
+                .starts_with("manifest.jobs[0]: unknown key x;"),
+            "{}",
+            message
+        );
+        assert!(manifest(r#"{"job": []}"#)
+            .to_string()
+            .starts_with("manifest: unknown key job;"));
+    }
+
+    #[test]
+    fn names_the_path_to_a_bad_value() {
+        for (text, message) in [
+            (
+                r#"{"objects": [{"type": "group", "children": [
+                    {"type": "sphere", "center": [0, 0], "radius": 1}]}]}"#,
+                "scene.objects[0].children[0].center: expected three numbers",
+            ),
+            (
+                r#"{"objects": [{"type": "sphere", "center": [0, 0, 0]}]}"#,
+                "scene.objects[0]: missing radius",
+            ),
+            (
+                r#"{"objects": [{"type": "sphere", "center": [0, "x", 0], "radius": 1}]}"#,
+                "scene.objects[0].center[1]: expected a number, found a string",
+            ),
+            (
+                r#"{"render": {"samples": 1.5}}"#,
+                "scene.render.samples: expected a whole number, found 1.5",
+            ),
+            (
+                r#"{"objects": {}}"#,
+                "scene.objects: expected an array, found an object",
+            ),
+            (
+                r#"{"objects": [{"type": "blob"}]}"#,
+                "scene.objects[0]: unknown object type blob",
+            ),
+            (r#"[]"#, "scene: expected an object, found an array"),
+        ] {
+            assert_eq!(error(text), message, "{}", text);
+        }
+        // The JSON's own errors come through with their place in the text
+        assert_eq!(
+            error("{\n \"render\": {,}\n}"),
+            "JSON line 2, column 13: expected '\"'"
+        );
+    }
+
+    #[test]
+    fn reads_render_settings_and_lets_the_last_duplicate_win() {
+        let file = SceneFile::parse(
+            r#"{"render": {"resolution": "8x6", "samples": 3, "seed": 9},
+                "background": [1, 0, 0], "background": [0, 0.5, 1]}"#,
+        )
+        .unwrap();
+        assert_eq!(file.scene.background, Vec3f(0.0, 0.5, 1.0));
+        let mut settings = RenderSettings::default();
+        file.render.apply(&mut settings);
+        assert_eq!((settings.width, settings.height), (8, 6));
+        assert_eq!(settings.samples_per_pixel, 3);
+        assert_eq!(settings.seed, 9);
+
+        let json = Json::parse(r#"{"a": 1, "a": 2}"#).unwrap();
+        let fields = Fields::new(&json, "scene.list").unwrap();
+        assert_eq!(fields.number("a").unwrap(), Some(2.0));
+        assert_eq!(fields.child("[3]"), "scene.list[3]");
+        assert_eq!(fields.child("a"), "scene.list.a");
+        assert!(fields.only(&["a"]).is_ok());
+        assert!(fields.only(&["b"]).is_err());
+    }
+
+    #[test]
+    fn expands_manifest_sweeps_over_the_defaults() {
+        let jobs = BatchJob::parse_manifest(
+            r#"{"defaults": {"resolution": "64x48", "samples": 4},
+                "jobs": [
+                    {"output": "plain.png", "hfov": 50},
+                    {"scene": "scenes/room.json", "output": "out/room_{fov}_{samples}.png",
+                     "samples": 2, "sweep": {"fov": [30, 42.5], "samples": [1, 8, 64]}},
+                    {"output": "{scene}.png", "sweep": {"scene": ["a/one.json", "b/two.json"]}}]}"#,
+            Path::new("shots"),
+        )
+        .unwrap();
+        let outputs: Vec<_> = jobs.iter().map(|job| job.output.clone()).collect();
+        let expected: Vec<PathBuf> = [
+            "plain.png",
+            "out/room_30_1.png",
+            "out/room_30_8.png",
+            "out/room_30_64.png",
+            "out/room_42.5_1.png",
+            "out/room_42.5_8.png",
+            "out/room_42.5_64.png",
+            "one.png",
+            "two.png",
+        ]
+        .iter()
+        .map(|path| Path::new("shots").join(path))
+        .collect();
+        assert_eq!(outputs, expected);
+
+        assert_eq!(jobs[0].scene, None);
+        assert_eq!(jobs[0].fov, Some((50.0, true)));
+        assert_eq!(jobs[0].render.resolution, Some((64, 48)));
+        assert_eq!(jobs[0].render.samples_per_pixel, Some(4));
+        // The sweep outranks the job, which outranks the defaults
+        assert_eq!(jobs[1].scene, Some(PathBuf::from("shots/scenes/room.json")));
+        assert_eq!(jobs[1].fov, Some((30.0, false)));
+        assert_eq!(jobs[3].render.samples_per_pixel, Some(64));
+        assert_eq!(jobs[4].fov, Some((42.5, false)));
+        assert_eq!(jobs[4].render.resolution, Some((64, 48)));
+        assert_eq!(jobs[8].scene, Some(PathBuf::from("shots/b/two.json")));
+    }
+
+    #[test]
+    fn names_the_manifest_line_at_fault() {
+        let error = |text: &str| match BatchJob::parse_manifest(text, Path::new("")) {
+            Ok(_) => panic!("{} parsed", text),
+            Err(e) => e.to_string(),
+        };
+        for (text, start) in [
+            (r#"{"jobs": []}"#, "manifest: jobs is empty"),
+            (
+                r#"{"jobs": [{"scene": "a.json"}]}"#,
+                "manifest.jobs[0]: missing output",
+            ),
+            (
+                r#"{"jobs": [{"output": "a.png"}, {"output": "b.png", "fov": 20, "hfov": 30}]}"#,
+                "manifest.jobs[1]: give fov or hfov, not both",
+            ),
+            (
+                r#"{"jobs": [{"output": "a.png", "fov": 180}]}"#,
+                "manifest.jobs[0]: field of view must lie between 0 and 180 degrees",
+            ),
+            (
+                r#"{"jobs": [{"output": "a.png", "sweep": {"fov": [20, 30]}}]}"#,
+                "manifest.jobs[0]: output must contain {fov}",
+            ),
+            (
+                r#"{"jobs": [{"output": "{fov}.png", "sweep": {"fov": []}}]}"#,
+                "manifest.jobs[0].sweep: fov has no values",
+            ),
+            (
+                r#"{"jobs": [{"output": "{fov}.png", "sweep": {"fov": [20, true]}}]}"#,
+                "manifest.jobs[0].sweep.fov[1]: sweep values must be numbers or strings",
+            ),
+            (
+                r#"{"jobs": [{"output": "{output}.png", "sweep": {"output": ["a"]}}]}"#,
+                "manifest.jobs[0].sweep: unknown key output;",
+            ),
+            (
+                r#"{"defaults": {"output": "a.png"}, "jobs": [{"output": "b.png"}]}"#,
+                "manifest.defaults: unknown key output;",
+            ),
+            (
+                r#"{"jobs": [{"output": "a.png", "samples": -1}]}"#,
+                "manifest.jobs[0].samples:",
+            ),
+            (
+                "{\"jobs\": [\n  {\"output\": \"a.png\",}]}",
+                "JSON line 2, column 22: expected '\"'",
+            ),
+        ] {
+            let message = error(text);
+            assert!(message.starts_with(start), "{}", message);
+        }
+    }
+
+    #[test]
+    fn turns_z_up_files_upright() {
+        let z_up = Transform::z_up();
+        assert_eq!(z_up.vector(&Vec3f(0.0, 0.0, 1.0)), Vec3f(0.0, 1.0, 0.0));
+        assert_eq!(z_up.vector(&Vec3f(0.0, 1.0, 0.0)), Vec3f(0.0, 0.0, -1.0));
+        assert_eq!(z_up.determinant(), 1.0);
+
+        let text = |axis: &str| {
+            format!(
+                r#"{{"up_axis": "{}",
+                    "camera": {{"position": [0, -10, 2], "target": [0, 0, 2], "up": [0, 0, 1]}},
+                    "objects": [
+                        {{"type": "sphere", "name": "ball", "center": [1, 2, 3], "radius": 1}},
+                        {{"type": "cylinder", "name": "post", "base": [4, 0, 0], "height": 3,
+                          "radius": 0.5}}],
+                    "lights": [{{"position": [0, 0, 10]}}],
+                    "clip": [{{"point": [0, 0, 2], "normal": [0, 0, 1]}}]}}"#,
+                axis
+            )
+        };
+        let file = SceneFile::parse(&text("z")).unwrap();
+        let scene = &file.scene;
+        let bounds = scene.get("ball").unwrap().shape.bounds();
+        assert!(((bounds.min + bounds.max) * 0.5 - Vec3f(1.0, 3.0, -2.0)).length() < 1e-5);
+        assert_eq!(file.camera.position, Vec3f(0.0, 2.0, 10.0));
+        assert_eq!(file.camera.up, Vec3f(0.0, 1.0, 0.0));
+        assert_eq!(scene.lights[0].position, Vec3f(0.0, 10.0, 0.0));
+        assert_eq!(scene.clip_planes[0].normal, Vec3f(0.0, 1.0, 0.0));
+        // Standing along the file's z axis, which is now up
+        let post = scene.get("post").unwrap().shape.bounds();
+        assert!(
+            (post.max.1 - 3.0).abs() < 1e-5 && post.min.1.abs() < 1e-5,
+            "{:?}",
+            post
+        );
+        assert!((post.max.2 - post.min.2 - 1.0).abs() < 1e-5, "{:?}", post);
+
+        // Read as y-up, the same numbers mean what they say
+        let file = SceneFile::parse(&text("y")).unwrap();
+        assert_eq!(file.scene.lights[0].position, Vec3f(0.0, 0.0, 10.0));
+        let post = file.scene.get("post").unwrap().shape.bounds();
+        assert!((post.max.1 - 3.0).abs() < 1e-5, "{:?}", post);
+        assert!(error(&text("x")).contains("up_axis must be \"y\" or \"z\", got x"));
+    }
+
+    #[test]
+    fn light_temperatures_become_their_blackbody_colors() {
+        let light = |fields: &str| {
+            format!(
+                r#"{{"lights": [{{"position": [0, 5, 0], "intensity": 1{}}}]}}"#,
+                fields
+            )
+        };
+        let file = SceneFile::parse(&light(r#", "temperature": 2700"#)).unwrap();
+        assert_eq!(file.scene.lights[0].color, blackbody(2700.0));
+        let file = SceneFile::parse(&light(r#", "color": [0.5, 0.25, 1]"#)).unwrap();
+        assert_eq!(file.scene.lights[0].color, Vec3f(0.5, 0.25, 1.0));
+        let file = SceneFile::parse(&light("")).unwrap();
+        assert_eq!(file.scene.lights[0].color, Vec3f(1.0, 1.0, 1.0));
+
+        let message = error(&light(r#", "color": [1, 1, 1], "temperature": 2700"#));
+        assert!(
+            message.contains("give color or temperature, not both"),
+            "{}",
+            message
+        );
+        for kelvin in ["500", "50000"] {
+            let message = error(&light(&format!(r#", "temperature": {}"#, kelvin)));
+            assert!(
+                message.contains("temperature must be between 1000 and 40000 kelvin"),
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn shadow_catchers_are_true_or_their_reflections() {
+        let material = |catcher: &str| {
+            format!(
+                r#"{{"materials": {{"ground": {{"shadow_catcher": {}}}}},
+                    "objects": [{{"type": "sphere", "center": [0, 0, 0], "radius": 1,
+                                  "material": "ground"}}]}}"#,
+                catcher
+            )
+        };
+        let catcher = |text: &str| {
+            let file = SceneFile::parse(text).unwrap();
+            file.scene.objects()[0].material.shadow_catcher
+        };
+        assert_eq!(catcher(&material("false")), None);
+        assert_eq!(
+            catcher(&material("true")),
+            Some(ShadowCatcher { reflections: 0.0 })
+        );
+        assert_eq!(
+            catcher(&material(r#"{"reflections": 0.25}"#)),
+            Some(ShadowCatcher { reflections: 0.25 })
+        );
+        let message = error(&material(r#"{"reflections": 2}"#));
+        assert!(
+            message.contains("scene.materials.ground.shadow_catcher: reflections must be"),
+            "{}",
+            message
+        );
+        let message = error(&material(r#"{"blur": 1}"#));
+        assert!(message.contains("unknown key blur"), "{}", message);
+    }
+
+    #[test]
+    fn backplates_load_relative_to_the_scene_and_clamp() {
+        let dir = std::env::temp_dir().join("rusty_rays_backplate");
+        fs::create_dir_all(&dir).unwrap();
+        let mut image = Framebuffer::new(3, 2);
+        image.set(2, 1, Vec3f(1.0, 0.0, 0.0));
+        image.write_png(&dir.join("street.png")).unwrap();
+        let file = SceneFile::parse_relative_to(r#"{"backplate": "street.png"}"#, &dir).unwrap();
+        let plate = file.scene.backplate.unwrap();
+        assert_eq!((plate.width(), plate.height()), (3, 2));
+        assert_eq!(plate.wrap, Wrap::Clamp);
+        assert_eq!(plate.texel(2, 1), Vec3f(1.0, 0.0, 0.0));
+
+        let message = match SceneFile::parse_relative_to(r#"{"backplate": "road.png"}"#, &dir) {
+            Ok(_) => panic!("a missing backplate loaded"),
+            Err(e) => e.to_string(),
+        };
+        assert!(message.starts_with("scene.backplate: "), "{}", message);
+        assert!(message.contains("road.png"), "{}", message);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,814 @@
+// Renders over HTTP, for a web front end or a notebook on another machine: POST a scene
+// file's JSON to /render and the reply is the PNG. The scene's own render settings apply
+// over the server's, up to limits on pixels, samples, bounces and filter radii so one
+// request cannot tie the machine up indefinitely; each is a bound on the work a frame
+// takes, since a render cannot be stopped partway through a tile. Scenes come from the network, so their images, fonts, models
+// and includes can only be files under the server's asset directory, and without one
+// no files at all. Only what a render needs of HTTP/1.1 is spoken: the body must come
+// with a Content-Length, and every connection closes after its reply.
+//
+// So one machine can be shared, scenes can instead be queued: POST /jobs answers at once
+// with the job's id, GET /jobs/ID says whether it is queued (and how many jobs are ahead
+// of it), rendering (and how far along) or done, and GET /jobs/ID/result then gives the
+// PNG. ?priority=N on either POST puts it ahead of jobs with less, default 0, and equal
+// priorities go first come first served. DELETE /jobs/ID drops a job, stopping it if it
+// is rendering. One job renders at a time, since a render already keeps every core busy,
+// and /render waits its turn like the rest. So no one client can fill memory, disk or
+// the thread count, new jobs and connections are turned away with 503 once too many are
+// waiting or open. With a queue directory the queue outlives the server: each job keeps
+// its scene in ID.json beside ID.job, its priority, and then ID.png or ID.error, and a
+// restart picks up where the last left off.
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::framebuffer::Framebuffer;
+use crate::json::{self, Json};
+use crate::material::MaterialOverride;
+use crate::render::{render_with, RenderObserver, RenderSettings};
+use crate::scene::Severity;
+use crate::scene_file::SceneFile;
+use crate::tiles::TileRect;
+use crate::vec3::{Float, Vec3f};
+
+// Longest a client may leave the server waiting for its request
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_HEADER: usize = 16 * 1024;
+
+pub struct RenderServer {
+    pub settings: RenderSettings,
+    pub material_override: Option<MaterialOverride>,
+    // Largest scene accepted, in bytes
+    pub max_body: usize,
+    // Most a scene may ask for: pixels in the frame, samples for each one (and for each
+    // irradiance cache record), and bounces, which Whitted shading doubles the rays of at
+    // every glass surface
+    pub max_pixels: usize,
+    pub max_samples: u32,
+    pub max_depth: u32,
+    // Widest, in pixels, a denoise or bloom filter may reach
+    pub max_radius: usize,
+    // The only directory scenes may read assets from; None lets them read nothing
+    pub asset_dir: Option<PathBuf>,
+    // Where queued jobs and their results are kept; without one they last only as long
+    // as the server
+    pub queue_dir: Option<PathBuf>,
+    // Most jobs, /render's included, waiting or rendering at once, and most connections
+    // open at once; past either the server replies 503
+    pub max_queued: usize,
+    pub max_connections: usize,
+}
+
+impl Default for RenderServer {
+    fn default() -> RenderServer {
+        RenderServer {
+            settings: RenderSettings::default(),
+            material_override: None,
+            max_body: 16 * 1024 * 1024,
+            max_pixels: 3840 * 2160,
+            max_samples: 4096,
+            max_depth: 10,
+            max_radius: 32,
+            asset_dir: None,
+            queue_dir: None,
+            max_queued: 64,
+            max_connections: 64,
+        }
+    }
+}
+
+// A reply yet to be written
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn text(status: u16, message: &str) -> Response {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{}\n", message).into_bytes(),
+        }
+    }
+
+    fn json(status: u16, json: String) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body: format!("{}\n", json).into_bytes(),
+        }
+    }
+
+    fn png(png: Vec<u8>) -> Response {
+        Response {
+            status: 200,
+            content_type: "image/png",
+            body: png,
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(
+            out,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len()
+        )?;
+        out.write_all(&self.body)?;
+        out.flush()
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    body: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Status {
+    Queued,
+    Rendering,
+    Done,
+    Failed,
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Queued => "queued",
+            Status::Rendering => "rendering",
+            Status::Done => "done",
+            Status::Failed => "failed",
+        }
+    }
+}
+
+struct Job {
+    priority: i64,
+    status: Status,
+    // Tiles done of those in the frame
+    tiles: (usize, usize),
+    // Until the job renders
+    scene: Vec<u8>,
+    // Kept here only without a queue directory
+    png: Option<Vec<u8>>,
+    error: Option<String>,
+    // Sent to /render, which takes the result away itself, so never listed or stored
+    transient: bool,
+    // Deleted while rendering
+    cancelled: bool,
+}
+
+impl Job {
+    fn new(priority: i64, status: Status, scene: Vec<u8>) -> Job {
+        Job {
+            priority,
+            status,
+            tiles: (0, 0),
+            scene,
+            png: None,
+            error: None,
+            transient: false,
+            cancelled: false,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: BTreeMap<u64, Job>,
+    next_id: u64,
+    stopping: bool,
+}
+
+impl Queue {
+    // The queued jobs in the order they will render
+    fn waiting(&self) -> Vec<u64> {
+        let mut ids: Vec<u64> = self
+            .jobs
+            .iter()
+            .filter(|(_, job)| job.status == Status::Queued)
+            .map(|(&id, _)| id)
+            .collect();
+        ids.sort_by_key(|id| (Reverse(self.jobs[id].priority), *id));
+        ids
+    }
+
+    fn describe(&self, id: u64) -> String {
+        let job = &self.jobs[&id];
+        let mut json = format!(
+            "{{\"id\": {}, \"status\": \"{}\", \"priority\": {}",
+            id,
+            job.status.name(),
+            job.priority
+        );
+        match job.status {
+            Status::Queued => {
+                let ahead = self.waiting().iter().position(|&i| i == id).unwrap_or(0);
+                json.push_str(&format!(", \"ahead\": {}", ahead));
+            }
+            Status::Rendering => {
+                let (done, total) = job.tiles;
+                let progress = if total == 0 {
+                    0.0
+                } else {
+                    done as f64 / total as f64
+                };
+                json.push_str(&format!(", \"progress\": {}", progress));
+            }
+            Status::Done => json.push_str(", \"progress\": 1"),
+            Status::Failed => json.push_str(&format!(
+                ", \"error\": {}",
+                json::quote(job.error.as_deref().unwrap_or(""))
+            )),
+        }
+        json.push('}');
+        json
+    }
+}
+
+struct Jobs {
+    queue: Mutex<Queue>,
+    // Signalled whenever a job is added or finishes, or the server stops
+    changed: Condvar,
+    dir: Option<PathBuf>,
+    // Most jobs queued or rendering at once
+    limit: usize,
+}
+
+// Job id as its files in dir left it, where path is its ID.job
+fn reload(dir: &Path, id: u64, path: &Path) -> io::Result<Job> {
+    let priority = Json::parse(&fs::read_to_string(path)?)?
+        .get("priority")
+        .and_then(Json::as_f64)
+        .unwrap_or(0.0) as i64;
+    let file = |extension| dir.join(format!("{}.{}", id, extension));
+    Ok(if file("png").exists() {
+        Job::new(priority, Status::Done, Vec::new())
+    } else if let Ok(error) = fs::read_to_string(file("error")) {
+        Job {
+            error: Some(error),
+            ..Job::new(priority, Status::Failed, Vec::new())
+        }
+    } else {
+        Job::new(priority, Status::Queued, fs::read(file("json"))?)
+    })
+}
+
+impl Jobs {
+    // The jobs left in dir by an earlier server: those with neither a result nor an error
+    // are queued again, including any that were rendering when it stopped
+    fn load(dir: Option<&Path>, limit: usize) -> io::Result<Jobs> {
+        let mut queue = Queue {
+            next_id: 1,
+            ..Queue::default()
+        };
+        if let Some(dir) = dir {
+            fs::create_dir_all(dir)?;
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                let id = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse::<u64>().ok());
+                let (Some(id), Some("job")) = (id, path.extension().and_then(|e| e.to_str()))
+                else {
+                    continue;
+                };
+                // A job that cannot be read back fails on its own; the rest still load
+                let job = reload(dir, id, &path).unwrap_or_else(|e| {
+                    crate::warn!("{}: job {} could not be reloaded: {}", dir.display(), id, e);
+                    Job {
+                        error: Some(format!("could not be reloaded: {}", e)),
+                        ..Job::new(0, Status::Failed, Vec::new())
+                    }
+                });
+                queue.jobs.insert(id, job);
+                queue.next_id = queue.next_id.max(id + 1);
+            }
+            if !queue.jobs.is_empty() {
+                crate::info!(
+                    "{}: {} jobs, {} still to render",
+                    dir.display(),
+                    queue.jobs.len(),
+                    queue.waiting().len()
+                );
+            }
+        }
+        Ok(Jobs {
+            queue: Mutex::new(queue),
+            changed: Condvar::new(),
+            dir: dir.map(Path::to_path_buf),
+            limit,
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn wait<'a>(&self, queue: MutexGuard<'a, Queue>) -> MutexGuard<'a, Queue> {
+        self.changed.wait(queue).unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn file(&self, id: u64, extension: &str) -> Option<PathBuf> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.{}", id, extension)))
+    }
+
+    // The new job's id, or None with the queue already full
+    fn submit(&self, scene: Vec<u8>, priority: i64, transient: bool) -> io::Result<Option<u64>> {
+        let mut queue = self.lock();
+        let unfinished = queue
+            .jobs
+            .values()
+            .filter(|job| matches!(job.status, Status::Queued | Status::Rendering))
+            .count();
+        if unfinished >= self.limit {
+            return Ok(None);
+        }
+        let id = queue.next_id;
+        if !transient {
+            if let (Some(json), Some(job)) = (self.file(id, "json"), self.file(id, "job")) {
+                // The .job file last, so a job is never found without its scene
+                fs::write(json, &scene)?;
+                fs::write(job, format!("{{\"priority\": {}}}\n", priority))?;
+            }
+        }
+        queue.next_id += 1;
+        let job = Job {
+            transient,
+            ..Job::new(priority, Status::Queued, scene)
+        };
+        queue.jobs.insert(id, job);
+        self.changed.notify_all();
+        Ok(Some(id))
+    }
+
+    // Forgets the job and its files, or if it is rendering asks the render to stop and
+    // leaves the rest to the worker
+    fn remove(&self, queue: &mut Queue, id: u64) {
+        let Some(job) = queue.jobs.get_mut(&id) else {
+            return;
+        };
+        if job.status == Status::Rendering {
+            job.cancelled = true;
+            return;
+        }
+        if queue.jobs.remove(&id).is_some_and(|job| job.transient) {
+            return;
+        }
+        for extension in ["job", "json", "png", "error"] {
+            let Some(path) = self.file(id, extension) else {
+                continue;
+            };
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    crate::warn!("removing {}: {}", path.display(), e)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn stop(&self) {
+        self.lock().stopping = true;
+        self.changed.notify_all();
+    }
+}
+
+// Reports a job's render to the queue, and stops it once the job is deleted
+struct Progress<'a> {
+    jobs: &'a Jobs,
+    id: u64,
+}
+
+impl Progress<'_> {
+    fn update(&self, update: impl FnOnce(&mut Job)) {
+        if let Some(job) = self.jobs.lock().jobs.get_mut(&self.id) {
+            update(job);
+        }
+    }
+}
+
+impl RenderObserver for Progress<'_> {
+    fn on_render_start(&self, _width: usize, _height: usize, tile_count: usize) {
+        self.update(|job| job.tiles = (0, tile_count));
+    }
+
+    fn on_tile_complete(&self, _tile: &TileRect, _pixels: &[Vec3f]) {
+        self.update(|job| job.tiles.0 += 1);
+    }
+
+    fn cancelled(&self) -> bool {
+        self.jobs
+            .lock()
+            .jobs
+            .get(&self.id)
+            .is_none_or(|job| job.cancelled)
+    }
+}
+
+impl RenderServer {
+    // Answers connections, each on its own thread, until accepting one fails; a client
+    // that breaks off only ends its own request
+    pub fn serve(&self, listener: &TcpListener) -> io::Result<()> {
+        let jobs = Jobs::load(self.queue_dir.as_deref(), self.max_queued)?;
+        let jobs = &jobs;
+        let open = &AtomicUsize::new(0);
+        thread::scope(|scope| {
+            scope.spawn(|| self.work(jobs));
+            let result = loop {
+                let (mut stream, peer) = match listener.accept() {
+                    Ok(connection) => connection,
+                    Err(e) => break Err(e),
+                };
+                // Turned away without a thread, and without reading a request that could
+                // keep this loop waiting
+                if open.load(Ordering::SeqCst) >= self.max_connections {
+                    crate::warn!(
+                        "turning {} away: {} connections open",
+                        peer,
+                        open.load(Ordering::SeqCst)
+                    );
+                    let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));
+                    let _ = Response::text(503, "too many connections; try again later")
+                        .write_to(&mut stream);
+                    continue;
+                }
+                open.fetch_add(1, Ordering::SeqCst);
+                scope.spawn(move || {
+                    let request = match stream.set_read_timeout(Some(READ_TIMEOUT)) {
+                        Ok(()) => self.read_request(&mut stream),
+                        Err(e) => Err(Response::text(500, &e.to_string())),
+                    };
+                    let response = match request {
+                        Ok(request) => {
+                            let response = self.respond(jobs, &request);
+                            crate::info!(
+                                "{} {} {} from {}",
+                                request.method,
+                                request.path,
+                                response.status,
+                                peer
+                            );
+                            response
+                        }
+                        Err(response) => response,
+                    };
+                    if let Err(e) = response.write_to(&mut stream) {
+                        crate::warn!("replying to {}: {}", peer, e);
+                    }
+                    open.fetch_sub(1, Ordering::SeqCst);
+                });
+            };
+            jobs.stop();
+            result
+        })
+    }
+
+    // Renders the queue's jobs in turn until the server stops
+    fn work(&self, jobs: &Jobs) {
+        loop {
+            let (id, scene) = {
+                let mut queue = jobs.lock();
+                loop {
+                    if queue.stopping {
+                        return;
+                    }
+                    if let Some(&id) = queue.waiting().first() {
+                        let job = queue.jobs.get_mut(&id).expect("waiting jobs exist");
+                        job.status = Status::Rendering;
+                        break (id, std::mem::take(&mut job.scene));
+                    }
+                    queue = jobs.wait(queue);
+                }
+            };
+            crate::info!("rendering job {}", id);
+            let mut rendered = self
+                .render(&scene, &Progress { jobs, id })
+                .and_then(|image| {
+                    let mut png = Vec::new();
+                    image.encode_png(&mut png)?;
+                    Ok(png)
+                });
+            let mut queue = jobs.lock();
+            let Some(job) = queue.jobs.get_mut(&id) else {
+                continue;
+            };
+            if !job.transient {
+                let stored = match &rendered {
+                    Ok(png) => jobs.file(id, "png").map(|path| fs::write(path, png)),
+                    Err(e) => jobs
+                        .file(id, "error")
+                        .map(|path| fs::write(path, e.to_string())),
+                };
+                match stored {
+                    Some(Ok(())) => rendered = rendered.map(|_| Vec::new()),
+                    Some(Err(e)) => rendered = Err(e),
+                    None => {}
+                }
+            }
+            match rendered {
+                Ok(png) => {
+                    job.status = Status::Done;
+                    job.png = Some(png).filter(|png| !png.is_empty());
+                }
+                Err(e) => {
+                    job.status = Status::Failed;
+                    job.error = Some(e.to_string());
+                }
+            }
+            if job.cancelled {
+                jobs.remove(&mut queue, id);
+            }
+            jobs.changed.notify_all();
+        }
+    }
+
+    // The request on stream, or the error to reply with
+    fn read_request<S: Read + Write>(&self, stream: &mut S) -> Result<Request, Response> {
+        let bad = |message: &str| Response::text(400, message);
+        let mut reader = BufReader::new(stream);
+        let mut lines = Vec::new();
+        let mut read = 0;
+        loop {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(0) => return Err(bad("the request ended before its headers did")),
+                Ok(n) => read += n,
+                Err(e) => return Err(bad(&format!("reading the request: {}", e))),
+            }
+            if read > MAX_HEADER {
+                return Err(Response::text(431, "the request's headers are too long"));
+            }
+            let line = line.trim_end_matches(['\r', '\n']).to_string();
+            if line.is_empty() {
+                break;
+            }
+            lines.push(line);
+        }
+        let mut request = lines.first().map_or("", |l| l.as_str()).split(' ');
+        let (Some(method), Some(target), Some(_)) =
+            (request.next(), request.next(), request.next())
+        else {
+            return Err(bad("malformed request line"));
+        };
+        let header = |name: &str| {
+            lines[1..].iter().find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                key.trim()
+                    .eq_ignore_ascii_case(name)
+                    .then(|| value.trim().to_string())
+            })
+        };
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let mut request = Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: query.to_string(),
+            body: Vec::new(),
+        };
+        if method != "POST" {
+            return Ok(request);
+        }
+        if header("transfer-encoding").is_some() {
+            return Err(Response::text(411, "send the scene with a Content-Length"));
+        }
+        let length: usize = match header("content-length").map(|v| v.parse()) {
+            Some(Ok(length)) => length,
+            Some(Err(_)) => return Err(bad("invalid Content-Length")),
+            None => return Err(Response::text(411, "send the scene with a Content-Length")),
+        };
+        if length > self.max_body {
+            return Err(Response::text(
+                413,
+                &format!("scenes are limited to {} bytes", self.max_body),
+            ));
+        }
+        if header("expect").is_some_and(|v| v.eq_ignore_ascii_case("100-continue")) {
+            let _ = reader.get_mut().write_all(b"HTTP/1.1 100 Continue\r\n\r\n");
+        }
+        request.body = vec![0; length];
+        reader
+            .read_exact(&mut request.body)
+            .map_err(|e| bad(&format!("reading the scene: {}", e)))?;
+        Ok(request)
+    }
+
+    fn respond(&self, jobs: &Jobs, request: &Request) -> Response {
+        let segments: Vec<&str> = request.path.split('/').skip(1).collect();
+        let priority = match query_value(&request.query, "priority").map(str::parse) {
+            None => 0,
+            Some(Ok(priority)) => priority,
+            Some(Err(_)) => return Response::text(400, "priority must be a whole number"),
+        };
+        let submit = |transient| {
+            // A scene that will not load is refused before it takes a place in the queue
+            self.load(&request.body)
+                .map_err(|e| Response::text(400, &e.to_string()))?;
+            match jobs.submit(request.body.clone(), priority, transient) {
+                Ok(Some(id)) => Ok(id),
+                Ok(None) => Err(Response::text(
+                    503,
+                    &format!("{} jobs are already waiting; try again later", jobs.limit),
+                )),
+                Err(e) => Err(Response::text(500, &format!("queueing the scene: {}", e))),
+            }
+        };
+        // The listed job named by text, with the queue locked
+        let find = |text: &str| {
+            let queue = jobs.lock();
+            match text.parse::<u64>() {
+                Ok(id) if queue.jobs.get(&id).is_some_and(|job| !job.transient) => Ok((queue, id)),
+                _ => Err(Response::text(404, &format!("no job {}", text))),
+            }
+        };
+        let reply = match (request.method.as_str(), segments.as_slice()) {
+            ("POST", ["render"]) => submit(true).map(|id| {
+                let mut queue = jobs.lock();
+                while matches!(queue.jobs[&id].status, Status::Queued | Status::Rendering) {
+                    if queue.stopping {
+                        return Response::text(503, "the server is stopping");
+                    }
+                    queue = jobs.wait(queue);
+                }
+                let job = queue
+                    .jobs
+                    .remove(&id)
+                    .expect("only this request removes it");
+                match (job.png, job.error) {
+                    (Some(png), _) => Response::png(png),
+                    (None, error) => Response::text(400, error.as_deref().unwrap_or("")),
+                }
+            }),
+            ("POST", ["jobs"]) => {
+                submit(false).map(|id| Response::json(202, jobs.lock().describe(id)))
+            }
+            ("GET", ["jobs"]) => {
+                let queue = jobs.lock();
+                let listed: Vec<String> = queue
+                    .jobs
+                    .iter()
+                    .filter(|(_, job)| !job.transient)
+                    .map(|(&id, _)| queue.describe(id))
+                    .collect();
+                Ok(Response::json(
+                    200,
+                    format!("{{\"jobs\": [{}]}}", listed.join(", ")),
+                ))
+            }
+            ("GET", ["jobs", id]) => {
+                find(id).map(|(queue, id)| Response::json(200, queue.describe(id)))
+            }
+            ("DELETE", ["jobs", id]) => find(id).map(|(mut queue, id)| {
+                jobs.remove(&mut queue, id);
+                Response::text(200, &format!("deleted job {}", id))
+            }),
+            ("GET", ["jobs", id, "result"]) => find(id).map(|(queue, id)| {
+                let job = &queue.jobs[&id];
+                match (job.status, &job.png, jobs.file(id, "png")) {
+                    (Status::Done, Some(png), _) => Response::png(png.clone()),
+                    (Status::Done, None, Some(path)) => match fs::read(path) {
+                        Ok(png) => Response::png(png),
+                        Err(e) => Response::text(500, &format!("reading the result: {}", e)),
+                    },
+                    (Status::Failed, _, _) => Response::text(
+                        409,
+                        &format!("job {} failed: {}", id, job.error.as_deref().unwrap_or("")),
+                    ),
+                    (status, _, _) => {
+                        Response::text(409, &format!("job {} is {}", id, status.name()))
+                    }
+                }
+            }),
+            ("GET", ["health"]) => Ok(Response::text(200, "ok")),
+            (_, ["render"] | ["health"] | ["jobs", ..]) => {
+                Ok(Response::text(405, "method not allowed"))
+            }
+            _ => Ok(Response::text(404, "POST a scene to /render or /jobs")),
+        };
+        reply.unwrap_or_else(|response| response)
+    }
+
+    // The scene in body and the settings to render it with
+    fn load(&self, body: &[u8]) -> io::Result<(SceneFile, RenderSettings)> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let text =
+            std::str::from_utf8(body).map_err(|_| invalid("the scene is not UTF-8".to_string()))?;
+        let mut file = SceneFile::parse_confined(text, self.asset_dir.as_deref())?;
+        file.scene.material_override = self.material_override;
+        let errors: Vec<String> = file
+            .scene
+            .validate()
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.message)
+            .collect();
+        if !errors.is_empty() {
+            return Err(invalid(format!(
+                "scene failed validation: {}",
+                errors.join("; ")
+            )));
+        }
+        let mut settings = self.settings.clone();
+        file.apply(&mut settings);
+        // A prepass needs no more pixels than the frame it meters
+        if let Some(auto) = &mut settings.auto_exposure {
+            auto.prepass_height = auto.prepass_height.min(settings.height);
+        }
+        self.check(&settings).map_err(invalid)?;
+        Ok((file, settings))
+    }
+
+    // Whether settings stay within the server's limits, and which one they pass if not
+    fn check(&self, settings: &RenderSettings) -> Result<(), String> {
+        if settings.width.saturating_mul(settings.height) > self.max_pixels {
+            return Err(format!(
+                "{}x{} is more than the {} pixels this server renders",
+                settings.width, settings.height, self.max_pixels
+            ));
+        }
+        if settings.samples_per_pixel > self.max_samples {
+            return Err(format!(
+                "{} samples per pixel is more than the {} this server takes",
+                settings.samples_per_pixel, self.max_samples
+            ));
+        }
+        if settings.max_depth > self.max_depth {
+            return Err(format!(
+                "max_depth {} is more than the {} this server takes",
+                settings.max_depth, self.max_depth
+            ));
+        }
+        if let Some(caching) = &settings.irradiance_cache {
+            if caching.samples > self.max_samples {
+                return Err(format!(
+                    "{} samples per irradiance cache record is more than the {} this server \
+                     takes",
+                    caching.samples, self.max_samples
+                ));
+            }
+        }
+        let radii = [
+            ("denoise", settings.denoise.map(|d| d.radius as Float)),
+            ("bloom", settings.bloom.map(|b| b.radius)),
+        ];
+        for (name, radius) in radii {
+            match radius {
+                Some(radius) if radius > self.max_radius as Float => {
+                    return Err(format!(
+                        "a {} radius of {} is more than the {} pixels this server takes",
+                        name, radius, self.max_radius
+                    ))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // The scene in body rendered through its camera
+    fn render(&self, body: &[u8], observer: &dyn RenderObserver) -> io::Result<Framebuffer> {
+        let (file, settings) = self.load(body)?;
+        Ok(render_with(&file.scene, &file.camera, &settings, observer))
+    }
+}
+
+// The value of key in a query string such as "a=1&b=2"
+fn query_value<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(key)?.strip_prefix('='))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
@@ -1,20 +1,266 @@
-mod vec3;
-use vec3::Vec3f;
+use crate::bvh::Aabb;
+use crate::lod::View;
+use crate::mesh::{intersect_triangle, TriangleMesh};
+use crate::quartic::solve_quartic;
+use crate::scene::Diagnostic;
+use crate::vec3::{consts::PI, Float, Vec3f};
+
+#[derive(Clone, Copy, Debug)]
+pub struct HitRecord {
+    pub t: Float,
+    pub point: Vec3f,
+    pub normal: Vec3f,
+    // Surface coordinates in [0, 1]^2 for texturing, from shapes that define them
+    pub uv: Option<(Float, Float)>,
+    // A unit direction along the surface for orienting anisotropic highlights, from
+    // shapes with a natural one
+    pub tangent: Option<Vec3f>,
+    // A color the surface carries itself, like a voxel's; tints the diffuse color as a
+    // texture does
+    pub color: Option<Vec3f>,
+    // Whether the ray arrived from the side normal points out of. Shapes report normals
+    // pointing out of them; the scene then turns each to face the ray, so after
+    // Scene::intersect this is what tells entering a solid from leaving it.
+    pub front_face: bool,
+}
+
+impl HitRecord {
+    // Turns the normal to face back along dir, leaving front_face as it was
+    pub fn face(&mut self, dir: &Vec3f) {
+        if self.normal.dot(dir) > 0.0 {
+            self.normal = -self.normal;
+        }
+    }
+}
+
+pub trait Shape: Send + Sync {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord>;
+    fn bounds(&self) -> Aabb;
+
+    // Problems with the shape's own parameters, reported by Scene::validate
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        Vec::new()
+    }
+
+    // Called before each frame with where it is seen from, for shapes that change with
+    // how large they appear
+    fn set_view(&self, _view: &View) {}
+
+    // The shape as triangles placed in the world, for baking light into its texture
+    // coordinates; None for shapes that are not meshes
+    fn mesh(&self) -> Option<TriangleMesh> {
+        None
+    }
+}
+
+// One shape shared by many placements, as scattered instances are
+impl<S: Shape + ?Sized> Shape for std::sync::Arc<S> {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        (**self).hit(orig, dir)
+    }
+
+    fn bounds(&self) -> Aabb {
+        (**self).bounds()
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        (**self).diagnostics()
+    }
+
+    fn set_view(&self, view: &View) {
+        (**self).set_view(view)
+    }
+
+    fn mesh(&self) -> Option<TriangleMesh> {
+        (**self).mesh()
+    }
+}
+
+// Complaints about dimensions that must be finite and positive
+pub(crate) fn check_dimensions(dimensions: &[(&str, Float)]) -> Vec<Diagnostic> {
+    dimensions
+        .iter()
+        .filter(|(_, value)| !(value.is_finite() && *value > 0.0))
+        .map(|(name, value)| {
+            Diagnostic::error(format!(
+                "{} must be finite and positive, got {}",
+                name, value
+            ))
+        })
+        .collect()
+}
+
+pub(crate) fn check_point(name: &str, point: &Vec3f) -> Vec<Diagnostic> {
+    if point.0.is_finite() && point.1.is_finite() && point.2.is_finite() {
+        Vec::new()
+    } else {
+        vec![Diagnostic::error(format!(
+            "{} is not finite: {:?}",
+            name, point
+        ))]
+    }
+}
+
+fn hit_record(orig: &Vec3f, dir: &Vec3f, t: Float, normal: Vec3f) -> HitRecord {
+    let normal = normal.normalized().unwrap_or(Vec3f(0.0, 1.0, 0.0));
+    HitRecord {
+        t,
+        point: *orig + *dir * t,
+        normal,
+        uv: None,
+        tangent: None,
+        color: None,
+        front_face: normal.dot(dir) < 0.0,
+    }
+}
+
+// The direction of travel around the y axis at a point relative to the axis, which is
+// the way u increases on spheres; None on the axis itself
+fn around_y(local: &Vec3f) -> Option<Vec3f> {
+    Vec3f(local.2, 0.0, -local.0).normalized()
+}
 
-struct Sphere {
+// Latitude and longitude of a unit direction as (u, v), the layout of equirectangular
+// maps: u runs once around the y axis starting and ending at -x, where the seam is, and
+// v from 0 at the south pole to 1 at the north pole
+pub fn sphere_uv(direction: &Vec3f) -> (Float, Float) {
+    let theta = (-direction.1).clamp(-1.0, 1.0).acos();
+    let phi = (-direction.2).atan2(direction.0) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+// A mesh over a surface given as position and normal at (u, v) in [0, 1]^2, with columns
+// cells across u and rows up v and the surface's (u, v) as texture coordinates. The
+// column at u = 1 repeats the one at u = 0 rather than sharing its vertices, so
+// coordinates do not wrap back across the seam inside a face. Faces wind outward where
+// the u direction crossed with the v direction points out, and those a pole squeezes to
+// nothing are left out, so poles must come out as exactly the same point.
+fn tessellate<F>(columns: usize, rows: usize, surface: F) -> TriangleMesh
+where
+    F: Fn(Float, Float) -> (Vec3f, Vec3f),
+{
+    let mut vertices = Vec::with_capacity((columns + 1) * (rows + 1));
+    let mut normals = Vec::with_capacity(vertices.capacity());
+    let mut uvs = Vec::with_capacity(vertices.capacity());
+    for row in 0..=rows {
+        for column in 0..=columns {
+            let (u, v) = (
+                column as Float / columns as Float,
+                row as Float / rows as Float,
+            );
+            let (point, normal) = surface(u, v);
+            vertices.push(point);
+            normals.push(normal.normalized().unwrap_or(Vec3f(0.0, 1.0, 0.0)));
+            uvs.push((u, v));
+        }
+    }
+    let index = |row: usize, column: usize| row * (columns + 1) + column;
+    let mut faces = Vec::with_capacity(2 * columns * rows);
+    for row in 0..rows {
+        for column in 0..columns {
+            let a = index(row, column);
+            let b = index(row, column + 1);
+            let c = index(row + 1, column + 1);
+            let d = index(row + 1, column);
+            for face in [[a, b, c], [a, c, d]] {
+                let [v0, v1, v2] = face.map(|i| vertices[i]);
+                if (v1 - v0).cross(&(v2 - v0)).length() > 0.0 {
+                    faces.push(face);
+                }
+            }
+        }
+    }
+    TriangleMesh::with_normals(vertices, normals, faces).with_uvs(uvs)
+}
+
+// The unit direction out from the y axis at u of the way around it, starting from -x as
+// sphere_uv does
+fn around_y_at(u: Float) -> Vec3f {
+    let angle = 2.0 * PI * u - PI;
+    Vec3f(angle.cos(), 0.0, -angle.sin())
+}
+
+// How a box's faces map to texture coordinates. Each face is seen from outside with +y
+// up, or for the top and bottom faces with the front (+z) face below and above them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoxUv {
+    // Every face shows the whole image, as for crates
+    Faces,
+    // The faces unfold into a horizontal cross on a 4x3 grid, as for dice and skyboxes:
+    //
+    //         +y
+    //     -x  +z  +x  -z
+    //         -y
+    Cross,
+}
+
+// The face of an axis-aligned box the local hit point lies on, by its dominant axis, as
+// the axis, its sign and the point scaled to [-1, 1] across the box
+fn box_face(point: &Vec3f, min: &Vec3f, max: &Vec3f) -> (usize, Float, [Float; 3]) {
+    let center = (*min + *max) * 0.5;
+    let half = (*max - *min) * 0.5;
+    let local = *point - center;
+    let scaled = [local.0 / half.0, local.1 / half.1, local.2 / half.2];
+    let axis = (0..3)
+        .max_by(|&a, &b| scaled[a].abs().total_cmp(&scaled[b].abs()))
+        .unwrap_or(0);
+    (axis, scaled[axis].signum(), scaled)
+}
+
+// Picks the face normal of an axis-aligned box by the dominant axis of the local hit point.
+fn box_normal(point: &Vec3f, min: &Vec3f, max: &Vec3f) -> Vec3f {
+    let (axis, sign, _) = box_face(point, min, max);
+    match axis {
+        0 => Vec3f(sign, 0.0, 0.0),
+        1 => Vec3f(0.0, sign, 0.0),
+        _ => Vec3f(0.0, 0.0, sign),
+    }
+}
+
+// The direction u increases in across the face, for either layout
+fn box_tangent(point: &Vec3f, min: &Vec3f, max: &Vec3f) -> Vec3f {
+    match box_face(point, min, max) {
+        (0, sign, _) => Vec3f(0.0, 0.0, -sign),
+        (1, _, _) => Vec3f(1.0, 0.0, 0.0),
+        (_, sign, _) => Vec3f(sign, 0.0, 0.0),
+    }
+}
+
+fn box_uv(point: &Vec3f, min: &Vec3f, max: &Vec3f, layout: BoxUv) -> (Float, Float) {
+    let (axis, sign, [x, y, z]) = box_face(point, min, max);
+    // Coordinates across the face in [-1, 1] and the face's cell in the cross
+    let ((s, t), cell) = match (axis, sign > 0.0) {
+        (0, true) => ((-z, y), (2.0, 1.0)),
+        (0, false) => ((z, y), (0.0, 1.0)),
+        (1, true) => ((x, -z), (1.0, 2.0)),
+        (1, false) => ((x, z), (1.0, 0.0)),
+        (_, true) => ((x, y), (1.0, 1.0)),
+        (_, false) => ((-x, y), (3.0, 1.0)),
+    };
+    let (s, t) = (
+        ((s + 1.0) * 0.5).clamp(0.0, 1.0),
+        ((t + 1.0) * 0.5).clamp(0.0, 1.0),
+    );
+    match layout {
+        BoxUv::Faces => (s, t),
+        BoxUv::Cross => ((cell.0 + s) / 4.0, (cell.1 + t) / 3.0),
+    }
+}
+
+pub struct Sphere {
     center: Vec3f,
-    radius: f32,
+    radius: Float,
 }
 
 impl Sphere {
-    fn new(center: Vec3f, radius: f32) -> Sphere {
+    pub fn new(center: Vec3f, radius: Float) -> Sphere {
         Sphere { center, radius }
     }
 
-    fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<f32> {
-        let l = self.center.subtract(orig);
+    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<Float> {
+        let l = self.center - *orig;
         let tca = l.dot(dir);
-        let d2 = l.magnitude_squared() - tca * tca;
+        let d2 = l.dot(&l) - tca * tca;
         if d2 > self.radius * self.radius {
             return None;
         }
@@ -29,19 +275,69 @@ impl Sphere {
         }
         Some(t0)
     }
+
+    // Triangles approximating the sphere, resolution of them around its equator and half
+    // as many rows between the poles, with the same texture coordinates as the sphere
+    pub fn to_mesh(&self, resolution: usize) -> TriangleMesh {
+        let columns = resolution.max(3);
+        tessellate(columns, (columns / 2).max(2), |u, v| {
+            let direction = unit_sphere_at(u, v);
+            (self.center + direction * self.radius, direction)
+        })
+    }
+}
+
+// The point on the unit sphere with the texture coordinates (u, v) of sphere_uv
+fn unit_sphere_at(u: Float, v: Float) -> Vec3f {
+    if v == 0.0 || v == 1.0 {
+        return Vec3f(0.0, 2.0 * v - 1.0, 0.0);
+    }
+    let theta = PI * v;
+    around_y_at(u) * theta.sin() + Vec3f(0.0, -theta.cos(), 0.0)
+}
+
+impl Shape for Sphere {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        let t = self.ray_intersect(orig, dir)?;
+        let point = *orig + *dir * t;
+        let mut record = hit_record(orig, dir, t, point - self.center);
+        record.uv = Some(sphere_uv(&record.normal));
+        record.tangent = around_y(&record.normal);
+        Some(record)
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::around(self.center, Vec3f(self.radius, self.radius, self.radius))
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut issues = check_point("center", &self.center);
+        issues.extend(check_dimensions(&[("radius", self.radius)]));
+        issues
+    }
 }
 
 pub struct RecgtangularPrism {
     min: Vec3f,
     max: Vec3f,
+    uv: BoxUv,
 }
 
 impl RecgtangularPrism {
     pub fn new(min: Vec3f, max: Vec3f) -> RecgtangularPrism {
-        RecgtangularPrism { min, max }
+        RecgtangularPrism {
+            min,
+            max,
+            uv: BoxUv::Faces,
+        }
     }
 
-    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<f32> {
+    pub fn with_uv_layout(mut self, layout: BoxUv) -> RecgtangularPrism {
+        self.uv = layout;
+        self
+    }
+
+    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<Float> {
         let t1 = (self.min.0 - orig.0) / dir.0;
         let t2 = (self.max.0 - orig.0) / dir.0;
         let t3 = (self.min.1 - orig.1) / dir.1;
@@ -60,14 +356,112 @@ impl RecgtangularPrism {
         Some(t)
     }
 }
+
+impl Shape for RecgtangularPrism {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        let t = self.ray_intersect(orig, dir)?;
+        let point = *orig + *dir * t;
+        let mut record = hit_record(orig, dir, t, box_normal(&point, &self.min, &self.max));
+        record.uv = Some(box_uv(&point, &self.min, &self.max, self.uv));
+        record.tangent = Some(box_tangent(&point, &self.min, &self.max));
+        Some(record)
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::new(self.min, self.max)
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut issues = check_point("min corner", &self.min);
+        issues.extend(check_point("max corner", &self.max));
+        let extent = self.max - self.min;
+        issues.extend(check_dimensions(&[
+            ("width", extent.0),
+            ("height", extent.1),
+            ("depth", extent.2),
+        ]));
+        issues
+    }
+}
+
+// A parallelogram from corner along the edges u and v, for walls and the panels of area
+// lights. It faces the way u crossed with v points, and its texture coordinates run from
+// 0 to 1 along each edge.
+pub struct Quad {
+    corner: Vec3f,
+    u: Vec3f,
+    v: Vec3f,
+}
+
+impl Quad {
+    pub fn new(corner: Vec3f, u: Vec3f, v: Vec3f) -> Quad {
+        Quad { corner, u, v }
+    }
+
+    pub fn area(&self) -> Float {
+        self.u.cross(&self.v).length()
+    }
+
+    // The distance along dir to the quad and how far along u and v it is met there
+    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<(Float, Float, Float)> {
+        let normal = self.u.cross(&self.v);
+        let t = normal.dot(&(self.corner - *orig)) / normal.dot(dir);
+        if !t.is_finite() || t <= 0.0 {
+            return None;
+        }
+        // The offset from the corner split along the edges by the reciprocal basis
+        let offset = *orig + *dir * t - self.corner;
+        let w = normal * (1.0 / normal.dot(&normal));
+        let a = w.dot(&offset.cross(&self.v));
+        let b = w.dot(&self.u.cross(&offset));
+        ((0.0..=1.0).contains(&a) && (0.0..=1.0).contains(&b)).then_some((t, a, b))
+    }
+}
+
+impl Shape for Quad {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        let (t, a, b) = self.ray_intersect(orig, dir)?;
+        let mut record = hit_record(orig, dir, t, self.u.cross(&self.v));
+        record.uv = Some((a, b));
+        record.tangent = self.u.normalized();
+        Some(record)
+    }
+
+    fn bounds(&self) -> Aabb {
+        let far = self.corner + self.u + self.v;
+        Aabb::new(
+            self.corner
+                .min(&far)
+                .min(&(self.corner + self.u))
+                .min(&(self.corner + self.v)),
+            self.corner
+                .max(&far)
+                .max(&(self.corner + self.u))
+                .max(&(self.corner + self.v)),
+        )
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut issues = check_point("corner", &self.corner);
+        issues.extend(check_point("u", &self.u));
+        issues.extend(check_point("v", &self.v));
+        if issues.is_empty() && self.area() <= 0.0 {
+            issues.push(Diagnostic::error(
+                "the edges u and v must be non-zero and not parallel".to_string(),
+            ));
+        }
+        issues
+    }
+}
+
 pub struct Cone {
     apex: Vec3f,
-    height: f32,
-    base_radius: f32,
+    height: Float,
+    base_radius: Float,
 }
 
 impl Cone {
-    pub fn new(apex: Vec3f, height: f32, base_radius: f32) -> Cone {
+    pub fn new(apex: Vec3f, height: Float, base_radius: Float) -> Cone {
         Cone {
             apex,
             height,
@@ -75,7 +469,7 @@ impl Cone {
         }
     }
 
-    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<f32> {
+    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<Float> {
         let k = self.base_radius / self.height;
 
         let a = dir.0 * dir.0 + dir.2 * dir.2 - k * k * dir.1 * dir.1;
@@ -95,8 +489,10 @@ impl Cone {
         let t0 = (-b - discriminant.sqrt()) / (2.0 * a);
         let t1 = (-b + discriminant.sqrt()) / (2.0 * a);
 
-        let valid_t0 = (orig.1 + t0 * dir.1).between(self.apex.1, self.apex.1 + self.height);
-        let valid_t1 = (orig.1 + t1 * dir.1).between(self.apex.1, self.apex.1 + self.height);
+        let valid_t0 =
+            t0 > 0.0 && (orig.1 + t0 * dir.1).between(self.apex.1, self.apex.1 + self.height);
+        let valid_t1 =
+            t1 > 0.0 && (orig.1 + t1 * dir.1).between(self.apex.1, self.apex.1 + self.height);
 
         if valid_t0 && valid_t1 {
             return Some(t0.min(t1));
@@ -108,16 +504,62 @@ impl Cone {
 
         None
     }
+
+    // Triangles approximating the cone's side, which is open like the cone itself,
+    // resolution of them around it
+    pub fn to_mesh(&self, resolution: usize) -> TriangleMesh {
+        let k = self.base_radius / self.height;
+        tessellate(resolution.max(3), 1, |u, v| {
+            let out = around_y_at(u);
+            let point = self.apex + out * (v * self.base_radius) + Vec3f(0.0, v * self.height, 0.0);
+            (point, out + Vec3f(0.0, -k, 0.0))
+        })
+    }
+}
+
+impl Shape for Cone {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        let t = self.ray_intersect(orig, dir)?;
+        let local = *orig + *dir * t - self.apex;
+        let k = self.base_radius / self.height;
+        let mut record = hit_record(orig, dir, t, Vec3f(local.0, -k * k * local.1, local.2));
+        record.tangent = around_y(&local);
+        Some(record)
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::new(
+            Vec3f(
+                self.apex.0 - self.base_radius,
+                self.apex.1,
+                self.apex.2 - self.base_radius,
+            ),
+            Vec3f(
+                self.apex.0 + self.base_radius,
+                self.apex.1 + self.height,
+                self.apex.2 + self.base_radius,
+            ),
+        )
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut issues = check_point("apex", &self.apex);
+        issues.extend(check_dimensions(&[
+            ("height", self.height),
+            ("base radius", self.base_radius),
+        ]));
+        issues
+    }
 }
 
 pub struct Cylinder {
     base_center: Vec3f,
-    height: f32,
-    radius: f32,
+    height: Float,
+    radius: Float,
 }
 
 impl Cylinder {
-    pub fn new(base_center: Vec3f, height: f32, radius: f32) -> Cylinder {
+    pub fn new(base_center: Vec3f, height: Float, radius: Float) -> Cylinder {
         Cylinder {
             base_center,
             height,
@@ -125,7 +567,7 @@ impl Cylinder {
         }
     }
 
-    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<f32> {
+    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<Float> {
         let a = dir.0 * dir.0 + dir.2 * dir.2;
         let b =
             2.0 * (dir.0 * (orig.0 - self.base_center.0) + dir.2 * (orig.2 - self.base_center.2));
@@ -142,10 +584,10 @@ impl Cylinder {
         let t0 = (-b - discriminant.sqrt()) / (2.0 * a);
         let t1 = (-b + discriminant.sqrt()) / (2.0 * a);
 
-        let valid_t0 =
-            (orig.1 + t0 * dir.1).between(self.base_center.1, self.base_center.1 + self.height);
-        let valid_t1 =
-            (orig.1 + t1 * dir.1).between(self.base_center.1, self.base_center.1 + self.height);
+        let valid_t0 = t0 > 0.0
+            && (orig.1 + t0 * dir.1).between(self.base_center.1, self.base_center.1 + self.height);
+        let valid_t1 = t1 > 0.0
+            && (orig.1 + t1 * dir.1).between(self.base_center.1, self.base_center.1 + self.height);
 
         if valid_t0 && valid_t1 {
             return Some(t0.min(t1));
@@ -157,16 +599,60 @@ impl Cylinder {
 
         None
     }
+
+    // Triangles approximating the cylinder's side, which is open like the cylinder
+    // itself, resolution of them around it
+    pub fn to_mesh(&self, resolution: usize) -> TriangleMesh {
+        tessellate(resolution.max(3), 1, |u, v| {
+            let out = around_y_at(u);
+            let point = self.base_center + out * self.radius + Vec3f(0.0, v * self.height, 0.0);
+            (point, out)
+        })
+    }
+}
+
+impl Shape for Cylinder {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        let t = self.ray_intersect(orig, dir)?;
+        let local = *orig + *dir * t - self.base_center;
+        let mut record = hit_record(orig, dir, t, Vec3f(local.0, 0.0, local.2));
+        record.tangent = around_y(&local);
+        Some(record)
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::new(
+            Vec3f(
+                self.base_center.0 - self.radius,
+                self.base_center.1,
+                self.base_center.2 - self.radius,
+            ),
+            Vec3f(
+                self.base_center.0 + self.radius,
+                self.base_center.1 + self.height,
+                self.base_center.2 + self.radius,
+            ),
+        )
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut issues = check_point("base center", &self.base_center);
+        issues.extend(check_dimensions(&[
+            ("height", self.height),
+            ("radius", self.radius),
+        ]));
+        issues
+    }
 }
 
 pub struct Pyramid {
     base_center: Vec3f,
-    height: f32,
-    half_base_length: f32,
+    height: Float,
+    half_base_length: Float,
 }
 
 impl Pyramid {
-    pub fn new(base_center: Vec3f, height: f32, half_base_length: f32) -> Pyramid {
+    pub fn new(base_center: Vec3f, height: Float, half_base_length: Float) -> Pyramid {
         Pyramid {
             base_center,
             height,
@@ -174,34 +660,61 @@ impl Pyramid {
         }
     }
 
-    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<f32> {
-        let epsilon = 1e-6;
-
-        // Intersection with base square
-        let t_base = (self.base_center.1 - orig.1) / dir.1;
-        if t_base >= 0.0 {
-            let x = orig.0 + t_base * dir.0;
-            let z = orig.2 + t_base * dir.2;
-            if x.between(
-                self.base_center.0 - self.half_base_length,
-                self.base_center.0 + self.half_base_length,
-            ) && z.between(
-                self.base_center.2 - self.half_base_length,
-                self.base_center.2 + self.half_base_length,
-            ) {
-                return Some(t_base);
-            }
-        }
+    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<Float> {
+        self.intersect(orig, dir).map(|(t, _)| t)
+    }
 
+    fn intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<(Float, Vec3f)> {
         let apex = Vec3f(
             self.base_center.0,
             self.base_center.1 + self.height,
             self.base_center.2,
         );
-
-        // Möller–Trumbore intersection algorithm for triangles
-        let mut best_t = std::f32::MAX;
+        let h = self.half_base_length;
+        let c = self.base_center;
         let base_points = [
+            Vec3f(c.0 - h, c.1, c.2 - h),
+            Vec3f(c.0 + h, c.1, c.2 - h),
+            Vec3f(c.0 + h, c.1, c.2 + h),
+            Vec3f(c.0 - h, c.1, c.2 + h),
+        ];
+
+        // Sides and base share their edges exactly, so the watertight test leaves no gaps
+        // With this base winding edge2 x edge1 faces out of the pyramid
+        let side = |i: usize| {
+            let (v1, v2) = (base_points[i], base_points[(i + 1) % 4]);
+            ([apex, v1, v2], (v2 - apex).cross(&(v1 - apex)))
+        };
+        let down = Vec3f(0.0, -1.0, 0.0);
+        let faces = [
+            side(0),
+            side(1),
+            side(2),
+            side(3),
+            ([base_points[0], base_points[1], base_points[2]], down),
+            ([base_points[0], base_points[2], base_points[3]], down),
+        ];
+
+        let mut best: Option<(Float, Vec3f)> = None;
+        for ([v0, v1, v2], normal) in faces {
+            if let Some((t, _)) = intersect_triangle(orig, dir, &v0, &v1, &v2) {
+                if best.is_none_or(|(best_t, _)| t < best_t) {
+                    best = Some((t, normal));
+                }
+            }
+        }
+        best
+    }
+}
+
+impl Shape for Pyramid {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        let (t, normal) = self.intersect(orig, dir)?;
+        Some(hit_record(orig, dir, t, normal))
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::new(
             Vec3f(
                 self.base_center.0 - self.half_base_length,
                 self.base_center.1,
@@ -209,78 +722,43 @@ impl Pyramid {
             ),
             Vec3f(
                 self.base_center.0 + self.half_base_length,
-                self.base_center.1,
-                self.base_center.2 - self.half_base_length,
-            ),
-            Vec3f(
-                self.base_center.0 + self.half_base_length,
-                self.base_center.1,
-                self.base_center.2 + self.half_base_length,
-            ),
-            Vec3f(
-                self.base_center.0 - self.half_base_length,
-                self.base_center.1,
+                self.base_center.1 + self.height,
                 self.base_center.2 + self.half_base_length,
             ),
-        ];
-
-        for i in 0..4 {
-            let v0 = apex;
-            let v1 = base_points[i];
-            let v2 = base_points[(i + 1) % 4];
-
-            let edge1 = v1.subtract(&v0);
-            let edge2 = v2.subtract(&v0);
-            let h = dir.cross(&edge2);
-            let a = edge1.dot(&h);
-
-            if a > -epsilon && a < epsilon {
-                continue; // Ray is parallel to triangle
-            }
-
-            let f = 1.0 / a;
-            let s = orig.subtract(&v0);
-            let u = f * s.dot(&h);
-
-            if u < 0.0 || u > 1.0 {
-                continue;
-            }
-
-            let q = s.cross(&edge1);
-            let v = f * dir.dot(&q);
-
-            if v < 0.0 || u + v > 1.0 {
-                continue;
-            }
-
-            let t = f * edge2.dot(&q);
-            if t > epsilon && t < best_t {
-                best_t = t;
-            }
-        }
-
-        if best_t < std::f32::MAX {
-            return Some(best_t);
-        }
+        )
+    }
 
-        None
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut issues = check_point("base center", &self.base_center);
+        issues.extend(check_dimensions(&[
+            ("height", self.height),
+            ("half base length", self.half_base_length),
+        ]));
+        issues
     }
 }
 
 pub struct Cube {
     center: Vec3f,
-    side_length: f32,
+    side_length: Float,
+    uv: BoxUv,
 }
 
 impl Cube {
-    pub fn new(center: Vec3f, side_length: f32) -> Cube {
+    pub fn new(center: Vec3f, side_length: Float) -> Cube {
         Cube {
             center,
             side_length,
+            uv: BoxUv::Faces,
         }
     }
 
-    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<f32> {
+    pub fn with_uv_layout(mut self, layout: BoxUv) -> Cube {
+        self.uv = layout;
+        self
+    }
+
+    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<Float> {
         let half_side = self.side_length / 2.0;
         let min = Vec3f(
             self.center.0 - half_side,
@@ -312,6 +790,29 @@ impl Cube {
     }
 }
 
+impl Shape for Cube {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        let t = self.ray_intersect(orig, dir)?;
+        let point = *orig + *dir * t;
+        let bounds = self.bounds();
+        let mut record = hit_record(orig, dir, t, box_normal(&point, &bounds.min, &bounds.max));
+        record.uv = Some(box_uv(&point, &bounds.min, &bounds.max, self.uv));
+        record.tangent = Some(box_tangent(&point, &bounds.min, &bounds.max));
+        Some(record)
+    }
+
+    fn bounds(&self) -> Aabb {
+        let half_side = self.side_length / 2.0;
+        Aabb::around(self.center, Vec3f(half_side, half_side, half_side))
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut issues = check_point("center", &self.center);
+        issues.extend(check_dimensions(&[("side length", self.side_length)]));
+        issues
+    }
+}
+
 pub struct Ovoid {
     center: Vec3f,
     radii: Vec3f,
@@ -322,7 +823,7 @@ impl Ovoid {
         Ovoid { center, radii }
     }
 
-    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<f32> {
+    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<Float> {
         let dir_normalized = Vec3f(
             dir.0 / self.radii.0,
             dir.1 / self.radii.1,
@@ -353,23 +854,67 @@ impl Ovoid {
 
         let t0 = (-b - discriminant.sqrt()) / (2.0 * a);
         let t1 = (-b + discriminant.sqrt()) / (2.0 * a);
+        let (t0, t1) = if t0 > t1 { (t1, t0) } else { (t0, t1) };
 
-        if t0 > t1 {
+        if t0 > 0.0 {
+            return Some(t0);
+        } else if t1 > 0.0 {
             return Some(t1);
         }
 
-        Some(t0)
+        None
+    }
+
+    // Triangles approximating the ovoid as a sphere's would, stretched along its radii,
+    // with the sphere's texture coordinates
+    pub fn to_mesh(&self, resolution: usize) -> TriangleMesh {
+        let columns = resolution.max(3);
+        let r = self.radii;
+        tessellate(columns, (columns / 2).max(2), |u, v| {
+            let d = unit_sphere_at(u, v);
+            (
+                self.center + d.multiply(&r),
+                Vec3f(d.0 / r.0, d.1 / r.1, d.2 / r.2),
+            )
+        })
+    }
+}
+
+impl Shape for Ovoid {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        let t = self.ray_intersect(orig, dir)?;
+        let local = *orig + *dir * t - self.center;
+        let r2 = self.radii.multiply(&self.radii);
+        let normal = Vec3f(local.0 / r2.0, local.1 / r2.1, local.2 / r2.2);
+        let mut record = hit_record(orig, dir, t, normal);
+        let (rx, rz) = (self.radii.0, self.radii.2);
+        record.tangent = Vec3f(local.2 * rx / rz, 0.0, -local.0 * rz / rx).normalized();
+        Some(record)
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::around(self.center, self.radii)
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut issues = check_point("center", &self.center);
+        issues.extend(check_dimensions(&[
+            ("x radius", self.radii.0),
+            ("y radius", self.radii.1),
+            ("z radius", self.radii.2),
+        ]));
+        issues
     }
 }
 
 pub struct Torus {
     center: Vec3f,
-    tube_radius: f32,
-    torus_radius: f32,
+    tube_radius: Float,
+    torus_radius: Float,
 }
 
 impl Torus {
-    pub fn new(center: Vec3f, tube_radius: f32, torus_radius: f32) -> Torus {
+    pub fn new(center: Vec3f, tube_radius: Float, torus_radius: Float) -> Torus {
         Torus {
             center,
             tube_radius,
@@ -377,33 +922,31 @@ impl Torus {
         }
     }
 
-    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<f32> {
-        let p = orig.subtract(&self.center);
+    // The torus lies in the XZ plane, revolving around the Y axis through its center.
+    pub fn ray_intersect(&self, orig: &Vec3f, dir: &Vec3f) -> Option<Float> {
+        let p = *orig - self.center;
 
-        let x = p.0;
-        let y = p.1;
-        let z = p.2;
-        let xd = dir.0;
-        let yd = dir.1;
-        let zd = dir.2;
+        let r2 = self.torus_radius * self.torus_radius;
+        let a2 = self.tube_radius * self.tube_radius;
+
+        let dd = dir.dot(dir);
+        let pd = p.dot(dir);
+        let k = p.dot(&p) + r2 - a2;
 
-        let c2 = self.torus_radius;
-        let a2 = self.tube_radius;
-        
         let coeffs = [
-            1.0,
-            4.0 * (x * xd + y * yd),
-            4.0 * (x * x + y * y) + 2.0 * (xd * xd + yd * yd) - a2 + c2 - 2.0 * c2 * zd * zd,
-            4.0 * (x * x * xd + y * y * yd) - 4.0 * a2 * zd,
-            x * x * x * x - 2.0 * a2 * (c2 - z * z) + (x * x + y * y + z * z + c2 - a2) * (x * x + y * y + z * z + c2 - a2),
+            dd * dd,
+            4.0 * dd * pd,
+            2.0 * dd * k + 4.0 * pd * pd - 4.0 * r2 * (dir.0 * dir.0 + dir.2 * dir.2),
+            4.0 * pd * k - 8.0 * r2 * (p.0 * dir.0 + p.2 * dir.2),
+            k * k - 4.0 * r2 * (p.0 * p.0 + p.2 * p.2),
         ];
 
         let roots = solve_quartic(&coeffs);
 
         // Choose the smallest positive root if there are any
         let mut min_root = None;
-        for root in roots {
-            if root > 0.0 {
+        for &root in roots.iter() {
+            if root > 1e-4 {
                 min_root = Some(if let Some(current_min) = min_root {
                     root.min(current_min)
                 } else {
@@ -413,15 +956,58 @@ impl Torus {
         }
         min_root
     }
+
+    // Triangles approximating the torus, resolution of them around the ring and half as
+    // many around the tube; u runs around the ring from -x like a sphere's, and v around
+    // the tube from its inner edge
+    pub fn to_mesh(&self, resolution: usize) -> TriangleMesh {
+        let columns = resolution.max(3);
+        tessellate(columns, (columns / 2).max(3), |u, v| {
+            let out = around_y_at(u);
+            let angle = 2.0 * PI * v - PI;
+            let normal = out * angle.cos() + Vec3f(0.0, angle.sin(), 0.0);
+            (
+                self.center + out * self.torus_radius + normal * self.tube_radius,
+                normal,
+            )
+        })
+    }
 }
 
+impl Shape for Torus {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        let t = self.ray_intersect(orig, dir)?;
+        let p = *orig + *dir * t - self.center;
+        let r2 = self.torus_radius * self.torus_radius;
+        let a2 = self.tube_radius * self.tube_radius;
+        let k = p.dot(&p) + r2 - a2;
+        let normal = p * k - Vec3f(p.0, 0.0, p.2) * (2.0 * r2);
+        let mut record = hit_record(orig, dir, t, normal);
+        record.tangent = around_y(&p);
+        Some(record)
+    }
+
+    fn bounds(&self) -> Aabb {
+        let outer = self.torus_radius + self.tube_radius;
+        Aabb::around(self.center, Vec3f(outer, self.tube_radius, outer))
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut issues = check_point("center", &self.center);
+        issues.extend(check_dimensions(&[
+            ("tube radius", self.tube_radius),
+            ("torus radius", self.torus_radius),
+        ]));
+        issues
+    }
+}
 
 trait Between {
-    fn between(self, min: f32, max: f32) -> bool;
+    fn between(self, min: Float, max: Float) -> bool;
 }
 
-impl Between for f32 {
-    fn between(self, min: f32, max: f32) -> bool {
+impl Between for Float {
+    fn between(self, min: Float, max: Float) -> bool {
         self >= min && self <= max
     }
 }
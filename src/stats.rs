@@ -0,0 +1,152 @@
+use std::cell::Cell;
+#[cfg(feature = "stats")]
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::AddAssign;
+
+use crate::scene::RayKind;
+
+// Work done while rendering, counted per thread without locking and summed when the
+// workers finish
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RayStats {
+    pub camera_rays: u64,
+    // Reflection, refraction, bounce and medium scatter rays
+    pub secondary_rays: u64,
+    pub shadow_rays: u64,
+    // Bounding boxes tested, including those inside mesh BVHs
+    pub bvh_node_tests: u64,
+}
+
+impl RayStats {
+    pub const ZERO: RayStats = RayStats {
+        camera_rays: 0,
+        secondary_rays: 0,
+        shadow_rays: 0,
+        bvh_node_tests: 0,
+    };
+
+    pub fn total_rays(&self) -> u64 {
+        self.camera_rays + self.secondary_rays + self.shadow_rays
+    }
+
+    // Secondary rays per camera ray
+    pub fn average_bounces(&self) -> f64 {
+        ratio(self.secondary_rays, self.camera_rays)
+    }
+
+    pub fn shadow_fraction(&self) -> f64 {
+        ratio(self.shadow_rays, self.total_rays())
+    }
+
+    pub fn node_tests_per_ray(&self) -> f64 {
+        ratio(self.bvh_node_tests, self.total_rays())
+    }
+}
+
+impl AddAssign for RayStats {
+    fn add_assign(&mut self, other: RayStats) {
+        self.camera_rays += other.camera_rays;
+        self.secondary_rays += other.secondary_rays;
+        self.shadow_rays += other.shadow_rays;
+        self.bvh_node_tests += other.bvh_node_tests;
+    }
+}
+
+fn ratio(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+thread_local! {
+    static COUNTS: Cell<RayStats> = const { Cell::new(RayStats::ZERO) };
+}
+
+pub(crate) fn record(update: impl FnOnce(&mut RayStats)) {
+    COUNTS.with(|counts| {
+        let mut current = counts.get();
+        update(&mut current);
+        counts.set(current);
+    });
+}
+
+// This thread's counts since the last take, resetting them
+pub(crate) fn take() -> RayStats {
+    COUNTS.replace(RayStats::ZERO)
+}
+
+// Rays that ended on one object, the nearest thing along them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ObjectHits {
+    // Camera rays, so nonzero for exactly the objects seen directly in the frame
+    pub camera: u64,
+    pub secondary: u64,
+    // Shadow rays the object blocked
+    pub shadow: u64,
+}
+
+impl ObjectHits {
+    pub fn total(&self) -> u64 {
+        self.camera + self.secondary + self.shadow
+    }
+}
+
+impl AddAssign for ObjectHits {
+    fn add_assign(&mut self, other: ObjectHits) {
+        self.camera += other.camera;
+        self.secondary += other.secondary;
+        self.shadow += other.shadow;
+    }
+}
+
+// Hits by object ID, the floor's under FLOOR_ID. Only counted when built with the stats
+// feature, as it costs a map update per ray; otherwise it stays empty. Objects no ray
+// ever ends on add nothing to the image but their cost, and can usually be dropped.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ObjectStats {
+    pub hits: BTreeMap<u32, ObjectHits>,
+}
+
+impl ObjectStats {
+    pub fn get(&self, id: u32) -> ObjectHits {
+        self.hits.get(&id).copied().unwrap_or_default()
+    }
+}
+
+impl AddAssign<&ObjectStats> for ObjectStats {
+    fn add_assign(&mut self, other: &ObjectStats) {
+        for (id, hits) in &other.hits {
+            *self.hits.entry(*id).or_default() += *hits;
+        }
+    }
+}
+
+#[cfg(feature = "stats")]
+thread_local! {
+    static OBJECT_HITS: RefCell<ObjectStats> = RefCell::new(ObjectStats::default());
+}
+
+#[cfg_attr(not(feature = "stats"), allow(unused_variables))]
+pub(crate) fn record_hit(id: u32, kind: RayKind) {
+    #[cfg(feature = "stats")]
+    OBJECT_HITS.with(|stats| {
+        let mut stats = stats.borrow_mut();
+        let hits = stats.hits.entry(id).or_default();
+        match kind {
+            RayKind::Camera => hits.camera += 1,
+            RayKind::Shadow => hits.shadow += 1,
+            RayKind::Reflection => hits.secondary += 1,
+        }
+    });
+}
+
+// This thread's object hits since the last take, resetting them
+pub(crate) fn take_object_hits() -> ObjectStats {
+    #[cfg(feature = "stats")]
+    return OBJECT_HITS.take();
+    #[cfg(not(feature = "stats"))]
+    ObjectStats::default()
+}
@@ -0,0 +1,240 @@
+// Renders shown in the terminal itself, for checking a render over ssh without copying the
+// image back. Any terminal with 24-bit color shows half blocks: each character cell is an
+// upper half block whose foreground color is one pixel and background the pixel below it,
+// so cells, about twice as tall as they are wide, hold square pixels. Terminals with a
+// graphics protocol show the image itself inline: SIXEL, a palette of 216 colors ordered-
+// dithered into bands six pixels tall, or the PNG passed whole by iTerm2's or kitty's.
+use std::collections::HashMap;
+use std::io;
+
+use crate::framebuffer::Framebuffer;
+use crate::vec3::{Float, Vec3f};
+
+// The upper half block, and the escape back to the terminal's own colors
+const CELL: &str = "\u{2580}";
+const RESET: &str = "\x1b[0m";
+
+// The most base64 kitty takes in one escape
+const KITTY_CHUNK: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Sixel,
+    Iterm,
+    Kitty,
+}
+
+impl Protocol {
+    pub const NAMES: [(&'static str, Protocol); 3] = [
+        ("sixel", Protocol::Sixel),
+        ("iterm", Protocol::Iterm),
+        ("kitty", Protocol::Kitty),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Protocol> {
+        Protocol::NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(_, protocol)| protocol)
+    }
+}
+
+// The image shrunk to fit columns by rows character cells, keeping its shape, and never
+// enlarged; each line ends by resetting the colors
+pub fn half_blocks(image: &Framebuffer, columns: usize, rows: usize) -> String {
+    let mut text = String::new();
+    if image.pixels.is_empty() {
+        return text;
+    }
+    let (across, down) = fit(image, columns, rows.saturating_mul(2));
+    let small = shrink(image, across, down.div_ceil(2) * 2);
+    for row in (0..small.height).step_by(2) {
+        for column in 0..small.width {
+            let [r, g, b] = bytes(small.get(column, row));
+            let [br, bg, bb] = bytes(small.get(column, row + 1));
+            text.push_str(&format!(
+                "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m{}",
+                r, g, b, br, bg, bb, CELL
+            ));
+        }
+        text.push_str(RESET);
+        text.push('\n');
+    }
+    text
+}
+
+// The escapes that show the image inline, shrunk to fit within pixels when the terminal's
+// size in pixels is known; the cursor ends up on the line below it
+pub fn inline_image(
+    image: &Framebuffer,
+    protocol: Protocol,
+    pixels: Option<(usize, usize)>,
+) -> io::Result<String> {
+    let image = match pixels {
+        Some((width, height)) if image.width > width || image.height > height => {
+            let (across, down) = fit(image, width, height);
+            shrink(image, across, down)
+        }
+        _ => image.clone(),
+    };
+    if protocol == Protocol::Sixel {
+        return Ok(sixel(&image));
+    }
+    let mut png = Vec::new();
+    image.encode_png(&mut png)?;
+    let data = base64(&png);
+    let mut text = String::new();
+    if protocol == Protocol::Iterm {
+        text.push_str(&format!(
+            "\x1b]1337;File=inline=1;size={};preserveAspectRatio=1:{}\x07",
+            png.len(),
+            data
+        ));
+    } else {
+        // Kitty takes the PNG in pieces, each saying whether more follow
+        let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let more = u8::from(i + 1 < chunks.len());
+            let keys = if i == 0 {
+                format!("f=100,a=T,m={}", more)
+            } else {
+                format!("m={}", more)
+            };
+            let chunk = std::str::from_utf8(chunk).expect("base64 is ASCII");
+            text.push_str(&format!("\x1b_G{};{}\x1b\\", keys, chunk));
+        }
+    }
+    text.push('\n');
+    Ok(text)
+}
+
+// The size image shrinks to, keeping its shape, to fit within width by height; never more
+// than its own
+fn fit(image: &Framebuffer, width: usize, height: usize) -> (usize, usize) {
+    let (w, h) = (image.width.max(1), image.height.max(1));
+    let scale = (width as Float / w as Float)
+        .min(height as Float / h as Float)
+        .min(1.0);
+    (
+        ((w as Float * scale).round() as usize).clamp(1, w),
+        ((h as Float * scale).round() as usize).clamp(1, h),
+    )
+}
+
+// The image averaged down to width by height, each pixel the mean of the box under it
+fn shrink(image: &Framebuffer, width: usize, height: usize) -> Framebuffer {
+    let mut small = Framebuffer::new(width, height);
+    for y in 0..height {
+        let rows = span(y, height, image.height);
+        for x in 0..width {
+            small.set(x, y, mean(image, span(x, width, image.width), rows));
+        }
+    }
+    small
+}
+
+// The pixels along a side size long that the ith of count slices across it covers, at
+// least one
+fn span(i: usize, count: usize, size: usize) -> (usize, usize) {
+    let start = (i * size / count).min(size - 1);
+    (start, ((i + 1) * size / count).clamp(start + 1, size))
+}
+
+// The average color over columns x.0..x.1 and rows y.0..y.1
+fn mean(image: &Framebuffer, x: (usize, usize), y: (usize, usize)) -> Vec3f {
+    let mut sum = Vec3f(0.0, 0.0, 0.0);
+    for row in y.0..y.1 {
+        for column in x.0..x.1 {
+            sum += image.get(column, row);
+        }
+    }
+    sum * (1.0 / ((x.1 - x.0) * (y.1 - y.0)) as Float)
+}
+
+fn bytes(color: Vec3f) -> [u8; 3] {
+    [color.0, color.1, color.2].map(|v| (255.0 * v.clamp(0.0, 1.0)) as u8)
+}
+
+// Each six rows of pixels as a band of characters whose low six bits say which of the
+// six pixels above them take the current color, one pass over the band per color used
+fn sixel(image: &Framebuffer) -> String {
+    const BAYER: [[Float; 4]; 4] = [
+        [0.0, 8.0, 2.0, 10.0],
+        [12.0, 4.0, 14.0, 6.0],
+        [3.0, 11.0, 1.0, 9.0],
+        [15.0, 7.0, 13.0, 5.0],
+    ];
+    let (width, height) = (image.width, image.height);
+    let mut text = format!("\x1bPq\"1;1;{};{}", width, height);
+    // Six levels of each channel, as percentages
+    for index in 0..216 {
+        let level = |step: usize| step * 100 / 5;
+        text.push_str(&format!(
+            "#{};2;{};{};{}",
+            index,
+            level(index / 36),
+            level(index / 6 % 6),
+            level(index % 6)
+        ));
+    }
+    for top in (0..height).step_by(6) {
+        // Per color in the band, the bits of each column
+        let mut colors: HashMap<usize, Vec<u8>> = HashMap::new();
+        for y in top..(top + 6).min(height) {
+            for x in 0..width {
+                let threshold = (BAYER[y % 4][x % 4] + 0.5) / 16.0;
+                let color = image.get(x, y);
+                let step = |v: Float| ((v.clamp(0.0, 1.0) * 5.0 + threshold) as usize).min(5);
+                let index = step(color.0) * 36 + step(color.1) * 6 + step(color.2);
+                colors.entry(index).or_insert_with(|| vec![0; width])[x] |= 1 << (y - top);
+            }
+        }
+        let mut indices: Vec<usize> = colors.keys().copied().collect();
+        indices.sort_unstable();
+        for (i, index) in indices.iter().enumerate() {
+            if i > 0 {
+                // Back to the start of the band for the next color
+                text.push('$');
+            }
+            text.push_str(&format!("#{}", index));
+            let bits = &colors[index];
+            let used = bits
+                .iter()
+                .rposition(|&b| b != 0)
+                .map_or(0, |last| last + 1);
+            let mut x = 0;
+            while x < used {
+                let run = bits[x..used].iter().take_while(|&&b| b == bits[x]).count();
+                let character = char::from(63 + bits[x]);
+                if run > 3 {
+                    text.push_str(&format!("!{}{}", run, character));
+                } else {
+                    text.extend(std::iter::repeat_n(character, run));
+                }
+                x += run;
+            }
+        }
+        text.push('-');
+    }
+    text.push_str("\x1b\\\n");
+    text
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
@@ -0,0 +1,478 @@
+// Text as solid geometry: each glyph's outline from a TrueType font filled in as a flat
+// face and pushed back into a slab, optionally with its front and back edges chamfered.
+// Lines run along +x from the origin with the first baseline through it and later lines
+// below, and the front faces +z through the origin with the slab extending back depth. Holes in
+// letters like "o" are found from which outlines lie inside which, so fonts that wind
+// their contours either way come out the same.
+use std::io;
+
+use crate::font::{Contour, Font};
+use crate::mesh::TriangleMesh;
+use crate::vec3::{Float, Vec3f};
+
+// Walls whose faces turn by more than this, in cosine, meet at a crease instead of being shaded smooth
+const CREASE: Float = 0.85;
+// How far past the bevel a sharp corner's inset may reach
+const MITER_LIMIT: Float = 3.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Text3D {
+    // Where the first line's baseline starts
+    pub origin: Vec3f,
+    // The font's em square in scene units, about the distance between lines
+    pub size: Float,
+    pub depth: Float,
+    // How far the front and back edges are cut back at 45 degrees, up to half the depth;
+    // it needs to be well under the width of the strokes
+    pub bevel: Float,
+    // Straight pieces each curve of an outline is cut into
+    pub curve_segments: usize,
+}
+
+impl Default for Text3D {
+    fn default() -> Text3D {
+        Text3D {
+            origin: Vec3f(0.0, 0.0, 0.0),
+            size: 1.0,
+            depth: 0.2,
+            bevel: 0.0,
+            curve_segments: 8,
+        }
+    }
+}
+
+impl Text3D {
+    // Characters the font lacks show as its missing-glyph box
+    pub fn to_mesh(&self, font: &Font, text: &str) -> io::Result<TriangleMesh> {
+        let mut builder = Builder {
+            origin: self.origin,
+            vertices: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            faces: Vec::new(),
+        };
+        let bevel = self.bevel.clamp(0.0, self.depth * 0.5);
+        let line_height = (font.ascender - font.descender + font.line_gap) * self.size;
+        for (line, characters) in text.lines().enumerate() {
+            let mut pen = 0.0;
+            let baseline = -(line as Float) * line_height;
+            for c in characters.chars() {
+                let glyph = font.glyph(c).unwrap_or(0);
+                let contours: Vec<Contour> = font
+                    .outline(glyph, self.curve_segments)?
+                    .into_iter()
+                    .map(|contour| {
+                        contour
+                            .iter()
+                            .map(|&(x, y)| (pen + x * self.size, baseline + y * self.size))
+                            .collect()
+                    })
+                    .collect();
+                builder.glyph(contours, self.depth, bevel);
+                pen += font.advance(glyph) * self.size;
+            }
+        }
+        Ok(
+            TriangleMesh::with_normals(builder.vertices, builder.normals, builder.faces)
+                .with_uvs(builder.uvs),
+        )
+    }
+}
+
+struct Builder {
+    origin: Vec3f,
+    vertices: Vec<Vec3f>,
+    normals: Vec<Vec3f>,
+    // Across the faces along x and y, and on the walls along the outline and back from
+    // the front, all in scene units
+    uvs: Vec<(Float, Float)>,
+    faces: Vec<[usize; 3]>,
+}
+
+impl Builder {
+    fn vertex(&mut self, point: Vec3f, normal: Vec3f, uv: (Float, Float)) -> usize {
+        self.vertices.push(point + self.origin);
+        self.normals.push(normal);
+        self.uvs.push(uv);
+        self.vertices.len() - 1
+    }
+
+    fn glyph(&mut self, mut contours: Vec<Contour>, depth: Float, bevel: Float) {
+        for (outer, holes) in nest(&mut contours) {
+            let shape: Vec<usize> = std::iter::once(outer)
+                .chain(holes.iter().copied())
+                .collect();
+            let inset: Vec<Contour> = shape.iter().map(|&i| inset(&contours[i], bevel)).collect();
+            // The faces are triangulated from the outline itself, which unlike an inset
+            // one never crosses itself, and the triangles then moved onto the inset
+            // points; any the move turns over are left out
+            let outline: Vec<(Float, Float)> = shape
+                .iter()
+                .flat_map(|&i| contours[i].iter().copied())
+                .collect();
+            let points: Vec<(Float, Float)> = inset.iter().flatten().copied().collect();
+            let mut polygons = Vec::with_capacity(shape.len());
+            let mut start = 0;
+            for &i in &shape {
+                polygons.push((start..start + contours[i].len()).collect::<Vec<usize>>());
+                start += contours[i].len();
+            }
+            let holes_indices = polygons.split_off(1);
+            let polygon = bridge(&outline, polygons.remove(0), holes_indices);
+            let triangles: Vec<[usize; 3]> = triangulate(&outline, polygon)
+                .into_iter()
+                .filter(|&[a, b, c]| cross(points[a], points[b], points[c]) > 0.0)
+                .collect();
+            // Front and back faces, the back wound the other way to face out
+            for (z, normal) in [(0.0, 1.0), (-depth, -1.0)] {
+                let base = self.vertices.len();
+                for &(x, y) in &points {
+                    self.vertex(Vec3f(x, y, z), Vec3f(0.0, 0.0, normal), (x, y));
+                }
+                for &[a, b, c] in &triangles {
+                    self.faces.push(if normal > 0.0 {
+                        [base + a, base + b, base + c]
+                    } else {
+                        [base + a, base + c, base + b]
+                    });
+                }
+            }
+            for (k, &i) in shape.iter().enumerate() {
+                self.walls(&contours[i], &inset[k], depth, bevel);
+            }
+        }
+    }
+
+    // The sides of the slab around one contour, from its inset outline on the front face
+    // out to the contour itself and back in to the inset outline on the back face
+    fn walls(&mut self, contour: &Contour, inset: &Contour, depth: Float, bevel: Float) {
+        let n = contour.len();
+        let outward: Vec<(Float, Float)> = (0..n)
+            .map(|i| {
+                let (a, b) = (contour[i], contour[(i + 1) % n]);
+                unit((b.1 - a.1, a.0 - b.0))
+            })
+            .collect();
+        // Distance along the outline to the start of each edge
+        let mut along = vec![0.0; n + 1];
+        for i in 0..n {
+            let (a, b) = (contour[i], contour[(i + 1) % n]);
+            along[i + 1] = along[i] + ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+        }
+        // Each end of each edge shaded smooth with the edge beyond it unless they meet at
+        // a crease
+        let joint = |edge: usize, other: usize| {
+            let (e, o) = (outward[edge], outward[other]);
+            if e.0 * o.0 + e.1 * o.1 > CREASE {
+                unit((e.0 + o.0, e.1 + o.1))
+            } else {
+                e
+            }
+        };
+        // Rings from front to back: (outline, z, how much the normal leans forwards)
+        let mut rings: Vec<(&Contour, Float, Float)> = Vec::new();
+        if bevel > 0.0 {
+            rings.push((inset, 0.0, 1.0));
+            rings.push((contour, -bevel, 1.0));
+            rings.push((contour, bevel - depth, -1.0));
+            rings.push((inset, -depth, -1.0));
+        } else {
+            rings.push((contour, 0.0, 0.0));
+            rings.push((contour, -depth, 0.0));
+        }
+        for band in rings.windows(2) {
+            let [(front, z0, lean0), (back, z1, lean1)] = [band[0], band[1]];
+            // A band's faces lean the way both of its rings agree on, and walls stand upright
+            let lean = if lean0 == lean1 { lean0 } else { 0.0 };
+            for i in 0..n {
+                let j = (i + 1) % n;
+                let normal = |(x, y): (Float, Float)| {
+                    Vec3f(x, y, lean)
+                        .normalized()
+                        .unwrap_or(Vec3f(0.0, 0.0, lean))
+                };
+                let (start, end) = (normal(joint(i, (i + n - 1) % n)), normal(joint(i, j)));
+                let corners = [
+                    (front[i], z0, start, along[i]),
+                    (back[i], z1, start, along[i]),
+                    (back[j], z1, end, along[i + 1]),
+                    (front[j], z0, end, along[i + 1]),
+                ];
+                let [a, b, c, d] = corners
+                    .map(|((x, y), z, normal, u)| self.vertex(Vec3f(x, y, z), normal, (u, -z)));
+                // Where the inset shrank an edge to a point, half the band has no area
+                for face in [[a, b, c], [a, c, d]] {
+                    let [p0, p1, p2] = face.map(|k| self.vertices[k]);
+                    if (p1 - p0).cross(&(p2 - p0)).length() > 0.0 {
+                        self.faces.push(face);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn unit((x, y): (Float, Float)) -> (Float, Float) {
+    let length = (x * x + y * y).sqrt();
+    if length > 0.0 {
+        (x / length, y / length)
+    } else {
+        (0.0, 0.0)
+    }
+}
+
+fn signed_area(contour: &Contour) -> Float {
+    let n = contour.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (contour[i], contour[(i + 1) % n]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum::<Float>()
+        * 0.5
+}
+
+// Even-odd point in polygon
+fn contains(contour: &Contour, (x, y): (Float, Float)) -> bool {
+    let n = contour.len();
+    let mut inside = false;
+    for i in 0..n {
+        let (a, b) = (contour[i], contour[(i + 1) % n]);
+        if (a.1 > y) != (b.1 > y) && x < a.0 + (y - a.1) / (b.1 - a.1) * (b.0 - a.0) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+// Each filled outline with the holes directly inside it. Outlines inside an even number
+// of others are filled and turned counterclockwise; the rest are holes, turned clockwise,
+// so filled areas always lie to the left.
+fn nest(contours: &mut [Contour]) -> Vec<(usize, Vec<usize>)> {
+    let parents: Vec<Vec<usize>> = (0..contours.len())
+        .map(|i| {
+            (0..contours.len())
+                .filter(|&j| j != i && contains(&contours[j], contours[i][0]))
+                .collect()
+        })
+        .collect();
+    let mut shapes: Vec<(usize, Vec<usize>)> = Vec::new();
+    for (i, outside) in parents.iter().enumerate() {
+        if outside.len() % 2 == 0 {
+            shapes.push((i, Vec::new()));
+        }
+    }
+    for (i, outside) in parents.iter().enumerate() {
+        if outside.len() % 2 == 1 {
+            // The nearest enclosing outline is the filled one a level up
+            let parent = outside
+                .iter()
+                .copied()
+                .filter(|&j| parents[j].len() + 1 == outside.len())
+                .min_by(|&a, &b| {
+                    signed_area(&contours[a])
+                        .abs()
+                        .total_cmp(&signed_area(&contours[b]).abs())
+                });
+            if let Some(shape) = shapes.iter_mut().find(|(outer, _)| Some(*outer) == parent) {
+                shape.1.push(i);
+            }
+        }
+    }
+    for (outer, holes) in &shapes {
+        if signed_area(&contours[*outer]) < 0.0 {
+            contours[*outer].reverse();
+        }
+        for &hole in holes {
+            if signed_area(&contours[hole]) > 0.0 {
+                contours[hole].reverse();
+            }
+        }
+    }
+    shapes
+}
+
+// The contour moved distance into its filled side, each corner along the bisector of
+// its edges so the edges stay parallel. Edges shorter than the corners at their ends need
+// turn around; they shrink to a point instead, so every point of the contour still has
+// one of the inset outline beside it.
+fn inset(contour: &Contour, distance: Float) -> Contour {
+    let n = contour.len();
+    if distance <= 0.0 {
+        return contour.clone();
+    }
+    let mut inset: Contour = (0..n)
+        .map(|i| {
+            let (prev, here, next) = (contour[(i + n - 1) % n], contour[i], contour[(i + 1) % n]);
+            // Left of the direction of travel, into the filled side
+            let inward = |a: (Float, Float), b: (Float, Float)| unit((a.1 - b.1, b.0 - a.0));
+            let (n1, n2) = (inward(prev, here), inward(here, next));
+            let cosine = n1.0 * n2.0 + n1.1 * n2.1;
+            let scale = (1.0 / (1.0 + cosine).max(1e-6)).min(MITER_LIMIT);
+            (
+                here.0 + (n1.0 + n2.0) * scale * distance,
+                here.1 + (n1.1 + n2.1) * scale * distance,
+            )
+        })
+        .collect();
+    for _ in 0..n {
+        let mut changed = false;
+        for i in 0..n {
+            let j = (i + 1) % n;
+            let (a, b) = (contour[i], contour[j]);
+            let (p, q) = (inset[i], inset[j]);
+            if (b.0 - a.0) * (q.0 - p.0) + (b.1 - a.1) * (q.1 - p.1) < 0.0 {
+                let middle = ((p.0 + q.0) * 0.5, (p.1 + q.1) * 0.5);
+                inset[i] = middle;
+                inset[j] = middle;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    inset
+}
+
+fn cross(o: (Float, Float), a: (Float, Float), b: (Float, Float)) -> Float {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
+
+// Merges the holes into the counterclockwise outer polygon, cutting a seam from each
+// hole's rightmost point to a point of the outline it can see (Eberly 2008), so the
+// result is one polygon that visits the seams' ends twice
+fn bridge(
+    points: &[(Float, Float)],
+    mut outer: Vec<usize>,
+    mut holes: Vec<Vec<usize>>,
+) -> Vec<usize> {
+    let rightmost = |hole: &[usize]| {
+        (0..hole.len())
+            .max_by(|&a, &b| points[hole[a]].0.total_cmp(&points[hole[b]].0))
+            .unwrap_or(0)
+    };
+    holes.retain(|hole| hole.len() >= 3);
+    holes.sort_by(|a, b| {
+        points[b[rightmost(b)]]
+            .0
+            .total_cmp(&points[a[rightmost(a)]].0)
+    });
+    for hole in holes {
+        let m = rightmost(&hole);
+        let (mx, my) = points[hole[m]];
+        // The nearest edge the ray to +x from the hole meets, and where
+        let mut nearest: Option<(Float, usize)> = None;
+        for i in 0..outer.len() {
+            let (a, b) = (points[outer[i]], points[outer[(i + 1) % outer.len()]]);
+            if (a.1 > my) == (b.1 > my) {
+                continue;
+            }
+            let x = a.0 + (my - a.1) / (b.1 - a.1) * (b.0 - a.0);
+            if x >= mx && nearest.is_none_or(|(best, _)| x < best) {
+                nearest = Some((x, i));
+            }
+        }
+        let Some((ix, edge)) = nearest else {
+            continue;
+        };
+        // The edge's end furthest along the ray, unless another point of the outline
+        // inside the triangle it makes with the ray would block the view of it; then the
+        // one of those closest in angle to the ray
+        let ends = [edge, (edge + 1) % outer.len()];
+        let mut p = ends
+            .into_iter()
+            .max_by(|&a, &b| points[outer[a]].0.total_cmp(&points[outer[b]].0))
+            .unwrap_or(edge);
+        let (m_point, i_point, p_point) = ((mx, my), (ix, my), points[outer[p]]);
+        let mut best_angle = Float::INFINITY;
+        for (k, &index) in outer.iter().enumerate() {
+            let q = points[index];
+            if q == p_point || q == m_point {
+                continue;
+            }
+            let inside = if cross(m_point, i_point, p_point) >= 0.0 {
+                cross(m_point, i_point, q) >= 0.0
+                    && cross(i_point, p_point, q) >= 0.0
+                    && cross(p_point, m_point, q) >= 0.0
+            } else {
+                cross(m_point, i_point, q) <= 0.0
+                    && cross(i_point, p_point, q) <= 0.0
+                    && cross(p_point, m_point, q) <= 0.0
+            };
+            if inside && q.0 > mx {
+                let angle = ((q.1 - my) / (q.0 - mx)).abs();
+                if angle < best_angle {
+                    best_angle = angle;
+                    p = k;
+                }
+            }
+        }
+        let mut merged = Vec::with_capacity(outer.len() + hole.len() + 2);
+        merged.extend_from_slice(&outer[..=p]);
+        merged.extend(hole[m..].iter().chain(&hole[..=m]));
+        merged.extend_from_slice(&outer[p..]);
+        outer = merged;
+    }
+    outer
+}
+
+// Ear clipping of a counterclockwise polygon into counterclockwise triangles, leaving
+// out any with no area
+fn triangulate(points: &[(Float, Float)], mut polygon: Vec<usize>) -> Vec<[usize; 3]> {
+    // Points drawn twice in a row would make corners with no direction
+    polygon.dedup_by(|a, b| points[*a] == points[*b]);
+    while polygon.len() > 1 && points[polygon[0]] == points[polygon[polygon.len() - 1]] {
+        polygon.pop();
+    }
+    let mut triangles = Vec::with_capacity(polygon.len().saturating_sub(2));
+    while polygon.len() > 3 {
+        let n = polygon.len();
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = (polygon[(i + n - 1) % n], polygon[i], polygon[(i + 1) % n]);
+            let (pa, pb, pc) = (points[a], points[b], points[c]);
+            if cross(pa, pb, pc) <= 0.0 {
+                return false;
+            }
+            // No other point of the polygon may lie in the ear, though the seams' doubled
+            // points may touch its corners
+            !polygon.iter().any(|&q| {
+                let pq = points[q];
+                pq != pa
+                    && pq != pb
+                    && pq != pc
+                    && cross(pa, pb, pq) >= 0.0
+                    && cross(pb, pc, pq) >= 0.0
+                    && cross(pc, pa, pq) >= 0.0
+            })
+        });
+        // Degenerate leftovers, like a run of points along a line, give up a point at a
+        // time without a triangle; anything else breaks its sharpest convex corner off
+        let i = match ear {
+            Some(i) => i,
+            None => (0..n)
+                .max_by(|&a, &b| {
+                    let turn = |i: usize| {
+                        cross(
+                            points[polygon[(i + n - 1) % n]],
+                            points[polygon[i]],
+                            points[polygon[(i + 1) % n]],
+                        )
+                    };
+                    turn(a).total_cmp(&turn(b))
+                })
+                .unwrap_or(0),
+        };
+        let (a, b, c) = (polygon[(i + n - 1) % n], polygon[i], polygon[(i + 1) % n]);
+        if cross(points[a], points[b], points[c]) > 0.0 {
+            triangles.push([a, b, c]);
+        }
+        polygon.remove(i);
+    }
+    if let [a, b, c] = polygon[..] {
+        if cross(points[a], points[b], points[c]) > 0.0 {
+            triangles.push([a, b, c]);
+        }
+    }
+    triangles
+}
@@ -0,0 +1,259 @@
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::framebuffer::Framebuffer;
+use crate::log::{self, Level};
+use crate::shapes::HitRecord;
+use crate::vec3::{Float, Vec3f};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    // Repeats in u across the seam but clamps in v, so the poles of an equirectangular
+    // map never blend with each other
+    LatLong,
+}
+
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<Vec3f>,
+}
+
+impl MipLevel {
+    fn texel(&self, x: isize, y: isize, wrap: Wrap) -> Vec3f {
+        let (x, y) = match wrap {
+            Wrap::Repeat => (
+                x.rem_euclid(self.width as isize),
+                y.rem_euclid(self.height as isize),
+            ),
+            Wrap::Clamp => (
+                x.clamp(0, self.width as isize - 1),
+                y.clamp(0, self.height as isize - 1),
+            ),
+            Wrap::LatLong => (
+                x.rem_euclid(self.width as isize),
+                y.clamp(0, self.height as isize - 1),
+            ),
+        };
+        self.texels[y as usize * self.width + x as usize]
+    }
+
+    fn bilinear(&self, u: Float, v: Float, wrap: Wrap) -> Vec3f {
+        // Texel centers sit at half-integer coordinates, rows stored top to bottom
+        let x = u * self.width as Float - 0.5;
+        let y = (1.0 - v) * self.height as Float - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+        let top = self.texel(x0, y0, wrap) * (1.0 - fx) + self.texel(x0 + 1, y0, wrap) * fx;
+        let bottom =
+            self.texel(x0, y0 + 1, wrap) * (1.0 - fx) + self.texel(x0 + 1, y0 + 1, wrap) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    // Next level down, each texel the box average of the up to 2x2 texels it covers
+    fn downsample(&self) -> MipLevel {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let mut texels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let xs = (2 * x)..(2 * x + 2).min(self.width);
+                let ys = (2 * y)..(2 * y + 2).min(self.height);
+                let count = (xs.len() * ys.len()) as Float;
+                let mut sum = Vec3f(0.0, 0.0, 0.0);
+                for sy in ys {
+                    for sx in xs.clone() {
+                        sum += self.texels[sy * self.width + sx];
+                    }
+                }
+                texels.push(sum * (1.0 / count));
+            }
+        }
+        MipLevel {
+            width,
+            height,
+            texels,
+        }
+    }
+}
+
+// An RGB image addressed by (u, v) in [0, 1]^2 with (0, 0) at the bottom-left corner.
+// A mipmap pyramid is built up front so minified lookups can be filtered trilinearly.
+pub struct ImageTexture {
+    levels: Vec<MipLevel>,
+    pub wrap: Wrap,
+    // Added to every level of detail; positive values blur, negative sharpen
+    pub lod_bias: Float,
+}
+
+impl ImageTexture {
+    // Texels are row-major from the top row down, like Framebuffer pixels
+    pub fn new(width: usize, height: usize, texels: Vec<Vec3f>) -> ImageTexture {
+        assert!(width > 0 && height > 0, "texture must not be empty");
+        assert_eq!(
+            texels.len(),
+            width * height,
+            "texel count does not match size"
+        );
+        let mut levels = vec![MipLevel {
+            width,
+            height,
+            texels,
+        }];
+        while let Some(last) = levels.last().filter(|l| l.width > 1 || l.height > 1) {
+            let next = last.downsample();
+            levels.push(next);
+        }
+        ImageTexture {
+            levels,
+            wrap: Wrap::Repeat,
+            lod_bias: 0.0,
+        }
+    }
+
+    pub fn from_framebuffer(image: &Framebuffer) -> ImageTexture {
+        ImageTexture::new(image.width, image.height, image.pixels.clone())
+    }
+
+    pub fn load(path: &Path) -> io::Result<ImageTexture> {
+        let start = log::timer(Level::Debug);
+        let texture = ImageTexture::from_framebuffer(&Framebuffer::read_image(path)?);
+        if let Some(start) = start {
+            crate::debug!(
+                "loaded texture {}: {}x{}, {} mip levels in {:.3}s",
+                path.display(),
+                texture.width(),
+                texture.height(),
+                texture.levels.len(),
+                start.elapsed().as_secs_f64()
+            );
+        }
+        Ok(texture)
+    }
+
+    pub fn width(&self) -> usize {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> usize {
+        self.levels[0].height
+    }
+
+    // The full-size texel at column x and row y, rows counted from the top
+    pub fn texel(&self, x: usize, y: usize) -> Vec3f {
+        let level = &self.levels[0];
+        level.texels[y * level.width + x]
+    }
+
+    pub fn mip_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    // Per-channel minimum and maximum texel; filtered lookups never leave this range
+    pub fn range(&self) -> (Vec3f, Vec3f) {
+        let texels = &self.levels[0].texels;
+        texels
+            .iter()
+            .fold((texels[0], texels[0]), |(lo, hi), t| (lo.min(t), hi.max(t)))
+    }
+
+    // Blends the two mip levels around a fractional level of detail, 0 being full size
+    pub fn sample_level(&self, u: Float, v: Float, lod: Float) -> Vec3f {
+        let max_level = (self.levels.len() - 1) as Float;
+        let lod = (lod + self.lod_bias).clamp(0.0, max_level);
+        let lower = lod.floor();
+        let t = lod - lower;
+        let fine = self.levels[lower as usize].bilinear(u, v, self.wrap);
+        if t == 0.0 {
+            return fine;
+        }
+        let coarse = self.levels[lower as usize + 1].bilinear(u, v, self.wrap);
+        fine * (1.0 - t) + coarse * t
+    }
+
+    // Filtered lookup for a footprint given by how (u, v) changes per pixel in x and y,
+    // as a ray differential provides; without one only the LOD bias applies
+    pub fn sample(
+        &self,
+        u: Float,
+        v: Float,
+        duv: Option<((Float, Float), (Float, Float))>,
+    ) -> Vec3f {
+        let lod = match duv {
+            Some(((dudx, dvdx), (dudy, dvdy))) => {
+                let (w, h) = (self.width() as Float, self.height() as Float);
+                let width = Float::max((dudx * w).hypot(dvdx * h), (dudy * w).hypot(dvdy * h));
+                if width > 0.0 {
+                    width.log2()
+                } else {
+                    0.0
+                }
+            }
+            None => 0.0,
+        };
+        self.sample_level(u, v, lod)
+    }
+}
+
+// How a hit finds its place in a texture
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mapping {
+    // The surface coordinates of the shape; shapes without them stay untextured
+    Uv,
+    // Three planar projections along the world axes, blended by how squarely the
+    // surface faces each one, for surfaces with no coordinates of their own. size is the
+    // world distance one copy of the image covers, and higher sharpness narrows the
+    // blend where projections meet.
+    Triplanar { size: Float, sharpness: Float },
+}
+
+impl Mapping {
+    pub fn triplanar(size: Float) -> Mapping {
+        Mapping::Triplanar {
+            size,
+            sharpness: 4.0,
+        }
+    }
+}
+
+// An image and how it is laid on a surface. Images are shared, so one loaded once can
+// texture many objects.
+#[derive(Clone)]
+pub struct TextureMap {
+    pub texture: Arc<ImageTexture>,
+    pub mapping: Mapping,
+}
+
+impl TextureMap {
+    pub fn new(texture: Arc<ImageTexture>, mapping: Mapping) -> TextureMap {
+        TextureMap { texture, mapping }
+    }
+
+    // The texture color at a hit, if the mapping can place it
+    pub fn lookup(&self, record: &HitRecord) -> Option<Vec3f> {
+        match self.mapping {
+            Mapping::Uv => record.uv.map(|(u, v)| self.texture.sample(u, v, None)),
+            Mapping::Triplanar { size, sharpness } => {
+                let n = record.normal;
+                let weights = [n.0, n.1, n.2].map(|c| c.abs().powf(sharpness));
+                let total: Float = weights.iter().sum();
+                if total <= 0.0 {
+                    return None;
+                }
+                let p = record.point * (1.0 / size);
+                // Each projection drops the axis it looks along
+                let projections = [(p.2, p.1), (p.0, p.2), (p.0, p.1)];
+                let mut color = Vec3f(0.0, 0.0, 0.0);
+                for (weight, (u, v)) in weights.iter().zip(projections) {
+                    if *weight > 0.0 {
+                        color += self.texture.sample(u, v, None) * (weight / total);
+                    }
+                }
+                Some(color)
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+// Half-open pixel rectangle [x0, x1) x [y0, y1)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileRect {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl TileRect {
+    pub fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1 - self.y0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.x0 >= self.x1 || self.y0 >= self.y1
+    }
+
+    // Overlap with other, if any
+    pub fn intersect(&self, other: &TileRect) -> Option<TileRect> {
+        let rect = TileRect {
+            x0: self.x0.max(other.x0),
+            y0: self.y0.max(other.y0),
+            x1: self.x1.min(other.x1),
+            y1: self.y1.min(other.y1),
+        };
+        (!rect.is_empty()).then_some(rect)
+    }
+
+    // Grown by margin on every side, the result clipped to bounds
+    pub fn expand(&self, margin: usize, bounds: &TileRect) -> TileRect {
+        TileRect {
+            x0: self.x0.saturating_sub(margin).max(bounds.x0),
+            y0: self.y0.saturating_sub(margin).max(bounds.y0),
+            x1: (self.x1 + margin).min(bounds.x1),
+            y1: (self.y1 + margin).min(bounds.y1),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileOrder {
+    // Left to right, top to bottom
+    Scanline,
+    // Outwards from the image center, which gives the most useful early previews
+    Spiral,
+    // Along a Hilbert curve, keeping consecutive tiles spatially coherent
+    Hilbert,
+    // Most expensive first, as estimated by a sparse low-resolution prepass, so no
+    // single costly tile is left running alone at the end of the frame
+    CostPredicted,
+}
+
+// Splits the image into tiles listed in the order they should be rendered
+pub fn tile_grid(width: usize, height: usize, tile_size: usize, order: TileOrder) -> Vec<TileRect> {
+    let tile_size = tile_size.max(1);
+    let tiles_x = width.div_ceil(tile_size);
+    let tiles_y = height.div_ceil(tile_size);
+
+    let mut coords: Vec<(usize, usize)> = (0..tiles_y)
+        .flat_map(|ty| (0..tiles_x).map(move |tx| (tx, ty)))
+        .collect();
+
+    match order {
+        // Cost prediction needs the renderer; it reorders the scanline grid itself
+        TileOrder::Scanline | TileOrder::CostPredicted => {}
+        TileOrder::Spiral => {
+            let cx = (tiles_x as f32 - 1.0) / 2.0;
+            let cy = (tiles_y as f32 - 1.0) / 2.0;
+            let key = |&(tx, ty): &(usize, usize)| {
+                let dx = tx as f32 - cx;
+                let dy = ty as f32 - cy;
+                // Square rings around the center, each walked clockwise
+                let ring = dx.abs().max(dy.abs()).round() as i64;
+                let angle = dy.atan2(dx);
+                (ring, angle)
+            };
+            coords.sort_by(|a, b| {
+                let (ra, aa) = key(a);
+                let (rb, ab) = key(b);
+                ra.cmp(&rb).then(aa.total_cmp(&ab))
+            });
+        }
+        TileOrder::Hilbert => {
+            let side = tiles_x.max(tiles_y).next_power_of_two();
+            coords.sort_by_key(|&(tx, ty)| hilbert_index(side, tx, ty));
+        }
+    }
+
+    coords
+        .into_iter()
+        .map(|(tx, ty)| TileRect {
+            x0: tx * tile_size,
+            y0: ty * tile_size,
+            x1: ((tx + 1) * tile_size).min(width),
+            y1: ((ty + 1) * tile_size).min(height),
+        })
+        .collect()
+}
+
+// Position of (x, y) along the Hilbert curve filling a side x side grid
+fn hilbert_index(side: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = usize::from(x & s > 0);
+        let ry = usize::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x0: usize, y0: usize, x1: usize, y1: usize) -> TileRect {
+        TileRect { x0, y0, x1, y1 }
+    }
+
+    #[test]
+    fn crops_clip_to_the_frame() {
+        let frame = rect(0, 0, 20, 10);
+        assert_eq!(rect(5, 2, 30, 8).intersect(&frame), Some(rect(5, 2, 20, 8)));
+        assert_eq!(frame.intersect(&rect(3, 3, 4, 4)), Some(rect(3, 3, 4, 4)));
+        // Touching edges share no pixels
+        assert_eq!(rect(20, 0, 25, 10).intersect(&frame), None);
+        assert_eq!(rect(4, 4, 4, 9).intersect(&frame), None);
+        assert!(rect(4, 4, 4, 9).is_empty());
+
+        assert_eq!(rect(5, 2, 8, 8).expand(3, &frame), rect(2, 0, 11, 10));
+        assert_eq!(rect(1, 1, 19, 9).expand(5, &frame), frame);
+        assert_eq!(rect(5, 2, 8, 8).expand(0, &frame), rect(5, 2, 8, 8));
+    }
+}
@@ -0,0 +1,274 @@
+use crate::bvh::Aabb;
+use crate::lod::View;
+use crate::mesh::TriangleMesh;
+use crate::scene::Diagnostic;
+use crate::shapes::{HitRecord, Shape};
+use crate::vec3::{Float, Vec3f};
+
+// An affine map: a linear part, stored as rows, followed by a translation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub linear: [Vec3f; 3],
+    pub translation: Vec3f,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            linear: [
+                Vec3f(1.0, 0.0, 0.0),
+                Vec3f(0.0, 1.0, 0.0),
+                Vec3f(0.0, 0.0, 1.0),
+            ],
+            translation: Vec3f(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn translation(offset: Vec3f) -> Transform {
+        Transform {
+            translation: offset,
+            ..Transform::identity()
+        }
+    }
+
+    pub fn scaling(factors: Vec3f) -> Transform {
+        Transform {
+            linear: [
+                Vec3f(factors.0, 0.0, 0.0),
+                Vec3f(0.0, factors.1, 0.0),
+                Vec3f(0.0, 0.0, factors.2),
+            ],
+            translation: Vec3f(0.0, 0.0, 0.0),
+        }
+    }
+
+    // Counter-clockwise rotation by angle radians about a unit axis (Rodrigues)
+    pub fn rotation(axis: Vec3f, angle: Float) -> Transform {
+        let (s, c) = angle.sin_cos();
+        let t = 1.0 - c;
+        let Vec3f(x, y, z) = axis;
+        Transform {
+            linear: [
+                Vec3f(t * x * x + c, t * x * y - s * z, t * x * z + s * y),
+                Vec3f(t * x * y + s * z, t * y * y + c, t * y * z - s * x),
+                Vec3f(t * x * z - s * y, t * y * z + s * x, t * z * z + c),
+            ],
+            translation: Vec3f(0.0, 0.0, 0.0),
+        }
+    }
+
+    // Rotation by angle radians about the unit axis through point
+    pub fn around(point: Vec3f, axis: Vec3f, angle: Float) -> Transform {
+        Transform::translation(-point)
+            .then(&Transform::rotation(axis, angle))
+            .then(&Transform::translation(point))
+    }
+
+    // Takes +z up, as Blender and most CAD tools have it, to this renderer's +y up. Both
+    // are right-handed, so it is a quarter turn about x: +y goes to -z.
+    pub fn z_up() -> Transform {
+        Transform {
+            linear: [
+                Vec3f(1.0, 0.0, 0.0),
+                Vec3f(0.0, 0.0, 1.0),
+                Vec3f(0.0, -1.0, 0.0),
+            ],
+            translation: Vec3f(0.0, 0.0, 0.0),
+        }
+    }
+
+    // Rotations about X, then Y, then Z, in radians
+    pub fn euler(angles: Vec3f) -> Transform {
+        Transform::rotation(Vec3f(1.0, 0.0, 0.0), angles.0)
+            .then(&Transform::rotation(Vec3f(0.0, 1.0, 0.0), angles.1))
+            .then(&Transform::rotation(Vec3f(0.0, 0.0, 1.0), angles.2))
+    }
+
+    // This transform followed by next
+    pub fn then(&self, next: &Transform) -> Transform {
+        let columns = self.columns();
+        let row = |r: &Vec3f| Vec3f(r.dot(&columns[0]), r.dot(&columns[1]), r.dot(&columns[2]));
+        Transform {
+            linear: next.linear.map(|r| row(&r)),
+            translation: next.point(&self.translation),
+        }
+    }
+
+    fn columns(&self) -> [Vec3f; 3] {
+        let [a, b, c] = self.linear;
+        [
+            Vec3f(a.0, b.0, c.0),
+            Vec3f(a.1, b.1, c.1),
+            Vec3f(a.2, b.2, c.2),
+        ]
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Transform::identity()
+    }
+
+    pub fn determinant(&self) -> Float {
+        let [a, b, c] = self.linear;
+        a.dot(&b.cross(&c))
+    }
+
+    // None when the linear part is singular, e.g. a zero scale
+    pub fn inverse(&self) -> Option<Transform> {
+        let det = self.determinant();
+        if det.abs() <= Float::EPSILON || !det.is_finite() {
+            return None;
+        }
+        // The inverse's columns are the cross products of the rows, over the determinant
+        let [a, b, c] = self.linear;
+        let columns = [b.cross(&c), c.cross(&a), a.cross(&b)].map(|v| v * (1.0 / det));
+        let linear = [
+            Vec3f(columns[0].0, columns[1].0, columns[2].0),
+            Vec3f(columns[0].1, columns[1].1, columns[2].1),
+            Vec3f(columns[0].2, columns[1].2, columns[2].2),
+        ];
+        let inverse = Transform {
+            linear,
+            translation: Vec3f(0.0, 0.0, 0.0),
+        };
+        Some(Transform {
+            translation: inverse.vector(&self.translation) * -1.0,
+            ..inverse
+        })
+    }
+
+    pub fn vector(&self, v: &Vec3f) -> Vec3f {
+        let [a, b, c] = self.linear;
+        Vec3f(a.dot(v), b.dot(v), c.dot(v))
+    }
+
+    pub fn point(&self, p: &Vec3f) -> Vec3f {
+        self.vector(p) + self.translation
+    }
+
+    pub fn bounds(&self, local: &Aabb) -> Aabb {
+        let (lo, hi) = (local.min, local.max);
+        (0..8)
+            .map(|corner| {
+                Vec3f(
+                    if corner & 1 == 0 { lo.0 } else { hi.0 },
+                    if corner & 2 == 0 { lo.1 } else { hi.1 },
+                    if corner & 4 == 0 { lo.2 } else { hi.2 },
+                )
+            })
+            .fold(Aabb::empty(), |acc, c| {
+                let p = self.point(&c);
+                acc.union(&Aabb::new(p, p))
+            })
+    }
+}
+
+// A shape placed in the world by a transform; rays are taken into the shape's own space
+pub struct Transformed<S: Shape + ?Sized> {
+    to_world: Transform,
+    to_local: Option<Transform>,
+    shape: Box<S>,
+}
+
+impl<S: Shape + ?Sized> Transformed<S> {
+    pub fn new(shape: Box<S>, to_world: Transform) -> Transformed<S> {
+        Transformed {
+            to_world,
+            to_local: to_world.inverse(),
+            shape,
+        }
+    }
+}
+
+impl<S: Shape + ?Sized> Shape for Transformed<S> {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        let to_local = self.to_local.as_ref()?;
+        let local_dir = to_local.vector(dir);
+        // Shapes expect unit directions, and distances shrink or grow with the scale
+        let stretch = local_dir.length();
+        let local = self
+            .shape
+            .hit(&to_local.point(orig), &(local_dir * (1.0 / stretch)))?;
+        let t = local.t / stretch;
+        // Normals transform by the inverse transpose
+        let [a, b, c] = to_local.linear;
+        let n = local.normal;
+        let normal = a * n.0 + b * n.1 + c * n.2;
+        Some(HitRecord {
+            t,
+            point: *orig + *dir * t,
+            normal: normal.normalized().unwrap_or(n),
+            uv: local.uv,
+            tangent: local
+                .tangent
+                .and_then(|tangent| self.to_world.vector(&tangent).normalized()),
+            color: local.color,
+            front_face: local.front_face,
+        })
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.to_world.bounds(&self.shape.bounds())
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut issues = self.shape.diagnostics();
+        if self.to_local.is_none() {
+            issues.push(Diagnostic::error(
+                "transform is singular or not finite".to_string(),
+            ));
+        }
+        issues
+    }
+
+    // Sizes on screen are ratios of lengths, which a uniform scale leaves alone, so only
+    // the position moves into the shape's space
+    fn set_view(&self, view: &View) {
+        if let Some(to_local) = &self.to_local {
+            self.shape.set_view(&View {
+                position: to_local.point(&view.position),
+                ..*view
+            });
+        }
+    }
+
+    fn mesh(&self) -> Option<TriangleMesh> {
+        let to_local = self.to_local.as_ref()?;
+        let local = self.shape.mesh()?;
+        let vertices = local
+            .vertices()
+            .iter()
+            .map(|v| self.to_world.point(v))
+            .collect();
+        // A mirroring transform turns the faces inside out unless their winding flips too
+        let mirrored = self.to_world.determinant() < 0.0;
+        let faces = local
+            .faces()
+            .iter()
+            .map(|&[a, b, c]| if mirrored { [a, c, b] } else { [a, b, c] })
+            .collect();
+        let [a, b, c] = to_local.linear;
+        let mut mesh = match local.normals() {
+            Some(normals) => {
+                let normals = normals
+                    .iter()
+                    .map(|n| {
+                        let normal = a * n.0 + b * n.1 + c * n.2;
+                        normal.normalized().unwrap_or(*n)
+                    })
+                    .collect();
+                TriangleMesh::with_normals(vertices, normals, faces)
+            }
+            None => TriangleMesh::new(vertices, faces),
+        };
+        if let Some(uvs) = local.uvs() {
+            mesh = mesh.with_uvs(uvs.to_vec());
+        }
+        Some(mesh)
+    }
+}
@@ -0,0 +1,201 @@
+// Model showcase animations: the camera circles the scene at a fixed height, looking at
+// the middle of its bounds, with one frame per equal step of a full turn.
+use std::io;
+
+use crate::camera::Camera;
+use crate::framebuffer::Framebuffer;
+use crate::render::{render, RenderSettings};
+use crate::scene::Scene;
+use crate::vec3::{consts::PI, Float, Vec3f};
+
+// Room left around the scene when the camera backs off to fit it, as a fraction of its size
+const PADDING: Float = 0.1;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Turntable {
+    pub frames: usize,
+    // How far from the middle of the scene the camera circles; None backs off until all of
+    // it fits the frame
+    pub radius: Option<Float>,
+    // Radians above the horizontal the camera looks down from
+    pub elevation: Float,
+}
+
+impl Default for Turntable {
+    fn default() -> Turntable {
+        Turntable {
+            frames: 36,
+            radius: None,
+            elevation: PI / 9.0,
+        }
+    }
+}
+
+impl Turntable {
+    // The camera of each frame, starting from the side of the scene camera is on and
+    // turning counter-clockwise seen from above; camera's lens, exposure and field of
+    // view carry over
+    pub fn cameras(&self, scene: &Scene, camera: &Camera) -> Vec<Camera> {
+        let bounds = scene.bounds();
+        let center = if bounds.min.0 <= bounds.max.0 {
+            bounds.centroid()
+        } else {
+            camera.target
+        };
+        let up = camera.up_axis();
+        let flat = |v: Vec3f| (v - up * v.dot(&up)).normalized();
+        let heading = flat(camera.position - center)
+            .or_else(|| flat(Vec3f(0.0, 0.0, 1.0)))
+            .or_else(|| flat(Vec3f(1.0, 0.0, 0.0)))
+            .unwrap_or(Vec3f(0.0, 0.0, 1.0));
+        let (sin, cos) = self.elevation.sin_cos();
+        let away = heading * cos + up * sin;
+
+        let mut start = camera.clone();
+        start.target = center;
+        start = match self.radius {
+            Some(radius) => {
+                start.position = center + away * radius;
+                start
+            }
+            // Framing keeps the direction the camera looks along
+            None => {
+                start.position = center + away;
+                start.frame(scene, PADDING)
+            }
+        };
+        let frames = self.frames.max(1);
+        (0..frames)
+            .map(|i| {
+                let angle = 2.0 * PI * i as Float / frames as Float;
+                start.clone().orbit(center, angle)
+            })
+            .collect()
+    }
+}
+
+// Renders the turn frame by frame, handing each to frame with its index as it finishes;
+// an error from frame stops the turn there
+pub fn render_turntable<F>(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    turntable: &Turntable,
+    mut frame: F,
+) -> io::Result<()>
+where
+    F: FnMut(usize, Framebuffer) -> io::Result<()>,
+{
+    for (i, camera) in turntable.cameras(scene, camera).iter().enumerate() {
+        frame(i, render(scene, camera, settings))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene_file::SceneFile;
+
+    // A ball of radius 1 at (2, 1, 0), seen from straight along +x of it
+    fn ball() -> SceneFile {
+        SceneFile::parse(
+            r#"{"camera": {"position": [12, 1, 0], "target": [2, 1, 0], "fov": 60},
+                "objects": [{"type": "sphere", "center": [2, 1, 0], "radius": 1}]}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn circles_the_middle_of_the_scene_in_equal_steps() {
+        let file = ball();
+        let center = Vec3f(2.0, 1.0, 0.0);
+        let turntable = Turntable {
+            frames: 8,
+            radius: Some(5.0),
+            elevation: PI / 6.0,
+        };
+        let cameras = turntable.cameras(&file.scene, &file.camera);
+        assert_eq!(cameras.len(), 8);
+        // Starting on the scene camera's side, half the radius up at 30 degrees
+        let first = cameras[0].position - center;
+        assert!((first - Vec3f(5.0 * Float::sqrt(0.75), 2.5, 0.0)).length() < 1e-4);
+        for pair in cameras.windows(2) {
+            let (a, b) = (pair[0].position - center, pair[1].position - center);
+            assert!((pair[1].target - center).length() < 1e-5);
+            assert_eq!(pair[1].fov, file.camera.fov);
+            assert!((b.length() - 5.0).abs() < 1e-4);
+            assert!((b.1 - 2.5).abs() < 1e-4);
+            // An eighth of a turn each, counter-clockwise seen from above
+            let (a, b) = (Vec3f(a.0, 0.0, a.2), Vec3f(b.0, 0.0, b.2));
+            let cos = a.dot(&b) / (a.length() * b.length());
+            assert!((cos - Float::sqrt(0.5)).abs() < 1e-4, "{}", cos);
+            assert!(a.cross(&b).1 > 0.0);
+        }
+
+        // Without a radius the turn backs off just far enough to fit the ball
+        let fitted = Turntable {
+            radius: None,
+            ..turntable
+        };
+        let cameras = fitted.cameras(&file.scene, &file.camera);
+        let framed = file.camera.clone().frame(&file.scene, PADDING);
+        let distance = (framed.position - center).length();
+        assert!(distance > 1.0);
+        for camera in &cameras {
+            assert!(((camera.position - center).length() - distance).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn turns_about_the_camera_target_in_an_empty_scene() {
+        let mut camera = ball().camera;
+        camera.position = camera.target;
+        let turntable = Turntable {
+            frames: 1,
+            radius: Some(2.0),
+            elevation: 0.0,
+        };
+        // With nothing to look at, and the camera on its own target, it faces back along -z
+        let cameras = turntable.cameras(&Scene::new(), &camera);
+        assert_eq!(cameras.len(), 1);
+        assert_eq!(cameras[0].target, camera.target);
+        let offset = cameras[0].position - camera.target;
+        assert!(
+            (offset - Vec3f(0.0, 0.0, 2.0)).length() < 1e-5,
+            "{:?}",
+            offset
+        );
+    }
+
+    #[test]
+    fn stops_rendering_at_the_first_frame_that_fails() {
+        let file = ball();
+        let settings = RenderSettings {
+            width: 4,
+            height: 4,
+            threads: Some(1),
+            ..RenderSettings::default()
+        };
+        let turntable = Turntable {
+            frames: 5,
+            ..Turntable::default()
+        };
+        let mut seen = Vec::new();
+        let result = render_turntable(
+            &file.scene,
+            &file.camera,
+            &settings,
+            &turntable,
+            |i, image| {
+                seen.push((i, image.width));
+                if i == 2 {
+                    return Err(io::Error::new(io::ErrorKind::StorageFull, "disk full"));
+                }
+                Ok(())
+            },
+        );
+        assert_eq!(result.unwrap_err().to_string(), "disk full");
+        assert_eq!(seen, [(0, 4), (1, 4), (2, 4)]);
+    }
+}
@@ -0,0 +1,273 @@
+// Variables a scene file defines and refers to from its strings, so one file can drive a
+// sweep of renders by setting them from outside instead of being templated by a script.
+// A string that is nothing but "${expression}" becomes the expression's value: a bare
+// name gives the variable's value whatever its type, so arrays and objects can be shared
+// too, and arithmetic over numbers with + - * / %, unary minus and parentheses gives a
+// number. Elsewhere in a string each ${...} is replaced by its value as text, as in
+// "wood_${grade}.png".
+use std::io;
+
+use crate::json::Json;
+
+pub struct Variables {
+    values: Vec<(String, Json)>,
+}
+
+impl Variables {
+    // Takes the "variables" object out of root, if it is an object that has one, and reads
+    // its members in order, each able to refer to those before it. Values in overrides
+    // replace the file's own, and naming one the file does not define is an error so a
+    // mistyped --set does not go unnoticed.
+    pub fn take(root: &mut Json, overrides: &[(String, Json)]) -> io::Result<Variables> {
+        let mut definitions = Vec::new();
+        if let Json::Object(members) = root {
+            let (taken, rest) = std::mem::take(members)
+                .into_iter()
+                .partition(|(key, _)| key == "variables");
+            *members = rest;
+            definitions = taken;
+        }
+        let mut variables = Variables { values: Vec::new() };
+        for (_, definition) in definitions {
+            let Json::Object(members) = definition else {
+                return Err(invalid(
+                    "scene.variables",
+                    &format!("expected an object, found {}", definition.kind()),
+                ));
+            };
+            for (name, mut value) in members {
+                let path = format!("scene.variables.{}", name);
+                if !is_name(&name) {
+                    return Err(invalid(&path, "not a valid variable name"));
+                }
+                match overrides.iter().rev().find(|(n, _)| *n == name) {
+                    Some((_, set)) => value = set.clone(),
+                    None => variables.substitute(&mut value, &path)?,
+                }
+                variables.values.push((name, value));
+            }
+        }
+        if let Some((name, _)) = overrides.iter().find(|(n, _)| variables.get(n).is_none()) {
+            return Err(invalid(
+                "scene.variables",
+                &format!("{} is set but the scene defines no such variable", name),
+            ));
+        }
+        Ok(variables)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Json> {
+        self.values
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|v| &v.1)
+    }
+
+    // Replaces the ${...} in every string in value, which is at path in the file
+    pub fn substitute(&self, value: &mut Json, path: &str) -> io::Result<()> {
+        match value {
+            Json::String(text) if text.contains("${") => *value = self.expand(text, path)?,
+            Json::Array(items) => {
+                for (i, item) in items.iter_mut().enumerate() {
+                    self.substitute(item, &format!("{}[{}]", path, i))?;
+                }
+            }
+            Json::Object(members) => {
+                for (key, member) in members {
+                    self.substitute(member, &format!("{}.{}", path, key))?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn expand(&self, text: &str, path: &str) -> io::Result<Json> {
+        let unclosed = || invalid(path, &format!("unclosed ${{ in {:?}", text));
+        if let Some(inner) = text.strip_prefix("${").and_then(|t| t.strip_suffix('}')) {
+            if !inner.contains('}') {
+                return self.evaluate(inner, path);
+            }
+        }
+        let mut expanded = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("${") {
+            expanded.push_str(&rest[..start]);
+            let end = rest[start..].find('}').ok_or_else(unclosed)? + start;
+            match self.evaluate(&rest[start + 2..end], path)? {
+                Json::String(s) => expanded.push_str(&s),
+                Json::Number(n) => expanded.push_str(&n.to_string()),
+                Json::Bool(b) => expanded.push_str(&b.to_string()),
+                other => {
+                    return Err(invalid(
+                        path,
+                        &format!("cannot put {} into the text {:?}", other.kind(), text),
+                    ))
+                }
+            }
+            rest = &rest[end + 1..];
+        }
+        expanded.push_str(rest);
+        Ok(Json::String(expanded))
+    }
+
+    fn evaluate(&self, expression: &str, path: &str) -> io::Result<Json> {
+        let name = expression.trim();
+        if is_name(name) {
+            return self
+                .get(name)
+                .cloned()
+                .ok_or_else(|| invalid(path, &format!("unknown variable {}", name)));
+        }
+        let mut parser = Expression {
+            bytes: expression.as_bytes(),
+            pos: 0,
+            variables: self,
+        };
+        let value = parser.sum().and_then(|value| {
+            parser.skip_whitespace();
+            match parser.bytes.get(parser.pos) {
+                None => Ok(value),
+                Some(_) => Err(parser.unexpected()),
+            }
+        });
+        match value {
+            Ok(value) if value.is_finite() => Ok(Json::Number(value)),
+            Ok(value) => Err(invalid(
+                path,
+                &format!("${{{}}} comes to {}", expression, value),
+            )),
+            Err(message) => Err(invalid(
+                path,
+                &format!("in ${{{}}}: {}", expression, message),
+            )),
+        }
+    }
+}
+
+// A name=value assignment, as --set takes it. The value is read as JSON where it parses,
+// so numbers and arrays keep their type, and as a plain string otherwise.
+pub fn parse_assignment(text: &str) -> Option<(String, Json)> {
+    let (name, value) = text.split_once('=')?;
+    let name = name.trim();
+    if !is_name(name) {
+        return None;
+    }
+    let value = Json::parse(value).unwrap_or_else(|_| Json::String(value.to_string()));
+    Some((name.to_string(), value))
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn invalid(path: &str, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, message))
+}
+
+// Recursive descent over sums of products of signed terms
+struct Expression<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    variables: &'a Variables,
+}
+
+impl Expression<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.pos += 1;
+        }
+    }
+
+    // The next non-space byte, consumed if it is one of ops
+    fn operator(&mut self, ops: &[u8]) -> Option<u8> {
+        self.skip_whitespace();
+        let op = *self.bytes.get(self.pos).filter(|b| ops.contains(b))?;
+        self.pos += 1;
+        Some(op)
+    }
+
+    fn unexpected(&self) -> String {
+        match self.bytes.get(self.pos) {
+            Some(&b) => format!("unexpected '{}'", b as char),
+            None => "unexpected end".to_string(),
+        }
+    }
+
+    fn sum(&mut self) -> Result<f64, String> {
+        let mut value = self.product()?;
+        while let Some(op) = self.operator(b"+-") {
+            let rhs = self.product()?;
+            value = if op == b'+' { value + rhs } else { value - rhs };
+        }
+        Ok(value)
+    }
+
+    fn product(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+        while let Some(op) = self.operator(b"*/%") {
+            let rhs = self.term()?;
+            value = match op {
+                b'*' => value * rhs,
+                b'/' => value / rhs,
+                _ => value % rhs,
+            };
+        }
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<f64, String> {
+        if self.operator(b"-").is_some() {
+            return Ok(-self.term()?);
+        }
+        if self.operator(b"(").is_some() {
+            let value = self.sum()?;
+            return match self.operator(b")") {
+                Some(_) => Ok(value),
+                None => Err("expected ')'".to_string()),
+            };
+        }
+        let start = self.pos;
+        let Some(&first) = self.bytes.get(start) else {
+            return Err(self.unexpected());
+        };
+        if first.is_ascii_digit() || first == b'.' {
+            while self
+                .bytes
+                .get(self.pos)
+                .is_some_and(|b| b.is_ascii_digit() || *b == b'.')
+            {
+                self.pos += 1;
+            }
+            let number = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or("");
+            return number
+                .parse()
+                .map_err(|_| format!("invalid number {}", number));
+        }
+        if first.is_ascii_alphabetic() || first == b'_' {
+            while self
+                .bytes
+                .get(self.pos)
+                .is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'_')
+            {
+                self.pos += 1;
+            }
+            let name = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or("");
+            return match self.variables.get(name) {
+                Some(Json::Number(n)) => Ok(*n),
+                Some(other) => Err(format!("{} is {}, not a number", name, other.kind())),
+                None => Err(format!("unknown variable {}", name)),
+            };
+        }
+        Err(self.unexpected())
+    }
+}
@@ -1,21 +1,33 @@
-use std::ops::{Add, Sub, Mul, Neg};
+use std::ops::{Add, AddAssign, Index, Mul, Neg, Sub};
 
-#[derive(Clone, Copy, Debug)]
-pub struct Vec3f(pub f32, pub f32, pub f32);
+// Scalar used for all geometry and shading; the f64 feature trades speed and memory for
+// precision in scenes with very large coordinates
+#[cfg(not(feature = "f64"))]
+pub type Float = f32;
+#[cfg(not(feature = "f64"))]
+pub use std::f32::consts;
+
+#[cfg(feature = "f64")]
+pub type Float = f64;
+#[cfg(feature = "f64")]
+pub use std::f64::consts;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vec3f(pub Float, pub Float, pub Float);
 
 impl Vec3f {
     #[inline]
-    pub fn new(x: f32, y: f32, z: f32) -> Self {
+    pub fn new(x: Float, y: Float, z: Float) -> Self {
         Vec3f(x, y, z)
     }
 
     #[inline]
-    pub fn dot(&self, other: &Self) -> f32 {
+    pub fn dot(&self, other: &Self) -> Float {
         self.0 * other.0 + self.1 * other.1 + self.2 * other.2
     }
 
     #[inline]
-    pub fn length(&self) -> f32 {
+    pub fn length(&self) -> Float {
         self.dot(self).sqrt()
     }
 
@@ -38,7 +50,7 @@ impl Vec3f {
     }
 
     #[inline]
-    pub fn multiply_scalar(&self, scalar: f32) -> Self {
+    pub fn multiply_scalar(&self, scalar: Float) -> Self {
         Vec3f(self.0 * scalar, self.1 * scalar, self.2 * scalar)
     }
 
@@ -46,6 +58,24 @@ impl Vec3f {
     pub fn multiply(&self, other: &Self) -> Self {
         Vec3f(self.0 * other.0, self.1 * other.1, self.2 * other.2)
     }
+
+    #[inline]
+    pub fn min(&self, other: &Self) -> Self {
+        Vec3f(
+            self.0.min(other.0),
+            self.1.min(other.1),
+            self.2.min(other.2),
+        )
+    }
+
+    #[inline]
+    pub fn max(&self, other: &Self) -> Self {
+        Vec3f(
+            self.0.max(other.0),
+            self.1.max(other.1),
+            self.2.max(other.2),
+        )
+    }
 }
 
 impl Add for Vec3f {
@@ -57,6 +87,13 @@ impl Add for Vec3f {
     }
 }
 
+impl AddAssign for Vec3f {
+    #[inline]
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
 impl Sub for Vec3f {
     type Output = Self;
 
@@ -66,11 +103,11 @@ impl Sub for Vec3f {
     }
 }
 
-impl Mul<f32> for Vec3f {
+impl Mul<Float> for Vec3f {
     type Output = Self;
 
     #[inline]
-    fn mul(self, scalar: f32) -> Self {
+    fn mul(self, scalar: Float) -> Self {
         self.multiply_scalar(scalar)
     }
 }
@@ -84,3 +121,16 @@ impl Neg for Vec3f {
     }
 }
 
+impl Index<usize> for Vec3f {
+    type Output = Float;
+
+    #[inline]
+    fn index(&self, axis: usize) -> &Float {
+        match axis {
+            0 => &self.0,
+            1 => &self.1,
+            2 => &self.2,
+            _ => panic!("Vec3f axis out of range: {}", axis),
+        }
+    }
+}
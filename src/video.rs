@@ -0,0 +1,131 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use crate::framebuffer::Framebuffer;
+use crate::vec3::{Float, Vec3f};
+
+// Streams frames as YUV4MPEG2, the uncompressed format ffmpeg and most players read
+// from a pipe. Chroma is kept at full resolution, so nothing is lost on the way in.
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    fps: u32,
+    // Frame size, fixed by the first frame
+    size: Option<(usize, usize)>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(out: W, fps: u32) -> Y4mWriter<W> {
+        Y4mWriter {
+            out,
+            fps: fps.max(1),
+            size: None,
+        }
+    }
+
+    pub fn write_frame(&mut self, frame: &Framebuffer) -> io::Result<()> {
+        match self.size {
+            None => {
+                writeln!(
+                    self.out,
+                    "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                    frame.width, frame.height, self.fps
+                )?;
+                self.size = Some((frame.width, frame.height));
+            }
+            Some(size) if size != (frame.width, frame.height) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "frame is {}x{} but the stream is {}x{}",
+                        frame.width, frame.height, size.0, size.1
+                    ),
+                ));
+            }
+            Some(_) => {}
+        }
+
+        // BT.709 with video-range levels, what encoders assume for HD material
+        let mut planes = vec![0u8; frame.pixels.len() * 3];
+        let (y_plane, chroma) = planes.split_at_mut(frame.pixels.len());
+        let (u_plane, v_plane) = chroma.split_at_mut(frame.pixels.len());
+        for (i, &Vec3f(r, g, b)) in frame.pixels.iter().enumerate() {
+            let (r, g, b) = (r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0));
+            let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+            y_plane[i] = level(16.0 + 219.0 * y);
+            u_plane[i] = level(128.0 + 224.0 * (b - y) / 1.8556);
+            v_plane[i] = level(128.0 + 224.0 * (r - y) / 1.5748);
+        }
+        self.out.write_all(b"FRAME\n")?;
+        self.out.write_all(&planes)
+    }
+
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn level(v: Float) -> u8 {
+    v.round().clamp(0.0, 255.0) as u8
+}
+
+// Where a frame sequence ends up: a .y4m file, y4m on stdout for "-", or any other
+// path encoded by an ffmpeg subprocess, which picks the container from the extension
+pub struct VideoEncoder {
+    writer: Y4mWriter<Box<dyn Write>>,
+    ffmpeg: Option<Child>,
+}
+
+impl VideoEncoder {
+    pub fn create(path: &Path, fps: u32) -> io::Result<VideoEncoder> {
+        if path == Path::new("-") {
+            return Ok(VideoEncoder {
+                writer: Y4mWriter::new(Box::new(BufWriter::new(io::stdout())), fps),
+                ffmpeg: None,
+            });
+        }
+        let is_y4m = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("y4m"));
+        if is_y4m {
+            return Ok(VideoEncoder {
+                writer: Y4mWriter::new(Box::new(BufWriter::new(File::create(path)?)), fps),
+                ffmpeg: None,
+            });
+        }
+
+        let mut child = Command::new("ffmpeg")
+            .args(["-loglevel", "error", "-y", "-f", "yuv4mpegpipe", "-i", "-"])
+            // yuv420p is the pixel format players reliably decode
+            .args(["-pix_fmt", "yuv420p"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("could not start ffmpeg: {}", e)))?;
+        let stdin: ChildStdin = child.stdin.take().expect("stdin is piped");
+        Ok(VideoEncoder {
+            writer: Y4mWriter::new(Box::new(BufWriter::new(stdin)), fps),
+            ffmpeg: Some(child),
+        })
+    }
+
+    pub fn write_frame(&mut self, frame: &Framebuffer) -> io::Result<()> {
+        self.writer.write_frame(frame)
+    }
+
+    // Flushes the stream and, for ffmpeg, waits for the file to be finalized
+    pub fn finish(self) -> io::Result<()> {
+        // Dropping the pipe is what tells ffmpeg the stream has ended
+        drop(self.writer.into_inner()?);
+        if let Some(mut child) = self.ffmpeg {
+            let status = child.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!("ffmpeg failed: {}", status)));
+            }
+        }
+        Ok(())
+    }
+}
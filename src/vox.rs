@@ -0,0 +1,299 @@
+// MagicaVoxel .vox files: models of palette-indexed voxels, a 256-color palette and
+// optional per-index materials, in little-endian RIFF-style chunks. Chunks this loader
+// has no use for, like the scene graph and layers, are skipped, so every model sits at
+// its own grid origin.
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::group::Group;
+use crate::log::{self, Level};
+use crate::material::{Material, Principled};
+use crate::shapes::Cube;
+use crate::vec3::{Float, Vec3f};
+use crate::voxel::VoxelOctree;
+
+pub struct VoxModel {
+    // Voxels along x, y and z, converted from MagicaVoxel's z-up axes to y-up
+    pub size: [u32; 3],
+    // Grid position and palette index, never 0
+    pub voxels: Vec<([u32; 3], u8)>,
+}
+
+pub struct VoxFile {
+    pub models: Vec<VoxModel>,
+    // Indexed as voxels index it; entry 0 is unused
+    pub palette: [Vec3f; 256],
+    // The material of each palette index, matte unless the file says otherwise
+    pub materials: Vec<Material>,
+}
+
+impl VoxFile {
+    pub fn load(path: &Path) -> io::Result<VoxFile> {
+        let start = log::timer(Level::Debug);
+        let file = VoxFile::parse(&fs::read(path)?)?;
+        if let Some(start) = start {
+            crate::debug!(
+                "loaded {}: {} models, {} voxels in {:.3}s",
+                path.display(),
+                file.models.len(),
+                file.models.iter().map(|m| m.voxels.len()).sum::<usize>(),
+                start.elapsed().as_secs_f64()
+            );
+        }
+        Ok(file)
+    }
+
+    pub fn parse(bytes: &[u8]) -> io::Result<VoxFile> {
+        let mut reader = Reader { bytes, at: 0 };
+        if reader.take(4)? != b"VOX " {
+            return Err(invalid_data("not a MagicaVoxel file".to_string()));
+        }
+        reader.u32()?;
+        let (id, content, children) = reader.chunk()?;
+        if id != b"MAIN" || !content.is_empty() {
+            return Err(invalid_data("vox file lacks its MAIN chunk".to_string()));
+        }
+
+        let mut models = Vec::new();
+        let mut size = None;
+        let mut palette = default_palette();
+        let mut settings = Vec::new();
+        let mut chunks = Reader {
+            bytes: children,
+            at: 0,
+        };
+        while !chunks.is_done() {
+            let (id, content, _) = chunks.chunk()?;
+            let mut content = Reader {
+                bytes: content,
+                at: 0,
+            };
+            match id {
+                b"SIZE" => size = Some([content.u32()?, content.u32()?, content.u32()?]),
+                b"XYZI" => {
+                    let [sx, sy, sz] = size
+                        .take()
+                        .ok_or_else(|| invalid_data("vox XYZI chunk without a SIZE".to_string()))?;
+                    let count = content.u32()? as usize;
+                    let mut voxels = Vec::with_capacity(count.min(content.bytes.len() / 4));
+                    for _ in 0..count {
+                        let [x, y, z, index] = content.array()?;
+                        if index == 0 || x as u32 >= sx || y as u32 >= sy || z as u32 >= sz {
+                            continue;
+                        }
+                        // (x, y, z) z-up becomes (x, z, -y) y-up, counted from the far side
+                        // so the grid stays positive
+                        voxels.push(([x as u32, z as u32, sy - 1 - y as u32], index));
+                    }
+                    models.push(VoxModel {
+                        size: [sx, sz, sy],
+                        voxels,
+                    });
+                }
+                // Colors for indices 1 to 255; the 256th entry is unused
+                b"RGBA" => {
+                    for entry in palette.iter_mut().skip(1) {
+                        let [r, g, b, _] = content.array()?;
+                        *entry = Vec3f(r as Float, g as Float, b as Float) * (1.0 / 255.0);
+                    }
+                }
+                b"MATL" => {
+                    let index = content.u32()?;
+                    settings.push((index, content.dict()?));
+                }
+                _ => {}
+            }
+        }
+        if models.is_empty() {
+            return Err(invalid_data("vox file has no models".to_string()));
+        }
+
+        let mut materials: Vec<Material> = palette.iter().map(|c| material(*c, &[])).collect();
+        for (index, dict) in &settings {
+            if let Some(slot) = materials.get_mut(*index as usize) {
+                *slot = material(palette[*index as usize], dict);
+            }
+        }
+        Ok(VoxFile {
+            models,
+            palette,
+            materials,
+        })
+    }
+
+    // The model as one octree colored from the palette, for material to tint
+    pub fn octree(&self, model: usize, origin: Vec3f, voxel_size: Float) -> VoxelOctree {
+        let voxels: Vec<_> = self.models[model]
+            .voxels
+            .iter()
+            .map(|(p, index)| (*p, self.palette[*index as usize]))
+            .collect();
+        VoxelOctree::new(&voxels, origin, voxel_size)
+    }
+
+    // The model as a cube per voxel in its palette material, leaving out voxels buried on
+    // every side since nothing can see them
+    pub fn cubes(&self, model: usize, origin: Vec3f, voxel_size: Float) -> Group {
+        let model = &self.models[model];
+        let occupied = VoxelOctree::new(
+            &model
+                .voxels
+                .iter()
+                .map(|(p, _)| (*p, Vec3f(1.0, 1.0, 1.0)))
+                .collect::<Vec<_>>(),
+            origin,
+            voxel_size,
+        );
+        let filled = |p: [u32; 3], axis: usize, step: i64| {
+            let mut q = p;
+            let moved = p[axis] as i64 + step;
+            if moved < 0 {
+                return false;
+            }
+            q[axis] = moved as u32;
+            occupied.get(q).is_some()
+        };
+        let mut group = Group::new();
+        for (p, index) in &model.voxels {
+            if (0..3).all(|axis| filled(*p, axis, -1) && filled(*p, axis, 1)) {
+                continue;
+            }
+            let center = origin
+                + Vec3f(
+                    p[0] as Float + 0.5,
+                    p[1] as Float + 0.5,
+                    p[2] as Float + 0.5,
+                ) * voxel_size;
+            group.add(
+                Cube::new(center, voxel_size),
+                self.materials[*index as usize],
+            );
+        }
+        group
+    }
+}
+
+// A palette color as a material, from a MATL dictionary like {"_type": "_metal",
+// "_rough": "0.2"}; MagicaVoxel stores the refractive index less one
+fn material(color: Vec3f, dict: &[(String, String)]) -> Material {
+    let value = |key: &str| {
+        dict.iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, v)| v.parse::<Float>().ok())
+    };
+    let kind = dict
+        .iter()
+        .find(|(k, _)| k == "_type")
+        .map_or("_diffuse", |(_, v)| v.as_str());
+    let mut principled = Principled {
+        base_color: color,
+        roughness: 1.0,
+        specular: 0.0,
+        ..Principled::default()
+    };
+    match kind {
+        "_metal" => {
+            principled.metallic = value("_metal").unwrap_or(1.0);
+            principled.roughness = value("_rough").unwrap_or(0.1);
+            principled.specular = value("_sp").unwrap_or(0.5);
+        }
+        "_glass" => {
+            principled.transmission = value("_trans").unwrap_or(1.0);
+            principled.roughness = value("_rough").unwrap_or(0.0);
+            principled.specular = 0.5;
+            principled.refractive_index = 1.0 + value("_ior").unwrap_or(0.5);
+        }
+        _ => {}
+    }
+    Material::from(principled)
+}
+
+// MagicaVoxel's built-in palette, for files without an RGBA chunk: a 6x6x6 color cube
+// from white down, black left out, then ten-step ramps of red, green, blue and gray
+fn default_palette() -> [Vec3f; 256] {
+    const CUBE: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    let mut colors = Vec::with_capacity(256);
+    colors.push([0, 0, 0]);
+    for r in CUBE {
+        for g in CUBE {
+            for b in CUBE {
+                colors.push([r, g, b]);
+            }
+        }
+    }
+    // The cube ends on black
+    colors.pop();
+    for channel in 0..4 {
+        for level in RAMP {
+            colors.push(match channel {
+                0 => [level, 0, 0],
+                1 => [0, level, 0],
+                2 => [0, 0, level],
+                _ => [level, level, level],
+            });
+        }
+    }
+    let mut palette = [Vec3f(0.0, 0.0, 0.0); 256];
+    for (entry, [r, g, b]) in palette.iter_mut().zip(colors) {
+        *entry = Vec3f(r as Float, g as Float, b as Float) * (1.0 / 255.0);
+    }
+    palette
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_done(&self) -> bool {
+        self.at >= self.bytes.len()
+    }
+
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        let end = self
+            .at
+            .checked_add(count)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| invalid_data("vox file ended early".to_string()))?;
+        let taken = &self.bytes[self.at..end];
+        self.at = end;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    // The id, content and children of the next chunk
+    fn chunk(&mut self) -> io::Result<(&'a [u8], &'a [u8], &'a [u8])> {
+        let id = self.take(4)?;
+        let content = self.u32()? as usize;
+        let children = self.u32()? as usize;
+        Ok((id, self.take(content)?, self.take(children)?))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let length = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    fn dict(&mut self) -> io::Result<Vec<(String, String)>> {
+        let count = self.u32()?;
+        (0..count)
+            .map(|_| Ok((self.string()?, self.string()?)))
+            .collect()
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,252 @@
+// Voxel models as a sparse octree: each node covers a cube of the grid and is empty,
+// solid in one color, or split into eight half-size children. Rays descend only into the
+// children they cross, nearest first, so empty space costs one box test per level and
+// every voxel keeps its hard cube silhouette.
+use crate::bvh::Aabb;
+use crate::scene::Diagnostic;
+use crate::shapes::{check_dimensions, check_point, HitRecord, Shape};
+use crate::vec3::{Float, Vec3f};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Node {
+    Empty,
+    Solid(Vec3f),
+    // Index of the first of eight consecutive children, numbered x + 2y + 4z by which
+    // half of the parent they take on each axis
+    Branch(u32),
+}
+
+pub struct VoxelOctree {
+    nodes: Vec<Node>,
+    // The outer corner of voxel (0, 0, 0); the grid extends along +x, +y and +z
+    origin: Vec3f,
+    voxel_size: Float,
+    // The grid is 2^depth voxels on a side
+    depth: u32,
+    count: usize,
+}
+
+impl VoxelOctree {
+    // Voxels by integer grid position and color; a position given twice keeps the
+    // later color. Eight children of one color merge into a single solid node.
+    pub fn new(voxels: &[([u32; 3], Vec3f)], origin: Vec3f, voxel_size: Float) -> VoxelOctree {
+        let extent = voxels
+            .iter()
+            .flat_map(|(p, _)| *p)
+            .max()
+            .map_or(1, |m| m + 1);
+        let depth = extent.next_power_of_two().trailing_zeros();
+        // Sorting by Morton code puts every node's voxels in one run, its children's
+        // runs in child order
+        let mut keyed: Vec<(u64, Vec3f)> = voxels.iter().map(|(p, c)| (morton(p), *c)).collect();
+        keyed.reverse();
+        keyed.sort_by_key(|(key, _)| *key);
+        keyed.dedup_by_key(|(key, _)| *key);
+        let mut octree = VoxelOctree {
+            nodes: vec![Node::Empty],
+            origin,
+            voxel_size,
+            depth,
+            count: keyed.len(),
+        };
+        octree.nodes[0] = octree.build(&keyed, depth);
+        octree
+    }
+
+    // The node for voxels, all within one cube 2^level voxels on a side
+    fn build(&mut self, voxels: &[(u64, Vec3f)], level: u32) -> Node {
+        if voxels.is_empty() {
+            return Node::Empty;
+        }
+        if level == 0 {
+            return Node::Solid(voxels[0].1);
+        }
+        let first = self.nodes.len();
+        self.nodes.extend([Node::Empty; 8]);
+        let shift = 3 * (level - 1);
+        let mut start = 0;
+        for child in 0..8u64 {
+            let end = start
+                + voxels[start..]
+                    .iter()
+                    .take_while(|(key, _)| (key >> shift) & 7 == child)
+                    .count();
+            self.nodes[first + child as usize] = self.build(&voxels[start..end], level - 1);
+            start = end;
+        }
+        let children = &self.nodes[first..first + 8];
+        match children[0] {
+            Node::Solid(color) if children.iter().all(|c| *c == Node::Solid(color)) => {
+                // Nothing below the merged children can be referenced any more
+                self.nodes.truncate(first);
+                Node::Solid(color)
+            }
+            _ => Node::Branch(first as u32),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    // Voxels along each side of the grid
+    pub fn resolution(&self) -> u32 {
+        1 << self.depth
+    }
+
+    pub fn get(&self, position: [u32; 3]) -> Option<Vec3f> {
+        if position.iter().any(|&p| p >= self.resolution()) {
+            return None;
+        }
+        let mut node = self.nodes[0];
+        for level in (0..self.depth).rev() {
+            match node {
+                Node::Branch(first) => {
+                    let child = (0..3)
+                        .map(|a| ((position[a] >> level) & 1) << a)
+                        .sum::<u32>();
+                    node = self.nodes[(first + child) as usize];
+                }
+                _ => break,
+            }
+        }
+        match node {
+            Node::Solid(color) => Some(color),
+            _ => None,
+        }
+    }
+
+    fn side(&self) -> Float {
+        self.voxel_size * self.resolution() as Float
+    }
+
+    // The nearest solid voxel under node, which covers the cube at min with the given side
+    fn descend(
+        &self,
+        node: Node,
+        min: Vec3f,
+        side: Float,
+        orig: &Vec3f,
+        dir: &Vec3f,
+        inv_dir: &Vec3f,
+    ) -> Option<HitRecord> {
+        match node {
+            Node::Empty => None,
+            Node::Solid(color) => {
+                let span = slab(&min, side, orig, inv_dir)?;
+                // From inside a voxel the ray leaves through its far face
+                let (t, axis, sign) = if span.near > 0.0 {
+                    (span.near, span.near_axis, -dir[span.near_axis].signum())
+                } else {
+                    (span.far, span.far_axis, dir[span.far_axis].signum())
+                };
+                let mut normal = [0.0; 3];
+                normal[axis] = sign;
+                let normal = Vec3f(normal[0], normal[1], normal[2]);
+                Some(HitRecord {
+                    t,
+                    point: *orig + *dir * t,
+                    normal,
+                    uv: None,
+                    tangent: None,
+                    color: Some(color),
+                    front_face: normal.dot(dir) < 0.0,
+                })
+            }
+            Node::Branch(first) => {
+                let half = side * 0.5;
+                let corner = |child: usize| {
+                    let bit = |a: usize| ((child >> a) & 1) as Float * half;
+                    min + Vec3f(bit(0), bit(1), bit(2))
+                };
+                // Children are disjoint, so the first one along the ray with a hit in it
+                // holds the nearest hit
+                let mut crossed = [(0.0, 0); 8];
+                let mut count = 0;
+                for child in 0..8 {
+                    if self.nodes[first as usize + child] == Node::Empty {
+                        continue;
+                    }
+                    if let Some(span) = slab(&corner(child), half, orig, inv_dir) {
+                        crossed[count] = (span.near, child);
+                        count += 1;
+                    }
+                }
+                let crossed = &mut crossed[..count];
+                crossed.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+                crossed.iter().find_map(|&(_, child)| {
+                    let node = self.nodes[first as usize + child];
+                    self.descend(node, corner(child), half, orig, dir, inv_dir)
+                })
+            }
+        }
+    }
+}
+
+impl Shape for VoxelOctree {
+    fn hit(&self, orig: &Vec3f, dir: &Vec3f) -> Option<HitRecord> {
+        let inv_dir = Vec3f(1.0 / dir.0, 1.0 / dir.1, 1.0 / dir.2);
+        self.descend(self.nodes[0], self.origin, self.side(), orig, dir, &inv_dir)
+    }
+
+    fn bounds(&self) -> Aabb {
+        let side = self.side();
+        Aabb::new(self.origin, self.origin + Vec3f(side, side, side))
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut issues = check_point("origin", &self.origin);
+        issues.extend(check_dimensions(&[("voxel size", self.voxel_size)]));
+        if self.is_empty() {
+            issues.push(Diagnostic::warning("voxel model has no voxels".to_string()));
+        }
+        issues
+    }
+}
+
+// Where a ray is inside a cube, and the axes of the faces it crosses in and out through
+struct Span {
+    near: Float,
+    far: Float,
+    near_axis: usize,
+    far_axis: usize,
+}
+
+fn slab(min: &Vec3f, side: Float, orig: &Vec3f, inv_dir: &Vec3f) -> Option<Span> {
+    let mut span = Span {
+        near: Float::NEG_INFINITY,
+        far: Float::INFINITY,
+        near_axis: 0,
+        far_axis: 0,
+    };
+    for axis in 0..3 {
+        let t0 = (min[axis] - orig[axis]) * inv_dir[axis];
+        let t1 = (min[axis] + side - orig[axis]) * inv_dir[axis];
+        let (t0, t1) = if t0 <= t1 { (t0, t1) } else { (t1, t0) };
+        if t0 > span.near {
+            span.near = t0;
+            span.near_axis = axis;
+        }
+        if t1 < span.far {
+            span.far = t1;
+            span.far_axis = axis;
+        }
+    }
+    (span.near <= span.far && span.far > 0.0).then_some(span)
+}
+
+// Interleaves the bits of the coordinates, x lowest, so each three bits from the top
+// pick the child at one level
+fn morton(position: &[u32; 3]) -> u64 {
+    let mut key = 0;
+    for bit in 0..21 {
+        for (axis, p) in position.iter().enumerate() {
+            key |= ((*p as u64 >> bit) & 1) << (3 * bit + axis);
+        }
+    }
+    key
+}
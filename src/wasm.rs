@@ -0,0 +1,90 @@
+// Entry points for running the renderer in a browser. The module has no imports: the
+// page copies scene JSON into linear memory and reads RGBA pixels back out of it.
+use std::cell::RefCell;
+use std::io;
+
+use crate::render::{render, RenderSettings};
+use crate::scene_file::SceneFile;
+
+thread_local! {
+    static LAST_ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+// A buffer of len bytes for the caller to fill, released with rr_free
+#[no_mangle]
+pub extern "C" fn rr_alloc(len: usize) -> *mut u8 {
+    let mut buffer = Vec::<u8>::with_capacity(len);
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+/// # Safety
+/// ptr must come from rr_alloc or rr_render with the same len, and not be freed twice.
+#[no_mangle]
+pub unsafe extern "C" fn rr_free(ptr: *mut u8, len: usize) {
+    if !ptr.is_null() {
+        drop(Vec::from_raw_parts(ptr, 0, len));
+    }
+}
+
+/// Renders the scene file held in the len bytes at scene as a width×height image. Returns
+/// width * height * 4 bytes of straight-alpha RGBA, the layout ImageData takes, or null
+/// with the reason left for rr_error.
+///
+/// # Safety
+/// scene must point to len readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rr_render(
+    scene: *const u8,
+    len: usize,
+    width: usize,
+    height: usize,
+) -> *mut u8 {
+    let text = std::slice::from_raw_parts(scene, len);
+    match render_json(text, width, height) {
+        Ok(mut pixels) => {
+            // rr_free rebuilds the Vec from its length, so capacity must match it
+            pixels.shrink_to_fit();
+            let ptr = pixels.as_mut_ptr();
+            std::mem::forget(pixels);
+            ptr
+        }
+        Err(e) => {
+            LAST_ERROR.with(|error| *error.borrow_mut() = e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+// The message of the last failed render, as UTF-8 at rr_error with rr_error_len bytes
+#[no_mangle]
+pub extern "C" fn rr_error() -> *const u8 {
+    LAST_ERROR.with(|error| error.borrow().as_ptr())
+}
+
+#[no_mangle]
+pub extern "C" fn rr_error_len() -> usize {
+    LAST_ERROR.with(|error| error.borrow().len())
+}
+
+fn render_json(text: &[u8], width: usize, height: usize) -> io::Result<Vec<u8>> {
+    let text = std::str::from_utf8(text)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    if width == 0 || height == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("empty image size {}x{}", width, height),
+        ));
+    }
+    let file = SceneFile::parse(text)?;
+    let mut settings = RenderSettings::default();
+    file.apply(&mut settings);
+    let settings = RenderSettings {
+        width,
+        height,
+        threads: Some(1),
+        ..settings
+    };
+    Ok(render(&file.scene, &file.camera, &settings).to_rgba8())
+}
@@ -0,0 +1,166 @@
+// Ray intersection runs millions of times per frame, so it must not touch the heap.
+// This binary counts every allocation to check that, and holds a single test so no
+// other thread allocates while it measures.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rusty_rays::group::Group;
+use rusty_rays::material::{GLASS, IVORY, MIRROR, RED_RUBBER};
+use rusty_rays::mesh::{DisplacedMesh, TriangleMesh};
+use rusty_rays::point_cloud::PointCloud;
+use rusty_rays::scene::{Checkerboard, Scene};
+use rusty_rays::shapes::{Cone, Cube, Cylinder, Ovoid, Pyramid, RecgtangularPrism, Sphere, Torus};
+use rusty_rays::texture::ImageTexture;
+use rusty_rays::transform::Transform;
+use rusty_rays::vec3::{consts::PI, Float, Vec3f};
+use rusty_rays::voxel::VoxelOctree;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// One of every kind of shape, some of them inside a transformed group
+fn build_scene() -> Scene {
+    let mut scene = Scene::new();
+    scene.add(Sphere::new(Vec3f(-3.0, 0.0, -16.0), 2.0), IVORY);
+    scene.add(
+        RecgtangularPrism::new(Vec3f(-8.0, -3.0, -20.0), Vec3f(-6.0, -1.0, -18.0)),
+        GLASS,
+    );
+    scene.add(Cube::new(Vec3f(6.0, -2.0, -14.0), 1.5), RED_RUBBER);
+    scene.add(Cone::new(Vec3f(0.0, 3.0, -20.0), 3.0, 1.5), IVORY);
+    scene.add(Cylinder::new(Vec3f(4.0, -3.0, -22.0), 3.0, 1.0), MIRROR);
+    scene.add(Pyramid::new(Vec3f(-4.0, -3.0, -12.0), 2.0, 1.0), RED_RUBBER);
+    scene.add(
+        Ovoid::new(Vec3f(2.0, 2.0, -12.0), Vec3f(1.0, 0.5, 0.8)),
+        GLASS,
+    );
+    scene.add(Torus::new(Vec3f(0.0, -2.0, -15.0), 0.4, 1.5), MIRROR);
+
+    let vertices = vec![
+        Vec3f(-1.0, 0.0, -1.0),
+        Vec3f(1.0, 0.0, -1.0),
+        Vec3f(1.0, 0.0, 1.0),
+        Vec3f(-1.0, 0.0, 1.0),
+        Vec3f(0.0, 1.5, 0.0),
+    ];
+    let faces = vec![
+        [0, 1, 4],
+        [1, 2, 4],
+        [2, 3, 4],
+        [3, 0, 4],
+        [0, 2, 1],
+        [0, 3, 2],
+    ];
+    let mut group = Group::with_transform(
+        Transform::rotation(Vec3f(0.0, 1.0, 0.0), PI / 5.0)
+            .then(&Transform::translation(Vec3f(-1.0, 1.0, -10.0))),
+    );
+    group.add(TriangleMesh::new(vertices.clone(), faces.clone()), IVORY);
+    scene.add_group(group);
+
+    let uvs = vertices
+        .iter()
+        .map(|v| (0.5 + 0.5 * v.0, 0.5 + 0.5 * v.2))
+        .collect();
+    let texels = (0..16)
+        .map(|i| Vec3f(1.0, 1.0, 1.0) * (i as Float / 15.0))
+        .collect();
+    let mut displaced = DisplacedMesh::new(
+        TriangleMesh::new(vertices, faces),
+        uvs,
+        ImageTexture::new(4, 4, texels),
+        0.2,
+    );
+    displaced.subdivisions = 6;
+    scene.add_group({
+        let mut group = Group::with_transform(Transform::translation(Vec3f(3.0, 0.0, -11.0)));
+        group.add(displaced, GLASS);
+        group
+    });
+
+    let points = (0..64)
+        .map(|i| Vec3f((i % 8) as Float * 0.3 - 1.0, (i / 8) as Float * 0.3, -13.0))
+        .collect();
+    scene.add(PointCloud::new(points, 0.1), IVORY);
+
+    let voxels: Vec<_> = (0..64u32)
+        .filter(|i| (i % 4 + i / 4 % 4 + i / 16) % 2 == 0)
+        .map(|i| ([i % 4, i / 4 % 4, i / 16], Vec3f(1.0, 0.5, 0.2)))
+        .collect();
+    scene.add(
+        VoxelOctree::new(&voxels, Vec3f(-7.0, -3.0, -14.0), 0.5),
+        RED_RUBBER,
+    );
+
+    scene.floor = Some(Checkerboard {
+        height: -4.0,
+        min: (-10.0, -30.0),
+        max: (10.0, -10.0),
+        colors: [Vec3f(0.3, 0.3, 0.3), Vec3f(0.3, 0.2, 0.1)],
+    });
+    scene
+}
+
+// A fan of rays from the origin covering the whole scene, plus shadow rays toward a light
+fn cast_rays(scene: &Scene) -> usize {
+    let light = Vec3f(-20.0, 20.0, 20.0);
+    let mut hits = 0;
+    for y in 0..48 {
+        for x in 0..64 {
+            let dir = Vec3f(x as Float / 32.0 - 1.0, 0.75 - y as Float / 32.0, -1.0)
+                .normalized()
+                .unwrap();
+            let Some(hit) = scene.intersect(&Vec3f(0.0, 0.0, 0.0), &dir) else {
+                continue;
+            };
+            hits += 1;
+            let to_light = light - hit.record.point;
+            let shadow_dir = to_light.normalized().unwrap();
+            let shadow_orig = hit.record.point + hit.record.normal * 1e-3;
+            if scene.occluded(&shadow_orig, &shadow_dir, to_light.length()) {
+                hits += 1;
+            }
+        }
+    }
+    hits
+}
+
+#[test]
+fn intersection_does_not_allocate() {
+    let scene = build_scene();
+    // The first pass builds the scene BVH and grows per-thread scratch buffers
+    let warm = cast_rays(&scene);
+    assert!(warm > 300, "too few rays hit the scene: {}", warm);
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let hits = cast_rays(&scene);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+    assert_eq!(hits, warm);
+    assert_eq!(
+        allocations, 0,
+        "{} allocations while casting rays",
+        allocations
+    );
+}
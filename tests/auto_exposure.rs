@@ -0,0 +1,68 @@
+// Auto-exposure brings scenes lit far too dim or too bright to the same brightness
+
+use rusty_rays::camera::Camera;
+use rusty_rays::exposure::AutoExposure;
+use rusty_rays::framebuffer::Framebuffer;
+use rusty_rays::light::Light;
+use rusty_rays::material::IVORY;
+use rusty_rays::render::{render, RenderSettings};
+use rusty_rays::scene::Scene;
+use rusty_rays::scene_file::SceneFile;
+use rusty_rays::shapes::Sphere;
+use rusty_rays::vec3::{Float, Vec3f};
+
+fn lit(intensity: Float) -> Scene {
+    let mut scene = Scene::new();
+    scene.background = Vec3f(0.0, 0.0, 0.0);
+    scene.add(Sphere::new(Vec3f(0.0, 0.0, -5.0), 3.0), IVORY);
+    scene
+        .lights
+        .push(Light::new(Vec3f(0.0, 5.0, 0.0), intensity));
+    scene
+}
+
+fn log_average(image: &Framebuffer) -> Float {
+    let sum: Float = image
+        .pixels
+        .iter()
+        .map(|c| (0.2126 * c.0 + 0.7152 * c.1 + 0.0722 * c.2 + 1e-4).ln())
+        .sum();
+    (sum / image.pixels.len() as Float).exp()
+}
+
+#[test]
+fn exposure_follows_the_scene_and_compensation_applies_on_top() {
+    let settings = RenderSettings {
+        width: 64,
+        height: 48,
+        auto_exposure: Some(AutoExposure::default()),
+        ..RenderSettings::default()
+    };
+    let camera = Camera::new(Vec3f(0.0, 0.0, 0.0), 1.0);
+    let dim = log_average(&render(&lit(0.01), &camera, &settings));
+    let bright = log_average(&render(&lit(20.0), &camera, &settings));
+    assert!(
+        (dim / bright - 1.0).abs() < 0.05,
+        "{} against {}",
+        dim,
+        bright
+    );
+
+    // The camera's own exposure still counts, as compensation
+    let brighter = log_average(&render(
+        &lit(0.01),
+        &camera.clone().with_exposure_compensation(1.0),
+        &settings,
+    ));
+    assert!(brighter > dim * 1.5, "{} against {}", brighter, dim);
+
+    // A black frame is left alone
+    let mut dark = lit(0.0);
+    dark.ambient_lights.clear();
+    let black = render(&dark, &camera, &settings);
+    assert!(black.pixels.iter().all(|c| c.0.is_finite()));
+
+    let file = SceneFile::parse(r#"{"render": {"auto_exposure": {"key": 0.3}}}"#).unwrap();
+    assert_eq!(file.render.auto_exposure.map(|a| a.key), Some(0.3));
+    assert!(SceneFile::parse(r#"{"render": {"auto_exposure": {"key": 0}}}"#).is_err());
+}
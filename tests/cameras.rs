@@ -0,0 +1,23 @@
+// Scene files name extra cameras, the first standing in for a missing default
+
+use rusty_rays::scene_file::SceneFile;
+use rusty_rays::vec3::Vec3f;
+
+#[test]
+fn named_cameras_keep_their_order() {
+    let file = SceneFile::parse(
+        r#"{"cameras": {"right": {"position": [1, 0, 0]}, "left": {"position": [-1, 0, 0], "fov": 30}}}"#,
+    )
+    .unwrap();
+    let names: Vec<&str> = file.cameras.iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(names, ["right", "left"]);
+    assert_eq!(file.camera.position, Vec3f(1.0, 0.0, 0.0));
+    assert!((file.cameras[1].1.fov.to_degrees() - 30.0).abs() < 1e-4);
+
+    let file = SceneFile::parse(
+        r#"{"camera": {"position": [0, 5, 0]}, "cameras": {"side": {"position": [9, 0, 0]}}}"#,
+    )
+    .unwrap();
+    assert_eq!(file.camera.position, Vec3f(0.0, 5.0, 0.0));
+    assert!(SceneFile::parse(r#"{"cameras": {"side": {"zoom": 2}}}"#).is_err());
+}
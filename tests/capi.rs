@@ -0,0 +1,152 @@
+// include/rusty_rays.h is written by hand, so it is held to src/capi.rs here: a C program
+// built against the header reports its constants and struct layouts to compare with the
+// Rust definitions, and the functions it declares must be the ones the crate exports.
+#![cfg(feature = "capi")]
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::mem::{offset_of, size_of};
+use std::path::Path;
+use std::process::Command;
+
+use rusty_rays::capi::{RrCamera, RrMaterial, RR_INVALID_ID, RR_PATH, RR_WHITTED};
+
+const PROGRAM: &str = r#"#include <stdio.h>
+#include "rusty_rays.h"
+
+#define FIELD(type, field) printf(#type "." #field " %zu %zu\n", \
+    offsetof(type, field), sizeof(((type *)0)->field))
+
+int main(void) {
+    printf("RR_INVALID_ID %lu\n", (unsigned long)RR_INVALID_ID);
+    printf("RR_WHITTED %d\nRR_PATH %d\n", RR_WHITTED, RR_PATH);
+    printf("rr_material %zu\nrr_camera %zu\n", sizeof(rr_material), sizeof(rr_camera));
+    FIELD(rr_material, diffuse);
+    FIELD(rr_material, albedo);
+    FIELD(rr_material, specular_exponent);
+    FIELD(rr_material, refractive_index);
+    FIELD(rr_camera, position);
+    FIELD(rr_camera, target);
+    FIELD(rr_camera, up);
+    FIELD(rr_camera, fov);
+    return 0;
+}
+"#;
+
+fn root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+// Names of the form rr_... followed by an opening parenthesis
+fn functions(text: &str) -> BTreeSet<String> {
+    text.match_indices("rr_")
+        .filter_map(|(at, _)| {
+            let name: String = text[at..]
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+                .collect();
+            let before = text[..at].chars().next_back();
+            let call = text[at + name.len()..].starts_with('(');
+            (call && !matches!(before, Some(c) if c.is_ascii_alphanumeric() || c == '_'))
+                .then_some(name)
+        })
+        .collect()
+}
+
+#[test]
+fn header_declares_what_the_crate_exports() {
+    let header = fs::read_to_string(root().join("include/rusty_rays.h")).unwrap();
+    let source = fs::read_to_string(root().join("src/capi.rs")).unwrap();
+    let exported: BTreeSet<String> = source
+        .split("#[no_mangle]")
+        .skip(1)
+        .map(|item| {
+            let start = item.find("fn ").unwrap() + 3;
+            let end = item[start..].find('(').unwrap() + start;
+            item[start..end].to_string()
+        })
+        .collect();
+    assert!(exported.contains("rr_scene_render"), "{:?}", exported);
+    assert_eq!(functions(&header), exported);
+}
+
+#[test]
+fn header_matches_the_rust_layouts() {
+    let dir = std::env::temp_dir().join("rusty_rays_capi_header");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("layout.c"), PROGRAM).unwrap();
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let built = Command::new(&compiler)
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(root().join("include"))
+        .arg(dir.join("layout.c"))
+        .arg("-o")
+        .arg(dir.join("layout"))
+        .output();
+    let built = match built {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            eprintln!("no C compiler {} to check the header with", compiler);
+            return;
+        }
+        result => result.unwrap(),
+    };
+    assert!(
+        built.status.success(),
+        "{}",
+        String::from_utf8_lossy(&built.stderr)
+    );
+    let run = Command::new(dir.join("layout")).output().unwrap();
+    assert!(run.status.success());
+    let reported = String::from_utf8(run.stdout).unwrap();
+
+    let field = |offset: usize, size: usize| format!("{} {}", offset, size);
+    let expected = [
+        ("RR_INVALID_ID", RR_INVALID_ID.to_string()),
+        ("RR_WHITTED", RR_WHITTED.to_string()),
+        ("RR_PATH", RR_PATH.to_string()),
+        ("rr_material", size_of::<RrMaterial>().to_string()),
+        ("rr_camera", size_of::<RrCamera>().to_string()),
+        (
+            "rr_material.diffuse",
+            field(offset_of!(RrMaterial, diffuse), size_of::<[f32; 3]>()),
+        ),
+        (
+            "rr_material.albedo",
+            field(offset_of!(RrMaterial, albedo), size_of::<[f32; 4]>()),
+        ),
+        (
+            "rr_material.specular_exponent",
+            field(offset_of!(RrMaterial, specular_exponent), size_of::<f32>()),
+        ),
+        (
+            "rr_material.refractive_index",
+            field(offset_of!(RrMaterial, refractive_index), size_of::<f32>()),
+        ),
+        (
+            "rr_camera.position",
+            field(offset_of!(RrCamera, position), size_of::<[f32; 3]>()),
+        ),
+        (
+            "rr_camera.target",
+            field(offset_of!(RrCamera, target), size_of::<[f32; 3]>()),
+        ),
+        (
+            "rr_camera.up",
+            field(offset_of!(RrCamera, up), size_of::<[f32; 3]>()),
+        ),
+        (
+            "rr_camera.fov",
+            field(offset_of!(RrCamera, fov), size_of::<f32>()),
+        ),
+    ];
+    let lines: Vec<&str> = reported.lines().collect();
+    assert_eq!(lines.len(), expected.len(), "{}", reported);
+    for (line, (name, value)) in lines.iter().zip(&expected) {
+        assert_eq!(*line, format!("{} {}", name, value));
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
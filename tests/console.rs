@@ -0,0 +1,54 @@
+// The --repl command language, driven the way a session would drive it
+
+use rusty_rays::camera::Camera;
+use rusty_rays::console::{Console, Reply};
+use rusty_rays::render::RenderSettings;
+use rusty_rays::scene::Scene;
+use rusty_rays::vec3::{consts::PI, Float, Vec3f};
+
+fn console() -> Console {
+    Console::new(
+        Scene::new(),
+        Camera::new(Vec3f(0.0, 0.0, 0.0), PI / 3.0),
+        RenderSettings::default(),
+    )
+}
+
+#[test]
+fn commands_edit_the_scene() {
+    let mut console = console();
+    let reply = console.execute("add sphere 0 0 -10 2 glass").unwrap();
+    assert_eq!(reply, Reply::Continue(Some("object 1".to_string())));
+    console.execute("add box 0 0 0 1 1 1").unwrap();
+    console.execute("add light 10 10 0 1.5").unwrap();
+    console.execute("set camera fov 60").unwrap();
+    console.execute("set resolution 320x240").unwrap();
+    console.execute("# a comment").unwrap();
+    console.execute("").unwrap();
+    assert_eq!(console.scene.objects().len(), 2);
+    assert_eq!(console.scene.lights.len(), 1);
+    assert!((console.camera.fov - (60.0 as Float).to_radians()).abs() < 1e-6);
+    assert_eq!(
+        (console.settings.width, console.settings.height),
+        (320, 240)
+    );
+    assert_eq!(console.execute("quit").unwrap(), Reply::Quit);
+}
+
+#[test]
+fn bad_commands_change_nothing() {
+    let mut console = console();
+    for line in [
+        "add sphere 0 0 -10",
+        "add sphere 0 0 -10 2 unobtainium",
+        "add sphere 0 zero -10 2",
+        "add blob 0 0 0 1",
+        "set camera fov 200",
+        "set samples 0",
+        "frobnicate",
+    ] {
+        assert!(console.execute(line).is_err(), "{}", line);
+    }
+    assert!(console.scene.objects().is_empty());
+    assert_eq!(console.settings.samples_per_pixel, 1);
+}
@@ -0,0 +1,36 @@
+// Contact sheets step their parameter evenly and lay the thumbnails out in rows
+
+use rusty_rays::camera::Camera;
+use rusty_rays::contact_sheet::{ContactSheet, Parameter};
+use rusty_rays::framebuffer::Framebuffer;
+use rusty_rays::vec3::{consts::PI, Vec3f};
+
+#[test]
+fn thumbnails_fill_rows_in_order() {
+    let sheet = ContactSheet::new(Parameter::Orbit, 0.0, 90.0, 5);
+    assert_eq!(sheet.values(), vec![0.0, 22.5, 45.0, 67.5, 90.0]);
+    assert_eq!(sheet.columns, 3);
+    assert_eq!(
+        ContactSheet::new(Parameter::Orbit, 2.0, 4.0, 1).values(),
+        vec![2.0]
+    );
+
+    let thumbnails: Vec<Framebuffer> = (0..5)
+        .map(|i| {
+            let mut thumbnail = Framebuffer::new(10, 6);
+            thumbnail.pixels.fill(Vec3f(i as _, 0.0, 0.0));
+            thumbnail
+        })
+        .collect();
+    let image = sheet.compose(&thumbnails);
+    assert_eq!((image.width, image.height), (3 * 14 + 4, 2 * 10 + 4));
+    // The fifth lands in the second row's middle, and the last cell stays empty
+    assert_eq!(image.get(4 + 14 + 5, 4 + 10 + 3), Vec3f(4.0, 0.0, 0.0));
+    assert_eq!(image.get(4 + 5, 4 + 3), Vec3f(0.0, 0.0, 0.0));
+    assert_ne!(image.get(4 + 28 + 5, 4 + 10 + 3), Vec3f(4.0, 0.0, 0.0));
+
+    let camera = Camera::new(Vec3f(0.0, 1.0, 5.0), 1.0).looking_at(Vec3f(0.0, 1.0, 0.0));
+    let swung = camera.orbit(Vec3f(0.0, 0.0, 0.0), PI / 2.0);
+    assert!((swung.position - Vec3f(5.0, 1.0, 0.0)).length() < 1e-4);
+    assert!((swung.target - Vec3f(0.0, 1.0, 0.0)).length() < 1e-4);
+}
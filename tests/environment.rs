@@ -0,0 +1,113 @@
+// Cube maps show each face along its axis the right way round, with no seams where the
+// faces meet
+
+use rusty_rays::camera::Camera;
+use rusty_rays::environment::{CubeMap, Environment};
+use rusty_rays::render::{render, RenderSettings};
+use rusty_rays::scene::Scene;
+use rusty_rays::scene_file::SceneFile;
+use rusty_rays::texture::ImageTexture;
+use rusty_rays::vec3::{Float, Vec3f};
+
+const COLORS: [Vec3f; 6] = [
+    Vec3f(1.0, 0.0, 0.0),
+    Vec3f(0.0, 1.0, 0.0),
+    Vec3f(0.0, 0.0, 1.0),
+    Vec3f(1.0, 1.0, 0.0),
+    Vec3f(0.0, 1.0, 1.0),
+    Vec3f(1.0, 0.0, 1.0),
+];
+
+fn solid(size: usize, color: Vec3f) -> ImageTexture {
+    ImageTexture::new(size, size, vec![color; size * size])
+}
+
+fn close(a: Vec3f, b: Vec3f) -> bool {
+    (a - b).length() < 1e-4
+}
+
+#[test]
+fn faces_show_along_their_axes_and_blend_across_edges() {
+    let cube = CubeMap::new(COLORS.map(|color| solid(4, color))).unwrap();
+    let axes = [
+        Vec3f(1.0, 0.0, 0.0),
+        Vec3f(-1.0, 0.0, 0.0),
+        Vec3f(0.0, 1.0, 0.0),
+        Vec3f(0.0, -1.0, 0.0),
+        Vec3f(0.0, 0.0, 1.0),
+        Vec3f(0.0, 0.0, -1.0),
+    ];
+    for (axis, color) in axes.iter().zip(COLORS) {
+        assert!(close(cube.sample(axis), color), "{:?}", axis);
+    }
+    // Either side of the edge between +x and +y, and right on it, see the same half and
+    // half blend
+    let half = (COLORS[0] + COLORS[2]) * 0.5;
+    for lean in [-1e-3, 0.0, 1e-3] {
+        let dir = Vec3f(1.0, 1.0 + lean, 0.2).normalized().unwrap();
+        let seen = cube.sample(&dir);
+        assert!((seen - half).length() < 0.01, "{:?}", seen);
+    }
+    assert!(CubeMap::new([0, 1, 2, 3, 4, 5].map(|i| solid(4 + i / 5, COLORS[i]))).is_none());
+}
+
+#[test]
+fn faces_are_seen_from_inside() {
+    // White on the right half of the +x face, so looking along +x the white is towards +z,
+    // and on the top half of the +y face, which is towards +z looking up
+    let split = |on_right: bool| {
+        let texels = (0..16)
+            .map(|i| {
+                let (x, y) = (i % 4, i / 4);
+                let white = if on_right { x >= 2 } else { y < 2 };
+                if white {
+                    Vec3f(1.0, 1.0, 1.0)
+                } else {
+                    Vec3f(0.0, 0.0, 0.0)
+                }
+            })
+            .collect();
+        ImageTexture::new(4, 4, texels)
+    };
+    let mut faces = COLORS.map(|color| solid(4, color));
+    faces[0] = split(true);
+    faces[2] = split(false);
+    let sky = Environment::CubeMap(CubeMap::new(faces).unwrap());
+    let white = Vec3f(1.0, 1.0, 1.0);
+    assert!(close(
+        sky.sample(&Vec3f(1.0, 0.0, 0.5).normalized().unwrap()),
+        white
+    ));
+    assert!(close(
+        sky.sample(&Vec3f(1.0, 0.0, -0.5).normalized().unwrap()),
+        Vec3f(0.0, 0.0, 0.0)
+    ));
+    assert!(close(
+        sky.sample(&Vec3f(0.0, 1.0, 0.5).normalized().unwrap()),
+        white
+    ));
+
+    // An empty scene shows the -z face straight ahead
+    let mut scene = Scene::new();
+    scene.environment = Some(sky);
+    let settings = RenderSettings {
+        width: 8,
+        height: 8,
+        ..RenderSettings::default()
+    };
+    let image = render(&scene, &Camera::new(Vec3f(0.0, 0.0, 0.0), 0.5), &settings);
+    let center = image.pixels[4 * 8 + 4];
+    assert!(
+        (center - COLORS[5]).length() < 0.05 as Float,
+        "{:?}",
+        center
+    );
+}
+
+#[test]
+fn scene_files_need_six_faces() {
+    let text = r#"{"environment": {"cube_map": ["a.png", "b.png"]}}"#;
+    let error = SceneFile::parse(text).err().unwrap().to_string();
+    assert!(error.contains("six faces"), "{}", error);
+    assert!(SceneFile::parse(r#"{"environment": {"sphere": "a.png"}}"#).is_err());
+}
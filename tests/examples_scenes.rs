@@ -0,0 +1,78 @@
+// Every example scene must load cleanly and render the same way each time, since tests
+// and benchmarks compare against them
+
+use rusty_rays::examples_scenes::{example, generate, sphere_field, EXAMPLES, GENERATORS};
+use rusty_rays::render::{render, RenderSettings};
+use rusty_rays::scene::Severity;
+
+#[test]
+fn examples_validate_and_render() {
+    let settings = RenderSettings {
+        width: 32,
+        height: 24,
+        ..RenderSettings::default()
+    };
+    for (name, build) in EXAMPLES {
+        let (scene, camera) = build();
+        let errors: Vec<_> = scene
+            .validate()
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error)
+            .collect();
+        assert!(errors.is_empty(), "{name}: {errors:?}");
+        let image = render(&scene, &camera, &settings);
+        assert!(
+            image.pixels.iter().any(|c| c.0 + c.1 + c.2 > 0.0),
+            "{name} rendered black"
+        );
+    }
+    assert!(example("no_such_scene").is_none());
+}
+
+#[test]
+fn sphere_field_depends_only_on_seed() {
+    let positions = |seed| {
+        let (scene, _) = sphere_field(seed);
+        scene
+            .objects()
+            .iter()
+            .map(|o| o.shape.bounds().min)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(positions(7), positions(7));
+    assert_ne!(positions(7), positions(8));
+}
+
+#[test]
+fn generated_scenes_validate_and_follow_their_seed() {
+    let settings = RenderSettings {
+        width: 32,
+        height: 24,
+        ..RenderSettings::default()
+    };
+    let layout = |name, seed| {
+        let (scene, _) = generate(name, seed).unwrap();
+        scene
+            .objects()
+            .iter()
+            .map(|o| (o.shape.bounds().min, o.material.diffuse_color))
+            .collect::<Vec<_>>()
+    };
+    for (name, build) in GENERATORS {
+        let (scene, camera) = build(1);
+        let errors: Vec<_> = scene
+            .validate()
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error)
+            .collect();
+        assert!(errors.is_empty(), "{name}: {errors:?}");
+        let image = render(&scene, &camera, &settings);
+        assert!(
+            image.pixels.iter().any(|c| c.0 + c.1 + c.2 > 0.0),
+            "{name} rendered black"
+        );
+        assert_eq!(layout(name, 5), layout(name, 5), "{name}");
+        assert_ne!(layout(name, 5), layout(name, 6), "{name}");
+    }
+    assert!(generate("no_such_generator", 0).is_none());
+}
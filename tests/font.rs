@@ -0,0 +1,182 @@
+// Fonts read from TrueType files and the text meshes built from them. The fixture
+// tiny.ttf holds four glyphs in a 1000-unit em: none for missing characters; a 500-unit
+// square with one off-curve corner for A; for U+00C1 a composite of that square and a
+// copy at half size moved to (100, 600); and a triangle for U+1F600. Its format 4
+// character map maps A and U+00C1, the first through a delta and the second through the
+// glyph array, and its format 12 map adds U+1F600 and three characters whose glyph ids
+// run past what a u16 holds.
+
+use std::path::Path;
+
+use rusty_rays::font::{Contour, Font};
+use rusty_rays::text::Text3D;
+use rusty_rays::vec3::Float;
+
+fn fixture() -> Vec<u8> {
+    std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tiny.ttf")).unwrap()
+}
+
+fn bounds(contour: &Contour) -> [Float; 4] {
+    contour.iter().fold(
+        [Float::MAX, Float::MAX, Float::MIN, Float::MIN],
+        |[x0, y0, x1, y1], &(x, y)| [x0.min(x), y0.min(y), x1.max(x), y1.max(y)],
+    )
+}
+
+fn close(a: [Float; 4], b: [Float; 4]) -> bool {
+    a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 1e-5)
+}
+
+#[test]
+fn maps_characters_through_either_character_map() {
+    let data = fixture();
+    let font = Font::parse(data.clone()).unwrap();
+    assert_eq!(font.glyph('A'), Some(1));
+    assert_eq!(font.glyph('\u{c1}'), Some(2));
+    assert_eq!(font.glyph('\u{1f600}'), Some(3));
+    assert_eq!(font.glyph('B'), None);
+    // 0xffffffff overflows once the group's offset is added, and 0x10003 is past a u16
+    for c in ['\u{1f601}', '\u{1f602}', '\u{1f603}'] {
+        assert_eq!(font.glyph(c), None, "{:?}", c);
+    }
+    assert!((font.ascender - 0.8).abs() < 1e-6);
+    assert!((font.descender + 0.2).abs() < 1e-6);
+    assert!((font.advance(1) - 0.6).abs() < 1e-6);
+    // Glyphs past the metrics share the last advance
+    assert_eq!(font.advance(3), font.advance(2));
+
+    // Marking the format 12 map as a symbol encoding leaves only format 4
+    let mut bmp_only = data;
+    let record = bmp_only
+        .windows(4)
+        .position(|w| w == [0, 3, 0, 10])
+        .unwrap();
+    bmp_only[record + 3] = 0;
+    let font = Font::parse(bmp_only).unwrap();
+    assert_eq!(font.glyph('A'), Some(1));
+    assert_eq!(font.glyph('\u{c1}'), Some(2));
+    assert_eq!(font.glyph('\u{1f600}'), None);
+}
+
+#[test]
+fn outlines_simple_and_composite_glyphs() {
+    let font = Font::parse(fixture()).unwrap();
+    let square = font.outline(1, 4).unwrap();
+    assert_eq!(square.len(), 1);
+    // Two straight sides, then four pieces of the curve round the off-curve corner
+    assert_eq!(square[0].len(), 6);
+    assert!(close(bounds(&square[0]), [0.0, 0.0, 0.5, 0.5]));
+    assert!(square[0]
+        .iter()
+        .any(|&(x, y)| (x - 0.375).abs() < 1e-5 && (y - 0.375).abs() < 1e-5));
+
+    let composite = font.outline(2, 4).unwrap();
+    assert_eq!(composite.len(), 2);
+    assert!(close(bounds(&composite[0]), [0.0, 0.0, 0.5, 0.5]));
+    assert!(close(bounds(&composite[1]), [0.1, 0.6, 0.35, 0.85]));
+    assert!(font.outline(0, 4).unwrap().is_empty());
+    assert!(font.outline(9, 4).is_err());
+}
+
+#[test]
+fn refuses_truncated_and_corrupt_fonts() {
+    let data = fixture();
+    // Cut anywhere, the file either fails to parse or still outlines without panicking
+    for end in 0..data.len() {
+        if let Ok(font) = Font::parse(data[..end].to_vec()) {
+            for glyph in 0..4 {
+                let _ = font.outline(glyph, 4);
+            }
+        }
+    }
+    assert!(Font::parse(data[..data.len() - 40].to_vec()).is_err());
+
+    // The square's only contour claiming to end at point 200 runs off its table
+    let mut corrupt = data.clone();
+    let font = Font::parse(data).unwrap();
+    let glyf = corrupt.windows(4).position(|w| w == b"glyf").unwrap();
+    let offset = u32::from_be_bytes(corrupt[glyf + 8..glyf + 12].try_into().unwrap()) as usize;
+    // Glyph 0 is empty, so the square opens the table; its contour end follows the bounds
+    corrupt[offset + 10..offset + 12].copy_from_slice(&200u16.to_be_bytes());
+    let corrupt = Font::parse(corrupt).unwrap();
+    assert!(font.outline(1, 4).is_ok());
+    assert!(corrupt.outline(1, 4).is_err());
+    assert!(corrupt.outline(2, 4).is_err());
+}
+
+// Every glyph outlined and every character of the fixture looked up and built
+fn exercise(data: Vec<u8>) {
+    let Ok(font) = Font::parse(data) else {
+        return;
+    };
+    for glyph in 0..5 {
+        let _ = font.outline(glyph, 4);
+        let _ = font.advance(glyph);
+    }
+    let text = "A\u{c1}\u{1f600}\u{1f601}\u{1f603}B";
+    for c in text.chars() {
+        let _ = font.glyph(c);
+    }
+    let _ = Text3D::default().to_mesh(&font, text);
+}
+
+#[test]
+fn survives_corruption_anywhere() {
+    let data = fixture();
+    for at in 0..data.len() {
+        for value in [
+            0x00,
+            0xff,
+            0x7f,
+            0x80,
+            data[at] ^ 0x01,
+            data[at].wrapping_add(2),
+        ] {
+            let mut corrupt = data.clone();
+            corrupt[at] = value;
+            exercise(corrupt);
+        }
+    }
+    // Then several bytes at once, at places picked by a fixed xorshift sequence
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    for _ in 0..5000 {
+        let mut corrupt = data.clone();
+        for _ in 0..1 + next() % 8 {
+            let at = next() as usize % corrupt.len();
+            corrupt[at] = next() as u8;
+        }
+        exercise(corrupt);
+    }
+}
+
+#[test]
+fn builds_text_into_a_slab() {
+    let font = Font::parse(fixture()).unwrap();
+    let text = Text3D {
+        depth: 0.2,
+        ..Text3D::default()
+    };
+    let mesh = text.to_mesh(&font, "A\u{c1}").unwrap();
+    assert!(!mesh.is_empty());
+    for v in mesh.vertices() {
+        assert!((-1e-5..=1.1 + 1e-5).contains(&v.0), "{:?}", v);
+        assert!((-0.2 - 1e-5..=1e-5).contains(&v.2), "{:?}", v);
+    }
+    // The second glyph starts one advance along and carries its raised part
+    assert!(mesh.vertices().iter().any(|v| v.0 > 0.6 && v.1 > 0.6));
+    // Missing characters show the empty glyph 0 and move the pen by nothing drawn
+    assert!(text.to_mesh(&font, "B").unwrap().is_empty());
+    let two_lines = text.to_mesh(&font, "A\nA").unwrap();
+    let lowest = two_lines
+        .vertices()
+        .iter()
+        .map(|v| v.1)
+        .fold(Float::MAX, Float::min);
+    assert!((lowest + 1.1).abs() < 1e-5, "{}", lowest);
+}
@@ -0,0 +1,67 @@
+// Framing moves a camera stuck inside the scene out to where all of it shows
+
+use rusty_rays::camera::Camera;
+use rusty_rays::material::IVORY;
+use rusty_rays::render::{render, RenderSettings};
+use rusty_rays::scene::Scene;
+use rusty_rays::shapes::{Cube, Sphere};
+use rusty_rays::vec3::{consts::PI, Vec3f};
+
+#[test]
+fn framing_fits_the_whole_scene_in_view() {
+    let mut scene = Scene::new();
+    assert!(
+        scene.bounds().min.0 > scene.bounds().max.0,
+        "an empty scene has empty bounds"
+    );
+    let unchanged = Camera::new(Vec3f(1.0, 2.0, 3.0), PI / 3.0).frame(&scene, 0.1);
+    assert_eq!(unchanged.position, Vec3f(1.0, 2.0, 3.0));
+
+    scene.add(Cube::new(Vec3f(0.0, 0.0, -5.0), 8.0), IVORY);
+    scene.add(Sphere::new(Vec3f(10.0, 0.0, -5.0), 1.0), IVORY);
+    let bounds = scene.bounds();
+    assert_eq!(bounds.min, Vec3f(-4.0, -4.0, -9.0));
+    assert_eq!(bounds.max, Vec3f(11.0, 4.0, -1.0));
+
+    // Starting inside the cube, looking down -z with the far plane too close
+    let camera = Camera::new(Vec3f(0.0, 0.0, -5.0), PI / 3.0).with_clipping(0.5, 2.0);
+    let framed = camera.clone().frame(&scene, 0.1);
+    assert_eq!(framed.target, bounds.centroid());
+    let forward = (framed.target - framed.position).normalized().unwrap();
+    assert!((forward - Vec3f(0.0, 0.0, -1.0)).length() < 1e-5);
+    for x in [bounds.min.0, bounds.max.0] {
+        for y in [bounds.min.1, bounds.max.1] {
+            for z in [bounds.min.2, bounds.max.2] {
+                let to_corner = Vec3f(x, y, z) - framed.position;
+                let depth = to_corner.dot(&forward);
+                assert!(depth > framed.near && depth < framed.far);
+                let angle = (depth / to_corner.length()).acos();
+                assert!(angle < PI / 6.0, "corner {:?} out of view", (x, y, z));
+            }
+        }
+    }
+
+    // From inside, the camera sees nothing but the cube's back faces; from outside the
+    // sphere beside it shows too
+    let settings = RenderSettings {
+        width: 32,
+        height: 32,
+        ..RenderSettings::default()
+    };
+    let ids = |camera: &Camera| {
+        let image = render(
+            &scene,
+            camera,
+            &RenderSettings {
+                object_ids: true,
+                ..settings.clone()
+            },
+        );
+        let mut ids = image.object_ids().unwrap();
+        ids.sort();
+        ids.dedup();
+        ids
+    };
+    assert!(!ids(&camera).contains(&2));
+    assert!(ids(&framed).contains(&2));
+}
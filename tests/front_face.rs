@@ -0,0 +1,55 @@
+// Hits report which side of a surface the ray met, with the normal turned towards it
+
+use rusty_rays::camera::Camera;
+use rusty_rays::light::Light;
+use rusty_rays::material::{Material, Sides, IVORY};
+use rusty_rays::render::{render, RenderSettings};
+use rusty_rays::scene::Scene;
+use rusty_rays::shapes::{Shape, Sphere};
+use rusty_rays::vec3::Vec3f;
+
+#[test]
+fn normals_face_the_ray_from_either_side() {
+    let ball = Sphere::new(Vec3f(0.0, 0.0, 0.0), 1.0);
+    let dir = Vec3f(0.0, 0.0, -1.0);
+    // Shapes leave normals pointing out
+    let leaving = ball.hit(&Vec3f(0.0, 0.0, 0.0), &dir).unwrap();
+    assert!(!leaving.front_face);
+    assert!(leaving.normal.dot(&dir) > 0.0);
+
+    let mut scene = Scene::new();
+    scene.add(ball, IVORY);
+    for (orig, front) in [(Vec3f(0.0, 0.0, 5.0), true), (Vec3f(0.0, 0.0, 0.0), false)] {
+        let hit = scene.intersect(&orig, &dir).unwrap();
+        assert_eq!(hit.record.front_face, front);
+        assert!(hit.record.normal.dot(&dir) < 0.0);
+    }
+
+    // A surface showing both sides alike is entered from whichever side
+    let mut sheet = Scene::new();
+    sheet.add(
+        Sphere::new(Vec3f(0.0, 0.0, 0.0), 1.0),
+        Material {
+            sides: Sides::Both,
+            ..IVORY
+        },
+    );
+    let hit = sheet.intersect(&Vec3f(0.0, 0.0, 0.0), &dir).unwrap();
+    assert!(hit.record.front_face);
+    assert!(hit.record.normal.dot(&dir) < 0.0);
+}
+
+#[test]
+fn a_camera_inside_a_room_sees_its_lit_walls() {
+    let mut scene = Scene::new();
+    scene.add(Sphere::new(Vec3f(0.0, 0.0, 0.0), 10.0), IVORY);
+    scene.lights.push(Light::new(Vec3f(0.0, 5.0, 0.0), 1.0));
+    let settings = RenderSettings {
+        width: 16,
+        height: 16,
+        ..RenderSettings::default()
+    };
+    let image = render(&scene, &Camera::new(Vec3f(0.0, 0.0, 0.0), 1.0), &settings);
+    let lit = image.pixels.iter().filter(|c| c.0 > 0.05).count();
+    assert!(lit > image.pixels.len() / 2, "{} lit pixels", lit);
+}
@@ -0,0 +1,65 @@
+// Camera rays search only what the camera can see, and everything else still shows
+// where bounces or shadows reach it
+
+use rusty_rays::camera::{Aperture, Camera};
+use rusty_rays::light::Light;
+use rusty_rays::material::{IVORY, MIRROR, RED_RUBBER};
+use rusty_rays::render::{render, RenderSettings};
+use rusty_rays::scene::{with_camera_culling, Scene};
+use rusty_rays::shapes::Sphere;
+use rusty_rays::vec3::{Float, Vec3f};
+
+// A mirror ahead of the camera and a red ball behind it, which only the mirror shows
+fn mirrored(behind: bool) -> Scene {
+    let mut scene = Scene::new();
+    scene.add(Sphere::new(Vec3f(0.0, 0.0, -12.0), 3.0), MIRROR);
+    scene.add(Sphere::new(Vec3f(-6.0, 0.0, -12.0), 1.0), IVORY);
+    if behind {
+        scene.add(Sphere::new(Vec3f(0.0, 0.0, 6.0), 2.0), RED_RUBBER);
+    }
+    scene.lights.push(Light::new(Vec3f(0.0, 10.0, 0.0), 1.5));
+    scene
+}
+
+#[test]
+fn objects_out_of_view_are_culled_from_camera_rays_only() {
+    let (width, height) = (64, 48);
+    let scene = mirrored(true);
+    let camera = Camera::new(Vec3f(0.0, 0.0, 0.0), 1.0);
+    let frustum = camera.frustum(width, height, 1.0).unwrap();
+    let culling = scene
+        .camera_culling(&frustum)
+        .expect("the ball behind is out of view");
+    assert_eq!(culling.kept(), &[0, 1]);
+
+    // Camera rays land on the same surfaces either way, from anywhere on the lens
+    let blurred = camera.clone().with_aperture(Aperture::new(0.5, 12.0));
+    let culling = scene.camera_culling(&blurred.frustum(width, height, 1.0).unwrap());
+    let lens = [(0.0, 0.0), (1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)];
+    for y in 0..=height {
+        for x in 0..=width {
+            for lens_point in lens {
+                let (x, y) = (x as Float, y as Float);
+                let ray = blurred.channel_ray_differential(x, y, width, height, 1, lens_point);
+                let hit = |culling| {
+                    with_camera_culling(culling, || {
+                        scene
+                            .intersect_camera(&ray.orig, &ray.dir, 1e9)
+                            .map(|hit| (hit.object_id, hit.record.t))
+                    })
+                };
+                assert_eq!(hit(None), hit(culling.as_ref()));
+            }
+        }
+    }
+
+    // The mirror still shows the ball behind the camera
+    let settings = RenderSettings {
+        width,
+        height,
+        ..RenderSettings::default()
+    };
+    let with = render(&scene, &camera, &settings);
+    let without = render(&mirrored(false), &camera, &settings);
+    assert_ne!(with.to_rgba8(), without.to_rgba8());
+}
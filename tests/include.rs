@@ -0,0 +1,158 @@
+// Included components are read relative to themselves, use the materials of the files
+// that include them, and may not include themselves, however far round
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rusty_rays::framebuffer::Framebuffer;
+use rusty_rays::scene_file::SceneFile;
+use rusty_rays::vec3::Vec3f;
+
+// A fresh directory holding each (path, text) pair
+fn fixture(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&dir);
+    for (path, text) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+    dir
+}
+
+fn error(path: &Path) -> String {
+    match SceneFile::load(path) {
+        Ok(_) => panic!("{} loaded", path.display()),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn reads_nested_components_relative_to_themselves() {
+    let dir = fixture(
+        "rusty_rays_include_nested",
+        &[
+            (
+                "scene.json",
+                r#"{"materials": {"red": {"diffuse": [1, 0, 0]}},
+                    "objects": [{"type": "include", "file": "props/chair.json", "name": "chair",
+                                 "transform": {"translate": [5, 0, 0]}}]}"#,
+            ),
+            (
+                "props/chair.json",
+                r#"{"materials": {"blue": {"diffuse": [0, 0, 1]}},
+                    "objects": [
+                        {"type": "sphere", "name": "seat", "center": [0, 0, 0], "radius": 1,
+                         "material": "red"},
+                        {"type": "sphere", "name": "back", "center": [0, 2, 0], "radius": 1,
+                         "material": "blue", "texture": "wood.png"},
+                        {"type": "include", "file": "parts/leg.json"},
+                        {"type": "include", "file": "parts/leg.json"}]}"#,
+            ),
+            (
+                "props/parts/leg.json",
+                r#"{"objects": [{"type": "sphere", "name": "leg", "center": [0, -2, 0],
+                                 "radius": 0.5, "material": "blue"}]}"#,
+            ),
+        ],
+    );
+    Framebuffer::new(2, 2)
+        .write_png(&dir.join("props/wood.png"))
+        .unwrap();
+
+    let file = SceneFile::load(&dir.join("scene.json")).unwrap();
+    let scene = &file.scene;
+    assert!(scene.find_group("chair").is_some());
+    let seat = scene.get("seat").unwrap();
+    // Moved with the include, and red from the file that included it
+    let bounds = seat.shape.bounds();
+    assert_eq!(bounds.min, Vec3f(4.0, -1.0, -1.0));
+    assert_eq!(bounds.max, Vec3f(6.0, 1.0, 1.0));
+    assert_eq!(seat.material.diffuse_color, Vec3f(1.0, 0.0, 0.0));
+    let back = scene.get("back").unwrap();
+    assert_eq!(back.material.diffuse_color, Vec3f(0.0, 0.0, 1.0));
+    assert!(back.texture.is_some());
+    // Two of the same leg, each blue from the chair two levels up
+    let legs: Vec<_> = scene
+        .objects()
+        .iter()
+        .filter(|o| o.name.as_deref() == Some("leg"))
+        .collect();
+    assert_eq!(legs.len(), 2);
+    assert!(legs
+        .iter()
+        .all(|leg| leg.material.diffuse_color == Vec3f(0.0, 0.0, 1.0)));
+
+    for asset in ["props/chair.json", "props/wood.png", "props/parts/leg.json"] {
+        assert!(
+            file.assets.contains(&dir.join(asset)),
+            "{} not in {:?}",
+            asset,
+            file.assets
+        );
+    }
+
+    // A component's materials stay its own, and its paths its own as well
+    fs::write(
+        dir.join("scene.json"),
+        r#"{"materials": {"red": {"diffuse": [1, 0, 0]}},
+            "objects": [{"type": "include", "file": "props/chair.json"},
+            {"type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "blue"}]}"#,
+    )
+    .unwrap();
+    let message = error(&dir.join("scene.json"));
+    assert!(
+        message.contains("scene.objects[1].material: unknown material blue"),
+        "{}",
+        message
+    );
+    fs::write(
+        dir.join("scene.json"),
+        r#"{"objects": [{"type": "sphere", "center": [0, 0, 0], "radius": 1,
+            "texture": "wood.png"}]}"#,
+    )
+    .unwrap();
+    let message = error(&dir.join("scene.json"));
+    assert!(message.contains("wood.png"), "{}", message);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn refuses_files_that_include_themselves() {
+    let dir = fixture(
+        "rusty_rays_include_cycle",
+        &[
+            (
+                "loop.json",
+                r#"{"objects": [{"type": "include", "file": "loop.json"}]}"#,
+            ),
+            (
+                "a.json",
+                r#"{"objects": [{"type": "include", "file": "sub/b.json"}]}"#,
+            ),
+            (
+                "sub/b.json",
+                r#"{"objects": [{"type": "include", "file": "../a.json"}]}"#,
+            ),
+        ],
+    );
+
+    // Caught at the first include, with the file loaded counted as being included
+    let message = error(&dir.join("loop.json"));
+    assert!(message.contains("includes itself"), "{}", message);
+    assert_eq!(
+        message.matches("scene.objects[0].file").count(),
+        1,
+        "{}",
+        message
+    );
+    let message = error(&dir.join("a.json"));
+    assert!(message.contains("includes itself"), "{}", message);
+    assert_eq!(
+        message.matches("scene.objects[0].file").count(),
+        2,
+        "{}",
+        message
+    );
+    let _ = fs::remove_dir_all(&dir);
+}
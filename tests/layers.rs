@@ -0,0 +1,64 @@
+// Render layers hold out or hide everything outside them
+
+use rusty_rays::camera::Camera;
+use rusty_rays::layers::LayerRole;
+use rusty_rays::render::{render, RenderSettings};
+use rusty_rays::scene::{RayKind, Scene};
+use rusty_rays::scene_file::SceneFile;
+use rusty_rays::vec3::Vec3f;
+
+#[test]
+fn layers_hold_out_or_hide_the_rest() {
+    let mut file = SceneFile::parse(
+        r#"{"objects": [{"type": "sphere", "name": "hero", "center": [0, 0, -5], "radius": 1},
+            {"type": "group", "name": "set", "children": [
+                {"type": "sphere", "name": "rock", "center": [0, 0, -20], "radius": 8}]}],
+            "lights": [{"position": [0, 10, 0]}],
+            "layers": {"front": {"objects": ["set"]},
+                       "back": {"objects": ["set"], "others": "hidden"}}}"#,
+    )
+    .unwrap();
+    let names: Vec<&str> = file.layers.iter().map(|l| l.name.as_str()).collect();
+    assert_eq!(names, ["front", "back"]);
+    assert_eq!(file.layers[0].others, LayerRole::Holdout);
+    let (front, back) = (file.layers[0].clone(), file.layers[1].clone());
+    let scene = &mut file.scene;
+    let (orig, ahead) = (Vec3f(0.0, 0.0, 0.0), Vec3f(0.0, 0.0, -1.0));
+    let nearest = |scene: &Scene, kind| {
+        scene
+            .intersect_as(kind, &orig, &ahead, 100.0)
+            .map(|hit| (hit.record.t.round(), hit.holdout))
+    };
+
+    // Held out, the hero still stands in the way of every kind of ray
+    scene.set_layer(Some(&front)).unwrap();
+    assert_eq!(nearest(scene, RayKind::Camera), Some((4.0, true)));
+    assert_eq!(nearest(scene, RayKind::Shadow), Some((4.0, true)));
+    let settings = RenderSettings {
+        width: 9,
+        height: 9,
+        transparent_background: true,
+        ..RenderSettings::default()
+    };
+    let camera = Camera::new(orig, 1.0);
+    let image = render(scene, &camera, &settings);
+    let alpha = |x, y| image.alpha.as_ref().unwrap()[y * image.width + x];
+    assert_eq!(alpha(4, 4), 0.0);
+    assert_eq!(image.get(4, 4), Vec3f(0.0, 0.0, 0.0));
+    assert_eq!(alpha(1, 4), 1.0);
+
+    // Hidden, it is gone and the group behind it shows
+    scene.set_layer(Some(&back)).unwrap();
+    assert_eq!(nearest(scene, RayKind::Shadow), Some((12.0, false)));
+    scene.set_layer(None).unwrap();
+    assert_eq!(nearest(scene, RayKind::Camera), Some((4.0, false)));
+    assert_eq!(render(scene, &camera, &settings).alpha.unwrap()[40], 1.0);
+
+    for bad in [
+        r#"{"layers": {"a": {"objects": ["nobody"]}}}"#,
+        r#"{"layers": {"a": {"objects": [], "others": "shown"}}}"#,
+        r#"{"layers": {"a": {}}}"#,
+    ] {
+        assert!(SceneFile::parse(bad).is_err(), "{}", bad);
+    }
+}
@@ -0,0 +1,55 @@
+// Baked lightmaps hold the light on a mesh at its texture coordinates, padded at the edges
+
+use rusty_rays::lightmap::Lightmap;
+use rusty_rays::render::RenderSettings;
+use rusty_rays::scene_file::SceneFile;
+
+#[test]
+fn bakes_shadows_into_the_uv_layout() {
+    // A floor mapped to the lower left quarter of the lightmap, and a block over the part
+    // of it nearest the origin cutting off the light straight above
+    let file = SceneFile::parse(
+        r#"{"background": [0, 0, 0],
+            "objects": [
+              {"type": "mesh", "name": "floor",
+               "vertices": [[0, 0, 0], [0, 0, 4], [4, 0, 4], [4, 0, 0]],
+               "uvs": [[0, 0.5], [0, 0], [0.5, 0], [0.5, 0.5]], "faces": [[0, 1, 2], [0, 2, 3]]},
+              {"type": "box", "name": "block", "min": [0, 1, 0], "max": [1, 1.5, 1]},
+              {"type": "sphere", "name": "ball", "center": [0, 5, 0], "radius": 1}],
+            "lights": [{"position": [2, 10, 2], "intensity": 1},
+                       {"type": "ambient", "color": [0.1, 0.1, 0.1]}]}"#,
+    )
+    .unwrap();
+    let scene = &file.scene;
+    scene.build_bvh();
+    let lightmap = Lightmap {
+        width: 16,
+        height: 16,
+        samples: 4,
+        padding: 2,
+    };
+    let settings = RenderSettings {
+        max_depth: 1,
+        ..RenderSettings::default()
+    };
+    let image = lightmap.bake(scene, "floor", &settings).unwrap();
+    assert_eq!((image.width, image.height), (16, 16));
+    // The floor's (u, v) lands at texel (16u, 16(1 - v)), so x = 0 runs along its
+    // shadowed edge and the bottom right corner of the quarter is lit
+    let lit = image.get(7, 15);
+    let shadowed = image.get(0, 8);
+    assert!(lit.0 > 0.9, "{:?}", lit);
+    assert!(shadowed.0 < 0.5 && shadowed.0 >= 0.1, "{:?}", shadowed);
+    // Padding reaches two texels past the island and no further
+    assert!(image.get(9, 15).0 > 0.5);
+    assert_eq!(image.get(10, 15).0, 0.0);
+    assert_eq!(image.get(15, 0).0, 0.0);
+
+    for (name, message) in [
+        ("ball", "ball is not a mesh"),
+        ("missing", "no object named missing"),
+    ] {
+        let error = lightmap.bake(scene, name, &settings).unwrap_err();
+        assert_eq!(error.to_string(), message);
+    }
+}
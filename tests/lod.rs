@@ -0,0 +1,49 @@
+// Levels of detail switch with how large the shape appears from the camera
+
+use rusty_rays::camera::Camera;
+use rusty_rays::lod::View;
+use rusty_rays::scene_file::SceneFile;
+use rusty_rays::vec3::Vec3f;
+
+#[test]
+fn levels_follow_the_size_on_screen() {
+    // A sphere up close and its bounding cube from afar, moved away from the origin so
+    // the view has to follow the transform into the shape's space
+    let file = SceneFile::parse(
+        r#"{"objects": [{"type": "lod", "transform": {"translate": [0, 0, -10]}, "levels": [
+            {"object": {"type": "sphere", "center": [0, 0, 0], "radius": 1}},
+            {"below": 40, "object": {"type": "cube", "center": [0, 0, 0], "size": 2}}]}]}"#,
+    )
+    .unwrap();
+    let scene = &file.scene;
+    assert!(scene.validate().is_empty(), "{:?}", scene.validate());
+    let shape = &scene.objects()[0].shape;
+    let corner = |from: Vec3f| {
+        let dir = (Vec3f(0.95, 0.95, -10.0) - from).normalized().unwrap();
+        shape.hit(&from, &dir).is_some()
+    };
+
+    let near = Vec3f(0.0, 0.0, 0.0);
+    let view = View::new(&Camera::new(near, 1.0), 320, 240);
+    assert!(view.pixels(&Vec3f(0.0, 0.0, -10.0), 1.7) > 40.0);
+    scene.set_view(&view);
+    assert!(!corner(near), "the sphere should show up close");
+
+    let far = Vec3f(0.0, 0.0, 200.0);
+    let view = View::new(&Camera::new(far, 1.0), 320, 240);
+    assert!(view.pixels(&Vec3f(0.0, 0.0, -10.0), 1.7) < 40.0);
+    scene.set_view(&view);
+    assert!(corner(far), "the cube should show from afar");
+
+    for bad in [
+        r#"[{"below": 10, "object": {"type": "sphere", "center": [0, 0, 0], "radius": 1}}]"#,
+        r#"[{"object": {"type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "glass"}}]"#,
+        r#"[{"object": {"type": "sphere", "center": [0, 0, 0], "radius": 1}},
+            {"below": 10, "object": {"type": "sphere", "center": [0, 0, 0], "radius": 1}},
+            {"below": 20, "object": {"type": "sphere", "center": [0, 0, 0], "radius": 1}}]"#,
+        "[]",
+    ] {
+        let text = format!(r#"{{"objects": [{{"type": "lod", "levels": {}}}]}}"#, bad);
+        assert!(SceneFile::parse(&text).is_err(), "{}", bad);
+    }
+}
@@ -0,0 +1,49 @@
+// Overriding every material swaps in clay or a debug view of the surface
+
+use rusty_rays::material::{Emission, Material, MaterialOverride, CLAY, GLASS};
+use rusty_rays::scene::Scene;
+use rusty_rays::shapes::Sphere;
+use rusty_rays::vec3::Vec3f;
+
+#[test]
+fn overrides_replace_what_rays_meet() {
+    let mut scene = Scene::new();
+    scene.add(Sphere::new(Vec3f(0.0, 0.0, -5.0), 1.0), GLASS);
+    let lamp = Material {
+        emission: Some(Emission {
+            color: Vec3f(4.0, 4.0, 4.0),
+            two_sided: false,
+        }),
+        ..GLASS
+    };
+    scene.add(Sphere::new(Vec3f(0.0, 5.0, 0.0), 1.0), lamp);
+    let (orig, ahead, up) = (
+        Vec3f(0.0, 0.0, 0.0),
+        Vec3f(0.0, 0.0, -1.0),
+        Vec3f(0.0, 1.0, 0.0),
+    );
+    let material = |scene: &Scene, dir| scene.intersect(&orig, &dir).unwrap().material;
+
+    scene.material_override = Some(MaterialOverride::Clay);
+    let clay = material(&scene, ahead);
+    assert_eq!(clay.diffuse_color, CLAY.diffuse_color);
+    assert_eq!(clay.albedo[3], 0.0, "clay does not let light through");
+    assert_eq!(material(&scene, up).emission, lamp.emission);
+
+    // Facing the camera, the normal points back along +z
+    scene.material_override = Some(MaterialOverride::Normal);
+    let normal = material(&scene, ahead).emitted(true);
+    assert!(
+        (normal - Vec3f(0.5, 0.5, 1.0)).length() < 1e-4,
+        "{:?}",
+        normal
+    );
+
+    scene.material_override = None;
+    assert_eq!(material(&scene, ahead).albedo, GLASS.albedo);
+    assert_eq!(
+        MaterialOverride::from_name("uv"),
+        Some(MaterialOverride::Uv)
+    );
+    assert_eq!(MaterialOverride::from_name("wax"), None);
+}
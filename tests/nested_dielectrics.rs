@@ -0,0 +1,97 @@
+// Rays keep track of the solids they are inside, so refraction between touching or
+// overlapping ones uses both indices
+
+use rusty_rays::material::{Material, GLASS};
+use rusty_rays::medium_stack::MediumStack;
+use rusty_rays::scene::{Intersection, Scene};
+use rusty_rays::scene_file::SceneFile;
+use rusty_rays::shapes::Sphere;
+use rusty_rays::vec3::{Float, Vec3f};
+
+const WATER: Material = Material {
+    refractive_index: 1.33,
+    ..GLASS
+};
+
+// The next surface along -z from z, a little past it
+fn next(scene: &Scene, z: Float) -> (Intersection, Float) {
+    let orig = Vec3f(0.0, 0.0, z);
+    let hit = scene.intersect(&orig, &Vec3f(0.0, 0.0, -1.0)).unwrap();
+    let z = hit.record.point.2 - 1e-3;
+    (hit, z)
+}
+
+#[test]
+fn nested_solids_refract_into_each_other() {
+    // An ice ball floating inside a ball of water
+    let mut scene = Scene::new();
+    scene.add(Sphere::new(Vec3f(0.0, 0.0, 0.0), 2.0), WATER);
+    scene.add(
+        Sphere::new(Vec3f(0.0, 0.0, 0.0), 1.0),
+        Material {
+            refractive_index: 1.31,
+            ..GLASS
+        },
+    );
+    let mut media = MediumStack::new();
+    let mut z = 10.0;
+    let mut seen = Vec::new();
+    for _ in 0..4 {
+        let (hit, past) = next(&scene, z);
+        assert!(media.shows(&hit));
+        let (inside, beyond) = media.indices(&hit, None);
+        seen.push((inside, beyond));
+        media.cross(&hit);
+        z = past;
+    }
+    let close = |a: Float, b: Float| (a - b).abs() < 1e-6;
+    let expected = [(1.33, 1.0), (1.31, 1.33), (1.31, 1.33), (1.33, 1.0)];
+    for ((inside, beyond), (want_inside, want_beyond)) in seen.iter().zip(expected) {
+        assert!(
+            close(*inside, want_inside) && close(*beyond, want_beyond),
+            "{:?}",
+            seen
+        );
+    }
+}
+
+#[test]
+fn higher_priority_solids_fill_their_overlaps() {
+    // Water overlapping the far side of a glass ball, which takes the overlap
+    let mut scene = Scene::new();
+    scene.add(
+        Sphere::new(Vec3f(0.0, 0.0, 0.0), 1.0),
+        Material {
+            priority: 1,
+            ..GLASS
+        },
+    );
+    scene.add(Sphere::new(Vec3f(0.0, 0.0, -1.5), 1.0), WATER);
+    let mut media = MediumStack::new();
+
+    let (into_glass, z) = next(&scene, 10.0);
+    assert!(media.shows(&into_glass));
+    media.cross(&into_glass);
+    // The water's surface inside the glass is passed through
+    let (into_water, z) = next(&scene, z);
+    assert!(!media.shows(&into_water));
+    media.cross(&into_water);
+    // Leaving the glass goes into the water
+    let (out_of_glass, z) = next(&scene, z);
+    assert!(media.shows(&out_of_glass));
+    let (glass, water) = media.indices(&out_of_glass, None);
+    assert!((glass - 1.5).abs() < 1e-6 && (water - 1.33).abs() < 1e-6);
+    media.cross(&out_of_glass);
+    let (out_of_water, _) = next(&scene, z);
+    assert!(media.shows(&out_of_water));
+    let (water, air) = media.indices(&out_of_water, None);
+    assert!((water - 1.33).abs() < 1e-6 && air == 1.0);
+
+    let file = SceneFile::parse(
+        r#"{"materials": {"vessel": {"base": "glass", "priority": 2}},
+            "objects": [{"type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "vessel"}]}"#,
+    )
+    .unwrap();
+    let hit = next(&file.scene, 10.0).0;
+    assert_eq!(hit.material.priority, 2);
+}
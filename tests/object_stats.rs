@@ -0,0 +1,58 @@
+// Per-object ray counts, only kept when built with the stats feature
+#![cfg(feature = "stats")]
+
+use std::sync::Mutex;
+
+use rusty_rays::camera::Camera;
+use rusty_rays::material::IVORY;
+use rusty_rays::render::{render_with, RenderObserver, RenderSettings};
+use rusty_rays::scene::Scene;
+use rusty_rays::shapes::Sphere;
+use rusty_rays::stats::{ObjectStats, RayStats};
+use rusty_rays::vec3::Vec3f;
+
+#[derive(Default)]
+struct Collect {
+    rays: Mutex<RayStats>,
+    objects: Mutex<ObjectStats>,
+}
+
+impl RenderObserver for Collect {
+    fn on_render_end(&self, stats: &RayStats) {
+        *self.rays.lock().unwrap() += *stats;
+    }
+
+    fn on_object_stats(&self, stats: &ObjectStats) {
+        *self.objects.lock().unwrap() += stats;
+    }
+}
+
+#[test]
+fn objects_count_the_rays_they_stop() {
+    let mut scene = Scene::new();
+    let front = scene.add(Sphere::new(Vec3f(0.0, 0.0, -10.0), 2.0), IVORY);
+    // Hidden behind the first, and behind the camera
+    let hidden = scene.add(Sphere::new(Vec3f(0.0, 0.0, -20.0), 1.0), IVORY);
+    let behind = scene.add(Sphere::new(Vec3f(0.0, 0.0, 10.0), 1.0), IVORY);
+    let settings = RenderSettings {
+        width: 64,
+        height: 48,
+        ..RenderSettings::default()
+    };
+    let collect = Collect::default();
+    render_with(
+        &scene,
+        &Camera::new(Vec3f(0.0, 0.0, 0.0), 1.0),
+        &settings,
+        &collect,
+    );
+    let objects = collect.objects.into_inner().unwrap();
+    let rays = collect.rays.into_inner().unwrap();
+    assert!(objects.get(front).camera > 0);
+    assert_eq!(objects.get(hidden).camera, 0);
+    assert_eq!(objects.get(behind).camera, 0);
+    // Camera rays either hit the one sphere it sees or nothing
+    let camera_hits: u64 = objects.hits.values().map(|h| h.camera).sum();
+    assert_eq!(camera_hits, objects.get(front).camera);
+    assert!(camera_hits < rays.camera_rays);
+}
@@ -0,0 +1,49 @@
+// Probes project the light around them onto spherical harmonics
+
+use rusty_rays::bvh::Aabb;
+use rusty_rays::probes::ProbeGrid;
+use rusty_rays::render::RenderSettings;
+use rusty_rays::scene_file::SceneFile;
+use rusty_rays::vec3::{consts::PI, Float, Vec3f};
+
+#[test]
+fn a_black_ground_under_a_plain_sky() {
+    // A sky of radiance 1 above and nothing lit below, which the band 0 and y coefficients
+    // of the upper hemisphere alone catch
+    let file = SceneFile::parse(
+        r#"{"background": [1, 1, 1],
+            "objects": [{"type": "box", "min": [-1000, -10, -1000], "max": [1000, 0, 1000],
+                         "material": {"diffuse": [0, 0, 0]}}]}"#,
+    )
+    .unwrap();
+    let scene = &file.scene;
+    scene.build_bvh();
+    let grid = ProbeGrid {
+        counts: [2, 1, 1],
+        bounds: Some(Aabb::new(Vec3f(-1.0, 1.0, -1.0), Vec3f(1.0, 3.0, 1.0))),
+        order: 2,
+        samples: 4096,
+    };
+    let settings = RenderSettings::default();
+    let probes = grid.bake(scene, &settings).unwrap();
+    assert_eq!(probes.probes.len(), 2);
+    let probe = &probes.probes[1];
+    assert_eq!(probe.position, Vec3f(0.5, 2.0, 0.0));
+    assert_eq!(probe.coefficients.len(), 9);
+    let sh = &probe.coefficients;
+    let close = |a: Float, b: Float| (a - b).abs() < 0.03;
+    // Y00 over half the sphere, and 0.4886 y over the upper half
+    assert!(close(sh[0].0, 0.282095 * 2.0 * PI), "{:?}", sh);
+    assert!(close(sh[1].1, 0.488603 * PI), "{:?}", sh);
+    for c in [sh[2], sh[3], sh[4], sh[5], sh[7], sh[8]] {
+        assert!(c.0.abs() < 0.05, "{:?}", sh);
+    }
+
+    let first_order = ProbeGrid { order: 1, ..grid }
+        .bake(scene, &settings)
+        .unwrap();
+    assert_eq!(first_order.probes[0].coefficients.len(), 4);
+    assert!(ProbeGrid { order: 3, ..grid }
+        .bake(scene, &settings)
+        .is_err());
+}
@@ -0,0 +1,92 @@
+// Quads are hit inside their edges, face along u crossed with v, and glowing ones light
+// the path tracer's scenes
+
+use rusty_rays::camera::Camera;
+use rusty_rays::material::{Emission, Material, RED_RUBBER};
+use rusty_rays::render::{render, Integrator, RenderSettings};
+use rusty_rays::scene::Scene;
+use rusty_rays::shapes::{Quad, Shape};
+use rusty_rays::vec3::Vec3f;
+
+#[test]
+fn quads_are_hit_within_their_edges() {
+    let quad = Quad::new(
+        Vec3f(0.0, 0.0, 0.0),
+        Vec3f(2.0, 0.0, 0.0),
+        Vec3f(0.0, 1.0, 0.0),
+    );
+    assert!((quad.area() - 2.0).abs() < 1e-6);
+    let dir = Vec3f(0.0, 0.0, -1.0);
+    let hit = quad.hit(&Vec3f(1.5, 0.25, 3.0), &dir).unwrap();
+    assert!((hit.t - 3.0).abs() < 1e-6);
+    let (u, v) = hit.uv.unwrap();
+    assert!((u - 0.75).abs() < 1e-6 && (v - 0.25).abs() < 1e-6);
+    assert!(hit.front_face && hit.normal.2 > 0.99);
+    assert!(!quad.hit(&Vec3f(1.5, 0.25, -3.0), &-dir).unwrap().front_face);
+
+    assert!(quad.hit(&Vec3f(2.5, 0.5, 3.0), &dir).is_none());
+    assert!(quad.hit(&Vec3f(1.0, -0.1, 3.0), &dir).is_none());
+    assert!(quad.hit(&Vec3f(1.0, 0.5, -3.0), &dir).is_none());
+    assert!(quad
+        .hit(&Vec3f(1.0, 0.5, 1.0), &Vec3f(1.0, 0.0, 0.0))
+        .is_none());
+    assert!(quad.diagnostics().is_empty());
+
+    let bounds = quad.bounds();
+    assert_eq!(
+        (bounds.min, bounds.max),
+        (Vec3f(0.0, 0.0, 0.0), Vec3f(2.0, 1.0, 0.0))
+    );
+    let flat = Quad::new(
+        Vec3f(0.0, 0.0, 0.0),
+        Vec3f(1.0, 0.0, 0.0),
+        Vec3f(2.0, 0.0, 0.0),
+    );
+    assert_eq!(flat.diagnostics().len(), 1);
+}
+
+#[test]
+fn an_emissive_quad_lights_the_room_below() {
+    let lit_pixels = |two_sided: bool, facing_down: bool| {
+        let mut scene = Scene::new();
+        scene.background = Vec3f(0.0, 0.0, 0.0);
+        scene.add(
+            Quad::new(
+                Vec3f(-4.0, -1.0, 4.0),
+                Vec3f(8.0, 0.0, 0.0),
+                Vec3f(0.0, 0.0, -8.0),
+            ),
+            Material {
+                diffuse_color: Vec3f(0.8, 0.8, 0.8),
+                ..RED_RUBBER
+            },
+        );
+        let (u, v) = (Vec3f(2.0, 0.0, 0.0), Vec3f(0.0, 0.0, -2.0));
+        let (u, v) = if facing_down { (v, u) } else { (u, v) };
+        scene.add(
+            Quad::new(Vec3f(-1.0, 2.0, 1.0), u, v),
+            Material {
+                diffuse_color: Vec3f(0.0, 0.0, 0.0),
+                albedo: [0.0; 4],
+                emission: Some(Emission {
+                    color: Vec3f(4.0, 4.0, 4.0),
+                    two_sided,
+                }),
+                ..RED_RUBBER
+            },
+        );
+        let settings = RenderSettings {
+            width: 16,
+            height: 16,
+            samples_per_pixel: 16,
+            integrator: Integrator::Path,
+            ..RenderSettings::default()
+        };
+        let image = render(&scene, &Camera::new(Vec3f(0.0, 0.0, 5.0), 1.0), &settings);
+        // The floor covers most of the bottom half of the frame
+        image.pixels[8 * 16..].iter().filter(|c| c.0 > 0.01).count()
+    };
+    assert!(lit_pixels(false, true) > 40);
+    assert_eq!(lit_pixels(false, false), 0);
+    assert!(lit_pixels(true, false) > 40);
+}
@@ -0,0 +1,97 @@
+// Scattered placements land where they were asked to, as far apart as asked
+
+use std::sync::Arc;
+
+use rusty_rays::bvh::Aabb;
+use rusty_rays::mesh::TriangleMesh;
+use rusty_rays::scatter::Scatter;
+use rusty_rays::shapes::Sphere;
+use rusty_rays::texture::{ImageTexture, Wrap};
+use rusty_rays::vec3::{Float, Vec3f};
+
+// The square from -1 to 1 in x and z, facing up
+fn ground() -> TriangleMesh {
+    TriangleMesh::new(
+        vec![
+            Vec3f(-1.0, 0.0, -1.0),
+            Vec3f(-1.0, 0.0, 1.0),
+            Vec3f(1.0, 0.0, 1.0),
+            Vec3f(1.0, 0.0, -1.0),
+        ],
+        vec![[0, 1, 2], [0, 2, 3]],
+    )
+}
+
+#[test]
+fn placements_cover_the_region_and_keep_apart() {
+    let scatter = Scatter {
+        count: 200,
+        seed: 7,
+        ..Scatter::default()
+    };
+    let origin = Vec3f(0.0, 0.0, 0.0);
+    let placed = scatter.on_surface(&ground());
+    assert_eq!(placed.len(), 200);
+    assert!(placed.iter().all(|p| {
+        let at = p.point(&origin);
+        at.1.abs() < 1e-5 && at.0.abs() <= 1.0 && at.2.abs() <= 1.0
+    }));
+    // Both halves get their share
+    let left = placed.iter().filter(|p| p.point(&origin).0 < 0.0).count();
+    assert!((70..130).contains(&left), "{} of 200 on the left", left);
+    assert_eq!(placed, scatter.on_surface(&ground()));
+
+    let bounds = Aabb::new(Vec3f(0.0, 0.0, 0.0), Vec3f(4.0, 2.0, 4.0));
+    let apart = Scatter {
+        count: 100,
+        min_distance: Some(0.5),
+        ..Scatter::default()
+    };
+    let points: Vec<Vec3f> = apart
+        .in_volume(&bounds)
+        .iter()
+        .map(|p| p.point(&origin))
+        .collect();
+    assert_eq!(points.len(), 100);
+    for (i, a) in points.iter().enumerate() {
+        assert!(bounds.contains(a));
+        for b in &points[i + 1..] {
+            assert!((*a - *b).length() >= 0.5, "{:?} and {:?}", a, b);
+        }
+    }
+}
+
+#[test]
+fn density_maps_thin_placements_and_normals_turn_them() {
+    // Black on the left half, white on the right
+    let mut half = ImageTexture::new(2, 1, vec![Vec3f(0.0, 0.0, 0.0), Vec3f(1.0, 1.0, 1.0)]);
+    half.wrap = Wrap::Clamp;
+    let scatter = Scatter {
+        count: 100,
+        density: Some(Arc::new(half)),
+        ..Scatter::default()
+    };
+    let origin = Vec3f(0.0, 0.0, 0.0);
+    let placed = scatter.on_surface(&ground());
+    assert_eq!(placed.len(), 100);
+    // Bilinear filtering blends the two texels across the middle half
+    assert!(placed.iter().all(|p| p.point(&origin).0 > -0.5));
+
+    let sphere = Sphere::new(Vec3f(0.0, 0.0, 0.0), 2.0).to_mesh(32);
+    let aligned = Scatter {
+        count: 50,
+        scale: (0.5, 2.0),
+        rotation: Vec3f(0.0, 3.0, 0.0),
+        align_to_normal: true,
+        ..Scatter::default()
+    };
+    for placement in aligned.on_surface(&sphere) {
+        let at = placement.point(&origin);
+        let up = placement.vector(&Vec3f(0.0, 1.0, 0.0));
+        let scale = up.length();
+        assert!((0.5..=2.0).contains(&scale), "scale {}", scale);
+        let outward = at.normalized().unwrap();
+        assert!(up.dot(&outward) / scale > 0.99, "{:?} at {:?}", up, at);
+        assert!((at.length() - 2.0).abs() < 0.05 as Float);
+    }
+}
@@ -0,0 +1,322 @@
+// The server renders POSTed scenes to PNG, or queues them to render in turn, and says
+// what is wrong with the rest
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use rusty_rays::json::Json;
+use rusty_rays::server::RenderServer;
+
+const SPHERE: &str = r#"{"render": {"resolution": "8x6"},
+    "objects": [{"type": "sphere", "center": [0, 0, -5], "radius": 1}]}"#;
+
+fn request(address: &str, text: &str) -> (String, Vec<u8>) {
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(text.as_bytes()).unwrap();
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).unwrap();
+    let end = reply.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    let head = String::from_utf8(reply[..end].to_vec()).unwrap();
+    (head, reply[end + 4..].to_vec())
+}
+
+fn post(address: &str, target: &str, body: &str) -> (String, Vec<u8>) {
+    request(
+        address,
+        &format!(
+            "POST {} HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\n\r\n{}",
+            target,
+            body.len(),
+            body
+        ),
+    )
+}
+
+fn get(address: &str, method: &str, target: &str) -> (String, Vec<u8>) {
+    request(address, &format!("{} {} HTTP/1.1\r\n\r\n", method, target))
+}
+
+fn json(reply: (String, Vec<u8>)) -> Json {
+    Json::parse(std::str::from_utf8(&reply.1).unwrap()).unwrap()
+}
+
+fn start(server: RenderServer) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || server.serve(&listener));
+    address
+}
+
+#[test]
+fn serves_renders_over_http() {
+    let address = start(RenderServer {
+        max_body: 1000,
+        ..RenderServer::default()
+    });
+
+    let (head, body) = post(&address, "/render", SPHERE);
+    assert!(head.starts_with("HTTP/1.1 200 OK"), "{}", head);
+    assert!(head.contains("Content-Type: image/png"), "{}", head);
+    assert_eq!(&body[..8], b"\x89PNG\r\n\x1a\n");
+    // The width and height open the IHDR chunk
+    assert_eq!(&body[16..24], &[0, 0, 0, 8, 0, 0, 0, 6]);
+
+    let (head, body) = post(&address, "/render", r#"{"objects": 3}"#);
+    assert!(head.starts_with("HTTP/1.1 400"), "{}", head);
+    assert!(String::from_utf8(body).unwrap().contains("scene.objects"));
+
+    for (text, status) in [
+        ("GET /health HTTP/1.1\r\n\r\n", "200"),
+        ("GET /render HTTP/1.1\r\n\r\n", "405"),
+        ("GET /elsewhere HTTP/1.1\r\n\r\n", "404"),
+        ("POST /render HTTP/1.1\r\n\r\n", "411"),
+        // Refused before the body is sent
+        (
+            "POST /render HTTP/1.1\r\nContent-Length: 1001\r\n\r\n",
+            "413",
+        ),
+        ("nonsense\r\n\r\n", "400"),
+    ] {
+        let (head, _) = request(&address, text);
+        assert!(
+            head.starts_with(&format!("HTTP/1.1 {}", status)),
+            "{}",
+            head
+        );
+    }
+}
+
+#[test]
+fn queues_jobs_by_priority_across_restarts() {
+    let dir = std::env::temp_dir().join("rusty_rays_server_queue");
+    let _ = std::fs::remove_dir_all(&dir);
+    let server = || RenderServer {
+        queue_dir: Some(dir.clone()),
+        ..RenderServer::default()
+    };
+    let address = start(server());
+    let status = |address: &str, id: u64| {
+        let reply = json(get(address, "GET", &format!("/jobs/{}", id)));
+        reply.get("status").unwrap().as_str().unwrap().to_string()
+    };
+    let wait_for = |address: &str, id: u64, wanted: &str| {
+        for _ in 0..2000 {
+            if status(address, id) == wanted {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("job {} never became {}", id, wanted);
+    };
+
+    // Long enough to still be rendering while the others queue behind it
+    let slow = SPHERE.replace("8x6", "1000x1000");
+    let job = json(post(&address, "/jobs", &slow));
+    assert_eq!(job.get("id").and_then(Json::as_f64), Some(1.0));
+    wait_for(&address, 1, "rendering");
+    post(&address, "/jobs", SPHERE);
+    let urgent = json(post(&address, "/jobs?priority=5", SPHERE));
+    assert_eq!(urgent.get("ahead").and_then(Json::as_f64), Some(0.0));
+    let ahead = json(get(&address, "GET", "/jobs/2")).get("ahead").cloned();
+    assert_eq!(ahead, Some(Json::Number(1.0)));
+    let (head, _) = get(&address, "GET", "/jobs/2/result");
+    assert!(head.starts_with("HTTP/1.1 409"), "{}", head);
+
+    // Deleting the rendering job stops it and lets the rest through
+    let (head, _) = get(&address, "DELETE", "/jobs/1");
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    wait_for(&address, 2, "done");
+    assert_eq!(status(&address, 3), "done");
+    let (head, _) = get(&address, "GET", "/jobs/1");
+    assert!(head.starts_with("HTTP/1.1 404"), "{}", head);
+    assert!(!dir.join("1.json").exists());
+
+    // A second server on the same directory finds the finished jobs and carries on
+    let address = start(server());
+    let (head, png) = get(&address, "GET", "/jobs/3/result");
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert_eq!(&png[..4], b"\x89PNG");
+    let listed = json(get(&address, "GET", "/jobs"));
+    assert_eq!(
+        listed.get("jobs").and_then(Json::as_array).unwrap().len(),
+        2
+    );
+    let next = json(post(&address, "/jobs", SPHERE));
+    assert_eq!(next.get("id").and_then(Json::as_f64), Some(4.0));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn turns_jobs_and_connections_away_past_its_limits() {
+    let address = start(RenderServer {
+        max_queued: 1,
+        max_connections: 2,
+        ..RenderServer::default()
+    });
+    let code = |reply: (String, Vec<u8>)| reply.0[9..12].to_string();
+    let slow = SPHERE.replace("8x6", "1000x1000");
+    assert_eq!(code(post(&address, "/jobs", &slow)), "202");
+    let (head, body) = post(&address, "/jobs", SPHERE);
+    assert!(head.starts_with("HTTP/1.1 503"), "{}", head);
+    assert!(String::from_utf8(body).unwrap().contains("try again"));
+    assert_eq!(code(post(&address, "/render", SPHERE)), "503");
+    // Once the first is gone there is room again
+    get(&address, "DELETE", "/jobs/1");
+    let accepted = (0..2000).any(|_| {
+        thread::sleep(Duration::from_millis(10));
+        code(post(&address, "/jobs", SPHERE)) == "202"
+    });
+    assert!(accepted);
+
+    // A connection that is let in hears nothing until it asks, so the reply to saying
+    // nothing for a moment tells whether it was turned away
+    let connect = || {
+        let mut stream = TcpStream::connect(&address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let mut reply = String::new();
+        match stream.read_to_string(&mut reply) {
+            Ok(_) => (reply, stream),
+            Err(_) => (String::new(), stream),
+        }
+    };
+    // Two clients that never send a request fill every place
+    let idle: Vec<TcpStream> = (0..2).map(|_| connect()).map(|(_, s)| s).collect();
+    let (reply, _) = connect();
+    assert!(reply.starts_with("HTTP/1.1 503"), "{}", reply);
+    assert!(reply.contains("too many connections"), "{}", reply);
+    drop(idle);
+    let mut served = false;
+    for _ in 0..200 {
+        let (reply, mut stream) = connect();
+        if reply.is_empty() {
+            stream.set_read_timeout(None).unwrap();
+            stream.write_all(b"GET /health HTTP/1.1\r\n\r\n").unwrap();
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply).unwrap();
+            assert!(reply.starts_with("HTTP/1.1 200"), "{}", reply);
+            served = true;
+            break;
+        }
+    }
+    assert!(served);
+}
+
+#[test]
+fn reloads_what_it_can_of_a_damaged_queue() {
+    let dir = std::env::temp_dir().join("rusty_rays_server_damaged_queue");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let write = |name: &str, text: &str| std::fs::write(dir.join(name), text).unwrap();
+    // Queued with its scene gone, a priority that is not JSON, an error JSON has to
+    // escape, and one sound job
+    write("1.job", r#"{"priority": 0}"#);
+    write("2.job", "{priority");
+    write("3.job", r#"{"priority": 0}"#);
+    write("3.error", "\"bad\" \\ scene\n\tat line 1\u{1}");
+    write("4.job", r#"{"priority": 0}"#);
+    write("4.json", SPHERE);
+    let address = start(RenderServer {
+        queue_dir: Some(dir.clone()),
+        ..RenderServer::default()
+    });
+    let job = |id: u64| json(get(&address, "GET", &format!("/jobs/{}", id)));
+    let field = |job: &Json, key: &str| job.get(key).and_then(Json::as_str).unwrap().to_string();
+
+    for id in [1, 2] {
+        let job = job(id);
+        assert_eq!(field(&job, "status"), "failed");
+        assert!(field(&job, "error").contains("reloaded"), "{:?}", job);
+    }
+    assert_eq!(
+        field(&job(3), "error"),
+        "\"bad\" \\ scene\n\tat line 1\u{1}"
+    );
+    for _ in 0..2000 {
+        if field(&job(4), "status") == "done" {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(field(&job(4), "status"), "done");
+    let next = json(post(&address, "/jobs", SPHERE));
+    assert_eq!(next.get("id").and_then(Json::as_f64), Some(5.0));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn refuses_hostile_scenes_and_stays_up() {
+    let dir = std::env::temp_dir().join("rusty_rays_server_assets");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    rusty_rays::framebuffer::Framebuffer::new(2, 2)
+        .write_png(&dir.join("plate.png"))
+        .unwrap();
+    let confined = start(RenderServer {
+        max_pixels: 100,
+        max_samples: 4,
+        asset_dir: Some(dir.clone()),
+        ..RenderServer::default()
+    });
+    let denied = start(RenderServer::default());
+    let status = |address: &str, body: &str| {
+        let (head, body) = post(address, "/render", body);
+        (
+            head[9..12].to_string(),
+            String::from_utf8_lossy(&body).into_owned(),
+        )
+    };
+
+    // Deep enough to overflow the stack of a parser that did not stop it
+    let deep = "[".repeat(200_000);
+    let (code, message) = status(&denied, &deep);
+    assert_eq!(code, "400");
+    assert!(message.contains("nested"), "{}", message);
+    assert_eq!(status(&denied, SPHERE).0, "200");
+
+    let (code, message) = status(&confined, &SPHERE.replace("8x6", "20x20"));
+    assert_eq!(code, "400");
+    assert!(message.contains("pixels"), "{}", message);
+    let asking = |settings: &str| SPHERE.replace(r#""8x6""#, &format!(r#""8x6", {}"#, settings));
+    assert_eq!(status(&confined, &asking(r#""samples": 5"#)).0, "400");
+    // Whitted shading splits at every glass surface, so depth costs rays exponentially
+    let glass = asking(r#""integrator": "whitted", "max_depth": 40"#)
+        .replace(r#""radius": 1}"#, r#""radius": 1, "material": "glass"}"#);
+    let (code, message) = status(&denied, &glass);
+    assert_eq!(code, "400");
+    assert!(message.contains("max_depth 40"), "{}", message);
+    for (settings, wanted) in [
+        (r#""denoise": {"radius": 1000}"#, "denoise radius"),
+        (r#""bloom": {"radius": 1e9}"#, "bloom radius"),
+        (
+            r#""integrator": "path", "irradiance_cache": {"samples": 100000}"#,
+            "irradiance cache",
+        ),
+    ] {
+        let (code, message) = status(&denied, &asking(settings));
+        assert_eq!(code, "400", "{}", settings);
+        assert!(message.contains(wanted), "{}", message);
+    }
+    let within = asking(r#""max_depth": 10, "denoise": {"radius": 2}, "auto_exposure": true"#);
+    assert_eq!(status(&denied, &within).0, "200");
+
+    let plate = |path: &str| {
+        SPHERE.replace(
+            "\"render\"",
+            &format!("\"backplate\": {:?}, \"render\"", path),
+        )
+    };
+    assert_eq!(status(&confined, &plate("plate.png")).0, "200");
+    for outside in ["../plate.png", "/etc/passwd", "missing.png"] {
+        let (code, message) = status(&confined, &plate(outside));
+        assert_eq!(code, "400", "{}", outside);
+        assert!(message.contains("no such file"), "{}", message);
+    }
+    let (code, message) = status(&denied, &plate("plate.png"));
+    assert_eq!(code, "400");
+    assert!(message.contains("may not read files"), "{}", message);
+    let _ = std::fs::remove_dir_all(&dir);
+}
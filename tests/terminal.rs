@@ -0,0 +1,72 @@
+// Terminal previews shrink the image into half-block cells, two pixels to a cell
+
+use rusty_rays::framebuffer::Framebuffer;
+use rusty_rays::rng::Rng;
+use rusty_rays::terminal::{half_blocks, inline_image, Protocol};
+use rusty_rays::vec3::Vec3f;
+
+#[test]
+fn cells_average_the_pixels_under_each_half() {
+    // White over black on the left, red on the right
+    let mut image = Framebuffer::new(4, 4);
+    for y in 0..4 {
+        for x in 0..4 {
+            let color = match (x < 2, y < 2) {
+                (true, true) => Vec3f(1.0, 1.0, 1.0),
+                (true, false) => Vec3f(0.0, 0.0, 0.0),
+                (false, _) => Vec3f(1.0, 0.0, 0.0),
+            };
+            image.set(x, y, color);
+        }
+    }
+    let text = half_blocks(&image, 2, 10);
+    let cell = |fg: &str, bg: &str| format!("\x1b[38;2;{}m\x1b[48;2;{}m\u{2580}", fg, bg);
+    let expected = format!(
+        "{}{}\x1b[0m\n",
+        cell("255;255;255", "0;0;0"),
+        cell("255;0;0", "255;0;0")
+    );
+    assert_eq!(text, expected);
+
+    // Never enlarged, and a line per two rows of pixels
+    assert_eq!(half_blocks(&image, 100, 100).lines().count(), 2);
+    let shrunk = half_blocks(&Framebuffer::new(64, 16), 8, 100);
+    assert_eq!(shrunk.lines().count(), 1);
+    assert_eq!(shrunk.matches('\u{2580}').count(), 8);
+}
+
+#[test]
+fn inline_images_fit_the_terminal() {
+    let mut image = Framebuffer::new(300, 100);
+    image.pixels.fill(Vec3f(1.0, 0.0, 0.0));
+
+    // Pure red in every band of six rows, which a shrink to 150 by 50 makes nine
+    let sixel = inline_image(&image, Protocol::Sixel, Some((150, 400))).unwrap();
+    assert!(
+        sixel.starts_with("\x1bPq\"1;1;150;50#0;2;0;0;0"),
+        "{}",
+        &sixel[..40]
+    );
+    assert_eq!(sixel.matches("#180!150~").count(), 8);
+    assert!(sixel.ends_with("#180!150B-\x1b\\\n"));
+
+    // The PNG goes to kitty in pieces, all but the last saying more follow; noise keeps
+    // it from compressing into one
+    let mut rng = Rng::new(1);
+    for pixel in &mut image.pixels {
+        *pixel = Vec3f(rng.next_float(), rng.next_float(), rng.next_float());
+    }
+    let kitty = inline_image(&image, Protocol::Kitty, None).unwrap();
+    let pieces: Vec<&str> = kitty.split("\x1b_G").skip(1).collect();
+    assert!(pieces[0].starts_with("f=100,a=T,m="));
+    assert!(pieces.last().unwrap().starts_with("m=0;"));
+    assert!(pieces[..pieces.len() - 1]
+        .iter()
+        .all(|p| p.contains("m=1;")));
+    let iterm = inline_image(&image, Protocol::Iterm, None).unwrap();
+    assert!(iterm.starts_with("\x1b]1337;File=inline=1;size="));
+    assert!(
+        iterm.contains(":iVBORw0KGgo"),
+        "base64 of the PNG signature"
+    );
+}
@@ -0,0 +1,94 @@
+// Tessellated shapes checked against their analytic intersections
+
+use rusty_rays::shapes::{Cone, Cylinder, Ovoid, Shape, Sphere, Torus};
+use rusty_rays::vec3::{Float, Vec3f};
+
+// Rays aimed at the shape from every side, each offset a little from the center so
+// they meet the surface at varied angles
+fn rays(center: Vec3f, distance: Float) -> Vec<(Vec3f, Vec3f)> {
+    let mut rays = Vec::new();
+    for i in 0..64 {
+        let angle = i as Float * 0.7;
+        let height = (i as Float / 63.0) * 1.6 - 0.8;
+        let from = center
+            + Vec3f(
+                angle.cos() * distance,
+                height * distance,
+                angle.sin() * distance,
+            );
+        let aim = center + Vec3f(0.1 * (i % 3) as Float, 0.05 * (i % 5) as Float, 0.0);
+        rays.push((from, (aim - from).normalized().unwrap()));
+    }
+    rays
+}
+
+fn assert_matches(analytic: &dyn Shape, mesh: &dyn Shape, center: Vec3f, tolerance: Float) {
+    let mut compared = 0;
+    for (orig, dir) in rays(center, 10.0) {
+        let (Some(exact), Some(approx)) = (analytic.hit(&orig, &dir), mesh.hit(&orig, &dir)) else {
+            continue;
+        };
+        assert!(
+            (exact.t - approx.t).abs() < tolerance,
+            "t {} against {}",
+            exact.t,
+            approx.t
+        );
+        assert!(
+            exact.normal.dot(&approx.normal) > 0.99,
+            "normal {:?} against {:?}",
+            exact.normal,
+            approx.normal
+        );
+        compared += 1;
+    }
+    assert!(compared > 16, "only {} rays hit both", compared);
+}
+
+#[test]
+fn tessellations_match_the_analytic_shapes() {
+    let center = Vec3f(1.0, -2.0, 3.0);
+
+    let sphere = Sphere::new(center, 2.0);
+    assert_matches(&sphere, &sphere.to_mesh(128), center, 0.01);
+
+    let ovoid = Ovoid::new(center, Vec3f(2.0, 1.0, 1.5));
+    assert_matches(&ovoid, &ovoid.to_mesh(128), center, 0.01);
+
+    let torus = Torus::new(center, 0.5, 2.0);
+    assert_matches(&torus, &torus.to_mesh(128), center, 0.01);
+
+    let base = center - Vec3f(0.0, 1.5, 0.0);
+    let cylinder = Cylinder::new(base, 3.0, 1.5);
+    assert_matches(&cylinder, &cylinder.to_mesh(128), center, 0.01);
+
+    let cone = Cone::new(base, 3.0, 1.5);
+    assert_matches(&cone, &cone.to_mesh(128), center, 0.01);
+}
+
+#[test]
+fn sphere_meshes_carry_the_spheres_texture_coordinates() {
+    let sphere = Sphere::new(Vec3f(0.0, 0.0, 0.0), 1.0);
+    let mesh = sphere.to_mesh(256);
+    for (orig, dir) in rays(Vec3f(0.0, 0.0, 0.0), 10.0) {
+        let exact = sphere.hit(&orig, &dir).unwrap().uv.unwrap();
+        let approx = mesh.hit(&orig, &dir).unwrap().uv.unwrap();
+        assert!(
+            (exact.0 - approx.0).abs() < 0.01 && (exact.1 - approx.1).abs() < 0.01,
+            "uv {:?} against {:?}",
+            exact,
+            approx
+        );
+    }
+    let vertices = mesh.vertices();
+    for &[a, b, c] in mesh.faces() {
+        let winding = (vertices[b] - vertices[a]).cross(&(vertices[c] - vertices[a]));
+        assert!(
+            winding.dot(&vertices[a]) > 0.0,
+            "face {:?} winds inward",
+            [a, b, c]
+        );
+    }
+    // Poles are single points, so their slivers are dropped
+    assert!(mesh.diagnostics().is_empty(), "{:?}", mesh.diagnostics());
+}
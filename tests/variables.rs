@@ -0,0 +1,47 @@
+// Variables fill in the scene file's strings, and values given from outside win
+
+use std::path::Path;
+
+use rusty_rays::json::Json;
+use rusty_rays::scene_file::SceneFile;
+use rusty_rays::variables::parse_assignment;
+use rusty_rays::vec3::Vec3f;
+
+const SCENE: &str = r#"{
+    "variables": {"height": 2, "lift": "${(height + 1) * 2 - -1}", "sky": [0.1, 0.2, 0.3]},
+    "camera": {"position": [0, "${lift}", "${height % 3 / 4}"]},
+    "background": "${sky}"
+}"#;
+
+#[test]
+fn substitutes_and_overrides() {
+    let file = SceneFile::parse(SCENE).unwrap();
+    assert_eq!(file.camera.position, Vec3f(0.0, 7.0, 0.5));
+    assert_eq!(file.scene.background, Vec3f(0.1, 0.2, 0.3));
+
+    let set = [
+        parse_assignment("height=4").unwrap(),
+        parse_assignment("sky=[1, 1, 1]").unwrap(),
+    ];
+    let file = SceneFile::parse_with(SCENE, Path::new(""), &set).unwrap();
+    assert_eq!(file.camera.position, Vec3f(0.0, 11.0, 0.25));
+    assert_eq!(file.scene.background, Vec3f(1.0, 1.0, 1.0));
+
+    assert_eq!(
+        parse_assignment("name=wood.png"),
+        Some(("name".to_string(), Json::String("wood.png".to_string())))
+    );
+    assert_eq!(parse_assignment("2x=1"), None);
+    let unknown = [parse_assignment("width=1").unwrap()];
+    assert!(SceneFile::parse_with(SCENE, Path::new(""), &unknown).is_err());
+    for bad in [
+        r#"{"background": "${sky}"}"#,
+        r#"{"variables": {"a": "x"}, "background": "${a * 2}"}"#,
+        r#"{"variables": {"a": 0}, "camera": {"fov": "${1 / a}"}}"#,
+        r#"{"variables": {"a": 1}, "camera": {"fov": "${a +}"}}"#,
+        r#"{"variables": {"a": 1}, "backplate": "${a"}"#,
+        r#"{"variables": {"b": "${a}", "a": 1}}"#,
+    ] {
+        assert!(SceneFile::parse(bad).is_err(), "{}", bad);
+    }
+}
@@ -0,0 +1,47 @@
+// Occlusion baked per vertex darkens what is tucked under other shapes
+
+use rusty_rays::occlusion::VertexOcclusion;
+use rusty_rays::render::RenderSettings;
+use rusty_rays::scene_file::SceneFile;
+use rusty_rays::vec3::Vec3f;
+
+#[test]
+fn vertices_under_a_ball_are_occluded() {
+    let file = SceneFile::parse(
+        r#"{"objects": [
+              {"type": "mesh", "name": "floor",
+               "vertices": [[0, 0, 0], [-6, 0, -6], [6, 0, -6], [6, 0, 6], [-6, 0, 6]],
+               "faces": [[0, 2, 1], [0, 3, 2], [0, 4, 3], [0, 1, 4]]},
+              {"type": "sphere", "center": [0, 1.1, 0], "radius": 1}]}"#,
+    )
+    .unwrap();
+    let scene = &file.scene;
+    scene.build_bvh();
+    let settings = RenderSettings::default();
+    let bake = VertexOcclusion {
+        samples: 256,
+        distance: None,
+    };
+    let (mesh, open) = bake.bake(scene, "floor", &settings).unwrap();
+    assert_eq!(open.len(), mesh.vertices().len());
+    // Right under the ball most rays hit it, and out at the corners nearly none do
+    assert!(open[0] < 0.2, "{:?}", open);
+    assert!(open[1..].iter().all(|&o| o > 0.95), "{:?}", open);
+    // Stopping rays short of the ball leaves the middle open too
+    let near = VertexOcclusion {
+        distance: Some(0.05),
+        ..bake
+    };
+    assert_eq!(near.bake(scene, "floor", &settings).unwrap().1[0], 1.0);
+
+    let path = std::env::temp_dir().join("rusty_rays_vertex_occlusion.ply");
+    let colors: Vec<Vec3f> = open.iter().map(|&o| Vec3f(o, o, o)).collect();
+    mesh.write_ply(&path, Some(&colors)).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert!(text.contains("element vertex 5\n") && text.contains("property uchar red\n"));
+    let body: Vec<&str> = text.split("end_header\n").nth(1).unwrap().lines().collect();
+    assert_eq!(body.len(), 9, "{}", text);
+    assert!(body[3].starts_with("6 0 6 "), "{}", body[3]);
+    assert_eq!(body[8], "3 0 1 4");
+}
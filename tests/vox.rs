@@ -0,0 +1,62 @@
+// MagicaVoxel files assembled in memory, chunk by chunk
+
+use rusty_rays::vec3::Vec3f;
+use rusty_rays::vox::VoxFile;
+
+fn chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+    let mut bytes = id.to_vec();
+    bytes.extend((content.len() as u32).to_le_bytes());
+    bytes.extend((children.len() as u32).to_le_bytes());
+    bytes.extend(content);
+    bytes.extend(children);
+    bytes
+}
+
+fn file(voxels: &[[u8; 4]], palette: Option<&[[u8; 4]]>) -> Vec<u8> {
+    let size: Vec<u8> = [2u32, 3, 4].iter().flat_map(|v| v.to_le_bytes()).collect();
+    let mut xyzi = (voxels.len() as u32).to_le_bytes().to_vec();
+    xyzi.extend(voxels.iter().flatten());
+    let mut children = chunk(b"SIZE", &size, &[]);
+    children.extend(chunk(b"XYZI", &xyzi, &[]));
+    if let Some(palette) = palette {
+        let mut rgba: Vec<u8> = palette.iter().flatten().copied().collect();
+        rgba.resize(1024, 0);
+        children.extend(chunk(b"RGBA", &rgba, &[]));
+    }
+    let mut bytes = b"VOX ".to_vec();
+    bytes.extend(150u32.to_le_bytes());
+    bytes.extend(chunk(b"MAIN", &[], &children));
+    bytes
+}
+
+#[test]
+fn voxels_turn_y_up_and_take_palette_colors() {
+    let bytes = file(
+        &[[1, 0, 3, 1], [0, 2, 0, 2]],
+        Some(&[[255, 0, 0, 255], [0, 0, 255, 255]]),
+    );
+    let vox = VoxFile::parse(&bytes).unwrap();
+    let model = &vox.models[0];
+    assert_eq!(model.size, [2, 4, 3]);
+    assert_eq!(model.voxels, vec![([1, 3, 2], 1), ([0, 0, 0], 2)]);
+    assert_eq!(vox.palette[1], Vec3f(1.0, 0.0, 0.0));
+    assert_eq!(vox.palette[2], Vec3f(0.0, 0.0, 1.0));
+
+    let octree = vox.octree(0, Vec3f(0.0, 0.0, 0.0), 1.0);
+    assert_eq!(octree.get([1, 3, 2]), Some(Vec3f(1.0, 0.0, 0.0)));
+    assert_eq!(octree.get([1, 1, 1]), None);
+}
+
+#[test]
+fn files_without_a_palette_use_the_default() {
+    let vox = VoxFile::parse(&file(&[[0, 0, 0, 1]], None)).unwrap();
+    assert_eq!(vox.palette[1], Vec3f(1.0, 1.0, 1.0));
+    assert_eq!(vox.palette[255], Vec3f(1.0, 1.0, 1.0) * (17.0 / 255.0));
+}
+
+#[test]
+fn truncated_files_are_rejected() {
+    let bytes = file(&[[0, 0, 0, 1]], None);
+    assert!(VoxFile::parse(&bytes[..bytes.len() - 2]).is_err());
+    assert!(VoxFile::parse(b"PNG ").is_err());
+}
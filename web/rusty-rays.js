@@ -0,0 +1,39 @@
+// Loads the renderer built with
+//   cargo build --release --lib --target wasm32-unknown-unknown
+// and draws scene files into a canvas.
+export async function load(url = "rusty_rays.wasm") {
+  const { instance } = await WebAssembly.instantiateStreaming(fetch(url));
+  return new Renderer(instance.exports);
+}
+
+export class Renderer {
+  constructor(exports) {
+    this.wasm = exports;
+  }
+
+  // RGBA bytes for the scene JSON at the given size; throws with the renderer's message
+  // if the scene does not parse
+  render(sceneJson, width, height) {
+    const { memory, rr_alloc, rr_free, rr_render, rr_error, rr_error_len } = this.wasm;
+    const text = new TextEncoder().encode(sceneJson);
+    const scene = rr_alloc(text.length);
+    new Uint8Array(memory.buffer, scene, text.length).set(text);
+    const pixels = rr_render(scene, text.length, width, height);
+    rr_free(scene, text.length);
+    if (pixels === 0) {
+      const message = new Uint8Array(memory.buffer, rr_error(), rr_error_len());
+      throw new Error(new TextDecoder().decode(message));
+    }
+    // Copy out before freeing; memory.buffer is replaced whenever the module grows it
+    const length = width * height * 4;
+    const rgba = new Uint8ClampedArray(memory.buffer, pixels, length).slice();
+    rr_free(pixels, length);
+    return rgba;
+  }
+
+  draw(canvas, sceneJson) {
+    const rgba = this.render(sceneJson, canvas.width, canvas.height);
+    const context = canvas.getContext("2d");
+    context.putImageData(new ImageData(rgba, canvas.width, canvas.height), 0, 0);
+  }
+}